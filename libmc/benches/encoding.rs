@@ -0,0 +1,106 @@
+//! Encoding and decoding on the hot packet paths: `cargo bench -p libmc`
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use libmc::*;
+
+/// One of each length a VarInt can have
+const VARINTS: [i32; 5] = [1, 300, 70_000, 10_000_000, -1];
+
+fn varint(c: &mut Criterion) {
+    c.bench_function("varint encode", |b| {
+        let mut buf = Vec::with_capacity(64);
+        b.iter(|| {
+            buf.clear();
+            for x in VARINTS {
+                VarInt(black_box(x)).write(&mut buf);
+            }
+            black_box(&buf);
+        })
+    });
+
+    let mut encoded = Vec::new();
+    for x in VARINTS {
+        VarInt(x).write(&mut encoded);
+    }
+    c.bench_function("varint decode", |b| {
+        b.iter(|| {
+            let mut r = PacketReader::new(black_box(&encoded));
+            while !r.is_empty() {
+                black_box(r.varint().unwrap());
+            }
+        })
+    });
+    c.bench_function("varint read", |b| {
+        b.iter(|| {
+            let mut r = black_box(&encoded[..]);
+            while !r.is_empty() {
+                black_box(VarInt::read(&mut r));
+            }
+        })
+    });
+}
+
+fn string(c: &mut Criterion) {
+    let json = format!(
+        r#"{{"description":"{}"}}"#,
+        "A Minecraft Server ".repeat(50)
+    );
+    let packet = OwnedPacket::from_fn(json, |json| OutPacket::StatusResponse { json });
+    c.bench_function("string encode", |b| {
+        b.iter(|| black_box(packet.encode(PROTOCOL_VERSION)))
+    });
+
+    let encoded = packet.encode(PROTOCOL_VERSION);
+    c.bench_function("string decode", |b| {
+        b.iter(|| {
+            let mut r = PacketReader::new(black_box(&encoded[1..]));
+            black_box(r.str().unwrap());
+        })
+    });
+}
+
+/// A chunk's sections and light, as a server keeps them between sends
+struct Chunk {
+    data: Vec<i8>,
+    light: Vec<[i8; 2048]>,
+    light_mask: BitSet,
+    empty_mask: BitSet,
+}
+
+fn chunk_packet(chunk: &Chunk) -> OutPacket<'_> {
+    OutPacket::ChunkDataAndUpdateLight {
+        chunk_x: 3,
+        chunk_z: -7,
+        heightmaps: CompoundNbt::new(""),
+        data: &chunk.data,
+        block_entities: &[],
+        sky_light_mask: chunk.light_mask.clone(),
+        block_light_mask: chunk.light_mask.clone(),
+        empty_sky_light_mask: chunk.empty_mask.clone(),
+        empty_block_light_mask: chunk.empty_mask.clone(),
+        sky_light_arrays: &chunk.light,
+        block_light_arrays: &chunk.light,
+    }
+}
+
+fn chunk(c: &mut Criterion) {
+    // 24 sections and the 2 beyond them, each lit
+    let mut light_mask = BitSet::with_num_bits(26);
+    for i in 0..26 {
+        light_mask.set(i);
+    }
+    let chunk = Chunk {
+        // about as big as 24 sections of blocks with 8-bit palette indices
+        data: (0..24 * 4200).map(|i| i as i8).collect(),
+        light: vec![[0x7F; 2048]; 26],
+        light_mask,
+        empty_mask: BitSet::with_num_bits(26),
+    };
+    let packet = OwnedPacket::from_fn(chunk, chunk_packet);
+    c.bench_function("chunk encode", |b| {
+        b.iter(|| black_box(packet.encode(PROTOCOL_VERSION)))
+    });
+}
+
+criterion_group!(benches, varint, string, chunk);
+criterion_main!(benches);
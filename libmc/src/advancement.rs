@@ -0,0 +1,218 @@
+use crate::*;
+
+/// The shape of an advancement's icon frame, which also decides the color of its toast
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AdvancementFrame {
+    Task = 0,
+    Challenge = 1,
+    Goal = 2,
+}
+
+/// How an advancement is shown in the advancements screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdvancementDisplay {
+    pub title: TextComponent,
+    pub description: TextComponent,
+    pub icon: Option<Slot<'static>>,
+    pub frame: AdvancementFrame,
+    /// The tab's background, e.g. `minecraft:textures/gui/advancements/backgrounds/stone.png`.
+    /// Only used by root advancements.
+    pub background: Option<String>,
+    /// Show a toast when it's completed
+    pub show_toast: bool,
+    /// Hide it until it's completed
+    pub hidden: bool,
+    /// Position in the tab
+    pub x: f32,
+    pub y: f32,
+}
+
+/// An advancement as sent to clients. Advancements without a parent are roots, which get their
+/// own tab.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advancement {
+    pub parent: Option<String>,
+    /// None for advancements that aren't shown, e.g. recipe unlocks
+    pub display: Option<AdvancementDisplay>,
+    /// Criteria names, in groups: the advancement is done once every group has a criterion done
+    pub requirements: Vec<Vec<String>>,
+    pub sends_telemetry: bool,
+}
+
+impl Advancement {
+    /// Every criterion, once each
+    pub fn criteria(&self) -> impl Iterator<Item = &str> {
+        let mut seen = Vec::new();
+        self.requirements.iter().flatten().filter_map(move |c| {
+            if seen.contains(&c) {
+                None
+            } else {
+                seen.push(c);
+                Some(c.as_str())
+            }
+        })
+    }
+}
+
+/// A player's progress on an advancement: when each criterion was done, if it was
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AdvancementProgress {
+    /// (criterion, milliseconds since the Unix epoch)
+    pub criteria: Vec<(String, Option<i64>)>,
+}
+
+impl AdvancementProgress {
+    /// No progress on any of `advancement`'s criteria
+    pub fn new(advancement: &Advancement) -> Self {
+        Self {
+            criteria: advancement
+                .criteria()
+                .map(|c| (c.to_owned(), None))
+                .collect(),
+        }
+    }
+
+    /// Marks `criterion` done at `time` (in milliseconds since the Unix epoch). Returns false if it
+    /// was already done or isn't one of the advancement's criteria.
+    pub fn grant(&mut self, criterion: &str, time: i64) -> bool {
+        match self.criteria.iter_mut().find(|(c, _)| c == criterion) {
+            Some((_, done @ None)) => {
+                *done = Some(time);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Marks `criterion` not done. Returns false if it wasn't done.
+    pub fn revoke(&mut self, criterion: &str) -> bool {
+        match self.criteria.iter_mut().find(|(c, _)| c == criterion) {
+            Some((_, done @ Some(_))) => {
+                *done = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_criterion_done(&self, criterion: &str) -> bool {
+        self.criteria
+            .iter()
+            .any(|(c, done)| c == criterion && done.is_some())
+    }
+
+    /// Whether every requirement group of `advancement` has a criterion done
+    pub fn is_done(&self, advancement: &Advancement) -> bool {
+        advancement
+            .requirements
+            .iter()
+            .all(|group| group.iter().any(|c| self.is_criterion_done(c)))
+    }
+}
+
+pub(crate) fn write_advancement(buf: &mut Vec<u8>, advancement: &Advancement) {
+    write_bool(buf, advancement.parent.is_some());
+    if let Some(parent) = &advancement.parent {
+        write_string(buf, parent);
+    }
+    write_bool(buf, advancement.display.is_some());
+    if let Some(display) = &advancement.display {
+        write_string(buf, &display.title.to_json());
+        write_string(buf, &display.description.to_json());
+        write_slot(buf, display.icon.as_ref());
+        write_varint(buf, display.frame as i32);
+        let flags = i32::from(display.background.is_some())
+            | i32::from(display.show_toast) << 1
+            | i32::from(display.hidden) << 2;
+        write_int(buf, flags);
+        if let Some(background) = &display.background {
+            write_string(buf, background);
+        }
+        write_float(buf, display.x);
+        write_float(buf, display.y);
+    }
+    write_varint(buf, advancement.requirements.len().try_into().unwrap());
+    for group in &advancement.requirements {
+        write_varint(buf, group.len().try_into().unwrap());
+        for criterion in group {
+            write_string(buf, criterion);
+        }
+    }
+    write_bool(buf, advancement.sends_telemetry);
+}
+
+pub(crate) fn write_advancement_progress(buf: &mut Vec<u8>, progress: &AdvancementProgress) {
+    write_varint(buf, progress.criteria.len().try_into().unwrap());
+    for (criterion, done) in &progress.criteria {
+        write_string(buf, criterion);
+        write_bool(buf, done.is_some());
+        if let Some(time) = done {
+            write_long(buf, *time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let root = Advancement {
+            parent: None,
+            display: Some(AdvancementDisplay {
+                title: TextComponent::text("Mining"),
+                description: TextComponent::text("Dig"),
+                icon: Some(Slot::new(1, 1)),
+                frame: AdvancementFrame::Task,
+                background: Some("minecraft:textures/block/stone.png".to_owned()),
+                show_toast: true,
+                hidden: false,
+                x: 0.0,
+                y: 0.0,
+            }),
+            // one log of either kind, and a stick
+            requirements: vec![
+                vec!["oak_log".to_owned(), "birch_log".to_owned()],
+                vec!["stick".to_owned()],
+            ],
+            sends_telemetry: false,
+        };
+        let mut progress = AdvancementProgress::new(&root);
+        assert_eq!(progress.criteria.len(), 3);
+        assert!(progress.grant("birch_log", 1000));
+        assert!(!progress.grant("birch_log", 2000));
+        assert!(!progress.is_done(&root));
+        assert!(progress.grant("stick", 3000));
+        assert!(progress.is_done(&root));
+        assert!(progress.revoke("stick"));
+        assert!(!progress.is_done(&root));
+
+        let packet = encode_packet(
+            OutPacket::UpdateAdvancements {
+                reset: true,
+                added: &[("test:root", &root)],
+                removed: &[],
+                progress: &[("test:root", &progress)],
+            },
+            PROTOCOL_VERSION,
+        );
+        assert_eq!(packet[..2], [0x6C, 0x01]);
+        // the birch log's time is the only one
+        let time = 1000i64.to_be_bytes();
+        assert_eq!(packet.windows(8).filter(|w| *w == time).count(), 1);
+
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &[0x28, 0x01]).unwrap(),
+            InPacket::SeenAdvancements { tab: None }
+        ));
+        let mut p = vec![0x28, 0x00];
+        write_string(&mut p, "test:root");
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &p).unwrap(),
+            InPacket::SeenAdvancements {
+                tab: Some("test:root")
+            }
+        ));
+    }
+}
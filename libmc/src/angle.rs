@@ -0,0 +1,44 @@
+use std::f32::consts::TAU;
+
+/// A rotation as the protocol sends it: one byte, in 1/256ths of a full turn
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Angle(pub u8);
+
+impl Angle {
+    /// Wraps around, so e.g. -90° and 270° are the same angle.
+    /// Rounds down like vanilla, so the client sees the same angles it does on vanilla servers.
+    pub fn from_degrees(degrees: f32) -> Self {
+        Self((degrees * (256.0 / 360.0)).floor().rem_euclid(256.0) as u8)
+    }
+
+    pub fn from_radians(radians: f32) -> Self {
+        Self::from_degrees(radians.to_degrees())
+    }
+
+    /// In [0, 360)
+    pub fn degrees(self) -> f32 {
+        f32::from(self.0) * (360.0 / 256.0)
+    }
+
+    /// In [0, 2π)
+    pub fn radians(self) -> f32 {
+        f32::from(self.0) * (TAU / 256.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Angle::from_degrees(0.0), Angle(0));
+        assert_eq!(Angle::from_degrees(90.0), Angle(64));
+        assert_eq!(Angle::from_degrees(-90.0), Angle(192));
+        assert_eq!(Angle::from_degrees(360.0), Angle(0));
+        assert_eq!(Angle::from_degrees(450.0), Angle(64));
+        assert_eq!(Angle::from_radians(std::f32::consts::PI), Angle(128));
+        assert_eq!(Angle(64).degrees(), 90.0);
+        assert_eq!(Angle(128).radians(), std::f32::consts::PI);
+    }
+}
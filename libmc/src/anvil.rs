@@ -0,0 +1,572 @@
+use crate::compress::{gzip_decompress, zlib_compress, zlib_decompress};
+use crate::nbt::{compound_list, invalid, write_compound_nbt};
+use crate::util::write_atomically;
+use crate::*;
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 17w47a, the snapshot that replaced numeric block IDs with palettes of block states
+const FLATTENING_DATA_VERSION: i32 = 1451;
+/// 20w17a, since which values in `BlockStates` no longer straddle two longs
+const UNSPANNED_DATA_VERSION: i32 = 2529;
+/// 21w43a, which dropped the `Level` wrapper and moved sections to `block_states`
+const FLAT_CHUNK_DATA_VERSION: i32 = 2844;
+
+const SECTION_BLOCKS: usize = 16 * 16 * 16;
+
+/// One step of bringing an old chunk up to date. Chunks older than `before` go through
+/// `migrate`, and come out as `before`.
+struct Migration {
+    before: i32,
+    migrate: fn(&CompoundNbt<'static>) -> io::Result<CompoundNbt<'static>>,
+}
+
+/// In order, oldest first
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        before: UNSPANNED_DATA_VERSION,
+        migrate: unspan_block_states,
+    },
+    Migration {
+        before: FLAT_CHUNK_DATA_VERSION,
+        migrate: unwrap_level,
+    },
+];
+
+/// Brings a chunk read from a region file to the 1.18+ layout that `AnvilChunk::from_nbt()`
+/// reads, going by its `DataVersion`. Chunks from before 1.13 are an error: their numeric block
+/// IDs would need a whole table to turn into block states.
+pub fn migrate_chunk(chunk: &CompoundNbt<'static>) -> io::Result<CompoundNbt<'static>> {
+    let version = match chunk.get("DataVersion") {
+        Some(Nbt::Int(v)) => *v,
+        _ => 0,
+    };
+    if version < FLATTENING_DATA_VERSION {
+        return Err(invalid("chunk is from before 1.13"));
+    }
+    let mut chunk = chunk.clone();
+    for m in MIGRATIONS.iter().filter(|m| version < m.before) {
+        chunk = (m.migrate)(&chunk)?;
+        chunk.set("DataVersion", Nbt::Int(m.before));
+    }
+    Ok(chunk)
+}
+
+/// Before 20w17a, `BlockStates` was packed end to end, so values could straddle two longs
+fn unspan_block_states(chunk: &CompoundNbt<'static>) -> io::Result<CompoundNbt<'static>> {
+    let Some(Nbt::Compound(level)) = chunk.get("Level") else {
+        return Err(invalid("chunk without a Level"));
+    };
+    let mut sections = compound_list(level, "Sections")?.to_vec();
+    for section in &mut sections {
+        let (Some(Nbt::List(NbtList::Compound(palette))), Some(Nbt::LongArray(longs))) =
+            (section.get("Palette"), section.get("BlockStates"))
+        else {
+            continue;
+        };
+        let bits = block_bits(palette.len());
+        if longs.len() != SECTION_BLOCKS * usize::from(bits) / 64 {
+            return Err(invalid("wrong number of longs in BlockStates"));
+        }
+        let mask = (1u64 << bits) - 1;
+        let values: Vec<u32> = (0..SECTION_BLOCKS)
+            .map(|i| {
+                let bit = i * usize::from(bits);
+                let (long, shift) = (bit / 64, bit % 64);
+                let mut v = longs[long] as u64 >> shift;
+                if shift + usize::from(bits) > 64 {
+                    v |= (longs[long + 1] as u64) << (64 - shift);
+                }
+                (v & mask) as u32
+            })
+            .collect();
+        section.set("BlockStates", PackedIntArray::pack(bits, &values).to_nbt());
+    }
+    let mut level = level.clone();
+    level.set(
+        "Sections",
+        Nbt::List(NbtList::Compound(Cow::Owned(sections))),
+    );
+    let mut chunk = chunk.clone();
+    chunk.set("Level", Nbt::Compound(level));
+    Ok(chunk)
+}
+
+/// Before 21w43a, everything but `DataVersion` was in a `Level` compound, and some of it
+/// had other names
+fn unwrap_level(chunk: &CompoundNbt<'static>) -> io::Result<CompoundNbt<'static>> {
+    let Some(Nbt::Compound(level)) = chunk.get("Level") else {
+        return Err(invalid("chunk without a Level"));
+    };
+    let mut out = CompoundNbt::new(chunk.name().to_owned());
+    for (key, value) in level.props() {
+        let key = match key {
+            "Sections" => continue,
+            "TileEntities" => "block_entities",
+            "Entities" => "entities",
+            "TileTicks" => "block_ticks",
+            "LiquidTicks" => "fluid_ticks",
+            "Structures" => "structures",
+            key => key,
+        };
+        out.set(key.to_owned(), value.clone());
+    }
+    // old worlds were 256 blocks tall, starting at 0
+    out.set("yPos", Nbt::Int(0));
+
+    let mut sections = Vec::new();
+    for old in compound_list(level, "Sections")? {
+        let mut section = CompoundNbt::new("");
+        for key in ["Y", "BlockLight", "SkyLight"] {
+            if let Some(value) = old.get(key) {
+                section.set(key, value.clone());
+            }
+        }
+        let mut block_states = CompoundNbt::new("");
+        match old.get("Palette") {
+            Some(palette @ Nbt::List(NbtList::Compound(entries))) => {
+                block_states.set("palette", palette.clone());
+                // a single-state section has no data since 21w43a
+                if let (true, Some(data)) = (entries.len() > 1, old.get("BlockStates")) {
+                    block_states.set("data", data.clone());
+                }
+            }
+            // sections that are only there for their light
+            _ => {
+                let mut air = CompoundNbt::new("");
+                air.set("Name", Nbt::String("minecraft:air".into()));
+                block_states.set("palette", Nbt::List(NbtList::Compound(vec![air].into())));
+            }
+        }
+        section.set("block_states", Nbt::Compound(block_states));
+        sections.push(section);
+    }
+    out.set(
+        "sections",
+        Nbt::List(NbtList::Compound(Cow::Owned(sections))),
+    );
+    if let Some(version) = chunk.get("DataVersion") {
+        out.set("DataVersion", version.clone());
+    }
+    Ok(out)
+}
+
+/// Bits per block in a section with `palette_len` states. Vanilla never uses fewer than 4.
+fn block_bits(palette_len: usize) -> u8 {
+    PackedIntArray::bits_for(palette_len.saturating_sub(1) as u32).max(4)
+}
+
+/// A 16×16×16 section of a chunk read from a region file
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnvilSection {
+    /// Which section of the chunk this is, counting up from y = 0
+    pub y: i8,
+    pub palette: Vec<BlockState>,
+    /// Indices into `palette`, in YZX order. None if the palette has only one state.
+    data: Option<PackedIntArray>,
+}
+
+impl AnvilSection {
+    /// The state at `x`, `y`, `z` within the section (each 0-15)
+    pub fn block(&self, x: usize, y: usize, z: usize) -> &BlockState {
+        let i = match &self.data {
+            Some(data) => data.get((y * 16 + z) * 16 + x) as usize,
+            None => 0,
+        };
+        &self.palette[i]
+    }
+
+    /// Whether the section is all air
+    pub fn is_empty(&self) -> bool {
+        self.palette.iter().all(|state| {
+            matches!(
+                &*state.name,
+                "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air"
+            )
+        })
+    }
+
+    fn from_nbt(section: &CompoundNbt<'static>) -> io::Result<Option<Self>> {
+        let Some(&Nbt::Byte(y)) = section.get("Y") else {
+            return Err(invalid("section without a Y"));
+        };
+        let Some(Nbt::Compound(block_states)) = section.get("block_states") else {
+            return Ok(None);
+        };
+        let mut palette = Vec::new();
+        for state in compound_list(block_states, "palette")? {
+            let Some(Nbt::String(name)) = state.get("Name") else {
+                return Err(invalid("palette entry without a Name"));
+            };
+            let mut block = BlockState::new(name.to_string());
+            if let Some(Nbt::Compound(props)) = state.get("Properties") {
+                for (key, value) in props.props() {
+                    if let Nbt::String(value) = value {
+                        block = block.with(key, value.to_string());
+                    }
+                }
+                // compounds don't keep their order
+                block.properties.sort();
+            }
+            palette.push(block);
+        }
+        if palette.is_empty() {
+            return Err(invalid("section with an empty palette"));
+        }
+        let data = match block_states.get("data") {
+            Some(Nbt::LongArray(longs)) if palette.len() > 1 => {
+                let data = PackedIntArray::from_longs(
+                    block_bits(palette.len()),
+                    SECTION_BLOCKS,
+                    longs.to_vec(),
+                )
+                .ok_or_else(|| invalid("wrong number of longs in block states"))?;
+                if data.iter().any(|i| i as usize >= palette.len()) {
+                    return Err(invalid("block state isn't in the palette"));
+                }
+                Some(data)
+            }
+            None if palette.len() > 1 => return Err(invalid("section without block data")),
+            _ => None,
+        };
+        Ok(Some(Self { y, palette, data }))
+    }
+}
+
+/// The blocks of a chunk from a region file, after `migrate_chunk()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnvilChunk {
+    pub data_version: i32,
+    pub pos: ChunkPos,
+    /// Only the sections with block states, bottom first
+    pub sections: Vec<AnvilSection>,
+}
+
+impl AnvilChunk {
+    /// Reads a chunk in the 1.18+ layout
+    pub fn from_nbt(chunk: &CompoundNbt<'static>) -> io::Result<Self> {
+        let data_version = match chunk.get("DataVersion") {
+            Some(Nbt::Int(v)) => *v,
+            _ => 0,
+        };
+        let (Some(&Nbt::Int(x)), Some(&Nbt::Int(z))) = (chunk.get("xPos"), chunk.get("zPos"))
+        else {
+            return Err(invalid("chunk without a position"));
+        };
+        let mut sections = Vec::new();
+        for section in compound_list(chunk, "sections")? {
+            sections.extend(AnvilSection::from_nbt(section)?);
+        }
+        sections.sort_by_key(|s| s.y);
+        Ok(Self {
+            data_version,
+            pos: ChunkPos::new(x, z),
+            sections,
+        })
+    }
+
+    /// The state at a block position within the chunk (`x` and `z` 0-15), if there's a
+    /// section there
+    pub fn block(&self, x: usize, y: i32, z: usize) -> Option<&BlockState> {
+        let section = self
+            .sections
+            .iter()
+            .find(|s| i32::from(s.y) == y.div_euclid(16))?;
+        Some(section.block(x, y.rem_euclid(16) as usize, z))
+    }
+}
+
+/// A region file (`r.<x>.<z>.mca` in a dimension's `region/` folder), which holds the 32×32
+/// chunks starting at chunk `x * 32`, `z * 32`.
+///
+/// Chunks stored in their own `.mcc` file, which vanilla only does for ones over 1MiB, aren't
+/// supported.
+#[derive(Debug, Clone, Default)]
+pub struct RegionFile {
+    data: Vec<u8>,
+}
+
+impl RegionFile {
+    /// The file `chunk` is in, inside a `region/` folder
+    pub fn path_for(region_dir: &Path, chunk: ChunkPos) -> PathBuf {
+        region_dir.join(format!("r.{}.{}.mca", chunk.x >> 5, chunk.z >> 5))
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<Self> {
+        // vanilla can leave a file empty if it crashed while creating it
+        if !data.is_empty() && data.len() < 8192 {
+            return Err(invalid("region file is shorter than its header"));
+        }
+        Ok(Self { data })
+    }
+
+    /// A region file with no chunks in it yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The chunk's entry in the file: its length, compression and compressed NBT
+    fn entry(&self, index: usize) -> io::Result<Option<&[u8]>> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+        let i = 4 * index;
+        let location = u32::from_be_bytes(self.data[i..i + 4].try_into().unwrap());
+        if location == 0 {
+            return Ok(None);
+        }
+        // in 4KiB sectors: a 3 byte offset and a 1 byte length
+        let start = (location >> 8) as usize * 4096;
+        let Some([a, b, c, d, _, ..]) = self.data.get(start..start + 5) else {
+            return Err(invalid("chunk is past the end of the region file"));
+        };
+        let len = u32::from_be_bytes([*a, *b, *c, *d]) as usize;
+        // the length counts the compression byte
+        match self.data.get(start..start + 4 + len) {
+            Some(entry) if len > 0 => Ok(Some(entry)),
+            _ => Err(invalid("chunk is past the end of the region file")),
+        }
+    }
+
+    /// The chunk's NBT as it was saved, or None if it hasn't been generated.
+    /// Only the low 5 bits of `chunk`'s coordinates are used.
+    pub fn chunk_nbt(&self, chunk: ChunkPos) -> io::Result<Option<CompoundNbt<'static>>> {
+        let Some(entry) = self.entry(chunk.region_index())? else {
+            return Ok(None);
+        };
+        let body = &entry[5..];
+        let nbt = match entry[4] {
+            1 => gzip_decompress(body),
+            2 => zlib_decompress(body),
+            3 => Ok(body.to_vec()),
+            c if c & 0x80 != 0 => return Err(invalid("chunks in .mcc files aren't supported")),
+            _ => return Err(invalid("unknown chunk compression")),
+        }
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        Nbt::try_read_compound(&mut &nbt[..])
+            .map(Some)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Replaces the chunk's NBT, zlib compressed like vanilla does, or removes the chunk if `nbt`
+    /// is None. The other chunks are kept as they were, packed together from the start of the file.
+    pub fn set_chunk_nbt(
+        &mut self,
+        chunk: ChunkPos,
+        nbt: Option<&CompoundNbt<'_>>,
+    ) -> io::Result<()> {
+        let index = chunk.region_index();
+        let new_entry = nbt.map(|nbt| {
+            let mut raw = Vec::new();
+            write_compound_nbt(&mut raw, nbt);
+            let body = zlib_compress(&raw);
+            let mut entry = (body.len() as u32 + 1).to_be_bytes().to_vec();
+            entry.push(2);
+            entry.extend(body);
+            entry
+        });
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+
+        let mut data = vec![0; 8192];
+        for i in 0..1024 {
+            let (entry, timestamp) = if i == index {
+                (new_entry.as_deref(), now.to_be_bytes())
+            } else {
+                let Some(entry) = self.entry(i)? else {
+                    continue;
+                };
+                let t = 4096 + 4 * i;
+                (Some(entry), self.data[t..t + 4].try_into().unwrap())
+            };
+            let Some(entry) = entry else {
+                continue;
+            };
+            let sector = data.len() / 4096;
+            let sectors = entry.len().div_ceil(4096);
+            if sectors > 255 {
+                return Err(invalid("chunk is too big for a region file"));
+            }
+            data[4 * i..4 * i + 4]
+                .copy_from_slice(&((sector as u32) << 8 | sectors as u32).to_be_bytes());
+            data[4096 + 4 * i..4096 + 4 * i + 4].copy_from_slice(&timestamp);
+            data.extend_from_slice(entry);
+            data.resize((sector + sectors) * 4096, 0);
+        }
+        self.data = data;
+        Ok(())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_atomically(path, &self.data)
+    }
+
+    /// The chunk's blocks, brought up to date with `migrate_chunk()`
+    pub fn load_chunk(&self, chunk: ChunkPos) -> io::Result<Option<AnvilChunk>> {
+        match self.chunk_nbt(chunk)? {
+            Some(nbt) => AnvilChunk::from_nbt(&migrate_chunk(&nbt)?).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::gzip_compress;
+
+    /// A region file with `chunks` at the start of the region, gzipped
+    fn region(chunks: &[(ChunkPos, &CompoundNbt<'static>)]) -> RegionFile {
+        let mut data = vec![0; 8192];
+        for (pos, chunk) in chunks {
+            let mut nbt = Vec::new();
+            write_compound_nbt(&mut nbt, chunk);
+            let body = gzip_compress(&nbt);
+            let sector = data.len() / 4096;
+            let sectors = (body.len() + 5).div_ceil(4096);
+            let i = 4 * (pos.x + pos.z * 32) as usize;
+            data[i..i + 4].copy_from_slice(&((sector as u32) << 8 | sectors as u32).to_be_bytes());
+            data.extend_from_slice(&(body.len() as u32 + 1).to_be_bytes());
+            data.push(1);
+            data.extend_from_slice(&body);
+            data.resize((sector + sectors) * 4096, 0);
+        }
+        RegionFile::from_bytes(data).unwrap()
+    }
+
+    #[test]
+    fn migrates_old_chunks() {
+        // a 1.15 chunk: 17 states take 5 bits, which straddle longs when packed end to end
+        let palette: Vec<_> = (0..17)
+            .map(|i| {
+                let mut c = CompoundNbt::new("");
+                c.set("Name", Nbt::String(format!("minecraft:block_{i}").into()));
+                c
+            })
+            .collect();
+        let mut longs = vec![0i64; 64 * 5];
+        for i in 0..SECTION_BLOCKS {
+            let (bit, v) = (i * 5, (i % 17) as u64);
+            longs[bit / 64] |= (v << (bit % 64)) as i64;
+            if bit % 64 > 59 {
+                longs[bit / 64 + 1] |= (v >> (64 - bit % 64)) as i64;
+            }
+        }
+        let mut section = CompoundNbt::new("");
+        section.set("Y", Nbt::Byte(2));
+        section.set("Palette", Nbt::List(NbtList::Compound(palette.into())));
+        section.set("BlockStates", Nbt::LongArray(longs.into()));
+        let mut light_only = CompoundNbt::new("");
+        light_only.set("Y", Nbt::Byte(-1));
+        let mut level = CompoundNbt::new("");
+        level.set("xPos", Nbt::Int(3));
+        level.set("zPos", Nbt::Int(1));
+        level.set(
+            "Sections",
+            Nbt::List(NbtList::Compound(vec![section, light_only].into())),
+        );
+        level.set("TileEntities", Nbt::List(NbtList::Compound(vec![].into())));
+        let mut old = CompoundNbt::new("");
+        old.set("DataVersion", Nbt::Int(2230));
+        old.set("Level", Nbt::Compound(level));
+
+        let mut ancient = old.clone();
+        ancient.set("DataVersion", Nbt::Int(1343));
+        let mut garbage = CompoundNbt::new("");
+        garbage.set("DataVersion", Nbt::Int(3578));
+        garbage.set("sections", Nbt::Int(7));
+
+        let region = region(&[
+            (ChunkPos::new(3, 1), &old),
+            (ChunkPos::new(0, 0), &ancient),
+            (ChunkPos::new(1, 0), &garbage),
+        ]);
+        let chunk = region.load_chunk(ChunkPos::new(35, -31)).unwrap().unwrap();
+        assert_eq!(chunk.data_version, FLAT_CHUNK_DATA_VERSION);
+        assert_eq!(chunk.pos, ChunkPos::new(3, 1));
+        assert_eq!(chunk.sections.len(), 2);
+        assert!(chunk.sections[0].is_empty());
+        for i in [0, 12, 13, 100, 4095] {
+            let (x, z, y) = (i % 16, i / 16 % 16, i / 256);
+            let state = chunk.block(x, 32 + y as i32, z).unwrap();
+            assert_eq!(state.name, format!("minecraft:block_{}", i % 17));
+        }
+        assert_eq!(chunk.block(0, 100, 0), None);
+        let migrated = migrate_chunk(&old).unwrap();
+        assert!(migrated.get("Level").is_none());
+        assert!(migrated.get("block_entities").is_some());
+
+        assert!(region.load_chunk(ChunkPos::new(0, 0)).is_err());
+        assert!(region.load_chunk(ChunkPos::new(1, 0)).is_err());
+        assert!(region.load_chunk(ChunkPos::new(2, 0)).unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_vanilla_chunks() {
+        // trimmed, but with the lists of lists and lists of arrays vanilla writes
+        let new = CompoundNbt::parse_snbt(
+            r#"{
+                DataVersion: 3578, xPos: 0, zPos: 0, yPos: -4, Status: "minecraft:full",
+                sections: [{
+                    Y: -4b,
+                    block_states: {palette: [{Name: "minecraft:bedrock"}]},
+                    biomes: {palette: ["minecraft:plains"]}
+                }],
+                Heightmaps: {MOTION_BLOCKING: [L; 1L, 2L], WORLD_SURFACE: [L; 3L]},
+                PostProcessing: [[], [], [5s, 17s], [], [], [], [], [], [], [], [], [], [], [],
+                    [], [], [], [], [], [], [], [], [], []],
+                block_entities: [], block_ticks: [], fluid_ticks: [],
+                structures: {References: {}, starts: {}},
+                Sample: [[I; 1, 2], [I;]]
+            }"#,
+            "",
+        )
+        .unwrap();
+        let mut level = CompoundNbt::parse_snbt(
+            r#"{
+                xPos: 1, zPos: 0, Status: "full",
+                Heightmaps: {MOTION_BLOCKING: [L; 1L]},
+                Biomes: [I; 1, 1, 1],
+                Lights: [[], [0s, 4098s], [], [], [], [], [], [], [], [], [], [], [], [], [], []],
+                ToBeTicked: [[], [], [273s]],
+                LiquidsToBeTicked: [[], []],
+                PostProcessing: [[], [1s]],
+                TileEntities: [], Entities: [], TileTicks: [], LiquidTicks: []
+            }"#,
+            "",
+        )
+        .unwrap();
+        let mut section =
+            CompoundNbt::parse_snbt(r#"{Y: 0b, Palette: [{Name: "minecraft:stone"}]}"#, "")
+                .unwrap();
+        section.set("BlockStates", Nbt::LongArray(vec![0; 256].into()));
+        level.set(
+            "Sections",
+            Nbt::List(NbtList::Compound(vec![section].into())),
+        );
+        let mut old = CompoundNbt::new("");
+        old.set("DataVersion", Nbt::Int(2230));
+        old.set("Level", Nbt::Compound(level.clone()));
+
+        let region = region(&[(ChunkPos::new(0, 0), &new), (ChunkPos::new(1, 0), &old)]);
+        assert_eq!(region.chunk_nbt(ChunkPos::new(0, 0)).unwrap(), Some(new));
+        let chunk = region.load_chunk(ChunkPos::new(0, 0)).unwrap().unwrap();
+        assert_eq!(chunk.block(3, -64, 3).unwrap().name, "minecraft:bedrock");
+        let chunk = region.load_chunk(ChunkPos::new(1, 0)).unwrap().unwrap();
+        assert_eq!(chunk.block(0, 15, 0).unwrap().name, "minecraft:stone");
+        let migrated = migrate_chunk(&old).unwrap();
+        assert_eq!(migrated.get("ToBeTicked"), level.get("ToBeTicked"));
+    }
+}
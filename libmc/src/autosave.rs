@@ -0,0 +1,336 @@
+use crate::nbt::write_nbt_file;
+use crate::*;
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often vanilla saves (every 6000 ticks)
+pub const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Writes something out. Made on the main thread from the world as it is, run on the saver's thread.
+pub type SaveTask = Box<dyn FnOnce() -> io::Result<()> + Send>;
+
+/// What an `AutoSaver` asks the world for when saving
+pub trait SaveSource {
+    /// A task that writes `chunk` as it is now, or None if there's nothing to save (e.g. it unloaded)
+    fn save_chunk(&mut self, chunk: ChunkPos) -> Option<SaveTask>;
+    /// The contents of `level.dat`
+    fn level_data(&mut self) -> CompoundNbt<'static>;
+    /// The data of an online player, or None if they're gone
+    fn player_data(&mut self, uuid: Uuid) -> Option<CompoundNbt<'static>>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SaveItem {
+    Chunk(ChunkPos),
+    LevelDat,
+    Player(Uuid),
+}
+
+#[derive(Debug)]
+pub enum SaveProgress {
+    /// A save began, with this many things to write
+    Started {
+        total: usize,
+    },
+    Saved(SaveItem),
+    /// Writing failed. The item is saved again with the next save.
+    Failed(SaveItem, io::Error),
+    Finished {
+        saved: usize,
+        failed: usize,
+        took: Duration,
+    },
+}
+
+impl fmt::Display for SaveProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveProgress::Started { total } => write!(f, "saving {total} things"),
+            SaveProgress::Saved(item) => write!(f, "saved {item:?}"),
+            SaveProgress::Failed(item, e) => write!(f, "saving {item:?} failed: {e}"),
+            SaveProgress::Finished {
+                saved,
+                failed,
+                took,
+            } => {
+                write!(f, "saved {saved} things ({failed} failed) in {took:?}")
+            }
+        }
+    }
+}
+
+type ProgressCallback = Box<dyn FnMut(&SaveProgress)>;
+
+/// Saves a world in the background: dirty chunks, `level.dat` and player data are gathered on
+/// the main thread (see `SaveSource`) and written out on a thread of the saver's own.
+///
+/// Mark what changes as dirty, and call `tick()` every tick; it saves every `interval`, and
+/// passes progress to the `on_progress()` callbacks. `save_all()` saves right away, like
+/// `/save-all`, and `shutdown()` saves and waits for everything to be written.
+pub struct AutoSaver<T: Clock = SystemClock> {
+    clock: T,
+    interval: Duration,
+    last_save: Instant,
+    level_dat: PathBuf,
+    players: PlayerDataStore,
+    dirty_chunks: HashSet<ChunkPos>,
+    dirty_level: bool,
+    dirty_players: HashSet<Uuid>,
+    /// None once shut down, to stop the thread
+    jobs: Option<Sender<Vec<(SaveItem, SaveTask)>>>,
+    progress: Receiver<SaveProgress>,
+    /// Saves handed to the thread that haven't finished
+    in_flight: usize,
+    callbacks: Vec<ProgressCallback>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl AutoSaver {
+    /// Saves into the world directory `world_dir` (which has `level.dat` and `playerdata/`)
+    pub fn new(world_dir: &Path, interval: Duration) -> io::Result<Self> {
+        Self::with_clock(world_dir, interval, SystemClock)
+    }
+}
+
+impl<T: Clock> AutoSaver<T> {
+    pub fn with_clock(world_dir: &Path, interval: Duration, clock: T) -> io::Result<Self> {
+        let players = PlayerDataStore::open(world_dir.join("playerdata"))?;
+        let (jobs, job_rx) = mpsc::channel::<Vec<(SaveItem, SaveTask)>>();
+        let (progress_tx, progress) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            for batch in job_rx {
+                let start = Instant::now();
+                let (mut saved, mut failed) = (0, 0);
+                let _ = progress_tx.send(SaveProgress::Started { total: batch.len() });
+                for (item, task) in batch {
+                    let event = match task() {
+                        Ok(()) => {
+                            saved += 1;
+                            SaveProgress::Saved(item)
+                        }
+                        Err(e) => {
+                            failed += 1;
+                            SaveProgress::Failed(item, e)
+                        }
+                    };
+                    let _ = progress_tx.send(event);
+                }
+                let _ = progress_tx.send(SaveProgress::Finished {
+                    saved,
+                    failed,
+                    took: start.elapsed(),
+                });
+            }
+        });
+        Ok(Self {
+            last_save: clock.now(),
+            clock,
+            interval,
+            level_dat: world_dir.join("level.dat"),
+            players,
+            dirty_chunks: HashSet::new(),
+            dirty_level: false,
+            dirty_players: HashSet::new(),
+            jobs: Some(jobs),
+            progress,
+            in_flight: 0,
+            callbacks: Vec::new(),
+            worker: Some(worker),
+        })
+    }
+
+    /// Calls `f` with the progress of saves, on the main thread (from `tick()`, `flush()` and `shutdown()`)
+    pub fn on_progress(&mut self, f: impl FnMut(&SaveProgress) + 'static) {
+        self.callbacks.push(Box::new(f));
+    }
+
+    pub fn mark_chunk_dirty(&mut self, chunk: ChunkPos) {
+        self.dirty_chunks.insert(chunk);
+    }
+
+    pub fn mark_level_dirty(&mut self) {
+        self.dirty_level = true;
+    }
+
+    pub fn mark_player_dirty(&mut self, uuid: Uuid) {
+        self.dirty_players.insert(uuid);
+    }
+
+    fn mark_dirty(&mut self, item: SaveItem) {
+        match item {
+            SaveItem::Chunk(chunk) => self.mark_chunk_dirty(chunk),
+            SaveItem::LevelDat => self.mark_level_dirty(),
+            SaveItem::Player(uuid) => self.mark_player_dirty(uuid),
+        }
+    }
+
+    /// How many things would be written by a save now
+    pub fn dirty_count(&self) -> usize {
+        self.dirty_chunks.len() + usize::from(self.dirty_level) + self.dirty_players.len()
+    }
+
+    /// Whether a save is being written
+    pub fn is_saving(&self) -> bool {
+        self.in_flight > 0
+    }
+
+    /// Starts a save if it's been `interval` since the last one, and reports progress.
+    /// Returns whether a save was started.
+    pub fn tick(&mut self, source: &mut impl SaveSource) -> bool {
+        self.poll_progress();
+        if self.clock.now() - self.last_save < self.interval {
+            return false;
+        }
+        self.save_all(source);
+        true
+    }
+
+    /// Starts saving everything that's dirty right away
+    pub fn save_all(&mut self, source: &mut impl SaveSource) {
+        self.last_save = self.clock.now();
+        let mut batch: Vec<(SaveItem, SaveTask)> = Vec::new();
+        for chunk in std::mem::take(&mut self.dirty_chunks) {
+            if let Some(task) = source.save_chunk(chunk) {
+                batch.push((SaveItem::Chunk(chunk), task));
+            }
+        }
+        if std::mem::take(&mut self.dirty_level) {
+            let data = source.level_data();
+            let path = self.level_dat.clone();
+            batch.push((
+                SaveItem::LevelDat,
+                Box::new(move || write_nbt_file(&path, &data)),
+            ));
+        }
+        for uuid in std::mem::take(&mut self.dirty_players) {
+            if let Some(data) = source.player_data(uuid) {
+                let store = self.players.clone();
+                batch.push((
+                    SaveItem::Player(uuid),
+                    Box::new(move || store.save(uuid, &data)),
+                ));
+            }
+        }
+        if batch.is_empty() {
+            return;
+        }
+        self.in_flight += 1;
+        self.jobs.as_ref().unwrap().send(batch).unwrap();
+    }
+
+    /// Waits until every started save is written
+    pub fn flush(&mut self) {
+        while self.in_flight > 0 {
+            let Ok(event) = self.progress.recv() else {
+                break;
+            };
+            self.handle_progress(event);
+        }
+    }
+
+    /// Saves everything and waits for it to be written, e.g. from `Server::on_shutdown()`
+    pub fn shutdown(mut self, source: &mut impl SaveSource) {
+        self.save_all(source);
+        self.flush();
+    }
+
+    fn poll_progress(&mut self) {
+        while let Ok(event) = self.progress.try_recv() {
+            self.handle_progress(event);
+        }
+    }
+
+    fn handle_progress(&mut self, event: SaveProgress) {
+        match &event {
+            SaveProgress::Failed(item, _) => self.mark_dirty(*item),
+            SaveProgress::Finished { .. } => self.in_flight -= 1,
+            _ => {}
+        }
+        for f in &mut self.callbacks {
+            f(&event);
+        }
+    }
+}
+
+impl<T: Clock> Drop for AutoSaver<T> {
+    fn drop(&mut self) {
+        // lets the thread finish what it was given, then stop
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct World {
+        dir: PathBuf,
+    }
+
+    impl SaveSource for World {
+        fn save_chunk(&mut self, chunk: ChunkPos) -> Option<SaveTask> {
+            let path = self.dir.join(format!("chunk.{}.{}", chunk.x, chunk.z));
+            Some(Box::new(move || std::fs::write(path, b"chunk")))
+        }
+
+        fn level_data(&mut self) -> CompoundNbt<'static> {
+            let mut data = CompoundNbt::new("");
+            data.set("Time", Nbt::Long(1000));
+            data
+        }
+
+        fn player_data(&mut self, _uuid: Uuid) -> Option<CompoundNbt<'static>> {
+            Some(CompoundNbt::new(""))
+        }
+    }
+
+    #[test]
+    fn saving() {
+        let dir = std::env::temp_dir().join(format!("libmc-autosave-{}", std::process::id()));
+        let mut world = World { dir: dir.clone() };
+        let clock = ManualClock::new();
+        let mut saver =
+            AutoSaver::with_clock(&dir, Duration::from_secs(60), clock.clone()).unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let events2 = Rc::clone(&events);
+        saver.on_progress(move |p| events2.borrow_mut().push(p.to_string()));
+
+        saver.mark_chunk_dirty(ChunkPos::new(1, -2));
+        saver.mark_level_dirty();
+        saver.mark_player_dirty(Uuid(7));
+        assert_eq!(saver.dirty_count(), 3);
+        assert!(!saver.tick(&mut world));
+
+        clock.advance(Duration::from_secs(60));
+        assert!(saver.tick(&mut world));
+        assert_eq!(saver.dirty_count(), 0);
+        saver.flush();
+        assert!(!saver.is_saving());
+        assert!(dir.join("chunk.1.-2").exists());
+        assert!(dir.join("level.dat").exists());
+        assert!(PlayerDataStore::open(dir.join("playerdata"))
+            .unwrap()
+            .has_played_before(Uuid(7)));
+        assert_eq!(events.borrow().first().unwrap(), "saving 3 things");
+        assert!(events
+            .borrow()
+            .last()
+            .unwrap()
+            .starts_with("saved 3 things (0 failed)"));
+
+        saver.mark_chunk_dirty(ChunkPos::new(0, 0));
+        saver.shutdown(&mut world);
+        assert!(dir.join("chunk.0.0").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
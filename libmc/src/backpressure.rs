@@ -0,0 +1,118 @@
+use crate::sendqueue::MAX_QUEUED_BYTES;
+use crate::*;
+
+/// What's done about a client that isn't reading as fast as it's sent to, going by how many
+/// bytes are waiting to be written to it. Past `backed_up_at` it's backed up, and packets that
+/// can wait are held back; at `kick_at` it's disconnected.
+///
+/// Set it for every client with `ServerBuilder::backpressure()`, or for one with
+/// `ServerHandle::set_backpressure()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackpressurePolicy {
+    /// From this many bytes waiting, the client is backed up
+    pub backed_up_at: usize,
+    /// While backed up, entity updates that later ones make up for (see
+    /// `OutPacket::is_droppable()`) aren't sent
+    pub drop_entity_updates: bool,
+    /// While backed up, chunks aren't sent: `ServerHandle::send_chunk()` returns false, and
+    /// `ServerHandle::is_backed_up()` says to hold off on the next ones
+    pub pause_chunks: bool,
+    /// The client is kicked once this many bytes are waiting. It's never more than 8 MiB, the
+    /// most that's ever queued for a client.
+    pub kick_at: usize,
+}
+
+impl Default for BackpressurePolicy {
+    fn default() -> Self {
+        Self {
+            backed_up_at: 1024 * 1024,
+            drop_entity_updates: true,
+            pause_chunks: true,
+            kick_at: MAX_QUEUED_BYTES,
+        }
+    }
+}
+
+/// What a `BackpressurePolicy` says to do with a packet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BackpressureAction {
+    Send,
+    /// Don't send it
+    Drop,
+    /// Disconnect the client
+    Kick,
+}
+
+impl BackpressurePolicy {
+    /// Only kicks clients, at `kick_at` bytes waiting
+    pub fn kick_only(kick_at: usize) -> Self {
+        Self {
+            backed_up_at: kick_at,
+            drop_entity_updates: false,
+            pause_chunks: false,
+            kick_at,
+        }
+    }
+
+    pub fn is_backed_up(&self, queued: usize) -> bool {
+        queued >= self.backed_up_at
+    }
+
+    /// What to do with `packet` when `queued` bytes are waiting to be written
+    pub fn action(&self, queued: usize, packet: &OutPacket) -> BackpressureAction {
+        self.action_for(queued, packet.category(), packet.is_droppable())
+    }
+
+    /// Like `action()`, for an already encoded packet of `category`
+    pub fn action_for(
+        &self,
+        queued: usize,
+        category: PacketCategory,
+        droppable: bool,
+    ) -> BackpressureAction {
+        if queued >= self.kick_at {
+            BackpressureAction::Kick
+        } else if self.is_backed_up(queued)
+            && ((droppable && self.drop_entity_updates)
+                || (category == PacketCategory::Chunks && self.pause_chunks))
+        {
+            BackpressureAction::Drop
+        } else {
+            BackpressureAction::Send
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_back_then_kicks() {
+        let policy = BackpressurePolicy {
+            backed_up_at: 100,
+            kick_at: 1000,
+            ..Default::default()
+        };
+        let turn = OutPacket::SetHeadRotation {
+            entity_id: 1,
+            head_yaw: Angle(0),
+        };
+        let chat = TextComponent::text("hi");
+        let chat = OutPacket::SystemChat {
+            content: &chat,
+            overlay: false,
+        };
+        assert_eq!(policy.action(99, &turn), BackpressureAction::Send);
+        assert_eq!(policy.action(100, &turn), BackpressureAction::Drop);
+        assert_eq!(policy.action(500, &chat), BackpressureAction::Send);
+        assert_eq!(
+            policy.action_for(500, PacketCategory::Chunks, false),
+            BackpressureAction::Drop
+        );
+        assert_eq!(policy.action(1000, &chat), BackpressureAction::Kick);
+
+        let policy = BackpressurePolicy::kick_only(1000);
+        assert_eq!(policy.action(999, &turn), BackpressureAction::Send);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Rates are measured over this long
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Chunks are deferred once this fraction of the cap is used, leaving headroom for entity updates
+const CHUNK_SHARE: f64 = 0.75;
+
+/// What kind of data a packet carries, for bandwidth accounting
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PacketCategory {
+    Chunks,
+    Entities,
+    Chat,
+    Other,
+}
+
+impl PacketCategory {
+    pub const ALL: [PacketCategory; 4] = [
+        PacketCategory::Chunks,
+        PacketCategory::Entities,
+        PacketCategory::Chat,
+        PacketCategory::Other,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Lowercase, e.g. for labelling metrics
+    pub fn name(self) -> &'static str {
+        match self {
+            PacketCategory::Chunks => "chunks",
+            PacketCategory::Entities => "entities",
+            PacketCategory::Chat => "chat",
+            PacketCategory::Other => "other",
+        }
+    }
+
+    /// Whether packets of this category can be held back by a bandwidth cap
+    pub fn is_deferrable(self) -> bool {
+        matches!(self, PacketCategory::Chunks | PacketCategory::Entities)
+    }
+}
+
+impl OutPacket<'_> {
+    pub fn category(&self) -> PacketCategory {
+        match self {
+            OutPacket::ChunkDataAndUpdateLight { .. } => PacketCategory::Chunks,
+            OutPacket::SetEntityMetadata { .. }
+            | OutPacket::SpawnEntity { .. }
+            | OutPacket::SetEntityVelocity { .. }
+            | OutPacket::RemoveEntities { .. }
+            | OutPacket::UpdateEntityRotation { .. }
+            | OutPacket::SetHeadRotation { .. }
+            | OutPacket::UpdateEntityPosition { .. }
+            | OutPacket::UpdateEntityPositionAndRotation { .. }
+            | OutPacket::TeleportEntity { .. }
+            | OutPacket::SetEquipment { .. }
+            | OutPacket::EntityEvent { .. }
+            | OutPacket::LinkEntities { .. } => PacketCategory::Entities,
+            OutPacket::SystemChat { .. }
+            | OutPacket::PlayerChat { .. }
+            | OutPacket::SetActionBarText { .. }
+            | OutPacket::SetTitleText { .. }
+            | OutPacket::SetSubtitleText { .. } => PacketCategory::Chat,
+            _ => PacketCategory::Other,
+        }
+    }
+
+    /// Whether a later packet makes up for this one not being sent, so it can be dropped for
+    /// clients that are behind: entity rotations, head rotations and velocities. Moves are
+    /// relative, so they can't be.
+    pub fn is_droppable(&self) -> bool {
+        matches!(
+            self,
+            OutPacket::UpdateEntityRotation { .. }
+                | OutPacket::SetHeadRotation { .. }
+                | OutPacket::SetEntityVelocity { .. }
+        )
+    }
+}
+
+/// Bytes per second sent to a client, by category
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct BandwidthStats {
+    pub chunks: u64,
+    pub entities: u64,
+    pub chat: u64,
+    pub other: u64,
+    /// Packets per second
+    pub packets: u64,
+    /// All bytes ever sent, not per second
+    pub total_bytes: u64,
+}
+
+impl BandwidthStats {
+    pub fn bytes_per_second(&self) -> u64 {
+        self.chunks + self.entities + self.chat + self.other
+    }
+
+    /// Bytes per second of one category
+    pub fn bytes_of(&self, category: PacketCategory) -> u64 {
+        match category {
+            PacketCategory::Chunks => self.chunks,
+            PacketCategory::Entities => self.entities,
+            PacketCategory::Chat => self.chat,
+            PacketCategory::Other => self.other,
+        }
+    }
+
+    /// Adds up the stats of several clients
+    pub fn add(&mut self, other: &BandwidthStats) {
+        self.chunks += other.chunks;
+        self.entities += other.entities;
+        self.chat += other.chat;
+        self.other += other.other;
+        self.packets += other.packets;
+        self.total_bytes += other.total_bytes;
+    }
+}
+
+/// Tracks how much is sent to one client, and optionally caps it by deferring low-priority data.
+///
+/// Chat and other packets are never deferred. Entity packets are deferred once the cap is
+/// reached, and chunks somewhat before that. The server holds deferred packets back and sends them
+/// on later ticks, but it's cheaper to check `allows()` before generating deferrable packets (e.g.
+/// before taking the next chunk from a `ChunkSendQueue`), and try again next tick if it says no.
+#[derive(Debug, Default)]
+pub struct BandwidthTracker {
+    /// bytes per second
+    cap: Option<u64>,
+    /// (when, category, bytes) of the packets sent during the last `WINDOW`
+    recent: VecDeque<(Instant, PacketCategory, u64)>,
+    /// bytes in `recent`, by category
+    window_bytes: [u64; 4],
+    total_bytes: u64,
+}
+
+impl BandwidthTracker {
+    pub fn new(cap: Option<u64>) -> Self {
+        Self {
+            cap,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_cap(&mut self, cap: Option<u64>) {
+        self.cap = cap;
+    }
+
+    pub fn record(&mut self, category: PacketCategory, bytes: usize) {
+        let bytes = bytes as u64;
+        self.expire(Instant::now());
+        self.recent.push_back((Instant::now(), category, bytes));
+        self.window_bytes[category.index()] += bytes;
+        self.total_bytes += bytes;
+    }
+
+    /// Whether a packet of `category` can be sent now without going over the cap
+    pub fn allows(&mut self, category: PacketCategory) -> bool {
+        let Some(cap) = self.cap else {
+            return true;
+        };
+        self.expire(Instant::now());
+        let rate = self.window_bytes.iter().sum::<u64>() as f64;
+        match category {
+            PacketCategory::Chunks => rate < cap as f64 * CHUNK_SHARE,
+            PacketCategory::Entities => rate < cap as f64,
+            PacketCategory::Chat | PacketCategory::Other => true,
+        }
+    }
+
+    pub fn stats(&mut self) -> BandwidthStats {
+        self.expire(Instant::now());
+        let [chunks, entities, chat, other] = self.window_bytes;
+        BandwidthStats {
+            chunks,
+            entities,
+            chat,
+            other,
+            packets: self.recent.len() as u64,
+            total_bytes: self.total_bytes,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(when, category, bytes)) = self.recent.front() {
+            if now.duration_since(when) < WINDOW {
+                break;
+            }
+            self.window_bytes[category.index()] -= bytes;
+            self.recent.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defers_chunks_first() {
+        let mut bw = BandwidthTracker::new(Some(1000));
+        assert_eq!(
+            OutPacket::SetEntityVelocity {
+                entity_id: 1,
+                velocity: [0; 3]
+            }
+            .category(),
+            PacketCategory::Entities
+        );
+
+        bw.record(PacketCategory::Chunks, 800);
+        assert!(!bw.allows(PacketCategory::Chunks));
+        assert!(bw.allows(PacketCategory::Entities));
+        bw.record(PacketCategory::Entities, 200);
+        assert!(!bw.allows(PacketCategory::Entities));
+        assert!(bw.allows(PacketCategory::Chat));
+
+        let stats = bw.stats();
+        assert_eq!((stats.chunks, stats.entities), (800, 200));
+        assert_eq!(stats.bytes_per_second(), 1000);
+        assert_eq!(stats.packets, 2);
+        assert_eq!(stats.bytes_of(PacketCategory::Chunks), 800);
+
+        assert!(PacketCategory::Chunks.is_deferrable());
+        assert!(!PacketCategory::Chat.is_deferrable());
+
+        bw.set_cap(None);
+        assert!(bw.allows(PacketCategory::Chunks));
+    }
+}
@@ -0,0 +1,896 @@
+//! An experimental front-end for Bedrock edition clients, over `RakNetServer`.
+//!
+//! It takes clients through network settings, login and resource packs, and translates their
+//! login, movement and chat packets onto libmc's model (`BedrockPacket::to_in_packet()`).
+//!
+//! With `ServerBuilder::bedrock()`, `run_server()` runs a front-end on its own thread and hands
+//! the clients that log in to the `Server` like any other, tagged `BEDROCK_LISTENER`. What is sent
+//! to them is translated by a `BedrockTranslator`: the world, position and chat, but not yet
+//! entities, inventories or anything else. Servers can also poll a `BedrockFrontend` themselves.
+//!
+//! Like the Java side, there's no authentication: the identity in the login chain is trusted
+//! without checking its signatures.
+
+use crate::compress::{deflate_stored, inflate};
+use crate::util::base64_decode;
+use crate::*;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The Bedrock protocol version the front-end speaks
+pub const BEDROCK_PROTOCOL_VERSION: i32 = 622;
+
+/// The Bedrock version that `BEDROCK_PROTOCOL_VERSION` belongs to
+pub const BEDROCK_VERSION: &str = "1.20.40";
+
+/// The RakNet message ID of a batch of game packets
+const GAME_PACKET: u8 = 0xFE;
+
+const LOGIN: u32 = 0x01;
+pub(crate) const PLAY_STATUS: u32 = 0x02;
+const RESOURCE_PACKS_INFO: u32 = 0x06;
+const RESOURCE_PACK_STACK: u32 = 0x07;
+const RESOURCE_PACK_CLIENT_RESPONSE: u32 = 0x08;
+pub(crate) const TEXT: u32 = 0x09;
+pub(crate) const MOVE_PLAYER: u32 = 0x13;
+const NETWORK_SETTINGS: u32 = 0x8F;
+const PLAYER_AUTH_INPUT: u32 = 0x90;
+const REQUEST_NETWORK_SETTINGS: u32 = 0xC1;
+
+/// How far Bedrock's player positions (the eyes) are above Java's (the feet)
+pub(crate) const EYE_HEIGHT: f64 = 1.62;
+
+/// Statuses of Play Status
+const LOGIN_SUCCESS: i32 = 0;
+const FAILED_CLIENT: i32 = 1;
+const FAILED_SERVER: i32 = 2;
+
+/// The Resource Pack Client Response that ends the resource pack exchange
+const PACKS_COMPLETED: u8 = 4;
+
+/// How long a Bedrock client of `run_server()` that's being disconnected keeps its connection,
+/// so that what was sent to it last (like why) still gets there
+const CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// How long `serve_bedrock()` waits for datagrams before sending what the server wrote
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A packet from a Bedrock client
+#[derive(Debug, Clone, PartialEq)]
+pub enum BedrockPacket {
+    /// Sent once the client is logged in and done with resource packs, which the front-end has
+    /// already taken it through
+    Login {
+        username: String,
+        uuid: Uuid,
+        /// The player's Xbox user ID, if signed in to Xbox Live
+        xuid: Option<String>,
+    },
+    /// Move Player or Player Auth Input
+    Move {
+        /// The feet, as on Java
+        pos: Vec3,
+        yaw: f32,
+        pitch: f32,
+        /// Player Auth Input doesn't say
+        on_ground: Option<bool>,
+    },
+    Chat {
+        message: String,
+    },
+    /// A packet the front-end doesn't translate
+    Other {
+        id: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl BedrockPacket {
+    /// The Java packet this corresponds to, if there is one
+    pub fn to_in_packet(&self) -> Option<InPacket<'_>> {
+        match self {
+            BedrockPacket::Login { username, uuid, .. } => Some(InPacket::LoginStart {
+                name: username,
+                player_uuid: *uuid,
+            }),
+            &BedrockPacket::Move {
+                pos,
+                yaw,
+                pitch,
+                on_ground,
+            } => Some(InPacket::SetPlayerPositionAndRotation {
+                x: pos.x,
+                y: pos.y,
+                z: pos.z,
+                yaw,
+                pitch,
+                on_ground: on_ground.unwrap_or(true),
+            }),
+            BedrockPacket::Chat { message } => Some(InPacket::ChatMessage {
+                message,
+                timestamp: 0,
+                salt: 0,
+                signature: None,
+            }),
+            BedrockPacket::Other { .. } => None,
+        }
+    }
+}
+
+/// Reads Bedrock's little-endian and varint fields
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Some(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N).map(|b| b.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.array::<1>().map(|[b]| b)
+    }
+
+    fn u32_le(&mut self) -> Option<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn i32_be(&mut self) -> Option<i32> {
+        self.array().map(i32::from_be_bytes)
+    }
+
+    fn f32_le(&mut self) -> Option<f32> {
+        self.array().map(f32::from_le_bytes)
+    }
+
+    fn vec3(&mut self) -> Option<Vec3> {
+        let (x, y, z) = (self.f32_le()?, self.f32_le()?, self.f32_le()?);
+        Some(Vec3::new(x.into(), y.into(), z.into()))
+    }
+
+    fn varuint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for i in 0..10 {
+            let b = self.u8()?;
+            value |= u64::from(b & 0x7F) << (7 * i);
+            if b & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.varuint()?.try_into().ok()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+}
+
+pub(crate) fn write_varuint(buf: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let b = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf.push(b);
+            return;
+        }
+        buf.push(b | 0x80);
+    }
+}
+
+/// The identity in a Login packet's body: (protocol version, username, UUID, XUID)
+fn parse_login(body: &[u8]) -> Option<(i32, String, Uuid, Option<String>)> {
+    let mut r = Reader { buf: body };
+    let protocol_version = r.i32_be()?;
+    let len = r.varuint()?.try_into().ok()?;
+    let mut request = Reader { buf: r.take(len)? };
+    let chain_len = request.u32_le()?.try_into().ok()?;
+    let chain = std::str::from_utf8(request.take(chain_len)?).ok()?;
+
+    // the last JWT in the chain that has the player's identity
+    let chain = Json::parse(chain).ok()?;
+    let identity = chain
+        .get("chain")?
+        .as_array()?
+        .iter()
+        .rev()
+        .find_map(|jwt| {
+            let payload = jwt.as_str()?.split('.').nth(1)?;
+            let payload = String::from_utf8(base64_decode(payload)?).ok()?;
+            let payload = Json::parse(&payload).ok()?;
+            let extra = payload.get("extraData")?;
+            let name = extra.get("displayName")?.as_str()?.to_owned();
+            let uuid = Uuid::parse(extra.get("identity")?.as_str()?)?;
+            let xuid = extra
+                .get("XUID")
+                .and_then(Json::as_str)
+                .filter(|x| !x.is_empty())
+                .map(str::to_owned);
+            Some((name, uuid, xuid))
+        })?;
+    Some((protocol_version, identity.0, identity.1, identity.2))
+}
+
+#[derive(Debug, Default)]
+struct BedrockConn {
+    /// Set once Network Settings was sent, after which batches are compressed
+    compressed: bool,
+    /// The client's login, held back until it's done with resource packs
+    login: Option<BedrockPacket>,
+}
+
+impl BedrockConn {
+    /// The packets (header and body) in a game packet batch
+    fn decode_batch(&self, batch: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let inflated;
+        let mut r = Reader { buf: batch };
+        if self.compressed {
+            inflated = inflate(batch).ok()?.0;
+            r = Reader { buf: &inflated };
+        }
+        let mut packets = Vec::new();
+        while !r.buf.is_empty() {
+            let len = r.varuint()?.try_into().ok()?;
+            packets.push(r.take(len)?.to_vec());
+        }
+        Some(packets)
+    }
+
+    fn encode_batch(&self, packets: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut batch = Vec::new();
+        for &(id, body) in packets {
+            let mut header = Vec::new();
+            write_varuint(&mut header, id.into());
+            write_varuint(&mut batch, (header.len() + body.len()) as u64);
+            batch.extend_from_slice(&header);
+            batch.extend_from_slice(body);
+        }
+        self.wrap_batch(&batch)
+    }
+
+    /// The message for a batch whose packets are already laid out, each after its length
+    fn wrap_batch(&self, batch: &[u8]) -> Vec<u8> {
+        let mut message = vec![GAME_PACKET];
+        if self.compressed {
+            message.extend_from_slice(&deflate_stored(batch));
+        } else {
+            message.extend_from_slice(batch);
+        }
+        message
+    }
+}
+
+/// What happened on a `BedrockFrontend`
+#[derive(Debug, Clone, PartialEq)]
+pub enum BedrockEvent {
+    Connected(SocketAddr),
+    Packet(SocketAddr, BedrockPacket),
+    /// `Closed` if the client left (or RakNet timed it out), otherwise why the front-end
+    /// disconnected it
+    Disconnected(SocketAddr, DisconnectCause),
+}
+
+/// Accepts Bedrock clients, answering their network settings, login and resource pack requests.
+/// Call `poll()` in a loop.
+#[derive(Debug)]
+pub struct BedrockFrontend {
+    raknet: RakNetServer,
+    conns: HashMap<SocketAddr, BedrockConn>,
+}
+
+impl BedrockFrontend {
+    /// Listens on `addr`; Bedrock's default port is 19132
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let mut frontend = Self {
+            raknet: RakNetServer::bind(addr)?,
+            conns: HashMap::new(),
+        };
+        frontend.set_motd("A libmc server", 0, 20)?;
+        Ok(frontend)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.raknet.local_addr()
+    }
+
+    /// What Bedrock's server list shows
+    pub fn set_motd(&mut self, motd: &str, players: usize, max_players: usize) -> io::Result<()> {
+        let port = self.local_addr()?.port();
+        self.raknet.set_advertisement(format!(
+            "MCPE;{motd};{BEDROCK_PROTOCOL_VERSION};{BEDROCK_VERSION};{players};{max_players};{};libmc;Survival;1;{port};{port};",
+            self.raknet.guid()
+        ));
+        Ok(())
+    }
+
+    /// Receives for up to `timeout`
+    pub fn poll(&mut self, timeout: Duration) -> io::Result<Vec<BedrockEvent>> {
+        let mut events = Vec::new();
+        for event in self.raknet.poll(timeout)? {
+            match event {
+                RakNetEvent::Connected(addr) => {
+                    self.conns.insert(addr, BedrockConn::default());
+                    events.push(BedrockEvent::Connected(addr));
+                }
+                RakNetEvent::Disconnected(addr) => {
+                    if self.conns.remove(&addr).is_some() {
+                        events.push(BedrockEvent::Disconnected(addr, DisconnectCause::Closed));
+                    }
+                }
+                RakNetEvent::Message(addr, message) if message[0] == GAME_PACKET => {
+                    self.received(addr, &message[1..], &mut events);
+                }
+                RakNetEvent::Message(..) => {}
+            }
+        }
+        Ok(events)
+    }
+
+    /// Sends the game packet `id` with `body`, laid out by the caller
+    pub fn send(&mut self, addr: SocketAddr, id: u32, body: &[u8]) -> bool {
+        let Some(conn) = self.conns.get(&addr) else {
+            return false;
+        };
+        let message = conn.encode_batch(&[(id, body)]);
+        self.raknet.send(addr, &message)
+    }
+
+    /// Sends packets that are already laid out like in a batch: each one's length, then its header
+    /// and body. This is also how Java packet frames are laid out.
+    pub fn send_batch(&mut self, addr: SocketAddr, batch: &[u8]) -> bool {
+        let Some(conn) = self.conns.get(&addr) else {
+            return false;
+        };
+        let message = conn.wrap_batch(batch);
+        self.raknet.send(addr, &message)
+    }
+
+    pub fn disconnect(&mut self, addr: SocketAddr) {
+        self.conns.remove(&addr);
+        self.raknet.disconnect(addr);
+    }
+
+    /// Disconnects a client for something it did, reporting why in `events`
+    fn kick(&mut self, addr: SocketAddr, cause: DisconnectCause, events: &mut Vec<BedrockEvent>) {
+        if self.conns.contains_key(&addr) {
+            self.disconnect(addr);
+            events.push(BedrockEvent::Disconnected(addr, cause));
+        }
+    }
+
+    fn received(&mut self, addr: SocketAddr, batch: &[u8], events: &mut Vec<BedrockEvent>) {
+        let Some(packets) = self.conns.get(&addr).and_then(|c| c.decode_batch(batch)) else {
+            self.kick(addr, DisconnectCause::BadFrame("bad Bedrock batch"), events);
+            return;
+        };
+        for packet in packets {
+            let mut r = Reader { buf: &packet };
+            let Some(header) = r.varuint() else {
+                continue;
+            };
+            // the rest of the header is split-screen sub-client IDs
+            let id = (header & 0x3FF) as u32;
+            match self.translate(addr, id, r.buf) {
+                Ok(Some(packet)) => events.push(BedrockEvent::Packet(addr, packet)),
+                Ok(None) => {}
+                Err(cause) => {
+                    self.kick(addr, cause, events);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Answers the packets the front-end handles itself, and translates the rest. Fails if the
+    /// client has to be disconnected.
+    fn translate(
+        &mut self,
+        addr: SocketAddr,
+        id: u32,
+        body: &[u8],
+    ) -> Result<Option<BedrockPacket>, DisconnectCause> {
+        match id {
+            REQUEST_NETWORK_SETTINGS => {
+                let mut r = Reader { buf: body };
+                let protocol_version = r.i32_be().ok_or(DisconnectCause::BadPacket(None))?;
+                self.check_version(addr, protocol_version)?;
+                let mut settings = Vec::new();
+                // compress everything, with DEFLATE, and don't throttle
+                settings.extend_from_slice(&1u16.to_le_bytes());
+                settings.extend_from_slice(&0u16.to_le_bytes());
+                settings.push(0);
+                settings.push(0);
+                settings.extend_from_slice(&0f32.to_le_bytes());
+                self.send(addr, NETWORK_SETTINGS, &settings);
+                if let Some(conn) = self.conns.get_mut(&addr) {
+                    conn.compressed = true;
+                }
+                Ok(None)
+            }
+            LOGIN => {
+                let (protocol_version, username, uuid, xuid) =
+                    parse_login(body).ok_or(DisconnectCause::BadPacket(None))?;
+                self.check_version(addr, protocol_version)?;
+                self.send(addr, PLAY_STATUS, &LOGIN_SUCCESS.to_be_bytes());
+                // no packs: not required, no scripts, not forced, no behaviour packs, no texture
+                // packs and no CDN links
+                self.send(addr, RESOURCE_PACKS_INFO, &[0, 0, 0, 0, 0, 0, 0, 0]);
+                if let Some(conn) = self.conns.get_mut(&addr) {
+                    conn.login = Some(BedrockPacket::Login {
+                        username,
+                        uuid,
+                        xuid,
+                    });
+                }
+                Ok(None)
+            }
+            RESOURCE_PACK_CLIENT_RESPONSE => {
+                let status = Reader { buf: body }
+                    .u8()
+                    .ok_or(DisconnectCause::BadPacket(None))?;
+                if status == PACKS_COMPLETED {
+                    return Ok(self.conns.get_mut(&addr).and_then(|c| c.login.take()));
+                }
+                // whatever the client asked for, there's nothing to download
+                let mut stack = vec![0, 0, 0];
+                write_varuint(&mut stack, BEDROCK_VERSION.len() as u64);
+                stack.extend_from_slice(BEDROCK_VERSION.as_bytes());
+                // no experiments, which were never on
+                stack.extend_from_slice(&[0, 0, 0, 0, 0]);
+                self.send(addr, RESOURCE_PACK_STACK, &stack);
+                Ok(None)
+            }
+            _ => Ok(translate_game_packet(id, body)),
+        }
+    }
+
+    /// Tells clients on other protocol versions which of us is outdated. The caller then
+    /// disconnects them.
+    fn check_version(
+        &mut self,
+        addr: SocketAddr,
+        protocol_version: i32,
+    ) -> Result<(), DisconnectCause> {
+        if protocol_version == BEDROCK_PROTOCOL_VERSION {
+            return Ok(());
+        }
+        let status = if protocol_version < BEDROCK_PROTOCOL_VERSION {
+            FAILED_CLIENT
+        } else {
+            FAILED_SERVER
+        };
+        self.send(addr, PLAY_STATUS, &status.to_be_bytes());
+        Err(DisconnectCause::UnsupportedVersion(protocol_version))
+    }
+}
+
+/// Translates a packet that needs no answer. None if it's malformed, or a Text that isn't chat.
+fn translate_game_packet(id: u32, body: &[u8]) -> Option<BedrockPacket> {
+    let mut r = Reader { buf: body };
+    match id {
+        MOVE_PLAYER => {
+            // runtime entity ID
+            r.varuint()?;
+            let pos = r.vec3()?;
+            let (pitch, yaw, _head_yaw) = (r.f32_le()?, r.f32_le()?, r.f32_le()?);
+            // mode
+            r.u8()?;
+            let on_ground = r.u8()? != 0;
+            Some(BedrockPacket::Move {
+                pos: pos - Vec3::new(0.0, EYE_HEIGHT, 0.0),
+                yaw,
+                pitch,
+                on_ground: Some(on_ground),
+            })
+        }
+        PLAYER_AUTH_INPUT => {
+            let (pitch, yaw) = (r.f32_le()?, r.f32_le()?);
+            let pos = r.vec3()?;
+            Some(BedrockPacket::Move {
+                pos: pos - Vec3::new(0.0, EYE_HEIGHT, 0.0),
+                yaw,
+                pitch,
+                on_ground: None,
+            })
+        }
+        TEXT => {
+            // chat, rather than e.g. a whisper or announcement
+            if r.u8()? != 1 {
+                return None;
+            }
+            // needs translation, source name
+            r.u8()?;
+            r.string()?;
+            Some(BedrockPacket::Chat {
+                message: r.string()?,
+            })
+        }
+        _ => Some(BedrockPacket::Other {
+            id,
+            data: body.to_vec(),
+        }),
+    }
+}
+
+/// What a Bedrock client's `BedrockLink` asks of `serve_bedrock()`
+#[derive(Debug)]
+pub(crate) enum BedrockCommand {
+    /// Packet frames, as a `PacketStream` writes them
+    Send(ClientID, Vec<u8>),
+    Close(ClientID, Shutdown),
+}
+
+/// Stands in for the socket of a Bedrock client of `run_server()`, whose connection belongs to
+/// the front-end's thread. Frames written to it are sent as batches.
+#[derive(Debug, Clone)]
+pub(crate) struct BedrockLink {
+    cid: ClientID,
+    addr: SocketAddr,
+    tx: Sender<BedrockCommand>,
+}
+
+impl BedrockLink {
+    pub fn new(cid: ClientID, addr: SocketAddr, tx: Sender<BedrockCommand>) -> Self {
+        Self { cid, addr, tx }
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Disconnects the client. With `Shutdown::Read`, what was already written still gets a
+    /// moment to be sent.
+    pub fn shutdown(&self, how: Shutdown) {
+        let _ = self.tx.send(BedrockCommand::Close(self.cid, how));
+    }
+}
+
+impl Write for BedrockLink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .send(BedrockCommand::Send(self.cid, buf.to_vec()))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A client that `serve_bedrock()` handed to the server
+struct Routed {
+    addr: SocketAddr,
+    /// What was written after the last whole frame
+    partial: Vec<u8>,
+    /// When the connection gets closed, once the server has let go of it
+    closing: Option<Instant>,
+}
+
+/// The length of the whole frames at the start of `buf`
+fn whole_frames(buf: &[u8]) -> usize {
+    let mut r = Reader { buf };
+    let mut len = 0;
+    while let Some(frame_len) = r.varuint() {
+        match usize::try_from(frame_len).ok().and_then(|n| r.take(n)) {
+            Some(_) => len = buf.len() - r.buf.len(),
+            None => break,
+        }
+    }
+    len
+}
+
+/// The Java packets (IDs and bodies) a client that logged in as `username` would have started with
+pub(crate) fn login_packets(
+    server_addr: Option<SocketAddr>,
+    username: &str,
+    uuid: Uuid,
+) -> [Vec<u8>; 2] {
+    let mut handshake = vec![0x00];
+    write_varint(&mut handshake, PROTOCOL_VERSION);
+    let (host, port) = server_addr.map_or((String::new(), 0), |a| (a.ip().to_string(), a.port()));
+    write_string(&mut handshake, &host);
+    handshake.extend_from_slice(&port.to_be_bytes());
+    // next state: login
+    write_varint(&mut handshake, 2);
+
+    let mut login_start = vec![0x00];
+    write_string(&mut login_start, username);
+    write_uuid(&mut login_start, uuid);
+    [handshake, login_start]
+}
+
+/// The Java packet (ID and body) a Bedrock one after login stands for, if any
+fn java_packet(packet: &BedrockPacket) -> Option<Vec<u8>> {
+    let mut p = Vec::new();
+    match packet.to_in_packet()? {
+        InPacket::SetPlayerPositionAndRotation {
+            x,
+            y,
+            z,
+            yaw,
+            pitch,
+            on_ground,
+        } => {
+            write_varint(&mut p, 0x17);
+            write_double(&mut p, x);
+            write_double(&mut p, y);
+            write_double(&mut p, z);
+            write_float(&mut p, yaw);
+            write_float(&mut p, pitch);
+            write_bool(&mut p, on_ground);
+        }
+        InPacket::ChatMessage { message, .. } => {
+            write_varint(&mut p, 0x05);
+            write_string(&mut p, message);
+            // unsigned, with no timestamp or salt
+            write_long(&mut p, 0);
+            write_long(&mut p, 0);
+            write_bool(&mut p, false);
+        }
+        _ => return None,
+    }
+    Some(p)
+}
+
+/// Runs `frontend` for `run_server()`, on its own thread. Clients that log in are passed on as
+/// if they had connected over TCP and sent Java packets, and what the server writes to their
+/// `BedrockLink` (already translated by their `BedrockTranslator`) is sent to them.
+pub(crate) fn serve_bedrock(
+    mut frontend: BedrockFrontend,
+    tx: Sender<NetEvent>,
+    client_ids: Arc<ClientIDAllocator>,
+) {
+    let (link_tx, link_rx) = mpsc::channel();
+    let server_addr = frontend.local_addr().ok();
+    let mut cids: HashMap<SocketAddr, ClientID> = HashMap::new();
+    let mut routed: HashMap<ClientID, Routed> = HashMap::new();
+    loop {
+        // a failed receive only loses that datagram, which RakNet resends
+        let events = frontend.poll(POLL_INTERVAL).unwrap_or_default();
+        let mut to_server = Vec::new();
+        for event in events {
+            match event {
+                BedrockEvent::Connected(_) => {}
+                BedrockEvent::Packet(addr, BedrockPacket::Login { username, uuid, .. }) => {
+                    let cid = client_ids.allocate();
+                    let link = BedrockLink::new(cid, addr, link_tx.clone());
+                    cids.insert(addr, cid);
+                    routed.insert(
+                        cid,
+                        Routed {
+                            addr,
+                            partial: Vec::new(),
+                            closing: None,
+                        },
+                    );
+                    let socket = Socket::Bedrock(link.clone());
+                    let tag = BEDROCK_LISTENER.into();
+                    to_server.push(NetEvent::Connected(
+                        cid,
+                        socket,
+                        Transport::Bedrock(link),
+                        tag,
+                    ));
+                    for packet in login_packets(server_addr, &username, uuid) {
+                        to_server.push(NetEvent::Frame(cid, frame_packet(&packet)));
+                    }
+                }
+                BedrockEvent::Packet(addr, packet) => {
+                    if let (Some(&cid), Some(packet)) = (cids.get(&addr), java_packet(&packet)) {
+                        to_server.push(NetEvent::Frame(cid, frame_packet(&packet)));
+                    }
+                }
+                BedrockEvent::Disconnected(addr, cause) => {
+                    let Some(cid) = cids.remove(&addr) else {
+                        continue;
+                    };
+                    // unless the server closed it already
+                    if routed.remove(&cid).is_some_and(|r| r.closing.is_none()) {
+                        to_server.push(NetEvent::Closed(cid, cause));
+                    }
+                }
+            }
+        }
+        for event in to_server {
+            if tx.send(event).is_err() {
+                // the server shut down
+                return;
+            }
+        }
+
+        while let Ok(command) = link_rx.try_recv() {
+            match command {
+                BedrockCommand::Send(cid, data) => {
+                    let Some(r) = routed.get_mut(&cid) else {
+                        continue;
+                    };
+                    r.partial.extend_from_slice(&data);
+                    let len = whole_frames(&r.partial);
+                    if len > 0 {
+                        frontend.send_batch(r.addr, &r.partial[..len]);
+                        r.partial.drain(..len);
+                    }
+                }
+                BedrockCommand::Close(cid, how) => {
+                    let Some(r) = routed.get_mut(&cid) else {
+                        continue;
+                    };
+                    // like a TCP reader thread seeing its socket closed
+                    if r.closing.is_none()
+                        && tx
+                            .send(NetEvent::Closed(cid, DisconnectCause::Closed))
+                            .is_err()
+                    {
+                        return;
+                    }
+                    let grace = match how {
+                        Shutdown::Read => CLOSE_GRACE,
+                        _ => Duration::ZERO,
+                    };
+                    let at = Instant::now() + grace;
+                    r.closing = Some(r.closing.map_or(at, |t| t.min(at)));
+                }
+            }
+        }
+
+        let now = Instant::now();
+        routed.retain(|_, r| {
+            if r.closing.is_some_and(|t| t <= now) {
+                cids.remove(&r.addr);
+                frontend.disconnect(r.addr);
+                return false;
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::base64_encode;
+
+    /// A Login from Steve, with an unsigned chain
+    fn login_body() -> Vec<u8> {
+        let extra = r#"{"extraData":{"displayName":"Steve","identity":"069a79f4-44e9-4726-a5be-fca90e38aaf5","XUID":""}}"#;
+        let jwt = format!(
+            "e30.{}.sig",
+            base64_encode(extra.as_bytes()).replace('=', "")
+        );
+        let chain = format!(r#"{{"chain":["e30.e30.sig","{jwt}"]}}"#);
+        let mut request = Vec::new();
+        request.extend_from_slice(&(chain.len() as u32).to_le_bytes());
+        request.extend_from_slice(chain.as_bytes());
+        request.extend_from_slice(&0u32.to_le_bytes());
+        let mut body = BEDROCK_PROTOCOL_VERSION.to_be_bytes().to_vec();
+        write_varuint(&mut body, request.len() as u64);
+        body.extend_from_slice(&request);
+        body
+    }
+
+    #[test]
+    fn login_and_batches() {
+        let body = login_body();
+        let (protocol_version, name, uuid, xuid) = parse_login(&body).unwrap();
+        assert_eq!(protocol_version, BEDROCK_PROTOCOL_VERSION);
+        assert_eq!(name, "Steve");
+        assert_eq!(uuid.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!(xuid, None);
+
+        for compressed in [false, true] {
+            let conn = BedrockConn {
+                compressed,
+                ..BedrockConn::default()
+            };
+            let message = conn.encode_batch(&[(LOGIN, &body), (TEXT, &[1, 2])]);
+            assert_eq!(message[0], GAME_PACKET);
+            let packets = conn.decode_batch(&message[1..]).unwrap();
+            assert_eq!(packets.len(), 2);
+            assert_eq!(packets[0][0], LOGIN as u8);
+            assert_eq!(&packets[0][1..], body);
+            assert_eq!(packets[1], [TEXT as u8, 1, 2]);
+        }
+    }
+
+    #[test]
+    fn kicks_are_reported() {
+        let mut frontend = BedrockFrontend::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+        let mut events = Vec::new();
+        frontend.conns.insert(
+            addr,
+            BedrockConn {
+                compressed: true,
+                ..BedrockConn::default()
+            },
+        );
+        frontend.received(addr, &[0xFF, 0xFF], &mut events);
+        assert_eq!(
+            events,
+            [BedrockEvent::Disconnected(
+                addr,
+                DisconnectCause::BadFrame("bad Bedrock batch")
+            )]
+        );
+
+        events.clear();
+        frontend.conns.insert(addr, BedrockConn::default());
+        let batch =
+            BedrockConn::default().encode_batch(&[(REQUEST_NETWORK_SETTINGS, &[0, 0, 2, 0])]);
+        frontend.received(addr, &batch[1..], &mut events);
+        assert_eq!(
+            events,
+            [BedrockEvent::Disconnected(
+                addr,
+                DisconnectCause::UnsupportedVersion(512)
+            )]
+        );
+        assert!(frontend.conns.is_empty());
+    }
+
+    #[test]
+    fn login_waits_for_resource_packs() {
+        let mut frontend = BedrockFrontend::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+        frontend.conns.insert(addr, BedrockConn::default());
+        assert_eq!(frontend.translate(addr, LOGIN, &login_body()), Ok(None));
+        let have_all_packs = [3, 0, 0];
+        assert_eq!(
+            frontend.translate(addr, RESOURCE_PACK_CLIENT_RESPONSE, &have_all_packs),
+            Ok(None)
+        );
+        let completed = [PACKS_COMPLETED, 0, 0];
+        let login = frontend.translate(addr, RESOURCE_PACK_CLIENT_RESPONSE, &completed);
+        assert!(matches!(
+            login,
+            Ok(Some(BedrockPacket::Login { username, .. })) if username == "Steve"
+        ));
+    }
+
+    #[test]
+    fn java_frames() {
+        let moved = BedrockPacket::Move {
+            pos: Vec3::new(1.0, 2.0, 3.0),
+            yaw: 90.0,
+            pitch: 0.0,
+            on_ground: None,
+        };
+        let packet = java_packet(&moved).unwrap();
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &packet),
+            Ok(InPacket::SetPlayerPositionAndRotation {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+                on_ground: true,
+                ..
+            })
+        ));
+        let other = BedrockPacket::Other {
+            id: 0x45,
+            data: vec![],
+        };
+        assert_eq!(java_packet(&other), None);
+
+        // frames are only sent once they're whole
+        let mut frames = frame_packet(&packet);
+        let whole = frames.len();
+        frames.extend_from_slice(&frame_packet(&[0x05, 1, 2])[..2]);
+        assert_eq!(whole_frames(&frames), whole);
+        assert_eq!(whole_frames(&frames[..whole - 1]), 0);
+    }
+}
@@ -0,0 +1,857 @@
+//! Translation of what libmc sends into Bedrock packets, for the Bedrock clients `run_server()`
+//! accepts (see `BedrockFrontend`).
+
+use crate::bedrock::{write_varuint, EYE_HEIGHT, MOVE_PLAYER, PLAY_STATUS, TEXT};
+use crate::*;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+const DISCONNECT: u32 = 0x05;
+const START_GAME: u32 = 0x0B;
+const LEVEL_CHUNK: u32 = 0x3A;
+const CHUNK_RADIUS_UPDATED: u32 = 0x46;
+const AVAILABLE_ENTITY_IDENTIFIERS: u32 = 0x77;
+const NETWORK_CHUNK_PUBLISHER_UPDATE: u32 = 0x79;
+const BIOME_DEFINITION_LIST: u32 = 0x7A;
+const CREATIVE_CONTENT: u32 = 0x91;
+
+/// The Play Status that lets the client out of the loading screen
+const PLAYER_SPAWN: i32 = 3;
+
+/// Sub-chunks with this layout have their Y index in them, and no biomes
+const SUB_CHUNK_VERSION: u8 = 9;
+/// Bedrock's plains, which every chunk is shown as
+const PLAINS: i32 = 1;
+
+/// A biome or block storage that's the same as the one before it
+const SAME_AS_PREVIOUS: u8 = 0xFF;
+
+/// An empty compound, in Bedrock's network nbt
+const EMPTY_NBT: [u8; 3] = [0x0A, 0x00, 0x00];
+
+/// A value of a Bedrock block state property
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BedrockStateValue {
+    Byte(u8),
+    Int(i32),
+    String(String),
+}
+
+impl From<bool> for BedrockStateValue {
+    fn from(value: bool) -> Self {
+        Self::Byte(value.into())
+    }
+}
+
+impl From<i32> for BedrockStateValue {
+    fn from(value: i32) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<&str> for BedrockStateValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_owned())
+    }
+}
+
+/// A Bedrock block state, like `BlockState` is a Java one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BedrockBlock {
+    name: String,
+    states: BTreeMap<String, BedrockStateValue>,
+}
+
+impl BedrockBlock {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            states: BTreeMap::new(),
+        }
+    }
+
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<BedrockStateValue>) -> Self {
+        self.states.insert(key.into(), value.into());
+        self
+    }
+
+    /// The ID clients know the block by: the FNV-1a hash of its name and states, as
+    /// little-endian nbt
+    pub fn network_id(&self) -> u32 {
+        fn string(buf: &mut Vec<u8>, s: &str) {
+            buf.extend_from_slice(&(s.len() as u16).to_le_bytes());
+            buf.extend_from_slice(s.as_bytes());
+        }
+        let mut nbt = vec![0x0A, 0x00, 0x00, 0x08];
+        string(&mut nbt, "name");
+        string(&mut nbt, &self.name);
+        nbt.push(0x0A);
+        string(&mut nbt, "states");
+        // sorted by key, which the hash depends on
+        for (key, value) in &self.states {
+            match value {
+                BedrockStateValue::Byte(b) => {
+                    nbt.push(0x01);
+                    string(&mut nbt, key);
+                    nbt.push(*b);
+                }
+                BedrockStateValue::Int(i) => {
+                    nbt.push(0x03);
+                    string(&mut nbt, key);
+                    nbt.extend_from_slice(&i.to_le_bytes());
+                }
+                BedrockStateValue::String(s) => {
+                    nbt.push(0x08);
+                    string(&mut nbt, key);
+                    string(&mut nbt, s);
+                }
+            }
+        }
+        nbt.extend_from_slice(&[0x00, 0x00]);
+
+        nbt.iter().fold(0x811C_9DC5, |hash: u32, b| {
+            (hash ^ u32::from(*b)).wrapping_mul(0x0100_0193)
+        })
+    }
+}
+
+/// Which Bedrock block each Java block state is shown as, by protocol ID. Air is air, and
+/// states that aren't in it are shown as the fallback.
+#[derive(Debug, Clone)]
+pub struct BedrockBlocks {
+    /// Network IDs, by Java state
+    blocks: HashMap<u32, u32>,
+    fallback: u32,
+}
+
+impl BedrockBlocks {
+    pub fn new(fallback: &BedrockBlock) -> Self {
+        let mut blocks = Self {
+            blocks: HashMap::new(),
+            fallback: fallback.network_id(),
+        };
+        blocks.insert(0, &BedrockBlock::new("minecraft:air"));
+        blocks
+    }
+
+    pub fn insert(&mut self, java_state: u32, block: &BedrockBlock) {
+        self.blocks.insert(java_state, block.network_id());
+    }
+
+    /// The network ID of the Bedrock block that `java_state` is shown as
+    pub fn network_id(&self, java_state: u32) -> u32 {
+        self.blocks
+            .get(&java_state)
+            .copied()
+            .unwrap_or(self.fallback)
+    }
+}
+
+/// Everything but air is stone
+impl Default for BedrockBlocks {
+    fn default() -> Self {
+        Self::new(&BedrockBlock::new("minecraft:stone"))
+    }
+}
+
+/// The text of a JSON chat component, without its formatting. Translated parts are left as
+/// their keys.
+pub(crate) fn plain_text(json: &str) -> String {
+    fn walk(c: &Json, out: &mut String) {
+        if let Some(s) = c.as_str() {
+            out.push_str(s);
+        }
+        for c in c.as_array().unwrap_or_default() {
+            walk(c, out);
+        }
+        if let Some(text) = c
+            .get("text")
+            .or_else(|| c.get("translate"))
+            .and_then(Json::as_str)
+        {
+            out.push_str(text);
+        }
+        for c in c.get("extra").and_then(Json::as_array).unwrap_or_default() {
+            walk(c, out);
+        }
+    }
+    let mut out = String::new();
+    if let Ok(c) = Json::parse(json) {
+        walk(&c, &mut out);
+    }
+    out
+}
+
+fn write_zigzag(buf: &mut Vec<u8>, n: i64) {
+    write_varuint(buf, ((n << 1) ^ (n >> 63)) as u64);
+}
+
+fn write_bedrock_string(buf: &mut Vec<u8>, s: &str) {
+    write_varuint(buf, s.len() as u64);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn packet(id: u32) -> Vec<u8> {
+    let mut p = Vec::new();
+    write_varuint(&mut p, id.into());
+    p
+}
+
+fn java_packet(id: i32) -> Vec<u8> {
+    let mut p = Vec::new();
+    write_varint(&mut p, id);
+    p
+}
+
+/// The sub-chunks of a Bedrock dimension: (the lowest one's Y index, how many)
+fn sub_chunk_range(dimension: i32) -> (i32, usize) {
+    match dimension {
+        1 => (0, 8),
+        2 => (0, 16),
+        _ => (-4, 24),
+    }
+}
+
+/// Reads a section's blocks from Java chunk data, in Java's order (Y, then Z, then X), and skips
+/// its biomes
+fn read_section(r: &mut PacketReader) -> Result<Vec<u32>, DecodeError> {
+    r.short()?;
+    let blocks = read_container(r, 4096)?;
+    read_container(r, 64)?;
+    Ok(blocks)
+}
+
+/// Reads a paletted container of `len` values
+fn read_container(r: &mut PacketReader, len: usize) -> Result<Vec<u32>, DecodeError> {
+    let bits = r.ubyte()?;
+    // blocks have up to 8 bits of palette indices, biomes up to 3
+    let max_indirect = if len == 4096 { 8 } else { 3 };
+    let palette = match bits {
+        0 => Some(vec![r.varint()? as u32]),
+        b if b <= max_indirect => {
+            let count = r.varint()?;
+            let count = usize::try_from(count).map_err(|_| DecodeError::BadValue {
+                field: "palette length",
+                value: count,
+            })?;
+            let palette: Result<Vec<u32>, _> =
+                (0..count).map(|_| r.varint().map(|v| v as u32)).collect();
+            Some(palette?)
+        }
+        _ => None,
+    };
+    let longs = r.varint()?;
+    let longs = usize::try_from(longs).map_err(|_| DecodeError::BadValue {
+        field: "data length",
+        value: longs,
+    })?;
+    let longs: Vec<i64> = r
+        .slice(longs.checked_mul(8).ok_or(DecodeError::UnexpectedEnd)?)?
+        .chunks_exact(8)
+        .map(|l| i64::from_be_bytes(l.try_into().unwrap()))
+        .collect();
+    if bits == 0 {
+        return Ok(vec![palette.unwrap()[0]; len]);
+    }
+    let data = PackedIntArray::from_longs(bits, len, longs).ok_or(DecodeError::BadValue {
+        field: "data length",
+        value: bits.into(),
+    })?;
+    let values = data.iter();
+    Ok(match palette {
+        Some(palette) => values
+            .map(|i| palette.get(i as usize).copied().unwrap_or_default())
+            .collect(),
+        None => values.collect(),
+    })
+}
+
+/// Writes one sub-chunk's block storage of network IDs, which are in Bedrock's order (X, then Z,
+/// then Y)
+fn write_block_storage(buf: &mut Vec<u8>, ids: &[u32]) {
+    let mut palette = Vec::new();
+    let indices: Vec<u32> = ids
+        .iter()
+        .map(|id| match palette.iter().position(|p| p == id) {
+            Some(i) => i as u32,
+            None => {
+                palette.push(*id);
+                palette.len() as u32 - 1
+            }
+        })
+        .collect();
+    if palette.len() == 1 {
+        // no indices, and a palette of one without its length
+        buf.push(1);
+        write_zigzag(buf, i64::from(palette[0] as i32));
+        return;
+    }
+    let bits = [1, 2, 3, 4, 5, 6, 8, 16]
+        .into_iter()
+        .find(|bits| palette.len() <= 1 << bits)
+        .unwrap();
+    // the low bit says it's network IDs rather than saved ones
+    buf.push(bits << 1 | 1);
+    let per_word = 32 / usize::from(bits);
+    let mut words = vec![0u32; indices.len().div_ceil(per_word)];
+    for (i, index) in indices.into_iter().enumerate() {
+        words[i / per_word] |= index << ((i % per_word) * usize::from(bits));
+    }
+    for word in words {
+        buf.extend_from_slice(&word.to_le_bytes());
+    }
+    write_zigzag(buf, palette.len() as i64);
+    for id in palette {
+        write_zigzag(buf, i64::from(id as i32));
+    }
+}
+
+/// Translates what libmc sends into Bedrock packets, for clients of `BedrockFrontend` that
+/// `run_server()` hands to the `Server`. The front-end has already turned what they send into Java
+/// packets, so those pass through.
+///
+/// The login and configuration are completed on the client's behalf. Login (play), chunks,
+/// teleports, chat, view distance and disconnects are translated, and everything else is dropped
+/// for now, so Bedrock players can walk around and chat, but not see entities or build. Blocks
+/// are shown as given by `BedrockBlocks`, and every biome as plains.
+#[derive(Debug)]
+pub struct BedrockTranslator {
+    blocks: Arc<BedrockBlocks>,
+    /// The min_y of each Java dimension type, from Registry Data
+    min_ys: HashMap<String, i32>,
+    /// Bedrock's ID of the dimension the player is in
+    dimension: i32,
+    /// The bottom of the Java dimension the player is in
+    min_y: i32,
+    /// The player's entity ID, from Login (play)
+    runtime_id: u64,
+    view_distance: i32,
+    /// Whether the client was told it can spawn, after its first teleport
+    spawned: bool,
+}
+
+impl BedrockTranslator {
+    pub fn new(blocks: Arc<BedrockBlocks>) -> Self {
+        Self {
+            blocks,
+            min_ys: HashMap::new(),
+            dimension: 0,
+            min_y: -64,
+            runtime_id: 0,
+            view_distance: 0,
+            spawned: false,
+        }
+    }
+
+    /// Registry Data: keeps where each dimension type starts
+    fn registry_data(&mut self, body: &[u8]) -> Result<(), DecodeError> {
+        let codec = Nbt::try_read_network_compound(&mut &body[..])?;
+        let Some(Nbt::Compound(types)) = codec.get("minecraft:dimension_type") else {
+            return Ok(());
+        };
+        let Some(Nbt::List(NbtList::Compound(types))) = types.get("value") else {
+            return Ok(());
+        };
+        for t in types.iter() {
+            if let (Some(Nbt::String(name)), Some(Nbt::Compound(element))) =
+                (t.get("name"), t.get("element"))
+            {
+                if let Some(&Nbt::Int(min_y)) = element.get("min_y") {
+                    self.min_ys.insert(name.to_string(), min_y);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Login (play): Start Game, and the lists that have to follow it
+    fn login_play(&mut self, body: &[u8], out: &mut Vec<Vec<u8>>) -> Result<(), DecodeError> {
+        let mut r = PacketReader::new(body);
+        let entity_id = r.int()?;
+        r.bool()?;
+        for _ in 0..r.varint()? {
+            r.str()?;
+        }
+        r.varint()?;
+        self.view_distance = r.varint()?;
+        r.varint()?;
+        r.bool()?;
+        r.bool()?;
+        r.bool()?;
+        let dimension_type = r.str()?;
+        r.str()?;
+        let seed = r.long()?;
+        // survival, creative, adventure and spectator are the same numbers on Bedrock
+        let game_mode = r.ubyte()?;
+
+        (self.dimension, self.min_y) = match dimension_type {
+            "minecraft:the_nether" => (1, 0),
+            "minecraft:the_end" => (2, 0),
+            _ => (0, -64),
+        };
+        if let Some(&min_y) = self.min_ys.get(dimension_type) {
+            self.min_y = min_y;
+        }
+        self.runtime_id = entity_id as u32 as u64;
+        out.push(self.start_game(game_mode, seed));
+        let mut biomes = packet(BIOME_DEFINITION_LIST);
+        biomes.extend_from_slice(&EMPTY_NBT);
+        out.push(biomes);
+        let mut entities = packet(AVAILABLE_ENTITY_IDENTIFIERS);
+        entities.extend_from_slice(&EMPTY_NBT);
+        out.push(entities);
+        let mut creative = packet(CREATIVE_CONTENT);
+        write_varuint(&mut creative, 0);
+        out.push(creative);
+        out.push(self.chunk_radius());
+        Ok(())
+    }
+
+    /// Start Game, as protocol 622 lays it out. The client is put at the origin until its first
+    /// teleport, and is told that block network IDs are hashes (see `BedrockBlock::network_id()`).
+    fn start_game(&self, game_mode: u8, seed: i64) -> Vec<u8> {
+        let mut p = packet(START_GAME);
+        write_zigzag(&mut p, self.runtime_id as i64);
+        write_varuint(&mut p, self.runtime_id);
+        write_zigzag(&mut p, game_mode.into());
+        // position, pitch and yaw
+        for _ in 0..5 {
+            p.extend_from_slice(&0f32.to_le_bytes());
+        }
+        p.extend_from_slice(&seed.to_le_bytes());
+        // default biome, with no name
+        p.extend_from_slice(&0i16.to_le_bytes());
+        write_bedrock_string(&mut p, "");
+        write_zigzag(&mut p, self.dimension.into());
+        // infinite generator, world game mode, normal difficulty
+        write_zigzag(&mut p, 1);
+        write_zigzag(&mut p, game_mode.into());
+        write_zigzag(&mut p, 2);
+        // world spawn
+        write_zigzag(&mut p, 0);
+        write_varuint(&mut p, 0);
+        write_zigzag(&mut p, 0);
+        // achievements disabled, not an editor world (type, created in, exported from)
+        p.push(1);
+        write_zigzag(&mut p, 0);
+        p.extend_from_slice(&[0, 0]);
+        // day cycle lock time, education offer, education features, education product ID
+        write_zigzag(&mut p, 0);
+        write_zigzag(&mut p, 0);
+        p.push(0);
+        write_bedrock_string(&mut p, "");
+        // rain and lightning levels
+        p.extend_from_slice(&0f32.to_le_bytes());
+        p.extend_from_slice(&0f32.to_le_bytes());
+        // not platform locked, multiplayer, no LAN broadcast, public on Xbox Live and platforms
+        p.extend_from_slice(&[0, 1, 0]);
+        write_zigzag(&mut p, 4);
+        write_zigzag(&mut p, 4);
+        // commands enabled, no texture packs required, no game rules or experiments
+        p.extend_from_slice(&[1, 0]);
+        write_varuint(&mut p, 0);
+        p.extend_from_slice(&0u32.to_le_bytes());
+        // experiments never toggled, no bonus chest, no starting map, member permissions
+        p.extend_from_slice(&[0, 0, 0]);
+        write_zigzag(&mut p, 1);
+        p.extend_from_slice(&4i32.to_le_bytes());
+        // no locked packs or templates, Xbox Live not required, no v1 villagers, personas and
+        // custom skins allowed, emotes not muted
+        p.extend_from_slice(&[0; 10]);
+        write_bedrock_string(&mut p, "*");
+        // limited world width and depth, new nether
+        p.extend_from_slice(&16i32.to_le_bytes());
+        p.extend_from_slice(&16i32.to_le_bytes());
+        p.push(1);
+        // education shared resource (button name, link), no forced experimental gameplay,
+        // unrestricted chat, player interactions on
+        write_bedrock_string(&mut p, "");
+        write_bedrock_string(&mut p, "");
+        p.extend_from_slice(&[0, 0, 0]);
+        // level ID, world name, template content identity, not a trial
+        write_bedrock_string(&mut p, "");
+        write_bedrock_string(&mut p, "libmc");
+        write_bedrock_string(&mut p, "");
+        p.push(0);
+        // movement is up to the client: authority, rewind history size, block breaking
+        write_zigzag(&mut p, 0);
+        write_zigzag(&mut p, 0);
+        p.push(0);
+        // current tick, enchantment seed, no custom blocks or items
+        p.extend_from_slice(&0i64.to_le_bytes());
+        write_zigzag(&mut p, 0);
+        write_varuint(&mut p, 0);
+        write_varuint(&mut p, 0);
+        // multiplayer correlation ID, client-side inventory
+        write_bedrock_string(&mut p, "");
+        p.push(0);
+        write_bedrock_string(&mut p, BEDROCK_VERSION);
+        // property data, block registry checksum, world template ID
+        p.extend_from_slice(&EMPTY_NBT);
+        p.extend_from_slice(&0u64.to_le_bytes());
+        p.extend_from_slice(&[0; 16]);
+        // no client-side generation, hashed block network IDs, client-side sound
+        p.extend_from_slice(&[0, 1, 0]);
+        p
+    }
+
+    fn chunk_radius(&self) -> Vec<u8> {
+        let mut p = packet(CHUNK_RADIUS_UPDATED);
+        write_zigzag(&mut p, self.view_distance.into());
+        p
+    }
+
+    /// Chunk Data and Update Light: Level Chunk, with the blocks but not the light
+    fn level_chunk(&self, body: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut r = PacketReader::new(body);
+        let (chunk_x, chunk_z) = (r.int()?, r.int()?);
+        let mut rest = r.rest();
+        // heightmaps
+        Nbt::try_read_network_compound(&mut rest)?;
+        let mut r = PacketReader::new(rest);
+        let len = r.varint()?;
+        let len = usize::try_from(len).map_err(|_| DecodeError::BadValue {
+            field: "data length",
+            value: len,
+        })?;
+        let mut data = PacketReader::new(r.slice(len)?);
+        let mut sections = Vec::new();
+        while !data.is_empty() {
+            sections.push(read_section(&mut data)?);
+        }
+
+        let (bottom, count) = sub_chunk_range(self.dimension);
+        let air = [self.blocks.network_id(0); 4096];
+        let mut payload = Vec::new();
+        let mut ids = vec![0; 4096];
+        for y_index in bottom..bottom + count as i32 {
+            let section = usize::try_from(y_index - self.min_y.div_euclid(16))
+                .ok()
+                .and_then(|i| sections.get(i));
+            payload.extend_from_slice(&[SUB_CHUNK_VERSION, 1, y_index as u8]);
+            let Some(section) = section else {
+                write_block_storage(&mut payload, &air);
+                continue;
+            };
+            for (i, state) in section.iter().enumerate() {
+                // Java's index is (y * 16 + z) * 16 + x, Bedrock's (x * 16 + z) * 16 + y
+                let (x, z, y) = (i % 16, i / 16 % 16, i / 256);
+                ids[(x * 16 + z) * 16 + y] = self.blocks.network_id(*state);
+            }
+            write_block_storage(&mut payload, &ids);
+        }
+        // one biome for all of it
+        payload.push(1);
+        write_zigzag(&mut payload, PLAINS.into());
+        payload.resize(payload.len() + count - 1, SAME_AS_PREVIOUS);
+        // no border blocks or block entities
+        payload.push(0);
+
+        let mut p = packet(LEVEL_CHUNK);
+        write_zigzag(&mut p, chunk_x.into());
+        write_zigzag(&mut p, chunk_z.into());
+        write_varuint(&mut p, count as u64);
+        // not cached
+        p.push(0);
+        write_varuint(&mut p, payload.len() as u64);
+        p.extend_from_slice(&payload);
+        Ok(p)
+    }
+
+    /// Synchronize Player Position: Move Player, and Play Status the first time. Positions
+    /// relative to the player's aren't known here, so they're only confirmed.
+    fn teleport(
+        &mut self,
+        body: &[u8],
+        out: &mut Vec<Vec<u8>>,
+        reply: &mut Vec<Vec<u8>>,
+    ) -> Result<(), DecodeError> {
+        let mut r = PacketReader::new(body);
+        let (x, y, z) = (r.double()?, r.double()?, r.double()?);
+        let (yaw, pitch) = (r.float()?, r.float()?);
+        let flags = r.byte()?;
+        let teleport_id = r.varint()?;
+
+        if flags == 0 {
+            let mut p = packet(MOVE_PLAYER);
+            write_varuint(&mut p, self.runtime_id);
+            for f in [x as f32, (y + EYE_HEIGHT) as f32, z as f32, pitch, yaw, yaw] {
+                p.extend_from_slice(&f.to_le_bytes());
+            }
+            // teleport mode, not on the ground, not riding anything
+            p.extend_from_slice(&[2, 0]);
+            write_varuint(&mut p, 0);
+            // teleport cause and source entity type
+            p.extend_from_slice(&0i32.to_le_bytes());
+            p.extend_from_slice(&0i32.to_le_bytes());
+            // tick
+            write_varuint(&mut p, 0);
+            out.push(p);
+        }
+        if !self.spawned {
+            self.spawned = true;
+            let mut p = packet(PLAY_STATUS);
+            p.extend_from_slice(&PLAYER_SPAWN.to_be_bytes());
+            out.push(p);
+        }
+        // Confirm Teleportation
+        let mut confirm = java_packet(0x00);
+        write_varint(&mut confirm, teleport_id);
+        reply.push(confirm);
+        Ok(())
+    }
+
+    /// Set Center Chunk: Network Chunk Publisher Update, which tells the client which chunks to
+    /// show
+    fn center_chunk(&self, body: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut r = PacketReader::new(body);
+        let (chunk_x, chunk_z) = (r.varint()?, r.varint()?);
+        let mut p = packet(NETWORK_CHUNK_PUBLISHER_UPDATE);
+        write_zigzag(&mut p, i64::from(chunk_x) * 16 + 8);
+        write_varuint(&mut p, 0);
+        write_zigzag(&mut p, i64::from(chunk_z) * 16 + 8);
+        write_varuint(&mut p, (self.view_distance.max(0) * 16) as u64);
+        // no saved chunks
+        p.extend_from_slice(&0u32.to_le_bytes());
+        Ok(p)
+    }
+
+    /// Player Chat: a raw Text with the sender's name in front, like Java's default chat type
+    fn player_chat(body: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut r = PacketReader::new(body);
+        r.uuid()?;
+        r.varint()?;
+        if r.bool()? {
+            r.slice(256)?;
+        }
+        let message = r.str()?;
+        r.long()?;
+        r.long()?;
+        for _ in 0..r.varint()? {
+            if r.varint()? == 0 {
+                r.slice(256)?;
+            }
+        }
+        let content = if r.bool()? { Some(r.str()?) } else { None };
+        if r.varint()? == 2 {
+            BitSet::read(&mut r)?;
+        }
+        r.varint()?;
+        let sender = plain_text(r.str()?);
+        let message = match content {
+            Some(content) => plain_text(content),
+            None => message.to_owned(),
+        };
+        Ok(Self::text(false, &format!("<{sender}> {message}")))
+    }
+
+    /// A Text of type raw, or tip (above the hotbar) if `overlay`
+    fn text(overlay: bool, message: &str) -> Vec<u8> {
+        let mut p = packet(TEXT);
+        p.push(if overlay { 5 } else { 0 });
+        // doesn't need translating
+        p.push(0);
+        write_bedrock_string(&mut p, message);
+        // no XUID or platform chat ID
+        write_bedrock_string(&mut p, "");
+        write_bedrock_string(&mut p, "");
+        p
+    }
+
+    fn disconnect(body: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let reason = plain_text(PacketReader::new(body).str()?);
+        let mut p = packet(DISCONNECT);
+        // unknown reason, with the screen shown
+        write_zigzag(&mut p, 0);
+        p.push(0);
+        write_bedrock_string(&mut p, &reason);
+        Ok(p)
+    }
+
+    fn translate(
+        &mut self,
+        state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+        reply: &mut Vec<Vec<u8>>,
+    ) -> Result<(), DecodeError> {
+        let mut r = PacketReader::new(packet);
+        let id = r.varint()?;
+        let body = r.rest();
+        match (state, id) {
+            // Login Success, Finish Configuration: acknowledged for the client
+            (ProtocolState::Login, 0x02) => reply.push(java_packet(0x03)),
+            (ProtocolState::Config, 0x02) => reply.push(java_packet(0x02)),
+            // Keep Alive
+            (ProtocolState::Config, 0x03) => {
+                let mut p = java_packet(0x03);
+                p.extend_from_slice(body);
+                reply.push(p);
+            }
+            (ProtocolState::Play, 0x24) => {
+                let mut p = java_packet(0x14);
+                p.extend_from_slice(body);
+                reply.push(p);
+            }
+            // Disconnect
+            (ProtocolState::Login, 0x00)
+            | (ProtocolState::Config, 0x01)
+            | (ProtocolState::Play, 0x1B) => out.push(Self::disconnect(body)?),
+            (ProtocolState::Config, 0x05) => self.registry_data(body)?,
+            (ProtocolState::Play, 0x29) => self.login_play(body, out)?,
+            (ProtocolState::Play, 0x25) => out.push(self.level_chunk(body)?),
+            (ProtocolState::Play, 0x3E) => self.teleport(body, out, reply)?,
+            // Chunk Batch Finished: answered at the rate a Java client starts with
+            (ProtocolState::Play, 0x0C) => {
+                let mut received = java_packet(0x07);
+                write_float(&mut received, 9.0);
+                reply.push(received);
+            }
+            (ProtocolState::Play, 0x50) => out.push(self.center_chunk(body)?),
+            // Set Render Distance
+            (ProtocolState::Play, 0x51) => {
+                self.view_distance = PacketReader::new(body).varint()?;
+                out.push(self.chunk_radius());
+            }
+            // System Chat
+            (ProtocolState::Play, 0x67) => {
+                let mut r = PacketReader::new(body);
+                let message = plain_text(r.str()?);
+                out.push(Self::text(r.bool()?, &message));
+            }
+            (ProtocolState::Play, 0x37) => out.push(Self::player_chat(body)?),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl ProtocolTranslator for BedrockTranslator {
+    fn client_version(&self) -> i32 {
+        BEDROCK_PROTOCOL_VERSION
+    }
+
+    fn serverbound(
+        &mut self,
+        _state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<(), DecodeError> {
+        out.push(packet.to_vec());
+        Ok(())
+    }
+
+    /// Packets that don't parse (which libmc doesn't send) are dropped
+    fn clientbound(
+        &mut self,
+        state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+        reply: &mut Vec<Vec<u8>>,
+    ) {
+        let (mut translated, mut replies) = (Vec::new(), Vec::new());
+        if self
+            .translate(state, packet, &mut translated, &mut replies)
+            .is_ok()
+        {
+            out.append(&mut translated);
+            reply.append(&mut replies);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_network_ids() {
+        assert_eq!(
+            BedrockBlock::new("minecraft:air").network_id() as i32,
+            -604749536
+        );
+        let blocks = BedrockBlocks::default();
+        assert_eq!(
+            blocks.network_id(0),
+            BedrockBlock::new("minecraft:air").network_id()
+        );
+        assert_eq!(
+            blocks.network_id(1),
+            BedrockBlock::new("minecraft:stone").network_id()
+        );
+        // states are hashed in order of their names
+        let a = BedrockBlock::new("minecraft:wool")
+            .with("color", "red")
+            .with("age", 1);
+        let b = BedrockBlock::new("minecraft:wool")
+            .with("age", 1)
+            .with("color", "red");
+        assert_eq!(a.network_id(), b.network_id());
+        assert_ne!(
+            a.network_id(),
+            BedrockBlock::new("minecraft:wool").network_id()
+        );
+    }
+
+    #[test]
+    fn chunks_and_chat() {
+        let mut t = BedrockTranslator::new(Arc::default());
+        let (mut out, mut reply) = (Vec::new(), Vec::new());
+
+        let mut chunk = Chunk::new(-64, 384, Arc::new(ChunkSection::new(0, 0, 1)));
+        chunk.set_block(1, -64, 2, 5);
+        chunk.set_block(0, 0, 0, 7);
+        let chunk_packet = chunk.to_packet(ChunkPos { x: 3, z: -1 });
+        let encoded = encode_packet(chunk_packet.packet(), PROTOCOL_VERSION);
+        t.clientbound(ProtocolState::Play, &encoded, &mut out, &mut reply);
+        assert_eq!(out.len(), 1);
+        let p = &out[0];
+        // ID, X, Z (zigzagged), 24 sub-chunks, not cached
+        assert_eq!(&p[..5], [LEVEL_CHUNK as u8, 6, 1, 24, 0]);
+        let stone = BedrockBlock::new("minecraft:stone").network_id();
+        let air = BedrockBlock::new("minecraft:air").network_id();
+
+        // the bottom sub-chunk: version, one layer, Y index -4, then 1 bit per block
+        let payload_start = 5 + p[5..].iter().position(|b| b & 0x80 == 0).unwrap() + 1;
+        let sub_chunk = &p[payload_start..];
+        assert_eq!(&sub_chunk[..4], [9, 1, (-4i8) as u8, 1 << 1 | 1]);
+        let words = &sub_chunk[4..4 + 512];
+        let bit = |i: usize| words[i / 32 * 4 + i % 32 / 8] >> (i % 8) & 1;
+        // x 1, z 2, y 0 is the one block of stone
+        assert_eq!(bit((16 + 2) * 16), 1);
+        assert_eq!((0..4096).filter(|i| bit(*i) == 1).count(), 1);
+        let mut palette = Vec::new();
+        write_zigzag(&mut palette, 2);
+        write_zigzag(&mut palette, i64::from(air as i32));
+        write_zigzag(&mut palette, i64::from(stone as i32));
+        assert_eq!(&sub_chunk[4 + 512..4 + 512 + palette.len()], palette);
+
+        out.clear();
+        let text = TextComponent::text("hi ").append(TextComponent::text("there"));
+        let packet = OutPacket::SystemChat {
+            content: &text,
+            overlay: false,
+        };
+        t.clientbound(
+            ProtocolState::Play,
+            &encode_packet(packet, PROTOCOL_VERSION),
+            &mut out,
+            &mut reply,
+        );
+        assert_eq!(out, [BedrockTranslator::text(false, "hi there")]);
+        assert!(reply.is_empty());
+
+        // Keep Alives are answered for the client
+        let packet = OutPacket::KeepAlive { id: 42 };
+        t.clientbound(
+            ProtocolState::Play,
+            &encode_packet(packet, PROTOCOL_VERSION),
+            &mut out,
+            &mut reply,
+        );
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &reply[0]),
+            Ok(InPacket::KeepAlive { id: 42 })
+        ));
+    }
+}
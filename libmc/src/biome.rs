@@ -0,0 +1,246 @@
+use crate::util::sha256;
+use crate::*;
+use std::borrow::Cow;
+
+/// How a biome looks and behaves, as sent to clients in the registry data.
+/// Colors are RGB, e.g. `0x7BA4FF`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Biome {
+    /// Whether it rains (or snows, if it's cold enough)
+    pub has_precipitation: bool,
+    pub temperature: f32,
+    pub downfall: f32,
+    pub sky_color: i32,
+    pub fog_color: i32,
+    pub water_color: i32,
+    pub water_fog_color: i32,
+    /// None to color grass by temperature and downfall
+    pub grass_color: Option<i32>,
+    /// None to color leaves by temperature and downfall
+    pub foliage_color: Option<i32>,
+}
+
+/// Plains
+impl Default for Biome {
+    fn default() -> Self {
+        Self {
+            has_precipitation: true,
+            temperature: 0.8,
+            downfall: 0.4,
+            sky_color: 0x78A7FF,
+            fog_color: 0xC0D8FF,
+            water_color: 0x3F76E4,
+            water_fog_color: 0x050533,
+            grass_color: None,
+            foliage_color: None,
+        }
+    }
+}
+
+impl Biome {
+    /// The biome's `element` in the registry data
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let mut effects = CompoundNbt::new("effects");
+        effects.set("sky_color", Nbt::Int(self.sky_color));
+        effects.set("fog_color", Nbt::Int(self.fog_color));
+        effects.set("water_color", Nbt::Int(self.water_color));
+        effects.set("water_fog_color", Nbt::Int(self.water_fog_color));
+        if let Some(color) = self.grass_color {
+            effects.set("grass_color", Nbt::Int(color));
+        }
+        if let Some(color) = self.foliage_color {
+            effects.set("foliage_color", Nbt::Int(color));
+        }
+
+        let mut element = CompoundNbt::new("element");
+        element.set(
+            "has_precipitation",
+            Nbt::Byte(self.has_precipitation.into()),
+        );
+        element.set("temperature", Nbt::Float(self.temperature));
+        element.set("downfall", Nbt::Float(self.downfall));
+        element.set("effects", Nbt::Compound(effects));
+        element
+    }
+}
+
+/// The `hashed_seed` of `LoginPlay` and `Respawn` for a world generated from `seed`: the first 8
+/// bytes of its SHA-256. Clients use it to blend biome colors the same way the server's world does.
+pub fn hashed_seed(seed: i64) -> i64 {
+    let hash = sha256(&seed.to_le_bytes());
+    i64::from_le_bytes(hash[..8].try_into().unwrap())
+}
+
+/// The biomes of a world, which get network IDs in the order they're added.
+///
+/// Vanilla clients need `minecraft:plains` to exist, so a new registry starts out with it.
+#[derive(Debug, Clone)]
+pub struct BiomeRegistry {
+    biomes: Vec<(String, Biome)>,
+}
+
+impl Default for BiomeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BiomeRegistry {
+    pub fn new() -> Self {
+        Self {
+            biomes: vec![("minecraft:plains".to_owned(), Biome::default())],
+        }
+    }
+
+    /// Adds a biome, or replaces the one with the same name. Returns its network ID.
+    pub fn add(&mut self, name: &str, biome: Biome) -> i32 {
+        match self.id(name) {
+            Some(id) => {
+                self.biomes[id as usize].1 = biome;
+                id
+            }
+            None => {
+                self.biomes.push((name.to_owned(), biome));
+                (self.biomes.len() - 1).try_into().unwrap()
+            }
+        }
+    }
+
+    /// The network ID of the biome `name`
+    pub fn id(&self, name: &str) -> Option<i32> {
+        let i = self.biomes.iter().position(|(n, _)| n == name)?;
+        Some(i.try_into().unwrap())
+    }
+
+    /// The name and biome with network ID `id`
+    pub fn get(&self, id: i32) -> Option<(&str, &Biome)> {
+        let (name, biome) = self.biomes.get(usize::try_from(id).ok()?)?;
+        Some((name, biome))
+    }
+
+    pub fn len(&self) -> usize {
+        self.biomes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biomes.is_empty()
+    }
+
+    /// The `minecraft:worldgen/biome` entry of the registry codec sent in Registry Data
+    pub fn to_registry_nbt(&self) -> CompoundNbt<'static> {
+        let entries: Vec<CompoundNbt> = self
+            .biomes
+            .iter()
+            .enumerate()
+            .map(|(id, (name, biome))| {
+                let mut entry = CompoundNbt::new("");
+                entry.set("name", Nbt::String(Cow::Owned(name.clone())));
+                entry.set("id", Nbt::Int(id.try_into().unwrap()));
+                entry.set("element", Nbt::Compound(biome.to_nbt()));
+                entry
+            })
+            .collect();
+        let mut registry = CompoundNbt::new("minecraft:worldgen/biome");
+        registry.set(
+            "type",
+            Nbt::String(Cow::Borrowed("minecraft:worldgen/biome")),
+        );
+        registry.set("value", Nbt::List(NbtList::Compound(Cow::Owned(entries))));
+        registry
+    }
+
+    /// The biomes paletted container of a chunk section, which goes right after its block states.
+    /// `biomes` are network IDs of each 4x4x4 cell, indexed by `(y * 4 + z) * 4 + x`.
+    pub fn encode_biomes(&self, biomes: &[i32; 64]) -> Vec<u8> {
+        let mut palette: Vec<i32> = Vec::new();
+        for &b in biomes {
+            assert!(self.get(b).is_some(), "unknown biome ID {b}");
+            if !palette.contains(&b) {
+                palette.push(b);
+            }
+        }
+
+        let mut out = Vec::new();
+        if let [single] = palette[..] {
+            write_ubyte(&mut out, 0);
+            write_varint(&mut out, single);
+            // no data array
+            write_varint(&mut out, 0);
+            return out;
+        }
+        let bits = PackedIntArray::bits_for((palette.len() - 1) as u32);
+        // biome palettes are indirect for up to 3 bits, past that the IDs are used directly
+        let (bits, values): (u8, Vec<u32>) = if bits <= 3 {
+            let index = |b| palette.iter().position(|&p| p == b).unwrap() as u32;
+            (bits, biomes.iter().map(|&b| index(b)).collect())
+        } else {
+            let bits = PackedIntArray::bits_for((self.len() - 1) as u32);
+            (bits, biomes.iter().map(|&b| b as u32).collect())
+        };
+        write_ubyte(&mut out, bits);
+        if bits <= 3 {
+            write_varint(&mut out, palette.len().try_into().unwrap());
+            for &b in &palette {
+                write_varint(&mut out, b);
+            }
+        }
+        out.extend_from_slice(&PackedIntArray::pack(bits, &values).encode());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes() {
+        let mut registry = BiomeRegistry::new();
+        let desert = registry.add(
+            "minecraft:desert",
+            Biome {
+                has_precipitation: false,
+                temperature: 2.0,
+                downfall: 0.0,
+                ..Biome::default()
+            },
+        );
+        assert_eq!(desert, 1);
+        assert_eq!(registry.id("minecraft:plains"), Some(0));
+
+        // single valued
+        assert_eq!(registry.encode_biomes(&[desert; 64]), [0, 1, 0]);
+
+        // indirect: the bottom half is plains
+        let mut biomes = [desert; 64];
+        biomes[..32].fill(0);
+        let encoded = registry.encode_biomes(&biomes);
+        // 1 bit, palette [plains, desert], one long
+        assert_eq!(encoded[..5], [1, 2, 0, 1, 1]);
+        assert_eq!(encoded[5..], 0xFFFFFFFF00000000u64.to_be_bytes());
+
+        // direct once there are more than 8 biomes in a section
+        for i in 2..20 {
+            registry.add(&format!("test:biome{i}"), Biome::default());
+        }
+        let biomes: [i32; 64] = std::array::from_fn(|i| (i % 20) as i32);
+        assert_eq!(registry.encode_biomes(&biomes)[..2], [5, 6]);
+
+        let nbt = registry.to_registry_nbt();
+        let Some(Nbt::List(NbtList::Compound(entries))) = nbt.get("value") else {
+            panic!("no biome list");
+        };
+        assert_eq!(entries.len(), 20);
+
+        assert_eq!(
+            entries[1].get("name"),
+            Some(&Nbt::String("minecraft:desert".into()))
+        );
+    }
+
+    #[test]
+    fn hashed_seed() {
+        assert_eq!(super::hashed_seed(0), 8794265229978523055);
+        assert_eq!(super::hashed_seed(12345), 293737985876514017);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::*;
+
+/// The player inventory slot of the first hotbar slot
+const HOTBAR_START: i16 = 36;
+
+/// How many times a written book has been copied, shown under its title
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BookGeneration {
+    #[default]
+    Original = 0,
+    CopyOfOriginal = 1,
+    CopyOfCopy = 2,
+    Tattered = 3,
+}
+
+/// A written book, e.g. for showing rules or menus with clickable text.
+///
+/// To show it without giving it to the player, send `open_packets()` with the NBT from `nbt_bytes()`,
+/// then send the slot's real contents again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WrittenBook {
+    pub title: String,
+    pub author: String,
+    pub generation: BookGeneration,
+    pub pages: Vec<TextComponent>,
+}
+
+impl WrittenBook {
+    pub fn new(title: impl Into<String>, author: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            author: author.into(),
+            generation: BookGeneration::Original,
+            pages: Vec::new(),
+        }
+    }
+
+    /// Adds a page to the end of the book
+    pub fn page(mut self, page: TextComponent) -> Self {
+        self.pages.push(page);
+        self
+    }
+
+    /// The `minecraft:written_book` item's NBT
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let pages: Vec<_> = self.pages.iter().map(|p| p.to_json().into()).collect();
+
+        let mut nbt = CompoundNbt::new("");
+        nbt.set("title", Nbt::String(self.title.clone().into()));
+        nbt.set("author", Nbt::String(self.author.clone().into()));
+        nbt.set("generation", Nbt::Int(self.generation as i32));
+        nbt.set("pages", Nbt::List(NbtList::String(pages.into())));
+        // the pages are plain components, with no selectors or scores left to fill in
+        nbt.set("resolved", Nbt::Byte(1));
+        nbt
+    }
+
+    /// `to_nbt()` in network form, for `Slot::nbt`
+    pub fn nbt_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_network_nbt(&mut buf, &self.to_nbt(), PROTOCOL_VERSION);
+        buf
+    }
+
+    /// Puts the book in the player's selected hotbar slot (0 to 8) and opens it.
+    /// `book_item_id` is `minecraft:written_book`'s ID (see `ItemRegistry`).
+    pub fn open_packets(nbt: &[u8], book_item_id: i32, held_slot: i16) -> [OutPacket<'_>; 2] {
+        [
+            OutPacket::SetContainerSlot {
+                window_id: 0,
+                state_id: 0,
+                slot: HOTBAR_START + held_slot,
+                item: Some(Slot {
+                    item_id: book_item_id,
+                    count: 1,
+                    nbt: Some(nbt),
+                }),
+            },
+            OutPacket::OpenBook { hand: Hand::Main },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn book_nbt() {
+        let book = WrittenBook::new("Rules", "Server")
+            .page(TextComponent::text("Be nice"))
+            .page(TextComponent::text("That's it"));
+        let nbt = book.to_nbt();
+        assert_eq!(
+            nbt.get("pages"),
+            Some(&Nbt::List(NbtList::String(
+                vec![
+                    r#"{"text":"Be nice"}"#.into(),
+                    r#"{"text":"That's it"}"#.into()
+                ]
+                .into()
+            )))
+        );
+
+        let bytes = book.nbt_bytes();
+        assert_eq!(Nbt::read_network_compound(&mut &bytes[..]), nbt);
+        let [_, open] = WrittenBook::open_packets(&bytes, 1100, 2);
+        assert_eq!(encode_packet(open, PROTOCOL_VERSION), [0x30, 0]);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BossBarColor {
+    Pink = 0,
+    Blue = 1,
+    Red = 2,
+    Green = 3,
+    Yellow = 4,
+    Purple = 5,
+    White = 6,
+}
+
+/// How many notches the bar is split into
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BossBarDivision {
+    None = 0,
+    Notches6 = 1,
+    Notches10 = 2,
+    Notches12 = 3,
+    Notches20 = 4,
+}
+
+#[derive(Debug, Clone)]
+pub enum BossBarAction<'a> {
+    Add {
+        title: &'a TextComponent,
+        /// 0.0 to 1.0
+        health: f32,
+        color: BossBarColor,
+        division: BossBarDivision,
+        /// 0x1 = darken sky, 0x2 = dragon bar, 0x4 = create fog
+        flags: u8,
+    },
+    Remove,
+    UpdateHealth(f32),
+    UpdateTitle(&'a TextComponent),
+    UpdateStyle {
+        color: BossBarColor,
+        division: BossBarDivision,
+    },
+    UpdateFlags(u8),
+}
+
+/// Client-side state of a single boss bar, for generating the packets that show/update/hide it
+#[derive(Debug, Clone)]
+pub struct BossBar {
+    uuid: Uuid,
+    pub title: TextComponent,
+    /// 0.0 to 1.0
+    pub health: f32,
+    pub color: BossBarColor,
+    pub division: BossBarDivision,
+}
+
+impl BossBar {
+    pub fn new(title: TextComponent, color: BossBarColor, division: BossBarDivision) -> Self {
+        Self {
+            uuid: Uuid::random(),
+            title,
+            health: 1.0,
+            color,
+            division,
+        }
+    }
+
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn add_packet(&self) -> OutPacket<'_> {
+        OutPacket::BossBar {
+            uuid: self.uuid,
+            action: BossBarAction::Add {
+                title: &self.title,
+                health: self.health,
+                color: self.color,
+                division: self.division,
+                flags: 0,
+            },
+        }
+    }
+
+    pub fn health_packet(&self) -> OutPacket<'_> {
+        OutPacket::BossBar {
+            uuid: self.uuid,
+            action: BossBarAction::UpdateHealth(self.health),
+        }
+    }
+
+    pub fn title_packet(&self) -> OutPacket<'_> {
+        OutPacket::BossBar {
+            uuid: self.uuid,
+            action: BossBarAction::UpdateTitle(&self.title),
+        }
+    }
+
+    pub fn style_packet(&self) -> OutPacket<'_> {
+        OutPacket::BossBar {
+            uuid: self.uuid,
+            action: BossBarAction::UpdateStyle {
+                color: self.color,
+                division: self.division,
+            },
+        }
+    }
+
+    pub fn remove_packet(&self) -> OutPacket<'_> {
+        OutPacket::BossBar {
+            uuid: self.uuid,
+            action: BossBarAction::Remove,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Bots: `Client`s that walk along paths and chat, for load testing a server with many clients.
+
+use crate::*;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a tick is, for bots as for servers
+const TICK: Duration = Duration::from_millis(50);
+
+/// What a bot received while it was running
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BotStats {
+    pub ticks: u64,
+    pub packets: u64,
+    /// Chunk Data packets
+    pub chunks: u64,
+}
+
+/// A logged in client that walks where it's told to, one step per tick
+#[derive(Debug)]
+pub struct Bot {
+    client: Client,
+    pos: Vec3,
+    path: VecDeque<Vec3>,
+    /// Blocks per tick. Vanilla players walk about 0.22 and sprint about 0.28.
+    pub speed: f64,
+    stats: BotStats,
+}
+
+impl Bot {
+    pub fn connect(addr: SocketAddr, username: &str) -> Result<Self, ClientError> {
+        Ok(Self::new(Client::connect(addr, username)?))
+    }
+
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            pos: Vec3::ZERO,
+            path: VecDeque::new(),
+            speed: 0.22,
+            stats: BotStats::default(),
+        }
+    }
+
+    pub fn client_mut(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    /// Where the bot is, as far as it knows. The server moves it with Synchronize Player Position.
+    pub fn pos(&self) -> Vec3 {
+        self.pos
+    }
+
+    pub fn stats(&self) -> BotStats {
+        self.stats
+    }
+
+    /// Walks to each point of `path` in turn, after any points it was already walking to
+    pub fn walk(&mut self, path: impl IntoIterator<Item = Vec3>) {
+        self.path.extend(path);
+    }
+
+    /// Whether the bot has walked its whole path
+    pub fn is_idle(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    pub fn chat(&mut self, message: &str) -> Result<(), ClientError> {
+        Ok(self.client.chat(message)?)
+    }
+
+    /// Handles packets until `deadline`, then takes a step along the path
+    pub fn tick(&mut self, deadline: Instant) -> Result<(), ClientError> {
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            let Some(packet) = self.client.poll(deadline - now)? else {
+                break;
+            };
+            self.received(&packet)?;
+        }
+        self.stats.ticks += 1;
+        self.step()
+    }
+
+    fn received(&mut self, packet: &ClientboundPacket) -> Result<(), ClientError> {
+        self.stats.packets += 1;
+        match (packet.state, packet.id) {
+            // Chunk Data and Update Light
+            (ProtocolState::Play, 0x25) => self.stats.chunks += 1,
+            // Synchronize Player Position
+            (ProtocolState::Play, 0x3E) => {
+                let mut r = packet.reader();
+                let (x, y, z) = (r.double()?, r.double()?, r.double()?);
+                let (_yaw, _pitch) = (r.float()?, r.float()?);
+                let relative = r.byte()?;
+                let axis = |bit, old, new| if relative & bit != 0 { old + new } else { new };
+                self.pos = Vec3::new(
+                    axis(0x01, self.pos.x, x),
+                    axis(0x02, self.pos.y, y),
+                    axis(0x04, self.pos.z, z),
+                );
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<(), ClientError> {
+        let Some(&target) = self.path.front() else {
+            return Ok(());
+        };
+        let to_target = target - self.pos;
+        if to_target.length() <= self.speed {
+            self.pos = target;
+            self.path.pop_front();
+        } else {
+            self.pos = self.pos + to_target.normalize() * self.speed;
+        }
+        let Vec3 { x, y, z } = self.pos;
+        Ok(self.client.set_position(x, y, z, true)?)
+    }
+}
+
+/// Many bots, each on its own thread, named `Bot0`, `Bot1`, ...
+///
+/// Every tick, each bot handles what it received, `behavior` is called with the bot's index and
+/// the bot, and the bot takes a step along its path.
+#[derive(Debug)]
+pub struct BotSwarm {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<Result<BotStats, ClientError>>>,
+}
+
+impl BotSwarm {
+    pub fn spawn(
+        addr: SocketAddr,
+        count: usize,
+        behavior: impl Fn(usize, &mut Bot) + Send + Sync + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let behavior = Arc::new(behavior);
+        let threads = (0..count)
+            .map(|i| {
+                let stop = Arc::clone(&stop);
+                let behavior = Arc::clone(&behavior);
+                thread::spawn(move || {
+                    let mut bot = Bot::connect(addr, &format!("Bot{i}"))?;
+                    let mut next_tick = Instant::now();
+                    while !stop.load(Ordering::Relaxed) {
+                        next_tick += TICK;
+                        bot.tick(next_tick)?;
+                        behavior(i, &mut bot);
+                    }
+                    Ok(bot.stats())
+                })
+            })
+            .collect();
+        Self { stop, threads }
+    }
+
+    /// Stops every bot, returning what each received or why it stopped early
+    pub fn stop(self) -> Vec<Result<BotStats, ClientError>> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.threads
+            .into_iter()
+            .map(|t| t.join().expect("bot thread panicked"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use std::net::TcpListener;
+
+    #[test]
+    fn walks_path() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let swarm = BotSwarm::spawn(addr, 1, |_, bot| {
+            if bot.stats().ticks == 1 {
+                bot.walk([Vec3::new(0.0, 64.0, 0.5)]);
+            }
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut r = BufReader::new(stream.try_clone().unwrap());
+        let mut ps = PacketStream::new(stream);
+        let mut steps = Vec::new();
+        loop {
+            let frame = read_frame(&mut r).unwrap();
+            match ps.decode(&frame).unwrap() {
+                InPacket::LoginStart { name, player_uuid } => {
+                    assert_eq!(name, "Bot0");
+                    let profile = GameProfile::new(player_uuid, name);
+                    ps.send(OutPacket::LoginSuccess { profile: &profile })
+                        .unwrap();
+                }
+                InPacket::LoginAck => {
+                    ps.send(OutPacket::FinishConfig).unwrap();
+                }
+                InPacket::FinishConfig => {
+                    ps.send(OutPacket::SyncPlayerPos {
+                        x: 0.0,
+                        y: 64.0,
+                        z: 0.0,
+                        yaw: 0.0,
+                        pitch: 0.0,
+                        flags: 0,
+                        teleport_id: 3,
+                    })
+                    .unwrap();
+                }
+                InPacket::SetPlayerPosition { x, y, z, .. } => {
+                    steps.push([x, y, z]);
+                    if z == 0.5 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        assert!(steps.len() >= 3);
+        assert_eq!(steps.last(), Some(&[0.0, 64.0, 0.5]));
+        let stats = swarm.stop().pop().unwrap().unwrap();
+        assert!(stats.ticks >= 3);
+    }
+}
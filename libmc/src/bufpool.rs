@@ -0,0 +1,125 @@
+//! A pool of byte buffers, so that encoding packets and NBT reuses a few allocations instead of
+//! making new ones for every packet.
+//!
+//! Each thread has its own pool, so taking and returning buffers never waits on a lock.
+
+use std::cell::{Cell, RefCell};
+use std::ops::{Deref, DerefMut};
+
+/// How many buffers each thread keeps
+const MAX_POOLED: usize = 64;
+
+/// Buffers that grew bigger than this (the longest a frame can be) aren't kept, so one huge
+/// packet doesn't keep its memory
+const MAX_POOLED_CAPACITY: usize = 1 << 21;
+
+thread_local! {
+    static POOL: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+    static STATS: Cell<BufPoolStats> = const {
+        Cell::new(BufPoolStats {
+            reused: 0,
+            allocated: 0,
+        })
+    };
+}
+
+/// How often this thread's pool had a buffer to hand out
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BufPoolStats {
+    pub reused: u64,
+    /// Taken while the pool was empty
+    pub allocated: u64,
+}
+
+/// This thread's `BufPoolStats`
+pub fn buf_pool_stats() -> BufPoolStats {
+    STATS.with(Cell::get)
+}
+
+/// An empty buffer from this thread's pool, which goes back to the pool when dropped
+#[derive(Debug, Default)]
+pub struct PooledBuf {
+    buf: Vec<u8>,
+}
+
+impl PooledBuf {
+    pub fn take() -> Self {
+        let buf = POOL.with(|pool| pool.borrow_mut().pop());
+        STATS.with(|stats| {
+            let mut s = stats.get();
+            match buf {
+                Some(_) => s.reused += 1,
+                None => s.allocated += 1,
+            }
+            stats.set(s);
+        });
+        Self {
+            buf: buf.unwrap_or_default(),
+        }
+    }
+
+    /// The buffer itself, which then doesn't go back to the pool
+    pub fn into_vec(mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        let mut buf = std::mem::take(&mut self.buf);
+        if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buf.clear();
+        // the pool is gone if the thread is exiting
+        let _ = POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() < MAX_POOLED {
+                pool.push(buf);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse() {
+        let before = buf_pool_stats();
+        let mut buf = PooledBuf::take();
+        buf.extend_from_slice(&[1; 1000]);
+        let ptr = buf.as_ptr();
+        drop(buf);
+
+        let buf = PooledBuf::take();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+        assert!(buf.capacity() >= 1000);
+        let after = buf_pool_stats();
+        assert_eq!(
+            after.reused + after.allocated,
+            before.reused + before.allocated + 2
+        );
+        assert!(after.reused > before.reused);
+
+        // kept out of the pool
+        let v = buf.into_vec();
+        assert_ne!(PooledBuf::take().as_ptr(), v.as_ptr());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::util::random_u64;
+use crate::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The command that clickable callback components make the client run
+const CALLBACK_COMMAND: &str = "run_callback";
+
+type Callback<C> = Box<dyn FnMut(&mut C, Uuid)>;
+
+struct Entry<C> {
+    f: Callback<C>,
+    expires: Instant,
+    /// Only this player may run the callback
+    owner: Option<Uuid>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CallbackResult {
+    /// The command wasn't a `run_callback` command; handle it normally
+    NotACallback,
+    Ran,
+    /// The token is unknown, expired, or belongs to a different player
+    Invalid,
+    /// The player clicked too soon after their previous click
+    RateLimited,
+}
+
+/// Maps clickable chat components to Rust closures, so chat buttons don't need a registered command each.
+///
+/// Each registered closure gets an unguessable token, and clicking the component makes the
+/// client send `/run_callback <token>`, which should be passed to `handle_command()`.
+/// The closure is called with the `C` passed to `handle_command()` and the clicking player's UUID.
+pub struct CallbackRegistry<C = (), T: Clock = SystemClock> {
+    clock: T,
+    callbacks: HashMap<u64, Entry<C>>,
+    /// Minimum time between two callbacks run by the same player
+    cooldown: Duration,
+    last_run: HashMap<Uuid, Instant>,
+}
+
+impl<C> CallbackRegistry<C> {
+    pub fn new(cooldown: Duration) -> Self {
+        Self::with_clock(cooldown, SystemClock)
+    }
+}
+
+impl<C, T: Clock> CallbackRegistry<C, T> {
+    pub fn with_clock(cooldown: Duration, clock: T) -> Self {
+        Self {
+            clock,
+            callbacks: HashMap::new(),
+            cooldown,
+            last_run: HashMap::new(),
+        }
+    }
+
+    /// Registers a callback that anyone can run until `ttl` has passed.
+    /// Returns the click event to attach to a component (see `TextComponent::on_click`).
+    pub fn register(&mut self, ttl: Duration, f: impl FnMut(&mut C, Uuid) + 'static) -> ClickEvent {
+        self.insert(None, ttl, Box::new(f))
+    }
+
+    /// Like `register()`, but only `player` is allowed to run the callback
+    pub fn register_for(
+        &mut self,
+        player: Uuid,
+        ttl: Duration,
+        f: impl FnMut(&mut C, Uuid) + 'static,
+    ) -> ClickEvent {
+        self.insert(Some(player), ttl, Box::new(f))
+    }
+
+    fn insert(&mut self, owner: Option<Uuid>, ttl: Duration, f: Callback<C>) -> ClickEvent {
+        let mut token = random_u64();
+        while self.callbacks.contains_key(&token) {
+            token = random_u64();
+        }
+        self.callbacks.insert(
+            token,
+            Entry {
+                f,
+                expires: self.clock.now() + ttl,
+                owner,
+            },
+        );
+
+        ClickEvent::RunCommand(format!("/{CALLBACK_COMMAND} {token:x}"))
+    }
+
+    /// Removes the callback behind `ev`. Returns false if it was already gone.
+    pub fn unregister(&mut self, ev: &ClickEvent) -> bool {
+        let ClickEvent::RunCommand(cmd) = ev else {
+            return false;
+        };
+        match parse_token(cmd.trim_start_matches('/')) {
+            Some(token) => self.callbacks.remove(&token).is_some(),
+            None => false,
+        }
+    }
+
+    /// Runs the callback for a command sent by `player` (as in `InPacket::ChatCommand`)
+    pub fn handle_command(&mut self, ctx: &mut C, player: Uuid, command: &str) -> CallbackResult {
+        let Some(token) = parse_token(command) else {
+            return CallbackResult::NotACallback;
+        };
+
+        let now = self.clock.now();
+        let Some(entry) = self.callbacks.get_mut(&token) else {
+            return CallbackResult::Invalid;
+        };
+        if entry.expires <= now {
+            self.callbacks.remove(&token);
+            return CallbackResult::Invalid;
+        }
+        if entry.owner.is_some_and(|owner| owner != player) {
+            return CallbackResult::Invalid;
+        }
+        if let Some(last) = self.last_run.get(&player) {
+            if now.duration_since(*last) < self.cooldown {
+                return CallbackResult::RateLimited;
+            }
+        }
+
+        self.last_run.insert(player, now);
+        (entry.f)(ctx, player);
+        CallbackResult::Ran
+    }
+
+    /// Drops expired callbacks and stale rate limit entries. Call this every so often.
+    pub fn purge_expired(&mut self) {
+        let now = self.clock.now();
+        self.callbacks.retain(|_, e| e.expires > now);
+        let cooldown = self.cooldown;
+        self.last_run
+            .retain(|_, t| now.duration_since(*t) < cooldown);
+    }
+
+    /// Number of live (possibly expired but not yet purged) callbacks
+    pub fn len(&self) -> usize {
+        self.callbacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+}
+
+fn parse_token(command: &str) -> Option<u64> {
+    let mut args = command.split_whitespace();
+    match (args.next(), args.next(), args.next()) {
+        (Some(CALLBACK_COMMAND), Some(token), None) => u64::from_str_radix(token, 16).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_of(ev: &ClickEvent) -> &str {
+        let ClickEvent::RunCommand(cmd) = ev else {
+            panic!("not a callback")
+        };
+        cmd.strip_prefix('/').unwrap()
+    }
+
+    #[test]
+    fn run_and_rate_limit() {
+        let mut reg = CallbackRegistry::<Vec<Uuid>>::new(Duration::from_secs(60));
+        let ev = reg.register(Duration::from_secs(60), |clicks, player| {
+            clicks.push(player)
+        });
+
+        let mut clicks = Vec::new();
+        let cmd = command_of(&ev);
+        assert_eq!(
+            reg.handle_command(&mut clicks, Uuid(1), cmd),
+            CallbackResult::Ran
+        );
+        assert_eq!(
+            reg.handle_command(&mut clicks, Uuid(1), cmd),
+            CallbackResult::RateLimited
+        );
+        assert_eq!(
+            reg.handle_command(&mut clicks, Uuid(2), cmd),
+            CallbackResult::Ran
+        );
+        assert_eq!(clicks, vec![Uuid(1), Uuid(2)]);
+
+        assert_eq!(
+            reg.handle_command(&mut clicks, Uuid(3), "run_callback 0"),
+            CallbackResult::Invalid
+        );
+        assert_eq!(
+            reg.handle_command(&mut clicks, Uuid(3), "vote 1 2"),
+            CallbackResult::NotACallback
+        );
+
+        assert!(reg.unregister(&ev));
+        assert_eq!(
+            reg.handle_command(&mut clicks, Uuid(3), cmd),
+            CallbackResult::Invalid
+        );
+    }
+
+    #[test]
+    fn expiry_and_owner() {
+        let clock = ManualClock::new();
+        let mut reg = CallbackRegistry::<(), _>::with_clock(Duration::ZERO, clock.clone());
+        let expired = reg.register(Duration::from_secs(10), |_, _| {});
+        clock.advance(Duration::from_secs(10));
+        let owned = reg.register_for(Uuid(5), Duration::from_secs(60), |_, _| {});
+
+        assert_eq!(
+            reg.handle_command(&mut (), Uuid(5), command_of(&expired)),
+            CallbackResult::Invalid
+        );
+        assert_eq!(
+            reg.handle_command(&mut (), Uuid(6), command_of(&owned)),
+            CallbackResult::Invalid
+        );
+        assert_eq!(
+            reg.handle_command(&mut (), Uuid(5), command_of(&owned)),
+            CallbackResult::Ran
+        );
+
+        reg.purge_expired();
+        assert_eq!(reg.len(), 1);
+    }
+}
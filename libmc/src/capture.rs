@@ -0,0 +1,254 @@
+//! Packet captures: every packet sent and received on a connection, as it was on the wire,
+//! for debugging protocol mismatches with real clients.
+
+use crate::*;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PacketDirection {
+    /// From the client
+    Serverbound,
+    /// To the client
+    Clientbound,
+}
+
+impl PacketDirection {
+    fn name(self) -> &'static str {
+        match self {
+            PacketDirection::Serverbound => "serverbound",
+            PacketDirection::Clientbound => "clientbound",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "serverbound" => Some(PacketDirection::Serverbound),
+            "clientbound" => Some(PacketDirection::Clientbound),
+            _ => None,
+        }
+    }
+}
+
+/// One line of a capture file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPacket {
+    pub time_ms: u64,
+    pub dir: PacketDirection,
+    pub state: ProtocolState,
+    /// The packet's ID and body
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    /// A line (counting from 1) that isn't a captured packet
+    BadLine(usize, &'static str),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Io(e) => write!(f, "reading capture: {e}"),
+            CaptureError::BadLine(line, msg) => write!(f, "bad capture line {line}: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<io::Error> for CaptureError {
+    fn from(e: io::Error) -> Self {
+        CaptureError::Io(e)
+    }
+}
+
+/// Reads a capture written by `PacketCapture`
+pub fn read_capture(r: impl BufRead) -> Result<Vec<CapturedPacket>, CaptureError> {
+    let mut packets = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bad = |msg| CaptureError::BadLine(i + 1, msg);
+        let json = Json::parse(&line).map_err(|e| bad(e.msg))?;
+        let field = |key| json.get(key).ok_or(bad("missing field"));
+        let time_ms = field("time_ms")?.as_f64().ok_or(bad("bad time_ms"))? as u64;
+        let dir = field("dir")?
+            .as_str()
+            .and_then(PacketDirection::from_name)
+            .ok_or(bad("bad dir"))?;
+        let state = field("state")?
+            .as_str()
+            .and_then(state_from_name)
+            .ok_or(bad("bad state"))?;
+        let data = field("data")?
+            .as_str()
+            .and_then(from_hex)
+            .ok_or(bad("bad data"))?;
+        packets.push(CapturedPacket {
+            time_ms,
+            dir,
+            state,
+            data,
+        });
+    }
+    Ok(packets)
+}
+
+/// Reads a capture file written by `PacketCapture`
+pub fn read_capture_file(path: impl AsRef<Path>) -> Result<Vec<CapturedPacket>, CaptureError> {
+    read_capture(BufReader::new(File::open(path)?))
+}
+
+fn state_from_name(name: &str) -> Option<ProtocolState> {
+    Some(match name {
+        "Handshaking" => ProtocolState::Handshaking,
+        "Status" => ProtocolState::Status,
+        "Login" => ProtocolState::Login,
+        "Config" => ProtocolState::Config,
+        "Play" => ProtocolState::Play,
+        _ => return None,
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Records packets as JSON lines, e.g.
+/// `{"time_ms":1520,"dir":"serverbound","state":"Play","id":23,"data":"1701"}`.
+///
+/// `time_ms` counts from when the capture was created, `state` is the connection's state when the
+/// packet was sent or received, and `data` is the packet's ID and body in hex (without the length
+/// prefix). `id` is null if the packet doesn't start with a valid varint.
+/// Packets are in the client's protocol version, i.e. from before translation.
+pub struct PacketCapture {
+    out: Box<dyn Write>,
+    start: Instant,
+}
+
+impl fmt::Debug for PacketCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PacketCapture")
+            .field("start", &self.start)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PacketCapture {
+    /// Captures to a new file at `path`, replacing any existing one
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            start: Instant::now(),
+        }
+    }
+
+    /// Records one packet (its ID and body)
+    pub fn record(
+        &mut self,
+        dir: PacketDirection,
+        state: ProtocolState,
+        packet: &[u8],
+    ) -> io::Result<()> {
+        let mut line = format!(
+            r#"{{"time_ms":{},"dir":"{}","state":"{state:?}","id":"#,
+            self.start.elapsed().as_millis(),
+            dir.name(),
+        );
+        match packet_id(packet) {
+            Some(id) => line += &id.to_string(),
+            None => line += "null",
+        }
+        line += r#","data":""#;
+        for b in packet {
+            line += &format!("{b:02x}");
+        }
+        line += "\"}\n";
+        self.out.write_all(line.as_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Like `read_varint()`, but for packets that may be malformed
+pub(crate) fn packet_id(packet: &[u8]) -> Option<i32> {
+    let mut value = 0u32;
+    for (i, &b) in packet.iter().take(VarInt::MAX_LEN).enumerate() {
+        value |= ((b & 0x7F) as u32) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some(value as i32);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jsonl() {
+        let dir = std::env::temp_dir().join(format!("libmc-capture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.jsonl");
+
+        let mut capture = PacketCapture::create(&path).unwrap();
+        capture
+            .record(
+                PacketDirection::Serverbound,
+                ProtocolState::Play,
+                &[0x17, 0x01],
+            )
+            .unwrap();
+        capture
+            .record(PacketDirection::Clientbound, ProtocolState::Login, &[0xFF])
+            .unwrap();
+        drop(capture);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Json> = text.lines().map(|l| Json::parse(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].get("dir").unwrap().as_str(), Some("serverbound"));
+        assert_eq!(lines[0].get("state").unwrap().as_str(), Some("Play"));
+        assert_eq!(lines[0].get("id").unwrap().as_f64(), Some(23.0));
+        assert_eq!(lines[0].get("data").unwrap().as_str(), Some("1701"));
+        assert_eq!(lines[1].get("id"), Some(&Json::Null));
+
+        let packets = read_capture_file(&path).unwrap();
+        assert_eq!(
+            packets[0],
+            CapturedPacket {
+                time_ms: packets[0].time_ms,
+                dir: PacketDirection::Serverbound,
+                state: ProtocolState::Play,
+                data: vec![0x17, 0x01],
+            }
+        );
+        assert_eq!(packets[1].data, [0xFF]);
+        assert!(matches!(
+            read_capture(&b"{}"[..]),
+            Err(CaptureError::BadLine(1, _))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
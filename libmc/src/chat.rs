@@ -0,0 +1,410 @@
+use crate::*;
+use std::fmt::Write;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChatColor {
+    Black,
+    DarkBlue,
+    DarkGreen,
+    DarkAqua,
+    DarkRed,
+    DarkPurple,
+    Gold,
+    Gray,
+    DarkGray,
+    Blue,
+    Green,
+    Aqua,
+    Red,
+    LightPurple,
+    Yellow,
+    White,
+}
+
+impl ChatColor {
+    /// The name used for this color in the JSON chat format
+    pub fn name(self) -> &'static str {
+        use ChatColor::*;
+        match self {
+            Black => "black",
+            DarkBlue => "dark_blue",
+            DarkGreen => "dark_green",
+            DarkAqua => "dark_aqua",
+            DarkRed => "dark_red",
+            DarkPurple => "dark_purple",
+            Gold => "gold",
+            Gray => "gray",
+            DarkGray => "dark_gray",
+            Blue => "blue",
+            Green => "green",
+            Aqua => "aqua",
+            Red => "red",
+            LightPurple => "light_purple",
+            Yellow => "yellow",
+            White => "white",
+        }
+    }
+}
+
+/// What happens when the player clicks on a component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClickEvent {
+    /// Makes the client send this command (including the leading '/')
+    RunCommand(String),
+    /// Asks the player whether to open this http(s) URL in their browser
+    OpenUrl(String),
+    /// Puts this in the player's chat box, without sending it
+    SuggestCommand(String),
+    CopyToClipboard(String),
+}
+
+impl ClickEvent {
+    fn action(&self) -> (&'static str, &str) {
+        match self {
+            ClickEvent::RunCommand(cmd) => ("run_command", cmd),
+            ClickEvent::OpenUrl(url) => ("open_url", url),
+            ClickEvent::SuggestCommand(cmd) => ("suggest_command", cmd),
+            ClickEvent::CopyToClipboard(text) => ("copy_to_clipboard", text),
+        }
+    }
+}
+
+/// What's shown when the player hovers over a component
+#[derive(Debug, Clone, PartialEq)]
+pub enum HoverEvent {
+    ShowText(TextComponent),
+    /// An item's tooltip
+    ShowItem {
+        /// e.g. `minecraft:diamond_sword`
+        id: String,
+        count: i32,
+        /// The item's NBT, e.g. its enchantments and custom name
+        tag: Option<CompoundNbt<'static>>,
+    },
+    /// An entity's name, type and UUID, as shown with advanced tooltips (F3+H)
+    ShowEntity {
+        /// e.g. `minecraft:pig`
+        entity_type: String,
+        uuid: Uuid,
+        name: Option<TextComponent>,
+    },
+}
+
+/// A translation key, e.g. `chat.type.text`, which the client shows in its own language with
+/// `with` filled in. See `Language` for servers that resolve them themselves.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Translation {
+    pub key: String,
+    pub with: Vec<TextComponent>,
+}
+
+/// A 'JSON Chat' component
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextComponent {
+    /// Ignored if `translate` is set
+    pub text: String,
+    /// Boxed, since most components aren't translated
+    pub translate: Option<Box<Translation>>,
+    pub color: Option<ChatColor>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub click_event: Option<ClickEvent>,
+    /// Boxed, since few components have one
+    pub hover_event: Option<Box<HoverEvent>>,
+    /// Children, which inherit the style of this component
+    pub extra: Vec<TextComponent>,
+}
+
+impl TextComponent {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A translated component, e.g. `translate("multiplayer.player.joined", [name])`
+    pub fn translate(
+        key: impl Into<String>,
+        with: impl IntoIterator<Item = TextComponent>,
+    ) -> Self {
+        Self {
+            translate: Some(Box::new(Translation {
+                key: key.into(),
+                with: with.into_iter().collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    pub fn color(mut self, color: ChatColor) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn bold(mut self, bold: bool) -> Self {
+        self.bold = Some(bold);
+        self
+    }
+
+    pub fn italic(mut self, italic: bool) -> Self {
+        self.italic = Some(italic);
+        self
+    }
+
+    pub fn on_click(mut self, ev: ClickEvent) -> Self {
+        self.click_event = Some(ev);
+        self
+    }
+
+    pub fn on_hover(mut self, ev: HoverEvent) -> Self {
+        self.hover_event = Some(Box::new(ev));
+        self
+    }
+
+    /// Adds `child` to the end of this component's `extra` list
+    pub fn append(mut self, child: TextComponent) -> Self {
+        self.extra.push(child);
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut s = String::new();
+        self.write_json(&mut s);
+        s
+    }
+
+    /// The component as 1.20.3+ clients take it over the network, instead of JSON
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        fn string(s: &str) -> Nbt<'static> {
+            Nbt::String(s.to_owned().into())
+        }
+        fn list(components: &[TextComponent]) -> Nbt<'static> {
+            let list: Vec<_> = components.iter().map(TextComponent::to_nbt).collect();
+            Nbt::List(NbtList::Compound(list.into()))
+        }
+
+        let mut c = CompoundNbt::new("");
+        match &self.translate {
+            Some(t) => {
+                c.set("translate", string(&t.key));
+                if !t.with.is_empty() {
+                    c.set("with", list(&t.with));
+                }
+            }
+            None => c.set("text", string(&self.text)),
+        }
+        if let Some(color) = self.color {
+            c.set("color", string(color.name()));
+        }
+        if let Some(bold) = self.bold {
+            c.set("bold", Nbt::Byte(bold.into()));
+        }
+        if let Some(italic) = self.italic {
+            c.set("italic", Nbt::Byte(italic.into()));
+        }
+        if let Some(ev) = &self.click_event {
+            let (action, value) = ev.action();
+            let mut click = CompoundNbt::new("");
+            click.set("action", string(action));
+            click.set("value", string(value));
+            c.set("clickEvent", Nbt::Compound(click));
+        }
+        if let Some(ev) = &self.hover_event {
+            let mut hover = CompoundNbt::new("");
+            let mut contents = CompoundNbt::new("");
+            let action = match &**ev {
+                HoverEvent::ShowText(text) => {
+                    contents = text.to_nbt();
+                    "show_text"
+                }
+                HoverEvent::ShowItem { id, count, tag } => {
+                    contents.set("id", string(id));
+                    contents.set("count", Nbt::Int(*count));
+                    if let Some(tag) = tag {
+                        contents.set("tag", string(&tag.to_string()));
+                    }
+                    "show_item"
+                }
+                HoverEvent::ShowEntity {
+                    entity_type,
+                    uuid,
+                    name,
+                } => {
+                    contents.set("type", string(entity_type));
+                    contents.set("id", uuid.to_nbt());
+                    if let Some(name) = name {
+                        contents.set("name", Nbt::Compound(name.to_nbt()));
+                    }
+                    "show_entity"
+                }
+            };
+            hover.set("action", string(action));
+            hover.set("contents", Nbt::Compound(contents));
+            c.set("hoverEvent", Nbt::Compound(hover));
+        }
+        if !self.extra.is_empty() {
+            c.set("extra", list(&self.extra));
+        }
+        c
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match &self.translate {
+            Some(t) => {
+                out.push_str(r#"{"translate":"#);
+                write_json_string(out, &t.key);
+                if !t.with.is_empty() {
+                    out.push_str(r#","with":["#);
+                    for (i, arg) in t.with.iter().enumerate() {
+                        if i != 0 {
+                            out.push(',');
+                        }
+                        arg.write_json(out);
+                    }
+                    out.push(']');
+                }
+            }
+            None => {
+                out.push_str(r#"{"text":"#);
+                write_json_string(out, &self.text);
+            }
+        }
+        if let Some(color) = self.color {
+            write!(out, r#","color":"{}""#, color.name()).unwrap();
+        }
+        if let Some(bold) = self.bold {
+            write!(out, r#","bold":{bold}"#).unwrap();
+        }
+        if let Some(italic) = self.italic {
+            write!(out, r#","italic":{italic}"#).unwrap();
+        }
+        if let Some(ev) = &self.click_event {
+            let (action, value) = ev.action();
+            write!(out, r#","clickEvent":{{"action":"{action}","value":"#).unwrap();
+            write_json_string(out, value);
+            out.push('}');
+        }
+        if let Some(ev) = &self.hover_event {
+            out.push_str(r#","hoverEvent":{"action":"#);
+            match &**ev {
+                HoverEvent::ShowText(text) => {
+                    out.push_str(r#""show_text","contents":"#);
+                    text.write_json(out);
+                }
+                HoverEvent::ShowItem { id, count, tag } => {
+                    out.push_str(r#""show_item","contents":{"id":"#);
+                    write_json_string(out, id);
+                    write!(out, r#","count":{count}"#).unwrap();
+                    if let Some(tag) = tag {
+                        // the NBT goes in a string, as SNBT
+                        out.push_str(r#","tag":"#);
+                        write_json_string(out, &tag.to_string());
+                    }
+                    out.push('}');
+                }
+                HoverEvent::ShowEntity {
+                    entity_type,
+                    uuid,
+                    name,
+                } => {
+                    out.push_str(r#""show_entity","contents":{"type":"#);
+                    write_json_string(out, entity_type);
+                    write!(out, r#","id":"{uuid}""#).unwrap();
+                    if let Some(name) = name {
+                        out.push_str(r#","name":"#);
+                        name.write_json(out);
+                    }
+                    out.push('}');
+                }
+            }
+            out.push('}');
+        }
+        if !self.extra.is_empty() {
+            out.push_str(r#","extra":["#);
+            for (i, child) in self.extra.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                }
+                child.write_json(out);
+            }
+            out.push(']');
+        }
+        out.push('}');
+    }
+}
+
+impl From<&str> for TextComponent {
+    fn from(value: &str) -> Self {
+        Self::text(value)
+    }
+}
+
+impl From<String> for TextComponent {
+    fn from(value: String) -> Self {
+        Self::text(value)
+    }
+}
+
+/// Writes `s` as a quoted and escaped JSON string
+pub(crate) fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str(r#"\""#),
+            '\\' => out.push_str(r"\\"),
+            '\n' => out.push_str(r"\n"),
+            '\r' => out.push_str(r"\r"),
+            '\t' => out.push_str(r"\t"),
+            c if (c as u32) < 0x20 => write!(out, r"\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn component_json() {
+        let c = TextComponent::text("say \"hi\"\n")
+            .color(ChatColor::DarkAqua)
+            .bold(true)
+            .append(TextComponent::text("click").on_click(ClickEvent::RunCommand("/foo".into())));
+
+        assert_eq!(
+            c.to_json(),
+            r#"{"text":"say \"hi\"\n","color":"dark_aqua","bold":true,"extra":[{"text":"click","clickEvent":{"action":"run_command","value":"/foo"}}]}"#
+        );
+
+        let c = TextComponent::translate("chat.type.text", ["Steve".into(), "hi".into()]);
+        assert_eq!(
+            c.to_json(),
+            r#"{"translate":"chat.type.text","with":[{"text":"Steve"},{"text":"hi"}]}"#
+        );
+        let mut tag = CompoundNbt::new("");
+        tag.set("Damage".to_owned(), Nbt::Int(5));
+        let c = TextComponent::text("sword")
+            .on_click(ClickEvent::SuggestCommand("/give @s".into()))
+            .on_hover(HoverEvent::ShowItem {
+                id: "minecraft:iron_sword".into(),
+                count: 1,
+                tag: Some(tag),
+            });
+        assert_eq!(
+            c.to_json(),
+            r#"{"text":"sword","clickEvent":{"action":"suggest_command","value":"/give @s"},"hoverEvent":{"action":"show_item","contents":{"id":"minecraft:iron_sword","count":1,"tag":"{Damage: 5}"}}}"#
+        );
+        let c = TextComponent::text("pig").on_hover(HoverEvent::ShowEntity {
+            entity_type: "minecraft:pig".into(),
+            uuid: Uuid(1),
+            name: Some("Bob".into()),
+        });
+        assert_eq!(
+            c.to_json(),
+            r#"{"text":"pig","hoverEvent":{"action":"show_entity","contents":{"type":"minecraft:pig","id":"00000000-0000-0000-0000-000000000001","name":{"text":"Bob"}}}}"#
+        );
+    }
+}
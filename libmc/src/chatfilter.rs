@@ -0,0 +1,268 @@
+//! Chat filtering: a hook that sees every chat message before it's broadcast, and can pass,
+//! reject, rewrite or censor it. Censored parts are only hidden from players who turned text
+//! filtering on in their settings, as in vanilla.
+
+use crate::*;
+use std::fmt::Debug;
+use std::ops::Range;
+
+/// Which parts of a chat message players with text filtering on don't see
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum FilterMask {
+    #[default]
+    PassThrough,
+    /// The message isn't shown at all
+    FullyFiltered,
+    /// Bit `i` hides the `i`th UTF-16 code unit of the message, which the client shows as `#`
+    PartiallyFiltered(BitSet),
+}
+
+impl FilterMask {
+    /// Hides the characters of `message` in the byte `ranges`
+    pub fn from_ranges(message: &str, ranges: &[Range<usize>]) -> Self {
+        let units = message.encode_utf16().count();
+        let mut mask = BitSet::with_num_bits(units);
+        let mut unit = 0;
+        for (i, c) in message.char_indices() {
+            let len = c.len_utf16();
+            if ranges.iter().any(|r| r.contains(&i)) {
+                for u in unit..unit + len {
+                    mask.set(u);
+                }
+            }
+            unit += len;
+        }
+        match mask.count_ones() {
+            0 => FilterMask::PassThrough,
+            n if n == units => FilterMask::FullyFiltered,
+            _ => FilterMask::PartiallyFiltered(mask),
+        }
+    }
+
+    /// What a player with text filtering on sees of `message`, or None if it's hidden
+    pub fn apply(&self, message: &str) -> Option<String> {
+        let mask = match self {
+            FilterMask::PassThrough => return Some(message.to_string()),
+            FilterMask::FullyFiltered => return None,
+            FilterMask::PartiallyFiltered(mask) => mask,
+        };
+        let hidden = |u: usize| u < 64 * mask.longs().len() && mask.get(u);
+        let mut filtered = String::with_capacity(message.len());
+        let mut unit = 0;
+        for c in message.chars() {
+            let len = c.len_utf16();
+            if (unit..unit + len).any(hidden) {
+                filtered.extend(std::iter::repeat_n('#', len));
+            } else {
+                filtered.push(c);
+            }
+            unit += len;
+        }
+        Some(filtered)
+    }
+}
+
+/// What a `ChatFilter` decided to do with a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatVerdict {
+    Pass,
+    /// Nobody sees the message. The sender is told why, if there's a reason.
+    Reject(Option<TextComponent>),
+    /// Send this instead of the message
+    Rewrite(String),
+    /// Hide these byte ranges of the message from players with text filtering on
+    Censor(Vec<Range<usize>>),
+}
+
+/// Decides what happens to chat messages before they're broadcast, e.g. to censor swearing or
+/// block spam. See `FilteredChat::new()`.
+pub trait ChatFilter: Debug {
+    fn filter(&mut self, sender: ClientID, message: &str) -> ChatVerdict;
+}
+
+/// Censors (or rejects) messages containing any of a list of words, ignoring ASCII case
+#[derive(Debug, Clone, Default)]
+pub struct WordFilter {
+    words: Vec<String>,
+    /// Rejects messages with the words instead of censoring them
+    pub reject: bool,
+}
+
+impl WordFilter {
+    pub fn new<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            words: words
+                .into_iter()
+                .map(|w| w.as_ref().to_ascii_lowercase())
+                .filter(|w| !w.is_empty())
+                .collect(),
+            reject: false,
+        }
+    }
+}
+
+impl ChatFilter for WordFilter {
+    fn filter(&mut self, _sender: ClientID, message: &str) -> ChatVerdict {
+        // lowercasing ASCII keeps the byte offsets the same
+        let lower = message.to_ascii_lowercase();
+        let ranges: Vec<_> = self
+            .words
+            .iter()
+            .flat_map(|w| lower.match_indices(w.as_str()).map(|(i, w)| i..i + w.len()))
+            .collect();
+        match (ranges.is_empty(), self.reject) {
+            (true, _) => ChatVerdict::Pass,
+            (false, true) => ChatVerdict::Reject(None),
+            (false, false) => ChatVerdict::Censor(ranges),
+        }
+    }
+}
+
+/// A player's chat message that went through a `ChatFilter`, to send to everyone with
+/// `packet()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilteredChat {
+    pub sender: ClientID,
+    /// The message, rewritten if the filter did
+    pub message: String,
+    pub mask: FilterMask,
+    /// From Chat Message
+    pub timestamp: i64,
+    pub salt: i64,
+}
+
+impl FilteredChat {
+    /// Runs a message from Chat Message through `filter`. If it's rejected, returns what to tell
+    /// the sender instead, if anything.
+    pub fn new(
+        filter: &mut dyn ChatFilter,
+        sender: ClientID,
+        message: &str,
+        timestamp: i64,
+        salt: i64,
+    ) -> Result<Self, Option<TextComponent>> {
+        let (message, mask) = match filter.filter(sender, message) {
+            ChatVerdict::Pass => (message.to_string(), FilterMask::PassThrough),
+            ChatVerdict::Reject(reason) => return Err(reason),
+            ChatVerdict::Rewrite(message) => (message, FilterMask::PassThrough),
+            ChatVerdict::Censor(ranges) => (
+                message.to_string(),
+                FilterMask::from_ranges(message, &ranges),
+            ),
+        };
+        Ok(Self {
+            sender,
+            message,
+            mask,
+            timestamp,
+            salt,
+        })
+    }
+
+    /// The mask to send `recipient`, given whether it has text filtering on, or None if it
+    /// shouldn't get the message at all. Senders always see their own message as they wrote it.
+    pub fn mask_for(&self, recipient: ClientID, text_filtering: bool) -> Option<&FilterMask> {
+        const PASS_THROUGH: &FilterMask = &FilterMask::PassThrough;
+        if recipient == self.sender || !text_filtering {
+            return Some(PASS_THROUGH);
+        }
+        match self.mask {
+            FilterMask::FullyFiltered => None,
+            ref mask => Some(mask),
+        }
+    }
+
+    /// Player Chat for `recipient`, whose settings are `info`. `index` counts the sender's
+    /// messages, starting at 0.
+    pub fn packet<'a>(
+        &'a self,
+        recipient: ClientID,
+        info: Option<&ClientInfo>,
+        sender_uuid: Uuid,
+        sender_name: &'a TextComponent,
+        index: i32,
+    ) -> Option<OutPacket<'a>> {
+        let text_filtering = info.is_some_and(|i| i.enable_text_filtering);
+        let filter = self.mask_for(recipient, text_filtering)?;
+        Some(OutPacket::PlayerChat {
+            sender: sender_uuid,
+            index,
+            signature: None,
+            message: &self.message,
+            timestamp: self.timestamp,
+            salt: self.salt,
+            unsigned_content: None,
+            filter,
+            chat_type: 0,
+            sender_name,
+            target_name: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(enable_text_filtering: bool) -> ClientInfo {
+        ClientInfo {
+            locale: "en_us".into(),
+            view_distance: 10,
+            chat_mode: ChatMode::Enabled,
+            chat_colors: true,
+            displayed_skin_parts: SkinParts::NONE,
+            main_hand: MainHand::Right,
+            enable_text_filtering,
+            allow_server_listings: true,
+        }
+    }
+
+    #[test]
+    fn censors_for_filtering_players() {
+        let (alice, bob, carol) = (
+            ClientID::new(0, 0),
+            ClientID::new(1, 0),
+            ClientID::new(2, 0),
+        );
+        let mut filter = WordFilter::new(["darn"]);
+        let chat = FilteredChat::new(&mut filter, alice, "Darn it, ✨darn✨", 5, 6).unwrap();
+        assert_eq!(
+            chat.mask.apply(&chat.message).as_deref(),
+            Some("#### it, ✨####✨")
+        );
+
+        let name = TextComponent::text("alice");
+        let packet = |to, filtering| {
+            let p = chat.packet(to, Some(&info(filtering)), Uuid(1), &name, 0)?;
+            let OutPacket::PlayerChat { filter, .. } = p else {
+                unreachable!()
+            };
+            Some(filter.clone())
+        };
+        assert_eq!(packet(alice, true), Some(FilterMask::PassThrough));
+        assert_eq!(packet(bob, false), Some(FilterMask::PassThrough));
+        assert_eq!(packet(carol, true).as_ref(), Some(&chat.mask));
+        let bytes = encode_packet(
+            chat.packet(carol, Some(&info(true)), Uuid(1), &name, 0)
+                .unwrap(),
+            PROTOCOL_VERSION,
+        );
+        assert_eq!(bytes[0], 0x37);
+
+        let all = FilteredChat::new(&mut filter, alice, "DARN", 0, 0).unwrap();
+        assert_eq!(all.mask, FilterMask::FullyFiltered);
+        assert_eq!(all.mask.apply("DARN"), None);
+        assert!(all
+            .packet(carol, Some(&info(true)), Uuid(1), &name, 1)
+            .is_none());
+        assert!(all.packet(bob, None, Uuid(1), &name, 1).is_some());
+
+        filter.reject = true;
+        assert_eq!(
+            FilteredChat::new(&mut filter, alice, "darn", 0, 0),
+            Err(None)
+        );
+        let clean = FilteredChat::new(&mut filter, alice, "hello", 0, 0).unwrap();
+        assert_eq!(clean.mask, FilterMask::PassThrough);
+    }
+}
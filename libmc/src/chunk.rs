@@ -0,0 +1,611 @@
+use crate::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+/// Bits per block state once a section has too many different blocks for a palette. Enough for
+/// every block state in 1.20.2.
+const DIRECT_BLOCK_BITS: u8 = 15;
+/// Block state 0 is air, which doesn't count towards a section's block count
+const AIR: u32 = 0;
+
+/// What a paletted container holds, which decides how its values are packed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ContainerKind {
+    /// A section's 16×16×16 block states
+    Blocks,
+    /// A section's 4×4×4 biome cells. Holds how many bits a biome ID takes, which depends on the
+    /// biome registry.
+    Biomes { direct_bits: u8 },
+}
+
+impl ContainerKind {
+    fn len(self) -> usize {
+        match self {
+            Self::Blocks => 4096,
+            Self::Biomes { .. } => 64,
+        }
+    }
+
+    /// Bits per index into a palette: (fewest, most)
+    fn indirect_bits(self) -> (u8, u8) {
+        match self {
+            Self::Blocks => (4, 8),
+            Self::Biomes { .. } => (1, 3),
+        }
+    }
+
+    fn direct_bits(self) -> u8 {
+        match self {
+            Self::Blocks => DIRECT_BLOCK_BITS,
+            Self::Biomes { direct_bits } => direct_bits,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Palette {
+    /// Every value is the same, and there's no data
+    Single(u32),
+    /// The data holds indices into this
+    Indirect(Vec<u32>),
+    /// The data holds the values themselves
+    Direct,
+}
+
+/// Values stored the way the protocol sends them: as one value, as indices into a palette, or as
+/// the values themselves once there are too many different ones. A section of one block only
+/// takes a few bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PalettedContainer {
+    kind: ContainerKind,
+    palette: Palette,
+    /// None for `Palette::Single`
+    data: Option<PackedIntArray>,
+}
+
+impl PalettedContainer {
+    fn new(kind: ContainerKind, value: u32) -> Self {
+        Self {
+            kind,
+            palette: Palette::Single(value),
+            data: None,
+        }
+    }
+
+    fn get(&self, i: usize) -> u32 {
+        match (&self.palette, &self.data) {
+            (Palette::Single(value), _) => *value,
+            (Palette::Indirect(palette), Some(data)) => palette[data.get(i) as usize],
+            (Palette::Direct, Some(data)) => data.get(i),
+            _ => unreachable!("paletted container without data"),
+        }
+    }
+
+    /// Returns the old value
+    fn set(&mut self, i: usize, value: u32) -> u32 {
+        let old = self.get(i);
+        if old == value {
+            return old;
+        }
+        let index = match &mut self.palette {
+            Palette::Single(single) => {
+                let (min_bits, _) = self.kind.indirect_bits();
+                self.palette = Palette::Indirect(vec![*single, value]);
+                self.data = Some(PackedIntArray::new(min_bits, self.kind.len()));
+                1
+            }
+            Palette::Indirect(palette) => match palette.iter().position(|v| *v == value) {
+                Some(index) => index as u32,
+                None => {
+                    palette.push(value);
+                    let index = palette.len() as u32 - 1;
+                    self.grow();
+                    if self.palette == Palette::Direct {
+                        value
+                    } else {
+                        index
+                    }
+                }
+            },
+            Palette::Direct => value,
+        };
+        self.data.as_mut().unwrap().set(i, index);
+        old
+    }
+
+    /// Widens the data after the palette grew, if the indices don't fit anymore
+    fn grow(&mut self) {
+        let (Palette::Indirect(palette), Some(data)) = (&self.palette, &self.data) else {
+            return;
+        };
+        let needed = PackedIntArray::bits_for(palette.len() as u32 - 1);
+        if needed <= data.bits() {
+            return;
+        }
+        let (_, max_bits) = self.kind.indirect_bits();
+        let (palette, bits) = if needed > max_bits {
+            (Palette::Direct, self.kind.direct_bits())
+        } else {
+            (self.palette.clone(), needed)
+        };
+        let values: Vec<u32> = match &palette {
+            Palette::Direct => data.iter().map(|i| self.get_index(i)).collect(),
+            _ => data.iter().collect(),
+        };
+        self.data = Some(PackedIntArray::pack(bits, &values));
+        self.palette = palette;
+    }
+
+    fn get_index(&self, index: u32) -> u32 {
+        match &self.palette {
+            Palette::Indirect(palette) => palette[index as usize],
+            _ => index,
+        }
+    }
+
+    /// The paletted container as the protocol sends it
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match (&self.palette, &self.data) {
+            (Palette::Single(value), _) => {
+                write_ubyte(buf, 0);
+                write_varint(buf, *value as i32);
+                write_varint(buf, 0);
+            }
+            (Palette::Indirect(palette), Some(data)) => {
+                write_ubyte(buf, data.bits());
+                write_varint(buf, palette.len() as i32);
+                for v in palette {
+                    write_varint(buf, *v as i32);
+                }
+                buf.extend(data.encode());
+            }
+            (Palette::Direct, Some(data)) => {
+                write_ubyte(buf, data.bits());
+                buf.extend(data.encode());
+            }
+            _ => unreachable!("paletted container without data"),
+        }
+    }
+}
+
+/// A 16×16×16 section of a `Chunk`: its block states and 4×4×4 biome cells, by protocol ID
+#[derive(Debug, Clone)]
+pub struct ChunkSection {
+    /// Blocks that aren't air
+    block_count: u16,
+    blocks: PalettedContainer,
+    biomes: PalettedContainer,
+    /// The section as it goes in a chunk packet, once something has asked for it
+    encoded: OnceLock<Box<[i8]>>,
+}
+
+impl ChunkSection {
+    /// A section of nothing but `block`, in `biome`. `biome_count` is the size of the biome
+    /// registry.
+    pub fn new(block: u32, biome: u32, biome_count: usize) -> Self {
+        let direct_bits = PackedIntArray::bits_for(biome_count.saturating_sub(1) as u32);
+        Self {
+            block_count: if block == AIR { 0 } else { 4096 },
+            blocks: PalettedContainer::new(ContainerKind::Blocks, block),
+            biomes: PalettedContainer::new(ContainerKind::Biomes { direct_bits }, biome),
+            encoded: OnceLock::new(),
+        }
+    }
+
+    /// The block state at `x`, `y`, `z` within the section (each 0-15)
+    pub fn block(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.blocks.get(block_index(x, y, z))
+    }
+
+    /// Returns the old state
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, state: u32) -> u32 {
+        let old = self.blocks.set(block_index(x, y, z), state);
+        if old != state {
+            match (old == AIR, state == AIR) {
+                (true, false) => self.block_count += 1,
+                (false, true) => self.block_count -= 1,
+                _ => {}
+            }
+            self.encoded = OnceLock::new();
+        }
+        old
+    }
+
+    /// The biome of the 4×4×4 cell at `x`, `y`, `z` (each 0-3)
+    pub fn biome(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.biomes.get((y * 4 + z) * 4 + x)
+    }
+
+    pub fn set_biome(&mut self, x: usize, y: usize, z: usize, biome: u32) {
+        if self.biomes.set((y * 4 + z) * 4 + x, biome) != biome {
+            self.encoded = OnceLock::new();
+        }
+    }
+
+    /// How many blocks aren't air
+    pub fn block_count(&self) -> u16 {
+        self.block_count
+    }
+
+    /// The section as it goes in `ChunkDataAndUpdateLight`'s data. Encoded once, until the
+    /// section changes.
+    pub fn encoded(&self) -> &[i8] {
+        self.encoded.get_or_init(|| {
+            let mut buf = Vec::new();
+            write_short(&mut buf, self.block_count as i16);
+            self.blocks.encode(&mut buf);
+            self.biomes.encode(&mut buf);
+            buf.into_iter().map(|b| b as i8).collect()
+        })
+    }
+}
+
+fn block_index(x: usize, y: usize, z: usize) -> usize {
+    assert!(
+        x < 16 && y < 16 && z < 16,
+        "({x}, {y}, {z}) is outside the section"
+    );
+    (y * 16 + z) * 16 + x
+}
+
+/// A column of sections, from the bottom of the world to the top.
+///
+/// Sections are shared until they're changed, so a chunk that's mostly air only stores the
+/// sections with something in them, and copying a chunk is cheap.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    min_y: i32,
+    sections: Vec<Arc<ChunkSection>>,
+    /// Shared by every empty section in the chunk
+    empty: Arc<ChunkSection>,
+    /// Sections changed since the last `take_dirty()`
+    dirty: BitSet,
+}
+
+impl Chunk {
+    /// A chunk of air, `height` blocks tall from `min_y` (both multiples of 16), using `empty`
+    /// for each section. Sharing one `empty` among many chunks is what `ChunkStore` does.
+    pub fn new(min_y: i32, height: u32, empty: Arc<ChunkSection>) -> Self {
+        assert!(
+            min_y % 16 == 0 && height.is_multiple_of(16),
+            "chunks are whole sections"
+        );
+        let sections = height as usize / 16;
+        Self {
+            min_y,
+            sections: vec![Arc::clone(&empty); sections],
+            empty,
+            dirty: BitSet::with_num_bits(sections),
+        }
+    }
+
+    /// The bottom of the chunk's lowest section
+    pub fn min_y(&self) -> i32 {
+        self.min_y
+    }
+
+    pub fn sections(&self) -> &[Arc<ChunkSection>] {
+        &self.sections
+    }
+
+    /// The section with its bottom at `min_y + 16 * i`, to change. Copies it first if it's
+    /// shared, and marks it dirty.
+    pub fn section_mut(&mut self, i: usize) -> &mut ChunkSection {
+        self.dirty.set(i);
+        Arc::make_mut(&mut self.sections[i])
+    }
+
+    /// How many sections aren't shared with anything, which is roughly how many take up memory
+    pub fn unique_sections(&self) -> usize {
+        self.sections
+            .iter()
+            .filter(|s| Arc::strong_count(s) == 1)
+            .count()
+    }
+
+    /// Which section `y` is in, if it's in the world
+    fn section_index(&self, y: i32) -> Option<usize> {
+        let i = usize::try_from((y - self.min_y).div_euclid(16)).ok()?;
+        (i < self.sections.len()).then_some(i)
+    }
+
+    /// The block state at `x`, `y`, `z` in the chunk (`x` and `z` 0-15), or air outside the world
+    pub fn block(&self, x: usize, y: i32, z: usize) -> u32 {
+        match self.section_index(y) {
+            Some(i) => self.sections[i].block(x, (y - self.min_y) as usize % 16, z),
+            None => AIR,
+        }
+    }
+
+    /// Returns the old state, or None if `y` is outside the world
+    pub fn set_block(&mut self, x: usize, y: i32, z: usize, state: u32) -> Option<u32> {
+        let i = self.section_index(y)?;
+        let local_y = (y - self.min_y) as usize % 16;
+        if self.sections[i].block(x, local_y, z) == state {
+            return Some(state);
+        }
+        let empty = Arc::clone(&self.empty);
+        let section = self.section_mut(i);
+        let old = section.set_block(x, local_y, z, state);
+        // go back to sharing once it's all air again
+        if section.block_count() == 0 && section.biomes == empty.biomes {
+            self.sections[i] = empty;
+        }
+        Some(old)
+    }
+
+    /// Which sections changed since the last call, e.g. to know which chunk packets to invalidate
+    /// or which sections to send block updates for. Clears the flags.
+    pub fn take_dirty(&mut self) -> Vec<usize> {
+        let dirty = (0..self.sections.len())
+            .filter(|i| self.dirty.get(*i))
+            .collect();
+        self.dirty = BitSet::with_num_bits(self.sections.len());
+        dirty
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// The data of the chunk's `ChunkDataAndUpdateLight`, using each section's cached encoding
+    pub fn encode_sections(&self) -> Vec<i8> {
+        let mut data = Vec::new();
+        for section in &self.sections {
+            data.extend_from_slice(section.encoded());
+        }
+        data
+    }
+
+    /// Everything needed to send the chunk at `pos`, which can be sent on to
+    /// `ServerHandle::send_chunk_async()`. The chunk is fully lit by the sky.
+    pub fn to_packet(&self, pos: ChunkPos) -> ChunkPacket {
+        let mut heights = [0; 256];
+        for (i, height) in heights.iter_mut().enumerate() {
+            let (x, z) = (i % 16, i / 16);
+            *height = (0..self.sections.len() as u32 * 16)
+                .rev()
+                .find(|y| self.block(x, self.min_y + *y as i32, z) != AIR)
+                .map_or(0, |y| y + 1);
+        }
+        // light sections go one past the world at each end
+        let light_sections = self.sections.len() + 2;
+        let mut lit = BitSet::with_num_bits(light_sections);
+        for i in 0..light_sections {
+            lit.set(i);
+        }
+        ChunkPacket {
+            pos,
+            heights: PackedIntArray::pack(
+                PackedIntArray::bits_for(self.sections.len() as u32 * 16),
+                &heights,
+            ),
+            data: self.encode_sections(),
+            sky_light_mask: lit,
+            sky_light: vec![[-1; 2048]; light_sections],
+        }
+    }
+}
+
+/// A `Chunk`'s packet, made by `Chunk::to_packet()`
+#[derive(Debug, Clone)]
+pub struct ChunkPacket {
+    pub pos: ChunkPos,
+    /// The `MOTION_BLOCKING` heightmap
+    heights: PackedIntArray,
+    data: Vec<i8>,
+    sky_light_mask: BitSet,
+    sky_light: Vec<[i8; 2048]>,
+}
+
+impl ChunkPacketSource for ChunkPacket {
+    fn packet(&self) -> OutPacket<'_> {
+        let none = BitSet::with_num_bits(self.sky_light.len());
+        let mut heightmaps = CompoundNbt::new("");
+        heightmaps.set(
+            "MOTION_BLOCKING",
+            Nbt::LongArray(Cow::Borrowed(self.heights.longs())),
+        );
+        OutPacket::ChunkDataAndUpdateLight {
+            chunk_x: self.pos.x,
+            chunk_z: self.pos.z,
+            heightmaps,
+            data: &self.data,
+            block_entities: &[],
+            sky_light_mask: self.sky_light_mask.clone(),
+            block_light_mask: none.clone(),
+            empty_sky_light_mask: none.clone(),
+            empty_block_light_mask: none,
+            sky_light_arrays: &self.sky_light,
+            block_light_arrays: &[],
+        }
+    }
+}
+
+/// The loaded chunks of a world, all the same height.
+///
+/// Every chunk starts out sharing one empty section, and only copies the ones that get blocks
+/// put in them, so large view distances over mostly-empty worlds stay cheap.
+#[derive(Debug)]
+pub struct ChunkStore {
+    min_y: i32,
+    height: u32,
+    empty: Arc<ChunkSection>,
+    chunks: HashMap<ChunkPos, Chunk>,
+}
+
+impl ChunkStore {
+    /// Chunks `height` blocks tall from `min_y`, whose empty sections are air in `biome`.
+    /// `biome_count` is the size of the biome registry.
+    pub fn new(min_y: i32, height: u32, biome: u32, biome_count: usize) -> Self {
+        Self {
+            min_y,
+            height,
+            empty: Arc::new(ChunkSection::new(AIR, biome, biome_count)),
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// The chunk at `pos`, loading it as air if it isn't loaded
+    pub fn load(&mut self, pos: ChunkPos) -> &mut Chunk {
+        self.chunks
+            .entry(pos)
+            .or_insert_with(|| Chunk::new(self.min_y, self.height, Arc::clone(&self.empty)))
+    }
+
+    pub fn get(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn get_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&pos)
+    }
+
+    pub fn unload(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        self.chunks.remove(&pos)
+    }
+
+    /// An empty store of the same height and biome, sharing its empty section
+    fn empty_like(&self) -> Self {
+        Self {
+            min_y: self.min_y,
+            height: self.height,
+            empty: Arc::clone(&self.empty),
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// Moves the chunks at `positions` (the loaded ones) into a new store
+    pub fn split_off(&mut self, positions: impl IntoIterator<Item = ChunkPos>) -> Self {
+        let mut split = self.empty_like();
+        for pos in positions {
+            if let Some(chunk) = self.chunks.remove(&pos) {
+                split.chunks.insert(pos, chunk);
+            }
+        }
+        split
+    }
+
+    /// A new store with copies of the chunks at `positions` (the loaded ones). The copies share
+    /// their sections with these chunks until either is changed.
+    pub fn copy_of(&self, positions: impl IntoIterator<Item = ChunkPos>) -> Self {
+        let mut copy = self.empty_like();
+        for pos in positions {
+            if let Some(chunk) = self.chunks.get(&pos) {
+                copy.chunks.insert(pos, chunk.clone());
+            }
+        }
+        copy
+    }
+
+    /// Moves every chunk in `other` into this store, replacing any already at the same position
+    pub fn append(&mut self, other: Self) {
+        self.chunks.extend(other.chunks);
+    }
+
+    /// Where every loaded chunk is, in no particular order
+    pub fn positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn is_loaded(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// The block state at `pos`, or None if its chunk isn't loaded
+    pub fn block(&self, pos: &Position) -> Option<u32> {
+        let chunk = self.chunks.get(&ChunkPos::new(pos.x >> 4, pos.z >> 4))?;
+        Some(chunk.block((pos.x & 15) as usize, pos.y.into(), (pos.z & 15) as usize))
+    }
+
+    /// Returns the old state, or None if `pos`'s chunk isn't loaded or it's outside the world
+    pub fn set_block(&mut self, pos: &Position, state: u32) -> Option<u32> {
+        let chunk = self
+            .chunks
+            .get_mut(&ChunkPos::new(pos.x >> 4, pos.z >> 4))?;
+        chunk.set_block(
+            (pos.x & 15) as usize,
+            pos.y.into(),
+            (pos.z & 15) as usize,
+            state,
+        )
+    }
+
+    /// The chunks changed since the last call, clearing their dirty flags. Their cached packets
+    /// are stale: pass each to `ServerHandle::invalidate_chunk()`.
+    pub fn take_dirty(&mut self) -> Vec<ChunkPos> {
+        self.chunks
+            .iter_mut()
+            .filter(|(_, chunk)| chunk.is_dirty())
+            .map(|(pos, chunk)| {
+                chunk.take_dirty();
+                *pos
+            })
+            .collect()
+    }
+
+    /// How many sections are stored besides the shared empty one
+    pub fn unique_sections(&self) -> usize {
+        self.chunks.values().map(Chunk::unique_sections).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_and_palettes_sections() {
+        let mut store = ChunkStore::new(-64, 384, 0, 64);
+        for x in -8..8 {
+            for z in -8..8 {
+                store.load(ChunkPos::new(x, z));
+            }
+        }
+        assert_eq!(store.len(), 256);
+        assert_eq!(store.unique_sections(), 0);
+        let empty = store.get(ChunkPos::new(0, 0)).unwrap().encode_sections();
+        // block count, then single-valued blocks and biomes: 8 bytes a section
+        assert_eq!(empty.len(), 24 * 8);
+
+        let pos = Position {
+            x: 17,
+            y: -60,
+            z: -3,
+        };
+        assert_eq!(store.set_block(&pos, 9), Some(0));
+        assert_eq!(store.block(&pos), Some(9));
+        assert_eq!(store.unique_sections(), 1);
+        assert_eq!(store.take_dirty(), [ChunkPos::new(1, -1)]);
+        assert!(store.take_dirty().is_empty());
+
+        // more states than a palette holds go direct, and it's shared again once it's all air
+        let chunk = store.get_mut(ChunkPos::new(0, 0)).unwrap();
+        for i in 0..300 {
+            chunk.set_block(i % 16, 100 + (i / 256) as i32, i / 16 % 16, 1000 + i as u32);
+        }
+        assert_eq!(chunk.block(5, 100, 3), 1053);
+        assert_eq!(chunk.sections()[10].block_count(), 300);
+        assert_eq!(chunk.sections()[10].encoded()[2], DIRECT_BLOCK_BITS as i8);
+        for i in 0..300 {
+            chunk.set_block(i % 16, 100 + (i / 256) as i32, i / 16 % 16, AIR);
+        }
+        assert_eq!(chunk.unique_sections(), 0);
+        assert_eq!(chunk.take_dirty(), [10]);
+
+        let chunk = store.get_mut(ChunkPos::new(1, -1)).unwrap();
+        chunk.set_block(1, -60, 13, AIR);
+        assert_eq!(chunk.unique_sections(), 0);
+        let packet = chunk.to_packet(ChunkPos::new(1, -1));
+        encode_packet(packet.packet(), PROTOCOL_VERSION);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A packet encoded for a protocol version
+type Encoded = (i32, Rc<[u8]>);
+
+/// Encoded `ChunkDataAndUpdateLight` packets, so that a chunk viewed by many players is only
+/// serialized once (per protocol version) instead of once per player.
+///
+/// Nothing here knows when a chunk changes: call `invalidate()` after changing its blocks.
+#[derive(Debug, Default)]
+pub struct ChunkCache {
+    /// Each chunk's packet for each protocol version (nearly always just one)
+    packets: HashMap<ChunkPos, Vec<Encoded>>,
+}
+
+impl ChunkCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The packet for `chunk`, encoded as `encode_packet()` does, calling `packet` to make it if
+    /// it isn't cached
+    pub fn get_or_encode<'a>(
+        &mut self,
+        chunk: ChunkPos,
+        protocol_version: i32,
+        packet: impl FnOnce() -> OutPacket<'a>,
+    ) -> Rc<[u8]> {
+        if let Some(buf) = self.get(chunk, protocol_version) {
+            return buf;
+        }
+        let mut encoded = PooledBuf::take();
+        encode_packet_into(packet(), protocol_version, &mut encoded);
+        let buf: Rc<[u8]> = encoded[..].into();
+        self.insert(chunk, protocol_version, buf.clone());
+        buf
+    }
+
+    pub fn get(&self, chunk: ChunkPos, protocol_version: i32) -> Option<Rc<[u8]>> {
+        let versions = self.packets.get(&chunk)?;
+        let (_, buf) = versions.iter().find(|(v, _)| *v == protocol_version)?;
+        Some(buf.clone())
+    }
+
+    /// Caches a packet encoded elsewhere, e.g. by a `ChunkEncodePool`
+    pub fn insert(&mut self, chunk: ChunkPos, protocol_version: i32, packet: Rc<[u8]>) {
+        let versions = self.packets.entry(chunk).or_default();
+        versions.retain(|(v, _)| *v != protocol_version);
+        versions.push((protocol_version, packet));
+    }
+
+    /// Forgets `chunk`'s packet, e.g. because a block in it changed
+    pub fn invalidate(&mut self, chunk: ChunkPos) {
+        self.packets.remove(&chunk);
+    }
+
+    pub fn clear(&mut self) {
+        self.packets.clear();
+    }
+
+    pub fn contains(&self, chunk: ChunkPos) -> bool {
+        self.packets.contains_key(&chunk)
+    }
+
+    /// How many chunks are cached
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_once() {
+        let mut cache = ChunkCache::new();
+        let mut encoded = 0;
+        let mut get = |cache: &mut ChunkCache, version| {
+            cache.get_or_encode(ChunkPos::new(1, 2), version, || {
+                encoded += 1;
+                OutPacket::FinishConfig
+            })
+        };
+        let a = get(&mut cache, PROTOCOL_VERSION);
+        let b = get(&mut cache, PROTOCOL_VERSION);
+        assert!(Rc::ptr_eq(&a, &b));
+        get(&mut cache, 763);
+        assert_eq!(cache.len(), 1);
+
+        cache.invalidate(ChunkPos::new(1, 2));
+        assert!(!cache.contains(ChunkPos::new(1, 2)));
+        get(&mut cache, PROTOCOL_VERSION);
+        assert_eq!(encoded, 3);
+    }
+}
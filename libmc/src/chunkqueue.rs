@@ -0,0 +1,270 @@
+use crate::*;
+use std::collections::HashSet;
+
+/// Chunks directly behind the player are sent as if they were this many times further away
+const BEHIND_PENALTY: f64 = 1.0;
+
+/// Turning less than this (in degrees) doesn't reorder the queue
+const RESORT_YAW_THRESHOLD: f32 = 15.0;
+
+/// Chunks per tick until the client says how many it wants (vanilla's numbers, as are the ones below)
+const START_CHUNKS_PER_TICK: f32 = 9.0;
+const MIN_CHUNKS_PER_TICK: f32 = 0.01;
+const MAX_CHUNKS_PER_TICK: f32 = 64.0;
+
+/// Batches that may be in flight at once, once the client answered the first one
+const MAX_UNACKNOWLEDGED_BATCHES: u32 = 10;
+
+/// Decides which chunk to send a player next: closest first, preferring the ones in front of them.
+///
+/// Update it as the player moves and turns, and call `next_chunk()` whenever there's bandwidth
+/// to send another chunk. This only reorders chunks, so it needs nothing from the client;
+/// `ChunkBatchPacer` decides how many to send.
+#[derive(Debug)]
+pub struct ChunkSendQueue {
+    view_distance: i32,
+    center: ChunkPos,
+    yaw: f32,
+    /// The yaw `pending` was sorted for
+    sorted_yaw: f32,
+    sent: HashSet<ChunkPos>,
+    /// Unsent chunks in view, best last
+    pending: Vec<ChunkPos>,
+}
+
+impl ChunkSendQueue {
+    pub fn new(view_distance: i32, center: ChunkPos, yaw: f32) -> Self {
+        let mut q = Self {
+            view_distance,
+            center,
+            yaw,
+            sorted_yaw: yaw,
+            sent: HashSet::new(),
+            pending: Vec::new(),
+        };
+        q.refill();
+        q
+    }
+
+    pub fn set_yaw(&mut self, yaw: f32) {
+        self.yaw = yaw;
+        let turned = (yaw - self.sorted_yaw).rem_euclid(360.0);
+        if turned.min(360.0 - turned) >= RESORT_YAW_THRESHOLD {
+            self.sort();
+        }
+    }
+
+    /// Re-centers the view on the player's new chunk.
+    /// Returns the already-sent chunks that are now out of view and should be unloaded.
+    pub fn move_to(&mut self, center: ChunkPos) -> Vec<ChunkPos> {
+        if self.center == center {
+            return Vec::new();
+        }
+        self.center = center;
+        self.update_view()
+    }
+
+    /// Returns the already-sent chunks that are now out of view and should be unloaded
+    pub fn set_view_distance(&mut self, view_distance: i32) -> Vec<ChunkPos> {
+        self.view_distance = view_distance;
+        self.update_view()
+    }
+
+    fn update_view(&mut self) -> Vec<ChunkPos> {
+        let out_of_view: Vec<_> = self
+            .sent
+            .iter()
+            .copied()
+            .filter(|c| !self.in_view(*c))
+            .collect();
+        for c in &out_of_view {
+            self.sent.remove(c);
+        }
+        self.refill();
+        out_of_view
+    }
+
+    /// The chunk to send next (which is then considered sent), or None once all chunks in view are sent
+    pub fn next_chunk(&mut self) -> Option<ChunkPos> {
+        let chunk = self.pending.pop()?;
+        self.sent.insert(chunk);
+        Some(chunk)
+    }
+
+    /// Makes a sent chunk get sent again (e.g. because the client never received it)
+    pub fn resend(&mut self, chunk: ChunkPos) {
+        if self.sent.remove(&chunk) {
+            self.pending.push(chunk);
+            self.sort();
+        }
+    }
+
+    pub fn is_sent(&self, chunk: ChunkPos) -> bool {
+        self.sent.contains(&chunk)
+    }
+
+    /// How many chunks in view are still unsent
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn in_view(&self, chunk: ChunkPos) -> bool {
+        chunk.chebyshev_distance(self.center) <= self.view_distance.unsigned_abs()
+    }
+
+    fn refill(&mut self) {
+        self.pending = self
+            .center
+            .square(self.view_distance)
+            .filter(|c| !self.sent.contains(c))
+            .collect();
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.sorted_yaw = self.yaw;
+        let [look_x, _, look_z] = look_vector(self.yaw, 0.0);
+        let center = self.center;
+        let priority = |c: &ChunkPos| {
+            let (dx, dz) = (f64::from(c.x - center.x), f64::from(c.z - center.z));
+            let dist = dx.hypot(dz);
+            // the chunks right around the player are needed no matter where they look
+            if dist < 1.5 {
+                return dist;
+            }
+            // 0 straight ahead, 1 straight behind
+            let behind = (1.0 - (dx * look_x + dz * look_z) / dist) / 2.0;
+            dist * (1.0 + behind * BEHIND_PENALTY)
+        };
+        self.pending
+            .sort_by(|a, b| priority(b).total_cmp(&priority(a)));
+    }
+}
+
+/// Paces chunk sending by how fast the client says it can handle chunks, like vanilla does.
+///
+/// Each tick, `next_batch()` takes the chunks to send from a `ChunkSendQueue`. They have to be sent
+/// between a `ChunkBatchStart` and a `ChunkBatchFinished` with their count, which the client
+/// answers with Chunk Batch Received; pass that to `handle()`.
+#[derive(Debug)]
+pub struct ChunkBatchPacer {
+    chunks_per_tick: f32,
+    /// Chunks that may be sent, carried over between ticks
+    quota: f32,
+    unacknowledged: u32,
+    max_unacknowledged: u32,
+}
+
+impl Default for ChunkBatchPacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkBatchPacer {
+    pub fn new() -> Self {
+        Self {
+            chunks_per_tick: START_CHUNKS_PER_TICK,
+            quota: 0.0,
+            unacknowledged: 0,
+            // until the client has answered once
+            max_unacknowledged: 1,
+        }
+    }
+
+    /// The chunks to send this tick, as one batch. Empty if nothing should be sent,
+    /// in which case no batch packets are sent either.
+    pub fn next_batch(&mut self, queue: &mut ChunkSendQueue) -> Vec<ChunkPos> {
+        if self.unacknowledged >= self.max_unacknowledged || queue.pending_len() == 0 {
+            return Vec::new();
+        }
+        self.quota = (self.quota + self.chunks_per_tick).min(self.chunks_per_tick.max(1.0));
+        if self.quota < 1.0 {
+            return Vec::new();
+        }
+
+        let batch: Vec<_> = std::iter::from_fn(|| queue.next_chunk())
+            .take(self.quota as usize)
+            .collect();
+        self.quota -= batch.len() as f32;
+        self.unacknowledged += 1;
+        batch
+    }
+
+    /// Handles a packet from the client. Returns whether it was a Chunk Batch Received.
+    pub fn handle(&mut self, packet: &InPacket) -> bool {
+        let &InPacket::ChunkBatchReceived { chunks_per_tick } = packet else {
+            return false;
+        };
+        self.unacknowledged = self.unacknowledged.saturating_sub(1);
+        self.chunks_per_tick = if chunks_per_tick.is_nan() {
+            MIN_CHUNKS_PER_TICK
+        } else {
+            chunks_per_tick.clamp(MIN_CHUNKS_PER_TICK, MAX_CHUNKS_PER_TICK)
+        };
+        if self.unacknowledged == 0 {
+            self.quota = 1.0;
+        }
+        self.max_unacknowledged = MAX_UNACKNOWLEDGED_BATCHES;
+        true
+    }
+
+    /// How many chunks per tick are being sent
+    pub fn chunks_per_tick(&self) -> f32 {
+        self.chunks_per_tick
+    }
+
+    /// Batches sent that the client hasn't answered yet
+    pub fn unacknowledged(&self) -> u32 {
+        self.unacknowledged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn front_first() {
+        // facing +Z
+        let mut q = ChunkSendQueue::new(4, ChunkPos::new(0, 0), 0.0);
+        assert_eq!(q.pending_len(), 81);
+        assert_eq!(q.next_chunk(), Some(ChunkPos::new(0, 0)));
+        for _ in 0..8 {
+            let chunk = q.next_chunk().unwrap();
+            assert!(chunk.z.abs() <= 1);
+        }
+        assert_eq!(q.next_chunk(), Some(ChunkPos::new(0, 2)));
+
+        // turn around to face -Z
+        q.set_yaw(180.0);
+        assert_eq!(q.next_chunk(), Some(ChunkPos::new(0, -2)));
+
+        let unload = q.move_to(ChunkPos::new(10, 0));
+        assert_eq!(unload.len(), 11);
+        assert!(!q.is_sent(ChunkPos::new(0, 0)));
+        assert_eq!(q.next_chunk(), Some(ChunkPos::new(10, 0)));
+    }
+
+    #[test]
+    fn batch_pacing() {
+        let mut q = ChunkSendQueue::new(4, ChunkPos::new(0, 0), 0.0);
+        let mut pacer = ChunkBatchPacer::new();
+        assert_eq!(pacer.next_batch(&mut q).len(), 9);
+        // waits for the first batch to be answered
+        assert!(pacer.next_batch(&mut q).is_empty());
+
+        assert!(pacer.handle(&InPacket::ChunkBatchReceived {
+            chunks_per_tick: 2.5
+        }));
+        assert_eq!(pacer.next_batch(&mut q).len(), 2);
+        assert_eq!(pacer.next_batch(&mut q).len(), 2);
+        assert_eq!(pacer.unacknowledged(), 2);
+
+        pacer.handle(&InPacket::ChunkBatchReceived {
+            chunks_per_tick: f32::NAN,
+        });
+        assert_eq!(pacer.chunks_per_tick(), MIN_CHUNKS_PER_TICK);
+        assert_eq!(pacer.next_batch(&mut q).len(), 0);
+    }
+}
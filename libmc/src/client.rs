@@ -0,0 +1,363 @@
+//! A headless client: connects to a server (libmc or vanilla, in offline mode), logs in, and
+//! exchanges play packets, for end-to-end tests and tools like load testers.
+
+use crate::*;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    /// The connection closed, or a frame couldn't be read
+    Closed(DisconnectCause),
+    /// The server disconnected us, with this reason (as JSON)
+    Disconnected(String),
+    /// A packet we need couldn't be decoded
+    Decode(DecodeError),
+    /// The server wants something the client can't do, e.g. encryption
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "I/O error: {e}"),
+            ClientError::Closed(cause) => write!(f, "connection closed: {cause:?}"),
+            ClientError::Disconnected(reason) => write!(f, "disconnected: {reason}"),
+            ClientError::Decode(e) => write!(f, "bad packet: {e}"),
+            ClientError::Unsupported(what) => write!(f, "server requires {what}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<DecodeError> for ClientError {
+    fn from(e: DecodeError) -> Self {
+        ClientError::Decode(e)
+    }
+}
+
+/// A packet from the server. libmc only decodes serverbound packets, so its body is left for
+/// `reader()`.
+#[derive(Debug, Clone)]
+pub struct ClientboundPacket {
+    /// The state the packet was received in
+    pub state: ProtocolState,
+    pub id: i32,
+    /// The packet's body, after the ID
+    pub data: Vec<u8>,
+}
+
+impl ClientboundPacket {
+    pub fn reader(&self) -> PacketReader<'_> {
+        PacketReader::new(&self.data)
+    }
+}
+
+/// A client connection, speaking `PROTOCOL_VERSION`.
+///
+/// Keep Alives, Pings, Chunk Batches and teleports are answered automatically as they're
+/// received, so a client that keeps calling `recv()` stays connected.
+#[derive(Debug)]
+pub struct Client {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    state: ProtocolState,
+    username: String,
+    uuid: Uuid,
+}
+
+impl Client {
+    /// Connects and logs in as `username` with its offline UUID, returning once the client is in
+    /// the Play state. Packets received before that are answered as needed, then dropped.
+    pub fn connect(addr: impl ToSocketAddrs, username: &str) -> Result<Self, ClientError> {
+        let mut client = Self::open(addr, HandshakeNextState::Login, username)?;
+        let mut start = Vec::new();
+        write_string(&mut start, username);
+        write_uuid(&mut start, client.uuid);
+        client.send(0x00, &start)?;
+        while client.state != ProtocolState::Play {
+            client.recv()?;
+        }
+        Ok(client)
+    }
+
+    /// The server list status JSON of the server at `addr`
+    pub fn status(addr: impl ToSocketAddrs) -> Result<String, ClientError> {
+        let mut client = Self::open(addr, HandshakeNextState::Status, "")?;
+        client.send(0x00, &[])?;
+        let response = client.recv()?;
+        Ok(response.reader().str()?.to_owned())
+    }
+
+    fn open(
+        addr: impl ToSocketAddrs,
+        next_state: HandshakeNextState,
+        username: &str,
+    ) -> Result<Self, ClientError> {
+        let stream = TcpStream::connect(addr)?;
+        let _ = stream.set_nodelay(true);
+        let peer = stream.peer_addr()?;
+        let mut client = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+            state: ProtocolState::Handshaking,
+            username: username.to_owned(),
+            uuid: Uuid::offline(username),
+        };
+
+        let mut handshake = Vec::new();
+        write_varint(&mut handshake, PROTOCOL_VERSION);
+        write_string(&mut handshake, &peer.ip().to_string());
+        write_ushort(&mut handshake, peer.port());
+        write_varint(
+            &mut handshake,
+            match next_state {
+                HandshakeNextState::Status => 1,
+                HandshakeNextState::Login => 2,
+            },
+        );
+        client.send(0x00, &handshake)?;
+        client.state = match next_state {
+            HandshakeNextState::Status => ProtocolState::Status,
+            HandshakeNextState::Login => ProtocolState::Login,
+        };
+        Ok(client)
+    }
+
+    pub fn state(&self) -> ProtocolState {
+        self.state
+    }
+
+    /// Our username, as the server confirmed it in Login Success
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// Our UUID, as the server confirmed it in Login Success
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    /// Sends the serverbound packet `id` with `body`, which is laid out by the caller
+    pub fn send(&mut self, id: i32, body: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(body.len() + 1);
+        write_varint(&mut packet, id);
+        packet.extend_from_slice(body);
+        self.stream.write_all(&frame_packet(&packet))
+    }
+
+    /// Sends an unsigned chat message
+    pub fn chat(&mut self, message: &str) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut body = Vec::new();
+        write_string(&mut body, message);
+        write_long(&mut body, timestamp as i64);
+        // salt, no signature, no acknowledged messages
+        write_long(&mut body, 0);
+        write_bool(&mut body, false);
+        write_varint(&mut body, 0);
+        body.extend_from_slice(&[0; 3]);
+        self.send(0x05, &body)
+    }
+
+    /// Sends a chat command, without the leading '/'
+    pub fn command(&mut self, command: &str) -> io::Result<()> {
+        let mut body = Vec::new();
+        write_string(&mut body, command);
+        write_long(&mut body, 0);
+        write_long(&mut body, 0);
+        // no argument signatures or acknowledged messages
+        write_varint(&mut body, 0);
+        write_varint(&mut body, 0);
+        body.extend_from_slice(&[0; 3]);
+        self.send(0x04, &body)
+    }
+
+    /// Sends Set Player Position
+    pub fn set_position(&mut self, x: f64, y: f64, z: f64, on_ground: bool) -> io::Result<()> {
+        let mut body = Vec::new();
+        write_double(&mut body, x);
+        write_double(&mut body, y);
+        write_double(&mut body, z);
+        write_bool(&mut body, on_ground);
+        self.send(0x16, &body)
+    }
+
+    /// Waits for the next packet from the server, answering it first if it needs an answer.
+    /// Being disconnected is an error.
+    pub fn recv(&mut self) -> Result<ClientboundPacket, ClientError> {
+        let frame = read_frame(&mut self.reader).map_err(ClientError::Closed)?;
+        let mut r = PacketReader::new(&frame);
+        r.varint()?;
+        let id = r.varint()?;
+        let packet = ClientboundPacket {
+            state: self.state,
+            id,
+            data: r.rest().to_vec(),
+        };
+        self.handle(&packet)?;
+        Ok(packet)
+    }
+
+    /// Like `recv()`, but gives up after `timeout` if no packet starts arriving
+    pub fn poll(&mut self, timeout: Duration) -> Result<Option<ClientboundPacket>, ClientError> {
+        if self.reader.buffer().is_empty() {
+            // a zero timeout means none to the socket
+            self.stream
+                .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+            let filled = self.reader.fill_buf().map(|_| ());
+            self.stream.set_read_timeout(None)?;
+            if let Err(e) = filled {
+                return match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Ok(None),
+                    _ => Err(e.into()),
+                };
+            }
+        }
+        self.recv().map(Some)
+    }
+
+    fn handle(&mut self, packet: &ClientboundPacket) -> Result<(), ClientError> {
+        let mut r = packet.reader();
+        match (packet.state, packet.id) {
+            // Disconnect (login, configuration, play)
+            (ProtocolState::Login, 0x00)
+            | (ProtocolState::Config, 0x01)
+            | (ProtocolState::Play, 0x1B) => {
+                return Err(ClientError::Disconnected(r.str()?.to_owned()));
+            }
+            (ProtocolState::Login, 0x01) => return Err(ClientError::Unsupported("encryption")),
+            (ProtocolState::Login, 0x03) => return Err(ClientError::Unsupported("compression")),
+            // Login Success
+            (ProtocolState::Login, 0x02) => {
+                self.uuid = r.uuid()?;
+                self.username = r.str()?.to_owned();
+                self.send(0x03, &[])?;
+                self.state = ProtocolState::Config;
+                self.send_settings()?;
+            }
+            // Finish Configuration
+            (ProtocolState::Config, 0x02) => {
+                self.send(0x02, &[])?;
+                self.state = ProtocolState::Play;
+            }
+            // Keep Alive (configuration, play)
+            (ProtocolState::Config, 0x03) => self.send(0x03, r.rest())?,
+            (ProtocolState::Play, 0x24) => self.send(0x14, r.rest())?,
+            // Ping (configuration, play)
+            (ProtocolState::Config, 0x04) => self.send(0x04, r.rest())?,
+            (ProtocolState::Play, 0x33) => self.send(0x23, r.rest())?,
+            // Start Configuration
+            (ProtocolState::Play, 0x65) => {
+                self.send(0x0B, &[])?;
+                self.state = ProtocolState::Config;
+            }
+            // Chunk Batch Finished: take chunks as fast as a new vanilla client does
+            (ProtocolState::Play, 0x0C) => {
+                let mut body = Vec::new();
+                write_float(&mut body, 9.0);
+                self.send(0x07, &body)?;
+            }
+            // Synchronize Player Position: confirm the teleport
+            (ProtocolState::Play, 0x3E) => {
+                // x, y, z, yaw, pitch and flags
+                r.slice(8 * 3 + 4 * 2 + 1)?;
+                let mut body = Vec::new();
+                write_varint(&mut body, r.varint()?);
+                self.send(0x00, &body)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Client Information and brand, which vanilla clients send when configuration starts
+    fn send_settings(&mut self) -> io::Result<()> {
+        let mut info = Vec::new();
+        write_string(&mut info, "en_us");
+        // view distance, chat mode (enabled), chat colors, all skin parts, main hand (right),
+        // text filtering, server listings
+        write_ibyte(&mut info, 10);
+        write_varint(&mut info, 0);
+        write_bool(&mut info, true);
+        write_ubyte(&mut info, 0x7F);
+        write_varint(&mut info, 1);
+        write_bool(&mut info, false);
+        write_bool(&mut info, true);
+        self.send(0x00, &info)?;
+
+        let mut brand = Vec::new();
+        write_string(&mut brand, BRAND_CHANNEL);
+        brand.extend_from_slice(&encode_brand("libmc"));
+        self.send(0x01, &brand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Just enough of a server to log a client in and ping it
+    fn serve(listener: TcpListener) -> ClientSession {
+        let (stream, addr) = listener.accept().unwrap();
+        let mut r = BufReader::new(stream.try_clone().unwrap());
+        let mut ps = PacketStream::new(stream);
+        let mut session = ClientSession::new(Some(addr));
+        loop {
+            let frame = read_frame(&mut r).unwrap();
+            let packet = ps.decode(&frame).unwrap();
+            session.record(&packet);
+            match packet {
+                InPacket::LoginStart { name, player_uuid } => {
+                    let profile = GameProfile::new(player_uuid, name);
+                    ps.send(OutPacket::LoginSuccess { profile: &profile })
+                        .unwrap();
+                }
+                InPacket::LoginAck => {
+                    ps.send(OutPacket::FinishConfig).unwrap();
+                }
+                InPacket::FinishConfig => {
+                    ps.send(OutPacket::Ping { id: 42 }).unwrap();
+                }
+                InPacket::Pong { id } => {
+                    assert_eq!(id, 42);
+                    return session;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn login() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || serve(listener));
+
+        let mut client = Client::connect(addr, "Steve").unwrap();
+        assert_eq!(client.state(), ProtocolState::Play);
+        assert_eq!(client.uuid(), Uuid::offline("Steve"));
+        let ping = client.recv().unwrap();
+        assert_eq!((ping.state, ping.id), (ProtocolState::Play, 0x33));
+
+        let session = server.join().unwrap();
+        assert_eq!(session.username.as_deref(), Some("Steve"));
+        assert_eq!(session.brand.as_deref(), Some("libmc"));
+        assert_eq!(session.view_distance(), Some(10));
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, IoSlice, Write};
+
+/// Up to this many bytes per tick, packets are sent right away (about one MTU)
+const LATENCY_BOUND_BYTES: usize = 1400;
+/// Up to this many packets per tick, packets are sent right away
+const LATENCY_BOUND_PACKETS: usize = 8;
+/// Buffered data is flushed early once there's this much of it
+const MAX_BUFFERED: usize = 64 * 1024;
+
+/// When packets queued for a client are actually written to the socket.
+/// Sockets have `TCP_NODELAY` set, so this is the only batching that happens.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Send right away while only a few small packets are sent per tick (latency-bound),
+    /// and coalesce the rest of the tick's packets once there are more (throughput-bound)
+    Adaptive,
+    /// Write every packet right away
+    Immediate,
+    /// Write everything at the end of each tick
+    TickBoundary,
+}
+
+/// Buffers outgoing packets per `FlushPolicy`. Each `write()` is expected to be one whole frame.
+#[derive(Debug)]
+pub(crate) struct CoalescingWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    policy: FlushPolicy,
+    bytes_this_tick: usize,
+    packets_this_tick: usize,
+}
+
+impl<W: Write> CoalescingWriter<W> {
+    pub fn new(inner: W, policy: FlushPolicy) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            policy,
+            bytes_this_tick: 0,
+            packets_this_tick: 0,
+        }
+    }
+
+    /// Bytes buffered, not yet written to the inner writer
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Anything still buffered is discarded, so `flush()` first
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Writes out everything buffered during the tick
+    pub fn end_tick(&mut self) -> io::Result<()> {
+        self.bytes_this_tick = 0;
+        self.packets_this_tick = 0;
+        self.flush()
+    }
+
+    fn should_flush(&self) -> bool {
+        match self.policy {
+            FlushPolicy::Immediate => true,
+            FlushPolicy::TickBoundary => false,
+            FlushPolicy::Adaptive => {
+                self.bytes_this_tick <= LATENCY_BOUND_BYTES
+                    && self.packets_this_tick <= LATENCY_BOUND_PACKETS
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for CoalescingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(data)])
+    }
+
+    /// Each slice is expected to be one whole frame
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut len = 0;
+        for data in bufs {
+            self.buf.extend_from_slice(data);
+            len += data.len();
+        }
+        self.bytes_this_tick += len;
+        self.packets_this_tick += bufs.len();
+        if self.should_flush() || self.buf.len() >= MAX_BUFFERED {
+            self.flush()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            let res = self.inner.write_all(&self.buf);
+            // after a failed write the stream is unusable anyways
+            self.buf.clear();
+            res?;
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adaptive() {
+        let mut w = CoalescingWriter::new(Vec::new(), FlushPolicy::Adaptive);
+        w.write_all(&[1; 10]).unwrap();
+        assert_eq!(w.inner.len(), 10, "small packets are sent right away");
+
+        w.write_all(&[2; 2000]).unwrap();
+        w.write_all(&[3; 10]).unwrap();
+        assert_eq!(w.inner.len(), 10, "bulk data waits for the end of the tick");
+        w.end_tick().unwrap();
+        assert_eq!(w.inner.len(), 2020);
+
+        let mut w = CoalescingWriter::new(Vec::new(), FlushPolicy::TickBoundary);
+        w.write_all(&[4]).unwrap();
+        assert!(w.inner.is_empty());
+        w.end_tick().unwrap();
+        assert_eq!(w.inner.len(), 1);
+    }
+}
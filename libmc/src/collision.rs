@@ -0,0 +1,457 @@
+use crate::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Boxes closer than this count as touching, not overlapping (like vanilla's collision epsilon)
+const EPSILON: f64 = 1e-7;
+
+/// An axis-aligned bounding box, in blocks
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl Aabb {
+    /// A whole block at the origin
+    pub const FULL_BLOCK: Aabb = Aabb {
+        min: [0.0; 3],
+        max: [1.0; 3],
+    };
+
+    pub fn new(min: [f64; 3], max: [f64; 3]) -> Self {
+        Self { min, max }
+    }
+
+    /// An entity's hitbox, from the position of its feet
+    pub fn from_dimensions(pos: [f64; 3], dimensions: &EntityDimensions) -> Self {
+        let half = dimensions.width / 2.0;
+        Self {
+            min: [pos[0] - half, pos[1], pos[2] - half],
+            max: [pos[0] + half, pos[1] + dimensions.height, pos[2] + half],
+        }
+    }
+
+    pub fn offset(&self, by: [f64; 3]) -> Self {
+        Self {
+            min: std::array::from_fn(|i| self.min[i] + by[i]),
+            max: std::array::from_fn(|i| self.max[i] + by[i]),
+        }
+    }
+
+    /// Grows the box by `amount` on every side (shrinks it if negative)
+    pub fn inflate(&self, amount: f64) -> Self {
+        Self {
+            min: self.min.map(|x| x - amount),
+            max: self.max.map(|x| x + amount),
+        }
+    }
+
+    /// Stretches the box along `motion`, to cover everything it passes through while moving
+    pub fn expand_towards(&self, motion: [f64; 3]) -> Self {
+        Self {
+            min: std::array::from_fn(|i| self.min[i] + motion[i].min(0.0)),
+            max: std::array::from_fn(|i| self.max[i] + motion[i].max(0.0)),
+        }
+    }
+
+    pub fn center(&self) -> [f64; 3] {
+        std::array::from_fn(|i| (self.min[i] + self.max[i]) / 2.0)
+    }
+
+    /// Whether the boxes overlap. Boxes that only touch don't.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] < other.max[i] && self.max[i] > other.min[i])
+    }
+
+    pub fn contains(&self, point: [f64; 3]) -> bool {
+        (0..3).all(|i| self.min[i] <= point[i] && point[i] <= self.max[i])
+    }
+
+    /// Where the ray from `origin` along `direction` enters the box, as a multiple of `direction`.
+    /// 0.0 if `origin` is inside the box, None if the ray misses it.
+    pub fn ray_intersection(&self, origin: [f64; 3], direction: [f64; 3]) -> Option<f64> {
+        self.ray_entry(origin, direction).map(|(t, _)| t)
+    }
+
+    /// Like `ray_intersection()`, also returning the axis of the side the ray enters through
+    /// (None if `origin` is inside the box)
+    pub(crate) fn ray_entry(
+        &self,
+        origin: [f64; 3],
+        direction: [f64; 3],
+    ) -> Option<(f64, Option<usize>)> {
+        let mut enter = (0.0_f64, None);
+        let mut exit = f64::INFINITY;
+        for i in 0..3 {
+            if direction[i] == 0.0 {
+                if origin[i] < self.min[i] || origin[i] > self.max[i] {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (self.min[i] - origin[i]) / direction[i];
+            let t2 = (self.max[i] - origin[i]) / direction[i];
+            if t1.min(t2) > enter.0 {
+                enter = (t1.min(t2), Some(i));
+            }
+            exit = exit.min(t1.max(t2));
+        }
+        (enter.0 <= exit).then_some(enter)
+    }
+
+    /// How far along `motion` (0.0 to 1.0) this box can move before it hits `other`.
+    /// None if it doesn't hit it at all.
+    pub fn sweep(&self, motion: [f64; 3], other: &Aabb) -> Option<f64> {
+        // moving a box into another is moving its center into the other grown by its size
+        let half: [f64; 3] = std::array::from_fn(|i| (self.max[i] - self.min[i]) / 2.0);
+        let grown = Aabb {
+            min: std::array::from_fn(|i| other.min[i] - half[i]),
+            max: std::array::from_fn(|i| other.max[i] + half[i]),
+        };
+        grown
+            .ray_intersection(self.center(), motion)
+            .filter(|&t| t <= 1.0)
+    }
+
+    /// How far `moving` can go along `axis` (0 = x, 1 = y, 2 = z), up to `motion`, before it hits this box
+    pub fn clip_motion(&self, moving: &Aabb, axis: usize, motion: f64) -> f64 {
+        let overlaps_sideways = (0..3).filter(|&i| i != axis).all(|i| {
+            moving.max[i] - EPSILON > self.min[i] && moving.min[i] + EPSILON < self.max[i]
+        });
+        if !overlaps_sideways {
+            return motion;
+        }
+        if motion > 0.0 && moving.max[axis] - EPSILON <= self.min[axis] {
+            motion.min(self.min[axis] - moving.max[axis])
+        } else if motion < 0.0 && moving.min[axis] + EPSILON >= self.max[axis] {
+            motion.max(self.max[axis] - moving.min[axis])
+        } else {
+            motion
+        }
+    }
+}
+
+/// The part of `motion` that `aabb` can do without going into any of `obstacles`.
+///
+/// Like vanilla, the axes are moved along one at a time (y first, then the larger of x and z),
+/// so sliding along walls and landing on the ground work out.
+pub fn collide(aabb: &Aabb, motion: [f64; 3], obstacles: &[Aabb]) -> [f64; 3] {
+    let order = if motion[0].abs() < motion[2].abs() {
+        [1, 2, 0]
+    } else {
+        [1, 0, 2]
+    };
+    let mut moved = *aabb;
+    let mut allowed = [0.0; 3];
+    for axis in order {
+        let d = obstacles
+            .iter()
+            .fold(motion[axis], |d, o| o.clip_motion(&moved, axis, d));
+        let mut offset = [0.0; 3];
+        offset[axis] = d;
+        moved = moved.offset(offset);
+        allowed[axis] = d;
+    }
+    allowed
+}
+
+/// Collision boxes of block states, relative to the block's corner.
+///
+/// The data generator doesn't report collision shapes, so `from_blocks_report()` works them out
+/// from block names and properties. That gets common blocks right (full cubes, plants, slabs,
+/// stairs, fences, ...); anything else that matters can be corrected with `set()`.
+/// States without a shape are full cubes.
+#[derive(Debug, Clone, Default)]
+pub struct BlockShapes {
+    shapes: HashMap<i32, Vec<Aabb>>,
+}
+
+impl BlockShapes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the blocks report of the vanilla data generator (`generated/reports/blocks.json`,
+    /// see `ItemRegistry` for how to get it)
+    pub fn from_blocks_report(json: &str) -> Result<Self, RegistryError> {
+        let json = Json::parse(json).map_err(RegistryError::Json)?;
+        let blocks = json
+            .as_object()
+            .ok_or(RegistryError::BadReport("blocks report isn't an object"))?;
+        let mut shapes = Self::new();
+        for (name, block) in blocks {
+            let states = block
+                .get("states")
+                .and_then(Json::as_array)
+                .ok_or(RegistryError::BadReport("block without states"))?;
+            for state in states {
+                let id = state
+                    .get("id")
+                    .and_then(Json::as_f64)
+                    .ok_or(RegistryError::BadReport("block state without an id"))?;
+                let prop = |key: &str| {
+                    state
+                        .get("properties")
+                        .and_then(|p| p.get(key))
+                        .and_then(Json::as_str)
+                };
+                let shape = vanilla_shape(name, prop);
+                if shape != [Aabb::FULL_BLOCK] {
+                    shapes.set(id as i32, shape);
+                }
+            }
+        }
+        Ok(shapes)
+    }
+
+    /// Reads a blocks report from a file, see `from_blocks_report()`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        Self::from_blocks_report(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn set(&mut self, state: i32, shape: Vec<Aabb>) {
+        self.shapes.insert(state, shape);
+    }
+
+    /// The boxes of block state `state`; empty for blocks that can be walked through
+    pub fn shape(&self, state: i32) -> &[Aabb] {
+        self.shapes
+            .get(&state)
+            .map_or(std::slice::from_ref(&Aabb::FULL_BLOCK), Vec::as_slice)
+    }
+
+    /// The boxes of every block that could touch `region`, in world coordinates.
+    /// `block_at` gives the state at a block position.
+    pub fn collisions(
+        &self,
+        region: &Aabb,
+        mut block_at: impl FnMut(i32, i32, i32) -> i32,
+    ) -> Vec<Aabb> {
+        // fences and walls stick up into the block above them
+        let (min, max) = (
+            region.min.map(|v| v.floor() as i32),
+            region.max.map(|v| v.ceil() as i32),
+        );
+        let mut boxes = Vec::new();
+        for x in min[0]..max[0] {
+            for y in min[1] - 1..max[1] {
+                for z in min[2]..max[2] {
+                    let offset = [f64::from(x), f64::from(y), f64::from(z)];
+                    boxes.extend(
+                        self.shape(block_at(x, y, z))
+                            .iter()
+                            .map(|b| b.offset(offset))
+                            .filter(|b| b.intersects(&region.inflate(EPSILON))),
+                    );
+                }
+            }
+        }
+        boxes
+    }
+}
+
+/// Blocks without a collision box, besides the ones `no_collision()` finds by suffix
+const NO_COLLISION: &[&str] = &[
+    "water",
+    "lava",
+    "fire",
+    "soul_fire",
+    "grass",
+    "short_grass",
+    "tall_grass",
+    "fern",
+    "large_fern",
+    "dandelion",
+    "poppy",
+    "blue_orchid",
+    "allium",
+    "azure_bluet",
+    "oxeye_daisy",
+    "cornflower",
+    "lily_of_the_valley",
+    "wither_rose",
+    "torchflower",
+    "sunflower",
+    "lilac",
+    "rose_bush",
+    "peony",
+    "pink_petals",
+    "dead_bush",
+    "seagrass",
+    "tall_seagrass",
+    "kelp",
+    "kelp_plant",
+    "sugar_cane",
+    "wheat",
+    "carrots",
+    "potatoes",
+    "beetroots",
+    "nether_wart",
+    "cobweb",
+    "redstone_wire",
+    "tripwire",
+    "tripwire_hook",
+    "nether_portal",
+    "end_portal",
+    "structure_void",
+    "light",
+    "glow_lichen",
+    "sculk_vein",
+    "hanging_roots",
+    "spore_blossom",
+    "lever",
+    "crimson_roots",
+    "warped_roots",
+    "nether_sprouts",
+];
+
+fn no_collision(name: &str) -> bool {
+    const SUFFIXES: &[&str] = &[
+        "air",
+        "_sapling",
+        "torch",
+        "_sign",
+        "_button",
+        "_pressure_plate",
+        "rail",
+        "vine",
+        "vines",
+        "_banner",
+        "_tulip",
+        "_mushroom",
+        "_fungus",
+        "_coral",
+        "_coral_fan",
+        "_coral_wall_fan",
+    ];
+    NO_COLLISION.contains(&name) || SUFFIXES.iter().any(|s| name.ends_with(s))
+}
+
+/// A box in sixteenths of a block, like vanilla's `Block.box()`
+fn px(x1: f64, y1: f64, z1: f64, x2: f64, y2: f64, z2: f64) -> Aabb {
+    Aabb::new(
+        [x1 / 16.0, y1 / 16.0, z1 / 16.0],
+        [x2 / 16.0, y2 / 16.0, z2 / 16.0],
+    )
+}
+
+/// The collision boxes of block `name` (e.g. `minecraft:oak_slab`) with properties from `prop`
+fn vanilla_shape<'a>(name: &str, prop: impl Fn(&str) -> Option<&'a str>) -> Vec<Aabb> {
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    if no_collision(name) {
+        return Vec::new();
+    }
+    if name.ends_with("_slab") {
+        return match prop("type") {
+            Some("bottom") => vec![px(0.0, 0.0, 0.0, 16.0, 8.0, 16.0)],
+            Some("top") => vec![px(0.0, 8.0, 0.0, 16.0, 16.0, 16.0)],
+            _ => vec![Aabb::FULL_BLOCK],
+        };
+    }
+    if name.ends_with("_stairs") {
+        let top = prop("half") == Some("top");
+        let (slab_y, step_y) = if top { (8.0, 0.0) } else { (0.0, 8.0) };
+        let step = match prop("facing") {
+            Some("north") => px(0.0, step_y, 0.0, 16.0, step_y + 8.0, 8.0),
+            Some("south") => px(0.0, step_y, 8.0, 16.0, step_y + 8.0, 16.0),
+            Some("west") => px(0.0, step_y, 0.0, 8.0, step_y + 8.0, 16.0),
+            _ => px(8.0, step_y, 0.0, 16.0, step_y + 8.0, 16.0),
+        };
+        return vec![px(0.0, slab_y, 0.0, 16.0, slab_y + 8.0, 16.0), step];
+    }
+    if name.ends_with("carpet") {
+        return vec![px(0.0, 0.0, 0.0, 16.0, 1.0, 16.0)];
+    }
+    if name == "snow" {
+        let layers: f64 = prop("layers").and_then(|l| l.parse().ok()).unwrap_or(1.0);
+        return match layers - 1.0 {
+            h if h > 0.0 => vec![px(0.0, 0.0, 0.0, 16.0, h * 2.0, 16.0)],
+            _ => Vec::new(),
+        };
+    }
+    if name.ends_with("_fence_gate") {
+        if prop("open") == Some("true") {
+            return Vec::new();
+        }
+        return match prop("facing") {
+            Some("east" | "west") => vec![px(6.0, 0.0, 0.0, 10.0, 24.0, 16.0)],
+            _ => vec![px(0.0, 0.0, 6.0, 16.0, 24.0, 10.0)],
+        };
+    }
+    // fences and walls: a post, with arms to the sides they connect to
+    let (post, arm) = if name.ends_with("_fence") {
+        ((6.0, 10.0), (6.0, 10.0))
+    } else if name.ends_with("_wall") {
+        ((4.0, 12.0), (5.0, 11.0))
+    } else {
+        return vec![Aabb::FULL_BLOCK];
+    };
+    let connected = |side| matches!(prop(side), Some("true" | "low" | "tall"));
+    let mut boxes = vec![px(post.0, 0.0, post.0, post.1, 24.0, post.1)];
+    if connected("north") {
+        boxes.push(px(arm.0, 0.0, 0.0, arm.1, 24.0, post.0));
+    }
+    if connected("south") {
+        boxes.push(px(arm.0, 0.0, post.1, arm.1, 24.0, 16.0));
+    }
+    if connected("west") {
+        boxes.push(px(0.0, 0.0, arm.0, post.0, 24.0, arm.1));
+    }
+    if connected("east") {
+        boxes.push(px(post.1, 0.0, arm.0, 16.0, 24.0, arm.1));
+    }
+    boxes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walking_into_blocks() {
+        let report = r#"{
+            "minecraft:stone": {"states": [{"id": 1, "default": true}]},
+            "minecraft:air": {"states": [{"id": 0, "default": true}]},
+            "minecraft:oak_slab": {"states": [
+                {"id": 2, "properties": {"type": "top", "waterlogged": "false"}},
+                {"id": 3, "properties": {"type": "bottom", "waterlogged": "false"}}
+            ]}
+        }"#;
+        let shapes = BlockShapes::from_blocks_report(report).unwrap();
+        assert_eq!(shapes.shape(1), [Aabb::FULL_BLOCK]);
+        assert!(shapes.shape(0).is_empty());
+        assert_eq!(shapes.shape(3)[0].max[1], 0.5);
+
+        // a floor of stone at y = 0, a bottom slab at x = 2, and a wall at x = 4
+        let world = |x: i32, y: i32, _z: i32| match (x, y) {
+            (_, 0) | (4, 1 | 2) => 1,
+            (2, 1) => 3,
+            _ => 0,
+        };
+        let player = Aabb::from_dimensions([0.5, 1.0, 0.5], &Pose::Standing.player_dimensions());
+        let motion = [5.0, -0.5, 0.0];
+        let obstacles = shapes.collisions(&player.expand_towards(motion), world);
+        let allowed = collide(&player, motion, &obstacles);
+        // stopped by the floor, then by the slab
+        assert_eq!(allowed[1], 0.0);
+        assert!((allowed[0] - 1.2).abs() < 1e-9);
+
+        let over_slab = player.offset([0.0, 0.5, 0.0]);
+        let obstacles = shapes.collisions(&over_slab.expand_towards(motion), world);
+        let allowed = collide(&over_slab, [5.0, 0.0, 0.0], &obstacles);
+        assert!((allowed[0] - 3.2).abs() < 1e-9);
+
+        let wall = Aabb::FULL_BLOCK.offset([4.0, 1.0, 0.0]);
+        assert!((player.sweep([5.0, 0.0, 0.0], &wall).unwrap() - 0.64).abs() < 1e-9);
+        assert_eq!(player.sweep([0.0, 0.0, 5.0], &wall), None);
+        assert_eq!(
+            Aabb::FULL_BLOCK.ray_intersection([0.5, 5.0, 0.5], [0.0, -1.0, 0.0]),
+            Some(4.0)
+        );
+        assert_eq!(
+            Aabb::FULL_BLOCK.ray_intersection([0.5, 5.0, 0.5], [0.0, 1.0, 0.0]),
+            None
+        );
+    }
+}
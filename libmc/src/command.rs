@@ -0,0 +1,1223 @@
+//! Brigadier command trees: what's sent in the Commands packet so clients can suggest and
+//! highlight commands, and parsing the commands players send against the same tree.
+
+use crate::*;
+use std::borrow::Cow;
+use std::f64::consts::FRAC_PI_2;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError {
+    /// byte offset into the command where parsing failed
+    pub offset: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bad command at byte {}: {}", self.offset, self.msg)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// How a `brigadier:string` argument is read
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StringMode {
+    /// Up to the next space
+    SingleWord = 0,
+    /// One word, or a "quoted phrase"
+    QuotablePhrase = 1,
+    /// The rest of the command
+    GreedyPhrase = 2,
+}
+
+/// The type of a command argument, with its properties, in the order of their (1.20.2)
+/// network IDs. Numbers outside `min` and `max` are rejected by both the client and `parse()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentParser {
+    Bool,
+    Float {
+        min: Option<f32>,
+        max: Option<f32>,
+    },
+    Double {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    Integer {
+        min: Option<i32>,
+        max: Option<i32>,
+    },
+    Long {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    String(StringMode),
+    /// A player name, UUID or selector like `@e[type=pig]`
+    Entity {
+        single: bool,
+        players_only: bool,
+    },
+    GameProfile,
+    BlockPos,
+    ColumnPos,
+    Vec3,
+    Vec2,
+    BlockState,
+    /// A block state, or a block tag like `#minecraft:logs`
+    BlockPredicate,
+    ItemStack,
+    /// An item, or an item tag
+    ItemPredicate,
+    Color,
+    /// JSON text
+    Component,
+    /// The rest of the command
+    Message,
+    /// An SNBT compound
+    Nbt,
+    /// Any SNBT value
+    NbtTag,
+    NbtPath,
+    Objective,
+    ObjectiveCriteria,
+    Operation,
+    Particle,
+    Angle,
+    Rotation,
+    ScoreboardSlot,
+    ScoreHolder {
+        allow_multiple: bool,
+    },
+    Swizzle,
+    Team,
+    ItemSlot,
+    ResourceLocation,
+    Function,
+    EntityAnchor,
+    IntRange,
+    FloatRange,
+    Dimension,
+    GameMode,
+    /// A duration like `3d`, `10s` or `20` (ticks)
+    Time {
+        /// in ticks
+        min: i32,
+    },
+    ResourceOrTag {
+        registry: &'static str,
+    },
+    ResourceOrTagKey {
+        registry: &'static str,
+    },
+    Resource {
+        registry: &'static str,
+    },
+    ResourceKey {
+        registry: &'static str,
+    },
+    TemplateMirror,
+    TemplateRotation,
+    Heightmap,
+    Uuid,
+}
+
+impl ArgumentParser {
+    /// The parser's ID in the Commands packet
+    pub fn id(&self) -> i32 {
+        use ArgumentParser::*;
+        match self {
+            Bool => 0,
+            Float { .. } => 1,
+            Double { .. } => 2,
+            Integer { .. } => 3,
+            Long { .. } => 4,
+            String(_) => 5,
+            Entity { .. } => 6,
+            GameProfile => 7,
+            BlockPos => 8,
+            ColumnPos => 9,
+            Vec3 => 10,
+            Vec2 => 11,
+            BlockState => 12,
+            BlockPredicate => 13,
+            ItemStack => 14,
+            ItemPredicate => 15,
+            Color => 16,
+            Component => 17,
+            Message => 18,
+            Nbt => 19,
+            NbtTag => 20,
+            NbtPath => 21,
+            Objective => 22,
+            ObjectiveCriteria => 23,
+            Operation => 24,
+            Particle => 25,
+            Angle => 26,
+            Rotation => 27,
+            ScoreboardSlot => 28,
+            ScoreHolder { .. } => 29,
+            Swizzle => 30,
+            Team => 31,
+            ItemSlot => 32,
+            ResourceLocation => 33,
+            Function => 34,
+            EntityAnchor => 35,
+            IntRange => 36,
+            FloatRange => 37,
+            Dimension => 38,
+            GameMode => 39,
+            Time { .. } => 40,
+            ResourceOrTag { .. } => 41,
+            ResourceOrTagKey { .. } => 42,
+            Resource { .. } => 43,
+            ResourceKey { .. } => 44,
+            TemplateMirror => 45,
+            TemplateRotation => 46,
+            Heightmap => 47,
+            Uuid => 48,
+        }
+    }
+
+    /// The parser's ID, then its properties
+    fn write(&self, buf: &mut Vec<u8>) {
+        write_varint(buf, self.id());
+        match *self {
+            ArgumentParser::Float { min, max } => {
+                write_ubyte(buf, range_flags(min.is_some(), max.is_some()));
+                min.into_iter().chain(max).for_each(|x| write_float(buf, x));
+            }
+            ArgumentParser::Double { min, max } => {
+                write_ubyte(buf, range_flags(min.is_some(), max.is_some()));
+                min.into_iter()
+                    .chain(max)
+                    .for_each(|x| write_double(buf, x));
+            }
+            ArgumentParser::Integer { min, max } => {
+                write_ubyte(buf, range_flags(min.is_some(), max.is_some()));
+                min.into_iter().chain(max).for_each(|x| write_int(buf, x));
+            }
+            ArgumentParser::Long { min, max } => {
+                write_ubyte(buf, range_flags(min.is_some(), max.is_some()));
+                min.into_iter().chain(max).for_each(|x| write_long(buf, x));
+            }
+            ArgumentParser::String(mode) => write_varint(buf, mode as i32),
+            ArgumentParser::Entity {
+                single,
+                players_only,
+            } => write_ubyte(buf, u8::from(single) | u8::from(players_only) << 1),
+            ArgumentParser::ScoreHolder { allow_multiple } => {
+                write_ubyte(buf, u8::from(allow_multiple));
+            }
+            ArgumentParser::Time { min } => write_int(buf, min),
+            ArgumentParser::ResourceOrTag { registry }
+            | ArgumentParser::ResourceOrTagKey { registry }
+            | ArgumentParser::Resource { registry }
+            | ArgumentParser::ResourceKey { registry } => write_string(buf, registry),
+            _ => {}
+        }
+    }
+}
+
+fn range_flags(min: bool, max: bool) -> u8 {
+    u8::from(min) | u8::from(max) << 1
+}
+
+/// What the client asks the server to suggest for an argument
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SuggestionsType {
+    /// Suggestions come from Command Suggestions Request packets
+    AskServer,
+    AllRecipes,
+    AvailableSounds,
+    SummonableEntities,
+}
+
+impl SuggestionsType {
+    pub fn identifier(self) -> &'static str {
+        match self {
+            SuggestionsType::AskServer => "minecraft:ask_server",
+            SuggestionsType::AllRecipes => "minecraft:all_recipes",
+            SuggestionsType::AvailableSounds => "minecraft:available_sounds",
+            SuggestionsType::SummonableEntities => "minecraft:summonable_entities",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum NodeKind {
+    Root,
+    Literal(String),
+    Argument {
+        name: String,
+        parser: ArgumentParser,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    kind: NodeKind,
+    children: Vec<usize>,
+    executable: bool,
+    redirect: Option<usize>,
+    suggestions: Option<SuggestionsType>,
+}
+
+/// The commands players can run. Nodes are added under `ROOT` with `literal()` and `argument()`,
+/// and referred to by the indices those return; commands that may end at a node need
+/// `set_executable()`.
+///
+/// The tree is sent in `OutPacket::Commands`, and the commands players send back are parsed with
+/// `parse()`.
+#[derive(Debug, Clone)]
+pub struct CommandTree {
+    nodes: Vec<Node>,
+}
+
+impl Default for CommandTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandTree {
+    /// The root node, whose children are the commands
+    pub const ROOT: usize = 0;
+
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node {
+                kind: NodeKind::Root,
+                children: Vec::new(),
+                executable: false,
+                redirect: None,
+                suggestions: None,
+            }],
+        }
+    }
+
+    fn add(&mut self, parent: usize, kind: NodeKind) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            kind,
+            children: Vec::new(),
+            executable: false,
+            redirect: None,
+            suggestions: None,
+        });
+        self.nodes[parent].children.push(index);
+        index
+    }
+
+    /// Adds a fixed word under `parent`, e.g. a command's name
+    pub fn literal(&mut self, parent: usize, name: impl Into<String>) -> usize {
+        self.add(parent, NodeKind::Literal(name.into()))
+    }
+
+    /// Adds an argument under `parent`. `name` is shown to players, and used to look the value up
+    /// in `ParsedCommand::get()`.
+    pub fn argument(
+        &mut self,
+        parent: usize,
+        name: impl Into<String>,
+        parser: ArgumentParser,
+    ) -> usize {
+        self.add(
+            parent,
+            NodeKind::Argument {
+                name: name.into(),
+                parser,
+            },
+        )
+    }
+
+    /// Makes a command that ends at `node` valid
+    pub fn set_executable(&mut self, node: usize) {
+        self.nodes[node].executable = true;
+    }
+
+    /// Continues the command after `node` with the children of `target`, e.g. an alias
+    pub fn set_redirect(&mut self, node: usize, target: usize) {
+        self.nodes[node].redirect = Some(target);
+    }
+
+    /// Only for argument nodes
+    pub fn set_suggestions(&mut self, node: usize, suggestions: SuggestionsType) {
+        self.nodes[node].suggestions = Some(suggestions);
+    }
+
+    /// The Commands packet's body
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_varint(buf, self.nodes.len().try_into().unwrap());
+        for node in &self.nodes {
+            let mut flags = match node.kind {
+                NodeKind::Root => 0,
+                NodeKind::Literal(_) => 1,
+                NodeKind::Argument { .. } => 2,
+            };
+            if node.executable {
+                flags |= 0x04;
+            }
+            if node.redirect.is_some() {
+                flags |= 0x08;
+            }
+            if node.suggestions.is_some() {
+                flags |= 0x10;
+            }
+            write_ubyte(buf, flags);
+            write_varint(buf, node.children.len().try_into().unwrap());
+            for &child in &node.children {
+                write_varint(buf, child.try_into().unwrap());
+            }
+            if let Some(redirect) = node.redirect {
+                write_varint(buf, redirect.try_into().unwrap());
+            }
+            match &node.kind {
+                NodeKind::Root => {}
+                NodeKind::Literal(name) => write_string(buf, name),
+                NodeKind::Argument { name, parser } => {
+                    write_string(buf, name);
+                    parser.write(buf);
+                }
+            }
+            if let Some(suggestions) = node.suggestions {
+                write_string(buf, suggestions.identifier());
+            }
+        }
+        write_varint(buf, Self::ROOT as i32);
+    }
+
+    /// Parses a command (without the leading '/', as in `InPacket::ChatCommand`), giving the node
+    /// it ended at and its arguments
+    pub fn parse<'a>(&'a self, command: &'a str) -> Result<ParsedCommand<'a>, CommandError> {
+        let mut arguments = Vec::new();
+        let node = self.walk(Self::ROOT, Reader::new(command), &mut arguments)?;
+        Ok(ParsedCommand { node, arguments })
+    }
+
+    /// Tries each child of `node` in turn, literals first like Brigadier does
+    fn walk<'a>(
+        &'a self,
+        node: usize,
+        mut r: Reader<'a>,
+        arguments: &mut Vec<(&'a str, Argument<'a>)>,
+    ) -> Result<usize, CommandError> {
+        if r.at_end() {
+            return match self.nodes[node].executable {
+                true => Ok(node),
+                false => Err(r.err("incomplete command")),
+            };
+        }
+        if node != Self::ROOT && !r.eat(' ') {
+            return Err(r.err("expected a space"));
+        }
+        let children = match self.nodes[node].redirect {
+            Some(target) => &self.nodes[target].children,
+            None => &self.nodes[node].children,
+        };
+        let literals = children
+            .iter()
+            .filter(|&&c| matches!(self.nodes[c].kind, NodeKind::Literal(_)));
+        let args = children
+            .iter()
+            .filter(|&&c| matches!(self.nodes[c].kind, NodeKind::Argument { .. }));
+
+        let mut furthest = r.err("unknown command or argument");
+        for &child in literals.chain(args) {
+            let mut cr = r;
+            let len = arguments.len();
+            let parsed = match &self.nodes[child].kind {
+                NodeKind::Literal(name) => cr.literal(name),
+                NodeKind::Argument { name, parser } => {
+                    parse_argument(parser, &mut cr).map(|arg| arguments.push((name.as_str(), arg)))
+                }
+                NodeKind::Root => unreachable!(),
+            };
+            let res = parsed.and_then(|()| self.walk(child, cr, arguments));
+            match res {
+                Ok(end) => return Ok(end),
+                Err(e) => {
+                    arguments.truncate(len);
+                    if e.offset >= furthest.offset {
+                        furthest = e;
+                    }
+                }
+            }
+        }
+        Err(furthest)
+    }
+}
+
+/// A command parsed by `CommandTree::parse()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedCommand<'a> {
+    /// The executable node the command ended at
+    pub node: usize,
+    /// Argument names and values, in order
+    pub arguments: Vec<(&'a str, Argument<'a>)>,
+}
+
+impl<'a> ParsedCommand<'a> {
+    pub fn get(&self, name: &str) -> Option<&Argument<'a>> {
+        self.arguments
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, arg)| arg)
+    }
+}
+
+/// A parsed argument. Argument types that `parse()` doesn't look into, like colors and
+/// particles, are `Word`s.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument<'a> {
+    Bool(bool),
+    Float(f32),
+    Double(f64),
+    Integer(i32),
+    Long(i64),
+    String(Cow<'a, str>),
+    Entity(EntitySelector<'a>),
+    /// Block positions, columns, vectors and rotations
+    Coordinates(Coordinates),
+    Block(BlockArgument<'a>),
+    Item(ItemArgument<'a>),
+    Nbt(Nbt<'static>),
+    IntRange(NumberRange<i32>),
+    FloatRange(NumberRange<f64>),
+    GameMode(GameMode),
+    /// In ticks
+    Time(i32),
+    Uuid(Uuid),
+    Word(&'a str),
+}
+
+/// One axis of a position: `5`, or `~5` relative to the executor
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Coordinate {
+    pub value: f64,
+    pub relative: bool,
+}
+
+impl Coordinate {
+    pub fn resolve(self, origin: f64) -> f64 {
+        match self.relative {
+            true => origin + self.value,
+            false => self.value,
+        }
+    }
+}
+
+/// 1-3 coordinates, depending on the argument type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Coordinates {
+    World(Vec<Coordinate>),
+    /// `^left ^up ^forwards`, relative to where the executor is looking
+    Local([f64; 3]),
+}
+
+impl Coordinates {
+    /// A position, for an executor at `origin` looking in the direction of `yaw` and `pitch`
+    /// (degrees). Coordinates that weren't given (e.g. y for a column) are taken from `origin`.
+    pub fn resolve(&self, origin: Vec3, yaw: f32, pitch: f32) -> Vec3 {
+        match self {
+            Coordinates::World(c) => {
+                let at = |i: usize, o: f64| c.get(i).map_or(o, |c| c.resolve(o));
+                match c.len() {
+                    2 => Vec3::from([at(0, origin.x), origin.y, at(1, origin.z)]),
+                    _ => Vec3::from([at(0, origin.x), at(1, origin.y), at(2, origin.z)]),
+                }
+            }
+            &Coordinates::Local([left, up, forwards]) => {
+                // as vanilla does it
+                let (yaw, pitch) = (f64::from(yaw).to_radians(), f64::from(pitch).to_radians());
+                let forward = Vec3::from([
+                    (yaw + FRAC_PI_2).cos() * (-pitch).cos(),
+                    (-pitch).sin(),
+                    (yaw + FRAC_PI_2).sin() * (-pitch).cos(),
+                ]);
+                let upward = Vec3::from([
+                    (yaw + FRAC_PI_2).cos() * (FRAC_PI_2 - pitch).cos(),
+                    (FRAC_PI_2 - pitch).sin(),
+                    (yaw + FRAC_PI_2).sin() * (FRAC_PI_2 - pitch).cos(),
+                ]);
+                let leftward = -Vec3::from([
+                    forward.y * upward.z - forward.z * upward.y,
+                    forward.z * upward.x - forward.x * upward.z,
+                    forward.x * upward.y - forward.y * upward.x,
+                ]);
+                origin + forward * forwards + upward * up + leftward * left
+            }
+        }
+    }
+}
+
+/// `@p`, `@a`, ...
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SelectorTarget {
+    NearestPlayer,
+    RandomPlayer,
+    AllPlayers,
+    AllEntities,
+    /// The executor
+    Executor,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntitySelector<'a> {
+    Player(&'a str),
+    Uuid(Uuid),
+    Selector {
+        target: SelectorTarget,
+        /// e.g. `("type", "!minecraft:pig")`, as written
+        filters: Vec<(&'a str, &'a str)>,
+    },
+}
+
+impl<'a> EntitySelector<'a> {
+    /// The value of the last filter named `key`
+    pub fn filter(&self, key: &str) -> Option<&'a str> {
+        match self {
+            EntitySelector::Selector { filters, .. } => filters
+                .iter()
+                .rev()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| *v),
+            _ => None,
+        }
+    }
+
+    /// Whether it can only select one entity
+    pub fn is_single(&self) -> bool {
+        match self {
+            EntitySelector::Selector {
+                target: SelectorTarget::AllPlayers | SelectorTarget::AllEntities,
+                ..
+            } => self.filter("limit") == Some("1"),
+            _ => true,
+        }
+    }
+
+    /// Whether it can only select players
+    pub fn players_only(&self) -> bool {
+        match self {
+            EntitySelector::Uuid(_) => false,
+            EntitySelector::Selector {
+                target: SelectorTarget::AllEntities,
+                ..
+            } => matches!(self.filter("type"), Some("player" | "minecraft:player")),
+            _ => true,
+        }
+    }
+}
+
+/// `minecraft:oak_log[axis=y]{nbt}`, or `#minecraft:logs` where tags are allowed
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockArgument<'a> {
+    pub id: &'a str,
+    /// Whether `id` names a tag
+    pub tag: bool,
+    pub properties: Vec<(&'a str, &'a str)>,
+    pub nbt: Option<CompoundNbt<'static>>,
+}
+
+/// `minecraft:diamond_sword{nbt}`, or `#minecraft:swords` where tags are allowed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemArgument<'a> {
+    pub id: &'a str,
+    /// Whether `id` names a tag
+    pub tag: bool,
+    pub nbt: Option<CompoundNbt<'static>>,
+}
+
+/// `1..5`, `..5`, `1..` or `3`, inclusive
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NumberRange<T> {
+    pub min: Option<T>,
+    pub max: Option<T>,
+}
+
+impl<T: PartialOrd> NumberRange<T> {
+    pub fn contains(&self, x: T) -> bool {
+        self.min.as_ref().is_none_or(|min| *min <= x)
+            && self.max.as_ref().is_none_or(|max| x <= *max)
+    }
+}
+
+fn parse_argument<'a>(
+    parser: &ArgumentParser,
+    r: &mut Reader<'a>,
+) -> Result<Argument<'a>, CommandError> {
+    use ArgumentParser as P;
+    Ok(match *parser {
+        P::Bool => match r.word() {
+            "true" => Argument::Bool(true),
+            "false" => Argument::Bool(false),
+            _ => return Err(r.err("expected true or false")),
+        },
+        P::Float { min, max } => Argument::Float(r.number_in(min, max)?),
+        P::Double { min, max } => Argument::Double(r.number_in(min, max)?),
+        P::Integer { min, max } => Argument::Integer(r.number_in(min, max)?),
+        P::Long { min, max } => Argument::Long(r.number_in(min, max)?),
+        P::String(StringMode::SingleWord) => match r.word() {
+            "" => return Err(r.err("expected a word")),
+            word => Argument::String(Cow::Borrowed(word)),
+        },
+        P::String(StringMode::QuotablePhrase) => Argument::String(r.string()?),
+        P::String(StringMode::GreedyPhrase) | P::Message => {
+            Argument::String(Cow::Borrowed(r.take_rest()))
+        }
+        P::Entity {
+            single,
+            players_only,
+        } => {
+            let start = r.pos;
+            let selector = r.selector()?;
+            if single && !selector.is_single() {
+                return Err(r.err_at(start, "only one entity is allowed"));
+            }
+            if players_only && !selector.players_only() {
+                return Err(r.err_at(start, "only players are allowed"));
+            }
+            Argument::Entity(selector)
+        }
+        P::GameProfile => {
+            let start = r.pos;
+            let selector = r.selector()?;
+            if !selector.players_only() {
+                return Err(r.err_at(start, "only players are allowed"));
+            }
+            Argument::Entity(selector)
+        }
+        P::ScoreHolder { .. } if r.peek() == Some('*') => Argument::Word(r.token()?),
+        P::ScoreHolder { allow_multiple } => {
+            let start = r.pos;
+            let selector = r.selector()?;
+            if !allow_multiple && !selector.is_single() {
+                return Err(r.err_at(start, "only one entity is allowed"));
+            }
+            Argument::Entity(selector)
+        }
+        P::BlockPos => Argument::Coordinates(r.coordinates(3, true, true)?),
+        P::ColumnPos => Argument::Coordinates(r.coordinates(2, true, true)?),
+        P::Vec3 => Argument::Coordinates(r.coordinates(3, false, true)?),
+        P::Vec2 => Argument::Coordinates(r.coordinates(2, false, true)?),
+        P::Rotation => Argument::Coordinates(r.coordinates(2, false, false)?),
+        P::Angle => Argument::Coordinates(r.coordinates(1, false, false)?),
+        P::BlockState => Argument::Block(r.block(false)?),
+        P::BlockPredicate => Argument::Block(r.block(true)?),
+        P::ItemStack => Argument::Item(r.item(false)?),
+        P::ItemPredicate => Argument::Item(r.item(true)?),
+        P::Nbt => {
+            let start = r.pos;
+            match r.nbt()? {
+                nbt @ Nbt::Compound(_) => Argument::Nbt(nbt),
+                _ => return Err(r.err_at(start, "expected a compound")),
+            }
+        }
+        P::NbtTag => Argument::Nbt(r.nbt()?),
+        P::IntRange => Argument::IntRange(r.range()?),
+        P::FloatRange => Argument::FloatRange(r.range()?),
+        P::GameMode => Argument::GameMode(match r.word() {
+            "survival" => GameMode::Survival,
+            "creative" => GameMode::Creative,
+            "adventure" => GameMode::Adventure,
+            "spectator" => GameMode::Spectator,
+            _ => return Err(r.err("unknown game mode")),
+        }),
+        P::Time { min } => {
+            let start = r.pos;
+            let amount: f32 = r.number()?;
+            // in days, seconds or (the default) ticks
+            let scale = if r.eat('d') {
+                24000.0
+            } else if r.eat('s') {
+                20.0
+            } else {
+                r.eat('t');
+                1.0
+            };
+            let ticks = (amount * scale).round() as i32;
+            if ticks < min {
+                return Err(r.err_at(start, "time is too short"));
+            }
+            Argument::Time(ticks)
+        }
+        P::Uuid => {
+            let start = r.pos;
+            match Uuid::parse(r.token()?) {
+                Some(uuid) => Argument::Uuid(uuid),
+                None => return Err(r.err_at(start, "invalid UUID")),
+            }
+        }
+        P::Color
+        | P::Component
+        | P::NbtPath
+        | P::Objective
+        | P::ObjectiveCriteria
+        | P::Operation
+        | P::Particle
+        | P::ScoreboardSlot
+        | P::Swizzle
+        | P::Team
+        | P::ItemSlot
+        | P::ResourceLocation
+        | P::Function
+        | P::EntityAnchor
+        | P::Dimension
+        | P::ResourceOrTag { .. }
+        | P::ResourceOrTagKey { .. }
+        | P::Resource { .. }
+        | P::ResourceKey { .. }
+        | P::TemplateMirror
+        | P::TemplateRotation
+        | P::Heightmap => Argument::Word(r.token()?),
+    })
+}
+
+/// Reads through a command, like Brigadier's `StringReader`
+#[derive(Debug, Copy, Clone)]
+struct Reader<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(s: &'a str) -> Self {
+        Self { s, pos: 0 }
+    }
+
+    fn err(&self, msg: &'static str) -> CommandError {
+        self.err_at(self.pos, msg)
+    }
+
+    fn err_at(&self, offset: usize, msg: &'static str) -> CommandError {
+        CommandError { offset, msg }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.s.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char, msg: &'static str) -> Result<(), CommandError> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.err(msg)),
+        }
+    }
+
+    /// Whether the current argument ends here
+    fn at_separator(&self) -> bool {
+        matches!(self.peek(), None | Some(' '))
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn take_rest(&mut self) -> &'a str {
+        let rest = self.rest();
+        self.pos = self.s.len();
+        rest
+    }
+
+    fn literal(&mut self, name: &str) -> Result<(), CommandError> {
+        if !self.rest().starts_with(name) {
+            return Err(self.err("unknown command or argument"));
+        }
+        let mut after = *self;
+        after.pos += name.len();
+        if !after.at_separator() {
+            return Err(self.err("unknown command or argument"));
+        }
+        *self = after;
+        Ok(())
+    }
+
+    /// Characters allowed in unquoted strings
+    fn word(&mut self) -> &'a str {
+        self.take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'))
+    }
+
+    /// A word or quoted string
+    fn string(&mut self) -> Result<Cow<'a, str>, CommandError> {
+        let Some(quote @ ('"' | '\'')) = self.peek() else {
+            return Ok(Cow::Borrowed(self.word()));
+        };
+        let start = self.pos;
+        self.pos += 1;
+        let mut out = String::new();
+        let mut escaped = false;
+        for c in self.rest().chars() {
+            self.pos += c.len_utf8();
+            match c {
+                _ if escaped => {
+                    if c != quote && c != '\\' {
+                        return Err(self.err("invalid escape"));
+                    }
+                    out.push(c);
+                    escaped = false;
+                }
+                '\\' => escaped = true,
+                _ if c == quote => return Ok(Cow::Owned(out)),
+                _ => out.push(c),
+            }
+        }
+        Err(self.err_at(start, "unclosed quote"))
+    }
+
+    /// Everything up to `stop` (outside quotes and brackets), e.g. SNBT or JSON
+    fn balanced(&mut self, stop: impl Fn(char) -> bool) -> Result<&'a str, CommandError> {
+        let start = self.pos;
+        let mut depth = 0usize;
+        let mut quote = None;
+        let mut escaped = false;
+        for (i, c) in self.rest().char_indices() {
+            match (quote, c) {
+                (Some(_), _) if escaped => escaped = false,
+                (Some(_), '\\') => escaped = true,
+                (Some(q), _) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '[' | '{' | '(') => depth += 1,
+                (None, _) if depth == 0 && stop(c) => {
+                    self.pos += i;
+                    return Ok(&self.s[start..self.pos]);
+                }
+                (None, ']' | '}' | ')') => depth -= 1,
+                (None, _) => {}
+            }
+        }
+        if quote.is_some() || depth > 0 {
+            return Err(self.err_at(start, "unclosed brackets or quotes"));
+        }
+        Ok(self.take_rest())
+    }
+
+    /// A non-empty token that ends at a space
+    fn token(&mut self) -> Result<&'a str, CommandError> {
+        match self.balanced(|c| c == ' ')? {
+            "" => Err(self.err("expected an argument")),
+            token => Ok(token),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, CommandError> {
+        let start = self.pos;
+        let s = self.take_while(|c| c.is_ascii_digit() || matches!(c, '.' | '-'));
+        if s.is_empty() {
+            return Err(self.err("expected a number"));
+        }
+        s.parse().map_err(|_| self.err_at(start, "invalid number"))
+    }
+
+    fn number_in<T: std::str::FromStr + PartialOrd>(
+        &mut self,
+        min: Option<T>,
+        max: Option<T>,
+    ) -> Result<T, CommandError> {
+        let start = self.pos;
+        let x = self.number()?;
+        if min.is_some_and(|min| x < min) {
+            return Err(self.err_at(start, "number is too small"));
+        }
+        if max.is_some_and(|max| x > max) {
+            return Err(self.err_at(start, "number is too big"));
+        }
+        Ok(x)
+    }
+
+    fn range<T: std::str::FromStr + Copy>(&mut self) -> Result<NumberRange<T>, CommandError> {
+        let start = self.pos;
+        let s = self.take_while(|c| c.is_ascii_digit() || matches!(c, '.' | '-'));
+        let bound = |b: &str| match b {
+            "" => Ok(None),
+            b => b
+                .parse()
+                .map(Some)
+                .map_err(|_| self.err_at(start, "invalid range")),
+        };
+        let range = match s.split_once("..") {
+            Some((min, max)) => NumberRange {
+                min: bound(min)?,
+                max: bound(max)?,
+            },
+            None => {
+                let x = bound(s)?;
+                NumberRange { min: x, max: x }
+            }
+        };
+        if range.min.is_none() && range.max.is_none() {
+            return Err(self.err_at(start, "expected a range"));
+        }
+        Ok(range)
+    }
+
+    /// `n` space-separated coordinates; `^` is only allowed for 3
+    fn coordinates(
+        &mut self,
+        n: usize,
+        ints: bool,
+        relative: bool,
+    ) -> Result<Coordinates, CommandError> {
+        if n == 3 && self.peek() == Some('^') {
+            let mut local = [0.0; 3];
+            for (i, c) in local.iter_mut().enumerate() {
+                if i > 0 {
+                    self.expect(' ', "expected three coordinates")?;
+                }
+                self.expect('^', "can't mix local and world coordinates")?;
+                if !self.at_separator() {
+                    *c = self.number()?;
+                }
+            }
+            return Ok(Coordinates::Local(local));
+        }
+        let mut coords = Vec::with_capacity(n);
+        for i in 0..n {
+            if i > 0 {
+                self.expect(' ', "expected more coordinates")?;
+            }
+            let is_relative = relative && self.eat('~');
+            let value = if is_relative && self.at_separator() {
+                0.0
+            } else if ints && !is_relative {
+                f64::from(self.number::<i32>()?)
+            } else {
+                self.number()?
+            };
+            coords.push(Coordinate {
+                value,
+                relative: is_relative,
+            });
+        }
+        Ok(Coordinates::World(coords))
+    }
+
+    /// A namespaced ID, like `minecraft:stone`
+    fn resource_id(&mut self) -> Result<&'a str, CommandError> {
+        let id = self
+            .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':' | '/'));
+        match id {
+            "" => Err(self.err("expected an ID")),
+            id => Ok(id),
+        }
+    }
+
+    fn nbt(&mut self) -> Result<Nbt<'static>, CommandError> {
+        let start = self.pos;
+        let s = self.token()?;
+        Nbt::parse_snbt(s).map_err(|e| self.err_at(start + e.offset, e.msg))
+    }
+
+    /// `{...}` after a block or item, if there is one
+    fn compound(&mut self) -> Result<Option<CompoundNbt<'static>>, CommandError> {
+        if self.peek() != Some('{') {
+            return Ok(None);
+        }
+        let start = self.pos;
+        let s = self.balanced(|c| c == ' ')?;
+        CompoundNbt::parse_snbt(s, "")
+            .map(Some)
+            .map_err(|e| self.err_at(start + e.offset, e.msg))
+    }
+
+    fn block(&mut self, allow_tag: bool) -> Result<BlockArgument<'a>, CommandError> {
+        let tag = allow_tag && self.eat('#');
+        let id = self.resource_id()?;
+        let mut properties = Vec::new();
+        if self.eat('[') {
+            while !self.eat(']') {
+                if !properties.is_empty() {
+                    self.expect(',', "expected ',' or ']'")?;
+                }
+                let key = self.word();
+                self.expect('=', "expected '='")?;
+                let value = self.word();
+                if key.is_empty() || value.is_empty() {
+                    return Err(self.err("expected a block property"));
+                }
+                properties.push((key, value));
+            }
+        }
+        Ok(BlockArgument {
+            id,
+            tag,
+            properties,
+            nbt: self.compound()?,
+        })
+    }
+
+    fn item(&mut self, allow_tag: bool) -> Result<ItemArgument<'a>, CommandError> {
+        let tag = allow_tag && self.eat('#');
+        Ok(ItemArgument {
+            id: self.resource_id()?,
+            tag,
+            nbt: self.compound()?,
+        })
+    }
+
+    fn selector(&mut self) -> Result<EntitySelector<'a>, CommandError> {
+        if !self.eat('@') {
+            let start = self.pos;
+            let token = self.token()?;
+            if let Some(uuid) = Uuid::parse(token) {
+                return Ok(EntitySelector::Uuid(uuid));
+            }
+            if token.len() > 16 || !token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(self.err_at(start, "invalid player name"));
+            }
+            return Ok(EntitySelector::Player(token));
+        }
+        let target = match self.peek() {
+            Some('p') => SelectorTarget::NearestPlayer,
+            Some('r') => SelectorTarget::RandomPlayer,
+            Some('a') => SelectorTarget::AllPlayers,
+            Some('e') => SelectorTarget::AllEntities,
+            Some('s') => SelectorTarget::Executor,
+            _ => return Err(self.err("unknown selector type")),
+        };
+        self.pos += 1;
+        let mut filters = Vec::new();
+        if self.eat('[') {
+            while !self.eat(']') {
+                if !filters.is_empty() {
+                    self.expect(',', "expected ',' or ']'")?;
+                }
+                let key = self.word();
+                if key.is_empty() {
+                    return Err(self.err("expected a selector option"));
+                }
+                self.expect('=', "expected '='")?;
+                let value = self.balanced(|c| c == ',' || c == ']')?;
+                if self.at_end() {
+                    return Err(self.err("expected ']'"));
+                }
+                filters.push((key, value));
+            }
+        }
+        Ok(EntitySelector::Selector { target, filters })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_against_tree() {
+        let mut tree = CommandTree::new();
+        let tp = tree.literal(CommandTree::ROOT, "tp");
+        let target = tree.argument(
+            tp,
+            "target",
+            ArgumentParser::Entity {
+                single: true,
+                players_only: false,
+            },
+        );
+        let pos = tree.argument(target, "pos", ArgumentParser::Vec3);
+        tree.set_executable(pos);
+        let give = tree.literal(CommandTree::ROOT, "give");
+        let item = tree.argument(give, "item", ArgumentParser::ItemStack);
+        tree.set_executable(item);
+        let count = tree.argument(
+            item,
+            "count",
+            ArgumentParser::Integer {
+                min: Some(1),
+                max: Some(64),
+            },
+        );
+        tree.set_executable(count);
+        let alias = tree.literal(CommandTree::ROOT, "teleport");
+        tree.set_redirect(alias, tp);
+        let say = tree.literal(CommandTree::ROOT, "say");
+        let msg = tree.argument(say, "message", ArgumentParser::Message);
+        tree.set_executable(msg);
+
+        let cmd = tree
+            .parse("tp @e[type=pig,limit=1,nbt={a:[1,2]}] ~ ~1.5 -3")
+            .unwrap();
+        assert_eq!(cmd.node, pos);
+        let Some(Argument::Entity(selector)) = cmd.get("target") else {
+            panic!()
+        };
+        assert_eq!(selector.filter("nbt"), Some("{a:[1,2]}"));
+        assert!(!selector.players_only());
+        let Some(Argument::Coordinates(coords)) = cmd.get("pos") else {
+            panic!()
+        };
+        assert_eq!(
+            coords.resolve(Vec3::from([10.0, 64.0, 0.0]), 0.0, 0.0),
+            Vec3::from([10.0, 65.5, -3.0])
+        );
+        assert_eq!(tree.parse("teleport Notch 1 2 3").unwrap().node, pos);
+        assert_eq!(
+            tree.parse("tp @a 1 2 3").unwrap_err().msg,
+            "only one entity is allowed"
+        );
+
+        let cmd = tree
+            .parse("give minecraft:stone{display:{Name:'\"x y\"'}} 5")
+            .unwrap();
+        assert_eq!(cmd.node, count);
+        let Some(Argument::Item(item)) = cmd.get("item") else {
+            panic!()
+        };
+        assert_eq!(item.id, "minecraft:stone");
+        assert!(item.nbt.is_some());
+        assert_eq!(cmd.get("count"), Some(&Argument::Integer(5)));
+        let err = tree.parse("give stone 65").unwrap_err();
+        assert_eq!((err.offset, err.msg), (11, "number is too big"));
+        assert_eq!(tree.parse("give").unwrap_err().msg, "incomplete command");
+        assert_eq!(
+            tree.parse("say hello  there").unwrap().get("message"),
+            Some(&Argument::String(Cow::Borrowed("hello  there")))
+        );
+
+        let mut packet = Vec::new();
+        tree.write(&mut packet);
+        // node count, then the root: flags, 4 children
+        assert_eq!(packet[..3], [tree.nodes.len() as u8, 0x00, 4]);
+        assert_eq!(*packet.last().unwrap(), 0);
+
+        let mut r = Reader::new("#minecraft:logs[axis=y] 1..5 10s");
+        let block = r.block(true).unwrap();
+        assert!(block.tag);
+        assert_eq!(block.properties, [("axis", "y")]);
+        r.pos += 1;
+        assert_eq!(
+            r.range::<i32>().unwrap(),
+            NumberRange {
+                min: Some(1),
+                max: Some(5)
+            }
+        );
+        r.pos += 1;
+        assert_eq!(
+            parse_argument(&ArgumentParser::Time { min: 0 }, &mut r),
+            Ok(Argument::Time(200))
+        );
+    }
+}
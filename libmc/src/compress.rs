@@ -0,0 +1,433 @@
+//! gzip, which Minecraft compresses most of its NBT files (`level.dat`, `scoreboard.dat`, ...) with,
+//! and zlib, which region files' chunks usually are.
+//!
+//! Decompression supports all of DEFLATE. Compression only emits stored (uncompressed) blocks,
+//! which every reader accepts, but which don't make files any smaller.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DecompressError(pub(crate) &'static str);
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bad compressed data: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecompressError {}
+
+fn err<T>(msg: &'static str) -> Result<T, DecompressError> {
+    Err(DecompressError(msg))
+}
+
+// ----- checksums -----
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in data {
+        crc = CRC32_TABLE[((crc ^ u32::from(*b)) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // the sums can't overflow within a chunk this size
+    for chunk in data.chunks(5552) {
+        for x in chunk {
+            a += u32::from(*x);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// ----- DEFLATE -----
+
+/// DEFLATE with stored blocks only
+pub(crate) fn deflate_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 65535 * 5 + 5);
+    let mut chunks = data.chunks(0xFFFF).peekable();
+    if chunks.peek().is_none() {
+        // a single empty final block
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let is_final = chunks.peek().is_none();
+        out.push(is_final as u8);
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// in bits
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, DecompressError> {
+        let mut v = 0;
+        for i in 0..n {
+            let Some(byte) = self.data.get(self.pos / 8) else {
+                return err("unexpected end of data");
+            };
+            v |= u32::from((byte >> (self.pos % 8)) & 1) << i;
+            self.pos += 1;
+        }
+        Ok(v)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&[u8], DecompressError> {
+        let start = self.pos / 8;
+        let Some(bytes) = self.data.get(start..start + n) else {
+            return err("unexpected end of data");
+        };
+        self.pos += n * 8;
+        Ok(bytes)
+    }
+}
+
+/// A canonical Huffman code
+struct Huffman {
+    /// number of codes of each length
+    counts: [u16; 16],
+    /// symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, DecompressError> {
+        let mut counts = [0u16; 16];
+        for len in lengths {
+            counts[usize::from(*len)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (sym, len) in lengths.iter().enumerate() {
+            if *len != 0 {
+                symbols[usize::from(offsets[usize::from(*len)])] = sym as u16;
+                offsets[usize::from(*len)] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, br: &mut BitReader<'_>) -> Result<u16, DecompressError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= br.bits(1)? as i32;
+            let count = i32::from(self.counts[len]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        err("bad Huffman code")
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompresses raw DEFLATE data. Returns the data and how many bytes of `data` it took up.
+pub(crate) fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), DecompressError> {
+    inflate_max(data, usize::MAX)
+}
+
+/// Like `inflate()`, but fails instead of decompressing more than `max_len` bytes, for data
+/// from clients that could otherwise make a small packet take up a lot of memory
+pub(crate) fn inflate_max(
+    data: &[u8],
+    max_len: usize,
+) -> Result<(Vec<u8>, usize), DecompressError> {
+    let mut br = BitReader { data, pos: 0 };
+    let mut out = Vec::new();
+    loop {
+        let is_final = br.bits(1)? == 1;
+        match br.bits(2)? {
+            0 => {
+                br.align_to_byte();
+                let header = br.bytes(4)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let nlen = u16::from_le_bytes([header[2], header[3]]);
+                if len != !nlen {
+                    return err("stored block length mismatch");
+                }
+                if out.len() + usize::from(len) > max_len {
+                    return err("decompressed data too long");
+                }
+                out.extend_from_slice(br.bytes(len.into())?);
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let lit = Huffman::new(&lengths)?;
+                let dist = Huffman::new(&[5; 30])?;
+                inflate_block(&mut br, &mut out, max_len, &lit, &dist)?;
+            }
+            2 => {
+                let nlit = br.bits(5)? as usize + 257;
+                let ndist = br.bits(5)? as usize + 1;
+                let ncode = br.bits(4)? as usize + 4;
+                let mut code_lengths = [0u8; 19];
+                for i in CODE_LENGTH_ORDER.iter().take(ncode) {
+                    code_lengths[*i] = br.bits(3)? as u8;
+                }
+                let code = Huffman::new(&code_lengths)?;
+
+                let mut lengths = Vec::with_capacity(nlit + ndist);
+                while lengths.len() < nlit + ndist {
+                    let (len, repeat) = match code.decode(&mut br)? {
+                        sym @ 0..=15 => (sym as u8, 1),
+                        16 => {
+                            let Some(&prev) = lengths.last() else {
+                                return err("repeat with no previous length");
+                            };
+                            (prev, 3 + br.bits(2)?)
+                        }
+                        17 => (0, 3 + br.bits(3)?),
+                        _ => (0, 11 + br.bits(7)?),
+                    };
+                    lengths.extend(std::iter::repeat_n(len, repeat as usize));
+                }
+                if lengths.len() > nlit + ndist {
+                    return err("too many code lengths");
+                }
+                let lit = Huffman::new(&lengths[..nlit])?;
+                let dist = Huffman::new(&lengths[nlit..])?;
+                inflate_block(&mut br, &mut out, max_len, &lit, &dist)?;
+            }
+            _ => return err("bad block type"),
+        }
+        if is_final {
+            return Ok((out, br.pos.div_ceil(8)));
+        }
+    }
+}
+
+fn inflate_block(
+    br: &mut BitReader<'_>,
+    out: &mut Vec<u8>,
+    max_len: usize,
+    lit: &Huffman,
+    dist: &Huffman,
+) -> Result<(), DecompressError> {
+    loop {
+        let sym = lit.decode(br)?;
+        if sym != 256 && out.len() >= max_len {
+            return err("decompressed data too long");
+        }
+        match sym {
+            0..=255 => out.push(sym as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = usize::from(sym - 257);
+                if i >= LENGTH_BASE.len() {
+                    return err("bad length code");
+                }
+                let len = usize::from(LENGTH_BASE[i]) + br.bits(LENGTH_EXTRA[i].into())? as usize;
+                let d = usize::from(dist.decode(br)?);
+                if d >= DIST_BASE.len() {
+                    return err("bad distance code");
+                }
+                let distance = usize::from(DIST_BASE[d]) + br.bits(DIST_EXTRA[d].into())? as usize;
+                if distance > out.len() {
+                    return err("distance too far back");
+                }
+                if out.len() + len > max_len {
+                    return err("decompressed data too long");
+                }
+                let start = out.len() - distance;
+                // the copy can overlap what it's writing
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+// ----- containers -----
+
+pub(crate) fn gzip_compress(data: &[u8]) -> Vec<u8> {
+    // magic, deflate, no flags, no mtime, no extra flags, unknown OS
+    let mut out = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF];
+    out.extend(deflate_stored(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+pub(crate) fn gzip_decompress(data: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let [0x1F, 0x8B, 8, flags, _, _, _, _, _, _, rest @ ..] = data else {
+        return err("not gzip data");
+    };
+    let mut rest = rest;
+    let skip_string = |rest: &mut &[u8]| match rest.iter().position(|b| *b == 0) {
+        Some(end) => {
+            *rest = &rest[end + 1..];
+            Ok(())
+        }
+        None => err("unterminated gzip header string"),
+    };
+    // FEXTRA
+    if flags & 0x04 != 0 {
+        let [a, b, tail @ ..] = rest else {
+            return err("truncated gzip header");
+        };
+        let len = usize::from(u16::from_le_bytes([*a, *b]));
+        rest = tail
+            .get(len..)
+            .ok_or(DecompressError("truncated gzip header"))?;
+    }
+    // FNAME, FCOMMENT
+    if flags & 0x08 != 0 {
+        skip_string(&mut rest)?;
+    }
+    if flags & 0x10 != 0 {
+        skip_string(&mut rest)?;
+    }
+    // FHCRC
+    if flags & 0x02 != 0 {
+        rest = rest
+            .get(2..)
+            .ok_or(DecompressError("truncated gzip header"))?;
+    }
+
+    let (out, used) = inflate(rest)?;
+    let Some(trailer) = rest.get(used..used + 8) else {
+        return err("truncated gzip trailer");
+    };
+    if trailer[..4] != crc32(&out).to_le_bytes() {
+        return err("gzip checksum mismatch");
+    }
+    Ok(out)
+}
+
+pub(crate) fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32KiB window, fastest compression level
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate_stored(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub(crate) fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    zlib_decompress_max(data, usize::MAX)
+}
+
+/// Like `zlib_decompress()`, but with at most `max_len` bytes of decompressed data
+pub(crate) fn zlib_decompress_max(data: &[u8], max_len: usize) -> Result<Vec<u8>, DecompressError> {
+    let [cmf, flg, rest @ ..] = data else {
+        return err("truncated zlib header");
+    };
+    if cmf & 0x0F != 8 || (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 != 0 {
+        return err("not zlib data");
+    }
+    // FDICT
+    if flg & 0x20 != 0 {
+        return err("zlib preset dictionaries aren't supported");
+    }
+    let (out, used) = inflate_max(rest, max_len)?;
+    let Some(trailer) = rest.get(used..used + 4) else {
+        return err("truncated zlib trailer");
+    };
+    if trailer != adler32(&out).to_be_bytes() {
+        return err("zlib checksum mismatch");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(gzip_decompress(&gzip_compress(&data)).unwrap(), data);
+        assert_eq!(gzip_decompress(&gzip_compress(&[])).unwrap(), []);
+        assert_eq!(zlib_decompress(&zlib_compress(&data)).unwrap(), data);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn inflate_real_data() {
+        // `printf 'hello hello hello hello\n' | gzip -9n`: fixed Huffman codes with a back-reference
+        let gz = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+            0xc9, 0x57, 0xc8, 0x40, 0x27, 0xb9, 0x00, 0x00, 0x88, 0x59, 0x0b, 0x18, 0x00, 0x00,
+            0x00,
+        ];
+        assert_eq!(gzip_decompress(&gz).unwrap(), b"hello hello hello hello\n");
+
+        // the back-reference would go past the limit
+        assert!(inflate_max(&gz[10..], 23).is_err());
+        assert_eq!(inflate_max(&gz[10..], 24).unwrap().0.len(), 24);
+        let zlib = zlib_compress(&[7; 100]);
+        assert!(zlib_decompress_max(&zlib, 99).is_err());
+        assert_eq!(zlib_decompress_max(&zlib, 100).unwrap(), [7; 100]);
+    }
+}
@@ -0,0 +1,414 @@
+use crate::*;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+/// How `run_server()` runs a server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    /// Where to listen for clients. They're tagged `DEFAULT_LISTENER`.
+    pub addr: SocketAddr,
+    /// More addresses to listen on at the same time, e.g. an IPv6 one, or a port only a proxy can
+    /// reach. Clients are tagged with the tag next to the address they connected to.
+    pub extra_listeners: Vec<(SocketAddr, String)>,
+    /// Where to listen for clients that connect over WebSocket, if anywhere. They're tagged
+    /// `WEBSOCKET_LISTENER`.
+    pub websocket_addr: Option<SocketAddr>,
+    /// Where to listen for Bedrock clients (over RakNet, on UDP), if anywhere. They're tagged
+    /// `BEDROCK_LISTENER`. Experimental: see `BedrockFrontend`.
+    pub bedrock_addr: Option<SocketAddr>,
+    /// Shown in the default status, and logins beyond it are rejected as `LoginRejection::ServerFull`
+    pub max_players: usize,
+    /// Shown in the default status
+    pub motd: TextComponent,
+    /// The names of the only players allowed to log in (in any case), if not everyone
+    pub whitelist: Option<Vec<String>>,
+    /// Sent to clients when they join, in chunks
+    pub view_distance: i32,
+    /// Sent to clients when they join, in chunks
+    pub simulation_distance: i32,
+    /// Clients that send nothing for this long are disconnected
+    pub read_timeout: Duration,
+    /// Clients that stop reading are disconnected once a write has been stuck this long
+    pub write_timeout: Duration,
+    /// Players that haven't done anything (see `IdleTracker`) for this long are kicked, like
+    /// vanilla's `player-idle-timeout`. None never kicks them.
+    pub player_idle_timeout: Option<Duration>,
+    /// How many threads encode chunks for `ServerHandle::send_chunk_async()`
+    pub chunk_encode_threads: usize,
+    /// The difficulty the server starts with. See `ServerHandle::set_difficulty()`.
+    pub difficulty: Difficulty,
+    /// Whether players' Change/Lock Difficulty packets change the difficulty, like in singleplayer.
+    /// Vanilla servers ignore them.
+    pub client_difficulty: bool,
+    /// Sent to clients when they join, on `minecraft:brand`. Shown on their F3 screen.
+    pub brand: String,
+    /// How TCP clients are read from and written to. WebSocket clients always get threads.
+    pub backend: NetBackend,
+    /// Whether players' latencies are sent to everyone's tab list every so often. Turn it off if
+    /// players aren't added to the tab list, or clients log a warning for each update.
+    pub tab_list_latency: bool,
+    /// What's done about clients that fall behind on what's sent to them
+    pub backpressure: BackpressurePolicy,
+    /// Bytes per second each client gets before chunk and entity packets are held back (see
+    /// `BandwidthTracker`), unless changed with `ServerHandle::set_bandwidth_cap()`. None for no cap.
+    pub bandwidth_cap: Option<u64>,
+    /// Whether SIGHUP reloads the config (see `Server::reload_config()`), on Unix
+    pub reload_on_sighup: bool,
+    /// Packets at least this many bytes long are compressed, like vanilla's
+    /// `network-compression-threshold`. None doesn't compress. libmc's compressor only stores the
+    /// data, so this makes packets slightly bigger; it's for proxies and clients that want it on.
+    /// Changes apply to players that log in after them.
+    pub compression_threshold: Option<usize>,
+    /// Whether players are authenticated with Mojang, like vanilla's `online-mode`. libmc has no
+    /// encryption, so running a server with it on fails.
+    pub online_mode: bool,
+}
+
+/// The listener tag of clients that connected to `ServerConfig::addr`
+pub const DEFAULT_LISTENER: &str = "default";
+/// The listener tag of clients that connected over WebSocket
+pub const WEBSOCKET_LISTENER: &str = "websocket";
+/// The listener tag of Bedrock clients
+pub const BEDROCK_LISTENER: &str = "bedrock";
+
+/// How a server does its networking
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum NetBackend {
+    /// A thread reading from and a thread writing to each client
+    #[default]
+    Threads,
+    /// One thread, waiting on every client at once with mio (the `mio` feature)
+    #[cfg(feature = "mio")]
+    EventLoop,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 25565),
+            extra_listeners: Vec::new(),
+            websocket_addr: None,
+            bedrock_addr: None,
+            max_players: 20,
+            motd: TextComponent::text("A Minecraft Server"),
+            whitelist: None,
+            view_distance: 10,
+            simulation_distance: 10,
+            read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            player_idle_timeout: None,
+            chunk_encode_threads: ChunkEncodePool::default_threads(),
+            difficulty: Difficulty::Easy,
+            client_difficulty: false,
+            brand: "libmc".to_owned(),
+            backend: NetBackend::default(),
+            tab_list_latency: true,
+            backpressure: BackpressurePolicy::default(),
+            bandwidth_cap: None,
+            reload_on_sighup: false,
+            compression_threshold: None,
+            online_mode: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn is_whitelisted(&self, name: &str) -> bool {
+        self.whitelist
+            .as_ref()
+            .is_none_or(|names| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+    }
+
+    /// `new` with the settings that can't change without a restart put back to ours, and the
+    /// names of those that were different
+    pub(crate) fn reloaded(&self, mut new: ServerConfig) -> (ServerConfig, Vec<&'static str>) {
+        let mut kept = Vec::new();
+        macro_rules! keep {
+            ($($field:ident),*) => {$(
+                if new.$field != self.$field {
+                    new.$field = self.$field.clone();
+                    kept.push(stringify!($field));
+                }
+            )*};
+        }
+        keep!(
+            addr,
+            extra_listeners,
+            websocket_addr,
+            bedrock_addr,
+            chunk_encode_threads,
+            backend,
+            reload_on_sighup,
+            online_mode
+        );
+        (new, kept)
+    }
+}
+
+/// Configures and runs a server, e.g. `ServerBuilder::new().port(25566).run(server)`.
+///
+/// Online mode isn't supported (libmc has no encryption), so `run()` fails if it's turned on.
+#[derive(Debug, Clone, Default)]
+pub struct ServerBuilder {
+    config: ServerConfig,
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(config: ServerConfig) -> Self {
+        Self { config }
+    }
+
+    /// The address to listen on, e.g. `0.0.0.0` to accept clients from other machines
+    pub fn ip(mut self, ip: IpAddr) -> Self {
+        self.config.addr.set_ip(ip);
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.addr.set_port(port);
+        self
+    }
+
+    /// Also accepts clients on `addr`, tagging them with `tag` (see `ClientSession::listener`)
+    pub fn listen(mut self, addr: SocketAddr, tag: impl Into<String>) -> Self {
+        self.config.extra_listeners.push((addr, tag.into()));
+        self
+    }
+
+    /// Also accepts clients over WebSocket on `addr`, e.g. from a browser. They send and receive
+    /// the same packet frames as other clients, in binary messages.
+    pub fn websocket(mut self, addr: SocketAddr) -> Self {
+        self.config.websocket_addr = Some(addr);
+        self
+    }
+
+    /// Also accepts Bedrock edition clients on `addr` (UDP; Bedrock's default port is 19132).
+    /// What they're sent is translated by a `BedrockTranslator`, using `Server::bedrock_blocks()`.
+    pub fn bedrock(mut self, addr: SocketAddr) -> Self {
+        self.config.bedrock_addr = Some(addr);
+        self
+    }
+
+    pub fn max_players(mut self, max_players: usize) -> Self {
+        self.config.max_players = max_players;
+        self
+    }
+
+    pub fn motd(mut self, motd: TextComponent) -> Self {
+        self.config.motd = motd;
+        self
+    }
+
+    /// Only lets the players named in `names` log in
+    pub fn whitelist(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.config.whitelist = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn view_distance(mut self, view_distance: i32) -> Self {
+        self.config.view_distance = view_distance;
+        self
+    }
+
+    pub fn simulation_distance(mut self, simulation_distance: i32) -> Self {
+        self.config.simulation_distance = simulation_distance;
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = timeout;
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.write_timeout = timeout;
+        self
+    }
+
+    pub fn player_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.player_idle_timeout = timeout;
+        self
+    }
+
+    /// Applies the settings in a vanilla `server.properties` that there are config settings for:
+    /// `server-ip`, `server-port`, `max-players`, `motd`, `view-distance`, `simulation-distance`,
+    /// `player-idle-timeout` (in minutes, 0 for none), `network-compression-threshold` (negative
+    /// for none) and `online-mode`. Missing ones are left as they are.
+    pub fn properties(mut self, props: &ServerProperties) -> Self {
+        let config = &mut self.config;
+        if let Some(Ok(ip)) = props
+            .get("server-ip")
+            .filter(|ip| !ip.is_empty())
+            .map(str::parse)
+        {
+            config.addr.set_ip(ip);
+        }
+        config
+            .addr
+            .set_port(props.get_or("server-port", config.addr.port()));
+        config.max_players = props.get_or("max-players", config.max_players);
+        if let Some(motd) = props.get("motd") {
+            config.motd = TextComponent::text(motd);
+        }
+        config.view_distance = props.get_or("view-distance", config.view_distance);
+        config.simulation_distance =
+            props.get_or("simulation-distance", config.simulation_distance);
+        if let Some(minutes) = props
+            .get("player-idle-timeout")
+            .and_then(|m| m.parse::<u64>().ok())
+        {
+            config.player_idle_timeout = (minutes > 0).then(|| Duration::from_secs(minutes * 60));
+        }
+        if let Some(Ok(threshold)) = props
+            .get("network-compression-threshold")
+            .map(str::parse::<i64>)
+        {
+            config.compression_threshold = usize::try_from(threshold).ok();
+        }
+        config.online_mode = props.get_or("online-mode", config.online_mode);
+        self
+    }
+
+    pub fn chunk_encode_threads(mut self, threads: usize) -> Self {
+        self.config.chunk_encode_threads = threads;
+        self
+    }
+
+    pub fn difficulty(mut self, difficulty: Difficulty) -> Self {
+        self.config.difficulty = difficulty;
+        self
+    }
+
+    pub fn client_difficulty(mut self, allowed: bool) -> Self {
+        self.config.client_difficulty = allowed;
+        self
+    }
+
+    pub fn brand(mut self, brand: impl Into<String>) -> Self {
+        self.config.brand = brand.into();
+        self
+    }
+
+    pub fn backend(mut self, backend: NetBackend) -> Self {
+        self.config.backend = backend;
+        self
+    }
+
+    pub fn tab_list_latency(mut self, enabled: bool) -> Self {
+        self.config.tab_list_latency = enabled;
+        self
+    }
+
+    pub fn backpressure(mut self, policy: BackpressurePolicy) -> Self {
+        self.config.backpressure = policy;
+        self
+    }
+
+    /// Caps how many bytes per second each client is sent (see `ServerConfig::bandwidth_cap`)
+    pub fn bandwidth_cap(mut self, cap: Option<u64>) -> Self {
+        self.config.bandwidth_cap = cap;
+        self
+    }
+
+    pub fn reload_on_sighup(mut self, enabled: bool) -> Self {
+        self.config.reload_on_sighup = enabled;
+        self
+    }
+
+    /// Compresses packets at least `threshold` bytes long (see `ServerConfig::compression_threshold`)
+    pub fn compression_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.config.compression_threshold = threshold;
+        self
+    }
+
+    /// Not supported: `run()` fails if it's on
+    pub fn online_mode(mut self, enabled: bool) -> Self {
+        self.config.online_mode = enabled;
+        self
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Runs `server` until it shuts down. Fails if the address can't be listened on.
+    pub fn run<S: Server>(self, server: S) -> io::Result<()> {
+        run_server_with(self.config, server)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_keeps_restart_only_settings() {
+        let old = ServerBuilder::new().whitelist(["Notch"]).config().clone();
+        assert!(old.is_whitelisted("notch"));
+        assert!(!old.is_whitelisted("jeb_"));
+
+        let new = ServerBuilder::new()
+            .port(25566)
+            .max_players(5)
+            .motd(TextComponent::text("Reloaded"))
+            .config()
+            .clone();
+        let (config, kept) = old.reloaded(new);
+        assert_eq!(kept, ["addr"]);
+        assert_eq!(config.addr, old.addr);
+        assert_eq!(config.max_players, 5);
+        assert_eq!(config.motd.text, "Reloaded");
+        assert!(config.is_whitelisted("jeb_"));
+    }
+
+    #[test]
+    fn from_properties() {
+        let props = ServerProperties::parse(
+            "server-ip=\nserver-port=25570\nmotd=Hello\nview-distance=abc\nplayer-idle-timeout=5\n",
+        );
+        let config = ServerBuilder::new().properties(&props).config().clone();
+        assert_eq!(config.addr.port(), 25570);
+        assert_eq!(config.addr.ip(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(config.motd.text, "Hello");
+        assert_eq!(config.view_distance, 10);
+        assert_eq!(config.player_idle_timeout, Some(Duration::from_secs(300)));
+
+        assert_eq!(config.compression_threshold, None);
+        assert!(!config.online_mode);
+
+        let props = ServerProperties::parse(
+            "player-idle-timeout=0\nnetwork-compression-threshold=256\nonline-mode=true",
+        );
+        let config = ServerBuilder::from_config(config)
+            .properties(&props)
+            .config()
+            .clone();
+        assert_eq!(config.player_idle_timeout, None);
+        assert_eq!(config.compression_threshold, Some(256));
+        assert!(config.online_mode);
+        let props = ServerProperties::parse("network-compression-threshold=-1");
+        let builder = ServerBuilder::from_config(config).properties(&props);
+        assert_eq!(builder.config().compression_threshold, None);
+
+        // rejected before anything is listened on
+        let err = builder.port(0).run(NoServer).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    struct NoServer;
+
+    impl Server for NoServer {
+        fn on_connect(&mut self, _cid: ClientID) {}
+
+        fn on_disconnect(&mut self, _cid: ClientID, _cause: DisconnectCause) {}
+
+        fn handle_packet(&mut self, _cid: ClientID, _packet: InPacket) {}
+
+        fn tick(&mut self, _tick: u64) {}
+    }
+}
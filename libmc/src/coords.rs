@@ -0,0 +1,450 @@
+use crate::*;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A point in the world, e.g. an entity's position
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vec3 {
+    pub const ZERO: Self = Self::new(0.0, 0.0, 0.0);
+
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn length(self) -> f64 {
+        self.length_squared().sqrt()
+    }
+
+    pub fn length_squared(self) -> f64 {
+        self.x * self.x + self.y * self.y + self.z * self.z
+    }
+
+    /// The same direction with length 1, or zero for zero
+    pub fn normalize(self) -> Self {
+        let len = self.length();
+        if len == 0.0 {
+            Self::ZERO
+        } else {
+            self * (1.0 / len)
+        }
+    }
+
+    pub fn dot(self, other: Self) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn distance(self, other: Self) -> f64 {
+        (self - other).length()
+    }
+
+    pub fn distance_squared(self, other: Self) -> f64 {
+        (self - other).length_squared()
+    }
+
+    /// Only along x and z, like vanilla's checks for how far away things are horizontally
+    pub fn horizontal_distance(self, other: Self) -> f64 {
+        (self.x - other.x).hypot(self.z - other.z)
+    }
+
+    /// The block the point is in
+    pub fn block(self) -> BlockPos {
+        BlockPos::new(
+            self.x.floor() as i32,
+            self.y.floor() as i32,
+            self.z.floor() as i32,
+        )
+    }
+
+    /// The chunk the point is in
+    pub fn chunk(self) -> ChunkPos {
+        self.block().chunk()
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<f64> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self {
+        Self::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl From<[f64; 3]> for Vec3 {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+impl From<Vec3> for [f64; 3] {
+    fn from(v: Vec3) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+/// One of the six directions along the axes. `id()` is the face number the protocol uses (e.g. in
+/// `InPacket::PlayerAction` and `InPacket::UseItemOn`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Down,
+    Up,
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Direction {
+    pub const ALL: [Self; 6] = [
+        Direction::Down,
+        Direction::Up,
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+
+    pub fn from_id(id: i8) -> Option<Self> {
+        usize::try_from(id)
+            .ok()
+            .and_then(|i| Self::ALL.get(i))
+            .copied()
+    }
+
+    pub fn id(self) -> i8 {
+        self as i8
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Down => Direction::Up,
+            Direction::Up => Direction::Down,
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+        }
+    }
+
+    /// One block in this direction
+    pub fn offset(self) -> BlockPos {
+        match self {
+            Direction::Down => BlockPos::new(0, -1, 0),
+            Direction::Up => BlockPos::new(0, 1, 0),
+            Direction::North => BlockPos::new(0, 0, -1),
+            Direction::South => BlockPos::new(0, 0, 1),
+            Direction::West => BlockPos::new(-1, 0, 0),
+            Direction::East => BlockPos::new(1, 0, 0),
+        }
+    }
+}
+
+/// A block's position. Unlike `Position`, this can be anywhere, not just where the protocol can
+/// send.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn offset(self, dx: i32, dy: i32, dz: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    /// The block `distance` blocks away towards `direction`
+    pub fn relative(self, direction: Direction, distance: i32) -> Self {
+        let o = direction.offset();
+        self.offset(o.x * distance, o.y * distance, o.z * distance)
+    }
+
+    pub fn chunk(self) -> ChunkPos {
+        ChunkPos::new(self.x >> 4, self.z >> 4)
+    }
+
+    pub fn section(self) -> SectionPos {
+        SectionPos::new(self.x >> 4, self.y >> 4, self.z >> 4)
+    }
+
+    /// The block's corner with the lowest coordinates
+    pub fn corner(self) -> Vec3 {
+        Vec3::new(self.x.into(), self.y.into(), self.z.into())
+    }
+
+    /// The middle of the block
+    pub fn center(self) -> Vec3 {
+        self.corner() + Vec3::new(0.5, 0.5, 0.5)
+    }
+
+    pub fn distance_squared(self, other: Self) -> i64 {
+        let d = |a: i32, b: i32| i64::from(a - b).pow(2);
+        d(self.x, other.x) + d(self.y, other.y) + d(self.z, other.z)
+    }
+
+    /// Blocks to walk to `other` along the axes
+    pub fn manhattan_distance(self, other: Self) -> u32 {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y) + self.z.abs_diff(other.z)
+    }
+}
+
+impl From<Position> for BlockPos {
+    fn from(pos: Position) -> Self {
+        Self::new(pos.x, pos.y.into(), pos.z)
+    }
+}
+
+impl TryFrom<BlockPos> for Position {
+    type Error = PositionOutOfRange;
+
+    fn try_from(pos: BlockPos) -> Result<Self, PositionOutOfRange> {
+        Position::new(pos.x, pos.y, pos.z)
+    }
+}
+
+impl From<[i32; 3]> for BlockPos {
+    fn from([x, y, z]: [i32; 3]) -> Self {
+        Self::new(x, y, z)
+    }
+}
+
+/// A chunk's coordinates (block coordinates divided by 16)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+
+    pub fn offset(self, dx: i32, dz: i32) -> Self {
+        Self::new(self.x + dx, self.z + dz)
+    }
+
+    /// The chunk's block with the lowest coordinates, at y = `y`
+    pub fn min_block(self, y: i32) -> BlockPos {
+        BlockPos::new(self.x << 4, y, self.z << 4)
+    }
+
+    /// The chunks away `other` is along whichever axis it's further on, which is how view
+    /// and simulation distances are measured
+    pub fn chebyshev_distance(self, other: Self) -> u32 {
+        self.x.abs_diff(other.x).max(self.z.abs_diff(other.z))
+    }
+
+    pub fn distance_squared(self, other: Self) -> i64 {
+        i64::from(self.x - other.x).pow(2) + i64::from(self.z - other.z).pow(2)
+    }
+
+    /// Every chunk within `radius` (chebyshev distance) of this one, x-major
+    pub fn square(self, radius: i32) -> impl Iterator<Item = ChunkPos> {
+        let Self { x: cx, z: cz } = self;
+        (cx - radius..=cx + radius)
+            .flat_map(move |x| (cz - radius..=cz + radius).map(move |z| ChunkPos::new(x, z)))
+    }
+
+    /// The region file the chunk is stored in
+    pub fn region(self) -> RegionPos {
+        RegionPos {
+            x: self.x >> 5,
+            z: self.z >> 5,
+        }
+    }
+
+    /// The chunk's place in its region file's header (0 to 1023)
+    pub fn region_index(self) -> usize {
+        ((self.x & 31) + (self.z & 31) * 32) as usize
+    }
+}
+
+/// A chunk section's coordinates: a 16x16x16 cube of blocks, the unit chunk data is sent in
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct SectionPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl SectionPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn chunk(self) -> ChunkPos {
+        ChunkPos::new(self.x, self.z)
+    }
+
+    pub fn relative(self, direction: Direction) -> Self {
+        let o = direction.offset();
+        Self::new(self.x + o.x, self.y + o.y, self.z + o.z)
+    }
+
+    /// The section's block with the lowest coordinates
+    pub fn min_block(self) -> BlockPos {
+        BlockPos::new(self.x << 4, self.y << 4, self.z << 4)
+    }
+
+    /// Whether `pos` is in this section
+    pub fn contains(self, pos: BlockPos) -> bool {
+        pos.section() == self
+    }
+
+    /// The index of `pos` within its section's blocks, as in a chunk section's paletted container
+    /// (x first, then z, then y)
+    pub fn block_index(pos: BlockPos) -> usize {
+        ((pos.y & 15) << 8 | (pos.z & 15) << 4 | (pos.x & 15)) as usize
+    }
+
+    /// The section as one long, as in Update Section Blocks: 22 bits of x, 22 of z and 20 of y
+    pub fn pack(self) -> i64 {
+        (i64::from(self.x) & 0x3F_FFFF) << 42
+            | (i64::from(self.z) & 0x3F_FFFF) << 20
+            | (i64::from(self.y) & 0xF_FFFF)
+    }
+
+    pub fn unpack(packed: i64) -> Self {
+        // shifting left first, so the right shifts sign-extend
+        Self::new(
+            (packed >> 42) as i32,
+            (packed << 44 >> 44) as i32,
+            (packed << 22 >> 42) as i32,
+        )
+    }
+
+    /// A block change as Update Section Blocks sends it: the block state, then the block's x, z
+    /// and y within its section
+    pub fn pack_block(pos: BlockPos, state: i32) -> i64 {
+        i64::from(state) << 12 | i64::from((pos.x & 15) << 8 | (pos.z & 15) << 4 | (pos.y & 15))
+    }
+
+    /// The block position (in this section) and state of a `pack_block()`ed change
+    pub fn unpack_block(self, packed: i64) -> (BlockPos, i32) {
+        let local = |shift: i64| ((packed >> shift) & 15) as i32;
+        let pos = self.min_block().offset(local(8), local(0), local(4));
+        (pos, (packed >> 12) as i32)
+    }
+}
+
+/// A region's coordinates: each region file has 32x32 chunks
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct RegionPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl RegionPos {
+    /// The region's file in a dimension's `region/` directory
+    pub fn file_name(self) -> String {
+        format!("r.{}.{}.mca", self.x, self.z)
+    }
+
+    /// The region's chunk with the lowest coordinates
+    pub fn min_chunk(self) -> ChunkPos {
+        ChunkPos::new(self.x << 5, self.z << 5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let v = Vec3::new(-0.5, 64.2, 31.9);
+        assert_eq!(v.block(), BlockPos::new(-1, 64, 31));
+        assert_eq!(v.chunk(), ChunkPos::new(-1, 1));
+        assert_eq!(v.block().center(), Vec3::new(-0.5, 64.5, 31.5));
+        assert_eq!((Vec3::new(3.0, 0.0, 4.0)).normalize().length(), 1.0);
+        assert_eq!(
+            Vec3::ZERO.horizontal_distance(Vec3::new(3.0, 9.0, 4.0)),
+            5.0
+        );
+
+        let pos = BlockPos::new(10, 64, -3);
+        assert_eq!(pos.relative(Direction::West, 2), BlockPos::new(8, 64, -3));
+        assert_eq!(
+            Direction::from_id(3).map(Direction::opposite),
+            Some(Direction::North)
+        );
+        assert_eq!(pos.manhattan_distance(BlockPos::new(9, 66, -3)), 3);
+        assert_eq!(Position::try_from(pos), Position::new(10, 64, -3));
+        assert!(Position::try_from(BlockPos::new(0, 5000, 0)).is_err());
+
+        let chunk = ChunkPos::new(-33, 40);
+        assert_eq!(chunk.min_block(0), BlockPos::new(-528, 0, 640));
+        assert_eq!(chunk.region(), RegionPos { x: -2, z: 1 });
+        assert_eq!(chunk.region().file_name(), "r.-2.1.mca");
+        assert_eq!(chunk.region_index(), 31 + 8 * 32);
+        assert_eq!(chunk.region().min_chunk(), ChunkPos::new(-64, 32));
+        assert_eq!(chunk.chebyshev_distance(ChunkPos::new(-30, 38)), 3);
+        assert_eq!(chunk.square(1).count(), 9);
+    }
+
+    #[test]
+    fn section_packing() {
+        let pos = BlockPos::new(-1, -64, 35);
+        let section = pos.section();
+        assert_eq!(section, SectionPos::new(-1, -4, 2));
+        assert_eq!(section.min_block(), BlockPos::new(-16, -64, 32));
+        assert_eq!(SectionPos::block_index(pos), 15 + 3 * 16);
+        assert_eq!(SectionPos::unpack(section.pack()), section);
+        let far = SectionPos::new(-(1 << 21), (1 << 19) - 1, (1 << 21) - 1);
+        assert_eq!(SectionPos::unpack(far.pack()), far);
+
+        let packed = SectionPos::pack_block(pos, 9);
+        assert_eq!(packed, 9 << 12 | 15 << 8 | 3 << 4);
+        assert_eq!(section.unpack_block(packed), (pos, 9));
+        assert_eq!(
+            section.relative(Direction::Down).min_block().y,
+            section.min_block().y - 16
+        );
+
+        let buf = encode_packet(
+            OutPacket::UpdateSectionBlocks {
+                section,
+                blocks: &[(pos, 1)],
+            },
+            PROTOCOL_VERSION,
+        );
+        // ID, the section, one change
+        assert_eq!(buf[0], 0x45);
+        assert_eq!(buf[1..9], section.pack().to_be_bytes());
+        assert_eq!(buf[9], 1);
+        assert_eq!(buf.len(), 12);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::*;
+
+/// Where a player respawns: the Respawn packet's fields, other than the death location
+#[derive(Debug, Clone)]
+pub struct RespawnInfo<'a> {
+    pub dimension_type: &'a str,
+    pub dimension_name: &'a str,
+    pub hashed_seed: i64,
+    pub game_mode: GameMode,
+    pub prev_game_mode: Option<GameMode>,
+    pub is_debug: bool,
+    pub is_superflat: bool,
+    pub portal_cooldown: i32,
+}
+
+/// Takes a player through dying and respawning the way the vanilla client expects: Player Combat
+/// Kill (which opens the death screen) and zero health, then once the player clicks Respawn (a
+/// Client Status), a Respawn packet and full health.
+///
+/// The last death location is remembered, for the compass and Login (play).
+#[derive(Debug, Clone)]
+pub struct DeathSequence {
+    entity_id: i32,
+    dead: bool,
+    /// (dimension, location)
+    last_death: Option<(String, Position)>,
+    keep_inventory: bool,
+    immediate_respawn: bool,
+}
+
+impl DeathSequence {
+    /// For the player whose entity ID is `entity_id`
+    pub fn new(entity_id: i32) -> Self {
+        Self {
+            entity_id,
+            dead: false,
+            last_death: None,
+            keep_inventory: false,
+            immediate_respawn: false,
+        }
+    }
+
+    /// Follows the `keepInventory` and `doImmediateRespawn` game rules. Returns the packet that
+    /// tells the client whether to skip the death screen, if that changed; call it again when
+    /// the rules change.
+    pub fn apply_game_rules(&mut self, rules: &GameRules) -> Option<OutPacket<'static>> {
+        self.keep_inventory = rules.keep_inventory();
+        let immediate = rules.do_immediate_respawn();
+        if immediate == self.immediate_respawn {
+            return None;
+        }
+        self.immediate_respawn = immediate;
+        Some(OutPacket::GameEvent {
+            event: 11,
+            value: if immediate { 1.0 } else { 0.0 },
+        })
+    }
+
+    /// Whether the player keeps its items and experience when it dies
+    pub fn keeps_inventory(&self) -> bool {
+        self.keep_inventory
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.dead
+    }
+
+    /// Where the player last died, for Login (play) and Respawn
+    pub fn death_info(&self) -> Option<DeathInfo<'_>> {
+        let (dimension, location) = self.last_death.as_ref()?;
+        Some(DeathInfo {
+            dimension,
+            location: *location,
+        })
+    }
+
+    /// Kills the player, showing `message` on the death screen. Returns the packets to send,
+    /// which are none if it's already dead.
+    pub fn die<'a>(
+        &mut self,
+        message: &'a TextComponent,
+        dimension: &str,
+        location: Position,
+    ) -> Vec<OutPacket<'a>> {
+        if self.dead {
+            return Vec::new();
+        }
+        self.dead = true;
+        self.last_death = Some((dimension.to_owned(), location));
+        vec![
+            OutPacket::SetHealth {
+                health: 0.0,
+                food: MAX_FOOD,
+                saturation: 5.0,
+            },
+            OutPacket::CombatDeath {
+                player_id: self.entity_id,
+                message,
+            },
+        ]
+    }
+
+    /// Whether `packet` is the dead player clicking Respawn, in which case `respawn()` should be
+    /// called (possibly after deciding where they respawn)
+    pub fn wants_respawn(&self, packet: &InPacket) -> bool {
+        self.dead
+            && matches!(
+                packet,
+                InPacket::ClientStatus {
+                    action: ClientStatusAction::PerformRespawn
+                }
+            )
+    }
+
+    /// Respawns the player. Returns the packets to send, which are none if it isn't dead.
+    /// The player also needs to be teleported to where it respawns.
+    pub fn respawn<'a>(&'a mut self, info: RespawnInfo<'a>) -> Vec<OutPacket<'a>> {
+        if !self.dead {
+            return Vec::new();
+        }
+        self.dead = false;
+        vec![
+            OutPacket::Respawn {
+                dimension_type: info.dimension_type,
+                dimension_name: info.dimension_name,
+                hashed_seed: info.hashed_seed,
+                game_mode: info.game_mode,
+                prev_game_mode: info.prev_game_mode,
+                is_debug: info.is_debug,
+                is_superflat: info.is_superflat,
+                death_info: self.death_info(),
+                portal_cooldown: info.portal_cooldown,
+                data_kept: 0,
+            },
+            OutPacket::SetHealth {
+                health: MAX_HEALTH,
+                food: MAX_FOOD,
+                saturation: 5.0,
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn die_and_respawn() {
+        let mut death = DeathSequence::new(5);
+        let mut rules = GameRules::new();
+        assert!(death.apply_game_rules(&rules).is_none());
+        rules.set_str("doImmediateRespawn", "true").unwrap();
+        rules.set_str("keepInventory", "true").unwrap();
+        assert!(matches!(
+            death.apply_game_rules(&rules),
+            Some(OutPacket::GameEvent {
+                event: 11,
+                value: 1.0
+            })
+        ));
+        assert!(death.keeps_inventory());
+        let message = TextComponent::text("Steve fell out of the world");
+        let location = Position { x: 1, y: -70, z: 2 };
+        let packets = death.die(&message, "minecraft:overworld", location);
+        assert!(matches!(
+            packets[..],
+            [
+                OutPacket::SetHealth { health: 0.0, .. },
+                OutPacket::CombatDeath { player_id: 5, .. }
+            ]
+        ));
+        assert!(death
+            .die(&message, "minecraft:overworld", location)
+            .is_empty());
+
+        let respawn = decode_packet(ProtocolState::Play, &[0x08, 0x00]).unwrap();
+        assert!(death.wants_respawn(&respawn));
+        let stats = decode_packet(ProtocolState::Play, &[0x08, 0x01]).unwrap();
+        assert!(!death.wants_respawn(&stats));
+
+        let packets = death.respawn(RespawnInfo {
+            dimension_type: "minecraft:overworld",
+            dimension_name: "minecraft:overworld",
+            hashed_seed: 0,
+            game_mode: GameMode::Survival,
+            prev_game_mode: None,
+            is_debug: false,
+            is_superflat: false,
+            portal_cooldown: 0,
+        });
+        let OutPacket::Respawn {
+            death_info: Some(info),
+            ..
+        } = &packets[0]
+        else {
+            panic!("no death location");
+        };
+        assert_eq!(info.location, location);
+        assert_eq!(encode_packet(packets[0].clone(), PROTOCOL_VERSION)[0], 0x43);
+        assert!(!death.is_dead());
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// How many samples are kept, which is as many as a 1.20.5+ client's tick chart shows
+const SAMPLE_HISTORY: usize = 240;
+
+/// Where the time of one tick went
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TickSample {
+    /// From the end of the previous tick to the end of this one
+    pub full: Duration,
+    /// Spent in `Server::tick()`
+    pub tick: Duration,
+    /// Spent handling packets and connections
+    pub tasks: Duration,
+    /// Spent waiting for something to do
+    pub idle: Duration,
+}
+
+impl TickSample {
+    /// In the format of a 1.20.5+ Debug Sample packet: nanoseconds of full, tick, tasks, idle
+    pub fn to_longs(self) -> [i64; 4] {
+        [self.full, self.tick, self.tasks, self.idle].map(|d| d.as_nanos() as i64)
+    }
+}
+
+/// Records recent `TickSample`s
+#[derive(Debug, Default)]
+pub struct TickSampler {
+    samples: VecDeque<TickSample>,
+}
+
+impl TickSampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, sample: TickSample) {
+        if self.samples.len() == SAMPLE_HISTORY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn latest(&self) -> Option<TickSample> {
+        self.samples.back().copied()
+    }
+
+    /// Oldest first
+    pub fn samples(&self) -> impl Iterator<Item = TickSample> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// Average milliseconds per tick spent not idling
+    pub fn mspt(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let busy: Duration = self.samples.iter().map(|s| s.full - s.idle).sum();
+        busy.as_secs_f64() * 1000.0 / self.samples.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_samples() {
+        let sample = TickSample {
+            full: Duration::from_millis(50),
+            tick: Duration::from_millis(3),
+            tasks: Duration::from_millis(1),
+            idle: Duration::from_millis(46),
+        };
+        let mut sampler = TickSampler::new();
+        sampler.record(sample);
+        assert_eq!(sampler.mspt(), 4.0);
+        assert_eq!(sampler.latest(), Some(sample));
+        assert_eq!(sample.to_longs()[0], 50_000_000);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::capture::packet_id;
+use crate::*;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The upper bounds of `TickHistogram`'s buckets; the last bucket has none
+const TICK_BUCKETS: [Duration; 5] = [
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+];
+
+/// How many packets of each ID went each way on a connection, since it connected
+#[derive(Debug, Clone, Default)]
+pub struct PacketCounters {
+    counts: HashMap<(PacketDirection, ProtocolState, i32), u64>,
+}
+
+impl PacketCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts an unframed packet, in our protocol version
+    pub fn record(&mut self, dir: PacketDirection, state: ProtocolState, packet: &[u8]) {
+        if let Some(id) = packet_id(packet) {
+            *self.counts.entry((dir, state, id)).or_default() += 1;
+        }
+    }
+
+    pub fn get(&self, dir: PacketDirection, state: ProtocolState, id: i32) -> u64 {
+        self.counts.get(&(dir, state, id)).copied().unwrap_or(0)
+    }
+
+    /// Every packet ID seen, most sent first
+    pub fn iter(&self) -> Vec<(PacketDirection, ProtocolState, i32, u64)> {
+        let mut counts: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(&(dir, state, id), &n)| (dir, state, id, n))
+            .collect();
+        counts.sort_by_key(|&(dir, _, id, n)| {
+            (
+                std::cmp::Reverse(n),
+                dir == PacketDirection::Clientbound,
+                id,
+            )
+        });
+        counts
+    }
+
+    pub fn total(&self, dir: PacketDirection) -> u64 {
+        self.counts
+            .iter()
+            .filter(|((d, _, _), _)| *d == dir)
+            .map(|(_, n)| n)
+            .sum()
+    }
+}
+
+/// How long recent ticks took (not counting time spent idle), in buckets of under 5, 10, 25, 50
+/// and 100ms, and longer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TickHistogram {
+    counts: [u64; TICK_BUCKETS.len() + 1],
+}
+
+impl TickHistogram {
+    pub fn from_samples(samples: impl IntoIterator<Item = TickSample>) -> Self {
+        let mut histogram = Self::default();
+        for sample in samples {
+            histogram.record(sample.full - sample.idle);
+        }
+        histogram
+    }
+
+    pub fn record(&mut self, busy: Duration) {
+        let bucket = TICK_BUCKETS
+            .iter()
+            .position(|&bound| busy < bound)
+            .unwrap_or(TICK_BUCKETS.len());
+        self.counts[bucket] += 1;
+    }
+
+    /// (upper bound, ticks) of each bucket, shortest first. The last has no upper bound.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        TICK_BUCKETS
+            .iter()
+            .map(|&bound| Some(bound))
+            .chain([None])
+            .zip(self.counts)
+    }
+}
+
+/// One client's part of a `ServerDiagnostics`
+#[derive(Debug, Clone)]
+pub struct ClientDiagnostics {
+    pub cid: ClientID,
+    pub username: Option<String>,
+    pub state: ProtocolState,
+    /// Bytes waiting to be written to the client
+    pub queued_bytes: usize,
+    pub view_distance: i32,
+    pub latency: Option<Duration>,
+    pub packets: PacketCounters,
+}
+
+/// A dump of the server's live state, from `ServerHandle::diagnostics()`, for when a deployed
+/// server is slow and the metrics don't say why
+#[derive(Debug, Clone)]
+pub struct ServerDiagnostics {
+    pub tps: f64,
+    pub mspt: f64,
+    /// Of the ticks `TickSampler` remembers
+    pub ticks: TickHistogram,
+    /// Encoded chunk packets in the `ChunkCache`
+    pub cached_chunks: usize,
+    /// Chunks waiting on the `ChunkEncodePool`
+    pub encoding_chunks: usize,
+    /// Set by the server with `ServerHandle::set_gauge()`, e.g. how many chunks are loaded
+    pub gauges: Vec<(&'static str, f64)>,
+    /// Busiest first
+    pub clients: Vec<ClientDiagnostics>,
+}
+
+impl ServerDiagnostics {
+    /// As text for people to read
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "tps {:.2}, mspt {:.2}", self.tps, self.mspt).unwrap();
+        out += "ticks:";
+        for (bound, n) in self.ticks.buckets() {
+            match bound {
+                Some(bound) => write!(out, " <{}ms {n}", bound.as_millis()).unwrap(),
+                None => write!(out, " longer {n}").unwrap(),
+            }
+        }
+        out += "\n";
+        writeln!(
+            out,
+            "chunks: {} cached, {} encoding",
+            self.cached_chunks, self.encoding_chunks
+        )
+        .unwrap();
+        for &(name, value) in &self.gauges {
+            writeln!(out, "{name}: {value}").unwrap();
+        }
+        for client in &self.clients {
+            writeln!(
+                out,
+                "\n{:?} {} ({:?}): {} bytes queued, view distance {}, latency {}",
+                client.cid,
+                client.username.as_deref().unwrap_or("-"),
+                client.state,
+                client.queued_bytes,
+                client.view_distance,
+                client
+                    .latency
+                    .map_or("-".to_owned(), |l| format!("{}ms", l.as_millis())),
+            )
+            .unwrap();
+            for (dir, state, id, n) in client.packets.iter() {
+                writeln!(out, "  {dir:?} {state:?} 0x{id:02X}: {n}").unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Writes `text` to everything that connects to `listener`, then hangs up, so that e.g.
+/// `nc localhost 25575` prints it
+pub(crate) fn serve_diagnostics(listener: TcpListener, text: Arc<Mutex<String>>) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+        let body = text.lock().unwrap().clone();
+        let _ = stream.write_all(body.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_and_dumps() {
+        let mut packets = PacketCounters::new();
+        let (dir, state) = (PacketDirection::Clientbound, ProtocolState::Play);
+        packets.record(dir, state, &[0x25, 1, 2]);
+        packets.record(dir, state, &[0x25]);
+        packets.record(PacketDirection::Serverbound, state, &[0x14, 0]);
+        // no ID at all
+        packets.record(dir, state, &[]);
+        assert_eq!(packets.get(dir, state, 0x25), 2);
+        assert_eq!(packets.total(dir), 2);
+        assert_eq!(packets.iter()[0], (dir, state, 0x25, 2));
+
+        let sample = |ms: u64| TickSample {
+            full: Duration::from_millis(200),
+            idle: Duration::from_millis(200 - ms),
+            ..Default::default()
+        };
+        let ticks = TickHistogram::from_samples([sample(2), sample(3), sample(30), sample(150)]);
+        let counts: Vec<_> = ticks.buckets().map(|(_, n)| n).collect();
+        assert_eq!(counts, [2, 0, 0, 1, 0, 1]);
+
+        let diagnostics = ServerDiagnostics {
+            tps: 20.0,
+            mspt: 4.5,
+            ticks,
+            cached_chunks: 3,
+            encoding_chunks: 1,
+            gauges: vec![("chunks_loaded", 441.0)],
+            clients: vec![ClientDiagnostics {
+                cid: ClientID::new(0, 0),
+                username: Some("Notch".to_owned()),
+                state,
+                queued_bytes: 1024,
+                view_distance: 10,
+                latency: Some(Duration::from_millis(45)),
+                packets,
+            }],
+        };
+        let text = diagnostics.to_text();
+        assert!(text.starts_with("tps 20.00, mspt 4.50\nticks: <5ms 2 <10ms 0"));
+        assert!(text.contains("chunks_loaded: 441\n"));
+        assert!(text.contains("Notch (Play): 1024 bytes queued, view distance 10, latency 45ms\n"));
+        assert!(text.contains("  Clientbound Play 0x25: 2\n"));
+    }
+}
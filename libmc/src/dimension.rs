@@ -0,0 +1,620 @@
+use crate::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How a dimension looks and behaves, as sent to clients in the `minecraft:dimension_type`
+/// registry
+#[derive(Debug, Clone, PartialEq)]
+pub struct DimensionType {
+    /// None for a day/night cycle, or the time of day it always is
+    pub fixed_time: Option<i64>,
+    pub has_skylight: bool,
+    /// Whether there's a bedrock ceiling, which changes how the client renders the sky and fog
+    pub has_ceiling: bool,
+    /// Water evaporates and lava spreads faster
+    pub ultrawarm: bool,
+    /// Compasses and clocks work, and nether portals spawn zombified piglins
+    pub natural: bool,
+    /// How far one block here is in the overworld, for nether portals
+    pub coordinate_scale: f64,
+    pub bed_works: bool,
+    pub respawn_anchor_works: bool,
+    pub min_y: i32,
+    pub height: u32,
+    /// How high portals and chorus fruit can take players
+    pub logical_height: u32,
+    /// The block tag that burns forever, e.g. `#minecraft:infiniburn_overworld`
+    pub infiniburn: String,
+    /// The sky the client renders: `minecraft:overworld`, `minecraft:the_nether` or
+    /// `minecraft:the_end`
+    pub effects: String,
+    pub ambient_light: f32,
+    pub piglin_safe: bool,
+    pub has_raids: bool,
+    /// The light levels monsters spawn in, picked between the two (inclusive)
+    pub monster_spawn_light_level: (i32, i32),
+    pub monster_spawn_block_light_limit: i32,
+}
+
+impl DimensionType {
+    pub fn overworld() -> Self {
+        Self {
+            fixed_time: None,
+            has_skylight: true,
+            has_ceiling: false,
+            ultrawarm: false,
+            natural: true,
+            coordinate_scale: 1.0,
+            bed_works: true,
+            respawn_anchor_works: false,
+            min_y: -64,
+            height: 384,
+            logical_height: 384,
+            infiniburn: "#minecraft:infiniburn_overworld".to_owned(),
+            effects: "minecraft:overworld".to_owned(),
+            ambient_light: 0.0,
+            piglin_safe: false,
+            has_raids: true,
+            monster_spawn_light_level: (0, 7),
+            monster_spawn_block_light_limit: 0,
+        }
+    }
+
+    pub fn the_nether() -> Self {
+        Self {
+            fixed_time: Some(18000),
+            has_skylight: false,
+            has_ceiling: true,
+            ultrawarm: true,
+            natural: false,
+            coordinate_scale: 8.0,
+            bed_works: false,
+            respawn_anchor_works: true,
+            min_y: 0,
+            height: 256,
+            logical_height: 128,
+            infiniburn: "#minecraft:infiniburn_nether".to_owned(),
+            effects: "minecraft:the_nether".to_owned(),
+            ambient_light: 0.1,
+            piglin_safe: true,
+            has_raids: false,
+            monster_spawn_light_level: (7, 7),
+            monster_spawn_block_light_limit: 15,
+        }
+    }
+
+    pub fn the_end() -> Self {
+        Self {
+            fixed_time: Some(6000),
+            has_skylight: false,
+            has_ceiling: false,
+            ultrawarm: false,
+            natural: false,
+            coordinate_scale: 1.0,
+            bed_works: false,
+            respawn_anchor_works: false,
+            min_y: 0,
+            height: 256,
+            logical_height: 256,
+            infiniburn: "#minecraft:infiniburn_end".to_owned(),
+            effects: "minecraft:the_end".to_owned(),
+            ambient_light: 0.0,
+            piglin_safe: false,
+            has_raids: true,
+            monster_spawn_light_level: (0, 7),
+            monster_spawn_block_light_limit: 0,
+        }
+    }
+
+    /// The dimension type's `element` in the registry data
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let mut element = CompoundNbt::new("element");
+        if let Some(time) = self.fixed_time {
+            element.set("fixed_time", Nbt::Long(time));
+        }
+        element.set("has_skylight", Nbt::Byte(self.has_skylight.into()));
+        element.set("has_ceiling", Nbt::Byte(self.has_ceiling.into()));
+        element.set("ultrawarm", Nbt::Byte(self.ultrawarm.into()));
+        element.set("natural", Nbt::Byte(self.natural.into()));
+        element.set("coordinate_scale", Nbt::Double(self.coordinate_scale));
+        element.set("bed_works", Nbt::Byte(self.bed_works.into()));
+        element.set(
+            "respawn_anchor_works",
+            Nbt::Byte(self.respawn_anchor_works.into()),
+        );
+        element.set("min_y", Nbt::Int(self.min_y));
+        element.set("height", Nbt::Int(self.height as i32));
+        element.set("logical_height", Nbt::Int(self.logical_height as i32));
+        element.set("infiniburn", Nbt::String(self.infiniburn.clone().into()));
+        element.set("effects", Nbt::String(self.effects.clone().into()));
+        element.set("ambient_light", Nbt::Float(self.ambient_light));
+        element.set("piglin_safe", Nbt::Byte(self.piglin_safe.into()));
+        element.set("has_raids", Nbt::Byte(self.has_raids.into()));
+        let light_level = match self.monster_spawn_light_level {
+            (min, max) if min == max => Nbt::Int(min),
+            (min, max) => {
+                let mut range = CompoundNbt::new("value");
+                range.set("min_inclusive", Nbt::Int(min));
+                range.set("max_inclusive", Nbt::Int(max));
+                let mut uniform = CompoundNbt::new("monster_spawn_light_level");
+                uniform.set("type", Nbt::String("minecraft:uniform".into()));
+                uniform.set("value", Nbt::Compound(range));
+                Nbt::Compound(uniform)
+            }
+        };
+        element.set("monster_spawn_light_level", light_level);
+        element.set(
+            "monster_spawn_block_light_limit",
+            Nbt::Int(self.monster_spawn_block_light_limit),
+        );
+        element
+    }
+}
+
+/// The dimension types of a world, which get network IDs in the order they're added.
+/// A new registry starts out with vanilla's overworld, nether and end.
+#[derive(Debug, Clone)]
+pub struct DimensionTypeRegistry {
+    types: Vec<(String, DimensionType)>,
+}
+
+impl Default for DimensionTypeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DimensionTypeRegistry {
+    pub fn new() -> Self {
+        Self {
+            types: vec![
+                (OVERWORLD.to_owned(), DimensionType::overworld()),
+                (THE_NETHER.to_owned(), DimensionType::the_nether()),
+                (THE_END.to_owned(), DimensionType::the_end()),
+            ],
+        }
+    }
+
+    /// Adds a dimension type, or replaces the one with the same name. Returns its network ID.
+    pub fn add(&mut self, name: &str, dimension_type: DimensionType) -> i32 {
+        match self.id(name) {
+            Some(id) => {
+                self.types[id as usize].1 = dimension_type;
+                id
+            }
+            None => {
+                self.types.push((name.to_owned(), dimension_type));
+                (self.types.len() - 1).try_into().unwrap()
+            }
+        }
+    }
+
+    /// The network ID of the dimension type `name`
+    pub fn id(&self, name: &str) -> Option<i32> {
+        let i = self.types.iter().position(|(n, _)| n == name)?;
+        Some(i.try_into().unwrap())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DimensionType> {
+        self.types.iter().find(|(n, _)| n == name).map(|(_, t)| t)
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// The `minecraft:dimension_type` entry of the registry codec sent in Registry Data
+    pub fn to_registry_nbt(&self) -> CompoundNbt<'static> {
+        let entries: Vec<CompoundNbt> = self
+            .types
+            .iter()
+            .enumerate()
+            .map(|(id, (name, dimension_type))| {
+                let mut entry = CompoundNbt::new("");
+                entry.set("name", Nbt::String(Cow::Owned(name.clone())));
+                entry.set("id", Nbt::Int(id.try_into().unwrap()));
+                entry.set("element", Nbt::Compound(dimension_type.to_nbt()));
+                entry
+            })
+            .collect();
+        let mut registry = CompoundNbt::new("minecraft:dimension_type");
+        registry.set(
+            "type",
+            Nbt::String(Cow::Borrowed("minecraft:dimension_type")),
+        );
+        registry.set("value", Nbt::List(NbtList::Compound(Cow::Owned(entries))));
+        registry
+    }
+}
+
+pub const OVERWORLD: &str = "minecraft:overworld";
+pub const THE_NETHER: &str = "minecraft:the_nether";
+pub const THE_END: &str = "minecraft:the_end";
+
+/// One of a world's dimensions, with its own chunks and chunk tickets
+#[derive(Debug)]
+pub struct Dimension {
+    /// e.g. `minecraft:the_nether`
+    pub name: String,
+    /// Its type's name in the `DimensionTypeRegistry`
+    pub dimension_type: String,
+    /// Where it's saved (see `Dimension::save_dir()`)
+    pub dir: PathBuf,
+    /// Sent to clients with `hashed_seed()`
+    pub hashed_seed: i64,
+    pub is_debug: bool,
+    pub is_superflat: bool,
+    pub chunks: ChunkStore,
+    pub tickets: ChunkTickets,
+}
+
+impl Dimension {
+    /// An empty dimension of `dimension_type` (`kind`, registered as `dimension_type`), saved in
+    /// `world_dir` the way vanilla saves `name`. Its empty sections are in `biome`, out of
+    /// `biome_count` biomes.
+    pub fn new(
+        world_dir: &Path,
+        name: &str,
+        dimension_type: &str,
+        kind: &DimensionType,
+        biome: u32,
+        biome_count: usize,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            dimension_type: dimension_type.to_owned(),
+            dir: Self::save_dir(world_dir, name),
+            hashed_seed: 0,
+            is_debug: false,
+            is_superflat: false,
+            chunks: ChunkStore::new(kind.min_y, kind.height, biome, biome_count),
+            tickets: ChunkTickets::new(),
+        }
+    }
+
+    /// Where vanilla saves the dimension `name` of the world in `world_dir`: the overworld in the
+    /// world's own folder, the nether in `DIM-1`, the end in `DIM1`, and others in
+    /// `dimensions/<namespace>/<path>`
+    pub fn save_dir(world_dir: &Path, name: &str) -> PathBuf {
+        match name {
+            OVERWORLD => world_dir.to_owned(),
+            THE_NETHER => world_dir.join("DIM-1"),
+            THE_END => world_dir.join("DIM1"),
+            _ => {
+                let (namespace, path) = name.split_once(':').unwrap_or(("minecraft", name));
+                world_dir.join("dimensions").join(namespace).join(path)
+            }
+        }
+    }
+
+    /// The folder of the dimension's block region files
+    pub fn region_dir(&self) -> PathBuf {
+        self.dir.join("region")
+    }
+
+    /// The folder of the dimension's entity region files
+    pub fn entities_dir(&self) -> PathBuf {
+        self.dir.join("entities")
+    }
+
+    /// The chunk's blocks as saved, or None if it hasn't been saved
+    pub fn load_anvil_chunk(&self, chunk: ChunkPos) -> io::Result<Option<AnvilChunk>> {
+        match open_region(&self.region_dir(), chunk)? {
+            Some(region) => region.load_chunk(chunk),
+            None => Ok(None),
+        }
+    }
+
+    /// The chunk's entities as saved, or None if it has none
+    pub fn load_entities(&self, chunk: ChunkPos) -> io::Result<Option<EntityChunk>> {
+        match open_region(&self.entities_dir(), chunk)? {
+            Some(region) => region.load_entities(chunk),
+            None => Ok(None),
+        }
+    }
+
+    /// Saves the entities of `entities.pos` in their region file, creating it if it doesn't exist
+    pub fn save_entities(&self, entities: &EntityChunk) -> io::Result<()> {
+        let dir = self.entities_dir();
+        let mut region = open_region(&dir, entities.pos)?.unwrap_or_default();
+        region.save_entities(entities)?;
+        fs::create_dir_all(&dir)?;
+        region.save(&RegionFile::path_for(&dir, entities.pos))
+    }
+}
+
+fn open_region(dir: &Path, chunk: ChunkPos) -> io::Result<Option<RegionFile>> {
+    match RegionFile::open(&RegionFile::path_for(dir, chunk)) {
+        Ok(region) => Ok(Some(region)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// What a player is told about dimensions in Login (play): all of the world's dimensions, and
+/// the one it spawns in. See `Server::login_dimensions()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginDimensions {
+    pub names: Vec<String>,
+    pub dimension_type: String,
+    pub dimension_name: String,
+    pub hashed_seed: i64,
+    pub is_debug: bool,
+    pub is_superflat: bool,
+}
+
+/// The placeholder dimension players are told about when the server doesn't say
+impl Default for LoginDimensions {
+    fn default() -> Self {
+        Self {
+            names: vec!["foo:bar".to_owned()],
+            dimension_type: "foo:baz".to_owned(),
+            dimension_name: "foo:bar".to_owned(),
+            hashed_seed: 999,
+            is_debug: false,
+            is_superflat: false,
+        }
+    }
+}
+
+/// A world's dimensions, and which one each player is in.
+///
+/// Players are moved between them with `change_dimension()`, which takes the client through
+/// vanilla's Respawn flow and starts over sending chunks from the new dimension.
+#[derive(Debug)]
+pub struct Dimensions {
+    world_dir: PathBuf,
+    types: DimensionTypeRegistry,
+    dimensions: Vec<Dimension>,
+    players: HashMap<ClientID, usize>,
+}
+
+impl Dimensions {
+    /// No dimensions yet, with the world saved in `world_dir`
+    pub fn new(world_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            world_dir: world_dir.into(),
+            types: DimensionTypeRegistry::new(),
+            dimensions: Vec::new(),
+            players: HashMap::new(),
+        }
+    }
+
+    pub fn world_dir(&self) -> &Path {
+        &self.world_dir
+    }
+
+    pub fn types(&self) -> &DimensionTypeRegistry {
+        &self.types
+    }
+
+    pub fn types_mut(&mut self) -> &mut DimensionTypeRegistry {
+        &mut self.types
+    }
+
+    /// Adds an empty dimension of the registered type `dimension_type`, or replaces the one
+    /// called `name` (whose players stay in it). Returns None if the type isn't registered.
+    pub fn add(
+        &mut self,
+        name: &str,
+        dimension_type: &str,
+        biome: u32,
+        biome_count: usize,
+    ) -> Option<&mut Dimension> {
+        let kind = self.types.get(dimension_type)?;
+        let dimension = Dimension::new(
+            &self.world_dir,
+            name,
+            dimension_type,
+            kind,
+            biome,
+            biome_count,
+        );
+        let i = match self.dimensions.iter().position(|d| d.name == name) {
+            Some(i) => {
+                self.dimensions[i] = dimension;
+                i
+            }
+            None => {
+                self.dimensions.push(dimension);
+                self.dimensions.len() - 1
+            }
+        };
+        Some(&mut self.dimensions[i])
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Dimension> {
+        self.dimensions.iter().find(|d| d.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Dimension> {
+        self.dimensions.iter_mut().find(|d| d.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Dimension> {
+        self.dimensions.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Dimension> {
+        self.dimensions.iter_mut()
+    }
+
+    /// The names of every dimension, in the order they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.dimensions.iter().map(|d| &d.name[..])
+    }
+
+    /// What to tell a player that joins in `name`, from `Server::login_dimensions()`
+    pub fn login_dimensions(&self, name: &str) -> Option<LoginDimensions> {
+        let dimension = self.get(name)?;
+        Some(LoginDimensions {
+            names: self.names().map(str::to_owned).collect(),
+            dimension_type: dimension.dimension_type.clone(),
+            dimension_name: dimension.name.clone(),
+            hashed_seed: dimension.hashed_seed,
+            is_debug: dimension.is_debug,
+            is_superflat: dimension.is_superflat,
+        })
+    }
+
+    /// The dimension `cid` is in
+    pub fn dimension_of(&self, cid: ClientID) -> Option<&Dimension> {
+        self.players.get(&cid).map(|&i| &self.dimensions[i])
+    }
+
+    /// Puts a player in the dimension `name` without telling its client, e.g. when it joins
+    /// there, giving it a chunk ticket at `chunk`. Returns false if there's no such dimension.
+    pub fn join(
+        &mut self,
+        cid: ClientID,
+        name: &str,
+        chunk: ChunkPos,
+        simulation_distance: u32,
+    ) -> bool {
+        let Some(i) = self.dimensions.iter().position(|d| d.name == name) else {
+            return false;
+        };
+        self.leave(cid);
+        self.dimensions[i]
+            .tickets
+            .set_player(cid, chunk, simulation_distance);
+        self.players.insert(cid, i);
+        true
+    }
+
+    /// Takes a player out of its dimension, e.g. when it disconnects
+    pub fn leave(&mut self, cid: ClientID) -> bool {
+        match self.players.remove(&cid) {
+            Some(i) => {
+                self.dimensions[i].tickets.remove_player(cid);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves a player to the dimension `name`, at `pos`: its client is sent a Respawn into the
+    /// new dimension (keeping its attributes and metadata, like vanilla does for portals), its
+    /// position, and told to wait for chunks, and its chunk ticket moves over.
+    ///
+    /// Returns the queue to send it the new dimension's chunks from, as the client has dropped
+    /// all of the old ones. None if there's no such dimension, or the client isn't connected.
+    pub fn change_dimension(
+        &mut self,
+        handle: &ServerHandle,
+        cid: ClientID,
+        name: &str,
+        pos: Vec3,
+        game_mode: GameMode,
+    ) -> Option<ChunkSendQueue> {
+        let view_distance = handle.view_distance(cid)?;
+        let simulation_distance = handle.config().simulation_distance.max(0) as u32;
+        let center = pos.chunk();
+        if !self.join(cid, name, center, simulation_distance) {
+            return None;
+        }
+        let dimension = self.dimension_of(cid)?;
+        handle.send_all(
+            cid,
+            vec![
+                OutPacket::Respawn {
+                    dimension_type: &dimension.dimension_type,
+                    dimension_name: &dimension.name,
+                    hashed_seed: dimension.hashed_seed,
+                    game_mode,
+                    prev_game_mode: None,
+                    is_debug: dimension.is_debug,
+                    is_superflat: dimension.is_superflat,
+                    death_info: None,
+                    portal_cooldown: 0,
+                    data_kept: 0x03,
+                },
+                OutPacket::SyncPlayerPos {
+                    x: pos.x,
+                    y: pos.y,
+                    z: pos.z,
+                    yaw: 0.0,
+                    pitch: 0.0,
+                    flags: 0,
+                    teleport_id: 0,
+                },
+                OutPacket::GameEvent {
+                    event: 13,
+                    value: 0.0,
+                },
+                OutPacket::SetCenterChunk {
+                    chunk_x: center.x,
+                    chunk_z: center.z,
+                },
+            ],
+        );
+        Some(ChunkSendQueue::new(view_distance, center, 0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dimensions_and_their_folders() {
+        let world = Path::new("world");
+        assert_eq!(Dimension::save_dir(world, OVERWORLD), world);
+        assert_eq!(Dimension::save_dir(world, THE_NETHER), world.join("DIM-1"));
+        assert_eq!(Dimension::save_dir(world, THE_END), world.join("DIM1"));
+        assert_eq!(
+            Dimension::save_dir(world, "example:mining"),
+            world.join("dimensions/example/mining")
+        );
+
+        let mut dimensions = Dimensions::new(world);
+        let mut mining = DimensionType::overworld();
+        mining.min_y = -128;
+        assert_eq!(dimensions.types_mut().add("example:mining", mining), 3);
+        assert!(dimensions.add(OVERWORLD, OVERWORLD, 0, 1).is_some());
+        assert!(dimensions.add(THE_NETHER, THE_NETHER, 0, 1).is_some());
+        let custom = dimensions.add("example:mining", "example:mining", 0, 1);
+        assert_eq!(
+            custom.unwrap().region_dir(),
+            world.join("dimensions/example/mining/region")
+        );
+        assert!(dimensions
+            .add("example:other", "example:unknown", 0, 1)
+            .is_none());
+
+        let login = dimensions.login_dimensions(THE_NETHER).unwrap();
+        assert_eq!(login.names, [OVERWORLD, THE_NETHER, "example:mining"]);
+        assert_eq!(login.dimension_type, THE_NETHER);
+
+        let cid = ClientID::new(0, 0);
+        let chunk = ChunkPos::new(0, 0);
+        assert!(dimensions.join(cid, OVERWORLD, chunk, 10));
+        let overworld = dimensions.get_mut(OVERWORLD).unwrap();
+        assert!(!overworld.tickets.update().is_empty());
+        assert!(overworld.tickets.level(chunk).is_some());
+        assert!(dimensions.join(cid, THE_NETHER, chunk, 10));
+        assert_eq!(dimensions.dimension_of(cid).unwrap().name, THE_NETHER);
+        let overworld = dimensions.get_mut(OVERWORLD).unwrap();
+        assert!(!overworld.tickets.update().is_empty());
+        assert!(overworld.tickets.level(chunk).is_none());
+        assert!(dimensions.leave(cid) && !dimensions.leave(cid));
+
+        let registry = dimensions.types().to_registry_nbt();
+        let Some(Nbt::List(NbtList::Compound(entries))) = registry.get("value") else {
+            panic!("no dimension types");
+        };
+        assert_eq!(entries.len(), 4);
+        let Some(Nbt::Compound(nether)) = entries[1].get("element") else {
+            panic!("no element");
+        };
+        assert_eq!(nether.get("fixed_time"), Some(&Nbt::Long(18000)));
+        assert_eq!(nether.get("monster_spawn_light_level"), Some(&Nbt::Int(7)));
+    }
+}
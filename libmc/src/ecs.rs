@@ -0,0 +1,662 @@
+use crate::entityregion::rotation_of;
+use crate::*;
+
+/// Refers to an entity in an `EntityStore`. Stops referring to anything once the entity is
+/// despawned, even after its slot is reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EntityHandle {
+    index: u32,
+    generation: u32,
+}
+
+/// Positions as the clients last saw them are kept in 1/4096ths of a block, the unit of the
+/// relative move packets, so rounding errors don't add up over many moves
+const DELTA_SCALE: f64 = 4096.0;
+
+fn to_fixed(pos: Vec3) -> [i64; 3] {
+    [pos.x, pos.y, pos.z].map(|c| (c * DELTA_SCALE).round() as i64)
+}
+
+/// What changed about an entity since the last `tick()`
+#[derive(Debug, Copy, Clone, Default)]
+struct Dirty {
+    rotation: bool,
+    head_yaw: bool,
+    velocity: bool,
+    metadata: bool,
+    equipment: bool,
+}
+
+/// What `EntityStore::advance()` found to send
+#[derive(Debug, Default)]
+pub(crate) struct TickChanges {
+    /// Entities to spawn on clients
+    new: Vec<usize>,
+    /// (index, delta, or None to teleport, whether it also rotated)
+    moves: Vec<(usize, Option<[i16; 3]>, bool)>,
+    changed: Vec<(usize, Dirty)>,
+}
+
+/// Server-side entities, kept as one array per component so the tick goes over them without
+/// chasing pointers. Slots of despawned entities are reused, so entity IDs stay small and dense.
+///
+/// Changes are sent by `tick()`, which is called once per tick (e.g. from `Server::tick()`) and
+/// gives the packets to broadcast to everyone who can see the entities. New viewers are sent
+/// `spawn_all()`. To send each client only the entities near it, tick the store with an
+/// `EntityTracker` instead.
+#[derive(Debug)]
+pub struct EntityStore {
+    /// Added to slot indices to make entity IDs, so they don't clash with the players'
+    first_entity_id: i32,
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    /// Whether the clients have been sent the entity's spawn packets
+    spawned: Vec<bool>,
+    kinds: Vec<EntityType>,
+    uuids: Vec<Uuid>,
+    positions: Vec<Vec3>,
+    sent_positions: Vec<[i64; 3]>,
+    /// In blocks per tick, added to the position every tick
+    velocities: Vec<Vec3>,
+    rotations: Vec<(Angle, Angle)>,
+    head_yaws: Vec<Angle>,
+    on_ground: Vec<bool>,
+    metadata: Vec<Vec<MetadataEntry<'static>>>,
+    equipment: Vec<[(EquipmentSlot, Option<Slot<'static>>); 6]>,
+    dirty: Vec<Dirty>,
+    /// What entities loaded with `spawn_saved()` were saved with, to save them again with
+    saved_nbt: Vec<Option<CompoundNbt<'static>>>,
+    free: Vec<u32>,
+    /// Despawned since the last tick; their slots are freed once the removal is sent
+    despawned: Vec<u32>,
+    /// The entity IDs in the last tick's Remove Entities packet
+    removed_ids: Vec<i32>,
+    len: usize,
+}
+
+impl EntityStore {
+    /// Entity IDs start at `first_entity_id`, and go up by one per entity that's alive at once, so
+    /// reserve enough of them with `ServerHandle::reserve_entity_ids()`
+    pub fn new(first_entity_id: i32) -> Self {
+        Self {
+            first_entity_id,
+            generations: Vec::new(),
+            alive: Vec::new(),
+            spawned: Vec::new(),
+            kinds: Vec::new(),
+            uuids: Vec::new(),
+            positions: Vec::new(),
+            sent_positions: Vec::new(),
+            velocities: Vec::new(),
+            rotations: Vec::new(),
+            head_yaws: Vec::new(),
+            on_ground: Vec::new(),
+            metadata: Vec::new(),
+            equipment: Vec::new(),
+            dirty: Vec::new(),
+            saved_nbt: Vec::new(),
+            free: Vec::new(),
+            despawned: Vec::new(),
+            removed_ids: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Adds an entity, which is shown to clients by the next `tick()`
+    pub fn spawn(&mut self, kind: EntityType, uuid: Uuid, pos: Vec3) -> EntityHandle {
+        let equipment = EquipmentSlot::ALL.map(|slot| (slot, None));
+        let index = match self.free.pop() {
+            Some(index) => {
+                let i = index as usize;
+                self.kinds[i] = kind;
+                self.uuids[i] = uuid;
+                self.positions[i] = pos;
+                self.sent_positions[i] = to_fixed(pos);
+                self.velocities[i] = Vec3::default();
+                self.rotations[i] = Default::default();
+                self.head_yaws[i] = Angle::default();
+                self.on_ground[i] = false;
+                self.metadata[i].clear();
+                self.equipment[i] = equipment;
+                self.dirty[i] = Dirty::default();
+                self.saved_nbt[i] = None;
+                index
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(false);
+                self.spawned.push(false);
+                self.kinds.push(kind);
+                self.uuids.push(uuid);
+                self.positions.push(pos);
+                self.sent_positions.push(to_fixed(pos));
+                self.velocities.push(Vec3::default());
+                self.rotations.push(Default::default());
+                self.head_yaws.push(Angle::default());
+                self.on_ground.push(false);
+                self.metadata.push(Vec::new());
+                self.equipment.push(equipment);
+                self.dirty.push(Dirty::default());
+                self.saved_nbt.push(None);
+                (self.alive.len() - 1) as u32
+            }
+        };
+        self.alive[index as usize] = true;
+        self.spawned[index as usize] = false;
+        self.len += 1;
+        EntityHandle {
+            index,
+            generation: self.generations[index as usize],
+        }
+    }
+
+    /// Spawns an entity loaded from a world, e.g. from `RegionFile::load_entities()`.
+    /// `to_saved()` gives it back with everything libmc doesn't model kept.
+    pub fn spawn_saved(&mut self, saved: SavedEntity) -> EntityHandle {
+        let h = self.spawn(saved.kind, saved.uuid, saved.pos);
+        let i = h.index as usize;
+        self.velocities[i] = saved.velocity;
+        self.rotations[i] = (
+            Angle::from_degrees(saved.yaw),
+            Angle::from_degrees(saved.pitch),
+        );
+        self.head_yaws[i] = Angle::from_degrees(saved.yaw);
+        self.on_ground[i] = saved.on_ground;
+        self.saved_nbt[i] = Some(saved.nbt);
+        h
+    }
+
+    /// The entity as vanilla would save it
+    pub fn to_saved(&self, h: EntityHandle) -> Option<SavedEntity> {
+        if !self.contains(h) {
+            return None;
+        }
+        let i = h.index as usize;
+        let nbt = self.saved_nbt[i]
+            .clone()
+            .unwrap_or_else(|| CompoundNbt::new(""));
+        let (yaw, pitch) = self.rotations[i];
+        // keep the saved rotation's precision unless it was turned since
+        let [yaw, pitch] = match rotation_of(&nbt) {
+            Some(saved)
+                if (Angle::from_degrees(saved[0]), Angle::from_degrees(saved[1]))
+                    == (yaw, pitch) =>
+            {
+                saved
+            }
+            _ => [yaw.degrees(), pitch.degrees()],
+        };
+        let mut saved = SavedEntity {
+            kind: self.kinds[i],
+            uuid: self.uuids[i],
+            pos: self.positions[i],
+            velocity: self.velocities[i],
+            yaw,
+            pitch,
+            on_ground: self.on_ground[i],
+            nbt,
+        };
+        saved.nbt = saved.to_nbt();
+        Some(saved)
+    }
+
+    /// The entities in `chunk` as vanilla would save them, for `RegionFile::save_entities()`.
+    /// Players aren't included; vanilla saves them in `playerdata/`.
+    pub fn saved_in_chunk(&self, chunk: ChunkPos) -> Vec<SavedEntity> {
+        self.iter()
+            .filter(|&h| {
+                let i = h.index as usize;
+                self.kinds[i] != EntityType::Player && self.positions[i].chunk() == chunk
+            })
+            .filter_map(|h| self.to_saved(h))
+            .collect()
+    }
+
+    /// Removes an entity, which is removed from clients by the next `tick()`.
+    /// Returns false if it was already gone.
+    pub fn despawn(&mut self, h: EntityHandle) -> bool {
+        if !self.contains(h) {
+            return false;
+        }
+        let i = h.index as usize;
+        self.alive[i] = false;
+        self.generations[i] = self.generations[i].wrapping_add(1);
+        self.len -= 1;
+        if self.spawned[i] {
+            self.despawned.push(h.index);
+        } else {
+            // the clients never heard of it
+            self.free.push(h.index);
+        }
+        true
+    }
+
+    pub fn contains(&self, h: EntityHandle) -> bool {
+        let i = h.index as usize;
+        i < self.alive.len() && self.alive[i] && self.generations[i] == h.generation
+    }
+
+    /// How many entities are spawned
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EntityHandle> + '_ {
+        (0..self.alive.len())
+            .filter(|&i| self.alive[i])
+            .map(|i| EntityHandle {
+                index: i as u32,
+                generation: self.generations[i],
+            })
+    }
+
+    /// The ID clients know the entity by
+    pub fn entity_id(&self, h: EntityHandle) -> Option<i32> {
+        self.contains(h)
+            .then(|| self.first_entity_id + h.index as i32)
+    }
+
+    /// The entity with this ID, e.g. the target of an Interact packet
+    pub fn handle_for_id(&self, entity_id: i32) -> Option<EntityHandle> {
+        let index = u32::try_from(entity_id.checked_sub(self.first_entity_id)?).ok()?;
+        let h = EntityHandle {
+            index,
+            generation: *self.generations.get(index as usize)?,
+        };
+        self.contains(h).then_some(h)
+    }
+
+    pub fn kind(&self, h: EntityHandle) -> Option<EntityType> {
+        self.contains(h).then(|| self.kinds[h.index as usize])
+    }
+
+    pub fn uuid(&self, h: EntityHandle) -> Option<Uuid> {
+        self.contains(h).then(|| self.uuids[h.index as usize])
+    }
+
+    pub fn position(&self, h: EntityHandle) -> Option<Vec3> {
+        self.contains(h).then(|| self.positions[h.index as usize])
+    }
+
+    pub fn set_position(&mut self, h: EntityHandle, pos: Vec3) {
+        if self.contains(h) {
+            self.positions[h.index as usize] = pos;
+        }
+    }
+
+    /// In blocks per tick
+    pub fn velocity(&self, h: EntityHandle) -> Option<Vec3> {
+        self.contains(h).then(|| self.velocities[h.index as usize])
+    }
+
+    /// The velocity is added to the position every tick until it's changed again
+    pub fn set_velocity(&mut self, h: EntityHandle, velocity: Vec3) {
+        if self.contains(h) {
+            let i = h.index as usize;
+            self.velocities[i] = velocity;
+            self.dirty[i].velocity = true;
+        }
+    }
+
+    /// (yaw, pitch)
+    pub fn rotation(&self, h: EntityHandle) -> Option<(Angle, Angle)> {
+        self.contains(h).then(|| self.rotations[h.index as usize])
+    }
+
+    pub fn set_rotation(&mut self, h: EntityHandle, yaw: Angle, pitch: Angle) {
+        if self.contains(h) && self.rotations[h.index as usize] != (yaw, pitch) {
+            let i = h.index as usize;
+            self.rotations[i] = (yaw, pitch);
+            self.dirty[i].rotation = true;
+        }
+    }
+
+    pub fn head_yaw(&self, h: EntityHandle) -> Option<Angle> {
+        self.contains(h).then(|| self.head_yaws[h.index as usize])
+    }
+
+    pub fn set_head_yaw(&mut self, h: EntityHandle, head_yaw: Angle) {
+        if self.contains(h) && self.head_yaws[h.index as usize] != head_yaw {
+            let i = h.index as usize;
+            self.head_yaws[i] = head_yaw;
+            self.dirty[i].head_yaw = true;
+        }
+    }
+
+    pub fn on_ground(&self, h: EntityHandle) -> Option<bool> {
+        self.contains(h).then(|| self.on_ground[h.index as usize])
+    }
+
+    /// Sent along with the next move
+    pub fn set_on_ground(&mut self, h: EntityHandle, on_ground: bool) {
+        if self.contains(h) {
+            self.on_ground[h.index as usize] = on_ground;
+        }
+    }
+
+    pub fn metadata(&self, h: EntityHandle) -> Option<&[MetadataEntry<'static>]> {
+        self.contains(h)
+            .then(|| &self.metadata[h.index as usize][..])
+    }
+
+    /// Sets one metadata entry, replacing the one with the same index
+    pub fn set_metadata(&mut self, h: EntityHandle, entry: MetadataEntry<'static>) {
+        if !self.contains(h) {
+            return;
+        }
+        let i = h.index as usize;
+        let metadata = &mut self.metadata[i];
+        match metadata.iter_mut().find(|e| e.index == entry.index) {
+            Some(e) if *e == entry => return,
+            Some(e) => *e = entry,
+            None => metadata.push(entry),
+        }
+        self.dirty[i].metadata = true;
+    }
+
+    pub fn equipment(&self, h: EntityHandle, slot: EquipmentSlot) -> Option<&Slot<'static>> {
+        self.contains(h)
+            .then(|| self.equipment[h.index as usize][slot as usize].1.as_ref())
+            .flatten()
+    }
+
+    pub fn set_equipment(
+        &mut self,
+        h: EntityHandle,
+        slot: EquipmentSlot,
+        item: Option<Slot<'static>>,
+    ) {
+        if self.contains(h) {
+            let i = h.index as usize;
+            self.equipment[i][slot as usize].1 = item;
+            self.dirty[i].equipment = true;
+        }
+    }
+
+    /// Moves entities by their velocities, and gives the packets for everything that changed since
+    /// the last tick, to send to all viewers in order. To only send entities to the clients near
+    /// them, use an `EntityTracker` instead.
+    pub fn tick(&mut self) -> Vec<OutPacket<'_>> {
+        let changes = self.advance();
+        self.packets(&changes)
+    }
+
+    /// The first half of `tick()`: moves entities and takes what changed
+    pub(crate) fn advance(&mut self) -> TickChanges {
+        self.removed_ids.clear();
+        for index in self.despawned.drain(..) {
+            self.removed_ids.push(self.first_entity_id + index as i32);
+            self.spawned[index as usize] = false;
+            self.free.push(index);
+        }
+
+        let mut new = Vec::new();
+        // (index, delta, or None to teleport)
+        let mut moves = Vec::new();
+        let mut changed = Vec::new();
+        for i in 0..self.alive.len() {
+            if !self.alive[i] {
+                continue;
+            }
+            self.positions[i] = self.positions[i] + self.velocities[i];
+            let dirty = std::mem::take(&mut self.dirty[i]);
+            if !self.spawned[i] {
+                self.spawned[i] = true;
+                self.sent_positions[i] = to_fixed(self.positions[i]);
+                new.push(i);
+                continue;
+            }
+            let pos = to_fixed(self.positions[i]);
+            let sent = &mut self.sent_positions[i];
+            if pos != *sent {
+                let delta = [0, 1, 2].map(|c| i16::try_from(pos[c] - sent[c]));
+                *sent = pos;
+                let delta = match delta {
+                    [Ok(x), Ok(y), Ok(z)] => Some([x, y, z]),
+                    _ => None,
+                };
+                moves.push((i, delta, dirty.rotation));
+            } else if dirty.rotation {
+                moves.push((i, Some([0; 3]), true));
+            }
+            if dirty.head_yaw || dirty.velocity || dirty.metadata || dirty.equipment {
+                changed.push((i, dirty));
+            }
+        }
+        TickChanges {
+            new,
+            moves,
+            changed,
+        }
+    }
+
+    /// The second half of `tick()`: the packets for what `advance()` found
+    pub(crate) fn packets(&self, changes: &TickChanges) -> Vec<OutPacket<'_>> {
+        let this = self;
+        let id = |i: usize| this.first_entity_id + i as i32;
+        let mut packets = Vec::new();
+        if !this.removed_ids.is_empty() {
+            packets.push(OutPacket::RemoveEntities {
+                entity_ids: &this.removed_ids,
+            });
+        }
+        for &i in &changes.new {
+            this.push_spawn(i, &mut packets);
+        }
+        for &(i, delta, rotated) in &changes.moves {
+            let (yaw, pitch) = this.rotations[i];
+            let on_ground = this.on_ground[i];
+            packets.push(match delta {
+                None => {
+                    let pos = this.positions[i];
+                    OutPacket::TeleportEntity {
+                        entity_id: id(i),
+                        x: pos.x,
+                        y: pos.y,
+                        z: pos.z,
+                        yaw,
+                        pitch,
+                        on_ground,
+                    }
+                }
+                Some([0, 0, 0]) => OutPacket::UpdateEntityRotation {
+                    entity_id: id(i),
+                    yaw,
+                    pitch,
+                    on_ground,
+                },
+                Some(delta) if rotated => OutPacket::UpdateEntityPositionAndRotation {
+                    entity_id: id(i),
+                    delta,
+                    yaw,
+                    pitch,
+                    on_ground,
+                },
+                Some(delta) => OutPacket::UpdateEntityPosition {
+                    entity_id: id(i),
+                    delta,
+                    on_ground,
+                },
+            });
+        }
+        for &(i, dirty) in &changes.changed {
+            if dirty.head_yaw {
+                packets.push(OutPacket::SetHeadRotation {
+                    entity_id: id(i),
+                    head_yaw: this.head_yaws[i],
+                });
+            }
+            if dirty.velocity {
+                packets.push(OutPacket::SetEntityVelocity {
+                    entity_id: id(i),
+                    velocity: encode_velocity(this.velocities[i].into()),
+                });
+            }
+            if dirty.metadata {
+                packets.push(OutPacket::SetEntityMetadata {
+                    entity_id: id(i),
+                    metadata: &this.metadata[i],
+                });
+            }
+            if dirty.equipment {
+                packets.push(OutPacket::SetEquipment {
+                    entity_id: id(i),
+                    equipment: &this.equipment[i],
+                });
+            }
+        }
+        packets
+    }
+
+    /// The entity IDs despawned in the last tick
+    pub(crate) fn removed_ids(&self) -> &[i32] {
+        &self.removed_ids
+    }
+
+    /// The packets that show `h` to a new viewer, if it has been spawned on clients
+    pub fn spawn_packets(&self, h: EntityHandle) -> Vec<OutPacket<'_>> {
+        let mut packets = Vec::new();
+        if self.contains(h) && self.spawned[h.index as usize] {
+            self.push_spawn(h.index as usize, &mut packets);
+        }
+        packets
+    }
+
+    /// The packets that show every spawned entity to a new viewer
+    pub fn spawn_all(&self) -> Vec<OutPacket<'_>> {
+        let mut packets = Vec::new();
+        for i in 0..self.alive.len() {
+            if self.alive[i] && self.spawned[i] {
+                self.push_spawn(i, &mut packets);
+            }
+        }
+        packets
+    }
+
+    fn push_spawn<'a>(&'a self, i: usize, packets: &mut Vec<OutPacket<'a>>) {
+        let entity_id = self.first_entity_id + i as i32;
+        let (yaw, pitch) = self.rotations[i];
+        let [x, y, z] = self.sent_positions[i].map(|c| c as f64 / DELTA_SCALE);
+        packets.push(OutPacket::SpawnEntity {
+            entity_id,
+            uuid: self.uuids[i],
+            entity_type: self.kinds[i],
+            x,
+            y,
+            z,
+            pitch,
+            yaw,
+            head_yaw: self.head_yaws[i],
+            data: 0,
+            velocity: encode_velocity(self.velocities[i].into()),
+        });
+        if !self.metadata[i].is_empty() {
+            packets.push(OutPacket::SetEntityMetadata {
+                entity_id,
+                metadata: &self.metadata[i],
+            });
+        }
+        if self.equipment[i].iter().any(|(_, item)| item.is_some()) {
+            packets.push(OutPacket::SetEquipment {
+                entity_id,
+                equipment: &self.equipment[i],
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_and_reuses_ids() {
+        let mut store = EntityStore::new(1000);
+        let a = store.spawn(EntityType::Zombie, Uuid(1), Vec3::from([0.0, 64.0, 0.0]));
+        let b = store.spawn(EntityType::Pig, Uuid(2), Vec3::from([5.0, 64.0, 5.0]));
+        assert_eq!(store.entity_id(b), Some(1001));
+        assert_eq!(store.handle_for_id(1001), Some(b));
+        store.set_equipment(
+            a,
+            EquipmentSlot::Helmet,
+            Some(Slot {
+                item_id: 1,
+                count: 1,
+                nbt: None,
+            }),
+        );
+        let packets = store.tick();
+        assert!(matches!(
+            packets[..],
+            [
+                OutPacket::SpawnEntity {
+                    entity_id: 1000,
+                    ..
+                },
+                OutPacket::SetEquipment {
+                    entity_id: 1000,
+                    ..
+                },
+                OutPacket::SpawnEntity {
+                    entity_id: 1001,
+                    ..
+                },
+            ]
+        ));
+        assert!(store.tick().is_empty());
+
+        store.set_position(a, Vec3::from([0.5, 64.0, 0.0]));
+        store.set_position(b, Vec3::from([50.0, 64.0, 5.0]));
+        store.set_rotation(b, Angle(64), Angle(0));
+        let packets = store.tick();
+        assert!(matches!(
+            packets[..],
+            [
+                OutPacket::UpdateEntityPosition {
+                    entity_id: 1000,
+                    delta: [2048, 0, 0],
+                    ..
+                },
+                OutPacket::TeleportEntity {
+                    entity_id: 1001,
+                    x: 50.0,
+                    yaw: Angle(64),
+                    ..
+                },
+            ]
+        ));
+
+        store.set_velocity(a, Vec3::from([0.0, 0.25, 0.0]));
+        assert!(store.despawn(b));
+        assert!(!store.contains(b));
+        let packets = store.tick();
+        assert!(matches!(
+            packets[..],
+            [
+                OutPacket::RemoveEntities {
+                    entity_ids: &[1001]
+                },
+                OutPacket::UpdateEntityPosition {
+                    delta: [0, 1024, 0],
+                    ..
+                },
+                OutPacket::SetEntityVelocity {
+                    velocity: [0, 2000, 0],
+                    ..
+                },
+            ]
+        ));
+        assert_eq!(store.position(a), Some(Vec3::from([0.5, 64.25, 0.0])));
+
+        // b's slot is reused, but its old handle doesn't refer to the new entity
+        let c = store.spawn(EntityType::Cow, Uuid(3), Vec3::default());
+        assert_eq!(store.entity_id(c), Some(1001));
+        assert_eq!(store.kind(b), None);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.spawn_all().len(), 2);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::*;
+
+/// Status effects, in the order of their (1.20.2) network IDs
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Effect {
+    Speed,
+    Slowness,
+    Haste,
+    MiningFatigue,
+    Strength,
+    InstantHealth,
+    InstantDamage,
+    JumpBoost,
+    Nausea,
+    Regeneration,
+    Resistance,
+    FireResistance,
+    WaterBreathing,
+    Invisibility,
+    Blindness,
+    NightVision,
+    Hunger,
+    Weakness,
+    Poison,
+    Wither,
+    HealthBoost,
+    Absorption,
+    Saturation,
+    Glowing,
+    Levitation,
+    Luck,
+    Unluck,
+    SlowFalling,
+    ConduitPower,
+    DolphinsGrace,
+    BadOmen,
+    HeroOfTheVillage,
+    Darkness,
+}
+
+impl Effect {
+    pub fn id(self) -> i32 {
+        self as i32
+    }
+}
+
+/// An effect on an entity
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ActiveEffect {
+    pub effect: Effect,
+    /// 0 for level I
+    pub amplifier: i8,
+    /// Ticks left, or None if it lasts forever
+    pub duration: Option<u32>,
+    /// From a beacon or conduit: fewer particles, and a different HUD icon border
+    pub ambient: bool,
+    pub show_particles: bool,
+    pub show_icon: bool,
+}
+
+impl ActiveEffect {
+    pub fn new(effect: Effect, amplifier: i8, duration: Option<u32>) -> Self {
+        Self {
+            effect,
+            amplifier,
+            duration,
+            ambient: false,
+            show_particles: true,
+            show_icon: true,
+        }
+    }
+
+    /// Entity Effect, to show it on `entity_id`
+    pub fn packet(&self, entity_id: i32) -> OutPacket<'static> {
+        OutPacket::EntityEffect {
+            entity_id,
+            effect: *self,
+        }
+    }
+
+    /// Whether this should replace `other`, which is the same effect: vanilla keeps the stronger
+    /// one, or the longer one of the same strength
+    fn overrides(&self, other: &ActiveEffect) -> bool {
+        let longer = match (self.duration, other.duration) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(a), Some(b)) => a > b,
+        };
+        self.amplifier > other.amplifier || (self.amplifier == other.amplifier && longer)
+    }
+}
+
+/// The effects on one entity. Tick it to count their durations down and find out which ran out.
+#[derive(Debug, Clone, Default)]
+pub struct ActiveEffects {
+    effects: Vec<ActiveEffect>,
+}
+
+impl ActiveEffects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an effect, unless a stronger (or as strong and longer) one of the same kind is already
+    /// active. Returns true if it was added, in which case its `packet()` should be sent.
+    pub fn add(&mut self, effect: ActiveEffect) -> bool {
+        match self.effects.iter_mut().find(|e| e.effect == effect.effect) {
+            Some(existing) if !effect.overrides(existing) => false,
+            Some(existing) => {
+                *existing = effect;
+                true
+            }
+            None => {
+                self.effects.push(effect);
+                true
+            }
+        }
+    }
+
+    /// Returns the effect if it was active, in which case a `RemoveEntityEffect` should be sent
+    pub fn remove(&mut self, effect: Effect) -> Option<ActiveEffect> {
+        let i = self.effects.iter().position(|e| e.effect == effect)?;
+        Some(self.effects.remove(i))
+    }
+
+    pub fn get(&self, effect: Effect) -> Option<&ActiveEffect> {
+        self.effects.iter().find(|e| e.effect == effect)
+    }
+
+    pub fn has(&self, effect: Effect) -> bool {
+        self.get(effect).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ActiveEffect> {
+        self.effects.iter()
+    }
+
+    /// Counts every effect down a tick, and returns the ones that ran out (and were removed)
+    pub fn tick(&mut self) -> Vec<Effect> {
+        let mut expired = Vec::new();
+        self.effects.retain_mut(|e| match &mut e.duration {
+            Some(0) | Some(1) => {
+                expired.push(e.effect);
+                false
+            }
+            Some(d) => {
+                *d -= 1;
+                true
+            }
+            None => true,
+        });
+        expired
+    }
+
+    /// Entity Effect packets for every active effect, e.g. for a player that just joined
+    pub fn packets(&self, entity_id: i32) -> Vec<OutPacket<'static>> {
+        self.effects.iter().map(|e| e.packet(entity_id)).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry() {
+        let mut effects = ActiveEffects::new();
+        assert!(effects.add(ActiveEffect::new(Effect::Speed, 0, Some(3))));
+        assert!(effects.add(ActiveEffect::new(Effect::NightVision, 0, None)));
+        // weaker
+        assert!(!effects.add(ActiveEffect::new(Effect::Speed, 0, Some(2))));
+        assert!(effects.add(ActiveEffect::new(Effect::Speed, 1, Some(2))));
+
+        assert!(effects.tick().is_empty());
+        assert_eq!(effects.tick(), [Effect::Speed]);
+        assert!(effects.has(Effect::NightVision));
+        assert_eq!(
+            effects.remove(Effect::NightVision).map(|e| e.duration),
+            Some(None)
+        );
+
+        let glowing = ActiveEffect {
+            ambient: true,
+            ..ActiveEffect::new(Effect::Glowing, 2, Some(200))
+        };
+        assert_eq!(
+            encode_packet(glowing.packet(7), PROTOCOL_VERSION),
+            [0x6E, 7, 23, 2, 0xC8, 0x01, 0x07, 0x00]
+        );
+        assert_eq!(
+            encode_packet(
+                OutPacket::RemoveEntityEffect {
+                    entity_id: 7,
+                    effect: Effect::Darkness
+                },
+                PROTOCOL_VERSION
+            ),
+            [0x41, 7, 32]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::*;
+
+/// Server-side elytra and falling state of one player.
+///
+/// Feed it the player's Player Command and movement packets; it validates
+/// gliding starts, stops gliding on landing, and computes fall damage the
+/// way vanilla does (gliding players only take damage from steep dives).
+#[derive(Debug, Clone, Default)]
+pub struct Glider {
+    last_y: Option<f64>,
+    fall_distance: f64,
+    on_ground: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct MoveOutcome {
+    /// Fall damage to apply to the player (0 if none)
+    pub fall_damage: f32,
+    /// The player landed while gliding. Send `PlayerPose::metadata_packet()` to update everyone.
+    pub stopped_gliding: bool,
+}
+
+impl Glider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fall_distance(&self) -> f64 {
+        self.fall_distance
+    }
+
+    /// Handles `PlayerCommandAction::StartFlyingWithElytra`. `has_elytra` should
+    /// be whether the player is wearing a non-broken elytra.
+    ///
+    /// Returns false if the player isn't allowed to start gliding; the client
+    /// has already started anyway, so send it `PlayerPose::metadata_packet()` to correct it.
+    pub fn try_start(&mut self, pose: &mut PlayerPose, has_elytra: bool) -> bool {
+        let allowed = has_elytra && !self.on_ground && !pose.fall_flying && !pose.swimming;
+        if allowed {
+            pose.fall_flying = true;
+        }
+        allowed
+    }
+
+    /// Handles a movement packet with the player's new feet height
+    pub fn on_move(&mut self, pose: &mut PlayerPose, y: f64, on_ground: bool) -> MoveOutcome {
+        let mut outcome = MoveOutcome::default();
+        let dy = self.last_y.map_or(0.0, |last| y - last);
+        self.last_y = Some(y);
+        self.on_ground = on_ground;
+
+        if dy < 0.0 {
+            self.fall_distance -= dy;
+        }
+        // vanilla resets the fall distance while gliding unless diving fast
+        if pose.fall_flying && dy > -0.5 {
+            self.fall_distance = 1.0;
+        }
+
+        if on_ground {
+            if pose.fall_flying {
+                pose.fall_flying = false;
+                outcome.stopped_gliding = true;
+            }
+            outcome.fall_damage = fall_damage(self.fall_distance);
+            self.fall_distance = 0.0;
+        }
+        outcome
+    }
+
+    /// Resets the fall distance, e.g. after a teleport or landing in water
+    pub fn reset_fall(&mut self) {
+        self.fall_distance = 0.0;
+        self.last_y = None;
+    }
+}
+
+/// Vanilla fall damage (without armor/effects) for falling `fall_distance` blocks
+pub fn fall_damage(fall_distance: f64) -> f32 {
+    (fall_distance - 3.0).ceil().max(0.0) as f32
+}
+
+/// Unit vector in the direction a player is looking
+pub fn look_vector(yaw: f32, pitch: f32) -> [f64; 3] {
+    let (yaw, pitch) = (f64::from(yaw).to_radians(), f64::from(pitch).to_radians());
+    [
+        -yaw.sin() * pitch.cos(),
+        -pitch.sin(),
+        yaw.cos() * pitch.cos(),
+    ]
+}
+
+/// One tick of firework acceleration applied to a gliding player's velocity (blocks per tick)
+pub fn boost_velocity(look: [f64; 3], velocity: [f64; 3]) -> [f64; 3] {
+    std::array::from_fn(|i| velocity[i] + look[i] * 0.1 + (look[i] * 1.5 - velocity[i]) * 0.5)
+}
+
+/// A firework rocket boosting a gliding player.
+///
+/// Sending `spawn_packets()` to the boosting player makes their client apply the
+/// boost itself; other players see the rocket attached to the player. The server only
+/// needs to `tick()` it (to update its idea of the player's velocity) and despawn it.
+#[derive(Debug)]
+pub struct FireworkBoost {
+    entity_id: i32,
+    uuid: Uuid,
+    ticks_left: u32,
+    metadata: [MetadataEntry<'static>; 1],
+}
+
+impl FireworkBoost {
+    /// `flight_duration` is the rocket's flight duration (1-3, the number of gunpowder used)
+    pub fn new(entity_id: i32, uuid: Uuid, shooter_entity_id: i32, flight_duration: u8) -> Self {
+        Self {
+            entity_id,
+            uuid,
+            // vanilla adds some randomness on top of this
+            ticks_left: 10 * (u32::from(flight_duration) + 1) + 6,
+            metadata: [MetadataEntry {
+                // the entity the rocket is attached to
+                index: 9,
+                value: MetadataValue::OptVarInt(Some(shooter_entity_id)),
+            }],
+        }
+    }
+
+    pub fn entity_id(&self) -> i32 {
+        self.entity_id
+    }
+
+    /// Spawns the rocket at the shooter's position
+    pub fn spawn_packets(&self, pos: [f64; 3]) -> [OutPacket<'_>; 2] {
+        [
+            OutPacket::SpawnEntity {
+                entity_id: self.entity_id,
+                uuid: self.uuid,
+                entity_type: EntityType::FireworkRocket,
+                x: pos[0],
+                y: pos[1],
+                z: pos[2],
+                pitch: Angle(0),
+                yaw: Angle(0),
+                head_yaw: Angle(0),
+                data: 0,
+                velocity: [0; 3],
+            },
+            OutPacket::SetEntityMetadata {
+                entity_id: self.entity_id,
+                metadata: &self.metadata,
+            },
+        ]
+    }
+
+    /// Applies one tick of boost to `velocity` while the shooter is still gliding.
+    /// Returns false once the rocket has burnt out, after which `despawn_packet()` should be sent.
+    pub fn tick(&mut self, pose: &PlayerPose, look: [f64; 3], velocity: &mut [f64; 3]) -> bool {
+        if self.ticks_left == 0 {
+            return false;
+        }
+        self.ticks_left -= 1;
+        if pose.fall_flying {
+            *velocity = boost_velocity(look, *velocity);
+        }
+        true
+    }
+
+    pub fn despawn_packet(&self) -> OutPacket<'_> {
+        OutPacket::RemoveEntities {
+            entity_ids: std::slice::from_ref(&self.entity_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glide_and_land() {
+        let mut pose = PlayerPose::new();
+        let mut g = Glider::new();
+
+        g.on_move(&mut pose, 100.0, true);
+        assert!(!g.try_start(&mut pose, true), "can't glide on the ground");
+
+        g.on_move(&mut pose, 99.0, false);
+        assert!(!g.try_start(&mut pose, false), "no elytra");
+        assert!(g.try_start(&mut pose, true));
+        assert_eq!(pose.pose(), Pose::FallFlying);
+
+        // a long, gentle glide doesn't accumulate fall distance
+        let mut y = 99.0;
+        for _ in 0..100 {
+            y -= 0.3;
+            g.on_move(&mut pose, y, false);
+        }
+        let out = g.on_move(&mut pose, y - 0.3, true);
+        assert!(out.stopped_gliding);
+        assert_eq!(out.fall_damage, 0.0);
+        assert_eq!(pose.pose(), Pose::Standing);
+
+        // but a regular fall does
+        g.on_move(&mut pose, 110.0, false);
+        let out = g.on_move(&mut pose, 100.0, true);
+        assert_eq!(out.fall_damage, 7.0);
+    }
+
+    #[test]
+    fn firework() {
+        let mut pose = PlayerPose::new();
+        pose.fall_flying = true;
+        let mut boost = FireworkBoost::new(50, Uuid(1), 7, 1);
+        let look = look_vector(0.0, 0.0);
+        assert!((look[2] - 1.0).abs() < 1e-9, "yaw 0 faces +z");
+
+        let mut vel = [0.0; 3];
+        let mut ticks = 0;
+        while boost.tick(&pose, look, &mut vel) {
+            ticks += 1;
+        }
+        assert_eq!(ticks, 26);
+        // converges towards 1.5 * look
+        assert!(vel[2] > 1.5 && vel[2] < 1.7, "{vel:?}");
+        assert!(vel[0].abs() < 1e-9);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::*;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// A chunk's data, owned so that its packet can be encoded on another thread
+pub trait ChunkPacketSource: Send + Sync + 'static {
+    /// Its `ChunkDataAndUpdateLight`
+    fn packet(&self) -> OutPacket<'_>;
+}
+
+/// A packet encoded by a `ChunkEncodePool`
+#[derive(Debug)]
+pub struct EncodedChunk {
+    pub chunk: ChunkPos,
+    pub protocol_version: i32,
+    /// As `encode_packet()` encodes it, or the panic's payload if making or encoding the packet
+    /// panicked, for `std::panic::resume_unwind()`
+    pub packet: thread::Result<Vec<u8>>,
+}
+
+struct Job {
+    chunk: ChunkPos,
+    protocol_version: i32,
+    source: Arc<dyn ChunkPacketSource>,
+}
+
+/// Encodes chunk packets on worker threads, so that loading lots of chunks at once (e.g. when
+/// players join) doesn't hold up the tick. Finished packets are picked up with `completed()`.
+pub struct ChunkEncodePool {
+    /// None once dropped, to stop the workers
+    jobs: Option<Sender<Job>>,
+    done: Receiver<EncodedChunk>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ChunkEncodePool {
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0, "ChunkEncodePool needs at least one thread");
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let (done_tx, done) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let workers = (0..threads)
+            .map(|_| {
+                let job_rx = Arc::clone(&job_rx);
+                let done_tx = done_tx.clone();
+                thread::spawn(move || loop {
+                    // the lock is only held while waiting for a job, not while encoding it
+                    let Ok(job) = job_rx.lock().unwrap().recv() else {
+                        return;
+                    };
+                    let packet = panic::catch_unwind(AssertUnwindSafe(|| {
+                        encode_packet(job.source.packet(), job.protocol_version)
+                    }));
+                    let encoded = EncodedChunk {
+                        chunk: job.chunk,
+                        protocol_version: job.protocol_version,
+                        packet,
+                    };
+                    if done_tx.send(encoded).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Self {
+            jobs: Some(jobs),
+            done,
+            workers,
+        }
+    }
+
+    /// One thread per CPU but one (which is left for the tick loop), and at least one
+    pub fn default_threads() -> usize {
+        thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1).max(1))
+    }
+
+    /// Queues `chunk`'s packet to be encoded for `protocol_version`
+    pub fn submit(
+        &self,
+        chunk: ChunkPos,
+        protocol_version: i32,
+        source: Arc<dyn ChunkPacketSource>,
+    ) {
+        let job = Job {
+            chunk,
+            protocol_version,
+            source,
+        };
+        // the workers only stop once we're dropped
+        self.jobs.as_ref().unwrap().send(job).unwrap();
+    }
+
+    /// The packets finished since the last call, without waiting for any
+    pub fn completed(&self) -> impl Iterator<Item = EncodedChunk> + '_ {
+        self.done.try_iter()
+    }
+}
+
+impl Drop for ChunkEncodePool {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for ChunkEncodePool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkEncodePool")
+            .field("threads", &self.workers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    struct Finish;
+
+    impl ChunkPacketSource for Finish {
+        fn packet(&self) -> OutPacket<'_> {
+            OutPacket::FinishConfig
+        }
+    }
+
+    struct Panics;
+
+    impl ChunkPacketSource for Panics {
+        fn packet(&self) -> OutPacket<'_> {
+            panic!("no chunk here");
+        }
+    }
+
+    #[test]
+    fn encodes_on_workers() {
+        let pool = ChunkEncodePool::new(2);
+        for x in 0..4 {
+            pool.submit(ChunkPos::new(x, 0), PROTOCOL_VERSION, Arc::new(Finish));
+        }
+        pool.submit(ChunkPos::new(9, 9), PROTOCOL_VERSION, Arc::new(Panics));
+
+        let mut done = Vec::new();
+        let start = Instant::now();
+        while done.len() < 5 && start.elapsed() < Duration::from_secs(5) {
+            done.extend(pool.completed());
+            thread::sleep(Duration::from_millis(1));
+        }
+        done.sort_by_key(|e| e.chunk);
+        assert_eq!(done.len(), 5);
+        assert_eq!(done[0].packet.as_deref().ok(), Some(&[0x02][..]));
+        let payload = done.pop().unwrap().packet.unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<&str>(),
+            Some(&"no chunk here"),
+            "the panic only lost its own chunk"
+        );
+    }
+}
@@ -0,0 +1,725 @@
+use crate::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Pose {
+    Standing = 0,
+    FallFlying = 1,
+    Sleeping = 2,
+    Swimming = 3,
+    SpinAttack = 4,
+    Sneaking = 5,
+    LongJumping = 6,
+    Dying = 7,
+    Croaking = 8,
+    UsingTongue = 9,
+    Sitting = 10,
+    Roaring = 11,
+    Sniffing = 12,
+    Emerging = 13,
+    Digging = 14,
+}
+
+/// Entity types, with their protocol IDs
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum EntityType {
+    Allay = 0,
+    AreaEffectCloud = 1,
+    ArmorStand = 2,
+    Arrow = 3,
+    Axolotl = 4,
+    Bat = 5,
+    Bee = 6,
+    Blaze = 7,
+    BlockDisplay = 8,
+    Boat = 9,
+    Camel = 10,
+    Cat = 11,
+    CaveSpider = 12,
+    ChestBoat = 13,
+    ChestMinecart = 14,
+    Chicken = 15,
+    Cod = 16,
+    CommandBlockMinecart = 17,
+    Cow = 18,
+    Creeper = 19,
+    Dolphin = 20,
+    Donkey = 21,
+    DragonFireball = 22,
+    Drowned = 23,
+    Egg = 24,
+    ElderGuardian = 25,
+    EndCrystal = 26,
+    EnderDragon = 27,
+    EnderPearl = 28,
+    Enderman = 29,
+    Endermite = 30,
+    Evoker = 31,
+    EvokerFangs = 32,
+    ExperienceBottle = 33,
+    ExperienceOrb = 34,
+    EyeOfEnder = 35,
+    FallingBlock = 36,
+    FireworkRocket = 37,
+    Fox = 38,
+    Frog = 39,
+    FurnaceMinecart = 40,
+    Ghast = 41,
+    Giant = 42,
+    GlowItemFrame = 43,
+    GlowSquid = 44,
+    Goat = 45,
+    Guardian = 46,
+    Hoglin = 47,
+    HopperMinecart = 48,
+    Horse = 49,
+    Husk = 50,
+    Illusioner = 51,
+    Interaction = 52,
+    IronGolem = 53,
+    Item = 54,
+    ItemDisplay = 55,
+    ItemFrame = 56,
+    Fireball = 57,
+    LeashKnot = 58,
+    LightningBolt = 59,
+    Llama = 60,
+    LlamaSpit = 61,
+    MagmaCube = 62,
+    Marker = 63,
+    Minecart = 64,
+    Mooshroom = 65,
+    Mule = 66,
+    Ocelot = 67,
+    Painting = 68,
+    Panda = 69,
+    Parrot = 70,
+    Phantom = 71,
+    Pig = 72,
+    Piglin = 73,
+    PiglinBrute = 74,
+    Pillager = 75,
+    PolarBear = 76,
+    Potion = 77,
+    Pufferfish = 78,
+    Rabbit = 79,
+    Ravager = 80,
+    Salmon = 81,
+    Sheep = 82,
+    Shulker = 83,
+    ShulkerBullet = 84,
+    Silverfish = 85,
+    Skeleton = 86,
+    SkeletonHorse = 87,
+    Slime = 88,
+    SmallFireball = 89,
+    Sniffer = 90,
+    SnowGolem = 91,
+    Snowball = 92,
+    SpawnerMinecart = 93,
+    SpectralArrow = 94,
+    Spider = 95,
+    Squid = 96,
+    Stray = 97,
+    Strider = 98,
+    Tadpole = 99,
+    TextDisplay = 100,
+    Tnt = 101,
+    TntMinecart = 102,
+    TraderLlama = 103,
+    Trident = 104,
+    TropicalFish = 105,
+    Turtle = 106,
+    Vex = 107,
+    Villager = 108,
+    Vindicator = 109,
+    WanderingTrader = 110,
+    Warden = 111,
+    Witch = 112,
+    Wither = 113,
+    WitherSkeleton = 114,
+    WitherSkull = 115,
+    Wolf = 116,
+    Zoglin = 117,
+    Zombie = 118,
+    ZombieHorse = 119,
+    ZombieVillager = 120,
+    ZombifiedPiglin = 121,
+    Player = 122,
+    FishingBobber = 123,
+}
+
+impl EntityType {
+    /// Every entity type, in protocol ID order
+    pub const ALL: [EntityType; 124] = {
+        use EntityType::*;
+        [
+            Allay,
+            AreaEffectCloud,
+            ArmorStand,
+            Arrow,
+            Axolotl,
+            Bat,
+            Bee,
+            Blaze,
+            BlockDisplay,
+            Boat,
+            Camel,
+            Cat,
+            CaveSpider,
+            ChestBoat,
+            ChestMinecart,
+            Chicken,
+            Cod,
+            CommandBlockMinecart,
+            Cow,
+            Creeper,
+            Dolphin,
+            Donkey,
+            DragonFireball,
+            Drowned,
+            Egg,
+            ElderGuardian,
+            EndCrystal,
+            EnderDragon,
+            EnderPearl,
+            Enderman,
+            Endermite,
+            Evoker,
+            EvokerFangs,
+            ExperienceBottle,
+            ExperienceOrb,
+            EyeOfEnder,
+            FallingBlock,
+            FireworkRocket,
+            Fox,
+            Frog,
+            FurnaceMinecart,
+            Ghast,
+            Giant,
+            GlowItemFrame,
+            GlowSquid,
+            Goat,
+            Guardian,
+            Hoglin,
+            HopperMinecart,
+            Horse,
+            Husk,
+            Illusioner,
+            Interaction,
+            IronGolem,
+            Item,
+            ItemDisplay,
+            ItemFrame,
+            Fireball,
+            LeashKnot,
+            LightningBolt,
+            Llama,
+            LlamaSpit,
+            MagmaCube,
+            Marker,
+            Minecart,
+            Mooshroom,
+            Mule,
+            Ocelot,
+            Painting,
+            Panda,
+            Parrot,
+            Phantom,
+            Pig,
+            Piglin,
+            PiglinBrute,
+            Pillager,
+            PolarBear,
+            Potion,
+            Pufferfish,
+            Rabbit,
+            Ravager,
+            Salmon,
+            Sheep,
+            Shulker,
+            ShulkerBullet,
+            Silverfish,
+            Skeleton,
+            SkeletonHorse,
+            Slime,
+            SmallFireball,
+            Sniffer,
+            SnowGolem,
+            Snowball,
+            SpawnerMinecart,
+            SpectralArrow,
+            Spider,
+            Squid,
+            Stray,
+            Strider,
+            Tadpole,
+            TextDisplay,
+            Tnt,
+            TntMinecart,
+            TraderLlama,
+            Trident,
+            TropicalFish,
+            Turtle,
+            Vex,
+            Villager,
+            Vindicator,
+            WanderingTrader,
+            Warden,
+            Witch,
+            Wither,
+            WitherSkeleton,
+            WitherSkull,
+            Wolf,
+            Zoglin,
+            Zombie,
+            ZombieHorse,
+            ZombieVillager,
+            ZombifiedPiglin,
+            Player,
+            FishingBobber,
+        ]
+    };
+
+    /// The type with this protocol ID
+    pub fn from_id(id: i32) -> Option<Self> {
+        usize::try_from(id)
+            .ok()
+            .and_then(|i| Self::ALL.get(i).copied())
+    }
+
+    /// The type with this identifier, e.g. `minecraft:zombie`. The namespace can be left out.
+    pub fn from_identifier(identifier: &str) -> Option<Self> {
+        let identifier = identifier.strip_prefix("minecraft:").unwrap_or(identifier);
+        Self::ALL
+            .into_iter()
+            .find(|t| t.identifier().strip_prefix("minecraft:") == Some(identifier))
+    }
+
+    pub fn identifier(self) -> &'static str {
+        use EntityType::*;
+        match self {
+            Allay => "minecraft:allay",
+            AreaEffectCloud => "minecraft:area_effect_cloud",
+            ArmorStand => "minecraft:armor_stand",
+            Arrow => "minecraft:arrow",
+            Axolotl => "minecraft:axolotl",
+            Bat => "minecraft:bat",
+            Bee => "minecraft:bee",
+            Blaze => "minecraft:blaze",
+            BlockDisplay => "minecraft:block_display",
+            Boat => "minecraft:boat",
+            Camel => "minecraft:camel",
+            Cat => "minecraft:cat",
+            CaveSpider => "minecraft:cave_spider",
+            ChestBoat => "minecraft:chest_boat",
+            ChestMinecart => "minecraft:chest_minecart",
+            Chicken => "minecraft:chicken",
+            Cod => "minecraft:cod",
+            CommandBlockMinecart => "minecraft:command_block_minecart",
+            Cow => "minecraft:cow",
+            Creeper => "minecraft:creeper",
+            Dolphin => "minecraft:dolphin",
+            Donkey => "minecraft:donkey",
+            DragonFireball => "minecraft:dragon_fireball",
+            Drowned => "minecraft:drowned",
+            Egg => "minecraft:egg",
+            ElderGuardian => "minecraft:elder_guardian",
+            EndCrystal => "minecraft:end_crystal",
+            EnderDragon => "minecraft:ender_dragon",
+            EnderPearl => "minecraft:ender_pearl",
+            Enderman => "minecraft:enderman",
+            Endermite => "minecraft:endermite",
+            Evoker => "minecraft:evoker",
+            EvokerFangs => "minecraft:evoker_fangs",
+            ExperienceBottle => "minecraft:experience_bottle",
+            ExperienceOrb => "minecraft:experience_orb",
+            EyeOfEnder => "minecraft:eye_of_ender",
+            FallingBlock => "minecraft:falling_block",
+            FireworkRocket => "minecraft:firework_rocket",
+            Fox => "minecraft:fox",
+            Frog => "minecraft:frog",
+            FurnaceMinecart => "minecraft:furnace_minecart",
+            Ghast => "minecraft:ghast",
+            Giant => "minecraft:giant",
+            GlowItemFrame => "minecraft:glow_item_frame",
+            GlowSquid => "minecraft:glow_squid",
+            Goat => "minecraft:goat",
+            Guardian => "minecraft:guardian",
+            Hoglin => "minecraft:hoglin",
+            HopperMinecart => "minecraft:hopper_minecart",
+            Horse => "minecraft:horse",
+            Husk => "minecraft:husk",
+            Illusioner => "minecraft:illusioner",
+            Interaction => "minecraft:interaction",
+            IronGolem => "minecraft:iron_golem",
+            Item => "minecraft:item",
+            ItemDisplay => "minecraft:item_display",
+            ItemFrame => "minecraft:item_frame",
+            Fireball => "minecraft:fireball",
+            LeashKnot => "minecraft:leash_knot",
+            LightningBolt => "minecraft:lightning_bolt",
+            Llama => "minecraft:llama",
+            LlamaSpit => "minecraft:llama_spit",
+            MagmaCube => "minecraft:magma_cube",
+            Marker => "minecraft:marker",
+            Minecart => "minecraft:minecart",
+            Mooshroom => "minecraft:mooshroom",
+            Mule => "minecraft:mule",
+            Ocelot => "minecraft:ocelot",
+            Painting => "minecraft:painting",
+            Panda => "minecraft:panda",
+            Parrot => "minecraft:parrot",
+            Phantom => "minecraft:phantom",
+            Pig => "minecraft:pig",
+            Piglin => "minecraft:piglin",
+            PiglinBrute => "minecraft:piglin_brute",
+            Pillager => "minecraft:pillager",
+            PolarBear => "minecraft:polar_bear",
+            Potion => "minecraft:potion",
+            Pufferfish => "minecraft:pufferfish",
+            Rabbit => "minecraft:rabbit",
+            Ravager => "minecraft:ravager",
+            Salmon => "minecraft:salmon",
+            Sheep => "minecraft:sheep",
+            Shulker => "minecraft:shulker",
+            ShulkerBullet => "minecraft:shulker_bullet",
+            Silverfish => "minecraft:silverfish",
+            Skeleton => "minecraft:skeleton",
+            SkeletonHorse => "minecraft:skeleton_horse",
+            Slime => "minecraft:slime",
+            SmallFireball => "minecraft:small_fireball",
+            Sniffer => "minecraft:sniffer",
+            SnowGolem => "minecraft:snow_golem",
+            Snowball => "minecraft:snowball",
+            SpawnerMinecart => "minecraft:spawner_minecart",
+            SpectralArrow => "minecraft:spectral_arrow",
+            Spider => "minecraft:spider",
+            Squid => "minecraft:squid",
+            Stray => "minecraft:stray",
+            Strider => "minecraft:strider",
+            Tadpole => "minecraft:tadpole",
+            TextDisplay => "minecraft:text_display",
+            Tnt => "minecraft:tnt",
+            TntMinecart => "minecraft:tnt_minecart",
+            TraderLlama => "minecraft:trader_llama",
+            Trident => "minecraft:trident",
+            TropicalFish => "minecraft:tropical_fish",
+            Turtle => "minecraft:turtle",
+            Vex => "minecraft:vex",
+            Villager => "minecraft:villager",
+            Vindicator => "minecraft:vindicator",
+            WanderingTrader => "minecraft:wandering_trader",
+            Warden => "minecraft:warden",
+            Witch => "minecraft:witch",
+            Wither => "minecraft:wither",
+            WitherSkeleton => "minecraft:wither_skeleton",
+            WitherSkull => "minecraft:wither_skull",
+            Wolf => "minecraft:wolf",
+            Zoglin => "minecraft:zoglin",
+            Zombie => "minecraft:zombie",
+            ZombieHorse => "minecraft:zombie_horse",
+            ZombieVillager => "minecraft:zombie_villager",
+            ZombifiedPiglin => "minecraft:zombified_piglin",
+            Player => "minecraft:player",
+            FishingBobber => "minecraft:fishing_bobber",
+        }
+    }
+
+    /// How far away clients are sent this type of entity, in chunks (vanilla's
+    /// `clientTrackingRange`). Clients still only see as far as their view distance.
+    pub fn tracking_range(self) -> u32 {
+        use EntityType::*;
+        match self {
+            Player => 32,
+            EndCrystal | EnderDragon | LightningBolt | Warden => 16,
+            Allay | ArmorStand | Axolotl | Boat | Camel | Cat | ChestBoat | ChestMinecart
+            | Chicken | CommandBlockMinecart | Cow | Dolphin | Donkey | Fox | Frog
+            | FurnaceMinecart | Goat | GlowSquid | HopperMinecart | Horse | IronGolem | Llama
+            | Minecart | Mooshroom | Mule | Ocelot | Panda | Parrot | Pig | PolarBear | Rabbit
+            | Sheep | SkeletonHorse | Sniffer | SnowGolem | SpawnerMinecart | Squid | Strider
+            | Tadpole | TntMinecart | TraderLlama | Turtle | Villager | WanderingTrader | Wolf
+            | ZombieHorse | BlockDisplay | ItemDisplay | TextDisplay | Interaction
+            | FallingBlock | Tnt | ItemFrame | GlowItemFrame | Painting | LeashKnot
+            | AreaEffectCloud => 10,
+            Item | ExperienceOrb | EvokerFangs => 6,
+            Bat => 5,
+            Arrow | Egg | Snowball | Potion | Trident | Fireball | SmallFireball
+            | DragonFireball | WitherSkull | LlamaSpit | EnderPearl | EyeOfEnder
+            | ExperienceBottle | FireworkRocket | SpectralArrow | ShulkerBullet | FishingBobber
+            | Cod | Salmon | Pufferfish | TropicalFish => 4,
+            Marker => 0,
+            _ => 8,
+        }
+    }
+}
+
+/// Size of an entity's hitbox, in blocks
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EntityDimensions {
+    pub width: f64,
+    pub height: f64,
+    pub eye_height: f64,
+}
+
+impl Pose {
+    /// The dimensions of a player's hitbox in this pose (same as vanilla's `Player.POSES`)
+    pub fn player_dimensions(self) -> EntityDimensions {
+        let (width, height, eye_height) = match self {
+            Pose::Sneaking => (0.6, 1.5, 1.27),
+            Pose::FallFlying | Pose::Swimming | Pose::SpinAttack => (0.6, 0.6, 0.4),
+            Pose::Sleeping | Pose::Dying => (0.2, 0.2, 0.2),
+            _ => (0.6, 1.8, 1.62),
+        };
+        EntityDimensions {
+            width,
+            height,
+            eye_height,
+        }
+    }
+}
+
+/// Bits of the shared entity flags byte (metadata index 0)
+pub mod entity_flags {
+    pub const ON_FIRE: i8 = 0x01;
+    pub const CROUCHING: i8 = 0x02;
+    pub const SPRINTING: i8 = 0x08;
+    pub const SWIMMING: i8 = 0x10;
+    pub const INVISIBLE: i8 = 0x20;
+    pub const GLOWING: i8 = 0x40;
+    pub const FALL_FLYING: i8 = 0x80_u8 as i8;
+}
+
+/// Metadata indices shared by all entities
+pub mod metadata_index {
+    pub const FLAGS: u8 = 0;
+    pub const AIR_TICKS: u8 = 1;
+    pub const CUSTOM_NAME: u8 = 2;
+    pub const CUSTOM_NAME_VISIBLE: u8 = 3;
+    pub const SILENT: u8 = 4;
+    pub const NO_GRAVITY: u8 = 5;
+    pub const POSE: u8 = 6;
+    pub const TICKS_FROZEN: u8 = 7;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue<'a> {
+    Byte(i8),
+    VarInt(i32),
+    Float(f32),
+    String(&'a str),
+    Chat(&'a TextComponent),
+    OptChat(Option<&'a TextComponent>),
+    Boolean(bool),
+    /// An entity ID, most of the time
+    OptVarInt(Option<i32>),
+    Pose(Pose),
+}
+
+impl MetadataValue<'_> {
+    /// The type ID used in the Set Entity Metadata packet
+    pub(crate) fn type_id(&self) -> i32 {
+        match self {
+            MetadataValue::Byte(_) => 0,
+            MetadataValue::VarInt(_) => 1,
+            MetadataValue::Float(_) => 3,
+            MetadataValue::String(_) => 4,
+            MetadataValue::Chat(_) => 5,
+            MetadataValue::OptChat(_) => 6,
+            MetadataValue::Boolean(_) => 8,
+            MetadataValue::OptVarInt(_) => 19,
+            MetadataValue::Pose(_) => 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataEntry<'a> {
+    pub index: u8,
+    pub value: MetadataValue<'a>,
+}
+
+/// Where an entity holds or wears an item, with its protocol ID
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EquipmentSlot {
+    MainHand = 0,
+    OffHand = 1,
+    Boots = 2,
+    Leggings = 3,
+    Chestplate = 4,
+    Helmet = 5,
+}
+
+impl EquipmentSlot {
+    pub const ALL: [Self; 6] = [
+        Self::MainHand,
+        Self::OffHand,
+        Self::Boots,
+        Self::Leggings,
+        Self::Chestplate,
+        Self::Helmet,
+    ];
+}
+
+/// Converts a velocity in blocks per tick to the protocol's fixed-point form
+pub fn encode_velocity(v: [f64; 3]) -> [i16; 3] {
+    // the client clamps velocities to this too
+    v.map(|x| (x.clamp(-3.9, 3.9) * 8000.0) as i16)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayerCommandAction {
+    StartSneaking,
+    StopSneaking,
+    LeaveBed,
+    StartSprinting,
+    StopSprinting,
+    StartHorseJump,
+    StopHorseJump,
+    OpenVehicleInventory,
+    StartFlyingWithElytra,
+}
+
+/// Tracks a player's movement state flags and derives their `Pose` from them,
+/// so the pose (and hitbox) stays in sync with what the client is doing.
+#[derive(Debug, Clone)]
+pub struct PlayerPose {
+    pub sneaking: bool,
+    pub sprinting: bool,
+    pub swimming: bool,
+    pub fall_flying: bool,
+    pub sleeping: bool,
+    metadata: [MetadataEntry<'static>; 2],
+}
+
+impl Default for PlayerPose {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PlayerPose {
+    pub fn new() -> Self {
+        Self {
+            sneaking: false,
+            sprinting: false,
+            swimming: false,
+            fall_flying: false,
+            sleeping: false,
+            metadata: [
+                MetadataEntry {
+                    index: metadata_index::FLAGS,
+                    value: MetadataValue::Byte(0),
+                },
+                MetadataEntry {
+                    index: metadata_index::POSE,
+                    value: MetadataValue::Pose(Pose::Standing),
+                },
+            ],
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        if self.sleeping {
+            Pose::Sleeping
+        } else if self.fall_flying {
+            Pose::FallFlying
+        } else if self.swimming {
+            Pose::Swimming
+        } else if self.sneaking {
+            Pose::Sneaking
+        } else {
+            Pose::Standing
+        }
+    }
+
+    pub fn dimensions(&self) -> EntityDimensions {
+        self.pose().player_dimensions()
+    }
+
+    /// The entity flags byte (metadata index 0) matching the current state
+    pub fn flags(&self) -> i8 {
+        let mut flags = 0;
+        if self.sneaking {
+            flags |= entity_flags::CROUCHING;
+        }
+        if self.sprinting {
+            flags |= entity_flags::SPRINTING;
+        }
+        if self.swimming {
+            flags |= entity_flags::SWIMMING;
+        }
+        if self.fall_flying {
+            flags |= entity_flags::FALL_FLYING;
+        }
+        flags
+    }
+
+    /// Updates the state from an `InPacket::PlayerCommand` action.
+    /// Returns true if the pose or flags changed, i.e. `metadata_packet()` should be sent to other players.
+    pub fn handle_command(&mut self, action: PlayerCommandAction) -> bool {
+        let before = (self.pose(), self.flags());
+        match action {
+            PlayerCommandAction::StartSneaking => self.sneaking = true,
+            PlayerCommandAction::StopSneaking => self.sneaking = false,
+            PlayerCommandAction::StartSprinting => self.sprinting = true,
+            PlayerCommandAction::StopSprinting => self.sprinting = false,
+            PlayerCommandAction::LeaveBed => self.sleeping = false,
+            // needs validation, so this is up to `Glider::try_start()`
+            PlayerCommandAction::StartFlyingWithElytra
+            | PlayerCommandAction::StartHorseJump
+            | PlayerCommandAction::StopHorseJump
+            | PlayerCommandAction::OpenVehicleInventory => {}
+        }
+        before != (self.pose(), self.flags())
+    }
+
+    /// Set Entity Metadata packet with the player's flags and pose
+    pub fn metadata_packet(&mut self, entity_id: i32) -> OutPacket<'_> {
+        self.metadata[0].value = MetadataValue::Byte(self.flags());
+        self.metadata[1].value = MetadataValue::Pose(self.pose());
+        OutPacket::SetEntityMetadata {
+            entity_id,
+            metadata: &self.metadata,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pose_from_commands() {
+        let mut p = PlayerPose::new();
+        assert_eq!(p.pose(), Pose::Standing);
+        assert_eq!(p.dimensions().height, 1.8);
+
+        assert!(p.handle_command(PlayerCommandAction::StartSneaking));
+        assert_eq!(p.pose(), Pose::Sneaking);
+        assert_eq!(p.dimensions().height, 1.5);
+        assert_eq!(p.flags(), entity_flags::CROUCHING);
+
+        assert!(!p.handle_command(PlayerCommandAction::StartSneaking));
+        assert!(p.handle_command(PlayerCommandAction::StartSprinting));
+        assert_eq!(p.flags(), entity_flags::CROUCHING | entity_flags::SPRINTING);
+
+        assert!(p.handle_command(PlayerCommandAction::StopSneaking));
+        assert_eq!(p.pose(), Pose::Standing);
+
+        let OutPacket::SetEntityMetadata {
+            entity_id,
+            metadata,
+        } = p.metadata_packet(12)
+        else {
+            unreachable!()
+        };
+        assert_eq!(entity_id, 12);
+        assert_eq!(
+            metadata[0].value,
+            MetadataValue::Byte(entity_flags::SPRINTING)
+        );
+        assert_eq!(metadata[1].value, MetadataValue::Pose(Pose::Standing));
+    }
+}
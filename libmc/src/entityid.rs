@@ -0,0 +1,121 @@
+use crate::*;
+use std::collections::{HashSet, VecDeque};
+
+/// Hands out entity IDs and UUIDs, so no two entities on the server share one.
+///
+/// Freed IDs are reused, oldest first, so an ID is only reused long after clients were told its
+/// entity is gone. IDs are allocated from 1 upwards; some clients treat 0 as "no entity".
+#[derive(Debug)]
+pub struct EntityIdAllocator {
+    next_id: i32,
+    free_ids: VecDeque<i32>,
+    live_ids: HashSet<i32>,
+    live_uuids: HashSet<Uuid>,
+}
+
+impl Default for EntityIdAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntityIdAllocator {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            free_ids: VecDeque::new(),
+            live_ids: HashSet::new(),
+            live_uuids: HashSet::new(),
+        }
+    }
+
+    /// An ID that no living entity has
+    pub fn allocate_id(&mut self) -> i32 {
+        let id = match self.free_ids.pop_front() {
+            Some(id) => id,
+            None => self.fresh_ids(1),
+        };
+        self.live_ids.insert(id);
+        id
+    }
+
+    /// Reserves `count` consecutive IDs, e.g. for an `EntityStore`, and returns the first.
+    /// They're never reused.
+    pub fn reserve_range(&mut self, count: u32) -> i32 {
+        self.fresh_ids(count)
+    }
+
+    fn fresh_ids(&mut self, count: u32) -> i32 {
+        let first = self.next_id;
+        self.next_id = i32::try_from(count)
+            .ok()
+            .and_then(|count| first.checked_add(count))
+            .expect("ran out of entity IDs");
+        first
+    }
+
+    /// Lets `id` be handed out again, once the clients have been told its entity is gone.
+    /// Returns false if it wasn't allocated by `allocate_id()`, or was already freed.
+    pub fn free_id(&mut self, id: i32) -> bool {
+        if !self.live_ids.remove(&id) {
+            return false;
+        }
+        self.free_ids.push_back(id);
+        true
+    }
+
+    /// How many IDs from `allocate_id()` haven't been freed
+    pub fn live_ids(&self) -> usize {
+        self.live_ids.len()
+    }
+
+    /// A random UUID that no living entity has
+    pub fn allocate_uuid(&mut self) -> Uuid {
+        loop {
+            let uuid = Uuid::random();
+            if self.live_uuids.insert(uuid) {
+                return uuid;
+            }
+        }
+    }
+
+    /// Marks a UUID that came from elsewhere, e.g. a saved entity's, as taken.
+    /// Returns false if it already was.
+    pub fn reserve_uuid(&mut self, uuid: Uuid) -> bool {
+        self.live_uuids.insert(uuid)
+    }
+
+    /// Returns false if `uuid` wasn't taken
+    pub fn free_uuid(&mut self, uuid: Uuid) -> bool {
+        self.live_uuids.remove(&uuid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recycles_oldest_first() {
+        let mut ids = EntityIdAllocator::new();
+        let a = ids.allocate_id();
+        let b = ids.allocate_id();
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(ids.reserve_range(10), 3);
+        assert_eq!(ids.allocate_id(), 13);
+
+        assert!(ids.free_id(b));
+        assert!(ids.free_id(a));
+        assert!(!ids.free_id(a));
+        assert!(!ids.free_id(5));
+        assert_eq!(ids.live_ids(), 1);
+        assert_eq!(ids.allocate_id(), b);
+        assert_eq!(ids.allocate_id(), a);
+        assert_eq!(ids.allocate_id(), 14);
+
+        let uuid = ids.allocate_uuid();
+        assert!(!ids.reserve_uuid(uuid));
+        assert!(ids.free_uuid(uuid));
+        assert!(ids.reserve_uuid(uuid));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::nbt::invalid;
+use crate::*;
+use std::io;
+
+/// An entity as vanilla saves it: the fields libmc models, and the whole compound for everything
+/// else (health, items, AI state...), so saving it again loses nothing
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedEntity {
+    pub kind: EntityType,
+    pub uuid: Uuid,
+    pub pos: Vec3,
+    /// In blocks per tick
+    pub velocity: Vec3,
+    /// In degrees
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+    pub nbt: CompoundNbt<'static>,
+}
+
+impl SavedEntity {
+    /// Returns None for entity types libmc doesn't know, e.g. from a newer version
+    pub fn from_nbt(nbt: &CompoundNbt<'static>) -> io::Result<Option<Self>> {
+        let Some(Nbt::String(id)) = nbt.get("id") else {
+            return Err(invalid("entity without an id"));
+        };
+        let Some(kind) = EntityType::from_identifier(id) else {
+            return Ok(None);
+        };
+        let uuid =
+            Uuid::from_compound(nbt, "UUID").ok_or_else(|| invalid("entity without a UUID"))?;
+        let pos = match nbt.get("Pos") {
+            Some(Nbt::List(NbtList::Double(pos))) if pos.len() == 3 => {
+                Vec3::from([pos[0], pos[1], pos[2]])
+            }
+            _ => return Err(invalid("entity without a Pos")),
+        };
+        let velocity = match nbt.get("Motion") {
+            Some(Nbt::List(NbtList::Double(v))) if v.len() == 3 => Vec3::from([v[0], v[1], v[2]]),
+            _ => Vec3::default(),
+        };
+        let [yaw, pitch] = rotation_of(nbt).unwrap_or_default();
+        Ok(Some(Self {
+            kind,
+            uuid,
+            pos,
+            velocity,
+            yaw,
+            pitch,
+            on_ground: matches!(nbt.get("OnGround"), Some(Nbt::Byte(1))),
+            nbt: nbt.clone(),
+        }))
+    }
+
+    /// The stored compound, with the modeled fields written over it
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let mut nbt = self.nbt.clone();
+        nbt.set("id", Nbt::String(self.kind.identifier().into()));
+        nbt.set("UUID", self.uuid.to_nbt());
+        let [x, y, z]: [f64; 3] = self.pos.into();
+        nbt.set("Pos", Nbt::List(NbtList::Double(vec![x, y, z].into())));
+        let [x, y, z]: [f64; 3] = self.velocity.into();
+        nbt.set("Motion", Nbt::List(NbtList::Double(vec![x, y, z].into())));
+        nbt.set(
+            "Rotation",
+            Nbt::List(NbtList::Float(vec![self.yaw, self.pitch].into())),
+        );
+        nbt.set("OnGround", Nbt::Byte(self.on_ground.into()));
+        nbt
+    }
+}
+
+/// (yaw, pitch) in degrees, from an entity's `Rotation`
+pub(crate) fn rotation_of(nbt: &CompoundNbt<'_>) -> Option<[f32; 2]> {
+    match nbt.get("Rotation") {
+        Some(Nbt::List(NbtList::Float(r))) if r.len() == 2 => Some([r[0], r[1]]),
+        _ => None,
+    }
+}
+
+/// One chunk's entities, as vanilla has saved them in `entities/` region files since 1.17. Load
+/// them with `RegionFile::load_entities()`, and spawn them with `EntityStore::spawn_saved()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityChunk {
+    pub pos: ChunkPos,
+    pub data_version: i32,
+    pub entities: Vec<SavedEntity>,
+    /// Entities of types libmc doesn't know, saved again as they were
+    pub unknown: Vec<CompoundNbt<'static>>,
+}
+
+impl EntityChunk {
+    pub fn new(pos: ChunkPos) -> Self {
+        Self {
+            pos,
+            data_version: DATA_VERSION,
+            entities: Vec::new(),
+            unknown: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty() && self.unknown.is_empty()
+    }
+
+    pub fn from_nbt(root: &CompoundNbt<'static>) -> io::Result<Self> {
+        let data_version = match root.get("DataVersion") {
+            Some(Nbt::Int(v)) => *v,
+            _ => 0,
+        };
+        let pos = match root.get("Position") {
+            Some(Nbt::IntArray(pos)) if pos.len() == 2 => ChunkPos::new(pos[0], pos[1]),
+            _ => return Err(invalid("entity chunk without a Position")),
+        };
+        let mut chunk = Self {
+            pos,
+            data_version,
+            entities: Vec::new(),
+            unknown: Vec::new(),
+        };
+        let entities = match root.get("Entities") {
+            Some(Nbt::List(NbtList::Compound(list))) => &list[..],
+            // empty lists can be saved with any element type
+            Some(Nbt::List(_)) | None => &[],
+            Some(_) => return Err(invalid("expected a list of compounds")),
+        };
+        for nbt in entities {
+            match SavedEntity::from_nbt(nbt)? {
+                Some(entity) => chunk.entities.push(entity),
+                None => chunk.unknown.push(nbt.clone()),
+            }
+        }
+        Ok(chunk)
+    }
+
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let entities: Vec<_> = self
+            .entities
+            .iter()
+            .map(SavedEntity::to_nbt)
+            .chain(self.unknown.iter().cloned())
+            .collect();
+        let mut root = CompoundNbt::new("");
+        root.set("DataVersion", Nbt::Int(self.data_version));
+        root.set(
+            "Position",
+            Nbt::IntArray(vec![self.pos.x, self.pos.z].into()),
+        );
+        root.set("Entities", Nbt::List(NbtList::Compound(entities.into())));
+        root
+    }
+}
+
+impl RegionFile {
+    /// The chunk's entities, from a region file in a dimension's `entities/` folder. Chunks
+    /// without entities aren't saved, so they're None.
+    pub fn load_entities(&self, chunk: ChunkPos) -> io::Result<Option<EntityChunk>> {
+        match self.chunk_nbt(chunk)? {
+            Some(nbt) => EntityChunk::from_nbt(&nbt).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the entities of `entities.pos`, removing the chunk if it has none like vanilla
+    pub fn save_entities(&mut self, entities: &EntityChunk) -> io::Result<()> {
+        let nbt = (!entities.is_empty()).then(|| entities.to_nbt());
+        self.set_chunk_nbt(entities.pos, nbt.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_entities() {
+        let mut zombie = CompoundNbt::new("");
+        zombie.set("id", Nbt::String("minecraft:zombie".into()));
+        zombie.set("UUID", Uuid(7).to_nbt());
+        zombie.set(
+            "Pos",
+            Nbt::List(NbtList::Double(vec![33.5, 64.0, -2.5].into())),
+        );
+        zombie.set(
+            "Rotation",
+            Nbt::List(NbtList::Float(vec![91.3, -10.0].into())),
+        );
+        zombie.set("Health", Nbt::Float(12.0));
+        zombie.set("OnGround", Nbt::Byte(1));
+        let mut unknown = CompoundNbt::new("");
+        unknown.set("id", Nbt::String("minecraft:not_yet_added".into()));
+        let mut root = CompoundNbt::new("");
+        root.set("DataVersion", Nbt::Int(3465));
+        root.set("Position", Nbt::IntArray(vec![2, -1].into()));
+        root.set(
+            "Entities",
+            Nbt::List(NbtList::Compound(vec![zombie, unknown].into())),
+        );
+
+        let pos = ChunkPos::new(2, -1);
+        let mut region = RegionFile::new();
+        region.set_chunk_nbt(pos, Some(&root)).unwrap();
+        let chunk = region.load_entities(pos).unwrap().unwrap();
+        assert_eq!(chunk.data_version, 3465);
+        assert_eq!(chunk.entities.len(), 1);
+        assert_eq!(chunk.unknown.len(), 1);
+        let saved = &chunk.entities[0];
+        assert_eq!(saved.kind, EntityType::Zombie);
+        assert_eq!(
+            EntityType::from_id(EntityType::Zombie as i32),
+            Some(saved.kind)
+        );
+        assert_eq!(
+            (saved.uuid, saved.yaw, saved.on_ground),
+            (Uuid(7), 91.3, true)
+        );
+
+        // spawned, moved, and saved again
+        let mut store = EntityStore::new(1);
+        let h = store.spawn_saved(saved.clone());
+        store.spawn(EntityType::Pig, Uuid(8), Vec3::from([0.0, 64.0, 0.0]));
+        assert_eq!(store.position(h), Some(saved.pos));
+        store.set_position(h, Vec3::from([34.0, 64.0, -2.5]));
+        let mut chunk = EntityChunk {
+            entities: store.saved_in_chunk(pos),
+            ..chunk
+        };
+        assert_eq!(chunk.entities.len(), 1);
+        let resaved = &chunk.entities[0];
+        // the rotation hasn't changed, so it keeps its precision
+        assert_eq!((resaved.yaw, resaved.pitch), (91.3, -10.0));
+        assert_eq!(resaved.nbt.get("Health"), Some(&Nbt::Float(12.0)));
+
+        let mut region = RegionFile::from_bytes(region.as_bytes().to_vec()).unwrap();
+        region.save_entities(&chunk).unwrap();
+        let other = ChunkPos::new(0, 0);
+        region.save_entities(&EntityChunk::new(other)).unwrap();
+        assert_eq!(region.load_entities(other).unwrap(), None);
+        assert_eq!(region.load_entities(pos).unwrap().as_ref(), Some(&chunk));
+
+        chunk.entities.clear();
+        chunk.unknown.clear();
+        region.save_entities(&chunk).unwrap();
+        assert_eq!(region.load_entities(pos).unwrap(), None);
+    }
+}
@@ -0,0 +1,401 @@
+use crate::*;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Something that happened in the game, which handlers registered on an `EventBus` can react to.
+/// Cancelling an event stops whatever caused it from taking effect.
+pub trait Event: 'static {
+    fn is_cancelled(&self) -> bool;
+    fn set_cancelled(&mut self, cancelled: bool);
+}
+
+macro_rules! cancellable_events {
+    ($($name:ident),* $(,)?) => {
+        $(
+            impl Event for $name {
+                fn is_cancelled(&self) -> bool {
+                    self.cancelled
+                }
+
+                fn set_cancelled(&mut self, cancelled: bool) {
+                    self.cancelled = cancelled;
+                }
+            }
+        )*
+    };
+}
+
+cancellable_events!(
+    PlayerJoinEvent,
+    JoinMessageEvent,
+    QuitMessageEvent,
+    ChatEvent,
+    BlockBreakEvent,
+    BlockPlaceEvent,
+    RandomTickEvent,
+);
+
+/// A player started logging in. Post it from `Server::filter_login()` (`post_packet()` does for
+/// Login Start) and refuse them if it was cancelled: by the time `Server::handle_packet()` sees
+/// Login Start they've been let in, so handlers there have to kick them instead.
+#[derive(Debug, Clone)]
+pub struct PlayerJoinEvent {
+    pub cid: ClientID,
+    pub uuid: Uuid,
+    pub name: String,
+    pub cancelled: bool,
+}
+
+/// A player finished joining, and `message` is about to be broadcast.
+/// Handlers may change the message; cancelling or setting it to None suppresses it.
+#[derive(Debug, Clone)]
+pub struct JoinMessageEvent {
+    pub cid: ClientID,
+    pub name: String,
+    /// The player has never joined before (see `PlayerDataStore::has_played_before()`)
+    pub first_join: bool,
+    pub message: Option<TextComponent>,
+    pub cancelled: bool,
+}
+
+impl JoinMessageEvent {
+    /// With vanilla's default message
+    pub fn new(cid: ClientID, name: impl Into<String>, first_join: bool) -> Self {
+        let name = name.into();
+        Self {
+            cid,
+            message: Some(
+                TextComponent::text(format!("{name} joined the game")).color(ChatColor::Yellow),
+            ),
+            name,
+            first_join,
+            cancelled: false,
+        }
+    }
+
+    /// The System Chat packet to broadcast, unless the message was suppressed
+    pub fn packet(&self) -> Option<OutPacket<'_>> {
+        broadcast_packet(self.cancelled, &self.message)
+    }
+}
+
+/// A player left, and `message` is about to be broadcast.
+/// Handlers may change the message; cancelling or setting it to None suppresses it.
+#[derive(Debug, Clone)]
+pub struct QuitMessageEvent {
+    pub cid: ClientID,
+    pub name: String,
+    pub message: Option<TextComponent>,
+    pub cancelled: bool,
+}
+
+impl QuitMessageEvent {
+    /// With vanilla's default message
+    pub fn new(cid: ClientID, name: impl Into<String>) -> Self {
+        let name = name.into();
+        Self {
+            cid,
+            message: Some(
+                TextComponent::text(format!("{name} left the game")).color(ChatColor::Yellow),
+            ),
+            name,
+            cancelled: false,
+        }
+    }
+
+    /// The System Chat packet to broadcast, unless the message was suppressed
+    pub fn packet(&self) -> Option<OutPacket<'_>> {
+        broadcast_packet(self.cancelled, &self.message)
+    }
+}
+
+fn broadcast_packet(cancelled: bool, message: &Option<TextComponent>) -> Option<OutPacket<'_>> {
+    match message {
+        Some(content) if !cancelled => Some(OutPacket::SystemChat {
+            content,
+            overlay: false,
+        }),
+        _ => None,
+    }
+}
+
+/// A player sent a chat message. Handlers may rewrite `message`.
+#[derive(Debug, Clone)]
+pub struct ChatEvent {
+    pub cid: ClientID,
+    pub message: String,
+    pub cancelled: bool,
+}
+
+/// A player finished breaking a block in survival mode.
+/// (Creative mode breaks only send `PlayerActionStatus::StartedDigging`.)
+#[derive(Debug, Clone)]
+pub struct BlockBreakEvent {
+    pub cid: ClientID,
+    pub location: Position,
+    pub cancelled: bool,
+}
+
+/// A player right-clicked a block, placing one at `location` if they're holding a block.
+#[derive(Debug, Clone)]
+pub struct BlockPlaceEvent {
+    pub cid: ClientID,
+    /// Where the new block would go: next to the clicked block, on the clicked face
+    pub location: Position,
+    pub cancelled: bool,
+}
+
+/// A block got a random tick (see `RandomTicker`). Handlers that grow crops, spread fire and
+/// the like set `new_state`; cancelling keeps the block as it is.
+#[derive(Debug, Clone)]
+pub struct RandomTickEvent {
+    pub location: Position,
+    /// The block's state ID
+    pub state: u32,
+    /// What to change the block to, if anything
+    pub new_state: Option<u32>,
+    pub cancelled: bool,
+}
+
+impl RandomTickEvent {
+    pub fn new(location: Position, state: u32) -> Self {
+        Self {
+            location,
+            state,
+            new_state: None,
+            cancelled: false,
+        }
+    }
+}
+
+/// The order handlers run in: from `Highest` down to `Lowest`, then `Monitor`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Lowest,
+    Low,
+    Normal,
+    High,
+    Highest,
+    /// Runs last, even for cancelled events. For observing the outcome, not changing it.
+    Monitor,
+}
+
+impl Priority {
+    fn run_order(self) -> u8 {
+        match self {
+            Priority::Highest => 0,
+            Priority::High => 1,
+            Priority::Normal => 2,
+            Priority::Low => 3,
+            Priority::Lowest => 4,
+            Priority::Monitor => 5,
+        }
+    }
+}
+
+/// Identifies a registered handler, for unregistering it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HandlerId(u64);
+
+type Handler<C, E> = Box<dyn FnMut(&mut C, &mut E)>;
+
+struct Registered {
+    id: u64,
+    priority: Priority,
+    /// a `Handler<C, E>`
+    handler: Box<dyn Any>,
+}
+
+/// Dispatches typed events to handlers, so game logic doesn't have to match on `InPacket`s directly.
+///
+/// Handlers are called with the `C` passed to `post()` and the event. Once a handler cancels
+/// an event, the remaining handlers are skipped, except `Priority::Monitor` ones.
+pub struct EventBus<C = ()> {
+    handlers: HashMap<TypeId, Vec<Registered>>,
+    next_id: u64,
+    _ctx: std::marker::PhantomData<fn(&mut C)>,
+}
+
+impl<C: 'static> Default for EventBus<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: 'static> EventBus<C> {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+            next_id: 0,
+            _ctx: std::marker::PhantomData,
+        }
+    }
+
+    pub fn register<E: Event>(
+        &mut self,
+        priority: Priority,
+        f: impl FnMut(&mut C, &mut E) + 'static,
+    ) -> HandlerId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let handler: Handler<C, E> = Box::new(f);
+        let handlers = self.handlers.entry(TypeId::of::<E>()).or_default();
+        // after existing handlers of the same priority
+        let pos = handlers.partition_point(|h| h.priority.run_order() <= priority.run_order());
+        handlers.insert(
+            pos,
+            Registered {
+                id,
+                priority,
+                handler: Box::new(handler),
+            },
+        );
+        HandlerId(id)
+    }
+
+    /// Returns false if the handler was already unregistered
+    pub fn unregister(&mut self, id: HandlerId) -> bool {
+        for handlers in self.handlers.values_mut() {
+            if let Some(pos) = handlers.iter().position(|h| h.id == id.0) {
+                handlers.remove(pos);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Runs the handlers for `event`. Returns true if it wasn't cancelled.
+    pub fn post<E: Event>(&mut self, ctx: &mut C, event: &mut E) -> bool {
+        let Some(handlers) = self.handlers.get_mut(&TypeId::of::<E>()) else {
+            return !event.is_cancelled();
+        };
+        for h in handlers {
+            if event.is_cancelled() && h.priority != Priority::Monitor {
+                continue;
+            }
+            let f = h.handler.downcast_mut::<Handler<C, E>>().unwrap();
+            f(ctx, event);
+        }
+        !event.is_cancelled()
+    }
+
+    /// Posts the event that `packet` from `cid` represents (if any). Returns false if the event
+    /// was cancelled, in which case the packet shouldn't be acted on.
+    pub fn post_packet(&mut self, ctx: &mut C, cid: ClientID, packet: &InPacket) -> bool {
+        match packet {
+            InPacket::LoginStart { name, player_uuid } => self.post(
+                ctx,
+                &mut PlayerJoinEvent {
+                    cid,
+                    uuid: *player_uuid,
+                    name: name.to_string(),
+                    cancelled: false,
+                },
+            ),
+            InPacket::ChatMessage { message, .. } => self.post(
+                ctx,
+                &mut ChatEvent {
+                    cid,
+                    message: message.to_string(),
+                    cancelled: false,
+                },
+            ),
+            InPacket::PlayerAction {
+                status: PlayerActionStatus::FinishedDigging,
+                location,
+                ..
+            } => self.post(
+                ctx,
+                &mut BlockBreakEvent {
+                    cid,
+                    location: *location,
+                    cancelled: false,
+                },
+            ),
+            InPacket::UseItemOn { location, face, .. } => self.post(
+                ctx,
+                &mut BlockPlaceEvent {
+                    cid,
+                    location: location.relative(*face),
+                    cancelled: false,
+                },
+            ),
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_and_cancel() {
+        let mut bus = EventBus::<Vec<&str>>::new();
+        bus.register(Priority::Low, |log, _: &mut ChatEvent| log.push("low"));
+        bus.register(Priority::Monitor, |log, e: &mut ChatEvent| {
+            log.push(if e.cancelled {
+                "monitor cancelled"
+            } else {
+                "monitor"
+            })
+        });
+        let censor = bus.register(Priority::High, |log, e: &mut ChatEvent| {
+            log.push("high");
+            e.cancelled = e.message.contains("bad");
+        });
+        bus.register(Priority::Normal, |log, e: &mut ChatEvent| {
+            log.push("normal");
+            e.message.make_ascii_uppercase();
+        });
+
+        let mut log = Vec::new();
+        let mut ev = ChatEvent {
+            cid: ClientID::new(0, 0),
+            message: "bad".to_string(),
+            cancelled: false,
+        };
+        assert!(!bus.post(&mut log, &mut ev));
+        assert_eq!(log, ["high", "monitor cancelled"]);
+
+        log.clear();
+        assert!(bus.unregister(censor));
+        let chat = InPacket::ChatMessage {
+            message: "hi",
+            timestamp: 0,
+            salt: 0,
+            signature: None,
+        };
+        assert!(bus.post_packet(&mut log, ClientID::new(0, 0), &chat));
+        assert_eq!(log, ["normal", "low", "monitor"]);
+    }
+
+    #[test]
+    fn join_message() {
+        let mut bus = EventBus::<()>::new();
+        bus.register(Priority::Normal, |_, e: &mut JoinMessageEvent| {
+            if e.first_join {
+                e.message = Some(TextComponent::text(format!("Welcome, {}!", e.name)));
+            }
+        });
+        bus.register(Priority::Normal, |_, e: &mut QuitMessageEvent| {
+            e.message = None
+        });
+
+        let mut join = JoinMessageEvent::new(ClientID::new(0, 0), "Steve", true);
+        bus.post(&mut (), &mut join);
+        let Some(OutPacket::SystemChat { content, .. }) = join.packet() else {
+            panic!("expected a message");
+        };
+        assert_eq!(content.text, "Welcome, Steve!");
+
+        let mut join = JoinMessageEvent::new(ClientID::new(0, 0), "Steve", false);
+        bus.post(&mut (), &mut join);
+        assert_eq!(join.message.unwrap().text, "Steve joined the game");
+
+        let mut quit = QuitMessageEvent::new(ClientID::new(0, 0), "Steve");
+        bus.post(&mut (), &mut quit);
+        assert!(quit.packet().is_none());
+    }
+}
@@ -0,0 +1,581 @@
+//! `NetBackend::EventLoop` (the `mio` feature): one thread that accepts, reads from and writes to
+//! every TCP client, instead of a reader and a writer thread per client.
+//!
+//! Each connection has a read buffer, which holds at most one frame, and a write queue, which is
+//! capped like a `SendQueue`'s, so how much memory a client can make the server use is bounded.
+
+use crate::sendqueue::MAX_QUEUED_BYTES;
+use crate::server::{NetEvent, Transport};
+use crate::*;
+use mio::net::{TcpListener as MioListener, TcpStream as MioStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LISTENER: Token = Token(usize::MAX);
+const WAKER: Token = Token(usize::MAX - 1);
+
+/// How much is read from a socket at a time
+const READ_CHUNK: usize = 16 * 1024;
+
+/// The longest the loop sleeps, so timeouts are noticed even when nothing happens
+const MAX_POLL_WAIT: Duration = Duration::from_secs(1);
+
+/// What the main thread asks of the event loop
+enum Command {
+    Write(ClientID, Vec<u8>),
+    /// Answered once everything written so far is sent, or can't be
+    Finish(ClientID, Sender<()>),
+    /// Nothing more will be written; the connection goes once its queue is empty
+    Close(ClientID),
+}
+
+/// Queues writes on the event loop, like a `SendQueue` does on its thread
+#[derive(Debug)]
+pub(crate) struct EventLoopWriter {
+    cid: ClientID,
+    commands: Sender<Command>,
+    waker: Arc<Waker>,
+    /// Bytes queued but not yet written out
+    queued: Arc<AtomicUsize>,
+    /// Set once a write to the socket failed
+    failed: Arc<AtomicBool>,
+}
+
+impl EventLoopWriter {
+    /// Waits for everything queued to be written out (or for the writing to fail)
+    pub fn finish(self) {
+        let (tx, rx) = mpsc::channel();
+        if self.command(Command::Finish(self.cid, tx)).is_ok() {
+            let _ = rx.recv();
+        }
+    }
+
+    pub fn queued_bytes(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    fn command(&self, command: Command) -> io::Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        self.waker.wake()
+    }
+}
+
+impl Write for EventLoopWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.failed.load(Ordering::Relaxed) {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        if self.queued_bytes() + data.len() > MAX_QUEUED_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "client isn't keeping up with what's sent to it",
+            ));
+        }
+        self.queued.fetch_add(data.len(), Ordering::Relaxed);
+        self.command(Command::Write(self.cid, data.to_vec()))?;
+        Ok(data.len())
+    }
+
+    /// Doesn't wait for the data to actually be written
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EventLoopWriter {
+    fn drop(&mut self) {
+        let _ = self.command(Command::Close(self.cid));
+    }
+}
+
+struct Conn {
+    stream: MioStream,
+    /// Bytes of frames that haven't been received completely yet
+    read_buf: Vec<u8>,
+    writes: VecDeque<Vec<u8>>,
+    /// How much of the first write has been written
+    written: usize,
+    queued: Arc<AtomicUsize>,
+    failed: Arc<AtomicBool>,
+    last_read: Instant,
+    /// When the socket last took any of our writes, while there were writes waiting
+    last_write: Instant,
+    /// Set once the main thread has been told the connection closed
+    reported: bool,
+    /// Set once the writer is dropped
+    writer_closed: bool,
+    finishing: Vec<Sender<()>>,
+}
+
+impl Conn {
+    fn token(cid: ClientID) -> Token {
+        // the generation is lost on 32-bit targets, but IDs are still unique among live clients
+        Token(cid.to_bits() as usize)
+    }
+
+    /// Reads what's available, returning the complete frames in it, or why the client is gone
+    fn read_frames(&mut self) -> Result<Vec<Vec<u8>>, DisconnectCause> {
+        let mut frames = Vec::new();
+        let mut chunk = [0; READ_CHUNK];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(DisconnectCause::Closed),
+                Ok(n) => {
+                    self.last_read = Instant::now();
+                    self.read_buf.extend_from_slice(&chunk[..n]);
+                    self.split_frames(&mut frames)?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(frames),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(DisconnectCause::Io(e.kind())),
+            }
+        }
+    }
+
+    /// Moves the complete frames at the start of the read buffer into `frames`
+    fn split_frames(&mut self, frames: &mut Vec<Vec<u8>>) -> Result<(), DisconnectCause> {
+        let mut start = 0;
+        while let Some((prefix_len, len)) = frame_len(&self.read_buf[start..])? {
+            let end = start + prefix_len + len;
+            if end > self.read_buf.len() {
+                break;
+            }
+            frames.push(self.read_buf[start..end].to_vec());
+            start = end;
+        }
+        self.read_buf.drain(..start);
+        Ok(())
+    }
+
+    /// Writes as much as the socket takes
+    fn write_queued(&mut self) -> io::Result<()> {
+        while let Some(data) = self.writes.front() {
+            match self.stream.write(&data[self.written..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.written += n;
+                    self.last_write = Instant::now();
+                    self.queued.fetch_sub(n, Ordering::Relaxed);
+                    if self.written == data.len() {
+                        self.writes.pop_front();
+                        self.written = 0;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        for done in self.finishing.drain(..) {
+            let _ = done.send(());
+        }
+        Ok(())
+    }
+
+    /// Whether nothing more will happen on the connection
+    fn is_done(&self) -> bool {
+        self.writer_closed && self.writes.is_empty()
+    }
+}
+
+/// The (length prefix length, frame length) of the frame at the start of `buf`, if its length
+/// prefix has been received
+fn frame_len(buf: &[u8]) -> Result<Option<(usize, usize)>, DisconnectCause> {
+    match VarInt::decode(buf) {
+        Ok((VarInt(len), prefix_len)) if prefix_len <= 3 => {
+            let len = len as usize;
+            if len > MAX_FRAME_LEN {
+                return Err(DisconnectCause::BadFrame("packet too long"));
+            }
+            Ok(Some((prefix_len, len)))
+        }
+        Err(DecodeError::UnexpectedEnd) if buf.len() < 3 => Ok(None),
+        _ => Err(DisconnectCause::BadFrame("packet length prefix too long")),
+    }
+}
+
+struct EventLoop {
+    poll: Poll,
+    listener: TcpListener,
+    /// The listener tag its clients get
+    tag: Arc<str>,
+    tx: Sender<NetEvent>,
+    client_ids: Arc<ClientIDAllocator>,
+    commands: Receiver<Command>,
+    commands_tx: Sender<Command>,
+    waker: Arc<Waker>,
+    conns: HashMap<ClientID, Conn>,
+    read_timeout: Duration,
+    write_timeout: Duration,
+}
+
+/// Starts the event loop on its own thread, accepting clients from `listener`
+pub(crate) fn spawn_event_loop(
+    listener: TcpListener,
+    tag: Arc<str>,
+    tx: Sender<NetEvent>,
+    client_ids: Arc<ClientIDAllocator>,
+    config: &ServerConfig,
+) -> io::Result<()> {
+    let (mut event_loop, mio_listener) = EventLoop::new(listener, tag, tx, client_ids, config)?;
+    thread::spawn(move || {
+        // keeps the listener registered
+        let _mio_listener = mio_listener;
+        event_loop.run()
+    });
+    Ok(())
+}
+
+impl EventLoop {
+    /// The loop, and the listener registered with it
+    fn new(
+        listener: TcpListener,
+        tag: Arc<str>,
+        tx: Sender<NetEvent>,
+        client_ids: Arc<ClientIDAllocator>,
+        config: &ServerConfig,
+    ) -> io::Result<(Self, MioListener)> {
+        let poll = Poll::new()?;
+        listener.set_nonblocking(true)?;
+        let mut mio_listener = MioListener::from_std(listener.try_clone()?);
+        poll.registry()
+            .register(&mut mio_listener, LISTENER, Interest::READABLE)?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+        let (commands_tx, commands) = mpsc::channel();
+        let event_loop = EventLoop {
+            poll,
+            listener,
+            tag,
+            tx,
+            client_ids,
+            commands,
+            commands_tx,
+            waker,
+            conns: HashMap::new(),
+            read_timeout: config.read_timeout,
+            write_timeout: config.write_timeout,
+        };
+        Ok((event_loop, mio_listener))
+    }
+
+    fn run(&mut self) {
+        let mut events = Events::with_capacity(1024);
+        loop {
+            if let Err(e) = self.poll.poll(&mut events, Some(MAX_POLL_WAIT)) {
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                // nothing can be read or written anymore, so every client is gone
+                self.fail_all(DisconnectCause::Io(e.kind()));
+                return;
+            }
+            for event in &events {
+                match event.token() {
+                    LISTENER => {
+                        if !self.accept() {
+                            // the server shut down
+                            return;
+                        }
+                    }
+                    WAKER => {}
+                    Token(cid) => {
+                        let cid = ClientID::from_bits(cid as u64);
+                        if event.is_readable() || event.is_read_closed() {
+                            self.readable(cid);
+                        }
+                        if event.is_writable() {
+                            self.write(cid);
+                        }
+                    }
+                }
+            }
+            self.run_commands();
+            self.check_timeouts();
+        }
+    }
+
+    /// Accepts every waiting client. Returns false if the main thread is gone.
+    fn accept(&mut self) -> bool {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                // none left, or an error (e.g. too many open files) that's retried on the next
+                // connection
+                Err(_) => return true,
+            };
+            let Some((conn, main_half)) = self.register(stream) else {
+                continue;
+            };
+            let cid = self.client_ids.allocate();
+            let writer = EventLoopWriter {
+                cid,
+                commands: self.commands_tx.clone(),
+                waker: Arc::clone(&self.waker),
+                queued: Arc::clone(&conn.queued),
+                failed: Arc::clone(&conn.failed),
+            };
+            let mut conn = conn;
+            let interest = Interest::READABLE | Interest::WRITABLE;
+            if self
+                .poll
+                .registry()
+                .register(&mut conn.stream, Conn::token(cid), interest)
+                .is_err()
+            {
+                continue;
+            }
+            self.conns.insert(cid, conn);
+            let transport = Transport::EventLoop(writer);
+            if self
+                .tx
+                .send(NetEvent::Connected(
+                    cid,
+                    main_half.into(),
+                    transport,
+                    Arc::clone(&self.tag),
+                ))
+                .is_err()
+            {
+                return false;
+            }
+        }
+    }
+
+    /// The loop's half of a new client's stream, and the main thread's (for shutting it down)
+    fn register(&mut self, stream: TcpStream) -> Option<(Conn, TcpStream)> {
+        stream.set_nonblocking(true).ok()?;
+        let main_half = stream.try_clone().ok()?;
+        let now = Instant::now();
+        let conn = Conn {
+            stream: MioStream::from_std(stream),
+            read_buf: Vec::new(),
+            writes: VecDeque::new(),
+            written: 0,
+            queued: Arc::new(AtomicUsize::new(0)),
+            failed: Arc::new(AtomicBool::new(false)),
+            last_read: now,
+            last_write: now,
+            reported: false,
+            writer_closed: false,
+            finishing: Vec::new(),
+        };
+        Some((conn, main_half))
+    }
+
+    fn readable(&mut self, cid: ClientID) {
+        let Some(conn) = self.conns.get_mut(&cid) else {
+            return;
+        };
+        if conn.reported {
+            return;
+        }
+        let res = conn.read_frames();
+        let frames = match &res {
+            Ok(frames) => &frames[..],
+            Err(_) => &[],
+        };
+        for frame in frames {
+            let _ = self.tx.send(NetEvent::Frame(cid, frame.clone()));
+        }
+        if let Err(cause) = res {
+            // whatever is still queued gets written, as with a `SendQueue`
+            self.report_closed(cid, cause);
+        }
+    }
+
+    fn write(&mut self, cid: ClientID) {
+        let Some(conn) = self.conns.get_mut(&cid) else {
+            return;
+        };
+        if let Err(e) = conn.write_queued() {
+            self.fail(cid, DisconnectCause::WriteFailed(e.kind()));
+            return;
+        }
+        if conn.is_done() {
+            self.remove(cid);
+        }
+    }
+
+    fn run_commands(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                Command::Write(cid, data) => {
+                    let Some(conn) = self.conns.get_mut(&cid) else {
+                        continue;
+                    };
+                    if conn.writes.is_empty() {
+                        conn.last_write = Instant::now();
+                    }
+                    conn.writes.push_back(data);
+                    self.write(cid);
+                }
+                Command::Finish(cid, done) => match self.conns.get_mut(&cid) {
+                    Some(conn) if !conn.writes.is_empty() => conn.finishing.push(done),
+                    _ => {
+                        let _ = done.send(());
+                    }
+                },
+                Command::Close(cid) => {
+                    let Some(conn) = self.conns.get_mut(&cid) else {
+                        continue;
+                    };
+                    conn.writer_closed = true;
+                    if conn.is_done() {
+                        self.remove(cid);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_timeouts(&mut self) {
+        let now = Instant::now();
+        let mut read_timed_out = Vec::new();
+        let mut write_timed_out = Vec::new();
+        for (&cid, conn) in &self.conns {
+            if !conn.writes.is_empty() && now - conn.last_write > self.write_timeout {
+                write_timed_out.push(cid);
+            } else if !conn.reported && now - conn.last_read > self.read_timeout {
+                read_timed_out.push(cid);
+            }
+        }
+        for cid in read_timed_out {
+            self.report_closed(cid, DisconnectCause::TimedOut);
+        }
+        for cid in write_timed_out {
+            self.fail(cid, DisconnectCause::WriteFailed(io::ErrorKind::TimedOut));
+        }
+    }
+
+    /// Tells the main thread the client is gone, once
+    fn report_closed(&mut self, cid: ClientID, cause: DisconnectCause) {
+        let Some(conn) = self.conns.get_mut(&cid) else {
+            return;
+        };
+        if !conn.reported {
+            conn.reported = true;
+            let _ = self.tx.send(NetEvent::Closed(cid, cause));
+        }
+    }
+
+    /// Drops a connection that can't be written to anymore
+    fn fail(&mut self, cid: ClientID, cause: DisconnectCause) {
+        if let Some(conn) = self.conns.get(&cid) {
+            conn.failed.store(true, Ordering::Relaxed);
+        }
+        self.report_closed(cid, cause);
+        self.remove(cid);
+    }
+
+    /// Drops every connection, e.g. when the loop can't go on
+    fn fail_all(&mut self, cause: DisconnectCause) {
+        let cids: Vec<ClientID> = self.conns.keys().copied().collect();
+        for cid in cids {
+            self.fail(cid, cause);
+        }
+    }
+
+    fn remove(&mut self, cid: ClientID) {
+        let Some(mut conn) = self.conns.remove(&cid) else {
+            return;
+        };
+        let _ = self.poll.registry().deregister(&mut conn.stream);
+        let _ = conn.stream.shutdown(std::net::Shutdown::Both);
+        conn.queued.store(0, Ordering::Relaxed);
+        for done in conn.finishing {
+            let _ = done.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(rx: &Receiver<NetEvent>) -> NetEvent {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn frames_and_writes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let config = ServerConfig::default();
+        let tag = Arc::from("test");
+        let client_ids = Arc::new(ClientIDAllocator::default());
+        client_ids.allocate();
+        spawn_event_loop(listener, tag, tx, client_ids, &config).unwrap();
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        let NetEvent::Connected(cid, _, Transport::EventLoop(mut writer), tag) = next(&rx) else {
+            panic!("expected a connection");
+        };
+        assert_eq!(cid, ClientID::new(1, 0));
+        assert_eq!(&*tag, "test");
+
+        // a frame split across writes, then two at once
+        client.write_all(&[3, 0x00]).unwrap();
+        client.flush().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        client.write_all(&[1, 2, 1, 9, 0]).unwrap();
+        for expected in [&[3, 0, 1, 2][..], &[1, 9], &[0]] {
+            match next(&rx) {
+                NetEvent::Frame(c, frame) => {
+                    assert_eq!(c, cid);
+                    assert_eq!(frame, expected);
+                }
+                _ => panic!("expected a frame"),
+            }
+        }
+
+        writer.write_all(&[5, 6, 7]).unwrap();
+        let mut received = [0; 3];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(received, [5, 6, 7]);
+
+        drop(client);
+        assert!(matches!(
+            next(&rx),
+            NetEvent::Closed(c, DisconnectCause::Closed) if c == cid
+        ));
+        writer.finish();
+
+        assert_eq!(frame_len(&[0x80]), Ok(None));
+        assert!(frame_len(&[0x80, 0x80, 0x80, 0x01]).is_err());
+    }
+
+    #[test]
+    fn failing_disconnects_everyone() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let config = ServerConfig::default();
+        let client_ids = Arc::new(ClientIDAllocator::default());
+        let (mut event_loop, _mio_listener) =
+            EventLoop::new(listener, Arc::from("test"), tx, client_ids, &config).unwrap();
+
+        let _client = TcpStream::connect(addr).unwrap();
+        while event_loop.conns.is_empty() {
+            assert!(event_loop.accept());
+        }
+        let NetEvent::Connected(cid, _, Transport::EventLoop(mut writer), _) = next(&rx) else {
+            panic!("expected a connection");
+        };
+
+        let cause = DisconnectCause::Io(io::ErrorKind::Other);
+        event_loop.fail_all(cause);
+        assert!(matches!(next(&rx), NetEvent::Closed(c, e) if c == cid && e == cause));
+        assert!(event_loop.conns.is_empty());
+        assert!(writer.write_all(&[1]).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::*;
+
+/// A player's experience, kept the way vanilla does: a level, progress towards the next one
+/// (0.0 to 1.0), and the total points collected (which is only used for the death drop and score).
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Experience {
+    pub level: i32,
+    pub progress: f32,
+    pub total: i32,
+}
+
+impl Experience {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exactly `total` points, converted to a level and progress
+    pub fn from_total(total: i32) -> Self {
+        let total = total.max(0);
+        let mut level = 0;
+        while points_for_level(level + 1) <= total {
+            level += 1;
+        }
+        Self {
+            level,
+            progress: (total - points_for_level(level)) as f32 / points_to_next_level(level) as f32,
+            total,
+        }
+    }
+
+    /// Adds (or, if negative, removes) experience points, levelling up or down like vanilla's
+    /// `giveExperiencePoints()`. Returns how many levels were gained (negative if lost).
+    pub fn give_xp(&mut self, points: i32) -> i32 {
+        let start = self.level;
+        self.progress += points as f32 / points_to_next_level(self.level) as f32;
+        self.total = self.total.saturating_add(points).max(0);
+        while self.progress < 0.0 {
+            let remaining = self.progress * points_to_next_level(self.level) as f32;
+            if self.level > 0 {
+                self.give_levels(-1);
+                self.progress = 1.0 + remaining / points_to_next_level(self.level) as f32;
+            } else {
+                self.give_levels(-1);
+                self.progress = 0.0;
+            }
+        }
+        while self.progress >= 1.0 {
+            self.progress = (self.progress - 1.0) * points_to_next_level(self.level) as f32;
+            self.give_levels(1);
+            self.progress /= points_to_next_level(self.level) as f32;
+        }
+        self.level - start
+    }
+
+    /// Adds (or removes) whole levels, keeping the progress, like enchanting does
+    pub fn give_levels(&mut self, levels: i32) {
+        self.level = self.level.saturating_add(levels);
+        if self.level < 0 {
+            *self = Self::new();
+        }
+    }
+
+    /// Sets the level, with no progress towards the next one and the total it takes to get there
+    pub fn set_level(&mut self, level: i32) {
+        *self = Self::from_total(points_for_level(level.max(0)));
+    }
+
+    /// The points the player would have if it collected them all from level 0, which is what the
+    /// level and progress are worth (unlike `total`, which only ever counts up)
+    pub fn points(&self) -> i32 {
+        points_for_level(self.level)
+            + (self.progress * points_to_next_level(self.level) as f32).round() as i32
+    }
+
+    pub fn packet(&self) -> OutPacket<'static> {
+        OutPacket::SetExperience {
+            bar: self.progress,
+            level: self.level,
+            total: self.total,
+        }
+    }
+}
+
+/// How many points it takes to get from `level` to the next level
+pub fn points_to_next_level(level: i32) -> i32 {
+    if level >= 30 {
+        112 + (level - 30) * 9
+    } else if level >= 15 {
+        37 + (level - 15) * 5
+    } else {
+        7 + level * 2
+    }
+}
+
+/// How many points it takes to get from level 0 to `level`
+pub fn points_for_level(level: i32) -> i32 {
+    let l = i64::from(level);
+    let points = if level <= 16 {
+        l * l + 6 * l
+    } else if level <= 31 {
+        (5 * l * l - 81 * l + 720) / 2
+    } else {
+        (9 * l * l - 325 * l + 4440) / 2
+    };
+    points.try_into().unwrap_or(i32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        // the closed forms agree with adding up each level
+        let mut sum = 0;
+        for level in 0..100 {
+            assert_eq!(points_for_level(level), sum, "level {level}");
+            sum += points_to_next_level(level);
+        }
+        assert_eq!(points_for_level(30), 1395);
+
+        let mut xp = Experience::new();
+        assert_eq!(xp.give_xp(10), 1);
+        assert_eq!(xp.level, 1);
+        // 3 of the 9 points level 1 needs
+        assert!((xp.progress - 3.0 / 9.0).abs() < 1e-6);
+        assert_eq!(xp.points(), 10);
+        assert_eq!(xp.give_xp(-5), -1);
+        assert_eq!((xp.level, xp.points()), (0, 5));
+
+        xp.set_level(30);
+        assert_eq!(xp, Experience::from_total(1395));
+        assert_eq!((xp.level, xp.progress), (30, 0.0));
+        let from_total = Experience::from_total(1400);
+        assert_eq!(from_total.level, 30);
+        assert!((from_total.progress - 5.0 / 112.0).abs() < 1e-6);
+
+        assert_eq!(
+            encode_packet(xp.packet(), PROTOCOL_VERSION),
+            [0x58, 0, 0, 0, 0, 30, 0xF3, 0x0A]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::*;
+
+/// Entity event that makes a client-side fishing bobber pull its hooked entity
+/// (which only has an effect if that entity is the client's own player)
+const BOBBER_PULL_EVENT: i8 = 31;
+
+/// Velocity (blocks per tick) that pulls something at `from` towards `to`.
+/// Vanilla fishing rods use a `strength` of 0.1; grappling hooks typically use more,
+/// plus some extra upwards velocity so the player clears ledges.
+pub fn pull_velocity(from: [f64; 3], to: [f64; 3], strength: f64) -> [f64; 3] {
+    std::array::from_fn(|i| (to[i] - from[i]) * strength)
+}
+
+/// A fishing rod's bobber.
+///
+/// The client ignores bobbers whose owner isn't a player entity it knows about,
+/// so the owner must have been spawned for a client before the bobber is.
+#[derive(Debug)]
+pub struct FishingBobber {
+    entity_id: i32,
+    uuid: Uuid,
+    owner_entity_id: i32,
+    hooked: Option<i32>,
+    metadata: [MetadataEntry<'static>; 1],
+}
+
+impl FishingBobber {
+    pub fn new(entity_id: i32, uuid: Uuid, owner_entity_id: i32) -> Self {
+        Self {
+            entity_id,
+            uuid,
+            owner_entity_id,
+            hooked: None,
+            metadata: [MetadataEntry {
+                index: 8,
+                value: MetadataValue::VarInt(0),
+            }],
+        }
+    }
+
+    pub fn entity_id(&self) -> i32 {
+        self.entity_id
+    }
+
+    pub fn owner(&self) -> i32 {
+        self.owner_entity_id
+    }
+
+    pub fn hooked(&self) -> Option<i32> {
+        self.hooked
+    }
+
+    /// `velocity` is in blocks per tick. Vanilla casts the bobber with a
+    /// velocity of ~0.6 blocks/tick in the look direction of the owner.
+    pub fn spawn_packet(&self, pos: [f64; 3], velocity: [f64; 3]) -> OutPacket<'_> {
+        OutPacket::SpawnEntity {
+            entity_id: self.entity_id,
+            uuid: self.uuid,
+            entity_type: EntityType::FishingBobber,
+            x: pos[0],
+            y: pos[1],
+            z: pos[2],
+            pitch: Angle(0),
+            yaw: Angle(0),
+            head_yaw: Angle(0),
+            // the owner's entity ID goes in the spawn data
+            data: self.owner_entity_id,
+            velocity: encode_velocity(velocity),
+        }
+    }
+
+    /// Hooks (or unhooks, with `None`) an entity. Send the returned packet to everyone who can see the bobber.
+    pub fn set_hooked(&mut self, entity_id: Option<i32>) -> OutPacket<'_> {
+        self.hooked = entity_id;
+        // hooked entity ID + 1, or 0 for none
+        self.metadata[0].value = MetadataValue::VarInt(entity_id.map_or(0, |id| id + 1));
+        OutPacket::SetEntityMetadata {
+            entity_id: self.entity_id,
+            metadata: &self.metadata,
+        }
+    }
+
+    /// The velocity to add to the hooked entity when the rod is reeled in.
+    /// If the hooked entity is a player, `reel_in_packets()` makes their client apply it.
+    pub fn reel_in_velocity(&self, owner_pos: [f64; 3], bobber_pos: [f64; 3]) -> [f64; 3] {
+        pull_velocity(bobber_pos, owner_pos, 0.1)
+    }
+
+    /// Pulls the hooked entity (if any) and removes the bobber
+    pub fn reel_in_packets(&self) -> Vec<OutPacket<'_>> {
+        let mut packets = Vec::new();
+        if self.hooked.is_some() {
+            packets.push(OutPacket::EntityEvent {
+                entity_id: self.entity_id,
+                status: BOBBER_PULL_EVENT,
+            });
+        }
+        packets.push(self.despawn_packet());
+        packets
+    }
+
+    pub fn despawn_packet(&self) -> OutPacket<'_> {
+        OutPacket::RemoveEntities {
+            entity_ids: std::slice::from_ref(&self.entity_id),
+        }
+    }
+}
+
+/// The knot entity that appears on a fence post when a leash is tied to it
+#[derive(Debug)]
+pub struct LeashKnot {
+    entity_id: i32,
+    uuid: Uuid,
+    fence: Position,
+}
+
+impl LeashKnot {
+    pub fn new(entity_id: i32, uuid: Uuid, fence: Position) -> Self {
+        Self {
+            entity_id,
+            uuid,
+            fence,
+        }
+    }
+
+    pub fn entity_id(&self) -> i32 {
+        self.entity_id
+    }
+
+    pub fn fence(&self) -> &Position {
+        &self.fence
+    }
+
+    pub fn spawn_packet(&self) -> OutPacket<'_> {
+        OutPacket::SpawnEntity {
+            entity_id: self.entity_id,
+            uuid: self.uuid,
+            entity_type: EntityType::LeashKnot,
+            // centered on the fence post
+            x: f64::from(self.fence.x) + 0.5,
+            y: f64::from(self.fence.y) + 0.5,
+            z: f64::from(self.fence.z) + 0.5,
+            pitch: Angle(0),
+            yaw: Angle(0),
+            head_yaw: Angle(0),
+            data: 0,
+            velocity: [0; 3],
+        }
+    }
+
+    /// Ties the leash of `leashed` to this knot
+    pub fn attach_packet(&self, leashed: i32) -> OutPacket<'_> {
+        leash_packet(leashed, Some(self.entity_id))
+    }
+
+    pub fn despawn_packet(&self) -> OutPacket<'_> {
+        OutPacket::RemoveEntities {
+            entity_ids: std::slice::from_ref(&self.entity_id),
+        }
+    }
+}
+
+/// Leashes `leashed` to `holder` (a player, mob, or `LeashKnot`), or unleashes it if `holder` is None
+pub fn leash_packet<'a>(leashed: i32, holder: Option<i32>) -> OutPacket<'a> {
+    OutPacket::LinkEntities {
+        attached: leashed,
+        holder: holder.unwrap_or(-1),
+    }
+}
+
+/// Whether a leashed entity at `leashed_pos` has strayed far enough from its holder for the leash to snap
+pub fn leash_should_break(leashed_pos: [f64; 3], holder_pos: [f64; 3]) -> bool {
+    let dist_sq: f64 = (0..3)
+        .map(|i| (leashed_pos[i] - holder_pos[i]).powi(2))
+        .sum();
+    // vanilla breaks leashes at 10 blocks
+    dist_sq > 10.0 * 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bobber() {
+        let mut b = FishingBobber::new(10, Uuid(1), 3);
+        let OutPacket::SpawnEntity { data, .. } = b.spawn_packet([0.0; 3], [0.0; 3]) else {
+            unreachable!()
+        };
+        assert_eq!(data, 3);
+
+        assert_eq!(b.reel_in_packets().len(), 1, "nothing to pull");
+        let OutPacket::SetEntityMetadata { metadata, .. } = b.set_hooked(Some(7)) else {
+            unreachable!()
+        };
+        assert_eq!(metadata[0].value, MetadataValue::VarInt(8));
+        assert!(matches!(
+            b.reel_in_packets()[..],
+            [
+                OutPacket::EntityEvent { status: 31, .. },
+                OutPacket::RemoveEntities { .. }
+            ]
+        ));
+
+        let v = b.reel_in_velocity([0.0, 64.0, 0.0], [10.0, 60.0, -5.0]);
+        assert_eq!(v, [-1.0, 0.4, 0.5]);
+    }
+
+    #[test]
+    fn leash_distance() {
+        assert!(!leash_should_break([0.0; 3], [6.0, 0.0, 6.0]));
+        assert!(leash_should_break([0.0; 3], [8.0, 0.0, 8.0]));
+    }
+}
@@ -0,0 +1,419 @@
+use crate::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// A game rule's value. Vanilla only has boolean and integer rules.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameRuleValue {
+    Bool(bool),
+    Int(i32),
+}
+
+impl GameRuleValue {
+    /// Parses a value of the same type as `self`, the way `level.dat` stores them
+    fn parse_like(self, s: &str) -> Option<Self> {
+        match self {
+            GameRuleValue::Bool(_) => match s {
+                "true" => Some(GameRuleValue::Bool(true)),
+                "false" => Some(GameRuleValue::Bool(false)),
+                _ => None,
+            },
+            GameRuleValue::Int(_) => s.parse().ok().map(GameRuleValue::Int),
+        }
+    }
+
+    fn same_type(self, other: Self) -> bool {
+        matches!(
+            (self, other),
+            (GameRuleValue::Bool(_), GameRuleValue::Bool(_))
+                | (GameRuleValue::Int(_), GameRuleValue::Int(_))
+        )
+    }
+}
+
+impl fmt::Display for GameRuleValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameRuleValue::Bool(b) => write!(f, "{b}"),
+            GameRuleValue::Int(i) => write!(f, "{i}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameRuleError {
+    UnknownRule,
+    /// A boolean rule was set to an integer, or the other way around
+    WrongType,
+    /// The value couldn't be parsed as the rule's type
+    BadValue,
+}
+
+impl fmt::Display for GameRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GameRuleError::UnknownRule => "unknown game rule",
+            GameRuleError::WrongType => "wrong type for game rule",
+            GameRuleError::BadValue => "bad game rule value",
+        })
+    }
+}
+
+impl std::error::Error for GameRuleError {}
+
+/// Vanilla's game rules, with their defaults
+const VANILLA_GAME_RULES: [(&str, GameRuleValue); 46] = {
+    use GameRuleValue::{Bool, Int};
+    [
+        ("announceAdvancements", Bool(true)),
+        ("blockExplosionDropDecay", Bool(true)),
+        ("commandBlockOutput", Bool(true)),
+        ("commandModificationBlockLimit", Int(32768)),
+        ("disableElytraMovementCheck", Bool(false)),
+        ("disableRaids", Bool(false)),
+        ("doDaylightCycle", Bool(true)),
+        ("doEntityDrops", Bool(true)),
+        ("doFireTick", Bool(true)),
+        ("doImmediateRespawn", Bool(false)),
+        ("doInsomnia", Bool(true)),
+        ("doLimitedCrafting", Bool(false)),
+        ("doMobLoot", Bool(true)),
+        ("doMobSpawning", Bool(true)),
+        ("doPatrolSpawning", Bool(true)),
+        ("doTileDrops", Bool(true)),
+        ("doTraderSpawning", Bool(true)),
+        ("doVinesSpread", Bool(true)),
+        ("doWardenSpawning", Bool(true)),
+        ("doWeatherCycle", Bool(true)),
+        ("drowningDamage", Bool(true)),
+        ("enderPearlsVanishOnDeath", Bool(true)),
+        ("fallDamage", Bool(true)),
+        ("fireDamage", Bool(true)),
+        ("forgiveDeadPlayers", Bool(true)),
+        ("freezeDamage", Bool(true)),
+        ("globalSoundEvents", Bool(true)),
+        ("keepInventory", Bool(false)),
+        ("lavaSourceConversion", Bool(false)),
+        ("logAdminCommands", Bool(true)),
+        ("maxCommandChainLength", Int(65536)),
+        ("maxEntityCramming", Int(24)),
+        ("mobExplosionDropDecay", Bool(true)),
+        ("mobGriefing", Bool(true)),
+        ("naturalRegeneration", Bool(true)),
+        ("playersSleepingPercentage", Int(100)),
+        ("randomTickSpeed", Int(DEFAULT_RANDOM_TICK_SPEED as i32)),
+        ("reducedDebugInfo", Bool(false)),
+        ("sendCommandFeedback", Bool(true)),
+        ("showDeathMessages", Bool(true)),
+        ("snowAccumulationHeight", Int(1)),
+        ("spawnRadius", Int(10)),
+        ("spectatorsGenerateChunks", Bool(true)),
+        ("tntExplosionDropDecay", Bool(false)),
+        ("universalAnger", Bool(false)),
+        ("waterSourceConversion", Bool(true)),
+    ]
+};
+
+/// A world's game rules, typed: each rule keeps the type of its default, and can only be set to
+/// values of that type. Starts out with vanilla's rules; servers can `register()` their own.
+///
+/// Saved in `level.dat` as `Data.GameRules`, with every value as a string like vanilla does.
+/// Rules `level.dat` has that aren't registered (e.g. from a newer version) are kept as they were.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRules {
+    rules: BTreeMap<String, GameRuleValue>,
+    unknown: BTreeMap<String, String>,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameRules {
+    pub fn new() -> Self {
+        Self {
+            rules: VANILLA_GAME_RULES
+                .iter()
+                .map(|&(name, value)| (name.to_owned(), value))
+                .collect(),
+            unknown: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a rule of the server's own, set to `default`. A rule `level.dat` had under that name
+    /// gets its saved value back.
+    pub fn register(&mut self, name: &str, default: GameRuleValue) {
+        let value = self
+            .unknown
+            .remove(name)
+            .and_then(|saved| default.parse_like(&saved))
+            .unwrap_or(default);
+        self.rules.insert(name.to_owned(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<GameRuleValue> {
+        self.rules.get(name).copied()
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            GameRuleValue::Bool(b) => Some(b),
+            GameRuleValue::Int(_) => None,
+        }
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<i32> {
+        match self.get(name)? {
+            GameRuleValue::Int(i) => Some(i),
+            GameRuleValue::Bool(_) => None,
+        }
+    }
+
+    /// Sets a rule, which has to be registered and of the same type
+    pub fn set(&mut self, name: &str, value: GameRuleValue) -> Result<(), GameRuleError> {
+        let rule = self.rules.get_mut(name).ok_or(GameRuleError::UnknownRule)?;
+        if !rule.same_type(value) {
+            return Err(GameRuleError::WrongType);
+        }
+        *rule = value;
+        Ok(())
+    }
+
+    /// Sets a rule from a string, e.g. `"true"` or `"3"`
+    pub fn set_str(&mut self, name: &str, value: &str) -> Result<(), GameRuleError> {
+        let rule = self.get(name).ok_or(GameRuleError::UnknownRule)?;
+        let value = rule.parse_like(value).ok_or(GameRuleError::BadValue)?;
+        self.set(name, value)
+    }
+
+    /// Every registered rule and its value, by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, GameRuleValue)> {
+        self.rules.iter().map(|(name, &value)| (&name[..], value))
+    }
+
+    /// Whether the sun and moon move
+    pub fn do_daylight_cycle(&self) -> bool {
+        self.get_bool("doDaylightCycle").unwrap_or(true)
+    }
+
+    /// Whether players keep their items and experience when they die
+    pub fn keep_inventory(&self) -> bool {
+        self.get_bool("keepInventory").unwrap_or(false)
+    }
+
+    /// Whether players respawn right away, without the death screen
+    pub fn do_immediate_respawn(&self) -> bool {
+        self.get_bool("doImmediateRespawn").unwrap_or(false)
+    }
+
+    pub fn show_death_messages(&self) -> bool {
+        self.get_bool("showDeathMessages").unwrap_or(true)
+    }
+
+    /// Blocks random ticked per section per tick (see `RandomTicker`)
+    pub fn random_tick_speed(&self) -> u32 {
+        self.get_int("randomTickSpeed")
+            .map_or(DEFAULT_RANDOM_TICK_SPEED, |speed| speed.max(0) as u32)
+    }
+
+    /// Reads the `GameRules` compound of `level.dat`. Values that don't parse as their rule's
+    /// type are left at the default.
+    pub fn from_nbt(nbt: &CompoundNbt<'_>) -> Self {
+        let mut rules = Self::new();
+        for (name, value) in nbt.props() {
+            let Nbt::String(value) = value else {
+                continue;
+            };
+            match rules.get(name) {
+                Some(_) => {
+                    let _ = rules.set_str(name, value);
+                }
+                None => {
+                    rules.unknown.insert(name.to_owned(), value.to_string());
+                }
+            }
+        }
+        rules
+    }
+
+    /// The `GameRules` compound of `level.dat`
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let mut nbt = CompoundNbt::new("GameRules");
+        for (name, value) in &self.unknown {
+            nbt.set(name.clone(), Nbt::String(value.clone().into()));
+        }
+        for (name, value) in self.iter() {
+            nbt.set(name.to_owned(), Nbt::String(value.to_string().into()));
+        }
+        nbt
+    }
+
+    /// The rules in `level.dat`'s root compound, or the defaults if it has none
+    pub fn from_level_dat(root: &CompoundNbt<'_>) -> Self {
+        match level_data(root).and_then(|data| data.get("GameRules")) {
+            Some(Nbt::Compound(rules)) => Self::from_nbt(rules),
+            _ => Self::new(),
+        }
+    }
+
+    /// Puts the rules in `level.dat`'s root compound, e.g. in `SaveSource::level_data()`
+    pub fn write_level_dat(&self, root: &mut CompoundNbt<'static>) {
+        let mut data = match level_data(root) {
+            Some(data) => data.clone(),
+            None => CompoundNbt::new("Data"),
+        };
+        data.set("GameRules", Nbt::Compound(self.to_nbt()));
+        root.set("Data", Nbt::Compound(data));
+    }
+}
+
+/// The `Data` compound that `level.dat` keeps everything in
+pub(crate) fn level_data<'b, 'a>(root: &'b CompoundNbt<'a>) -> Option<&'b CompoundNbt<'a>> {
+    match root.get("Data") {
+        Some(Nbt::Compound(data)) => Some(data),
+        _ => None,
+    }
+}
+
+/// The `/gamerule` command, like vanilla's: `/gamerule <rule>` says what the rule is set to, and
+/// `/gamerule <rule> <value>` sets it.
+///
+/// `register()` adds it to a `CommandTree`, and `run()` runs the commands parsed with it.
+#[derive(Debug, Clone)]
+pub struct GameRuleCommand {
+    /// Node -> rule, for the nodes that query a rule
+    query: HashMap<usize, String>,
+    /// Node -> rule, for the nodes that set one
+    set: HashMap<usize, String>,
+}
+
+impl GameRuleCommand {
+    /// Adds `/gamerule` to `tree`, with every rule in `rules`
+    pub fn register(tree: &mut CommandTree, rules: &GameRules) -> Self {
+        let mut command = Self {
+            query: HashMap::new(),
+            set: HashMap::new(),
+        };
+        let gamerule = tree.literal(CommandTree::ROOT, "gamerule");
+        for (name, value) in rules.iter() {
+            let rule = tree.literal(gamerule, name);
+            tree.set_executable(rule);
+            let parser = match value {
+                GameRuleValue::Bool(_) => ArgumentParser::Bool,
+                GameRuleValue::Int(_) => ArgumentParser::Integer {
+                    min: None,
+                    max: None,
+                },
+            };
+            let value = tree.argument(rule, "value", parser);
+            tree.set_executable(value);
+            command.query.insert(rule, name.to_owned());
+            command.set.insert(value, name.to_owned());
+        }
+        command
+    }
+
+    /// Runs `cmd` if it's a `/gamerule` command, giving the feedback for whoever ran it.
+    /// None if it's some other command.
+    pub fn run(&self, rules: &mut GameRules, cmd: &ParsedCommand) -> Option<TextComponent> {
+        if let Some(name) = self.query.get(&cmd.node) {
+            let value = rules.get(name)?;
+            return Some(TextComponent::translate(
+                "commands.gamerule.query",
+                [
+                    TextComponent::text(name.clone()),
+                    TextComponent::text(value.to_string()),
+                ],
+            ));
+        }
+        let name = self.set.get(&cmd.node)?;
+        let value = match cmd.get("value")? {
+            Argument::Bool(b) => GameRuleValue::Bool(*b),
+            Argument::Integer(i) => GameRuleValue::Int(*i),
+            _ => return None,
+        };
+        rules.set(name, value).ok()?;
+        Some(TextComponent::translate(
+            "commands.gamerule.set",
+            [
+                TextComponent::text(name.clone()),
+                TextComponent::text(value.to_string()),
+            ],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_rules_saved_and_commanded() {
+        let mut rules = GameRules::new();
+        assert!(rules.do_daylight_cycle() && !rules.keep_inventory());
+        assert_eq!(rules.random_tick_speed(), 3);
+        assert_eq!(
+            rules.set("keepInventory", GameRuleValue::Int(1)),
+            Err(GameRuleError::WrongType)
+        );
+        assert_eq!(
+            rules.set_str("noSuchRule", "true"),
+            Err(GameRuleError::UnknownRule)
+        );
+        assert_eq!(
+            rules.set_str("randomTickSpeed", "fast"),
+            Err(GameRuleError::BadValue)
+        );
+        rules.set_str("randomTickSpeed", "10").unwrap();
+        assert_eq!(rules.random_tick_speed(), 10);
+
+        // level.dat keeps rules it doesn't know about, until they're registered
+        let mut root = CompoundNbt::new("");
+        rules.write_level_dat(&mut root);
+        let Some(Nbt::Compound(data)) = root.get("Data") else {
+            panic!("no Data");
+        };
+        let Some(Nbt::Compound(saved)) = data.get("GameRules") else {
+            panic!("no GameRules");
+        };
+        assert_eq!(
+            saved.get("randomTickSpeed"),
+            Some(&Nbt::String("10".into()))
+        );
+        let mut saved = saved.clone();
+        saved.set("fromTheFuture", Nbt::String("7".into()));
+        saved.set("keepInventory", Nbt::String("maybe".into()));
+        let mut loaded = GameRules::from_nbt(&saved);
+        assert_eq!(loaded.random_tick_speed(), 10);
+        assert!(!loaded.keep_inventory());
+        assert_eq!(loaded.get("fromTheFuture"), None);
+        assert_eq!(
+            loaded.to_nbt().get("fromTheFuture"),
+            Some(&Nbt::String("7".into()))
+        );
+        loaded.register("fromTheFuture", GameRuleValue::Int(0));
+        assert_eq!(loaded.get_int("fromTheFuture"), Some(7));
+
+        let mut tree = CommandTree::new();
+        let command = GameRuleCommand::register(&mut tree, &loaded);
+        let cmd = tree.parse("gamerule keepInventory true").unwrap();
+        assert!(command.run(&mut loaded, &cmd).is_some());
+        assert!(loaded.keep_inventory());
+        let cmd = tree.parse("gamerule fromTheFuture").unwrap();
+        let feedback = command.run(&mut loaded, &cmd).unwrap();
+        assert_eq!(
+            feedback.to_json(),
+            TextComponent::translate(
+                "commands.gamerule.query",
+                [
+                    TextComponent::text("fromTheFuture"),
+                    TextComponent::text("7")
+                ]
+            )
+            .to_json()
+        );
+        assert!(tree.parse("gamerule keepInventory 3").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use crate::*;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Generates chunks for a `ChunkGenPool`, on its worker threads
+pub trait WorldGenerator: Send + Sync + 'static {
+    /// A generated chunk, e.g. its sections, or a `ChunkPacketSource` to send it with
+    type Chunk: Send + Sync + 'static;
+
+    fn generate(&self, chunk: ChunkPos) -> Self::Chunk;
+}
+
+/// Why a `ChunkGenPool` has no chunk for a request: the generator panicked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenerateError {
+    pub chunk: ChunkPos,
+    /// What the generator panicked with, if it was a message
+    pub message: Option<String>,
+}
+
+impl GenerateError {
+    fn new(chunk: ChunkPos, payload: &(dyn Any + Send)) -> Self {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(s) => Some(s.to_string()),
+            None => payload.downcast_ref::<String>().cloned(),
+        };
+        Self { chunk, message }
+    }
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "generating chunk {:?} panicked", self.chunk)?;
+        match &self.message {
+            Some(message) => write!(f, ": {message}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
+
+/// What a chunk request gets
+type Generated<C> = Result<Arc<C>, GenerateError>;
+
+/// Called with a generated chunk, or why there isn't one
+type Callback<C> = Box<dyn FnOnce(ChunkPos, Generated<C>)>;
+
+/// Runs a `WorldGenerator` on worker threads, so generating the area around a new player doesn't
+/// hold up the tick.
+///
+/// Chunks are asked for with `request()`, and handed to the callbacks given for them when the tick
+/// loop calls `run_callbacks()` (e.g. from `Server::tick()`). A chunk requested again while it's
+/// being generated is only generated once, for every callback.
+pub struct ChunkGenPool<G: WorldGenerator> {
+    /// None once dropped, to stop the workers
+    jobs: Option<Sender<ChunkPos>>,
+    done: Receiver<(ChunkPos, Generated<G::Chunk>)>,
+    workers: Vec<JoinHandle<()>>,
+    /// Chunks being generated, and who's waiting for each
+    pending: HashMap<ChunkPos, Vec<Callback<G::Chunk>>>,
+}
+
+impl<G: WorldGenerator> ChunkGenPool<G> {
+    /// See `ChunkEncodePool::default_threads()` for a sensible number of threads
+    pub fn new(generator: G, threads: usize) -> Self {
+        assert!(threads > 0, "ChunkGenPool needs at least one thread");
+        let generator = Arc::new(generator);
+        let (jobs, job_rx) = mpsc::channel::<ChunkPos>();
+        let (done_tx, done) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let workers = (0..threads)
+            .map(|_| {
+                let generator = Arc::clone(&generator);
+                let job_rx = Arc::clone(&job_rx);
+                let done_tx = done_tx.clone();
+                thread::spawn(move || loop {
+                    // the lock is only held while waiting for a job, not while generating it
+                    let Ok(chunk) = job_rx.lock().unwrap().recv() else {
+                        return;
+                    };
+                    let generated =
+                        panic::catch_unwind(AssertUnwindSafe(|| generator.generate(chunk)))
+                            .map(Arc::new)
+                            .map_err(|payload| GenerateError::new(chunk, &*payload));
+                    if done_tx.send((chunk, generated)).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        Self {
+            jobs: Some(jobs),
+            done,
+            workers,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Generates `chunk`, unless it's already being generated, and calls `callback` with it (or
+    /// why it couldn't be) from a later `run_callbacks()`. Returns whether generating it started.
+    pub fn request(
+        &mut self,
+        chunk: ChunkPos,
+        callback: impl FnOnce(ChunkPos, Generated<G::Chunk>) + 'static,
+    ) -> bool {
+        let callbacks = self.pending.entry(chunk).or_default();
+        callbacks.push(Box::new(callback));
+        if callbacks.len() > 1 {
+            return false;
+        }
+        // the workers only stop once we're dropped
+        self.jobs.as_ref().unwrap().send(chunk).unwrap();
+        true
+    }
+
+    pub fn is_pending(&self, chunk: ChunkPos) -> bool {
+        self.pending.contains_key(&chunk)
+    }
+
+    /// How many chunks are being generated
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Calls the callbacks of the chunks generated since the last call, without waiting for any.
+    /// Returns how many chunks were done.
+    pub fn run_callbacks(&mut self) -> usize {
+        let mut count = 0;
+        while let Ok((chunk, generated)) = self.done.try_recv() {
+            count += 1;
+            for callback in self.pending.remove(&chunk).unwrap_or_default() {
+                callback(chunk, generated.clone());
+            }
+        }
+        count
+    }
+}
+
+impl<G: WorldGenerator> Drop for ChunkGenPool<G> {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<G: WorldGenerator> std::fmt::Debug for ChunkGenPool<G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkGenPool")
+            .field("threads", &self.workers.len())
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// Generates each chunk as its x + z, and panics on (9, 9)
+    struct Sum(Arc<AtomicUsize>);
+
+    impl WorldGenerator for Sum {
+        type Chunk = i32;
+
+        fn generate(&self, chunk: ChunkPos) -> i32 {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            if chunk == ChunkPos::new(9, 9) {
+                panic!("no chunk here");
+            }
+            thread::sleep(Duration::from_millis(20));
+            chunk.x + chunk.z
+        }
+    }
+
+    #[test]
+    fn dedups_requests() {
+        let generated = Arc::new(AtomicUsize::new(0));
+        let mut pool = ChunkGenPool::new(Sum(Arc::clone(&generated)), 2);
+        let results = Rc::new(RefCell::new(Vec::new()));
+        for pos in [(1, 2), (3, 4), (1, 2), (9, 9)] {
+            let results = Rc::clone(&results);
+            pool.request(ChunkPos::new(pos.0, pos.1), move |chunk, generated| {
+                results.borrow_mut().push((chunk.x, generated.map(|g| *g)));
+            });
+        }
+        assert!(pool.is_pending(ChunkPos::new(1, 2)));
+        assert_eq!(pool.pending_count(), 3);
+
+        let start = Instant::now();
+        while pool.pending_count() > 0 && start.elapsed() < Duration::from_secs(5) {
+            pool.run_callbacks();
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(generated.load(Ordering::Relaxed), 3);
+        let mut results = results.take();
+        results.sort_by_key(|(x, _)| *x);
+        let failed = GenerateError {
+            chunk: ChunkPos::new(9, 9),
+            message: Some("no chunk here".to_owned()),
+        };
+        assert_eq!(
+            results,
+            [(1, Ok(3)), (1, Ok(3)), (3, Ok(7)), (9, Err(failed))]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::*;
+
+/// Vanilla's full health
+pub const MAX_HEALTH: f32 = 20.0;
+
+/// Vanilla's full food level
+pub const MAX_FOOD: i32 = 20;
+
+// Exhaustion from doing things, as vanilla adds it
+pub const EXHAUSTION_JUMP: f32 = 0.05;
+pub const EXHAUSTION_SPRINT_JUMP: f32 = 0.2;
+pub const EXHAUSTION_ATTACK: f32 = 0.1;
+pub const EXHAUSTION_DAMAGE_TAKEN: f32 = 0.1;
+pub const EXHAUSTION_BLOCK_BROKEN: f32 = 0.005;
+/// Per meter sprinted
+pub const EXHAUSTION_SPRINT: f32 = 0.1;
+/// Per meter swum
+pub const EXHAUSTION_SWIM: f32 = 0.01;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Difficulty {
+    Peaceful = 0,
+    Easy = 1,
+    Normal = 2,
+    Hard = 3,
+}
+
+/// A player's health, food and saturation, with vanilla's hunger rules: exhaustion uses up
+/// saturation and then food, a full food bar heals, and an empty one starves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Health {
+    pub health: f32,
+    pub max_health: f32,
+    /// 0 to `MAX_FOOD`
+    pub food: i32,
+    /// Used up before food. Never more than `food`.
+    pub saturation: f32,
+    /// Every 4.0 of it costs a point of saturation (or food)
+    pub exhaustion: f32,
+    /// Ticks towards the next heal or starvation damage
+    food_timer: u32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            health: MAX_HEALTH,
+            max_health: MAX_HEALTH,
+            food: MAX_FOOD,
+            saturation: 5.0,
+            exhaustion: 0.0,
+            food_timer: 0,
+        }
+    }
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+
+    /// Returns true if this killed the player
+    pub fn damage(&mut self, amount: f32) -> bool {
+        if self.is_dead() || amount <= 0.0 {
+            return false;
+        }
+        self.add_exhaustion(EXHAUSTION_DAMAGE_TAKEN);
+        self.health = (self.health - amount).max(0.0);
+        self.is_dead()
+    }
+
+    pub fn heal(&mut self, amount: f32) {
+        if !self.is_dead() {
+            self.health = (self.health + amount).min(self.max_health);
+        }
+    }
+
+    /// See the `EXHAUSTION_*` constants for how much things cost
+    pub fn add_exhaustion(&mut self, amount: f32) {
+        self.exhaustion = (self.exhaustion + amount).min(40.0);
+    }
+
+    /// Eats something that restores `food` points, with a saturation modifier (e.g. 0.6 for
+    /// bread, 0.8 for steak)
+    pub fn eat(&mut self, food: i32, saturation_modifier: f32) {
+        self.food = (self.food + food).min(MAX_FOOD);
+        self.saturation =
+            (self.saturation + food as f32 * saturation_modifier * 2.0).min(self.food as f32);
+    }
+
+    /// Whether the player is hungry enough to eat (anything but golden apples and the like)
+    pub fn can_eat(&self) -> bool {
+        self.food < MAX_FOOD
+    }
+
+    /// Whether the player can sprint
+    pub fn can_sprint(&self) -> bool {
+        self.food > 6
+    }
+
+    /// Runs one tick of hunger, healing and starving. Returns true if anything the client shows
+    /// changed, i.e. a `packet()` should be sent.
+    pub fn tick(&mut self, difficulty: Difficulty, natural_regeneration: bool) -> bool {
+        let before = (self.health, self.food, self.saturation);
+        if self.is_dead() {
+            return false;
+        }
+
+        if self.exhaustion > 4.0 {
+            self.exhaustion -= 4.0;
+            if self.saturation > 0.0 {
+                self.saturation = (self.saturation - 1.0).max(0.0);
+            } else if difficulty != Difficulty::Peaceful {
+                self.food = (self.food - 1).max(0);
+            }
+        }
+
+        let hurt = self.health < self.max_health;
+        if natural_regeneration && self.saturation > 0.0 && hurt && self.food >= MAX_FOOD {
+            // fast healing off saturation
+            self.food_timer += 1;
+            if self.food_timer >= 10 {
+                let used = self.saturation.min(6.0);
+                self.heal(used / 6.0);
+                self.add_exhaustion(used);
+                self.food_timer = 0;
+            }
+        } else if natural_regeneration && self.food >= 18 && hurt {
+            self.food_timer += 1;
+            if self.food_timer >= 80 {
+                self.heal(1.0);
+                self.add_exhaustion(6.0);
+                self.food_timer = 0;
+            }
+        } else if self.food <= 0 {
+            self.food_timer += 1;
+            if self.food_timer >= 80 {
+                let can_starve = match difficulty {
+                    Difficulty::Hard => true,
+                    Difficulty::Normal => self.health > 1.0,
+                    Difficulty::Easy | Difficulty::Peaceful => self.health > 10.0,
+                };
+                if can_starve {
+                    self.health = (self.health - 1.0).max(0.0);
+                }
+                self.food_timer = 0;
+            }
+        } else {
+            self.food_timer = 0;
+        }
+
+        (self.health, self.food, self.saturation) != before
+    }
+
+    pub fn packet(&self) -> OutPacket<'static> {
+        OutPacket::SetHealth {
+            health: self.health,
+            food: self.food,
+            saturation: self.saturation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hunger() {
+        let mut h = Health::new();
+        assert!(!h.damage(5.0));
+        assert_eq!(h.health, 15.0);
+
+        // full food and saturation heals a point per 10 ticks, using up saturation
+        let changed: usize = (0..10)
+            .map(|_| h.tick(Difficulty::Normal, true) as usize)
+            .sum();
+        assert_eq!(changed, 1);
+        assert!((h.health - 15.0 - 5.0 / 6.0).abs() < 1e-6);
+        assert_eq!(h.exhaustion, 5.0 + EXHAUSTION_DAMAGE_TAKEN);
+        h.tick(Difficulty::Normal, true);
+        assert_eq!(h.saturation, 4.0);
+
+        // starving stops at half a heart on normal
+        let mut h = Health {
+            food: 0,
+            saturation: 0.0,
+            health: 2.0,
+            ..Health::new()
+        };
+        for _ in 0..80 * 5 {
+            h.tick(Difficulty::Normal, true);
+        }
+        assert_eq!(h.health, 1.0);
+
+        h.eat(5, 0.6);
+        assert_eq!((h.food, h.saturation), (5, 5.0));
+        assert!(!h.can_sprint());
+        assert!(h.damage(3.0));
+        assert!(!h.tick(Difficulty::Hard, true));
+        assert!(matches!(
+            h.packet(),
+            OutPacket::SetHealth {
+                health: 0.0,
+                food: 5,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::*;
+use std::time::{Duration, Instant};
+
+/// Tracks when a player last did something, like vanilla does for `player-idle-timeout`: moved,
+/// looked around, chatted, or used, hit or broke something. Clients resend their position every
+/// second even when standing still, so that on its own doesn't count.
+#[derive(Debug, Clone)]
+pub struct IdleTracker<C: Clock = SystemClock> {
+    clock: C,
+    last_action: Instant,
+    /// From the last movement packet
+    pos: Option<[f64; 3]>,
+    rotation: Option<[f32; 2]>,
+}
+
+impl IdleTracker {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<C: Clock> IdleTracker<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            last_action: clock.now(),
+            clock,
+            pos: None,
+            rotation: None,
+        }
+    }
+
+    /// Handles a packet from the client. Returns whether it was something the player did.
+    pub fn handle(&mut self, packet: &InPacket) -> bool {
+        let acted = match *packet {
+            InPacket::SetPlayerPosition { x, y, z, .. } => self.moved(Some([x, y, z]), None),
+            InPacket::SetPlayerPositionAndRotation {
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+                ..
+            } => self.moved(Some([x, y, z]), Some([yaw, pitch])),
+            InPacket::SetPlayerRotation { yaw, pitch, .. } => self.moved(None, Some([yaw, pitch])),
+            InPacket::ChatCommand { .. }
+            | InPacket::ChatMessage { .. }
+            | InPacket::PlayerCommand { .. }
+            | InPacket::UseItem { .. }
+            | InPacket::UseItemOn { .. }
+            | InPacket::Interact { .. }
+            | InPacket::PlayerAction { .. }
+            | InPacket::PlaceRecipe { .. }
+            | InPacket::ClientStatus { .. } => true,
+            _ => false,
+        };
+        if acted {
+            self.last_action = self.clock.now();
+        }
+        acted
+    }
+
+    /// Records a movement, returning whether it moved or turned the player
+    fn moved(&mut self, pos: Option<[f64; 3]>, rotation: Option<[f32; 2]>) -> bool {
+        let mut changed = false;
+        if pos.is_some() && pos != self.pos {
+            // the first position the client sends isn't the player moving
+            changed |= self.pos.is_some();
+            self.pos = pos;
+        }
+        if rotation.is_some() && rotation != self.rotation {
+            changed |= self.rotation.is_some();
+            self.rotation = rotation;
+        }
+        changed
+    }
+
+    /// How long since the player last did something (or since this was made)
+    pub fn idle_for(&self) -> Duration {
+        self.clock.now() - self.last_action
+    }
+
+    /// Counts as the player doing something now, e.g. when it respawns or is teleported
+    pub fn reset(&mut self) {
+        self.last_action = self.clock.now();
+    }
+}
+
+impl Default for IdleTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_actions_count() {
+        let mut idle = IdleTracker::new();
+        let still = InPacket::SetPlayerPosition {
+            x: 0.5,
+            y: 64.0,
+            z: 0.5,
+            on_ground: true,
+        };
+        assert!(!idle.handle(&still));
+        // the once-a-second reminder of where it is
+        assert!(!idle.handle(&still));
+        assert!(!idle.handle(&InPacket::KeepAlive { id: 1 }));
+        assert!(!idle.handle(&InPacket::SetPlayerRotation {
+            yaw: 90.0,
+            pitch: 0.0,
+            on_ground: true,
+        }));
+        assert!(idle.handle(&InPacket::SetPlayerPositionAndRotation {
+            x: 0.5,
+            y: 64.0,
+            z: 0.5,
+            yaw: 45.0,
+            pitch: 0.0,
+            on_ground: true,
+        }));
+        assert!(idle.handle(&InPacket::SetPlayerPosition {
+            x: 1.0,
+            y: 64.0,
+            z: 0.5,
+            on_ground: true,
+        }));
+        assert!(idle.handle(&InPacket::UseItem {
+            hand: Hand::Main,
+            sequence: 0,
+        }));
+        assert!(idle.idle_for() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn idle_time() {
+        let clock = ManualClock::new();
+        let mut idle = IdleTracker::with_clock(clock.clone());
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(idle.idle_for(), Duration::from_secs(30));
+        assert!(idle.handle(&InPacket::ChatMessage {
+            message: "hi",
+            timestamp: 0,
+            salt: 0,
+            signature: None,
+        }));
+        assert_eq!(idle.idle_for(), Duration::ZERO);
+        clock.advance(Duration::from_secs(5));
+        idle.reset();
+        assert_eq!(idle.idle_for(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::*;
+use std::collections::HashMap;
+
+// 1.20.2 has no Player Input packet with key states: it was added in 1.21.2, replacing the
+// steering-only `InPacket::PlayerInput`, whose signs can't tell which keys are held. libmc doesn't
+// speak 1.21.2, so it can't decode one; servers that get the flags byte some other way (e.g. with
+// a `ProtocolTranslator` of their own, as an `InPacket::Unknown`) pass it to `InputCapture`.
+
+/// Which movement keys a player is holding
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct InputKeys {
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+    pub sneak: bool,
+    pub sprint: bool,
+}
+
+impl InputKeys {
+    /// From the 1.21.2+ Player Input flags byte
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            forward: bits & 0x01 != 0,
+            backward: bits & 0x02 != 0,
+            left: bits & 0x04 != 0,
+            right: bits & 0x08 != 0,
+            jump: bits & 0x10 != 0,
+            sneak: bits & 0x20 != 0,
+            sprint: bits & 0x40 != 0,
+        }
+    }
+
+    /// The 1.21.2+ Player Input flags byte
+    pub fn bits(self) -> u8 {
+        (self.forward as u8)
+            | (self.backward as u8) << 1
+            | (self.left as u8) << 2
+            | (self.right as u8) << 3
+            | (self.jump as u8) << 4
+            | (self.sneak as u8) << 5
+            | (self.sprint as u8) << 6
+    }
+
+    /// -1, 0 or 1 on each axis: (right - left, forward - backward)
+    pub fn direction(self) -> (i8, i8) {
+        (
+            self.right as i8 - self.left as i8,
+            self.forward as i8 - self.backward as i8,
+        )
+    }
+}
+
+/// What a player's input was during one tick
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct InputSnapshot {
+    /// Held at the end of the tick
+    pub held: InputKeys,
+    /// Pressed at some point during the tick, even if already released again
+    pub pressed: InputKeys,
+    pub released: InputKeys,
+}
+
+#[derive(Debug, Default)]
+struct PlayerInputState {
+    held: InputKeys,
+    /// since the last snapshot
+    pressed: u8,
+    released: u8,
+}
+
+/// Collects players' movement key states for custom controls (steering a custom mount,
+/// moving a menu cursor, ...) and hands them out as one snapshot per tick.
+///
+/// Nothing records keys by itself: see `set_keys()`.
+#[derive(Debug, Default)]
+pub struct InputCapture {
+    players: HashMap<ClientID, PlayerInputState>,
+}
+
+impl InputCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the keys `cid` is holding now, e.g. from `InputKeys::from_bits()` of the flags of
+    /// a 1.21.2+ Player Input packet
+    pub fn set_keys(&mut self, cid: ClientID, keys: InputKeys) {
+        let state = self.players.entry(cid).or_default();
+        let (before, after) = (state.held.bits(), keys.bits());
+        state.pressed |= after & !before;
+        state.released |= before & !after;
+        state.held = keys;
+    }
+
+    /// The keys `cid` is currently holding
+    pub fn keys(&self, cid: ClientID) -> InputKeys {
+        self.players.get(&cid).map(|s| s.held).unwrap_or_default()
+    }
+
+    /// Takes the input of `cid` since the previous snapshot. Call this once per tick per player.
+    pub fn snapshot(&mut self, cid: ClientID) -> InputSnapshot {
+        let Some(state) = self.players.get_mut(&cid) else {
+            return InputSnapshot::default();
+        };
+        let snapshot = InputSnapshot {
+            held: state.held,
+            pressed: InputKeys::from_bits(state.pressed),
+            released: InputKeys::from_bits(state.released),
+        };
+        state.pressed = 0;
+        state.released = 0;
+        snapshot
+    }
+
+    /// Forgets a player (e.g. when they disconnect)
+    pub fn remove(&mut self, cid: ClientID) {
+        self.players.remove(&cid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tap_within_tick() {
+        let cid = ClientID::new(0, 0);
+        let mut input = InputCapture::new();
+
+        // left + jump, then just left
+        input.set_keys(cid, InputKeys::from_bits(0x14));
+        input.set_keys(cid, InputKeys::from_bits(0x04));
+        let snap = input.snapshot(cid);
+        assert!(snap.held.left && !snap.held.jump);
+        assert!(snap.pressed.jump && snap.pressed.left && snap.released.jump);
+        assert_eq!(snap.held.direction(), (-1, 0));
+        input.set_keys(cid, InputKeys::from_bits(0x44));
+        assert!(input.keys(cid).sprint);
+
+        let snap = input.snapshot(cid);
+        assert_eq!(snap.pressed, InputKeys::from_bits(0x40));
+        assert!(snap.held.left);
+
+        let keys = InputKeys::from_bits(0b100001);
+        assert!(keys.forward && keys.sneak);
+        assert_eq!(keys.bits(), 0b100001);
+    }
+}
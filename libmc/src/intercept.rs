@@ -0,0 +1,69 @@
+//! Packet interceptors: hooks that see every packet on a connection before it's decoded or sent,
+//! for logging, anti-cheat, or experimenting with the protocol without changing libmc.
+
+use crate::*;
+use std::fmt::Debug;
+
+/// Observes, rewrites or drops a connection's packets.
+///
+/// Packets are passed as their ID followed by their body, without the length prefix, in libmc's
+/// protocol version (i.e. after a `ProtocolTranslator` has rewritten the client's packets, and
+/// before it rewrites libmc's). `state` is the connection's state. Returning false drops the
+/// packet, and later interceptors don't see it.
+pub trait PacketInterceptor: Debug {
+    /// A packet from the client, before it's decoded
+    fn inbound(&mut self, _state: ProtocolState, _packet: &mut Vec<u8>) -> bool {
+        true
+    }
+
+    /// A packet to the client, after it's encoded but before it's written
+    fn outbound(&mut self, _state: ProtocolState, _packet: &mut Vec<u8>) -> bool {
+        true
+    }
+}
+
+/// Runs `packet` through `interceptors` in order. Returns false if one dropped it.
+pub(crate) fn intercept(
+    interceptors: &mut [Box<dyn PacketInterceptor>],
+    dir: PacketDirection,
+    state: ProtocolState,
+    packet: &mut Vec<u8>,
+) -> bool {
+    interceptors.iter_mut().all(|i| match dir {
+        PacketDirection::Serverbound => i.inbound(state, packet),
+        PacketDirection::Clientbound => i.outbound(state, packet),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drops clientbound Clear Titles and turns serverbound Swing Arm into Use Item
+    #[derive(Debug)]
+    struct Meddler;
+
+    impl PacketInterceptor for Meddler {
+        fn inbound(&mut self, _state: ProtocolState, packet: &mut Vec<u8>) -> bool {
+            if packet[0] == 0x2F {
+                packet.splice(.., [0x32, 0x00, 0x07]);
+            }
+            true
+        }
+
+        fn outbound(&mut self, _state: ProtocolState, packet: &mut Vec<u8>) -> bool {
+            packet[0] != 0x0F
+        }
+    }
+
+    #[test]
+    fn meddling() {
+        let mut ps = PacketStream::new(Vec::new());
+        ps.add_interceptor(Box::new(Meddler));
+        assert_eq!(ps.send(OutPacket::ClearTitles { reset: false }).unwrap(), 0);
+        assert!(ps.writer_mut().is_empty());
+
+        let frames = ps.translate_incoming(frame_packet(&[0x2F, 0x00])).unwrap();
+        assert_eq!(frames, [frame_packet(&[0x32, 0x00, 0x07])]);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Maps item identifiers (e.g. `minecraft:stone`) to the IDs items have in the protocol, which
+/// change between versions.
+///
+/// Load it from the registries report of the vanilla data generator
+/// (`java -DbundlerMainClass=net.minecraft.data.Main -jar server.jar --reports`, then
+/// `generated/reports/registries.json`).
+#[derive(Debug, Clone, Default)]
+pub struct ItemRegistry {
+    ids: HashMap<String, i32>,
+    /// Indexed by protocol ID
+    names: Vec<Option<String>>,
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(io::Error),
+    Json(JsonError),
+    /// The JSON isn't a registries report (or tag file)
+    BadReport(&'static str),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Io(e) => write!(f, "reading registry data: {e}"),
+            RegistryError::Json(e) => write!(f, "parsing registry data: {e}"),
+            RegistryError::BadReport(msg) => write!(f, "bad registry data: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+impl From<io::Error> for RegistryError {
+    fn from(e: io::Error) -> Self {
+        RegistryError::Io(e)
+    }
+}
+
+impl ItemRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the `minecraft:item` registry from a registries report
+    pub fn from_registries_report(json: &str) -> Result<Self, RegistryError> {
+        let json = Json::parse(json).map_err(RegistryError::Json)?;
+        let entries = json
+            .get("minecraft:item")
+            .ok_or(RegistryError::BadReport("no minecraft:item registry"))?
+            .get("entries")
+            .and_then(Json::as_object)
+            .ok_or(RegistryError::BadReport("no item entries"))?;
+        let mut registry = Self::new();
+        for (name, entry) in entries {
+            let id = entry
+                .get("protocol_id")
+                .and_then(Json::as_f64)
+                .ok_or(RegistryError::BadReport("item without a protocol_id"))?;
+            registry.insert(name, id as i32);
+        }
+        Ok(registry)
+    }
+
+    /// Reads a registries report from a file, see `from_registries_report()`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        Self::from_registries_report(&std::fs::read_to_string(path)?)
+    }
+
+    /// Adds (or renumbers) an item
+    pub fn insert(&mut self, name: &str, id: i32) {
+        let index = usize::try_from(id).expect("negative item ID");
+        if let Some(old) = self.ids.insert(name.to_owned(), id) {
+            self.names[old as usize] = None;
+        }
+        if self.names.len() <= index {
+            self.names.resize(index + 1, None);
+        }
+        if let Some(old_name) = self.names[index].replace(name.to_owned()) {
+            if old_name != name {
+                self.ids.remove(&old_name);
+            }
+        }
+    }
+
+    /// The protocol ID of the item `name`, which may leave out the `minecraft:` namespace
+    pub fn id(&self, name: &str) -> Option<i32> {
+        match self.ids.get(name) {
+            Some(&id) => Some(id),
+            None if !name.contains(':') => self.ids.get(&format!("minecraft:{name}")).copied(),
+            None => None,
+        }
+    }
+
+    /// The identifier of the item with protocol ID `id`
+    pub fn name(&self, id: i32) -> Option<&str> {
+        let index = usize::try_from(id).ok()?;
+        self.names.get(index)?.as_deref()
+    }
+
+    /// A stack of the item `name`. None if there's no such item.
+    pub fn stack(&self, name: &str, count: i8) -> Option<Slot<'static>> {
+        Some(Slot::new(self.id(name)?, count))
+    }
+
+    /// How many items there are
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// The contents of a non-empty inventory slot, as sent over the network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot<'a> {
+    /// The item's protocol ID, see `ItemRegistry`
+    pub item_id: i32,
+    pub count: i8,
+    /// The item's NBT (enchantments, custom name, ...) in network form, starting with its tag type
+    pub nbt: Option<&'a [u8]>,
+}
+
+impl Slot<'_> {
+    pub fn new(item_id: i32, count: i8) -> Self {
+        Self {
+            item_id,
+            count,
+            nbt: None,
+        }
+    }
+
+    /// The item's identifier, e.g. `minecraft:stone`
+    pub fn item_name<'r>(&self, registry: &'r ItemRegistry) -> Option<&'r str> {
+        registry.name(self.item_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry() {
+        let report = r#"{
+            "minecraft:block": {"entries": {"minecraft:stone": {"protocol_id": 1}}, "protocol_id": 4},
+            "minecraft:item": {
+                "default": "minecraft:air",
+                "entries": {
+                    "minecraft:air": {"protocol_id": 0},
+                    "minecraft:stone": {"protocol_id": 1},
+                    "minecraft:diamond": {"protocol_id": 802}
+                },
+                "protocol_id": 7
+            }
+        }"#;
+        let items = ItemRegistry::from_registries_report(report).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items.id("minecraft:diamond"), Some(802));
+        assert_eq!(items.id("stone"), Some(1));
+        assert_eq!(items.name(802), Some("minecraft:diamond"));
+        assert_eq!(items.name(5), None);
+        assert!(matches!(
+            ItemRegistry::from_registries_report("{}"),
+            Err(RegistryError::BadReport(_))
+        ));
+
+        // a creative player putting 64 diamonds in their first hotbar slot
+        let mut packet = vec![0x2E, 0x00, 36, 0x01];
+        write_varint(&mut packet, 802);
+        packet.extend_from_slice(&[64, 0x00]);
+        let InPacket::SetCreativeModeSlot {
+            slot: 36,
+            item: Some(item),
+        } = decode_packet(ProtocolState::Play, &packet).unwrap()
+        else {
+            panic!("wrong packet");
+        };
+        assert_eq!(item, items.stack("diamond", 64).unwrap());
+        assert_eq!(item.item_name(&items), Some("minecraft:diamond"));
+    }
+}
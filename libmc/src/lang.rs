@@ -0,0 +1,194 @@
+use crate::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// Translations for `TextComponent::translate` keys, from a vanilla-style language file (a flat
+/// JSON object like `{"chat.type.text": "<%s> %s"}`).
+///
+/// Clients translate components themselves; this is for turning them into plain text where
+/// that doesn't happen, e.g. the console, logs, or clients that can't localize them.
+#[derive(Debug, Clone, Default)]
+pub struct Language {
+    translations: HashMap<String, String>,
+}
+
+impl Language {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_json(s: &str) -> io::Result<Self> {
+        let json = Json::parse(s).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let entries = json.as_object().ok_or_else(|| {
+            io::Error::new(ErrorKind::InvalidData, "language file isn't an object")
+        })?;
+        let mut lang = Self::new();
+        for (key, value) in entries {
+            if let Some(value) = value.as_str() {
+                lang.insert(key.clone(), value.to_owned());
+            }
+        }
+        Ok(lang)
+    }
+
+    /// Loads a language file, like `en_us.json` from the vanilla client jar
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Adds or replaces a translation. `%s` is replaced by the next argument, `%2$s` by the second
+    /// one, and `%%` by `%`.
+    pub fn insert(&mut self, key: impl Into<String>, format: impl Into<String>) {
+        self.translations.insert(key.into(), format.into());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.translations.get(key).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.translations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.translations.is_empty()
+    }
+
+    /// `c` with every translation replaced by its text, keeping styles. Keys that aren't known
+    /// are shown as-is, like the client does.
+    pub fn flatten(&self, c: &TextComponent) -> TextComponent {
+        let mut out = TextComponent {
+            text: String::new(),
+            translate: None,
+            color: c.color,
+            bold: c.bold,
+            italic: c.italic,
+            click_event: c.click_event.clone(),
+            hover_event: c.hover_event.clone(),
+            extra: Vec::new(),
+        };
+        match &c.translate {
+            Some(t) => {
+                let format = self.get(&t.key).unwrap_or(&t.key);
+                for piece in split_format(format, t.with.len()) {
+                    out.extra.push(match piece {
+                        Piece::Text(text) => TextComponent::text(text),
+                        Piece::Arg(i) => self.flatten(&t.with[i]),
+                    });
+                }
+            }
+            None => out.text = c.text.clone(),
+        }
+        out.extra
+            .extend(c.extra.iter().map(|child| self.flatten(child)));
+        out
+    }
+
+    /// `c`'s text, translated, without any styling
+    pub fn plain_text(&self, c: &TextComponent) -> String {
+        let mut out = String::new();
+        self.write_plain(c, &mut out);
+        out
+    }
+
+    fn write_plain(&self, c: &TextComponent, out: &mut String) {
+        match &c.translate {
+            Some(t) => {
+                let format = self.get(&t.key).unwrap_or(&t.key);
+                for piece in split_format(format, t.with.len()) {
+                    match piece {
+                        Piece::Text(text) => out.push_str(&text),
+                        Piece::Arg(i) => self.write_plain(&t.with[i], out),
+                    }
+                }
+            }
+            None => out.push_str(&c.text),
+        }
+        for child in &c.extra {
+            self.write_plain(child, out);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    /// Index into the arguments
+    Arg(usize),
+}
+
+/// Splits a translation into text and the arguments to put between it. Specifiers for
+/// arguments that weren't given are left in the text.
+fn split_format(format: &str, args: usize) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut next_arg = 0;
+    let mut rest = format;
+    while let Some(i) = rest.find('%') {
+        text.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (arg, len) = if rest.starts_with("%%") {
+            text.push('%');
+            rest = &rest[2..];
+            continue;
+        } else if rest.starts_with("%s") {
+            next_arg += 1;
+            (next_arg - 1, 2)
+        } else {
+            // %n$s, numbered from 1
+            let digits = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - 1);
+            let n = rest[1..1 + digits].parse::<usize>().ok().filter(|&n| n > 0);
+            match n {
+                Some(n) if rest[1 + digits..].starts_with("$s") => (n - 1, digits + 3),
+                _ => (usize::MAX, 1),
+            }
+        };
+        if arg < args {
+            if !text.is_empty() {
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+            }
+            pieces.push(Piece::Arg(arg));
+        } else {
+            text.push_str(&rest[..len]);
+        }
+        rest = &rest[len..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_translations() {
+        let lang = Language::from_json(
+            r#"{"chat.type.text": "<%s> %s", "swap": "%2$s then %1$s: 100%%", "x": 5}"#,
+        )
+        .unwrap();
+        assert_eq!(lang.len(), 2);
+
+        let name = TextComponent::text("Steve").color(ChatColor::Gold);
+        let c = TextComponent::translate("chat.type.text", [name, "hi".into()]).bold(true);
+        assert_eq!(lang.plain_text(&c), "<Steve> hi");
+        let flat = lang.flatten(&c);
+        assert_eq!(flat.translate, None);
+        assert_eq!(flat.bold, Some(true));
+        assert_eq!(flat.extra[1].color, Some(ChatColor::Gold));
+
+        let c = TextComponent::translate("swap", ["a".into(), "b".into()])
+            .append(TextComponent::text("!"));
+        assert_eq!(lang.plain_text(&c), "b then a: 100%!");
+        // unknown keys, and specifiers without an argument, are left alone
+        let c = TextComponent::translate("no.such.key %s", []);
+        assert_eq!(lang.plain_text(&c), "no.such.key %s");
+    }
+}
@@ -0,0 +1,316 @@
+use crate::*;
+
+/// Metadata index of a text display's text
+const TEXT_DISPLAY_TEXT: u8 = 23;
+/// Metadata index of a display entity's billboard constraints
+const DISPLAY_BILLBOARD: u8 = 15;
+
+/// Where a leaderboard is shown
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LeaderboardDisplay {
+    /// The scoreboard sidebar
+    Sidebar,
+    /// A text display entity floating at a position
+    Hologram {
+        entity_id: i32,
+        uuid: Uuid,
+        pos: [f64; 3],
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaderboardEntry {
+    pub uuid: Uuid,
+    pub name: String,
+    pub value: i32,
+}
+
+/// The top players by one statistic (e.g. `minecraft:custom` / `minecraft:jump`),
+/// refreshed from a `StatsStore` every so often and rendered in the sidebar or as a hologram.
+#[derive(Debug, Clone)]
+pub struct Leaderboard {
+    /// Also used as the name of the sidebar objective
+    name: String,
+    title: TextComponent,
+    stat_type: String,
+    stat: String,
+    size: usize,
+    display: LeaderboardDisplay,
+    refresh_period: u64,
+    next_refresh: u64,
+    entries: Vec<LeaderboardEntry>,
+    /// Names that dropped off the sidebar since the last `update()`
+    removed: Vec<String>,
+}
+
+impl Leaderboard {
+    /// Shows the top 10 in the sidebar, refreshed once a minute
+    pub fn new(
+        name: impl Into<String>,
+        title: TextComponent,
+        stat_type: impl Into<String>,
+        stat: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            title,
+            stat_type: stat_type.into(),
+            stat: stat.into(),
+            size: 10,
+            display: LeaderboardDisplay::Sidebar,
+            refresh_period: 60 * u64::from(TICKS_PER_SECOND),
+            next_refresh: 0,
+            entries: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    /// How many players to show (the sidebar fits 15)
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn display(mut self, display: LeaderboardDisplay) -> Self {
+        self.display = display;
+        self
+    }
+
+    /// In ticks
+    pub fn refresh_period(mut self, ticks: u64) -> Self {
+        self.refresh_period = ticks.max(1);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The current standings, highest first
+    pub fn entries(&self) -> &[LeaderboardEntry] {
+        &self.entries
+    }
+
+    /// Refreshes the standings if it's time to. `name_of` looks up player names;
+    /// players without one are shown by UUID.
+    ///
+    /// Returns true if the standings changed, i.e. `update()` should be sent to viewers.
+    pub fn tick(
+        &mut self,
+        tick: u64,
+        stats: &StatsStore,
+        name_of: impl Fn(Uuid) -> Option<String>,
+    ) -> bool {
+        if tick < self.next_refresh {
+            return false;
+        }
+        self.next_refresh = tick + self.refresh_period;
+        self.refresh(stats, name_of)
+    }
+
+    /// Refreshes the standings now. Returns true if they changed.
+    pub fn refresh(
+        &mut self,
+        stats: &StatsStore,
+        name_of: impl Fn(Uuid) -> Option<String>,
+    ) -> bool {
+        let entries: Vec<_> = stats
+            .top(&self.stat_type, &self.stat, self.size)
+            .into_iter()
+            .map(|(uuid, value)| LeaderboardEntry {
+                uuid,
+                name: name_of(uuid).unwrap_or_else(|| uuid.to_string()),
+                value,
+            })
+            .collect();
+        if entries == self.entries {
+            return false;
+        }
+        for old in &self.entries {
+            if !entries.iter().any(|e| e.name == old.name) && !self.removed.contains(&old.name) {
+                self.removed.push(old.name.clone());
+            }
+        }
+        self.removed
+            .retain(|name| !entries.iter().any(|e| &e.name == name));
+        self.entries = entries;
+        true
+    }
+
+    /// The hologram's text: the title, then one line per entry
+    pub fn hologram_text(&self) -> TextComponent {
+        let mut text = self.title.clone();
+        for (i, e) in self.entries.iter().enumerate() {
+            text = text
+                .append(TextComponent::text(format!("\n{}. ", i + 1)).color(ChatColor::Gold))
+                .append(TextComponent::text(format!("{} - {}", e.name, e.value)));
+        }
+        text
+    }
+
+    /// Sends the packets that show the leaderboard to a player who isn't viewing it yet
+    pub fn show(&self, mut send: impl FnMut(OutPacket<'_>)) {
+        match self.display {
+            LeaderboardDisplay::Sidebar => {
+                send(OutPacket::UpdateObjectives {
+                    name: &self.name,
+                    action: ObjectiveAction::Create {
+                        display_name: &self.title,
+                        render_type: ObjectiveRenderType::Integer,
+                    },
+                });
+                send(OutPacket::DisplayObjective {
+                    slot: 1,
+                    objective: &self.name,
+                });
+                self.send_scores(&mut send);
+            }
+            LeaderboardDisplay::Hologram {
+                entity_id,
+                uuid,
+                pos,
+            } => {
+                send(OutPacket::SpawnEntity {
+                    entity_id,
+                    uuid,
+                    entity_type: EntityType::TextDisplay,
+                    x: pos[0],
+                    y: pos[1],
+                    z: pos[2],
+                    pitch: Angle(0),
+                    yaw: Angle(0),
+                    head_yaw: Angle(0),
+                    data: 0,
+                    velocity: [0; 3],
+                });
+                self.send_hologram_text(entity_id, &mut send);
+            }
+        }
+    }
+
+    /// Sends the standings as of the last refresh to a player who is already viewing the leaderboard
+    pub fn update(&mut self, mut send: impl FnMut(OutPacket<'_>)) {
+        match self.display {
+            LeaderboardDisplay::Sidebar => {
+                for name in self.removed.drain(..) {
+                    send(OutPacket::UpdateScore {
+                        holder: &name,
+                        objective: &self.name,
+                        value: None,
+                    });
+                }
+                self.send_scores(&mut send);
+            }
+            LeaderboardDisplay::Hologram { entity_id, .. } => {
+                self.send_hologram_text(entity_id, &mut send);
+            }
+        }
+    }
+
+    pub fn hide(&self, mut send: impl FnMut(OutPacket<'_>)) {
+        match self.display {
+            LeaderboardDisplay::Sidebar => send(OutPacket::UpdateObjectives {
+                name: &self.name,
+                action: ObjectiveAction::Remove,
+            }),
+            LeaderboardDisplay::Hologram { entity_id, .. } => send(OutPacket::RemoveEntities {
+                entity_ids: &[entity_id],
+            }),
+        }
+    }
+
+    fn send_scores(&self, send: &mut impl FnMut(OutPacket<'_>)) {
+        for e in &self.entries {
+            send(OutPacket::UpdateScore {
+                holder: &e.name,
+                objective: &self.name,
+                value: Some(e.value),
+            });
+        }
+    }
+
+    fn send_hologram_text(&self, entity_id: i32, send: &mut impl FnMut(OutPacket<'_>)) {
+        let text = self.hologram_text();
+        let metadata = [
+            MetadataEntry {
+                index: DISPLAY_BILLBOARD,
+                // always face the viewer
+                value: MetadataValue::Byte(3),
+            },
+            MetadataEntry {
+                index: TEXT_DISPLAY_TEXT,
+                value: MetadataValue::Chat(&text),
+            },
+        ];
+        send(OutPacket::SetEntityMetadata {
+            entity_id,
+            metadata: &metadata,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_players() {
+        let dir = std::env::temp_dir().join(format!("libmc-leaderboard-{}", std::process::id()));
+        let mut stats = StatsStore::open(&dir).unwrap();
+        for (uuid, jumps) in [(1, 5), (2, 50), (3, 20), (4, 20)] {
+            stats
+                .get_mut(Uuid(uuid))
+                .set("minecraft:custom", "minecraft:jump", jumps);
+        }
+        stats
+            .get_mut(Uuid(5))
+            .set("minecraft:custom", "minecraft:walk_one_cm", 100);
+
+        let names = |uuid: Uuid| (uuid != Uuid(4)).then(|| format!("p{}", uuid.0));
+        let mut lb = Leaderboard::new(
+            "jumps",
+            "Jumps".into(),
+            "minecraft:custom",
+            "minecraft:jump",
+        )
+        .size(3)
+        .refresh_period(100);
+        assert!(lb.tick(0, &stats, names));
+        let top: Vec<_> = lb
+            .entries()
+            .iter()
+            .map(|e| (e.name.as_str(), e.value))
+            .collect();
+        assert_eq!(
+            top,
+            [
+                ("p2", 50),
+                ("p3", 20),
+                ("00000000-0000-0000-0000-000000000004", 20)
+            ]
+        );
+
+        stats
+            .get_mut(Uuid(1))
+            .set("minecraft:custom", "minecraft:jump", 30);
+        assert!(!lb.tick(50, &stats, names), "not time to refresh yet");
+        assert!(lb.tick(100, &stats, names));
+        assert_eq!(lb.entries()[1].name, "p1");
+
+        // the player who dropped out is removed from the sidebar
+        let mut removed = Vec::new();
+        lb.update(|p| {
+            if let OutPacket::UpdateScore {
+                holder,
+                value: None,
+                ..
+            } = p
+            {
+                removed.push(holder.to_owned());
+            }
+        });
+        assert_eq!(removed, ["00000000-0000-0000-0000-000000000004"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,204 @@
-mod server;
-mod proto;
+mod advancement;
+mod angle;
+mod anvil;
+mod autosave;
+mod backpressure;
+mod bandwidth;
+mod bedrock;
+mod bedrocktranslate;
+mod biome;
+mod book;
+mod bossbar;
+mod bot;
+mod bufpool;
+mod callback;
+mod capture;
+mod chat;
+mod chatfilter;
+mod chunk;
+mod chunkcache;
+mod chunkqueue;
+mod client;
+mod clock;
+mod coalesce;
+mod collision;
+mod command;
+mod compress;
+mod config;
+mod coords;
+mod death;
+mod debug;
+mod diagnostics;
+mod dimension;
+mod ecs;
+mod effect;
+mod elytra;
+mod encodepool;
+mod entity;
+mod entityid;
+mod entityregion;
+mod event;
+#[cfg(feature = "mio")]
+mod eventloop;
+mod experience;
+mod fishing;
+mod gamerules;
+mod genpool;
+mod health;
+mod idle;
+mod input;
+mod intercept;
+mod item;
+mod json;
+mod lang;
+mod leaderboard;
+mod map;
+mod merchant;
+mod metrics;
+mod mojang;
 mod nbt;
+#[cfg(feature = "serde")]
+mod nbtserde;
+mod ownedpacket;
+mod packed;
+mod ping;
+mod playerdata;
+mod plugin;
+mod poll;
+mod profile;
+mod progress;
+mod properties;
+mod proto;
+mod proxy;
+mod raknet;
+mod randomtick;
+mod ratelimit;
+mod raycast;
+mod recipe;
+mod regiontick;
+mod replay;
+mod router;
+mod scheduler;
+mod schematic;
+mod scoreboard;
+mod sendqueue;
+mod server;
+mod session;
+mod sighup;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+mod snbt;
+mod spawn;
+mod status;
+mod structure;
+mod tags;
+mod throttle;
+mod tick;
+mod tickets;
+mod tracker;
+mod translate;
+mod util;
+mod uuid;
+mod varint;
+mod websocket;
+mod worldborder;
+mod worldtime;
 
-pub use server::*;
-pub use proto::*;
+pub use advancement::*;
+pub use angle::*;
+pub use anvil::*;
+pub use autosave::*;
+pub use backpressure::*;
+pub use bandwidth::*;
+pub use bedrock::*;
+pub use bedrocktranslate::*;
+pub use biome::*;
+pub use book::*;
+pub use bossbar::*;
+pub use bot::*;
+pub use bufpool::*;
+pub use callback::*;
+pub use capture::*;
+pub use chat::*;
+pub use chatfilter::*;
+pub use chunk::*;
+pub use chunkcache::*;
+pub use chunkqueue::*;
+pub use client::*;
+pub use clock::*;
+pub use coalesce::*;
+pub use collision::*;
+pub use command::*;
+pub use config::*;
+pub use coords::*;
+pub use death::*;
+pub use debug::*;
+pub use diagnostics::*;
+pub use dimension::*;
+pub use ecs::*;
+pub use effect::*;
+pub use elytra::*;
+pub use encodepool::*;
+pub use entity::*;
+pub use entityid::*;
+pub use entityregion::*;
+pub use event::*;
+pub use experience::*;
+pub use fishing::*;
+pub use gamerules::*;
+pub use genpool::*;
+pub use health::*;
+pub use idle::*;
+pub use input::*;
+pub use intercept::*;
+pub use item::*;
+pub use json::*;
+pub use lang::*;
+pub use leaderboard::*;
+pub use map::*;
+pub use merchant::*;
+pub use metrics::*;
+pub use mojang::*;
 pub use nbt::*;
+#[cfg(feature = "serde")]
+pub use nbtserde::*;
+pub use ownedpacket::*;
+pub use packed::*;
+pub use ping::*;
+pub use playerdata::*;
+pub use plugin::*;
+pub use poll::*;
+pub use profile::*;
+pub use progress::*;
+pub use properties::*;
+pub use proto::*;
+pub use proxy::*;
+pub use raknet::*;
+pub use randomtick::*;
+pub use ratelimit::*;
+pub use raycast::*;
+pub use recipe::*;
+pub use regiontick::*;
+pub use replay::*;
+pub use router::*;
+pub use scheduler::*;
+pub use schematic::*;
+pub use scoreboard::*;
+pub use server::*;
+pub use session::*;
+#[cfg(feature = "snapshot")]
+pub use snapshot::*;
+pub use snbt::*;
+pub use spawn::*;
+pub use status::*;
+pub use structure::*;
+pub use tags::*;
+pub use throttle::*;
+pub use tick::*;
+pub use tickets::*;
+pub use tracker::*;
+pub use translate::*;
+pub use uuid::*;
+pub use varint::*;
+pub use worldborder::*;
+pub use worldtime::*;
@@ -0,0 +1,210 @@
+use crate::*;
+
+/// Maps are 128x128 pixels
+pub const MAP_SIZE: usize = 128;
+
+/// RGB of each base map color, indexed by base color ID (0 is transparent)
+const BASE_COLORS: [u32; 62] = [
+    0x000000, 0x7FB238, 0xF7E9A3, 0xC7C7C7, 0xFF0000, 0xA0A0FF, 0xA7A7A7, 0x007C00, 0xFFFFFF,
+    0xA4A8B8, 0x976D4D, 0x707070, 0x4040FF, 0x8F7748, 0xFFFCF5, 0xD87F33, 0xB24CD8, 0x6699D8,
+    0xE5E533, 0x7FCC19, 0xF27FA5, 0x4C4C4C, 0x999999, 0x4C7F99, 0x7F3FB2, 0x334CB2, 0x664C33,
+    0x667F33, 0x993333, 0x191919, 0xFAEE4D, 0x5CDBD5, 0x4A80FF, 0x00D93A, 0x815631, 0x700200,
+    0xD1B1A1, 0x9F5224, 0x95576C, 0x706C8A, 0xBA8524, 0x677535, 0xA04D4E, 0x392923, 0x876B62,
+    0x575C5C, 0x7A4958, 0x4C3E5C, 0x4C3223, 0x4C522A, 0x8E3C2E, 0x251610, 0xBD3031, 0x943F61,
+    0x5C191D, 0x167E86, 0x3A8E8C, 0x562C3E, 0x14B485, 0x646464, 0xD8AF93, 0x7FA796,
+];
+
+/// How much each of the 4 shades of a base color is darkened, out of 255
+const SHADES: [u32; 4] = [180, 220, 255, 135];
+
+/// The RGB a map color (`base * 4 + shade`) is shown as. None for transparent and unknown colors.
+pub fn map_color_rgb(color: u8) -> Option<[u8; 3]> {
+    let base = *BASE_COLORS.get(usize::from(color / 4))?;
+    if color < 4 {
+        return None;
+    }
+    let shade = SHADES[usize::from(color % 4)];
+    let channel = |shift: u32| (((base >> shift) & 0xFF) * shade / 255) as u8;
+    Some([channel(16), channel(8), channel(0)])
+}
+
+/// The map color that looks most like `rgb`
+pub fn nearest_map_color(rgb: [u8; 3]) -> u8 {
+    let distance = |color: u8| {
+        let c = map_color_rgb(color).unwrap();
+        (0..3)
+            .map(|i| (i32::from(c[i]) - i32::from(rgb[i])).pow(2))
+            .sum::<i32>()
+    };
+    (4..BASE_COLORS.len() as u8 * 4)
+        .min_by_key(|&color| distance(color))
+        .unwrap()
+}
+
+/// A marker on a map
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapIcon {
+    /// 0 = player (white arrow), 1 = item frame (green arrow), 2 = red marker, 3 = blue marker,
+    /// 4 = white X, 5 = red triangle, 6 = player off the map (white dot), ...
+    pub kind: i32,
+    /// -128 to 127, from the left edge to the right
+    pub x: i8,
+    /// -128 to 127, from the top edge to the bottom
+    pub z: i8,
+    /// 0 to 15, clockwise from south
+    pub direction: u8,
+    pub name: Option<TextComponent>,
+}
+
+/// A rectangle of map pixels, as sent in Map Data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapPatch {
+    pub x: u8,
+    pub z: u8,
+    pub columns: u8,
+    pub rows: u8,
+    /// Row by row
+    pub colors: Vec<u8>,
+}
+
+/// The pixels and icons of a map item, keeping track of what changed so that only that has to
+/// be sent. Colors are map color indices (see `nearest_map_color()`).
+#[derive(Debug, Clone)]
+pub struct MapCanvas {
+    colors: Vec<u8>,
+    /// (min x, min z, max x, max z) of what changed since the last `take_patch()`
+    dirty: Option<(usize, usize, usize, usize)>,
+    pub icons: Vec<MapIcon>,
+}
+
+impl Default for MapCanvas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapCanvas {
+    /// A transparent canvas
+    pub fn new() -> Self {
+        Self {
+            colors: vec![0; MAP_SIZE * MAP_SIZE],
+            dirty: None,
+            icons: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> u8 {
+        self.colors[z * MAP_SIZE + x]
+    }
+
+    pub fn set(&mut self, x: usize, z: usize, color: u8) {
+        assert!(x < MAP_SIZE && z < MAP_SIZE, "({x}, {z}) is off the map");
+        let pixel = &mut self.colors[z * MAP_SIZE + x];
+        if *pixel == color {
+            return;
+        }
+        *pixel = color;
+        self.dirty = Some(match self.dirty {
+            None => (x, z, x, z),
+            Some((x0, z0, x1, z1)) => (x0.min(x), z0.min(z), x1.max(x), z1.max(z)),
+        });
+    }
+
+    /// Fills a rectangle, clipped to the map
+    pub fn fill(&mut self, x: usize, z: usize, width: usize, height: usize, color: u8) {
+        for pz in z..(z + height).min(MAP_SIZE) {
+            for px in x..(x + width).min(MAP_SIZE) {
+                self.set(px, pz, color);
+            }
+        }
+    }
+
+    /// Draws an RGB image (row by row, `width` wide) with its top left corner at (x, z), clipped
+    /// to the map
+    pub fn draw_rgb(&mut self, x: usize, z: usize, width: usize, pixels: &[[u8; 3]]) {
+        for (i, &rgb) in pixels.iter().enumerate() {
+            let (px, pz) = (x + i % width, z + i / width);
+            if px < MAP_SIZE && pz < MAP_SIZE {
+                self.set(px, pz, nearest_map_color(rgb));
+            }
+        }
+    }
+
+    /// The whole map, e.g. for a player that just got the map
+    pub fn full_patch(&self) -> MapPatch {
+        self.patch(0, 0, MAP_SIZE - 1, MAP_SIZE - 1)
+    }
+
+    /// What changed since the last call (as one rectangle around all of it), if anything
+    pub fn take_patch(&mut self) -> Option<MapPatch> {
+        let (x0, z0, x1, z1) = self.dirty.take()?;
+        Some(self.patch(x0, z0, x1, z1))
+    }
+
+    fn patch(&self, x0: usize, z0: usize, x1: usize, z1: usize) -> MapPatch {
+        let colors = (z0..=z1)
+            .flat_map(|z| &self.colors[z * MAP_SIZE + x0..=z * MAP_SIZE + x1])
+            .copied()
+            .collect();
+        MapPatch {
+            x: x0 as u8,
+            z: z0 as u8,
+            columns: (x1 - x0 + 1) as u8,
+            rows: (z1 - z0 + 1) as u8,
+            colors,
+        }
+    }
+
+    /// Map Data for map `map_id`, with `patch` (from `take_patch()` or `full_patch()`) and the icons
+    pub fn packet<'a>(&'a self, map_id: i32, patch: Option<&'a MapPatch>) -> OutPacket<'a> {
+        OutPacket::MapData {
+            map_id,
+            scale: 0,
+            locked: true,
+            icons: Some(&self.icons),
+            patch,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches() {
+        assert_eq!(map_color_rgb(4 * 8 + 2), Some([0xFF; 3]));
+        assert_eq!(map_color_rgb(2), None);
+        assert_eq!(nearest_map_color([0xFF, 0, 0]), 4 * 4 + 2);
+
+        let mut canvas = MapCanvas::new();
+        assert!(canvas.take_patch().is_none());
+        canvas.fill(10, 20, 3, 2, 34);
+        canvas.set(11, 23, 35);
+        let patch = canvas.take_patch().unwrap();
+        assert_eq!(
+            (patch.x, patch.z, patch.columns, patch.rows),
+            (10, 20, 3, 4)
+        );
+        assert_eq!(patch.colors[..3], [34; 3]);
+        assert_eq!(patch.colors[9..], [0, 35, 0]);
+        assert!(canvas.take_patch().is_none());
+
+        let full = canvas.full_patch();
+        assert_eq!((full.columns, full.colors.len()), (128, 128 * 128));
+
+        canvas.icons.push(MapIcon {
+            kind: 2,
+            x: -5,
+            z: 5,
+            direction: 8,
+            name: None,
+        });
+        let encoded = encode_packet(canvas.packet(3, Some(&patch)), PROTOCOL_VERSION);
+        assert_eq!(
+            encoded[..15],
+            [0x2A, 3, 0, 1, 1, 1, 2, 0xFB, 5, 8, 0, 3, 4, 10, 20]
+        );
+        assert_eq!(encoded[15], 12);
+    }
+}
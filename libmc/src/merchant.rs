@@ -0,0 +1,90 @@
+use crate::*;
+
+/// The largest stack a price can be
+const MAX_PRICE: i32 = 64;
+
+/// One trade in a villager's (or any merchant's) trading screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerchantOffer<'a> {
+    /// The first item the player pays with, before price adjustments
+    pub input: Slot<'a>,
+    /// The second item the player pays with, if any
+    pub second_input: Option<Slot<'a>>,
+    pub output: Slot<'a>,
+    /// Greys out the trade, with a red cross over the arrow
+    pub disabled: bool,
+    pub uses: i32,
+    pub max_uses: i32,
+    /// Experience the merchant gets per trade
+    pub xp: i32,
+    /// Added to the first input's count, e.g. negative after curing a zombie villager
+    pub special_price: i32,
+    /// How much `demand` raises the price
+    pub price_multiplier: f32,
+    /// Goes up when the trade is used a lot, raising the price at the next restock
+    pub demand: i32,
+}
+
+impl<'a> MerchantOffer<'a> {
+    pub fn new(input: Slot<'a>, output: Slot<'a>, max_uses: i32) -> Self {
+        Self {
+            input,
+            second_input: None,
+            output,
+            disabled: false,
+            uses: 0,
+            max_uses,
+            xp: 1,
+            special_price: 0,
+            price_multiplier: 0.05,
+            demand: 0,
+        }
+    }
+
+    pub fn is_out_of_stock(&self) -> bool {
+        self.uses >= self.max_uses
+    }
+
+    /// How many of the first input the trade costs, which is what the client shows and charges
+    pub fn price(&self) -> i32 {
+        let base = i32::from(self.input.count);
+        let demand_bonus = ((base * self.demand) as f32 * self.price_multiplier).floor() as i32;
+        (base + demand_bonus.max(0) + self.special_price).clamp(1, MAX_PRICE)
+    }
+}
+
+pub(crate) fn write_merchant_offer(buf: &mut Vec<u8>, offer: &MerchantOffer) {
+    write_slot(buf, Some(&offer.input));
+    write_slot(buf, Some(&offer.output));
+    write_slot(buf, offer.second_input.as_ref());
+    write_bool(buf, offer.disabled);
+    write_int(buf, offer.uses);
+    write_int(buf, offer.max_uses);
+    write_int(buf, offer.xp);
+    write_int(buf, offer.special_price);
+    write_float(buf, offer.price_multiplier);
+    write_int(buf, offer.demand);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices() {
+        let mut offer = MerchantOffer::new(Slot::new(1, 20), Slot::new(2, 1), 12);
+        assert_eq!(offer.price(), 20);
+        offer.demand = 3;
+        assert_eq!(offer.price(), 23);
+        offer.special_price = -30;
+        assert_eq!(offer.price(), 1);
+
+        offer.uses = 12;
+        assert!(offer.is_out_of_stock());
+        let mut buf = Vec::new();
+        write_merchant_offer(&mut buf, &offer);
+        // input, output, no second input, not disabled, then uses
+        assert_eq!(buf[..8], [1, 1, 20, 0, 1, 2, 1, 0]);
+        assert_eq!(buf[8..14], [0, 0, 0, 0, 0, 12]);
+    }
+}
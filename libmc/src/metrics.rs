@@ -0,0 +1,145 @@
+use crate::*;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A snapshot of how the server is doing, from `ServerHandle::metrics()`.
+/// Rates are over the last second, and only count clients that are still connected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerMetrics {
+    /// All connections, including ones that are pinging or still logging in
+    pub connections: usize,
+    /// Connections in the play state
+    pub players: usize,
+    pub packets_in_per_second: u64,
+    pub bytes_in_per_second: u64,
+    pub packets_out_per_second: u64,
+    pub bytes_out_per_second: u64,
+    /// What was sent over the last second, by packet category and summed over clients
+    pub out_by_category: BandwidthStats,
+    pub tps: f64,
+    pub mspt: f64,
+    /// Time spent in the last `Server::tick()`
+    pub last_tick: Duration,
+    /// Set by the server with `ServerHandle::set_gauge()`, e.g. how many chunks are loaded
+    pub gauges: Vec<(&'static str, f64)>,
+}
+
+impl ServerMetrics {
+    /// In the Prometheus text format, with every name prefixed by `libmc_`
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "# HELP libmc_bytes_out_per_second_by_category Bytes sent over the last second, by packet category"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE libmc_bytes_out_per_second_by_category gauge").unwrap();
+        for category in PacketCategory::ALL {
+            writeln!(
+                out,
+                "libmc_bytes_out_per_second_by_category{{category=\"{}\"}} {}",
+                category.name(),
+                self.out_by_category.bytes_of(category)
+            )
+            .unwrap();
+        }
+        let mut gauge = |name: &str, help: &str, value: f64| {
+            if !help.is_empty() {
+                writeln!(out, "# HELP libmc_{name} {help}").unwrap();
+            }
+            writeln!(out, "# TYPE libmc_{name} gauge").unwrap();
+            writeln!(out, "libmc_{name} {value}").unwrap();
+        };
+        gauge(
+            "connections",
+            "Open connections, including pings and logins",
+            self.connections as f64,
+        );
+        gauge("players", "Clients in the play state", self.players as f64);
+        gauge(
+            "packets_in_per_second",
+            "Packets received over the last second",
+            self.packets_in_per_second as f64,
+        );
+        gauge(
+            "bytes_in_per_second",
+            "Bytes received over the last second",
+            self.bytes_in_per_second as f64,
+        );
+        gauge(
+            "packets_out_per_second",
+            "Packets sent over the last second",
+            self.packets_out_per_second as f64,
+        );
+        gauge(
+            "bytes_out_per_second",
+            "Bytes sent over the last second",
+            self.bytes_out_per_second as f64,
+        );
+        gauge("tps", "Ticks per second", self.tps);
+        gauge("mspt", "Milliseconds per tick spent not idling", self.mspt);
+        gauge(
+            "last_tick_seconds",
+            "Time spent in the last tick",
+            self.last_tick.as_secs_f64(),
+        );
+        for &(name, value) in &self.gauges {
+            gauge(name, "", value);
+        }
+        out
+    }
+}
+
+/// Answers every HTTP request on `listener` with `text`, for Prometheus to scrape
+pub(crate) fn serve_metrics(listener: TcpListener, text: Arc<Mutex<String>>) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+        // whatever was asked for, there's only one thing to get
+        let _ = stream.read(&mut [0; 1024]);
+        let body = text.lock().unwrap().clone();
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prometheus() {
+        let metrics = ServerMetrics {
+            connections: 3,
+            players: 2,
+            out_by_category: BandwidthStats {
+                chunks: 800,
+                chat: 20,
+                ..Default::default()
+            },
+            tps: 19.5,
+            last_tick: Duration::from_millis(4),
+            gauges: vec![("chunks_loaded", 441.0)],
+            ..Default::default()
+        };
+        let text = metrics.to_prometheus();
+        assert!(text.contains("# TYPE libmc_connections gauge\nlibmc_connections 3\n"));
+        assert!(
+            text.contains("\nlibmc_bytes_out_per_second_by_category{category=\"chunks\"} 800\n")
+        );
+        assert!(
+            text.contains("\nlibmc_bytes_out_per_second_by_category{category=\"entities\"} 0\n")
+        );
+        assert!(text.contains("\nlibmc_tps 19.5\n"));
+        assert!(text.contains("\nlibmc_last_tick_seconds 0.004\n"));
+        assert!(text.ends_with("# TYPE libmc_chunks_loaded gauge\nlibmc_chunks_loaded 441\n"));
+    }
+}
@@ -0,0 +1,282 @@
+use crate::json::Json;
+use crate::{GameProfile, ProfileProperty, Uuid};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The HTTP transport used by `MojangApi`. libmc doesn't depend on an HTTP/TLS
+/// library, so implement this with whichever client your server already uses.
+pub trait HttpClient {
+    /// Performs a GET request, returning the status code and body
+    fn get(&mut self, url: &str) -> Result<(u16, String), Box<dyn std::error::Error>>;
+}
+
+#[derive(Debug)]
+pub enum MojangError {
+    /// No profile exists for that name/UUID
+    NotFound,
+    /// Mojang answered 429, and we're waiting out the backoff
+    RateLimited,
+    Http(Box<dyn std::error::Error>),
+    UnexpectedStatus(u16),
+    BadResponse(&'static str),
+}
+
+impl fmt::Display for MojangError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MojangError::NotFound => write!(f, "profile not found"),
+            MojangError::RateLimited => write!(f, "rate limited by the Mojang API"),
+            MojangError::Http(e) => write!(f, "HTTP error: {e}"),
+            MojangError::UnexpectedStatus(s) => write!(f, "unexpected HTTP status {s}"),
+            MojangError::BadResponse(why) => write!(f, "bad response from the Mojang API: {why}"),
+        }
+    }
+}
+
+impl std::error::Error for MojangError {}
+
+const USERNAME_URL: &str = "https://api.mojang.com/users/profiles/minecraft/";
+const SESSION_PROFILE_URL: &str = "https://sessionserver.mojang.com/session/minecraft/profile/";
+
+/// Client for the Mojang profile API, for looking up skins of players when the
+/// server isn't doing online-mode authentication itself (offline mode, behind a proxy, NPCs).
+/// The returned `GameProfile`s can be used directly in `OutPacket::LoginSuccess`.
+///
+/// Responses are cached for `ttl`, and after a 429 response no requests are made until
+/// `backoff` has passed (stale cache entries are served in the meantime).
+pub struct MojangApi<H: HttpClient> {
+    http: H,
+    ttl: Duration,
+    backoff: Duration,
+    rate_limited_until: Option<Instant>,
+    /// lowercase username -> (uuid, when it was fetched)
+    uuids: HashMap<String, (Option<Uuid>, Instant)>,
+    profiles: HashMap<Uuid, (Option<GameProfile>, Instant)>,
+}
+
+impl<H: HttpClient> MojangApi<H> {
+    pub fn new(http: H) -> Self {
+        Self::with_durations(http, Duration::from_secs(10 * 60), Duration::from_secs(60))
+    }
+
+    pub fn with_durations(http: H, ttl: Duration, backoff: Duration) -> Self {
+        Self {
+            http,
+            ttl,
+            backoff,
+            rate_limited_until: None,
+            uuids: HashMap::new(),
+            profiles: HashMap::new(),
+        }
+    }
+
+    /// Looks up the UUID of the account called `username`. Names that no account can have (in
+    /// offline mode, clients can pick anything) aren't looked up.
+    pub fn uuid_of(&mut self, username: &str) -> Result<Uuid, MojangError> {
+        if !is_valid_username(username) {
+            return Err(MojangError::NotFound);
+        }
+        let key = username.to_lowercase();
+        if let Some((uuid, fetched)) = self.uuids.get(&key) {
+            if fetched.elapsed() < self.ttl || self.is_rate_limited() {
+                return uuid.ok_or(MojangError::NotFound);
+            }
+        }
+
+        let body = match self.fetch(&format!("{USERNAME_URL}{username}")) {
+            Err(MojangError::NotFound) => {
+                self.uuids.insert(key, (None, Instant::now()));
+                return Err(MojangError::NotFound);
+            }
+            x => x?,
+        };
+        let json = Json::parse(&body).map_err(|_| MojangError::BadResponse("invalid JSON"))?;
+        let uuid = json
+            .get("id")
+            .and_then(Json::as_str)
+            .and_then(parse_undashed_uuid)
+            .ok_or(MojangError::BadResponse("missing id"))?;
+
+        self.uuids.insert(key, (Some(uuid), Instant::now()));
+        Ok(uuid)
+    }
+
+    /// Fetches the profile (including the signed textures) of the account with `uuid`
+    pub fn profile(&mut self, uuid: Uuid) -> Result<GameProfile, MojangError> {
+        if let Some((profile, fetched)) = self.profiles.get(&uuid) {
+            if fetched.elapsed() < self.ttl || self.is_rate_limited() {
+                return profile.clone().ok_or(MojangError::NotFound);
+            }
+        }
+
+        let body = match self.fetch(&format!(
+            "{SESSION_PROFILE_URL}{}?unsigned=false",
+            uuid.simple()
+        )) {
+            Err(MojangError::NotFound) => {
+                self.profiles.insert(uuid, (None, Instant::now()));
+                return Err(MojangError::NotFound);
+            }
+            x => x?,
+        };
+        let profile = parse_profile(&body)?;
+
+        self.profiles
+            .insert(uuid, (Some(profile.clone()), Instant::now()));
+        Ok(profile)
+    }
+
+    /// `uuid_of()` followed by `profile()`
+    pub fn profile_by_name(&mut self, username: &str) -> Result<GameProfile, MojangError> {
+        let uuid = self.uuid_of(username)?;
+        self.profile(uuid)
+    }
+
+    fn is_rate_limited(&self) -> bool {
+        self.rate_limited_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn fetch(&mut self, url: &str) -> Result<String, MojangError> {
+        if self.is_rate_limited() {
+            return Err(MojangError::RateLimited);
+        }
+
+        let (status, body) = self.http.get(url).map_err(MojangError::Http)?;
+        match status {
+            200 => Ok(body),
+            204 | 404 => Err(MojangError::NotFound),
+            429 => {
+                self.rate_limited_until = Some(Instant::now() + self.backoff);
+                Err(MojangError::RateLimited)
+            }
+            s => Err(MojangError::UnexpectedStatus(s)),
+        }
+    }
+}
+
+/// 1 to 16 of `[A-Za-z0-9_]`, so it's safe to put in a URL
+fn is_valid_username(name: &str) -> bool {
+    (1..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_undashed_uuid(s: &str) -> Option<Uuid> {
+    if s.len() != 32 {
+        return None;
+    }
+    Uuid::parse(s)
+}
+
+fn parse_profile(body: &str) -> Result<GameProfile, MojangError> {
+    let json = Json::parse(body).map_err(|_| MojangError::BadResponse("invalid JSON"))?;
+    let uuid = json
+        .get("id")
+        .and_then(Json::as_str)
+        .and_then(parse_undashed_uuid)
+        .ok_or(MojangError::BadResponse("missing id"))?;
+    let name = json
+        .get("name")
+        .and_then(Json::as_str)
+        .ok_or(MojangError::BadResponse("missing name"))?
+        .to_owned();
+
+    let mut properties = Vec::new();
+    for p in json
+        .get("properties")
+        .and_then(Json::as_array)
+        .unwrap_or_default()
+    {
+        let (Some(name), Some(value)) = (
+            p.get("name").and_then(Json::as_str),
+            p.get("value").and_then(Json::as_str),
+        ) else {
+            return Err(MojangError::BadResponse("malformed property"));
+        };
+        properties.push(ProfileProperty {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            signature: p.get("signature").and_then(Json::as_str).map(str::to_owned),
+        });
+    }
+
+    Ok(GameProfile {
+        uuid,
+        name,
+        properties,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers from a fixed url -> response table, and counts requests
+    struct FakeHttp {
+        responses: Vec<(&'static str, u16, &'static str)>,
+        requests: usize,
+    }
+
+    impl HttpClient for &mut FakeHttp {
+        fn get(&mut self, url: &str) -> Result<(u16, String), Box<dyn std::error::Error>> {
+            self.requests += 1;
+            let (_, status, body) = self
+                .responses
+                .iter()
+                .find(|(prefix, _, _)| url.starts_with(prefix))
+                .expect("unexpected url");
+            Ok((*status, body.to_string()))
+        }
+    }
+
+    #[test]
+    fn lookup_and_cache() {
+        let mut http = FakeHttp {
+            responses: vec![
+                (
+                    "https://api.mojang.com/users/profiles/minecraft/Notch",
+                    200,
+                    r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch"}"#,
+                ),
+                ("https://api.mojang.com/users/profiles/minecraft/", 404, ""),
+                (
+                    "https://sessionserver.mojang.com/session/minecraft/profile/069a79f444e94726a5befca90e38aaf5?unsigned=false",
+                    200,
+                    r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch","properties":[{"name":"textures","value":"abc=","signature":"sig="}]}"#,
+                ),
+            ],
+            requests: 0,
+        };
+        let mut api = MojangApi::new(&mut http);
+
+        let profile = api.profile_by_name("Notch").unwrap();
+        assert_eq!(profile.uuid, Uuid(0x069a79f444e94726a5befca90e38aaf5));
+        let textures = profile.textures().unwrap();
+        assert_eq!(textures.value, "abc=");
+        assert_eq!(textures.signature.as_deref(), Some("sig="));
+
+        // cached
+        api.profile_by_name("notch").unwrap();
+        assert!(matches!(api.uuid_of("nobody"), Err(MojangError::NotFound)));
+        assert!(matches!(api.uuid_of("nobody"), Err(MojangError::NotFound)));
+        // never requested
+        for name in ["Notch/../x", "a?b", "a#b", "", "seventeen_letters"] {
+            assert!(matches!(api.uuid_of(name), Err(MojangError::NotFound)));
+        }
+        drop(api);
+        assert_eq!(http.requests, 3);
+    }
+
+    #[test]
+    fn rate_limit_backoff() {
+        let mut http = FakeHttp {
+            responses: vec![("https://", 429, "")],
+            requests: 0,
+        };
+        let mut api = MojangApi::new(&mut http);
+        assert!(matches!(api.uuid_of("a"), Err(MojangError::RateLimited)));
+        assert!(matches!(api.uuid_of("b"), Err(MojangError::RateLimited)));
+        drop(api);
+        assert_eq!(http.requests, 1, "no requests during the backoff");
+    }
+}
@@ -1,8 +1,13 @@
+use crate::bufpool::PooledBuf;
+use crate::compress::{gzip_compress, gzip_decompress};
 use crate::proto::*;
+use crate::util::write_atomically;
 use std::borrow::Borrow;
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{Read, Write};
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
 
 #[derive(Debug, Clone)]
 pub struct CompoundNbt<'a> {
@@ -33,9 +38,32 @@ impl<'a> CompoundNbt<'a> {
     pub fn props(&self) -> impl Iterator<Item = (&str, &Nbt<'a>)> {
         self.props.iter().map(|(a, b)| (a.borrow(), b.borrow()))
     }
+
+    /// Merges `other` into this compound the way vanilla's `/data merge` does: compounds present
+    /// in both are merged recursively, and every other value from `other` replaces the existing one
+    /// (lists included).
+    pub fn merge(&mut self, other: &CompoundNbt<'a>) {
+        for (key, value) in &other.props {
+            if let (Some(existing), Nbt::Compound(theirs)) = (self.props.get_mut(key), &**value) {
+                if let Nbt::Compound(ours) = existing.to_mut() {
+                    ours.merge(theirs);
+                    continue;
+                }
+            }
+            self.props.insert(key.clone(), value.clone());
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Compares the props, but not the name: only the root compound's name is ever written out,
+/// and it rarely matters.
+impl PartialEq for CompoundNbt<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.props == other.props
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum NbtList<'a> {
     Compound(Cow<'a, [CompoundNbt<'a>]>),
     Byte(Cow<'a, [i8]>),
@@ -45,9 +73,13 @@ pub enum NbtList<'a> {
     Float(Cow<'a, [f32]>),
     Double(Cow<'a, [f64]>),
     String(Cow<'a, [Cow<'a, str>]>),
+    List(Cow<'a, [NbtList<'a>]>),
+    ByteArray(Cow<'a, [Cow<'a, [i8]>]>),
+    IntArray(Cow<'a, [Cow<'a, [i32]>]>),
+    LongArray(Cow<'a, [Cow<'a, [i64]>]>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Nbt<'a> {
     Compound(CompoundNbt<'a>),
     Byte(i8),
@@ -65,29 +97,33 @@ pub enum Nbt<'a> {
 
 impl Nbt<'static> {
     /// Reads a full nbt. This is to be called to parse the entire nbt from the root, which is always a compound.
-    /// As such, this is the only publicly visible nbt read method.
+    /// Panics on malformed nbt; use `try_read_compound()` for data that can be, like files.
     pub fn read_compound<R: Read>(r: &mut R) -> CompoundNbt<'static> {
-        let ttype = read_tagtype(r);
-        // 10 = TAG_Compound
-        if ttype != TagType::Compound {
-            panic!("Expected tag type Compound, got tag type '{ttype:?}'");
-        }
-
-        let compound_name = read_ushort_string(r);
-
-        let mut compound = CompoundNbt::new(compound_name);
+        Self::try_read_compound(r).unwrap_or_else(|e| panic!("malformed nbt: {e}"))
+    }
 
-        loop {
-            let tagid = read_tagtype(r);
-            if tagid == TagType::End {
-                return compound;
-            }
+    /// Reads a full nbt like `read_compound()`, returning an error instead of panicking if it's
+    /// malformed
+    pub fn try_read_compound<R: Read>(r: &mut R) -> Result<CompoundNbt<'static>, DecodeError> {
+        let mut r = NbtReader { r, depth: 0 };
+        r.root_tag()?;
+        let compound_name = r.string()?;
+        r.compound_payload(compound_name)
+    }
 
-            let elem_name = read_ushort_string(r);
-            let elem = read_nbt(r, tagid);
+    /// Reads a full nbt in the network form used since 1.20.2, where the root compound has no name
+    pub fn read_network_compound<R: Read>(r: &mut R) -> CompoundNbt<'static> {
+        Self::try_read_network_compound(r).unwrap_or_else(|e| panic!("malformed nbt: {e}"))
+    }
 
-            compound.set(elem_name, elem);
-        }
+    /// Reads network nbt like `read_network_compound()`, returning an error instead of panicking
+    /// if it's malformed
+    pub fn try_read_network_compound<R: Read>(
+        r: &mut R,
+    ) -> Result<CompoundNbt<'static>, DecodeError> {
+        let mut r = NbtReader { r, depth: 0 };
+        r.root_tag()?;
+        r.compound_payload(String::new())
     }
 }
 
@@ -121,149 +157,238 @@ enum TagType {
     LongArray = 12,
 }
 
-fn read_nbt<R: Read>(r: &mut R, tag: TagType) -> Nbt<'static> {
-    match tag {
-        TagType::Byte => Nbt::Byte(read_byte(r)),
-        TagType::Short => Nbt::Short(read_short(r)),
-        TagType::Int => Nbt::Int(read_int(r)),
-        TagType::Long => Nbt::Long(read_long(r)),
-        TagType::Float => Nbt::Float(read_float(r)),
-        TagType::Double => Nbt::Double(read_double(r)),
-        // TODO: refactor the copy-paste between ByteArray, IntArray, LongArray
-        TagType::ByteArray => {
-            let len = read_int(r);
-            assert!(len >= 0, "len < 0 :(");
-            let len: usize = len.try_into().unwrap();
-            let mut arr = Vec::with_capacity(len);
-            for _ in 0..len {
-                arr.push(read_byte(r));
-            }
-            Nbt::ByteArray(Cow::Owned(arr))
+/// How deeply compounds and lists can be nested, as in vanilla
+const MAX_DEPTH: usize = 512;
+
+/// Lengths come from the data, so only this many elements are allocated up front
+const MAX_PREALLOCATED: usize = 1 << 16;
+
+/// Reads nbt without panicking, since files can be corrupt
+struct NbtReader<'r, R> {
+    r: &'r mut R,
+    /// How many compounds and lists are being read
+    depth: usize,
+}
+
+impl<R: Read> NbtReader<'_, R> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut b = [0; N];
+        self.r
+            .read_exact(&mut b)
+            .map_err(|_| DecodeError::UnexpectedEnd)?;
+        Ok(b)
+    }
+
+    fn byte(&mut self) -> Result<i8, DecodeError> {
+        Ok(i8::from_be_bytes(self.array()?))
+    }
+
+    fn short(&mut self) -> Result<i16, DecodeError> {
+        Ok(i16::from_be_bytes(self.array()?))
+    }
+
+    fn int(&mut self) -> Result<i32, DecodeError> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn long(&mut self) -> Result<i64, DecodeError> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    fn float(&mut self) -> Result<f32, DecodeError> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+
+    fn double(&mut self) -> Result<f64, DecodeError> {
+        Ok(f64::from_be_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = u16::from_be_bytes(self.array()?);
+        let mut vs = vec![0; len.into()];
+        self.r
+            .read_exact(&mut vs)
+            .map_err(|_| DecodeError::UnexpectedEnd)?;
+        // TODO: convert from Java's "Modified UTF-8" :(
+        String::from_utf8(vs).map_err(|_| DecodeError::InvalidString)
+    }
+
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        let len = self.int()?;
+        len.try_into().map_err(|_| DecodeError::BadValue {
+            field: "nbt length",
+            value: len,
+        })
+    }
+
+    /// `len` elements read with `f`
+    fn vec<T>(
+        &mut self,
+        len: usize,
+        mut f: impl FnMut(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Vec<T>, DecodeError> {
+        let mut arr = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+        for _ in 0..len {
+            arr.push(f(self)?);
         }
-        TagType::String => Nbt::String(read_ushort_string(r).into()),
-        TagType::List => {
-            let list_type = read_tagtype(r);
-            let len = read_int(r);
-
-            // TODO: this is awful. fix all the copy-paste
-            Nbt::List(match list_type {
-                TagType::String => {
-                    let mut arr = Vec::with_capacity(len.try_into().unwrap());
-                    if len > 0 {
-                        for _ in 0..len {
-                            arr.push(Cow::Owned(read_ushort_string(r)));
-                        }
-                    }
-                    NbtList::String(Cow::Owned(arr))
-                }
-                TagType::Compound => {
-                    let mut arr = Vec::with_capacity(len.try_into().unwrap());
-                    if len > 0 {
-                        for _ in 0..len {
-                            arr.push(Nbt::read_compound(r));
-                        }
-                    }
-                    NbtList::Compound(Cow::Owned(arr))
-                }
-                TagType::Int => {
-                    let mut arr = Vec::with_capacity(len.try_into().unwrap());
-                    if len > 0 {
-                        for _ in 0..len {
-                            arr.push(read_int(r));
-                        }
-                    }
-                    NbtList::Int(Cow::Owned(arr))
-                }
-                TagType::Long => {
-                    let mut arr = Vec::with_capacity(len.try_into().unwrap());
-                    if len > 0 {
-                        for _ in 0..len {
-                            arr.push(read_long(r));
-                        }
-                    }
-                    NbtList::Long(Cow::Owned(arr))
-                }
-                TagType::Short => {
-                    let mut arr = Vec::with_capacity(len.try_into().unwrap());
-                    if len > 0 {
-                        for _ in 0..len {
-                            arr.push(read_short(r));
-                        }
-                    }
-                    NbtList::Short(Cow::Owned(arr))
-                }
-                TagType::Byte => {
-                    let mut arr = Vec::with_capacity(len.try_into().unwrap());
-                    if len > 0 {
-                        for _ in 0..len {
-                            arr.push(read_byte(r));
-                        }
-                    }
-                    NbtList::Byte(Cow::Owned(arr))
-                }
-                TagType::Double => {
-                    let mut arr = Vec::with_capacity(len.try_into().unwrap());
-                    if len > 0 {
-                        for _ in 0..len {
-                            arr.push(read_double(r));
-                        }
-                    }
-                    NbtList::Double(Cow::Owned(arr))
-                }
-                TagType::Float => {
-                    let mut arr = Vec::with_capacity(len.try_into().unwrap());
-                    if len > 0 {
-                        for _ in 0..len {
-                            arr.push(read_float(r));
-                        }
-                    }
-                    NbtList::Float(Cow::Owned(arr))
-                }
-                x => todo!("implement nbt parsing for lists of {x:?}"),
-            })
+        Ok(arr)
+    }
+
+    fn tag_type(&mut self) -> Result<TagType, DecodeError> {
+        use TagType::*;
+        Ok(match self.byte()? {
+            0 => End,
+            1 => Byte,
+            2 => Short,
+            3 => Int,
+            4 => Long,
+            5 => Float,
+            6 => Double,
+            7 => ByteArray,
+            8 => String,
+            9 => List,
+            10 => Compound,
+            11 => IntArray,
+            12 => LongArray,
+            x => {
+                return Err(DecodeError::BadValue {
+                    field: "nbt tag type",
+                    value: x.into(),
+                })
+            }
+        })
+    }
+
+    /// The tag type of the root, which is always a compound
+    fn root_tag(&mut self) -> Result<(), DecodeError> {
+        match self.tag_type()? {
+            TagType::Compound => Ok(()),
+            ttype => Err(DecodeError::BadValue {
+                field: "root tag type",
+                value: ttype as i32,
+            }),
         }
-        TagType::Compound => Nbt::Compound(Nbt::read_compound(r)),
-        TagType::IntArray => {
-            let len = read_int(r);
-            assert!(len >= 0, "len < 0 :(");
-            let len: usize = len.try_into().unwrap();
-            let mut arr = Vec::with_capacity(len);
-            for _ in 0..len {
-                arr.push(read_int(r));
-            }
-            Nbt::IntArray(Cow::Owned(arr))
+    }
+
+    /// Goes one compound or list deeper
+    fn descend(&mut self) -> Result<(), DecodeError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(DecodeError::BadValue {
+                field: "nbt depth",
+                value: self.depth.try_into().unwrap_or(i32::MAX),
+            });
         }
-        TagType::LongArray => {
-            let len = read_int(r);
-            assert!(len >= 0, "len < 0 :(");
-            let len: usize = len.try_into().unwrap();
-            let mut arr = Vec::with_capacity(len);
-            for _ in 0..len {
-                arr.push(read_long(r));
-            }
-            Nbt::LongArray(Cow::Owned(arr))
+        Ok(())
+    }
+
+    /// Reads the props of a compound whose tag type and name have already been read
+    fn compound_payload(&mut self, name: String) -> Result<CompoundNbt<'static>, DecodeError> {
+        self.descend()?;
+        let mut compound = CompoundNbt::new(name);
+        loop {
+            let tagid = self.tag_type()?;
+            if tagid == TagType::End {
+                self.depth -= 1;
+                return Ok(compound);
+            }
+
+            let elem_name = self.string()?;
+            let elem = self.nbt(tagid)?;
+            compound.set(elem_name, elem);
         }
-        TagType::End => panic!("can't read_nbt() with TagType::End"),
     }
-}
 
-fn read_tagtype<R: Read>(r: &mut R) -> TagType {
-    use TagType::*;
-    match read_byte(r) {
-        0 => End,
-        1 => Byte,
-        2 => Short,
-        3 => Int,
-        4 => Long,
-        5 => Float,
-        6 => Double,
-        7 => ByteArray,
-        8 => String,
-        9 => List,
-        10 => Compound,
-        11 => IntArray,
-        12 => LongArray,
-        x => panic!("bad tag type {x}"),
+    fn nbt(&mut self, tag: TagType) -> Result<Nbt<'static>, DecodeError> {
+        Ok(match tag {
+            TagType::Byte => Nbt::Byte(self.byte()?),
+            TagType::Short => Nbt::Short(self.short()?),
+            TagType::Int => Nbt::Int(self.int()?),
+            TagType::Long => Nbt::Long(self.long()?),
+            TagType::Float => Nbt::Float(self.float()?),
+            TagType::Double => Nbt::Double(self.double()?),
+            TagType::ByteArray => {
+                let len = self.len()?;
+                Nbt::ByteArray(self.vec(len, Self::byte)?.into())
+            }
+            TagType::String => Nbt::String(self.string()?.into()),
+            TagType::List => Nbt::List(self.list()?),
+            // named by the prop it's in
+            TagType::Compound => Nbt::Compound(self.compound_payload(String::new())?),
+            TagType::IntArray => {
+                let len = self.len()?;
+                Nbt::IntArray(self.vec(len, Self::int)?.into())
+            }
+            TagType::LongArray => {
+                let len = self.len()?;
+                Nbt::LongArray(self.vec(len, Self::long)?.into())
+            }
+            TagType::End => {
+                return Err(DecodeError::BadValue {
+                    field: "nbt tag type",
+                    value: 0,
+                })
+            }
+        })
+    }
+
+    fn list(&mut self) -> Result<NbtList<'static>, DecodeError> {
+        let list_type = self.tag_type()?;
+        let len = self.int()?;
+        // vanilla writes empty lists with no element type
+        if list_type == TagType::End && len <= 0 {
+            return Ok(NbtList::Compound(Cow::Owned(Vec::new())));
+        }
+        let len = usize::try_from(len).map_err(|_| DecodeError::BadValue {
+            field: "nbt length",
+            value: len,
+        })?;
+        self.descend()?;
+        let list = match list_type {
+            TagType::Byte => NbtList::Byte(self.vec(len, Self::byte)?.into()),
+            TagType::Short => NbtList::Short(self.vec(len, Self::short)?.into()),
+            TagType::Int => NbtList::Int(self.vec(len, Self::int)?.into()),
+            TagType::Long => NbtList::Long(self.vec(len, Self::long)?.into()),
+            TagType::Float => NbtList::Float(self.vec(len, Self::float)?.into()),
+            TagType::Double => NbtList::Double(self.vec(len, Self::double)?.into()),
+            TagType::String => {
+                NbtList::String(self.vec(len, |r| r.string().map(Cow::Owned))?.into())
+            }
+            // list elements are nameless
+            TagType::Compound => {
+                NbtList::Compound(self.vec(len, |r| r.compound_payload(String::new()))?.into())
+            }
+            TagType::List => NbtList::List(self.vec(len, Self::list)?.into()),
+            TagType::ByteArray => NbtList::ByteArray(
+                self.vec(len, |r| {
+                    let len = r.len()?;
+                    r.vec(len, Self::byte).map(Cow::Owned)
+                })?
+                .into(),
+            ),
+            TagType::IntArray => NbtList::IntArray(
+                self.vec(len, |r| {
+                    let len = r.len()?;
+                    r.vec(len, Self::int).map(Cow::Owned)
+                })?
+                .into(),
+            ),
+            TagType::LongArray => NbtList::LongArray(
+                self.vec(len, |r| {
+                    let len = r.len()?;
+                    r.vec(len, Self::long).map(Cow::Owned)
+                })?
+                .into(),
+            ),
+            TagType::End => {
+                return Err(DecodeError::BadValue {
+                    field: "nbt list length",
+                    value: len.try_into().unwrap_or(i32::MAX),
+                })
+            }
+        };
+        self.depth -= 1;
+        Ok(list)
     }
 }
 
@@ -273,9 +398,19 @@ fn write_tagtype<W: Write>(w: &mut W, tt: TagType) {
 
 fn write_compound_nbt_no_tagtype<W: Write>(w: &mut W, nbt: &CompoundNbt<'_>) {
     write_ushort_string(w, &nbt.name);
+    write_compound_payload(w, nbt);
+}
+
+/// The props of a compound, without its name
+fn write_compound_payload<W: Write>(w: &mut W, nbt: &CompoundNbt<'_>) {
     for (prop_name, prop_value) in nbt.props() {
         match prop_value {
-            Nbt::Compound(c) => write_compound_nbt(w, c),
+            Nbt::Compound(c) => {
+                // a nested compound is named by the prop it's in
+                write_tagtype(w, TagType::Compound);
+                write_ushort_string(w, prop_name);
+                write_compound_payload(w, c);
+            }
             Nbt::String(s) => {
                 write_tagtype(w, TagType::String);
                 write_ushort_string(w, prop_name);
@@ -314,72 +449,13 @@ fn write_compound_nbt_no_tagtype<W: Write>(w: &mut W, nbt: &CompoundNbt<'_>) {
             Nbt::List(l) => {
                 write_tagtype(w, TagType::List);
                 write_ushort_string(w, prop_name);
-                match l {
-                    NbtList::Compound(c) => {
-                        write_tagtype(w, TagType::Compound);
-                        write_int(w, c.len().try_into().unwrap());
-                        for x in c.iter() {
-                            write_compound_nbt_no_tagtype(w, x);
-                        }
-                    }
-                    NbtList::Byte(lst) => {
-                        write_tagtype(w, TagType::Byte);
-                        write_int(w, lst.len().try_into().unwrap());
-                        for x in lst.iter() {
-                            write_ibyte(w, *x);
-                        }
-                    }
-                    NbtList::Short(lst) => {
-                        write_tagtype(w, TagType::Short);
-                        write_int(w, lst.len().try_into().unwrap());
-                        for x in lst.iter() {
-                            write_short(w, *x);
-                        }
-                    }
-                    NbtList::Int(lst) => {
-                        write_tagtype(w, TagType::Int);
-                        write_int(w, lst.len().try_into().unwrap());
-                        for x in lst.iter() {
-                            write_int(w, *x);
-                        }
-                    }
-                    NbtList::Long(lst) => {
-                        write_tagtype(w, TagType::Long);
-                        write_int(w, lst.len().try_into().unwrap());
-                        for x in lst.iter() {
-                            write_long(w, *x);
-                        }
-                    }
-                    NbtList::Float(lst) => {
-                        write_tagtype(w, TagType::Float);
-                        write_int(w, lst.len().try_into().unwrap());
-                        for x in lst.iter() {
-                            write_float(w, *x);
-                        }
-                    }
-                    NbtList::Double(lst) => {
-                        write_tagtype(w, TagType::Double);
-                        write_int(w, lst.len().try_into().unwrap());
-                        for x in lst.iter() {
-                            write_double(w, *x);
-                        }
-                    }
-                    NbtList::String(lst) => {
-                        write_tagtype(w, TagType::String);
-                        write_int(w, lst.len().try_into().unwrap());
-                        for x in lst.iter() {
-                            write_ushort_string(w, x);
-                        }
-                    }
-                }
+                write_list_payload(w, l);
             }
             Nbt::ByteArray(arr) => {
                 write_tagtype(w, TagType::ByteArray);
                 write_ushort_string(w, prop_name);
                 write_int(w, arr.len().try_into().unwrap());
-                for b in arr.iter().copied() {
-                    write_ibyte(w, b);
-                }
+                write_ibytes(w, arr);
             }
             Nbt::IntArray(arr) => {
                 write_tagtype(w, TagType::IntArray);
@@ -402,11 +478,155 @@ fn write_compound_nbt_no_tagtype<W: Write>(w: &mut W, nbt: &CompoundNbt<'_>) {
     write_tagtype(w, TagType::End);
 }
 
+/// A list's element type, length and elements
+fn write_list_payload<W: Write>(w: &mut W, list: &NbtList<'_>) {
+    match list {
+        NbtList::Compound(c) => {
+            write_tagtype(w, TagType::Compound);
+            write_int(w, c.len().try_into().unwrap());
+            for x in c.iter() {
+                write_compound_payload(w, x);
+            }
+        }
+        NbtList::Byte(lst) => {
+            write_tagtype(w, TagType::Byte);
+            write_int(w, lst.len().try_into().unwrap());
+            write_ibytes(w, lst);
+        }
+        NbtList::Short(lst) => {
+            write_tagtype(w, TagType::Short);
+            write_int(w, lst.len().try_into().unwrap());
+            for x in lst.iter() {
+                write_short(w, *x);
+            }
+        }
+        NbtList::Int(lst) => {
+            write_tagtype(w, TagType::Int);
+            write_int(w, lst.len().try_into().unwrap());
+            for x in lst.iter() {
+                write_int(w, *x);
+            }
+        }
+        NbtList::Long(lst) => {
+            write_tagtype(w, TagType::Long);
+            write_int(w, lst.len().try_into().unwrap());
+            for x in lst.iter() {
+                write_long(w, *x);
+            }
+        }
+        NbtList::Float(lst) => {
+            write_tagtype(w, TagType::Float);
+            write_int(w, lst.len().try_into().unwrap());
+            for x in lst.iter() {
+                write_float(w, *x);
+            }
+        }
+        NbtList::Double(lst) => {
+            write_tagtype(w, TagType::Double);
+            write_int(w, lst.len().try_into().unwrap());
+            for x in lst.iter() {
+                write_double(w, *x);
+            }
+        }
+        NbtList::String(lst) => {
+            write_tagtype(w, TagType::String);
+            write_int(w, lst.len().try_into().unwrap());
+            for x in lst.iter() {
+                write_ushort_string(w, x);
+            }
+        }
+        NbtList::List(lst) => {
+            write_tagtype(w, TagType::List);
+            write_int(w, lst.len().try_into().unwrap());
+            for x in lst.iter() {
+                write_list_payload(w, x);
+            }
+        }
+        NbtList::ByteArray(lst) => {
+            write_tagtype(w, TagType::ByteArray);
+            write_int(w, lst.len().try_into().unwrap());
+            for arr in lst.iter() {
+                write_int(w, arr.len().try_into().unwrap());
+                write_ibytes(w, arr);
+            }
+        }
+        NbtList::IntArray(lst) => {
+            write_tagtype(w, TagType::IntArray);
+            write_int(w, lst.len().try_into().unwrap());
+            for arr in lst.iter() {
+                write_int(w, arr.len().try_into().unwrap());
+                for x in arr.iter().copied() {
+                    write_int(w, x);
+                }
+            }
+        }
+        NbtList::LongArray(lst) => {
+            write_tagtype(w, TagType::LongArray);
+            write_int(w, lst.len().try_into().unwrap());
+            for arr in lst.iter() {
+                write_int(w, arr.len().try_into().unwrap());
+                for x in arr.iter().copied() {
+                    write_long(w, x);
+                }
+            }
+        }
+    }
+}
+
 pub(crate) fn write_compound_nbt<W: Write>(w: &mut W, nbt: &CompoundNbt<'_>) {
     write_tagtype(w, TagType::Compound);
     write_compound_nbt_no_tagtype(w, nbt);
 }
 
+/// For files whose NBT doesn't have the layout they should
+pub(crate) fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// The list of compounds at `key`, which is empty if it's missing
+pub(crate) fn compound_list<'b, 'a>(
+    c: &'b CompoundNbt<'a>,
+    key: &str,
+) -> io::Result<&'b [CompoundNbt<'a>]> {
+    match c.get(key) {
+        Some(Nbt::List(NbtList::Compound(list))) => Ok(list),
+        None => Ok(&[]),
+        Some(_) => Err(invalid("expected a list of compounds")),
+    }
+}
+
+/// Reads a gzipped nbt file, like most of the ones in a world. Returns None if it doesn't exist.
+pub(crate) fn read_nbt_file(path: &Path) -> io::Result<Option<CompoundNbt<'static>>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let data = gzip_decompress(&bytes).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    Nbt::try_read_compound(&mut &data[..])
+        .map(Some)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+pub(crate) fn write_nbt_file(path: &Path, nbt: &CompoundNbt<'_>) -> io::Result<()> {
+    let mut data = PooledBuf::take();
+    write_compound_nbt(&mut *data, nbt);
+    write_atomically(path, &gzip_compress(&data))
+}
+
+/// The first protocol version (1.20.2) that leaves out the root compound's name in network nbt
+const NAMELESS_NETWORK_NBT_SINCE: i32 = 764;
+
+/// Writes nbt the way it's sent over the network by clients speaking `protocol_version`
+pub(crate) fn write_network_nbt<W: Write>(w: &mut W, nbt: &CompoundNbt<'_>, protocol_version: i32) {
+    if protocol_version >= NAMELESS_NETWORK_NBT_SINCE {
+        write_tagtype(w, TagType::Compound);
+        write_compound_payload(w, nbt);
+    } else {
+        write_compound_nbt(w, nbt);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -448,4 +668,128 @@ mod tests {
         write_compound_nbt(&mut deserialized, &compound);
         assert_eq!(buf.as_slice(), &deserialized);
     }
+
+    #[test]
+    fn malformed_is_an_error() {
+        let mut root = CompoundNbt::new("root");
+        root.set("list", Nbt::List(NbtList::Int(vec![1, 2, 3].into())));
+        let mut buf = Vec::new();
+        write_compound_nbt(&mut buf, &root);
+        assert_eq!(Nbt::try_read_compound(&mut buf.as_slice()), Ok(root));
+
+        let truncated = &buf[..buf.len() - 3];
+        assert_eq!(
+            Nbt::try_read_compound(&mut &truncated[..]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert!(Nbt::try_read_compound(&mut &[0x08, 0x00, 0x00][..]).is_err());
+        // an unknown tag type, and a huge length with nothing behind it
+        assert!(Nbt::try_read_compound(&mut &[0x0a, 0x00, 0x00, 0x0d][..]).is_err());
+        let huge = [0x0a, 0, 0, 0x0b, 0, 1, b'a', 0x7f, 0xff, 0xff, 0xff];
+        assert_eq!(
+            Nbt::try_read_compound(&mut &huge[..]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+
+        // compounds nested deeper than vanilla allows
+        let mut deep = vec![0x0a, 0, 0];
+        for _ in 0..600 {
+            deep.extend([0x0a, 0, 0]);
+        }
+        assert!(matches!(
+            Nbt::try_read_compound(&mut deep.as_slice()),
+            Err(DecodeError::BadValue {
+                field: "nbt depth",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn lists_of_lists_and_arrays() {
+        // as vanilla writes PostProcessing: a list of short lists, the empty ones typeless
+        let buf = [
+            0x0a, 0, 0, 0x09, 0, 1, b'p', 0x09, 0, 0, 0, 3, 0x00, 0, 0, 0, 0, 0x02, 0, 0, 0, 1, 0,
+            7, 0x00, 0, 0, 0, 0, 0x00,
+        ];
+        let root = Nbt::try_read_compound(&mut &buf[..]).unwrap();
+        let Some(Nbt::List(NbtList::List(lists))) = root.get("p") else {
+            panic!("{root:?}");
+        };
+        assert_eq!(lists.len(), 3);
+        assert_eq!(lists[1], NbtList::Short(vec![7].into()));
+
+        let mut root = CompoundNbt::new("root");
+        root.set("lists", Nbt::List(NbtList::List(lists.clone())));
+        let bytes = vec![vec![1, -1].into(), vec![].into()];
+        root.set("bytes", Nbt::List(NbtList::ByteArray(bytes.into())));
+        let ints = vec![vec![1, 2, 3].into()];
+        root.set("ints", Nbt::List(NbtList::IntArray(ints.into())));
+        let longs = vec![vec![i64::MIN].into(), vec![4, 5].into()];
+        root.set("longs", Nbt::List(NbtList::LongArray(longs.into())));
+        let mut buf = Vec::new();
+        write_compound_nbt(&mut buf, &root);
+        assert_eq!(Nbt::try_read_compound(&mut buf.as_slice()), Ok(root));
+    }
+
+    #[test]
+    fn nested_compounds() {
+        let mut inner = CompoundNbt::new("");
+        inner.set("x", Nbt::Int(1));
+        let mut root = CompoundNbt::new("root");
+        root.set("inner", Nbt::Compound(inner.clone()));
+        root.set(
+            "list",
+            Nbt::List(NbtList::Compound(vec![inner.clone(), inner].into())),
+        );
+
+        let mut buf = Vec::new();
+        write_compound_nbt(&mut buf, &root);
+        let read = Nbt::read_compound(&mut buf.as_slice());
+        let Some(Nbt::Compound(inner)) = read.get("inner") else {
+            panic!("expected compound");
+        };
+        assert!(matches!(inner.get("x"), Some(Nbt::Int(1))));
+        let Some(Nbt::List(NbtList::Compound(list))) = read.get("list") else {
+            panic!("expected list of compounds");
+        };
+        assert!(matches!(list[1].get("x"), Some(Nbt::Int(1))));
+    }
+
+    #[test]
+    fn merge() {
+        let mut target =
+            CompoundNbt::parse_snbt("{a: 1, list: [1, 2], nested: {x: 1b, y: 2b}}", "").unwrap();
+        let patch = CompoundNbt::parse_snbt(
+            "{a: \"one\", list: [3], nested: {y: 3b, z: 4b}, new: 5L}",
+            "",
+        )
+        .unwrap();
+        target.merge(&patch);
+        let expected = CompoundNbt::parse_snbt(
+            "{a: \"one\", list: [3], nested: {x: 1b, y: 3b, z: 4b}, new: 5L}",
+            "other name",
+        )
+        .unwrap();
+        assert_eq!(target, expected);
+        assert_ne!(Nbt::Int(1), Nbt::Long(1));
+    }
+
+    #[test]
+    fn network_nbt() {
+        let mut root = CompoundNbt::new("ignored");
+        root.set("x", Nbt::Byte(1));
+
+        let mut buf = Vec::new();
+        write_network_nbt(&mut buf, &root, 764);
+        assert_eq!(buf, [0x0a, 0x01, 0x00, 0x01, 0x78, 0x01, 0x00]);
+        let read = Nbt::read_network_compound(&mut buf.as_slice());
+        assert_eq!(read.name(), "");
+        assert!(matches!(read.get("x"), Some(Nbt::Byte(1))));
+
+        // older clients get the name
+        buf.clear();
+        write_network_nbt(&mut buf, &root, 763);
+        assert_eq!(Nbt::read_compound(&mut buf.as_slice()).name(), "ignored");
+    }
 }
@@ -0,0 +1,877 @@
+//! A serde data format for NBT, for mapping Rust structs to and from `CompoundNbt`s.
+//!
+//! Structs and maps become compounds, sequences become lists, and `None` fields are left out.
+//! Unsigned integers are stored in the signed NBT type of the same width, bit for bit.
+//! Enum unit variants are stored as strings, and other variants as a compound with the
+//! variant name as the only key. Use `NbtByteArray`, `NbtIntArray` and `NbtLongArray` for
+//! the NBT array types (a `Vec<i32>` becomes a list of ints).
+
+use crate::*;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+
+const BYTE_ARRAY_TOKEN: &str = "__libmc_nbt_byte_array";
+const INT_ARRAY_TOKEN: &str = "__libmc_nbt_int_array";
+const LONG_ARRAY_TOKEN: &str = "__libmc_nbt_long_array";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NbtSerdeError(String);
+
+impl fmt::Display for NbtSerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NbtSerdeError {}
+
+impl ser::Error for NbtSerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl de::Error for NbtSerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+fn err<T>(msg: impl Into<String>) -> Result<T, NbtSerdeError> {
+    Err(NbtSerdeError(msg.into()))
+}
+
+macro_rules! nbt_array {
+    ($name:ident, $elem:ty, $token:ident) => {
+        /// Serializes as an NBT array tag instead of a list
+        #[derive(Debug, Default, Clone, PartialEq, Eq)]
+        pub struct $name(pub Vec<$elem>);
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_newtype_struct($token, &self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                Vec::deserialize(d).map(Self)
+            }
+        }
+    };
+}
+
+nbt_array!(NbtByteArray, i8, BYTE_ARRAY_TOKEN);
+nbt_array!(NbtIntArray, i32, INT_ARRAY_TOKEN);
+nbt_array!(NbtLongArray, i64, LONG_ARRAY_TOKEN);
+
+/// Serializes `value`, which has to serialize as a struct or map, into a compound named `name`
+pub fn to_nbt<T: Serialize + ?Sized>(
+    value: &T,
+    name: &str,
+) -> Result<CompoundNbt<'static>, NbtSerdeError> {
+    match value.serialize(NbtSerializer)? {
+        Some(Nbt::Compound(c)) => {
+            let mut named = CompoundNbt::new(name.to_string());
+            for (k, v) in c.props() {
+                named.set(k.to_string(), v.clone());
+            }
+            Ok(named)
+        }
+        _ => err("the root of an NBT has to be a compound"),
+    }
+}
+
+pub fn from_nbt<'de, 'a, T: Deserialize<'de>>(
+    compound: &'de CompoundNbt<'a>,
+) -> Result<T, NbtSerdeError> {
+    T::deserialize(Input::Compound(compound))
+}
+
+// ----- serialization -----
+
+/// Serializes a value into an `Nbt`, or `None` for `Option::None`
+struct NbtSerializer;
+
+fn to_list(items: Vec<Nbt<'static>>) -> Result<NbtList<'static>, NbtSerdeError> {
+    macro_rules! collect {
+        ($variant:ident, $conv:expr) => {
+            items
+                .into_iter()
+                .map(|x| match x {
+                    Nbt::$variant(x) => Ok($conv(x)),
+                    _ => err("all elements of an NBT list must have the same type"),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(|v| NbtList::$variant(Cow::Owned(v)))
+        };
+    }
+
+    // empty lists have no element type to go by
+    match items.first() {
+        None | Some(Nbt::Compound(_)) => collect!(Compound, |x| x),
+        Some(Nbt::Byte(_)) => collect!(Byte, |x| x),
+        Some(Nbt::Short(_)) => collect!(Short, |x| x),
+        Some(Nbt::Int(_)) => collect!(Int, |x| x),
+        Some(Nbt::Long(_)) => collect!(Long, |x| x),
+        Some(Nbt::Float(_)) => collect!(Float, |x| x),
+        Some(Nbt::Double(_)) => collect!(Double, |x| x),
+        Some(Nbt::String(_)) => collect!(String, |x| x),
+        Some(Nbt::List(_)) => collect!(List, |x| x),
+        Some(Nbt::ByteArray(_)) => collect!(ByteArray, |x| x),
+        Some(Nbt::IntArray(_)) => collect!(IntArray, |x| x),
+        Some(Nbt::LongArray(_)) => collect!(LongArray, |x| x),
+    }
+}
+
+fn single_entry(key: &'static str, value: Nbt<'static>) -> Nbt<'static> {
+    let mut c = CompoundNbt::new("");
+    c.set(key, value);
+    Nbt::Compound(c)
+}
+
+impl Serializer for NbtSerializer {
+    type Ok = Option<Nbt<'static>>;
+    type Error = NbtSerdeError;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeList;
+    type SerializeMap = SerializeCompound;
+    type SerializeStruct = SerializeCompound;
+    type SerializeStructVariant = SerializeCompound;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Byte(v as i8)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Byte(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Short(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Int(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Long(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Byte(v as i8)))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Short(v as i16)))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Int(v as i32)))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Long(v as i64)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Float(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::Double(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::String(v.to_string().into())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::String(v.to_string().into())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Nbt::ByteArray(
+            v.iter().map(|b| *b as i8).collect::<Vec<_>>().into(),
+        )))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        err("NBT can't represent ()")
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        err(format!("NBT can't represent unit struct {name}"))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let inner = value.serialize(self)?;
+        let list = match inner {
+            Some(Nbt::List(list)) => list,
+            _ => return Ok(inner),
+        };
+        Ok(Some(match (name, list) {
+            (BYTE_ARRAY_TOKEN, NbtList::Byte(v)) => Nbt::ByteArray(v),
+            (INT_ARRAY_TOKEN, NbtList::Int(v)) => Nbt::IntArray(v),
+            (LONG_ARRAY_TOKEN, NbtList::Long(v)) => Nbt::LongArray(v),
+            (BYTE_ARRAY_TOKEN, NbtList::Compound(v)) if v.is_empty() => {
+                Nbt::ByteArray(Vec::new().into())
+            }
+            (INT_ARRAY_TOKEN, NbtList::Compound(v)) if v.is_empty() => {
+                Nbt::IntArray(Vec::new().into())
+            }
+            (LONG_ARRAY_TOKEN, NbtList::Compound(v)) if v.is_empty() => {
+                Nbt::LongArray(Vec::new().into())
+            }
+            (_, list) => Nbt::List(list),
+        }))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        match value.serialize(self)? {
+            Some(v) => Ok(Some(single_entry(variant, v))),
+            None => err("NBT can't represent None in an enum variant"),
+        }
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SerializeList {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(SerializeList {
+            items: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(SerializeCompound {
+            compound: CompoundNbt::new(""),
+            next_key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(SerializeCompound {
+            compound: CompoundNbt::new(""),
+            next_key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+struct SerializeList {
+    items: Vec<Nbt<'static>>,
+    /// For tuple variants
+    variant: Option<&'static str>,
+}
+
+impl SerializeList {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), NbtSerdeError> {
+        match value.serialize(NbtSerializer)? {
+            Some(v) => {
+                self.items.push(v);
+                Ok(())
+            }
+            None => err("NBT can't represent None in a list"),
+        }
+    }
+
+    fn finish(self) -> Result<Option<Nbt<'static>>, NbtSerdeError> {
+        let list = Nbt::List(to_list(self.items)?);
+        Ok(Some(match self.variant {
+            Some(variant) => single_entry(variant, list),
+            None => list,
+        }))
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Option<Nbt<'static>>;
+    type Error = NbtSerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Option<Nbt<'static>>;
+    type Error = NbtSerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Option<Nbt<'static>>;
+    type Error = NbtSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+    type Ok = Option<Nbt<'static>>;
+    type Error = NbtSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+struct SerializeCompound {
+    compound: CompoundNbt<'static>,
+    /// For maps, whose keys and values are serialized separately
+    next_key: Option<String>,
+    /// For struct variants
+    variant: Option<&'static str>,
+}
+
+impl SerializeCompound {
+    fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        key: String,
+        value: &T,
+    ) -> Result<(), NbtSerdeError> {
+        // `None` fields are left out
+        if let Some(v) = value.serialize(NbtSerializer)? {
+            self.compound.set(key, v);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Nbt<'static>>, NbtSerdeError> {
+        let compound = Nbt::Compound(self.compound);
+        Ok(Some(match self.variant {
+            Some(variant) => single_entry(variant, compound),
+            None => compound,
+        }))
+    }
+}
+
+impl ser::SerializeMap for SerializeCompound {
+    type Ok = Option<Nbt<'static>>;
+    type Error = NbtSerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        match key.serialize(NbtSerializer)? {
+            Some(Nbt::String(s)) => {
+                self.next_key = Some(s.into_owned());
+                Ok(())
+            }
+            _ => err("NBT compound keys must be strings"),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value() called before serialize_key()");
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeCompound {
+    type Ok = Option<Nbt<'static>>;
+    type Error = NbtSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeCompound {
+    type Ok = Option<Nbt<'static>>;
+    type Error = NbtSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+// ----- deserialization -----
+
+/// A value being deserialized. List elements aren't stored as `Nbt`s, so they get their own variants.
+#[derive(Copy, Clone)]
+enum Input<'de, 'a: 'de> {
+    Compound(&'de CompoundNbt<'a>),
+    Seq(Seq<'de, 'a>),
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Str(&'de str),
+}
+
+/// A list or array, which can also be an element of a list
+#[derive(Copy, Clone)]
+enum Seq<'de, 'a: 'de> {
+    List(&'de NbtList<'a>),
+    Bytes(&'de [i8]),
+    Ints(&'de [i32]),
+    Longs(&'de [i64]),
+}
+
+impl<'de, 'a: 'de> Input<'de, 'a> {
+    fn of(nbt: &'de Nbt<'a>) -> Self {
+        match nbt {
+            Nbt::Compound(c) => Input::Compound(c),
+            Nbt::Byte(x) => Input::Byte(*x),
+            Nbt::Short(x) => Input::Short(*x),
+            Nbt::Int(x) => Input::Int(*x),
+            Nbt::Long(x) => Input::Long(*x),
+            Nbt::Float(x) => Input::Float(*x),
+            Nbt::Double(x) => Input::Double(*x),
+            Nbt::String(s) => Input::Str(s),
+            Nbt::ByteArray(v) => Input::Seq(Seq::Bytes(v)),
+            Nbt::IntArray(v) => Input::Seq(Seq::Ints(v)),
+            Nbt::LongArray(v) => Input::Seq(Seq::Longs(v)),
+            Nbt::List(l) => Input::Seq(Seq::List(l)),
+        }
+    }
+
+    fn seq_item(seq: Seq<'de, 'a>, i: usize) -> Option<Self> {
+        match seq {
+            Seq::Bytes(v) => v.get(i).map(|x| Input::Byte(*x)),
+            Seq::Ints(v) => v.get(i).map(|x| Input::Int(*x)),
+            Seq::Longs(v) => v.get(i).map(|x| Input::Long(*x)),
+            Seq::List(l) => match l {
+                NbtList::Compound(v) => v.get(i).map(Input::Compound),
+                NbtList::Byte(v) => v.get(i).map(|x| Input::Byte(*x)),
+                NbtList::Short(v) => v.get(i).map(|x| Input::Short(*x)),
+                NbtList::Int(v) => v.get(i).map(|x| Input::Int(*x)),
+                NbtList::Long(v) => v.get(i).map(|x| Input::Long(*x)),
+                NbtList::Float(v) => v.get(i).map(|x| Input::Float(*x)),
+                NbtList::Double(v) => v.get(i).map(|x| Input::Double(*x)),
+                NbtList::String(v) => v.get(i).map(|s| Input::Str(s)),
+                NbtList::List(v) => v.get(i).map(|l| Input::Seq(Seq::List(l))),
+                NbtList::ByteArray(v) => v.get(i).map(|a| Input::Seq(Seq::Bytes(a))),
+                NbtList::IntArray(v) => v.get(i).map(|a| Input::Seq(Seq::Ints(a))),
+                NbtList::LongArray(v) => v.get(i).map(|a| Input::Seq(Seq::Longs(a))),
+            },
+        }
+    }
+
+    fn seq_len(seq: Seq<'_, '_>) -> usize {
+        match seq {
+            Seq::Bytes(v) => v.len(),
+            Seq::Ints(v) => v.len(),
+            Seq::Longs(v) => v.len(),
+            Seq::List(l) => match l {
+                NbtList::Compound(v) => v.len(),
+                NbtList::Byte(v) => v.len(),
+                NbtList::Short(v) => v.len(),
+                NbtList::Int(v) => v.len(),
+                NbtList::Long(v) => v.len(),
+                NbtList::Float(v) => v.len(),
+                NbtList::Double(v) => v.len(),
+                NbtList::String(v) => v.len(),
+                NbtList::List(v) => v.len(),
+                NbtList::ByteArray(v) => v.len(),
+                NbtList::IntArray(v) => v.len(),
+                NbtList::LongArray(v) => v.len(),
+            },
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Input::Compound(_) => "compound",
+            Input::Seq(_) => "list",
+            Input::Byte(_) => "byte",
+            Input::Short(_) => "short",
+            Input::Int(_) => "int",
+            Input::Long(_) => "long",
+            Input::Float(_) => "float",
+            Input::Double(_) => "double",
+            Input::Str(_) => "string",
+        }
+    }
+}
+
+/// Unsigned integers are stored bit for bit in the signed type of the same width
+macro_rules! deserialize_unsigned {
+    ($method:ident, $visit:ident, $variant:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            match self {
+                Input::$variant(x) => visitor.$visit(x as $ty),
+                _ => self.deserialize_any(visitor),
+            }
+        }
+    };
+}
+
+impl<'de, 'a: 'de> Deserializer<'de> for Input<'de, 'a> {
+    type Error = NbtSerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Input::Compound(c) => visitor.visit_map(CompoundAccess {
+                props: c.props().collect(),
+                i: 0,
+            }),
+            Input::Seq(seq) => visitor.visit_seq(ListAccess { seq, i: 0 }),
+            Input::Byte(x) => visitor.visit_i8(x),
+            Input::Short(x) => visitor.visit_i16(x),
+            Input::Int(x) => visitor.visit_i32(x),
+            Input::Long(x) => visitor.visit_i64(x),
+            Input::Float(x) => visitor.visit_f32(x),
+            Input::Double(x) => visitor.visit_f64(x),
+            Input::Str(s) => visitor.visit_borrowed_str(s),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Input::Byte(x) => visitor.visit_bool(x != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_unsigned!(deserialize_u8, visit_u8, Byte, u8);
+    deserialize_unsigned!(deserialize_u16, visit_u16, Short, u16);
+    deserialize_unsigned!(deserialize_u32, visit_u32, Int, u32);
+    deserialize_unsigned!(deserialize_u64, visit_u64, Long, u64);
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Input::Seq(Seq::Bytes(v)) => {
+                visitor.visit_byte_buf(v.iter().map(|b| *b as u8).collect())
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        // missing fields are `None`, and serde handles those itself
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            Input::Str(s) => visitor.visit_enum(s.into_deserializer()),
+            Input::Compound(c) => {
+                let mut props = c.props();
+                match (props.next(), props.next()) {
+                    (Some((variant, value)), None) => visitor.visit_enum(EnumAccess {
+                        variant,
+                        value: Input::of(value),
+                    }),
+                    _ => err("expected a compound with exactly one entry for an enum"),
+                }
+            }
+            _ => err(format!("expected an enum, found a {}", self.type_name())),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct CompoundAccess<'de, 'a> {
+    props: Vec<(&'de str, &'de Nbt<'a>)>,
+    i: usize,
+}
+
+impl<'de, 'a: 'de> MapAccess<'de> for CompoundAccess<'de, 'a> {
+    type Error = NbtSerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.props.get(self.i) {
+            Some((key, _)) => seed.deserialize(Input::Str(key)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (_, value) = self.props[self.i];
+        self.i += 1;
+        seed.deserialize(Input::of(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.props.len() - self.i)
+    }
+}
+
+struct ListAccess<'de, 'a> {
+    seq: Seq<'de, 'a>,
+    i: usize,
+}
+
+impl<'de, 'a: 'de> SeqAccess<'de> for ListAccess<'de, 'a> {
+    type Error = NbtSerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        let Some(item) = Input::seq_item(self.seq, self.i) else {
+            return Ok(None);
+        };
+        self.i += 1;
+        seed.deserialize(item).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(Input::seq_len(self.seq) - self.i)
+    }
+}
+
+struct EnumAccess<'de, 'a> {
+    variant: &'de str,
+    value: Input<'de, 'a>,
+}
+
+impl<'de, 'a: 'de> de::EnumAccess<'de> for EnumAccess<'de, 'a> {
+    type Error = NbtSerdeError;
+    type Variant = Input<'de, 'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(Input::Str(self.variant))?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de, 'a: 'de> de::VariantAccess<'de> for Input<'de, 'a> {
+    type Error = NbtSerdeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        err("expected a unit variant to be stored as a string")
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum GameType {
+        Survival,
+        Custom { speed: f32 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct LevelData {
+        level_name: String,
+        spawn_x: i32,
+        hardcore: bool,
+        random_seed: u64,
+        game_type: GameType,
+        other_game_type: GameType,
+        data_packs: Vec<String>,
+        heightmap: NbtLongArray,
+        post_processing: Vec<Vec<i16>>,
+        bossbar: Option<String>,
+        gamerules: HashMap<String, String>,
+    }
+
+    #[test]
+    fn roundtrip_struct() {
+        let level = LevelData {
+            level_name: "world".to_string(),
+            spawn_x: -12,
+            hardcore: true,
+            random_seed: u64::MAX,
+            game_type: GameType::Survival,
+            other_game_type: GameType::Custom { speed: 2.0 },
+            data_packs: vec!["vanilla".to_string()],
+            heightmap: NbtLongArray(vec![1, 2, 3]),
+            post_processing: vec![vec![], vec![5, 17]],
+            bossbar: None,
+            gamerules: HashMap::from([("doDaylightCycle".to_string(), "true".to_string())]),
+        };
+
+        let nbt = to_nbt(&level, "Data").unwrap();
+        assert_eq!(nbt.name(), "Data");
+        assert!(matches!(nbt.get("Hardcore"), Some(Nbt::Byte(1))));
+        assert!(matches!(nbt.get("RandomSeed"), Some(Nbt::Long(-1))));
+        assert!(matches!(nbt.get("Heightmap"), Some(Nbt::LongArray(_))));
+        assert!(nbt.get("Bossbar").is_none());
+
+        // through the binary format too
+        let mut buf = Vec::new();
+        write_compound_nbt(&mut buf, &nbt);
+        let read = Nbt::read_compound(&mut buf.as_slice());
+        assert_eq!(from_nbt::<LevelData>(&read).unwrap(), level);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(to_nbt(&5, "").is_err());
+        let mixed = (1i32, "two");
+        assert!(to_nbt(&HashMap::from([("x", mixed)]), "").is_err());
+
+        let mut c = CompoundNbt::new("");
+        c.set("LevelName", Nbt::Int(3));
+        assert!(from_nbt::<LevelData>(&c).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::*;
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+/// Data that a packet can be made from. Unlike an `OutPacket`, which borrows what it sends,
+/// this owns it, so it can be kept around and moved between threads.
+pub trait PacketSource: Send + Sync + 'static {
+    fn packet(&self) -> OutPacket<'_>;
+}
+
+/// Some data and how to make a packet out of it
+struct FnPacket<T> {
+    data: T,
+    make: fn(&T) -> OutPacket<'_>,
+}
+
+impl<T: Send + Sync + 'static> PacketSource for FnPacket<T> {
+    fn packet(&self) -> OutPacket<'_> {
+        (self.make)(&self.data)
+    }
+}
+
+/// A packet that owns its data, so it can be queued, sent through channels, and built in
+/// callbacks that outlive what the packet would borrow. Cloning it is cheap.
+///
+/// `OutPacket<'static>`s convert into it directly; for the rest, `from_fn()` takes the data and a
+/// function (e.g. a closure that captures nothing) that makes the packet from it.
+#[derive(Clone)]
+pub struct OwnedPacket {
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    /// Borrows nothing, so it's used as is
+    Static(Arc<OutPacket<'static>>),
+    Source(Arc<dyn PacketSource>),
+}
+
+impl OwnedPacket {
+    pub fn new(source: impl PacketSource) -> Self {
+        Self {
+            inner: Inner::Source(Arc::new(source)),
+        }
+    }
+
+    /// A packet made by `make` from `data`, which the packet keeps
+    pub fn from_fn<T: Send + Sync + 'static>(data: T, make: fn(&T) -> OutPacket<'_>) -> Self {
+        Self::new(FnPacket { data, make })
+    }
+
+    /// Calls `f` with the packet, which borrows from this
+    pub fn with_packet<R>(&self, f: impl FnOnce(OutPacket<'_>) -> R) -> R {
+        match &self.inner {
+            Inner::Static(packet) => f(OutPacket::clone(packet)),
+            Inner::Source(source) => f(source.packet()),
+        }
+    }
+
+    /// Encoded as `encode_packet()` does
+    pub fn encode(&self, protocol_version: i32) -> Vec<u8> {
+        self.with_packet(|p| encode_packet(p, protocol_version))
+    }
+
+    pub fn category(&self) -> PacketCategory {
+        self.with_packet(|p| p.category())
+    }
+}
+
+impl From<OutPacket<'static>> for OwnedPacket {
+    fn from(packet: OutPacket<'static>) -> Self {
+        Self {
+            inner: Inner::Static(Arc::new(packet)),
+        }
+    }
+}
+
+impl fmt::Debug for OwnedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.with_packet(|p| f.debug_tuple("OwnedPacket").field(&p).finish())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum QueuedPacket {
+    To(ClientID, OwnedPacket),
+    Broadcast(OwnedPacket),
+}
+
+/// Sends packets to a server's clients from any thread. They're sent from the server's thread
+/// before its next tick. Get one with `ServerHandle::packet_sender()`.
+#[derive(Debug, Clone)]
+pub struct PacketSender {
+    pub(crate) tx: Sender<QueuedPacket>,
+}
+
+impl PacketSender {
+    /// Queues `packet` for `cid`. Returns false if the server has stopped.
+    pub fn send(&self, cid: ClientID, packet: impl Into<OwnedPacket>) -> bool {
+        self.tx.send(QueuedPacket::To(cid, packet.into())).is_ok()
+    }
+
+    /// Queues `packet` for every client that's playing. Returns false if the server has stopped.
+    pub fn broadcast(&self, packet: impl Into<OwnedPacket>) -> bool {
+        self.tx.send(QueuedPacket::Broadcast(packet.into())).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn cross_thread() {
+        let (tx, rx) = mpsc::channel();
+        let sender = PacketSender { tx };
+        thread::spawn(move || {
+            let text = TextComponent::text(format!("made on {:?}", thread::current().id()));
+            sender.send(
+                ClientID::new(1, 0),
+                OwnedPacket::from_fn(text, |content| OutPacket::SystemChat {
+                    content,
+                    overlay: false,
+                }),
+            );
+            sender.broadcast(OutPacket::ClearTitles { reset: true });
+        })
+        .join()
+        .unwrap();
+
+        let QueuedPacket::To(cid, chat) = rx.recv().unwrap() else {
+            panic!("expected a packet for one client");
+        };
+        assert_eq!(cid, ClientID::new(1, 0));
+        chat.with_packet(|p| {
+            let OutPacket::SystemChat { content, .. } = p else {
+                panic!("wrong packet");
+            };
+            assert!(content.text.starts_with("made on"));
+        });
+        assert_eq!(chat.category(), PacketCategory::Chat);
+        let QueuedPacket::Broadcast(clear) = rx.recv().unwrap() else {
+            panic!("expected a broadcast");
+        };
+        assert_eq!(clear.clone().encode(PROTOCOL_VERSION), [0x0F, 0x01]);
+    }
+}
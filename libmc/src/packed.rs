@@ -0,0 +1,158 @@
+use crate::*;
+use std::borrow::Cow;
+
+/// Fixed-width unsigned values packed into longs the way vanilla does it (since 1.16):
+/// each long holds `64 / bits` values starting from its least significant bits, and values
+/// never straddle two longs, so the leftover high bits of each long are unused.
+///
+/// Heightmaps and paletted chunk sections are stored like this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedIntArray {
+    bits: u8,
+    len: usize,
+    longs: Vec<i64>,
+}
+
+impl PackedIntArray {
+    /// `len` zeroes, `bits` bits each (1 to 32)
+    pub fn new(bits: u8, len: usize) -> Self {
+        assert!((1..=32).contains(&bits), "bad value width {bits}");
+        let per_long = 64 / usize::from(bits);
+        Self {
+            bits,
+            len,
+            longs: vec![0; len.div_ceil(per_long)],
+        }
+    }
+
+    /// Wraps already-packed longs. Returns None if there's the wrong number of them.
+    pub fn from_longs(bits: u8, len: usize, longs: Vec<i64>) -> Option<Self> {
+        let mut arr = Self::new(bits, 0);
+        arr.len = len;
+        if longs.len() != len.div_ceil(arr.per_long()) {
+            return None;
+        }
+        arr.longs = longs;
+        Some(arr)
+    }
+
+    /// The fewest bits that can hold every value from 0 to `max`
+    pub fn bits_for(max: u32) -> u8 {
+        (32 - max.leading_zeros()).max(1) as u8
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn longs(&self) -> &[i64] {
+        &self.longs
+    }
+
+    fn per_long(&self) -> usize {
+        64 / usize::from(self.bits)
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    /// (long index, bit offset in the long)
+    fn locate(&self, i: usize) -> (usize, usize) {
+        assert!(i < self.len, "index {i} out of range for len {}", self.len);
+        (
+            i / self.per_long(),
+            (i % self.per_long()) * usize::from(self.bits),
+        )
+    }
+
+    pub fn get(&self, i: usize) -> u32 {
+        let (long, shift) = self.locate(i);
+        ((self.longs[long] as u64 >> shift) & self.mask()) as u32
+    }
+
+    pub fn set(&mut self, i: usize, value: u32) {
+        assert!(
+            u64::from(value) <= self.mask(),
+            "{value} doesn't fit in {} bits",
+            self.bits
+        );
+        let (long, shift) = self.locate(i);
+        let mut l = self.longs[long] as u64;
+        l &= !(self.mask() << shift);
+        l |= u64::from(value) << shift;
+        self.longs[long] = l as i64;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len).map(|i| self.get(i))
+    }
+
+    /// Packs `values`, which must all fit in `bits` bits
+    pub fn pack(bits: u8, values: &[u32]) -> Self {
+        let mut arr = Self::new(bits, values.len());
+        for (i, v) in values.iter().enumerate() {
+            arr.set(i, *v);
+        }
+        arr
+    }
+
+    /// As a Long Array tag, e.g. for a heightmap
+    pub fn to_nbt(&self) -> Nbt<'static> {
+        Nbt::LongArray(Cow::Owned(self.longs.clone()))
+    }
+
+    /// The wire form of a paletted container's data array: the number of longs as a VarInt,
+    /// then the longs. (The bits-per-entry byte and palette come before it.)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(5 + 8 * self.longs.len());
+        write_varint(&mut out, self.longs.len().try_into().unwrap());
+        for l in &self.longs {
+            write_long(&mut out, *l);
+        }
+        out
+    }
+}
+
+/// The `MOTION_BLOCKING` heightmap compound sent with chunk data. `heights` are per column
+/// (x + z * 16), counted in blocks above the bottom of the world, in a world `world_height` tall.
+pub fn heightmaps_nbt(heights: &[u32; 256], world_height: u32) -> CompoundNbt<'static> {
+    let packed = PackedIntArray::pack(PackedIntArray::bits_for(world_height), heights);
+    let mut c = CompoundNbt::new("");
+    c.set("MOTION_BLOCKING", packed.to_nbt());
+    c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_straddling() {
+        assert_eq!(PackedIntArray::bits_for(384), 9);
+        assert_eq!(PackedIntArray::bits_for(0), 1);
+
+        // 7 values of 9 bits per long, so 256 values take 37 longs
+        let heights: Vec<u32> = (0..256).map(|i| i * 3 % 385).collect();
+        let arr = PackedIntArray::pack(9, &heights);
+        assert_eq!(arr.longs().len(), 37);
+        assert_eq!(arr.iter().collect::<Vec<_>>(), heights);
+        assert_eq!(arr.longs()[0] as u64 >> 63, 0, "top bit unused");
+        assert_eq!(arr.longs()[0] & 0x1FF, 0);
+        assert_eq!((arr.longs()[0] >> 9) & 0x1FF, 3);
+
+        let mut arr = PackedIntArray::from_longs(9, 256, arr.longs().to_vec()).unwrap();
+        arr.set(255, 384);
+        assert_eq!(arr.get(255), 384);
+        assert_eq!(arr.get(254), 254 * 3 % 385);
+        assert!(PackedIntArray::from_longs(9, 256, vec![0; 36]).is_none());
+    }
+}
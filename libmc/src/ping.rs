@@ -0,0 +1,167 @@
+use crate::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Pings that haven't been answered after this many more were sent are forgotten
+const MAX_PENDING: usize = 64;
+
+/// Measures a client's round trip time with play-state Ping and Pong packets, or Keep Alives.
+///
+/// The client answers a Ping only after handling everything sent before it, so a Pong also
+/// tells that the client has caught up (e.g. applied a teleport), which is useful for syncing things up.
+/// `average()` is smoothed the way vanilla smooths keep alive times, which is what the tab list shows.
+#[derive(Debug)]
+pub struct PingTracker<T: Clock = SystemClock> {
+    clock: T,
+    next_id: i32,
+    /// Sent pings that weren't answered yet, oldest first
+    pending: VecDeque<(Probe, Instant)>,
+    latest: Option<Duration>,
+    average: Option<Duration>,
+}
+
+/// A sent packet whose reply is waited for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Probe {
+    Ping(i32),
+    KeepAlive(i64),
+}
+
+impl Default for PingTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PingTracker {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<T: Clock> PingTracker<T> {
+    pub fn with_clock(clock: T) -> Self {
+        Self {
+            clock,
+            next_id: 0,
+            pending: VecDeque::new(),
+            latest: None,
+            average: None,
+        }
+    }
+
+    /// Starts a round trip. The returned packet has to be sent to the client.
+    pub fn ping(&mut self) -> OutPacket<'static> {
+        let id = self.start(Probe::Ping);
+        OutPacket::Ping { id }
+    }
+
+    /// Starts a round trip with a Keep Alive instead, which clients need every so often anyway.
+    /// The returned packet has to be sent to the client.
+    pub fn keep_alive(&mut self) -> OutPacket<'static> {
+        let id = self.start(|id| Probe::KeepAlive(id.into()));
+        OutPacket::KeepAlive { id: id.into() }
+    }
+
+    fn start(&mut self, probe: impl FnOnce(i32) -> Probe) -> i32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((probe(id), self.clock.now()));
+        id
+    }
+
+    /// Handles a packet from the client. Returns the round trip time if it's a Pong or Keep Alive
+    /// answering one of this tracker's pings.
+    pub fn handle(&mut self, packet: &InPacket) -> Option<Duration> {
+        let probe = match *packet {
+            InPacket::Pong { id } | InPacket::PongConfig { id } => Probe::Ping(id),
+            InPacket::KeepAlive { id } | InPacket::KeepAliveConfig { id } => Probe::KeepAlive(id),
+            _ => return None,
+        };
+        let index = self.pending.iter().position(|&(p, _)| p == probe)?;
+        // pings are answered in order, so older ones never will be
+        let (_, sent) = self.pending.drain(..=index).next_back().unwrap();
+        let rtt = self.clock.now() - sent;
+
+        self.latest = Some(rtt);
+        self.average = Some(match self.average {
+            Some(avg) => (avg * 3 + rtt) / 4,
+            None => rtt,
+        });
+        Some(rtt)
+    }
+
+    /// The most recently measured round trip time
+    pub fn latest(&self) -> Option<Duration> {
+        self.latest
+    }
+
+    /// The smoothed round trip time
+    pub fn average(&self) -> Option<Duration> {
+        self.average
+    }
+
+    /// `average()` in milliseconds, as the tab list takes it (0 until something was measured)
+    pub fn latency_ms(&self) -> i32 {
+        self.average
+            .map_or(0, |avg| avg.as_millis().try_into().unwrap_or(i32::MAX))
+    }
+
+    /// How many pings are still waiting for a Pong
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let clock = ManualClock::new();
+        let mut pings = PingTracker::with_clock(clock.clone());
+        assert_eq!(pings.latency_ms(), 0);
+
+        let OutPacket::Ping { id: first } = pings.ping() else {
+            panic!()
+        };
+        clock.advance(Duration::from_millis(40));
+        let OutPacket::Ping { id: second } = pings.ping() else {
+            panic!()
+        };
+        assert_eq!(pings.pending(), 2);
+        let OutPacket::KeepAlive { id: third } = pings.keep_alive() else {
+            panic!()
+        };
+        assert_eq!(pings.handle(&InPacket::Pong { id: third as i32 }), None);
+
+        clock.advance(Duration::from_millis(60));
+        assert_eq!(
+            pings.handle(&InPacket::Pong { id: first }),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(pings.latency_ms(), 100);
+        // unknown IDs and other packets are ignored
+        assert_eq!(pings.handle(&InPacket::Pong { id: first }), None);
+        assert_eq!(pings.handle(&InPacket::LoginAck), None);
+
+        clock.advance(Duration::from_millis(20));
+        assert_eq!(
+            pings.handle(&InPacket::Pong { id: second }),
+            Some(Duration::from_millis(80))
+        );
+        assert_eq!(pings.latest(), Some(Duration::from_millis(80)));
+        assert_eq!(pings.latency_ms(), 95);
+        assert_eq!(pings.pending(), 1);
+
+        assert_eq!(
+            pings.handle(&InPacket::KeepAlive { id: third }),
+            Some(Duration::from_millis(80))
+        );
+        assert_eq!(pings.pending(), 0);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::nbt::{read_nbt_file, write_nbt_file};
+use crate::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The `playerdata` directory of a world, holding one gzipped nbt file per player who has ever joined
+#[derive(Debug, Clone)]
+pub struct PlayerDataStore {
+    dir: PathBuf,
+}
+
+impl PlayerDataStore {
+    /// `dir` is created if it doesn't exist
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, uuid: Uuid) -> PathBuf {
+        self.dir.join(format!("{uuid}.dat"))
+    }
+
+    /// Whether the player has joined before, i.e. has saved data
+    pub fn has_played_before(&self, uuid: Uuid) -> bool {
+        self.path(uuid).exists()
+    }
+
+    /// Returns None for players who haven't joined before
+    pub fn load(&self, uuid: Uuid) -> io::Result<Option<CompoundNbt<'static>>> {
+        read_nbt_file(&self.path(uuid))
+    }
+
+    pub fn save(&self, uuid: Uuid, data: &CompoundNbt<'_>) -> io::Result<()> {
+        write_nbt_file(&self.path(uuid), data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_join() {
+        let dir = std::env::temp_dir().join(format!("libmc-playerdata-{}", std::process::id()));
+        let store = PlayerDataStore::open(&dir).unwrap();
+        let uuid = 0x069a79f444e94726a5befca90e38aaf5;
+
+        assert!(!store.has_played_before(Uuid(uuid)));
+        assert!(store.load(Uuid(uuid)).unwrap().is_none());
+        let ev = JoinMessageEvent::new(
+            ClientID::new(0, 0),
+            "Notch",
+            !store.has_played_before(Uuid(uuid)),
+        );
+        assert!(ev.first_join);
+
+        let mut data = CompoundNbt::new("");
+        data.set("DataVersion", Nbt::Int(DATA_VERSION));
+        store.save(Uuid(uuid), &data).unwrap();
+        assert!(store.has_played_before(Uuid(uuid)));
+        assert_eq!(store.load(Uuid(uuid)).unwrap(), Some(data));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
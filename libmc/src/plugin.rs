@@ -0,0 +1,310 @@
+use crate::*;
+use std::fmt;
+
+/// Bumped whenever the `Plugin` trait changes, so stale dynamic plugins are refused instead of crashing
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// A piece of server functionality that can be added without editing the server binary.
+///
+/// Plugins are either registered statically with `PluginManager::register()`, or built as
+/// `cdylib`s that use `declare_plugin!` and loaded with `PluginManager::load_dir()`.
+pub trait Plugin {
+    fn name(&self) -> &str;
+
+    /// Called once when the plugin is added. Subscribe to events here.
+    fn on_enable(&mut self, _ctx: &mut PluginContext<'_>) {}
+
+    /// Called once when the plugin is removed or the manager is dropped.
+    /// Its event handlers are unregistered automatically.
+    fn on_disable(&mut self) {}
+
+    fn on_tick(&mut self, _tick: u64) {}
+}
+
+/// What a plugin can access while being enabled
+pub struct PluginContext<'a> {
+    events: &'a mut EventBus,
+    handlers: &'a mut Vec<HandlerId>,
+}
+
+impl PluginContext<'_> {
+    pub fn subscribe<E: Event>(
+        &mut self,
+        priority: Priority,
+        mut f: impl FnMut(&mut E) + 'static,
+    ) -> HandlerId {
+        let id = self.events.register(priority, move |_, e: &mut E| f(e));
+        self.handlers.push(id);
+        id
+    }
+}
+
+#[derive(Debug)]
+pub enum PluginError {
+    Io(std::io::Error),
+    #[cfg(feature = "dynamic-plugins")]
+    Load(libloading::Error),
+    /// The library was built against a different version of the plugin API
+    ApiVersion {
+        expected: u32,
+        found: u32,
+    },
+    /// A plugin with that name is already loaded
+    Duplicate(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Io(e) => write!(f, "I/O error: {e}"),
+            #[cfg(feature = "dynamic-plugins")]
+            PluginError::Load(e) => write!(f, "couldn't load plugin library: {e}"),
+            PluginError::ApiVersion { expected, found } => write!(
+                f,
+                "plugin was built for plugin API version {found}, but this server uses version {expected}"
+            ),
+            PluginError::Duplicate(name) => write!(f, "a plugin named '{name}' is already loaded"),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+impl From<std::io::Error> for PluginError {
+    fn from(e: std::io::Error) -> Self {
+        PluginError::Io(e)
+    }
+}
+
+struct Loaded {
+    plugin: Box<dyn Plugin>,
+    handlers: Vec<HandlerId>,
+    /// Must outlive `plugin`, whose code lives in it
+    #[cfg(feature = "dynamic-plugins")]
+    _library: Option<libloading::Library>,
+}
+
+/// Owns the loaded plugins and the `EventBus` they subscribe to.
+///
+/// Forward packets to `post_packet()` and ticks to `tick()` from your `Server` impl.
+pub struct PluginManager {
+    plugins: Vec<Loaded>,
+    events: EventBus,
+}
+
+impl Default for PluginManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PluginManager {
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+            events: EventBus::new(),
+        }
+    }
+
+    /// Adds and enables a statically linked plugin
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) -> Result<(), PluginError> {
+        self.enable(
+            plugin,
+            #[cfg(feature = "dynamic-plugins")]
+            None,
+        )
+    }
+
+    fn enable(
+        &mut self,
+        mut plugin: Box<dyn Plugin>,
+        #[cfg(feature = "dynamic-plugins")] library: Option<libloading::Library>,
+    ) -> Result<(), PluginError> {
+        if self
+            .plugins
+            .iter()
+            .any(|p| p.plugin.name() == plugin.name())
+        {
+            return Err(PluginError::Duplicate(plugin.name().to_string()));
+        }
+
+        let mut handlers = Vec::new();
+        plugin.on_enable(&mut PluginContext {
+            events: &mut self.events,
+            handlers: &mut handlers,
+        });
+        self.plugins.push(Loaded {
+            plugin,
+            handlers,
+            #[cfg(feature = "dynamic-plugins")]
+            _library: library,
+        });
+        Ok(())
+    }
+
+    /// Loads every shared library in `dir` (e.g. `plugins/`) as a plugin.
+    /// Returns the names of the loaded plugins.
+    ///
+    /// The libraries must be built with the same compiler and libmc version as the server,
+    /// because the `Plugin` trait object is passed across the library boundary as-is.
+    #[cfg(feature = "dynamic-plugins")]
+    pub fn load_dir(
+        &mut self,
+        dir: impl AsRef<std::path::Path>,
+    ) -> Result<Vec<String>, PluginError> {
+        let mut names = Vec::new();
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // load in a consistent order
+        paths.sort();
+        for path in paths {
+            if path.extension() != Some(std::env::consts::DLL_EXTENSION.as_ref()) {
+                continue;
+            }
+            names.push(self.load(&path)?);
+        }
+        Ok(names)
+    }
+
+    /// Loads a single plugin library. Returns the plugin's name.
+    #[cfg(feature = "dynamic-plugins")]
+    pub fn load(&mut self, path: &std::path::Path) -> Result<String, PluginError> {
+        // SAFETY: running the library's initializers and trusting its exported symbols to have the
+        // types `declare_plugin!` gives them is inherent to loading plugins
+        unsafe {
+            let library = libloading::Library::new(path).map_err(PluginError::Load)?;
+            let version = **library
+                .get::<*const u32>(b"_MCSERVER_PLUGIN_API_VERSION\0")
+                .map_err(PluginError::Load)?;
+            if version != PLUGIN_API_VERSION {
+                return Err(PluginError::ApiVersion {
+                    expected: PLUGIN_API_VERSION,
+                    found: version,
+                });
+            }
+            let create = library
+                .get::<fn() -> Box<dyn Plugin>>(b"_mcserver_plugin_create\0")
+                .map_err(PluginError::Load)?;
+            let plugin = create();
+            let name = plugin.name().to_string();
+            self.enable(plugin, Some(library))?;
+            Ok(name)
+        }
+    }
+
+    /// Disables and removes a plugin. Returns false if no plugin has that name.
+    pub fn unload(&mut self, name: &str) -> bool {
+        let Some(i) = self.plugins.iter().position(|p| p.plugin.name() == name) else {
+            return false;
+        };
+        let loaded = self.plugins.remove(i);
+        self.disable(loaded);
+        true
+    }
+
+    fn disable(&mut self, mut loaded: Loaded) {
+        loaded.plugin.on_disable();
+        for id in loaded.handlers.drain(..) {
+            self.events.unregister(id);
+        }
+        // `loaded` is dropped field by field, in order: the plugin before its library
+    }
+
+    pub fn plugin_names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|p| p.plugin.name())
+    }
+
+    /// Posts an event to the plugins' handlers. Returns true if it wasn't cancelled.
+    pub fn post<E: Event>(&mut self, event: &mut E) -> bool {
+        self.events.post(&mut (), event)
+    }
+
+    /// See `EventBus::post_packet()`
+    pub fn post_packet(&mut self, cid: ClientID, packet: &InPacket) -> bool {
+        self.events.post_packet(&mut (), cid, packet)
+    }
+
+    pub fn tick(&mut self, tick: u64) {
+        for p in &mut self.plugins {
+            p.plugin.on_tick(tick);
+        }
+    }
+}
+
+impl Drop for PluginManager {
+    fn drop(&mut self) {
+        // in reverse load order
+        while let Some(loaded) = self.plugins.pop() {
+            self.disable(loaded);
+        }
+    }
+}
+
+/// Exports a plugin from a `cdylib` crate, for `PluginManager::load_dir()`.
+/// Takes an expression that creates the plugin, e.g. `declare_plugin!(MyPlugin::new())`.
+#[macro_export]
+macro_rules! declare_plugin {
+    ($create:expr) => {
+        #[no_mangle]
+        pub static _MCSERVER_PLUGIN_API_VERSION: u32 = $crate::PLUGIN_API_VERSION;
+
+        #[no_mangle]
+        pub fn _mcserver_plugin_create() -> ::std::boxed::Box<dyn $crate::Plugin> {
+            ::std::boxed::Box::new($create)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct NoSwearing {
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Plugin for NoSwearing {
+        fn name(&self) -> &str {
+            "no-swearing"
+        }
+
+        fn on_enable(&mut self, ctx: &mut PluginContext<'_>) {
+            ctx.subscribe(Priority::Normal, |e: &mut ChatEvent| {
+                e.cancelled = e.message.contains("heck");
+            });
+        }
+
+        fn on_disable(&mut self) {
+            self.log.borrow_mut().push("disabled".to_string());
+        }
+    }
+
+    #[test]
+    fn lifecycle() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut plugins = PluginManager::new();
+        plugins
+            .register(Box::new(NoSwearing { log: log.clone() }))
+            .unwrap();
+        assert!(matches!(
+            plugins.register(Box::new(NoSwearing { log: log.clone() })),
+            Err(PluginError::Duplicate(_))
+        ));
+
+        let mut chat = ChatEvent {
+            cid: ClientID::new(0, 0),
+            message: "heck".to_string(),
+            cancelled: false,
+        };
+        assert!(!plugins.post(&mut chat));
+
+        assert!(plugins.unload("no-swearing"));
+        assert_eq!(*log.borrow(), ["disabled"]);
+        chat.cancelled = false;
+        assert!(plugins.post(&mut chat), "handler was unregistered");
+        assert_eq!(plugins.plugin_names().count(), 0);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A chat vote (e.g. for picking the next map). Options are shown as clickable
+/// chat buttons that run `/vote <poll id> <option>`, and the live tally is shown on a boss bar.
+#[derive(Debug, Clone)]
+pub struct Poll {
+    id: u32,
+    question: String,
+    options: Vec<String>,
+    /// voter UUID -> index into `options`
+    votes: HashMap<Uuid, usize>,
+    started: Instant,
+    duration: Duration,
+    bossbar: BossBar,
+}
+
+impl Poll {
+    /// `id` must be unique among the currently running polls; it is what the chat buttons refer to.
+    pub fn new(
+        id: u32,
+        question: impl Into<String>,
+        options: Vec<String>,
+        duration: Duration,
+    ) -> Self {
+        assert!(!options.is_empty(), "poll needs at least one option");
+
+        let mut poll = Self {
+            id,
+            question: question.into(),
+            options,
+            votes: HashMap::new(),
+            started: Instant::now(),
+            duration,
+            bossbar: BossBar::new(
+                TextComponent::default(),
+                BossBarColor::Yellow,
+                BossBarDivision::None,
+            ),
+        };
+        poll.bossbar.title = poll.tally_title();
+        poll
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn options(&self) -> &[String] {
+        &self.options
+    }
+
+    /// Records (or changes) `voter`'s vote. Returns false if the option doesn't exist or the poll is over.
+    pub fn vote(&mut self, voter: Uuid, option: usize) -> bool {
+        if option >= self.options.len() || self.is_finished() {
+            return false;
+        }
+        self.votes.insert(voter, option);
+        self.bossbar.title = self.tally_title();
+        true
+    }
+
+    /// Handles a `vote <poll id> <option>` command (as sent in `InPacket::ChatCommand`).
+    /// Returns false if the command isn't a valid vote for this poll.
+    pub fn handle_command(&mut self, voter: Uuid, command: &str) -> bool {
+        let mut args = command.split_whitespace();
+        if args.next() != Some("vote") {
+            return false;
+        }
+        let (Some(id), Some(option), None) = (args.next(), args.next(), args.next()) else {
+            return false;
+        };
+        match (id.parse::<u32>(), option.parse::<usize>()) {
+            (Ok(id), Ok(option)) if id == self.id => self.vote(voter, option),
+            _ => false,
+        }
+    }
+
+    /// Number of votes for each option, in the same order as `options()`
+    pub fn tally(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.options.len()];
+        for opt in self.votes.values() {
+            counts[*opt] += 1;
+        }
+        counts
+    }
+
+    /// The option with the most votes (ties go to the earlier option), or None if nobody voted
+    pub fn winner(&self) -> Option<usize> {
+        if self.votes.is_empty() {
+            return None;
+        }
+        let tally = self.tally();
+        let max = *tally.iter().max().unwrap();
+        tally.iter().position(|x| *x == max)
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.duration.saturating_sub(self.started.elapsed())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// The chat message announcing the poll, with one clickable button per option
+    pub fn announcement(&self) -> TextComponent {
+        let mut msg = TextComponent::text(format!("{}\n", self.question))
+            .color(ChatColor::Gold)
+            .bold(true);
+        for (i, opt) in self.options.iter().enumerate() {
+            msg = msg.append(
+                TextComponent::text(format!("[{opt}] "))
+                    .color(ChatColor::Green)
+                    .bold(false)
+                    .on_click(ClickEvent::RunCommand(format!("/vote {} {i}", self.id))),
+            );
+        }
+        msg
+    }
+
+    /// Shows the tally boss bar. Send this once to each player that should see the poll.
+    pub fn show_packet(&self) -> OutPacket<'_> {
+        self.bossbar.add_packet()
+    }
+
+    /// Updates the tally and the time remaining. Send these to everyone viewing the poll
+    /// whenever a vote comes in, and periodically to animate the countdown.
+    pub fn update_packets(&mut self) -> [OutPacket<'_>; 2] {
+        self.bossbar.health =
+            self.remaining().as_secs_f32() / self.duration.as_secs_f32().max(f32::EPSILON);
+        [self.bossbar.title_packet(), self.bossbar.health_packet()]
+    }
+
+    pub fn hide_packet(&self) -> OutPacket<'_> {
+        self.bossbar.remove_packet()
+    }
+
+    fn tally_title(&self) -> TextComponent {
+        let mut title = TextComponent::text(format!("{} ", self.question)).color(ChatColor::Yellow);
+        for (opt, count) in self.options.iter().zip(self.tally()) {
+            title = title
+                .append(TextComponent::text(format!("| {opt}: {count} ")).color(ChatColor::White));
+        }
+        title
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voting() {
+        let mut p = Poll::new(
+            7,
+            "Next map?",
+            vec!["Castle".into(), "Desert".into()],
+            Duration::from_secs(60),
+        );
+        assert_eq!(p.winner(), None);
+
+        assert!(p.handle_command(Uuid(1), "vote 7 1"));
+        assert!(p.handle_command(Uuid(2), "vote 7 0"));
+        assert!(p.vote(Uuid(3), 1));
+        assert_eq!(p.tally(), vec![1, 2]);
+
+        // changing your vote
+        assert!(p.vote(Uuid(3), 0));
+        assert_eq!(p.tally(), vec![2, 1]);
+        assert_eq!(p.winner(), Some(0));
+
+        assert!(!p.handle_command(Uuid(4), "vote 8 0"), "wrong poll id");
+        assert!(!p.handle_command(Uuid(4), "vote 7 2"), "nonexistent option");
+        assert!(!p.handle_command(Uuid(4), "vote 7"));
+        assert!(!p.handle_command(Uuid(4), "kill 7 0"));
+        assert_eq!(p.tally(), vec![2, 1]);
+    }
+
+    #[test]
+    fn expiry() {
+        let mut p = Poll::new(1, "?", vec!["a".into()], Duration::ZERO);
+        assert!(p.is_finished());
+        assert!(!p.vote(Uuid(1), 0));
+    }
+}
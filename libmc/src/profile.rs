@@ -0,0 +1,54 @@
+use crate::*;
+
+/// A signed (or unsigned) property of a player's profile. In practice,
+/// this is the "textures" property holding the player's skin and cape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileProperty {
+    pub name: String,
+    /// base64
+    pub value: String,
+    /// base64 signature by Mojang's key, if any
+    pub signature: Option<String>,
+}
+
+/// A player's identity: their UUID, username, and skin properties
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameProfile {
+    pub uuid: Uuid,
+    pub name: String,
+    pub properties: Vec<ProfileProperty>,
+}
+
+impl GameProfile {
+    /// A profile without any properties (i.e. with the default skin)
+    pub fn new(uuid: Uuid, name: impl Into<String>) -> Self {
+        Self {
+            uuid,
+            name: name.into(),
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<&ProfileProperty> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    pub fn textures(&self) -> Option<&ProfileProperty> {
+        self.property("textures")
+    }
+}
+
+pub(crate) fn write_profile_properties<W: std::io::Write>(w: &mut W, props: &[ProfileProperty]) {
+    write_varint(w, props.len().try_into().unwrap());
+    for p in props {
+        write_string(w, &p.name);
+        write_string(w, &p.value);
+        match &p.signature {
+            Some(sig) => {
+                write_bool(w, true);
+                write_string(w, sig);
+            }
+            None => write_bool(w, false),
+        }
+    }
+}
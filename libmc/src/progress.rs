@@ -0,0 +1,221 @@
+use crate::*;
+use std::sync::{Arc, Mutex};
+
+/// Where a `ProgressIndicator` is shown
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProgressDisplay {
+    BossBar(BossBarColor),
+    ActionBar,
+    /// The label as the title, and the percentage as the subtitle
+    Title,
+}
+
+#[derive(Debug)]
+struct ProgressState {
+    label: String,
+    /// 0.0 to 1.0
+    progress: f32,
+    finished: bool,
+}
+
+/// Cheap-to-clone, thread-safe handle for reporting progress to a `ProgressIndicator`
+#[derive(Debug, Clone)]
+pub struct ProgressHandle {
+    state: Arc<Mutex<ProgressState>>,
+}
+
+impl ProgressHandle {
+    /// `progress` is clamped to 0.0 - 1.0
+    pub fn set_progress(&self, progress: f32) {
+        self.state.lock().unwrap().progress = progress.clamp(0.0, 1.0);
+    }
+
+    pub fn set_label(&self, label: impl Into<String>) {
+        self.state.lock().unwrap().label = label.into();
+    }
+
+    /// Marks the task as done, which makes the indicator hide itself
+    pub fn finish(&self) {
+        self.state.lock().unwrap().finished = true;
+    }
+}
+
+/// Shows a player the progress of some task (world loading, queue position, a download, ...)
+/// that is reported from any thread through a `ProgressHandle`.
+///
+/// Call `poll()` regularly (e.g. every tick) and send the returned packets to the player.
+/// Packets are only generated when the displayed text or bar would visibly change.
+#[derive(Debug)]
+pub struct ProgressIndicator {
+    display: ProgressDisplay,
+    state: Arc<Mutex<ProgressState>>,
+    bossbar: BossBar,
+    /// the action bar text or title, for non-bossbar displays
+    text: TextComponent,
+    subtitle: TextComponent,
+    shown: bool,
+    hidden: bool,
+    /// what was last sent to the client as (label, progress in percent)
+    sent: Option<(String, u8)>,
+}
+
+impl ProgressIndicator {
+    pub fn new(label: impl Into<String>, display: ProgressDisplay) -> (Self, ProgressHandle) {
+        let state = Arc::new(Mutex::new(ProgressState {
+            label: label.into(),
+            progress: 0.0,
+            finished: false,
+        }));
+        let color = match display {
+            ProgressDisplay::BossBar(color) => color,
+            _ => BossBarColor::White,
+        };
+
+        let indicator = Self {
+            display,
+            state: Arc::clone(&state),
+            bossbar: BossBar::new(TextComponent::default(), color, BossBarDivision::Notches10),
+            text: TextComponent::default(),
+            subtitle: TextComponent::default(),
+            shown: false,
+            hidden: false,
+            sent: None,
+        };
+        (indicator, ProgressHandle { state })
+    }
+
+    pub fn handle(&self) -> ProgressHandle {
+        ProgressHandle {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// True once the task has finished and the indicator has been hidden
+    pub fn is_finished(&self) -> bool {
+        self.hidden
+    }
+
+    /// Returns the packets needed to bring the player's display up to date
+    pub fn poll(&mut self) -> Vec<OutPacket<'_>> {
+        if self.hidden {
+            return Vec::new();
+        }
+
+        let (label, percent, finished) = {
+            let state = self.state.lock().unwrap();
+            (
+                state.label.clone(),
+                (state.progress * 100.0).round() as u8,
+                state.finished,
+            )
+        };
+
+        if finished {
+            self.hidden = true;
+            return match self.display {
+                ProgressDisplay::BossBar(_) if self.shown => vec![self.bossbar.remove_packet()],
+                ProgressDisplay::Title if self.shown => {
+                    vec![OutPacket::ClearTitles { reset: false }]
+                }
+                _ => Vec::new(),
+            };
+        }
+
+        let label_changed = self.sent.as_ref().map(|(l, _)| l) != Some(&label);
+        let percent_changed = self.sent.as_ref().map(|(_, p)| *p) != Some(percent);
+        if !label_changed && !percent_changed {
+            return Vec::new();
+        }
+        let first = !self.shown;
+        self.shown = true;
+
+        self.bossbar.health = percent as f32 / 100.0;
+        match self.display {
+            ProgressDisplay::BossBar(_) => self.bossbar.title = TextComponent::text(label.clone()),
+            ProgressDisplay::ActionBar => {
+                self.text = TextComponent::text(format!("{label} ({percent}%)"))
+            }
+            ProgressDisplay::Title => {
+                self.text = TextComponent::text(label.clone());
+                self.subtitle = TextComponent::text(format!("{percent}%"));
+            }
+        }
+        self.sent = Some((label, percent));
+
+        let mut packets = Vec::new();
+        match self.display {
+            ProgressDisplay::BossBar(_) if first => packets.push(self.bossbar.add_packet()),
+            ProgressDisplay::BossBar(_) => {
+                if label_changed {
+                    packets.push(self.bossbar.title_packet());
+                }
+                if percent_changed {
+                    packets.push(self.bossbar.health_packet());
+                }
+            }
+            ProgressDisplay::ActionBar => {
+                packets.push(OutPacket::SetActionBarText { text: &self.text })
+            }
+            ProgressDisplay::Title => {
+                if first {
+                    // keep the title up until the next update
+                    packets.push(OutPacket::SetTitleAnimationTimes {
+                        fade_in: 0,
+                        stay: 20 * 60,
+                        fade_out: 10,
+                    });
+                }
+                packets.push(OutPacket::SetSubtitleText {
+                    text: &self.subtitle,
+                });
+                packets.push(OutPacket::SetTitleText { text: &self.text });
+            }
+        }
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bossbar_updates() {
+        let (mut ind, handle) =
+            ProgressIndicator::new("Loading", ProgressDisplay::BossBar(BossBarColor::Green));
+
+        let p = ind.poll();
+        assert!(matches!(
+            p[..],
+            [OutPacket::BossBar {
+                action: BossBarAction::Add { .. },
+                ..
+            }]
+        ));
+        assert!(ind.poll().is_empty(), "nothing changed");
+
+        // below the 1% display granularity
+        handle.set_progress(0.001);
+        assert!(ind.poll().is_empty());
+
+        std::thread::spawn(move || handle.set_progress(0.5))
+            .join()
+            .unwrap();
+        let p = ind.poll();
+        assert!(
+            matches!(p[..], [OutPacket::BossBar { action: BossBarAction::UpdateHealth(h), .. }] if h == 0.5)
+        );
+
+        ind.handle().finish();
+        let p = ind.poll();
+        assert!(matches!(
+            p[..],
+            [OutPacket::BossBar {
+                action: BossBarAction::Remove,
+                ..
+            }]
+        ));
+        assert!(ind.is_finished());
+        assert!(ind.poll().is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The settings in a vanilla `server.properties` file (Java properties format)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerProperties {
+    values: HashMap<String, String>,
+}
+
+impl ServerProperties {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(s: &str) -> Self {
+        let mut values = HashMap::new();
+        let mut lines = s.lines();
+        while let Some(line) = lines.next() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            // a line ending in an odd number of backslashes continues on the next line
+            let mut logical = line.to_owned();
+            while logical.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1 {
+                logical.pop();
+                match lines.next() {
+                    Some(next) => logical.push_str(next.trim_start()),
+                    None => break,
+                }
+            }
+
+            let (key, value) = split_key_value(&logical);
+            values.insert(unescape(key), unescape(value));
+        }
+        Self { values }
+    }
+
+    /// Reads a `server.properties` file. A missing file has no settings, so every default applies.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(s) => Ok(Self::parse(&s)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// The parsed value of `key`, or `default` if it's missing or doesn't parse (like vanilla)
+    pub fn get_or<T: FromStr>(&self, key: &str, default: T) -> T {
+        self.get(key)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: impl ToString) {
+        self.values.insert(key.into(), value.to_string());
+    }
+}
+
+/// Splits at the first unescaped `=` or `:` (or whitespace, if neither comes first)
+fn split_key_value(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '=' | ':' => return (&line[..i], line[i + 1..].trim_start()),
+            c if c.is_whitespace() => {
+                let rest = line[i..].trim_start();
+                let rest = rest.strip_prefix(['=', ':']).map_or(rest, str::trim_start);
+                return (&line[..i], rest);
+            }
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('f') => out.push('\x0c'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    out.push(c);
+                }
+            }
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_properties() {
+        let props = ServerProperties::parse(
+            "#Minecraft server properties\n\
+             spawn-protection=4\n\
+             motd=A Minecraft Server \\u00a7c\\:)\n\
+             level-name = my\\ world\n\
+             long=a,\\\n    b\n\
+             max-players=lots\n",
+        );
+        assert_eq!(props.get_or("spawn-protection", 16), 4);
+        assert_eq!(props.get("motd"), Some("A Minecraft Server §c:)"));
+        assert_eq!(props.get("level-name"), Some("my world"));
+        assert_eq!(props.get("long"), Some("a,b"));
+        assert_eq!(props.get_or("max-players", 20), 20);
+        assert!(props.get_or("online-mode", true));
+    }
+}
@@ -1,5 +1,7 @@
+use crate::compress::{zlib_compress, zlib_decompress_max};
 use crate::*;
-use std::io::{Read, Write};
+use std::fmt;
+use std::io::{IoSlice, Read, Write};
 
 #[derive(Debug, Copy, Clone)]
 pub enum HandshakeNextState {
@@ -7,59 +9,374 @@ pub enum HandshakeNextState {
     Login,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ChatMode {
     Enabled,
     CommandsOnly,
     Hidden,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MainHand {
     Left,
     Right,
 }
 
+/// The skin layers a player has turned on, from Client Information. Other clients see them through
+/// the player's metadata (see `SkinParts::metadata()`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SkinParts(u8);
+
+impl SkinParts {
+    pub const NONE: Self = Self(0);
+    pub const CAPE: Self = Self(0x01);
+    pub const JACKET: Self = Self(0x02);
+    pub const LEFT_SLEEVE: Self = Self(0x04);
+    pub const RIGHT_SLEEVE: Self = Self(0x08);
+    pub const LEFT_PANTS_LEG: Self = Self(0x10);
+    pub const RIGHT_PANTS_LEG: Self = Self(0x20);
+    pub const HAT: Self = Self(0x40);
+    pub const ALL: Self = Self(0x7F);
+    /// The player metadata index the parts go in
+    pub const METADATA_INDEX: u8 = 17;
+
+    /// Unknown bits are kept, so the byte goes back out as the client sent it
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether every part in `parts` is shown
+    pub const fn contains(self, parts: Self) -> bool {
+        self.0 & parts.0 == parts.0
+    }
+
+    /// With `parts` shown or hidden
+    pub const fn with(self, parts: Self, shown: bool) -> Self {
+        if shown {
+            Self(self.0 | parts.0)
+        } else {
+            Self(self.0 & !parts.0)
+        }
+    }
+
+    pub const fn cape(self) -> bool {
+        self.contains(Self::CAPE)
+    }
+
+    pub const fn jacket(self) -> bool {
+        self.contains(Self::JACKET)
+    }
+
+    pub const fn left_sleeve(self) -> bool {
+        self.contains(Self::LEFT_SLEEVE)
+    }
+
+    pub const fn right_sleeve(self) -> bool {
+        self.contains(Self::RIGHT_SLEEVE)
+    }
+
+    pub const fn left_pants_leg(self) -> bool {
+        self.contains(Self::LEFT_PANTS_LEG)
+    }
+
+    pub const fn right_pants_leg(self) -> bool {
+        self.contains(Self::RIGHT_PANTS_LEG)
+    }
+
+    pub const fn hat(self) -> bool {
+        self.contains(Self::HAT)
+    }
+
+    /// The player's metadata entry for these parts, for Set Entity Metadata
+    pub fn metadata(self) -> MetadataEntry<'static> {
+        MetadataEntry {
+            index: Self::METADATA_INDEX,
+            value: MetadataValue::Byte(self.0 as i8),
+        }
+    }
+}
+
+/// Everything shown, which is what clients start out with
+impl Default for SkinParts {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl std::ops::BitOr for SkinParts {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Hand {
+    Main,
+    Off,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayerActionStatus {
+    StartedDigging,
+    CancelledDigging,
+    FinishedDigging,
+    DropItemStack,
+    DropItem,
+    /// Also used for finishing eating, and releasing other usable items
+    ShootArrow,
+    SwapItemInHand,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClientStatusAction {
+    /// Clicked Respawn on the death screen (or, in hardcore, spectate)
+    PerformRespawn,
+    /// Opened the statistics screen
+    RequestStats,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InteractAction {
+    Interact(Hand),
+    Attack,
+    /// Position is relative to the entity
+    InteractAt {
+        x: f32,
+        y: f32,
+        z: f32,
+        hand: Hand,
+    },
+}
+
+/// Strings and byte arrays are borrowed from the frame the packet was decoded from
 #[derive(Debug)]
-pub enum InPacket {
+pub enum InPacket<'a> {
     Handshake {
-        protocol_version: i64,
-        server_addr: String,
+        protocol_version: i32,
+        server_addr: &'a str,
         server_port: u16,
         next_state: HandshakeNextState,
     },
+    StatusRequest,
+    PingRequest {
+        payload: i64,
+    },
     LoginStart {
-        name: String,
-        player_uuid: u128,
+        name: &'a str,
+        player_uuid: Uuid,
     },
     LoginAck,
     PluginMessageConfig {
         // TODO: Identifier type?
-        channel: String,
-        data: Vec<u8>,
+        channel: &'a str,
+        data: &'a [u8],
+    },
+    /// Plugin Message (play)
+    PluginMessagePlay {
+        channel: &'a str,
+        data: &'a [u8],
     },
     ClientInfoConfig {
-        locale: String,
+        locale: &'a str,
+        view_distance: i8,
+        chat_mode: ChatMode,
+        chat_colors: bool,
+        displayed_skin_parts: SkinParts,
+        main_hand: MainHand,
+        enable_text_filtering: bool,
+        allow_server_listings: bool,
+    },
+    /// Client Information (play), sent when the player changes their settings in game
+    ClientInfoPlay {
+        locale: &'a str,
         view_distance: i8,
         chat_mode: ChatMode,
         chat_colors: bool,
-        // TODO: make this a nice type
-        displayed_skin_parts: u8,
+        displayed_skin_parts: SkinParts,
         main_hand: MainHand,
         enable_text_filtering: bool,
         allow_server_listings: bool,
     },
     FinishConfig,
+    /// The client is going back to the configuration state, after the server sent Start Configuration
+    ConfigAck,
+    ChatCommand {
+        /// The command, without the leading '/'
+        command: &'a str,
+        timestamp: i64,
+        salt: i64,
+    },
+    PlayerCommand {
+        entity_id: i32,
+        action: PlayerCommandAction,
+        /// Only used by StartHorseJump: 0 to 100
+        jump_boost: i32,
+    },
+    SetPlayerPosition {
+        x: f64,
+        /// feet
+        y: f64,
+        z: f64,
+        on_ground: bool,
+    },
+    SetPlayerPositionAndRotation {
+        x: f64,
+        /// feet
+        y: f64,
+        z: f64,
+        yaw: f32,
+        pitch: f32,
+        on_ground: bool,
+    },
+    SetPlayerRotation {
+        yaw: f32,
+        pitch: f32,
+        on_ground: bool,
+    },
+    SetPlayerOnGround {
+        on_ground: bool,
+    },
+    /// The client handled a chunk batch. See `ChunkBatchPacer`.
+    ChunkBatchReceived {
+        /// How many chunks per tick the client would like to get
+        chunks_per_tick: f32,
+    },
+    UseItem {
+        hand: Hand,
+        sequence: i32,
+    },
+    /// Right-clicking a block, which usually places one against it
+    UseItemOn {
+        hand: Hand,
+        location: Position,
+        /// The face of the block that was clicked, as in `PlayerAction`
+        face: i8,
+        /// Where on the face it was clicked, from 0.0 to 1.0 on each axis
+        cursor: [f32; 3],
+        /// The player's head is inside the block
+        inside_block: bool,
+        sequence: i32,
+    },
+    Interact {
+        entity_id: i32,
+        action: InteractAction,
+        sneaking: bool,
+    },
+    ChatMessage {
+        message: &'a str,
+        timestamp: i64,
+        salt: i64,
+        signature: Option<&'a [u8]>,
+    },
+    PlayerAction {
+        status: PlayerActionStatus,
+        location: Position,
+        /// The face of the block that was hit: 0 = -Y, 1 = +Y, 2 = -Z, 3 = +Z, 4 = -X, 5 = +X
+        face: i8,
+        sequence: i32,
+    },
+    /// How a player riding an entity steers it (Steer Vehicle). Which keys are held can't be told
+    /// from it (see `InputCapture`).
+    PlayerInput {
+        /// Positive to the left
+        sideways: f32,
+        /// Positive forward
+        forward: f32,
+        /// 0x1 = jump, 0x2 = unmount (sneak)
+        flags: u8,
+    },
+    /// Where the vehicle the player is driving moved to
+    MoveVehicle {
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: f32,
+        pitch: f32,
+    },
+    /// Which of a boat's paddles are turning, for their animation
+    PaddleBoat {
+        left: bool,
+        right: bool,
+    },
+    /// The reply to a play-state Ping, with its ID. See `PingTracker`.
+    Pong {
+        id: i32,
+    },
+    /// The reply to a play-state Keep Alive, with its ID
+    KeepAlive {
+        id: i64,
+    },
+    /// The reply to a configuration-state Keep Alive, with its ID
+    KeepAliveConfig {
+        id: i64,
+    },
+    /// The reply to a configuration-state Ping, with its ID
+    PongConfig {
+        id: i32,
+    },
+    /// A spectator clicked a player in the spectator menu, to teleport to them
+    TeleportToEntity {
+        target: Uuid,
+    },
+    /// A creative mode player put an item in (or took one out of) their inventory
+    SetCreativeModeSlot {
+        /// In the player inventory's numbering, or -1 to drop the item
+        slot: i16,
+        /// None if the slot was emptied
+        item: Option<Slot<'a>>,
+    },
+    /// The player clicked a recipe in the recipe book, to fill the crafting grid with it
+    PlaceRecipe {
+        window_id: i8,
+        recipe: &'a str,
+        /// Shift-clicked, to fill the grid with as many as possible
+        make_all: bool,
+    },
+    ChangeRecipeBookSettings {
+        book: RecipeBookType,
+        state: RecipeBookState,
+    },
+    ClientStatus {
+        action: ClientStatusAction,
+    },
+    /// The difficulty button in the options menu (vanilla only honors it in singleplayer)
+    ChangeDifficulty {
+        difficulty: Difficulty,
+    },
+    /// The lock button next to the difficulty one. Clients only ever lock it.
+    LockDifficulty {
+        locked: bool,
+    },
+    /// The player clicked a trade in a merchant screen
+    SelectTrade {
+        /// Index into `OutPacket::MerchantOffers::offers`
+        slot: i32,
+    },
+    /// The player looked at a recipe that was highlighted as new
+    SetSeenRecipe {
+        recipe: &'a str,
+    },
+    /// The player opened a tab of the advancements screen, or closed the screen (None)
+    SeenAdvancements {
+        tab: Option<&'a str>,
+    },
+    /// A packet libmc doesn't decode, so that servers can ignore it (or decode it themselves, with
+    /// a `PacketReader`)
+    Unknown {
+        id: i32,
+        state: ProtocolState,
+        /// The packet's body, after the ID
+        data: &'a [u8],
+    },
 }
 
-#[derive(Debug)]
-pub struct LoginSuccessProp<'a> {
-    pub name: &'a str,
-    pub value: &'a str,
-    pub signature: Option<&'a str>,
-}
-
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum GameMode {
     Survival = 0,
@@ -68,9 +385,9 @@ pub enum GameMode {
     Spectator = 3,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Position {
-    /// NOTE: this is actually only supposed to be 26 bits
+    /// NOTE: this is actually only supposed to be 26 bits (see `Position::new()`)
     pub x: i32,
     /// NOTE: this is actually only supposed to be 26 bits
     pub z: i32,
@@ -78,7 +395,57 @@ pub struct Position {
     pub y: i16,
 }
 
-#[derive(Debug)]
+/// A block position outside of what fits in the protocol's 26/12/26-bit encoding
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PositionOutOfRange {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl fmt::Display for PositionOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block position ({}, {}, {}) is out of range",
+            self.x, self.y, self.z
+        )
+    }
+}
+
+impl std::error::Error for PositionOutOfRange {}
+
+impl Position {
+    pub const MIN_XZ: i32 = -(1 << 25);
+    pub const MAX_XZ: i32 = (1 << 25) - 1;
+    pub const MIN_Y: i32 = -(1 << 11);
+    pub const MAX_Y: i32 = (1 << 11) - 1;
+
+    /// Checks that the position can be sent (x and z are 26-bit signed, y is 12-bit signed)
+    pub fn new(x: i32, y: i32, z: i32) -> Result<Self, PositionOutOfRange> {
+        let xz = Self::MIN_XZ..=Self::MAX_XZ;
+        if xz.contains(&x) && xz.contains(&z) && (Self::MIN_Y..=Self::MAX_Y).contains(&y) {
+            Ok(Self { x, y: y as i16, z })
+        } else {
+            Err(PositionOutOfRange { x, y, z })
+        }
+    }
+
+    /// The block next to this one on the side `face` (as in `InPacket::PlayerAction`)
+    pub fn relative(self, face: i8) -> Self {
+        let Some(direction) = Direction::from_id(face) else {
+            return self;
+        };
+        let offset = direction.offset();
+        Self {
+            x: self.x + offset.x,
+            y: self.y + offset.y as i16,
+            z: self.z + offset.z,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DeathInfo<'a> {
     /// dimension the player died in
     // TODO: Identifier type?
@@ -86,7 +453,7 @@ pub struct DeathInfo<'a> {
     pub location: Position,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BitSet {
     longs: Vec<i64>,
 }
@@ -99,6 +466,37 @@ impl BitSet {
         }
     }
 
+    /// Bit `i` is bit `i % 64` of `longs[i / 64]`, as on the wire
+    pub fn from_longs(longs: Vec<i64>) -> Self {
+        Self { longs }
+    }
+
+    pub fn longs(&self) -> &[i64] {
+        &self.longs
+    }
+
+    /// Reads a BitSet in the protocol's format (length-prefixed longs). The longs are checked to
+    /// be there before anything is allocated, so a made-up length can't allocate more than the
+    /// packet's size.
+    pub fn read(r: &mut PacketReader) -> Result<Self, DecodeError> {
+        let len = r.varint()?;
+        let bad_len = DecodeError::BadValue {
+            field: "bitset length",
+            value: len,
+        };
+        let bytes = usize::try_from(len)
+            .ok()
+            .and_then(|len| len.checked_mul(8))
+            .ok_or(bad_len)?;
+        let longs = r.slice(bytes)?;
+        Ok(Self::from_longs(
+            longs
+                .chunks_exact(8)
+                .map(|l| i64::from_be_bytes(l.try_into().unwrap()))
+                .collect(),
+        ))
+    }
+
     fn compute_idx(&self, bit_idx: usize) -> (usize, usize) {
         assert!(
             bit_idx < 64 * self.longs.len(),
@@ -119,6 +517,43 @@ impl BitSet {
 
         (self.longs[long_idx] & (1 << bit_idx)) != 0
     }
+
+    pub fn clear(&mut self, bit_idx: usize) {
+        let (long_idx, bit_idx) = self.compute_idx(bit_idx);
+        self.longs[long_idx] &= !(1 << bit_idx);
+    }
+
+    pub fn set_value(&mut self, bit_idx: usize, value: bool) {
+        if value {
+            self.set(bit_idx);
+        } else {
+            self.clear(bit_idx);
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.longs.iter().map(|l| l.count_ones() as usize).sum()
+    }
+
+    /// True if no bits are set
+    pub fn is_empty(&self) -> bool {
+        self.longs.iter().all(|l| *l == 0)
+    }
+
+    /// The indices of the set bits, in increasing order
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.longs.iter().enumerate().flat_map(|(i, &long)| {
+            let mut rest = long as u64;
+            std::iter::from_fn(move || {
+                if rest == 0 {
+                    return None;
+                }
+                let bit = rest.trailing_zeros() as usize;
+                rest &= rest - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -128,33 +563,121 @@ pub struct BlockEntity<'a> {
     pub z: u8,
     pub y: i16,
     /// type
-    pub tipe: i64,
+    pub tipe: i32,
     pub data: CompoundNbt<'a>,
 }
 
+/// One player's entry in `OutPacket::PlayerInfoUpdate`. Fields that are `None` aren't updated.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerInfoEntry<'a> {
+    pub uuid: Uuid,
+    /// Adds the player to the client's player list
+    pub add_player: Option<&'a GameProfile>,
+    pub game_mode: Option<GameMode>,
+    /// Whether the player shows up in the tab list. Unlisted players are still known to the
+    /// client, e.g. to render their skin, but hidden like vanished players should be.
+    pub listed: Option<bool>,
+    /// In milliseconds, shown as the player's ping bars
+    pub latency: Option<i32>,
+    /// The name shown in the tab list, e.g. with a rank prefix. `Some(None)` goes back to the
+    /// player's username.
+    pub display_name: Option<Option<&'a TextComponent>>,
+}
+
+impl<'a> PlayerInfoEntry<'a> {
+    /// An entry that doesn't update anything yet
+    pub fn new(uuid: Uuid) -> Self {
+        Self {
+            uuid,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_player(mut self, profile: &'a GameProfile) -> Self {
+        self.add_player = Some(profile);
+        self
+    }
+
+    pub fn game_mode(mut self, game_mode: GameMode) -> Self {
+        self.game_mode = Some(game_mode);
+        self
+    }
+
+    pub fn listed(mut self, listed: bool) -> Self {
+        self.listed = Some(listed);
+        self
+    }
+
+    pub fn latency(mut self, latency_ms: i32) -> Self {
+        self.latency = Some(latency_ms);
+        self
+    }
+
+    pub fn display_name(mut self, display_name: Option<&'a TextComponent>) -> Self {
+        self.display_name = Some(display_name);
+        self
+    }
+}
+
 // TODO: OutPacket trait, and make each outpacket variant its own type
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum OutPacket<'a> {
+    StatusResponse {
+        /// The server list ping JSON
+        json: &'a str,
+    },
+    PingResponse {
+        payload: i64,
+    },
     // TODO: implement full 'JSON Chat' structure
     DisconnectLogin {
-        reason: &'a str,
+        reason: &'a TextComponent,
+    },
+    /// The reason is sent as NBT to 1.20.3+ clients, and as JSON to older ones
+    DisconnectConfig {
+        reason: &'a TextComponent,
+    },
+    /// Disconnect (play). The reason is sent as NBT to 1.20.3+ clients, and as JSON to older ones.
+    Disconnect {
+        reason: &'a TextComponent,
+    },
+    /// Packets in both directions are compressed from the next one on (see
+    /// `ServerConfig::compression_threshold`)
+    SetCompression {
+        threshold: i32,
     },
     LoginSuccess {
-        uuid: u128,
-        username: &'a str,
-        //TODO: what are these props for?
-        props: &'a [LoginSuccessProp<'a>],
+        profile: &'a GameProfile,
     },
     FinishConfig,
+    /// Plugin Message (configuration), e.g. the server's brand on `minecraft:brand`
+    PluginMessageConfig {
+        channel: &'a str,
+        data: &'a [u8],
+    },
+    /// Plugin Message (play)
+    PluginMessagePlay {
+        channel: &'a str,
+        data: &'a [u8],
+    },
+    /// The registry codec (dimension types, biomes, ...), as a compound of registries such as
+    /// `BiomeRegistry::to_registry_nbt()`
+    RegistryData {
+        codec: &'a CompoundNbt<'a>,
+    },
+    /// Update Tags (configuration)
+    UpdateTagsConfig {
+        registries: &'a [RegistryTags],
+    },
     LoginPlay {
         /// ID of the player entity
         entity_id: i32,
         is_hardcore: bool,
         // TODO: Identifier type?
         dimension_names: &'a [&'a str],
-        max_players: i64,
-        view_distance: i64,
-        simulation_distance: i64,
+        max_players: i32,
+        view_distance: i32,
+        simulation_distance: i32,
         reduced_debug_info: bool,
         enable_respawn_screen: bool,
         do_limited_crafting: bool,
@@ -169,7 +692,7 @@ pub enum OutPacket<'a> {
         is_debug: bool,
         is_superflat: bool,
         death_info: Option<DeathInfo<'a>>,
-        portal_cooldown: i64,
+        portal_cooldown: i32,
     },
     ChunkDataAndUpdateLight {
         chunk_x: i32,
@@ -184,6 +707,13 @@ pub enum OutPacket<'a> {
         sky_light_arrays: &'a [[i8; 2048]],
         block_light_arrays: &'a [[i8; 2048]],
     },
+    /// Sent before the chunks of a batch
+    ChunkBatchStart,
+    /// Sent after the chunks of a batch, which the client answers with Chunk Batch Received
+    ChunkBatchFinished {
+        /// How many chunks the batch had
+        batch_size: i32,
+    },
     SyncPlayerPos {
         x: f64,
         y: f64,
@@ -191,331 +721,2449 @@ pub enum OutPacket<'a> {
         yaw: f32,
         pitch: f32,
         flags: i8,
-        teleport_id: i64,
+        teleport_id: i32,
     },
-}
-
-#[derive(Debug, Copy, Clone)]
-enum State {
-    Handshaking,
-    Login,
-    Config,
-    Play,
-}
-
-// TODO: assert state is correct for each sent packet (e.g. LoginPlay cant be sent while in Config state)
-#[derive(Debug)]
-pub(crate) struct PacketStream<R: Read, W: Write> {
-    r: R,
-    w: W,
-    state: State,
-}
-
-impl<R: Read, W: Write> PacketStream<R, W> {
-    pub fn new(r: R, w: W) -> Self {
-        Self {
-            r,
-            w,
-            state: State::Handshaking,
-        }
-    }
-
-    pub fn next_packet(&mut self) -> InPacket {
-        let packet_len_field = read_varint(&mut self.r);
-        let (packid, packidnread) = read_varint_with_nread(&mut self.r);
-        let packet_tail_len = packet_len_field - packidnread;
-
-        match (packid, self.state) {
-            // Handshake
-            (0x00, State::Handshaking) => {
-                let protocol_version = read_varint(&mut self.r);
-                let server_addr = read_varint_string(&mut self.r);
-                let server_port = read_ushort(&mut self.r);
-                let next_state = match read_varint(&mut self.r) {
-                    1 => HandshakeNextState::Status,
-                    2 => HandshakeNextState::Login,
-                    x => panic!("bad next state {x}"),
-                };
-                self.state = State::Login;
-
-                InPacket::Handshake {
-                    protocol_version,
-                    server_addr,
-                    server_port,
-                    next_state,
-                }
-            }
-            // Login Start
-            (0x00, State::Login) => {
-                let name = read_varint_string(&mut self.r);
-                let player_uuid = read_uuid(&mut self.r);
-                InPacket::LoginStart { name, player_uuid }
-            }
-            // LoginAck
-            (0x03, State::Login) => {
-                self.state = State::Config;
-
-                InPacket::LoginAck
-            }
-            // PluginMessageConfig
-            (0x01, State::Config) => {
-                let (channel, strlen) = read_varint_string_with_nread(&mut self.r);
-                let data_len = packet_tail_len - strlen;
-                let mut data = vec![0; data_len.try_into().unwrap()];
-                self.r.read_exact(&mut data).unwrap();
-
-                InPacket::PluginMessageConfig { channel, data }
-            }
-            // ClientInfoConfig
-            (0x00, State::Config) => {
-                let locale = read_varint_string(&mut self.r);
-                let view_distance = read_byte(&mut self.r);
-                let chat_mode = match read_varint(&mut self.r) {
-                    0 => ChatMode::Enabled,
-                    1 => ChatMode::CommandsOnly,
-                    2 => ChatMode::Hidden,
-                    x => panic!("bad chat mode '{x}'"),
-                };
-                let chat_colors = read_bool(&mut self.r);
-                let displayed_skin_parts = read_ubyte(&mut self.r);
-                let main_hand = match read_varint(&mut self.r) {
-                    0 => MainHand::Left,
-                    1 => MainHand::Right,
-                    x => panic!("bad main hand '{x}'"),
-                };
-                let enable_text_filtering = read_bool(&mut self.r);
-                let allow_server_listings = read_bool(&mut self.r);
-
-                InPacket::ClientInfoConfig {
-                    locale,
-                    view_distance,
-                    chat_mode,
-                    chat_colors,
-                    allow_server_listings,
-                    enable_text_filtering,
-                    displayed_skin_parts,
-                    main_hand,
+    BossBar {
+        uuid: Uuid,
+        action: BossBarAction<'a>,
+    },
+    SystemChat {
+        content: &'a TextComponent,
+        /// display in the action bar instead of the chat
+        overlay: bool,
+    },
+    /// Player Chat Message: a chat message from a player. See `FilteredChat::packet()`.
+    PlayerChat {
+        sender: Uuid,
+        /// How many messages the sender has sent before this one
+        index: i32,
+        signature: Option<&'a [u8]>,
+        message: &'a str,
+        timestamp: i64,
+        salt: i64,
+        /// Shown instead of `message`, if set
+        unsigned_content: Option<&'a TextComponent>,
+        /// What players with text filtering on see of the message
+        filter: &'a FilterMask,
+        /// ID in the `minecraft:chat_type` registry; 0 is `minecraft:chat` in vanilla's
+        chat_type: i32,
+        sender_name: &'a TextComponent,
+        target_name: Option<&'a TextComponent>,
+    },
+    SetActionBarText {
+        text: &'a TextComponent,
+    },
+    /// Sets up the world border. See `WorldBorder`.
+    InitializeWorldBorder {
+        x: f64,
+        z: f64,
+        old_diameter: f64,
+        new_diameter: f64,
+        /// How long going from `old_diameter` to `new_diameter` takes, in milliseconds
+        speed: i64,
+        /// How far from the center the other end of a nether portal may be (usually 29999984)
+        portal_teleport_boundary: i32,
+        warning_blocks: i32,
+        /// In seconds
+        warning_time: i32,
+    },
+    SetBorderCenter {
+        x: f64,
+        z: f64,
+    },
+    /// Resizes the world border over `speed` milliseconds
+    SetBorderLerpSize {
+        old_diameter: f64,
+        new_diameter: f64,
+        speed: i64,
+    },
+    SetBorderSize {
+        diameter: f64,
+    },
+    /// Makes the screen go red when the border will reach the player within this many seconds
+    SetBorderWarningDelay {
+        warning_time: i32,
+    },
+    /// Makes the screen go red within this many blocks of the border
+    SetBorderWarningDistance {
+        warning_blocks: i32,
+    },
+    /// The view distance, in chunks, that the client should render out to
+    SetRenderDistance {
+        view_distance: i32,
+    },
+    /// The distance, in chunks, that the client should simulate out to
+    SetSimulationDistance {
+        simulation_distance: i32,
+    },
+    /// The chunk the player is in, around which the client keeps chunks loaded
+    SetCenterChunk {
+        chunk_x: i32,
+        chunk_z: i32,
+    },
+    /// A change to the game state: e.g. 1 = end raining, 3 = change game mode (`value` is the
+    /// mode), 11 = respawn immediately (1) or show the respawn screen (0), 13 = start waiting for
+    /// chunks (the loading screen after a Respawn)
+    GameEvent {
+        event: u8,
+        value: f32,
+    },
+    /// The world's age and time of day, in ticks. A negative time of day stops the client's sun
+    /// from moving on its own (see the `doDaylightCycle` game rule).
+    UpdateTime {
+        world_age: i64,
+        time_of_day: i64,
+    },
+    SetTitleText {
+        text: &'a TextComponent,
+    },
+    SetSubtitleText {
+        text: &'a TextComponent,
+    },
+    /// All times are in ticks
+    SetTitleAnimationTimes {
+        fade_in: i32,
+        stay: i32,
+        fade_out: i32,
+    },
+    ClearTitles {
+        /// also reset the animation times to the defaults
+        reset: bool,
+    },
+    SetEntityMetadata {
+        entity_id: i32,
+        metadata: &'a [MetadataEntry<'a>],
+    },
+    SpawnEntity {
+        entity_id: i32,
+        uuid: Uuid,
+        entity_type: EntityType,
+        x: f64,
+        y: f64,
+        z: f64,
+        pitch: Angle,
+        yaw: Angle,
+        head_yaw: Angle,
+        /// meaning depends on the entity type
+        data: i32,
+        /// in 1/8000ths of a block per tick
+        velocity: [i16; 3],
+    },
+    SetEntityVelocity {
+        entity_id: i32,
+        /// in 1/8000ths of a block per tick
+        velocity: [i16; 3],
+    },
+    RemoveEntities {
+        entity_ids: &'a [i32],
+    },
+    UpdateEntityRotation {
+        entity_id: i32,
+        yaw: Angle,
+        pitch: Angle,
+        on_ground: bool,
+    },
+    SetHeadRotation {
+        entity_id: i32,
+        head_yaw: Angle,
+    },
+    /// Moves an entity by less than 8 blocks on each axis
+    UpdateEntityPosition {
+        entity_id: i32,
+        /// in 1/4096ths of a block
+        delta: [i16; 3],
+        on_ground: bool,
+    },
+    UpdateEntityPositionAndRotation {
+        entity_id: i32,
+        /// in 1/4096ths of a block
+        delta: [i16; 3],
+        yaw: Angle,
+        pitch: Angle,
+        on_ground: bool,
+    },
+    /// Moves an entity any distance
+    TeleportEntity {
+        entity_id: i32,
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: Angle,
+        pitch: Angle,
+        on_ground: bool,
+    },
+    /// What an entity is holding and wearing. Slots that aren't listed are left as they were.
+    SetEquipment {
+        entity_id: i32,
+        equipment: &'a [(EquipmentSlot, Option<Slot<'a>>)],
+    },
+    /// Changes several blocks in one chunk section at once
+    UpdateSectionBlocks {
+        section: SectionPos,
+        /// Positions (which must be in `section`) and their new block states
+        blocks: &'a [(BlockPos, i32)],
+    },
+    PlayerInfoUpdate {
+        /// All entries must have the same set of fields filled in
+        entries: &'a [PlayerInfoEntry<'a>],
+    },
+    /// Triggers an entity-specific effect/animation on the client
+    EntityEvent {
+        entity_id: i32,
+        status: i8,
+    },
+    /// Leashes `attached` to `holder`
+    LinkEntities {
+        attached: i32,
+        /// -1 to unleash
+        holder: i32,
+    },
+    /// Who's riding `vehicle`, replacing whoever was before. The first passenger steers.
+    SetPassengers {
+        vehicle: i32,
+        passengers: &'a [i32],
+    },
+    /// Asks the client to reply with a Pong with the same ID, after handling the packets sent before it
+    Ping {
+        id: i32,
+    },
+    /// Clients that get none of these for a while disconnect. The reply has the same ID.
+    KeepAlive {
+        id: i64,
+    },
+    /// Keep Alive (configuration)
+    KeepAliveConfig {
+        id: i64,
+    },
+    /// Ping (configuration): asks for a Pong with the same ID
+    PingConfig {
+        id: i32,
+    },
+    /// The commands players can run, for the client to suggest and highlight
+    Commands {
+        tree: &'a CommandTree,
+    },
+    /// Opens the written book in the player's hand. See `WrittenBook`.
+    OpenBook {
+        hand: Hand,
+    },
+    /// Moves the vehicle the player is driving, e.g. to correct where the client moved it to
+    MoveVehicle {
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: f32,
+        pitch: f32,
+    },
+    /// Shows an objective in a display slot (0 = list, 1 = sidebar, 2 = below name)
+    DisplayObjective {
+        slot: i32,
+        /// empty to clear the slot
+        objective: &'a str,
+    },
+    UpdateObjectives {
+        name: &'a str,
+        action: ObjectiveAction<'a>,
+    },
+    UpdateScore {
+        holder: &'a str,
+        objective: &'a str,
+        /// None removes the score
+        value: Option<i32>,
+    },
+    SetContainerSlot {
+        /// 0 for the player's inventory, -1 for the item held by the cursor
+        window_id: i8,
+        state_id: i32,
+        slot: i16,
+        /// None empties the slot
+        item: Option<Slot<'a>>,
+    },
+    /// Update Tags (play), e.g. after reloading data packs
+    UpdateTags {
+        registries: &'a [RegistryTags],
+    },
+    /// Every recipe there is. Which ones the player has unlocked is up to `UpdateRecipeBook`.
+    UpdateRecipes {
+        recipes: &'a [Recipe],
+        /// For the IDs of the items in the recipes
+        items: &'a ItemRegistry,
+        /// For the items in the tags the recipes use
+        tags: &'a TagRegistry,
+    },
+    UpdateRecipeBook {
+        action: RecipeBookAction<'a>,
+        settings: RecipeBookSettings,
+    },
+    EntityEffect {
+        entity_id: i32,
+        effect: ActiveEffect,
+    },
+    /// The trades in an open merchant screen
+    MerchantOffers {
+        window_id: i32,
+        offers: &'a [MerchantOffer<'a>],
+        /// 1 (novice) to 5 (master)
+        villager_level: i32,
+        /// Shown in the villager's level bar
+        experience: i32,
+        /// False hides the level bar, like for wandering traders
+        is_regular_villager: bool,
+        /// Shows the "out of stock" tooltip
+        can_restock: bool,
+    },
+    MapData {
+        map_id: i32,
+        /// 0 (1 block per pixel) to 4 (16 blocks per pixel)
+        scale: i8,
+        /// Locked in a cartography table
+        locked: bool,
+        /// None leaves the icons as they are
+        icons: Option<&'a [MapIcon]>,
+        /// None if no pixels changed
+        patch: Option<&'a MapPatch>,
+    },
+    RemoveEntityEffect {
+        entity_id: i32,
+        effect: Effect,
+    },
+    SetExperience {
+        /// Progress towards the next level, from 0.0 to 1.0
+        bar: f32,
+        level: i32,
+        total: i32,
+    },
+    /// The difficulty shown in the options menu, which is greyed out if `locked`
+    ChangeDifficulty {
+        difficulty: Difficulty,
+        locked: bool,
+    },
+    SetHealth {
+        /// 0 or less shows the death screen (once there's a `CombatDeath`)
+        health: f32,
+        /// 0 to 20
+        food: i32,
+        saturation: f32,
+    },
+    /// Player Combat Kill: the player died, and gets the death screen with `message`
+    CombatDeath {
+        player_id: i32,
+        message: &'a TextComponent,
+    },
+    /// Unused by the vanilla client
+    EnterCombat,
+    /// Unused by the vanilla client
+    EndCombat {
+        /// How long the combat lasted, in ticks
+        duration: i32,
+    },
+    /// Moves the player to a new world (or the same one, after dying), resetting its entities and
+    /// chunks
+    Respawn {
+        dimension_type: &'a str,
+        dimension_name: &'a str,
+        hashed_seed: i64,
+        game_mode: GameMode,
+        prev_game_mode: Option<GameMode>,
+        is_debug: bool,
+        is_superflat: bool,
+        death_info: Option<DeathInfo<'a>>,
+        portal_cooldown: i32,
+        /// 0x01 = keep attributes, 0x02 = keep entity metadata
+        data_kept: i8,
+    },
+    UpdateAdvancements {
+        /// Clear all of the client's advancements first
+        reset: bool,
+        /// (ID, advancement)
+        added: &'a [(&'a str, &'a Advancement)],
+        removed: &'a [&'a str],
+        /// (advancement ID, progress)
+        progress: &'a [(&'a str, &'a AdvancementProgress)],
+    },
+    /// A packet libmc doesn't have a variant for, sent as is. `id` is its 1.20.2 ID in the
+    /// connection's current state; older clients get it translated like any other packet.
+    Raw {
+        id: i32,
+        /// The packet's body, after the ID
+        payload: &'a [u8],
+    },
+}
+
+/// Which set of packets is in use on a connection
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ProtocolState {
+    Handshaking,
+    Status,
+    Login,
+    Config,
+    Play,
+}
+
+/// The protocol version of 1.20.2, which libmc speaks
+pub const PROTOCOL_VERSION: i32 = 764;
+
+/// The Minecraft version that `PROTOCOL_VERSION` belongs to
+pub const MINECRAFT_VERSION: &str = "1.20.2";
+
+// TODO: assert state is correct for each sent packet (e.g. LoginPlay cant be sent while in Config state)
+#[derive(Debug)]
+pub(crate) struct PacketStream<W: Write> {
+    w: W,
+    state: ProtocolState,
+    /// The client's protocol version, from its handshake
+    protocol_version: i32,
+    /// Rewrites packets between the client's protocol version and ours
+    translator: Option<Box<dyn ProtocolTranslator>>,
+    /// Frames the translator made up for the client, to be handled as if the client had sent them
+    injected: Vec<Vec<u8>>,
+    /// Set when a write fails, since part of a frame may have been written
+    broken: bool,
+    capture: Option<PacketCapture>,
+    /// Why the capture was stopped, until `take_capture_error()`
+    capture_error: Option<std::io::Error>,
+    interceptors: Vec<Box<dyn PacketInterceptor>>,
+    counters: PacketCounters,
+    /// Packets at least this long are compressed, once Set Compression has been sent
+    compression: Option<usize>,
+}
+
+impl<W: Write> PacketStream<W> {
+    pub fn new(w: W) -> Self {
+        Self {
+            w,
+            state: ProtocolState::Handshaking,
+            protocol_version: PROTOCOL_VERSION,
+            translator: None,
+            injected: Vec::new(),
+            broken: false,
+            capture: None,
+            capture_error: None,
+            interceptors: Vec::new(),
+            counters: PacketCounters::new(),
+            compression: None,
+        }
+    }
+
+    /// Decodes a whole frame (including its length prefix), borrowing strings and byte arrays from it
+    pub fn decode<'a>(&mut self, frame: &'a [u8]) -> Result<InPacket<'a>, DecodeError> {
+        let mut r = PacketReader { buf: frame };
+        // the frame holds exactly one packet, so its length isn't needed
+        r.varint()?;
+        let packet = r.rest();
+        self.counters
+            .record(PacketDirection::Serverbound, self.state, packet);
+        decode_and_advance(&mut self.state, &mut self.protocol_version, packet)
+    }
+
+    /// Returns how many bytes were written, including the length prefix.
+    ///
+    /// The frame is written with a single `write_all()`. If that fails, part of it may have been
+    /// written, so every later send fails too instead of writing packets after a partial frame.
+    pub fn send(&mut self, packet: OutPacket) -> std::io::Result<usize> {
+        self.check_broken()?;
+        let frame = self.encode(packet);
+        if frame.is_empty() {
+            return Ok(0);
+        }
+        if let Err(e) = self.w.write_all(&frame) {
+            self.broken = true;
+            return Err(e);
+        }
+        Ok(frame.len())
+    }
+
+    /// Sends several packets with one vectored write, e.g. the burst of packets when a player joins.
+    /// Returns how many bytes each packet took. Write errors are handled like in `send()`.
+    pub fn send_all<'a>(
+        &mut self,
+        packets: impl IntoIterator<Item = OutPacket<'a>>,
+    ) -> std::io::Result<Vec<usize>> {
+        self.check_broken()?;
+        let frames: Vec<PooledBuf> = packets.into_iter().map(|p| self.encode(p)).collect();
+        let mut slices: Vec<IoSlice> = frames
+            .iter()
+            .filter(|f| !f.is_empty())
+            .map(|f| IoSlice::new(f))
+            .collect();
+        if let Err(e) = write_all_vectored(&mut self.w, &mut slices) {
+            self.broken = true;
+            return Err(e);
+        }
+        Ok(frames.iter().map(|f| f.len()).collect())
+    }
+
+    fn check_broken(&self) -> std::io::Result<()> {
+        if self.broken {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "an earlier packet was only partially written",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Encodes `packet` as the frames to write (which are none if the translator drops it)
+    fn encode(&mut self, packet: OutPacket) -> PooledBuf {
+        let mut buf = PooledBuf::take();
+        encode_packet_into(packet, self.protocol_version, &mut buf);
+        self.frames_for(&buf)
+    }
+
+    /// Sends a packet encoded with `encode_packet()` for this client's protocol version,
+    /// so that packets sent to many clients only need to be encoded once
+    pub fn send_encoded(&mut self, packet: &[u8]) -> std::io::Result<usize> {
+        self.check_broken()?;
+        let frame = self.frames_for(packet);
+        if frame.is_empty() {
+            return Ok(0);
+        }
+        if let Err(e) = self.w.write_all(&frame) {
+            self.broken = true;
+            return Err(e);
+        }
+        Ok(frame.len())
+    }
+
+    /// Intercepts, translates (if needed) and frames an encoded packet
+    fn frames_for(&mut self, packet: &[u8]) -> PooledBuf {
+        let mut frames = PooledBuf::take();
+        let mut intercepted;
+        let mut packet = packet;
+        if !self.interceptors.is_empty() {
+            intercepted = PooledBuf::take();
+            intercepted.extend_from_slice(packet);
+            let dir = PacketDirection::Clientbound;
+            if !intercept(&mut self.interceptors, dir, self.state, &mut intercepted) {
+                return frames;
+            }
+            packet = &intercepted;
+        }
+        self.counters
+            .record(PacketDirection::Clientbound, self.state, packet);
+        let compression = self.compression;
+        match &mut self.translator {
+            None => {
+                self.capture(PacketDirection::Clientbound, packet);
+                frame_compressed_into(packet, compression, &mut frames);
+            }
+            Some(t) => {
+                let (mut out, mut reply) = (Vec::new(), Vec::new());
+                t.clientbound(self.state, packet, &mut out, &mut reply);
+                self.injected.extend(reply.iter().map(|p| frame_packet(p)));
+                for p in &out {
+                    self.capture(PacketDirection::Clientbound, p);
+                    frame_compressed_into(p, compression, &mut frames);
+                }
+            }
+        }
+        frames
+    }
+
+    /// Compresses packets at least `threshold` long in both directions from now on, or stops.
+    /// Set Compression has to have been sent (uncompressed) first.
+    pub fn set_compression(&mut self, threshold: Option<usize>) {
+        self.compression = threshold;
+    }
+
+    /// Records every packet sent and received from now on, or stops recording
+    pub fn set_capture(&mut self, capture: Option<PacketCapture>) {
+        self.capture = capture;
+    }
+
+    fn capture(&mut self, dir: PacketDirection, packet: &[u8]) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+        if let Err(e) = capture.record(dir, self.state, packet) {
+            self.capture = None;
+            self.capture_error = Some(e);
+        }
+    }
+
+    /// Why recording the capture failed, if it did since the last call. It's stopped once it fails.
+    pub fn take_capture_error(&mut self) -> Option<std::io::Error> {
+        self.capture_error.take()
+    }
+
+    pub fn state(&self) -> ProtocolState {
+        self.state
+    }
+
+    /// The packets sent and received so far, in our protocol version
+    pub fn counters(&self) -> &PacketCounters {
+        &self.counters
+    }
+
+    /// The protocol version of the client, from its handshake
+    pub fn protocol_version(&self) -> i32 {
+        self.protocol_version
+    }
+
+    /// Translates packets to and from the client from now on
+    pub fn set_translator(&mut self, translator: Box<dyn ProtocolTranslator>) {
+        self.translator = Some(translator);
+    }
+
+    /// Runs every packet sent and received from now on through `interceptor`, after the ones
+    /// already added
+    pub fn add_interceptor(&mut self, interceptor: Box<dyn PacketInterceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    /// Translates a frame received from the client into zero or more frames in our protocol
+    /// version, and runs them through the interceptors. The frames returned are never compressed.
+    /// Fails if the frame can't be decompressed, or the translator can't parse it.
+    pub fn translate_incoming(&mut self, frame: Vec<u8>) -> Result<Vec<Vec<u8>>, DecodeError> {
+        let mut r = PacketReader::new(&frame);
+        r.varint()?;
+        let decompressed;
+        let packet = match self.compression {
+            None => r.rest(),
+            Some(threshold) => {
+                decompressed = decompress_packet(r.rest(), threshold)?;
+                &decompressed[..]
+            }
+        };
+        self.capture(PacketDirection::Serverbound, packet);
+        let mut out = Vec::new();
+        match &mut self.translator {
+            None if self.interceptors.is_empty() && self.compression.is_none() => {
+                return Ok(vec![frame])
+            }
+            None => out.push(packet.to_vec()),
+            Some(t) => t.serverbound(self.state, packet, &mut out)?,
+        }
+        let dir = PacketDirection::Serverbound;
+        out.retain_mut(|p| intercept(&mut self.interceptors, dir, self.state, p));
+        Ok(out.iter().map(|p| frame_packet(p)).collect())
+    }
+
+    /// Frames the translator made up for the client since the last call
+    pub fn take_injected(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.injected)
+    }
+
+    /// The writer that outgoing packets are written to
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.w
+    }
+
+    pub fn into_writer(self) -> W {
+        self.w
+    }
+}
+
+/// Why a packet couldn't be decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The packet ended in the middle of a field
+    UnexpectedEnd,
+    /// A varint longer than 5 bytes
+    VarIntTooLong,
+    /// A string that isn't UTF-8
+    InvalidString,
+    /// A field with a value it can't have, e.g. an unknown enum variant
+    BadValue { field: &'static str, value: i32 },
+    /// A compressed packet that doesn't decompress to the size it says it has
+    BadCompression,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of packet"),
+            DecodeError::VarIntTooLong => write!(f, "varint too long"),
+            DecodeError::InvalidString => write!(f, "string isn't valid UTF-8"),
+            DecodeError::BadValue { field, value } => write!(f, "bad {field} '{value}'"),
+            DecodeError::BadCompression => write!(f, "bad compressed packet"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Reads the fields of a serverbound packet. Unlike the `read_*()` functions, it returns errors
+/// instead of panicking, since clients can send anything.
+///
+/// Servers can use it to decode the `data` of `InPacket::Unknown`s themselves.
+#[derive(Debug, Clone)]
+pub struct PacketReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Whether everything has been read
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn slice(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if n > self.buf.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (slice, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(slice)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.slice(N)?.try_into().unwrap())
+    }
+
+    /// Everything that's left
+    pub fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+
+    pub fn varint(&mut self) -> Result<i32, DecodeError> {
+        let (VarInt(value), len) = VarInt::decode(self.buf)?;
+        self.buf = &self.buf[len..];
+        Ok(value)
+    }
+
+    pub fn str(&mut self) -> Result<&'a str, DecodeError> {
+        let len = self.varint()?;
+        let len = len.try_into().map_err(|_| DecodeError::BadValue {
+            field: "string length",
+            value: len,
+        })?;
+        // TODO: convert from Java's "Modified UTF-8" :(
+        std::str::from_utf8(self.slice(len)?).map_err(|_| DecodeError::InvalidString)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, DecodeError> {
+        match self.ubyte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            x => Err(DecodeError::BadValue {
+                field: "bool",
+                value: x.into(),
+            }),
+        }
+    }
+
+    pub fn ubyte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn byte(&mut self) -> Result<i8, DecodeError> {
+        Ok(i8::from_be_bytes(self.array()?))
+    }
+
+    pub fn ushort(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn short(&mut self) -> Result<i16, DecodeError> {
+        Ok(i16::from_be_bytes(self.array()?))
+    }
+
+    pub fn int(&mut self) -> Result<i32, DecodeError> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    pub fn long(&mut self) -> Result<i64, DecodeError> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    pub fn float(&mut self) -> Result<f32, DecodeError> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+
+    pub fn double(&mut self) -> Result<f64, DecodeError> {
+        Ok(f64::from_be_bytes(self.array()?))
+    }
+
+    pub fn uuid(&mut self) -> Result<Uuid, DecodeError> {
+        Ok(Uuid(u128::from_be_bytes(self.array()?)))
+    }
+
+    pub fn position(&mut self) -> Result<Position, DecodeError> {
+        Ok(read_position(&mut &self.array::<8>()?[..]))
+    }
+
+    /// Only for slots at the end of a packet, since the NBT is taken to be the rest of it
+    pub fn slot(&mut self) -> Result<Option<Slot<'a>>, DecodeError> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        let item_id = self.varint()?;
+        let count = self.byte()?;
+        let nbt = self.rest();
+        Ok(Some(Slot {
+            item_id,
+            count,
+            // TAG_End for no NBT
+            nbt: (nbt.first().is_some_and(|&t| t != 0x00)).then_some(nbt),
+        }))
+    }
+
+    pub fn hand(&mut self) -> Result<Hand, DecodeError> {
+        match self.varint()? {
+            0 => Ok(Hand::Main),
+            1 => Ok(Hand::Off),
+            x => Err(DecodeError::BadValue {
+                field: "hand",
+                value: x,
+            }),
+        }
+    }
+}
+
+/// Decodes a serverbound packet (its ID and body, without the length prefix) that was received in
+/// `state`. Works on any bytes without panicking, e.g. for fuzzing.
+pub fn decode_packet(mut state: ProtocolState, packet: &[u8]) -> Result<InPacket<'_>, DecodeError> {
+    let mut protocol_version = PROTOCOL_VERSION;
+    decode_and_advance(&mut state, &mut protocol_version, packet)
+}
+
+/// Like `decode_packet()`, but also updates the connection's state and protocol version
+fn decode_and_advance<'a>(
+    state: &mut ProtocolState,
+    protocol_version: &mut i32,
+    packet: &'a [u8],
+) -> Result<InPacket<'a>, DecodeError> {
+    let mut r = PacketReader { buf: packet };
+    let packid = r.varint()?;
+
+    let packet = match (packid, *state) {
+        // Handshake
+        (0x00, ProtocolState::Handshaking) => {
+            let version = r.varint()?;
+            let server_addr = r.str()?;
+            let server_port = r.ushort()?;
+            let next_state = match r.varint()? {
+                1 => HandshakeNextState::Status,
+                2 => HandshakeNextState::Login,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "next state",
+                        value: x,
+                    })
+                }
+            };
+            *state = match next_state {
+                HandshakeNextState::Status => ProtocolState::Status,
+                HandshakeNextState::Login => ProtocolState::Login,
+            };
+            *protocol_version = version;
+
+            InPacket::Handshake {
+                protocol_version: version,
+                server_addr,
+                server_port,
+                next_state,
+            }
+        }
+        // StatusRequest
+        (0x00, ProtocolState::Status) => InPacket::StatusRequest,
+        // PingRequest
+        (0x01, ProtocolState::Status) => InPacket::PingRequest { payload: r.long()? },
+        // Login Start
+        (0x00, ProtocolState::Login) => {
+            let name = r.str()?;
+            let player_uuid = r.uuid()?;
+            InPacket::LoginStart { name, player_uuid }
+        }
+        // LoginAck
+        (0x03, ProtocolState::Login) => {
+            *state = ProtocolState::Config;
+
+            InPacket::LoginAck
+        }
+        // PluginMessageConfig
+        (0x01, ProtocolState::Config) => {
+            let channel = r.str()?;
+            // the rest of the packet
+            let data = r.rest();
+
+            InPacket::PluginMessageConfig { channel, data }
+        }
+        // PluginMessagePlay
+        (0x0F, ProtocolState::Play) => {
+            let channel = r.str()?;
+            let data = r.rest();
+
+            InPacket::PluginMessagePlay { channel, data }
+        }
+        // ClientInfoConfig, ClientInfoPlay
+        (0x00, ProtocolState::Config) | (0x09, ProtocolState::Play) => {
+            let locale = r.str()?;
+            let view_distance = r.byte()?;
+            let chat_mode = match r.varint()? {
+                0 => ChatMode::Enabled,
+                1 => ChatMode::CommandsOnly,
+                2 => ChatMode::Hidden,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "chat mode",
+                        value: x,
+                    })
+                }
+            };
+            let chat_colors = r.bool()?;
+            let displayed_skin_parts = SkinParts::from_bits(r.ubyte()?);
+            let main_hand = match r.varint()? {
+                0 => MainHand::Left,
+                1 => MainHand::Right,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "main hand",
+                        value: x,
+                    })
+                }
+            };
+            let enable_text_filtering = r.bool()?;
+            let allow_server_listings = r.bool()?;
+
+            if *state == ProtocolState::Config {
+                InPacket::ClientInfoConfig {
+                    locale,
+                    view_distance,
+                    chat_mode,
+                    chat_colors,
+                    allow_server_listings,
+                    enable_text_filtering,
+                    displayed_skin_parts,
+                    main_hand,
+                }
+            } else {
+                InPacket::ClientInfoPlay {
+                    locale,
+                    view_distance,
+                    chat_mode,
+                    chat_colors,
+                    allow_server_listings,
+                    enable_text_filtering,
+                    displayed_skin_parts,
+                    main_hand,
+                }
+            }
+        }
+        (0x02, ProtocolState::Config) => {
+            *state = ProtocolState::Play;
+
+            InPacket::FinishConfig
+        }
+        (0x03, ProtocolState::Config) => InPacket::KeepAliveConfig { id: r.long()? },
+        (0x04, ProtocolState::Config) => InPacket::PongConfig { id: r.int()? },
+        // Acknowledge Configuration
+        (0x0B, ProtocolState::Play) => {
+            *state = ProtocolState::Config;
+
+            InPacket::ConfigAck
+        }
+        // ChatCommand
+        (0x04, ProtocolState::Play) => {
+            let command = r.str()?;
+            let timestamp = r.long()?;
+            let salt = r.long()?;
+            // TODO: argument signatures and message acknowledgements
+
+            InPacket::ChatCommand {
+                command,
+                timestamp,
+                salt,
+            }
+        }
+        // ChatMessage
+        (0x05, ProtocolState::Play) => {
+            let message = r.str()?;
+            let timestamp = r.long()?;
+            let salt = r.long()?;
+            let signature = if r.bool()? { Some(r.slice(256)?) } else { None };
+            // TODO: message acknowledgements
+
+            InPacket::ChatMessage {
+                message,
+                timestamp,
+                salt,
+                signature,
+            }
+        }
+        // PlayerAction
+        (0x20, ProtocolState::Play) => {
+            let status = match r.varint()? {
+                0 => PlayerActionStatus::StartedDigging,
+                1 => PlayerActionStatus::CancelledDigging,
+                2 => PlayerActionStatus::FinishedDigging,
+                3 => PlayerActionStatus::DropItemStack,
+                4 => PlayerActionStatus::DropItem,
+                5 => PlayerActionStatus::ShootArrow,
+                6 => PlayerActionStatus::SwapItemInHand,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "player action status",
+                        value: x,
+                    })
+                }
+            };
+            let location = r.position()?;
+            let face = r.byte()?;
+            let sequence = r.varint()?;
+
+            InPacket::PlayerAction {
+                status,
+                location,
+                face,
+                sequence,
+            }
+        }
+        // PlayerCommand
+        (0x21, ProtocolState::Play) => {
+            let entity_id = r.varint()?;
+            let action = match r.varint()? {
+                0 => PlayerCommandAction::StartSneaking,
+                1 => PlayerCommandAction::StopSneaking,
+                2 => PlayerCommandAction::LeaveBed,
+                3 => PlayerCommandAction::StartSprinting,
+                4 => PlayerCommandAction::StopSprinting,
+                5 => PlayerCommandAction::StartHorseJump,
+                6 => PlayerCommandAction::StopHorseJump,
+                7 => PlayerCommandAction::OpenVehicleInventory,
+                8 => PlayerCommandAction::StartFlyingWithElytra,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "player command action",
+                        value: x,
+                    })
+                }
+            };
+            let jump_boost = r.varint()?;
+
+            InPacket::PlayerCommand {
+                entity_id,
+                action,
+                jump_boost,
+            }
+        }
+        // SetPlayerPosition
+        (0x16, ProtocolState::Play) => {
+            let x = r.double()?;
+            let y = r.double()?;
+            let z = r.double()?;
+            let on_ground = r.bool()?;
+
+            InPacket::SetPlayerPosition { x, y, z, on_ground }
+        }
+        // SetPlayerPositionAndRotation
+        (0x17, ProtocolState::Play) => {
+            let x = r.double()?;
+            let y = r.double()?;
+            let z = r.double()?;
+            let yaw = r.float()?;
+            let pitch = r.float()?;
+            let on_ground = r.bool()?;
+
+            InPacket::SetPlayerPositionAndRotation {
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+                on_ground,
+            }
+        }
+        // SetPlayerRotation
+        (0x18, ProtocolState::Play) => {
+            let yaw = r.float()?;
+            let pitch = r.float()?;
+            let on_ground = r.bool()?;
+
+            InPacket::SetPlayerRotation {
+                yaw,
+                pitch,
+                on_ground,
+            }
+        }
+        // SetPlayerOnGround
+        (0x19, ProtocolState::Play) => InPacket::SetPlayerOnGround {
+            on_ground: r.bool()?,
+        },
+        (0x07, ProtocolState::Play) => InPacket::ChunkBatchReceived {
+            chunks_per_tick: r.float()?,
+        },
+        // Interact
+        (0x12, ProtocolState::Play) => {
+            let entity_id = r.varint()?;
+            let action = match r.varint()? {
+                0 => InteractAction::Interact(r.hand()?),
+                1 => InteractAction::Attack,
+                2 => {
+                    let x = r.float()?;
+                    let y = r.float()?;
+                    let z = r.float()?;
+                    let hand = r.hand()?;
+                    InteractAction::InteractAt { x, y, z, hand }
+                }
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "interact type",
+                        value: x,
+                    })
+                }
+            };
+            let sneaking = r.bool()?;
+
+            InPacket::Interact {
+                entity_id,
+                action,
+                sneaking,
+            }
+        }
+        // UseItem
+        (0x33, ProtocolState::Play) => InPacket::TeleportToEntity { target: r.uuid()? },
+        (0x35, ProtocolState::Play) => {
+            let hand = r.hand()?;
+            let sequence = r.varint()?;
+
+            InPacket::UseItem { hand, sequence }
+        }
+        // UseItemOn
+        (0x34, ProtocolState::Play) => {
+            let hand = r.hand()?;
+            let location = r.position()?;
+            let face = r.varint()?;
+            let face = face.try_into().map_err(|_| DecodeError::BadValue {
+                field: "face",
+                value: face,
+            })?;
+            let cursor = [r.float()?, r.float()?, r.float()?];
+            let inside_block = r.bool()?;
+            let sequence = r.varint()?;
+
+            InPacket::UseItemOn {
+                hand,
+                location,
+                face,
+                cursor,
+                inside_block,
+                sequence,
+            }
+        }
+        // PlayerInput
+        (0x22, ProtocolState::Play) => {
+            let sideways = r.float()?;
+            let forward = r.float()?;
+            let flags = r.ubyte()?;
+
+            InPacket::PlayerInput {
+                sideways,
+                forward,
+                flags,
+            }
+        }
+        (0x1A, ProtocolState::Play) => {
+            let x = r.double()?;
+            let y = r.double()?;
+            let z = r.double()?;
+            let yaw = r.float()?;
+            let pitch = r.float()?;
+
+            InPacket::MoveVehicle {
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+            }
+        }
+        (0x1B, ProtocolState::Play) => {
+            let left = r.bool()?;
+            let right = r.bool()?;
+
+            InPacket::PaddleBoat { left, right }
+        }
+        (0x23, ProtocolState::Play) => InPacket::Pong { id: r.int()? },
+        (0x14, ProtocolState::Play) => InPacket::KeepAlive { id: r.long()? },
+        (0x1E, ProtocolState::Play) => {
+            let window_id = r.byte()?;
+            let recipe = r.str()?;
+            let make_all = r.bool()?;
+
+            InPacket::PlaceRecipe {
+                window_id,
+                recipe,
+                make_all,
+            }
+        }
+        (0x24, ProtocolState::Play) => {
+            let book = match r.varint()? {
+                0 => RecipeBookType::Crafting,
+                1 => RecipeBookType::Furnace,
+                2 => RecipeBookType::BlastFurnace,
+                3 => RecipeBookType::Smoker,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "recipe book",
+                        value: x,
+                    })
+                }
+            };
+            let open = r.bool()?;
+            let filtering = r.bool()?;
+
+            InPacket::ChangeRecipeBookSettings {
+                book,
+                state: RecipeBookState { open, filtering },
+            }
+        }
+        (0x25, ProtocolState::Play) => InPacket::SetSeenRecipe { recipe: r.str()? },
+        (0x08, ProtocolState::Play) => {
+            let action = match r.varint()? {
+                0 => ClientStatusAction::PerformRespawn,
+                1 => ClientStatusAction::RequestStats,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "client status action",
+                        value: x,
+                    })
+                }
+            };
+
+            InPacket::ClientStatus { action }
+        }
+        (0x02, ProtocolState::Play) => {
+            let difficulty = match r.ubyte()? {
+                0 => Difficulty::Peaceful,
+                1 => Difficulty::Easy,
+                2 => Difficulty::Normal,
+                3 => Difficulty::Hard,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "difficulty",
+                        value: x.into(),
+                    })
+                }
+            };
+
+            InPacket::ChangeDifficulty { difficulty }
+        }
+        (0x15, ProtocolState::Play) => InPacket::LockDifficulty { locked: r.bool()? },
+        (0x29, ProtocolState::Play) => InPacket::SelectTrade { slot: r.varint()? },
+        (0x28, ProtocolState::Play) => {
+            let tab = match r.varint()? {
+                0 => Some(r.str()?),
+                1 => None,
+                x => {
+                    return Err(DecodeError::BadValue {
+                        field: "seen advancements action",
+                        value: x,
+                    })
+                }
+            };
+
+            InPacket::SeenAdvancements { tab }
+        }
+        (0x2E, ProtocolState::Play) => {
+            let slot = r.short()?;
+            let item = r.slot()?;
+
+            InPacket::SetCreativeModeSlot { slot, item }
+        }
+        _ => InPacket::Unknown {
+            id: packid,
+            state: *state,
+            data: r.rest(),
+        },
+    };
+    Ok(packet)
+}
+
+/// Encodes `packet` (its ID and body) as `protocol_version` expects it
+pub(crate) fn encode_packet(packet: OutPacket, protocol_version: i32) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    encode_packet_into(packet, protocol_version, &mut encoded);
+    encoded
+}
+
+/// Like `encode_packet()`, appending to `buf` (e.g. a `PooledBuf`) instead of a new Vec
+pub(crate) fn encode_packet_into(packet: OutPacket, protocol_version: i32, buf: &mut Vec<u8>) {
+    match packet {
+        OutPacket::StatusResponse { json } => {
+            // packet ID:
+            write_varint(buf, 0x00);
+
+            write_string(buf, json);
+        }
+        OutPacket::PingResponse { payload } => {
+            // packet ID:
+            write_varint(buf, 0x01);
+
+            write_long(buf, payload);
+        }
+        OutPacket::DisconnectLogin { reason } => {
+            // packet ID:
+            write_varint(buf, 0x00);
+
+            write_string(buf, &reason.to_json());
+        }
+        OutPacket::DisconnectConfig { reason } => {
+            // packet ID:
+            write_varint(buf, 0x01);
+
+            write_chat(buf, reason, protocol_version);
+        }
+        OutPacket::Disconnect { reason } => {
+            // packet ID:
+            write_varint(buf, 0x1B);
+
+            write_chat(buf, reason, protocol_version);
+        }
+
+        OutPacket::SetCompression { threshold } => {
+            // packet ID:
+            write_varint(buf, 0x03);
+
+            write_varint(buf, threshold);
+        }
+        OutPacket::LoginSuccess { profile } => {
+            // packet ID:
+            write_varint(buf, 0x02);
+
+            write_uuid(buf, profile.uuid);
+            write_string(buf, &profile.name);
+            write_profile_properties(buf, &profile.properties);
+        }
+
+        OutPacket::LoginPlay {
+            entity_id,
+            is_hardcore,
+            dimension_names,
+            max_players,
+            view_distance,
+            simulation_distance,
+            reduced_debug_info,
+            enable_respawn_screen,
+            do_limited_crafting,
+            dimension_type,
+            dimension_name,
+            hashed_seed,
+            game_mode,
+            prev_game_mode,
+            is_debug,
+            is_superflat,
+            death_info,
+            portal_cooldown,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x29);
+
+            write_int(buf, entity_id);
+            write_bool(buf, is_hardcore);
+            write_varint(buf, dimension_names.len().try_into().unwrap());
+            for d in dimension_names.iter() {
+                write_string(buf, d);
+            }
+            write_varint(buf, max_players);
+            write_varint(buf, view_distance);
+            write_varint(buf, simulation_distance);
+            write_bool(buf, reduced_debug_info);
+            write_bool(buf, enable_respawn_screen);
+            write_bool(buf, do_limited_crafting);
+            write_string(buf, dimension_type);
+            write_string(buf, dimension_name);
+            write_long(buf, hashed_seed);
+            write_game_mode(buf, game_mode);
+            match prev_game_mode {
+                None => write_ibyte(buf, -1),
+                Some(gm) => write_game_mode(buf, gm),
+            }
+            write_bool(buf, is_debug);
+            write_bool(buf, is_superflat);
+            match death_info {
+                None => write_bool(buf, false),
+                Some(i) => {
+                    write_bool(buf, true);
+                    write_string(buf, i.dimension);
+                    write_position(buf, &i.location);
+                }
+            }
+            write_varint(buf, portal_cooldown);
+        }
+        OutPacket::FinishConfig => {
+            // packet ID:
+            write_varint(buf, 0x02);
+        }
+        OutPacket::PluginMessageConfig { channel, data } => {
+            // packet ID:
+            write_varint(buf, 0x00);
+
+            write_string(buf, channel);
+            buf.extend_from_slice(data);
+        }
+        OutPacket::PluginMessagePlay { channel, data } => {
+            // packet ID:
+            write_varint(buf, 0x18);
+
+            write_string(buf, channel);
+            buf.extend_from_slice(data);
+        }
+        OutPacket::RegistryData { codec } => {
+            // packet ID:
+            write_varint(buf, 0x05);
+
+            write_network_nbt(buf, codec, protocol_version);
+        }
+        OutPacket::UpdateTagsConfig { registries } => {
+            // packet ID:
+            write_varint(buf, 0x08);
+
+            write_tags(buf, registries);
+        }
+        OutPacket::ChunkDataAndUpdateLight {
+            chunk_x,
+            chunk_z,
+            heightmaps,
+            data,
+            block_entities,
+            sky_light_mask,
+            block_light_mask,
+            empty_sky_light_mask,
+            empty_block_light_mask,
+            sky_light_arrays,
+            block_light_arrays,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x25);
+
+            write_int(buf, chunk_x);
+            write_int(buf, chunk_z);
+            write_network_nbt(buf, &heightmaps, protocol_version);
+            write_varint(buf, data.len().try_into().unwrap());
+            write_ibytes(buf, data);
+            write_varint(buf, block_entities.len().try_into().unwrap());
+            for bent in block_entities.iter() {
+                write_block_entity(buf, bent, protocol_version);
+            }
+            write_bitset(buf, &sky_light_mask);
+            write_bitset(buf, &block_light_mask);
+            write_bitset(buf, &empty_sky_light_mask);
+            write_bitset(buf, &empty_block_light_mask);
+            write_varint(buf, sky_light_arrays.len().try_into().unwrap());
+            for arr in sky_light_arrays.iter() {
+                write_varint(buf, 2048);
+                write_ibytes(buf, arr);
+            }
+            write_varint(buf, block_light_arrays.len().try_into().unwrap());
+            for arr in block_light_arrays.iter() {
+                write_varint(buf, 2048);
+                write_ibytes(buf, arr);
+            }
+        }
+        OutPacket::ChunkBatchStart => {
+            // packet ID:
+            write_varint(buf, 0x0D);
+        }
+        OutPacket::ChunkBatchFinished { batch_size } => {
+            // packet ID:
+            write_varint(buf, 0x0C);
+
+            write_varint(buf, batch_size);
+        }
+        OutPacket::SyncPlayerPos {
+            x,
+            y,
+            z,
+            yaw,
+            pitch,
+            flags,
+            teleport_id,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x3E);
+
+            write_double(buf, x);
+            write_double(buf, y);
+            write_double(buf, z);
+            write_float(buf, yaw);
+            write_float(buf, pitch);
+            write_ibyte(buf, flags);
+            write_varint(buf, teleport_id);
+        }
+        OutPacket::BossBar { uuid, action } => {
+            // packet ID:
+            write_varint(buf, 0x0A);
+
+            write_uuid(buf, uuid);
+            match action {
+                BossBarAction::Add {
+                    title,
+                    health,
+                    color,
+                    division,
+                    flags,
+                } => {
+                    write_varint(buf, 0);
+                    write_string(buf, &title.to_json());
+                    write_float(buf, health);
+                    write_varint(buf, color as i32);
+                    write_varint(buf, division as i32);
+                    write_ubyte(buf, flags);
+                }
+                BossBarAction::Remove => write_varint(buf, 1),
+                BossBarAction::UpdateHealth(health) => {
+                    write_varint(buf, 2);
+                    write_float(buf, health);
+                }
+                BossBarAction::UpdateTitle(title) => {
+                    write_varint(buf, 3);
+                    write_string(buf, &title.to_json());
+                }
+                BossBarAction::UpdateStyle { color, division } => {
+                    write_varint(buf, 4);
+                    write_varint(buf, color as i32);
+                    write_varint(buf, division as i32);
+                }
+                BossBarAction::UpdateFlags(flags) => {
+                    write_varint(buf, 5);
+                    write_ubyte(buf, flags);
+                }
+            }
+        }
+        OutPacket::SystemChat { content, overlay } => {
+            // packet ID:
+            write_varint(buf, 0x67);
+
+            write_string(buf, &content.to_json());
+            write_bool(buf, overlay);
+        }
+        OutPacket::PlayerChat {
+            sender,
+            index,
+            signature,
+            message,
+            timestamp,
+            salt,
+            unsigned_content,
+            filter,
+            chat_type,
+            sender_name,
+            target_name,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x37);
+
+            write_uuid(buf, sender);
+            write_varint(buf, index);
+            write_bool(buf, signature.is_some());
+            if let Some(signature) = signature {
+                buf.extend_from_slice(signature);
+            }
+            write_string(buf, message);
+            write_long(buf, timestamp);
+            write_long(buf, salt);
+            // TODO: previous messages
+            write_varint(buf, 0);
+            write_bool(buf, unsigned_content.is_some());
+            if let Some(content) = unsigned_content {
+                write_string(buf, &content.to_json());
+            }
+            match filter {
+                FilterMask::PassThrough => write_varint(buf, 0),
+                FilterMask::FullyFiltered => write_varint(buf, 1),
+                FilterMask::PartiallyFiltered(mask) => {
+                    write_varint(buf, 2);
+                    write_bitset(buf, mask);
+                }
+            }
+            write_varint(buf, chat_type);
+            write_string(buf, &sender_name.to_json());
+            write_bool(buf, target_name.is_some());
+            if let Some(name) = target_name {
+                write_string(buf, &name.to_json());
+            }
+        }
+        OutPacket::SetActionBarText { text } => {
+            // packet ID:
+            write_varint(buf, 0x48);
+
+            write_string(buf, &text.to_json());
+        }
+        OutPacket::InitializeWorldBorder {
+            x,
+            z,
+            old_diameter,
+            new_diameter,
+            speed,
+            portal_teleport_boundary,
+            warning_blocks,
+            warning_time,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x23);
+
+            write_double(buf, x);
+            write_double(buf, z);
+            write_double(buf, old_diameter);
+            write_double(buf, new_diameter);
+            VarLong(speed).write(buf);
+            write_varint(buf, portal_teleport_boundary);
+            write_varint(buf, warning_blocks);
+            write_varint(buf, warning_time);
+        }
+        OutPacket::SetBorderCenter { x, z } => {
+            // packet ID:
+            write_varint(buf, 0x49);
+
+            write_double(buf, x);
+            write_double(buf, z);
+        }
+        OutPacket::SetBorderLerpSize {
+            old_diameter,
+            new_diameter,
+            speed,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x4A);
+
+            write_double(buf, old_diameter);
+            write_double(buf, new_diameter);
+            VarLong(speed).write(buf);
+        }
+        OutPacket::SetBorderSize { diameter } => {
+            // packet ID:
+            write_varint(buf, 0x4B);
+
+            write_double(buf, diameter);
+        }
+        OutPacket::SetBorderWarningDelay { warning_time } => {
+            // packet ID:
+            write_varint(buf, 0x4C);
+
+            write_varint(buf, warning_time);
+        }
+        OutPacket::SetBorderWarningDistance { warning_blocks } => {
+            // packet ID:
+            write_varint(buf, 0x4D);
+
+            write_varint(buf, warning_blocks);
+        }
+        OutPacket::SetRenderDistance { view_distance } => {
+            // packet ID:
+            write_varint(buf, 0x51);
+
+            write_varint(buf, view_distance);
+        }
+        OutPacket::SetSimulationDistance {
+            simulation_distance,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x5E);
+
+            write_varint(buf, simulation_distance);
+        }
+        OutPacket::SetCenterChunk { chunk_x, chunk_z } => {
+            // packet ID:
+            write_varint(buf, 0x50);
+
+            write_varint(buf, chunk_x);
+            write_varint(buf, chunk_z);
+        }
+        OutPacket::GameEvent { event, value } => {
+            // packet ID:
+            write_varint(buf, 0x20);
+
+            write_ubyte(buf, event);
+            write_float(buf, value);
+        }
+        OutPacket::UpdateTime {
+            world_age,
+            time_of_day,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x60);
+
+            write_long(buf, world_age);
+            write_long(buf, time_of_day);
+        }
+        OutPacket::SetTitleText { text } => {
+            // packet ID:
+            write_varint(buf, 0x61);
+
+            write_string(buf, &text.to_json());
+        }
+        OutPacket::SetSubtitleText { text } => {
+            // packet ID:
+            write_varint(buf, 0x5F);
+
+            write_string(buf, &text.to_json());
+        }
+        OutPacket::SetTitleAnimationTimes {
+            fade_in,
+            stay,
+            fade_out,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x62);
+
+            write_int(buf, fade_in);
+            write_int(buf, stay);
+            write_int(buf, fade_out);
+        }
+        OutPacket::ClearTitles { reset } => {
+            // packet ID:
+            write_varint(buf, 0x0F);
+
+            write_bool(buf, reset);
+        }
+        OutPacket::SetEntityMetadata {
+            entity_id,
+            metadata,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x54);
+
+            write_varint(buf, entity_id);
+            for entry in metadata.iter() {
+                write_metadata_entry(buf, entry);
+            }
+            // end of metadata marker
+            write_ubyte(buf, 0xFF);
+        }
+        OutPacket::SpawnEntity {
+            entity_id,
+            uuid,
+            entity_type,
+            x,
+            y,
+            z,
+            pitch,
+            yaw,
+            head_yaw,
+            data,
+            velocity,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x01);
+
+            write_varint(buf, entity_id);
+            write_uuid(buf, uuid);
+            write_varint(buf, entity_type as i32);
+            write_double(buf, x);
+            write_double(buf, y);
+            write_double(buf, z);
+            write_angle(buf, pitch);
+            write_angle(buf, yaw);
+            write_angle(buf, head_yaw);
+            write_varint(buf, data);
+            for v in velocity {
+                write_short(buf, v);
+            }
+        }
+        OutPacket::SetEntityVelocity {
+            entity_id,
+            velocity,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x56);
+
+            write_varint(buf, entity_id);
+            for v in velocity {
+                write_short(buf, v);
+            }
+        }
+        OutPacket::UpdateEntityRotation {
+            entity_id,
+            yaw,
+            pitch,
+            on_ground,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x2E);
+
+            write_varint(buf, entity_id);
+            write_angle(buf, yaw);
+            write_angle(buf, pitch);
+            write_bool(buf, on_ground);
+        }
+        OutPacket::SetHeadRotation {
+            entity_id,
+            head_yaw,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x44);
+
+            write_varint(buf, entity_id);
+            write_angle(buf, head_yaw);
+        }
+        OutPacket::UpdateEntityPosition {
+            entity_id,
+            delta,
+            on_ground,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x2C);
+
+            write_varint(buf, entity_id);
+            for d in delta {
+                write_short(buf, d);
+            }
+            write_bool(buf, on_ground);
+        }
+        OutPacket::UpdateEntityPositionAndRotation {
+            entity_id,
+            delta,
+            yaw,
+            pitch,
+            on_ground,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x2D);
+
+            write_varint(buf, entity_id);
+            for d in delta {
+                write_short(buf, d);
+            }
+            write_angle(buf, yaw);
+            write_angle(buf, pitch);
+            write_bool(buf, on_ground);
+        }
+        OutPacket::TeleportEntity {
+            entity_id,
+            x,
+            y,
+            z,
+            yaw,
+            pitch,
+            on_ground,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x6B);
+
+            write_varint(buf, entity_id);
+            write_double(buf, x);
+            write_double(buf, y);
+            write_double(buf, z);
+            write_angle(buf, yaw);
+            write_angle(buf, pitch);
+            write_bool(buf, on_ground);
+        }
+        OutPacket::SetEquipment {
+            entity_id,
+            equipment,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x57);
+
+            write_varint(buf, entity_id);
+            for (i, (slot, item)) in equipment.iter().enumerate() {
+                // the top bit is set on all but the last entry
+                let more = if i + 1 < equipment.len() { 0x80 } else { 0 };
+                write_ubyte(buf, *slot as u8 | more);
+                write_slot(buf, item.as_ref());
+            }
+        }
+        OutPacket::UpdateSectionBlocks { section, blocks } => {
+            // packet ID:
+            write_varint(buf, 0x45);
+
+            write_long(buf, section.pack());
+            write_varint(buf, blocks.len().try_into().unwrap());
+            for &(pos, state) in blocks {
+                debug_assert!(section.contains(pos), "{pos:?} isn't in {section:?}");
+                VarLong(SectionPos::pack_block(pos, state)).write(buf);
+            }
+        }
+        OutPacket::PlayerInfoUpdate { entries } => {
+            // packet ID:
+            write_varint(buf, 0x3C);
+
+            let actions = entries.first().map_or(0, player_info_actions);
+            write_ubyte(buf, actions);
+            write_varint(buf, entries.len().try_into().unwrap());
+            for e in entries.iter() {
+                assert_eq!(
+                    player_info_actions(e),
+                    actions,
+                    "all PlayerInfoUpdate entries must update the same fields"
+                );
+                write_uuid(buf, e.uuid);
+                if let Some(profile) = e.add_player {
+                    write_string(buf, &profile.name);
+                    write_profile_properties(buf, &profile.properties);
+                }
+                if let Some(gm) = e.game_mode {
+                    write_varint(buf, gm as i32);
+                }
+                if let Some(listed) = e.listed {
+                    write_bool(buf, listed);
+                }
+                if let Some(latency) = e.latency {
+                    write_varint(buf, latency);
+                }
+                if let Some(display_name) = e.display_name {
+                    write_bool(buf, display_name.is_some());
+                    if let Some(name) = display_name {
+                        write_chat(buf, name, protocol_version);
+                    }
                 }
             }
-            (0x02, State::Config) => {
-                self.state = State::Play;
+        }
+        OutPacket::EntityEvent { entity_id, status } => {
+            // packet ID:
+            write_varint(buf, 0x1D);
 
-                InPacket::FinishConfig
+            write_int(buf, entity_id);
+            write_ibyte(buf, status);
+        }
+        OutPacket::SetPassengers {
+            vehicle,
+            passengers,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x5B);
+
+            write_varint(buf, vehicle);
+            write_varint(buf, passengers.len().try_into().unwrap());
+            for &p in passengers {
+                write_varint(buf, p);
             }
-            _ => panic!(
-                "unknown packet '{:?}, 0x{packid:X}' (len = {packet_len_field})",
-                self.state
-            ),
         }
-    }
+        OutPacket::KeepAliveConfig { id } => {
+            // packet ID:
+            write_varint(buf, 0x03);
 
-    // TODO: buffer the entire packaet and only write it all at once
-    pub fn send(&mut self, packet: OutPacket) {
-        // TODO: reuse this vec. Or nicer way to do the length thing all together?
-        let mut buf = Vec::new();
-        {
-            let buf = &mut buf;
+            write_long(buf, id);
+        }
+        OutPacket::PingConfig { id } => {
+            // packet ID:
+            write_varint(buf, 0x04);
 
-            // hold a reference to the writer throughout the `match`
-            // so that we don't accidentally write directly to self.w
-            // instead of to `buf` :-)
-            let prevent_oopsie_doopsie = &mut self.w;
+            write_int(buf, id);
+        }
+        OutPacket::Commands { tree } => {
+            // packet ID:
+            write_varint(buf, 0x11);
 
-            match packet {
-                OutPacket::DisconnectLogin { reason } => {
-                    // packet ID:
-                    write_varint(buf, 0x00);
+            tree.write(buf);
+        }
+        OutPacket::KeepAlive { id } => {
+            // packet ID:
+            write_varint(buf, 0x24);
 
-                    write!(buf, r#"{{text:"{reason}"}}"#).unwrap();
-                }
+            write_long(buf, id);
+        }
+        OutPacket::Ping { id } => {
+            // packet ID:
+            write_varint(buf, 0x33);
 
-                OutPacket::LoginSuccess {
-                    uuid,
-                    username,
-                    props,
+            write_int(buf, id);
+        }
+        OutPacket::OpenBook { hand } => {
+            // packet ID:
+            write_varint(buf, 0x30);
+
+            write_varint(
+                buf,
+                match hand {
+                    Hand::Main => 0,
+                    Hand::Off => 1,
+                },
+            );
+        }
+        OutPacket::MoveVehicle {
+            x,
+            y,
+            z,
+            yaw,
+            pitch,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x2F);
+
+            write_double(buf, x);
+            write_double(buf, y);
+            write_double(buf, z);
+            write_float(buf, yaw);
+            write_float(buf, pitch);
+        }
+        OutPacket::LinkEntities { attached, holder } => {
+            // packet ID:
+            write_varint(buf, 0x55);
+
+            write_int(buf, attached);
+            write_int(buf, holder);
+        }
+        OutPacket::DisplayObjective { slot, objective } => {
+            // packet ID:
+            write_varint(buf, 0x53);
+
+            write_varint(buf, slot);
+            write_string(buf, objective);
+        }
+        OutPacket::UpdateObjectives { name, action } => {
+            // packet ID:
+            write_varint(buf, 0x5A);
+
+            write_string(buf, name);
+            match action {
+                ObjectiveAction::Create {
+                    display_name,
+                    render_type,
+                }
+                | ObjectiveAction::Update {
+                    display_name,
+                    render_type,
                 } => {
-                    // packet ID:
-                    write_varint(buf, 0x02);
-
-                    write_uuid(buf, uuid);
-                    write_string(buf, username);
-                    write_varint(buf, props.len().try_into().unwrap());
-                    for p in props {
-                        write_string(buf, p.name);
-                        write_string(buf, p.value);
-                        match p.signature {
-                            Some(sig) => {
-                                write_bool(buf, true);
-                                write_string(buf, sig);
-                            }
-                            None => write_bool(buf, false),
-                        }
-                    }
+                    let mode = if let ObjectiveAction::Create { .. } = action {
+                        0
+                    } else {
+                        2
+                    };
+                    write_ibyte(buf, mode);
+                    write_string(buf, &display_name.to_json());
+                    write_varint(buf, render_type as i32);
                 }
+                ObjectiveAction::Remove => write_ibyte(buf, 1),
+            }
+        }
+        OutPacket::UpdateScore {
+            holder,
+            objective,
+            value,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x5D);
+
+            write_string(buf, holder);
+            write_varint(buf, if value.is_some() { 0 } else { 1 });
+            write_string(buf, objective);
+            if let Some(value) = value {
+                write_varint(buf, value);
+            }
+        }
+        OutPacket::RemoveEntities { entity_ids } => {
+            // packet ID:
+            write_varint(buf, 0x40);
 
-                OutPacket::LoginPlay {
-                    entity_id,
-                    is_hardcore,
-                    dimension_names,
-                    max_players,
-                    view_distance,
-                    simulation_distance,
-                    reduced_debug_info,
-                    enable_respawn_screen,
-                    do_limited_crafting,
-                    dimension_type,
-                    dimension_name,
-                    hashed_seed,
-                    game_mode,
-                    prev_game_mode,
-                    is_debug,
-                    is_superflat,
-                    death_info,
-                    portal_cooldown,
-                } => {
-                    // packet ID:
-                    write_varint(buf, 0x29);
-
-                    write_int(buf, entity_id);
-                    write_bool(buf, is_hardcore);
-                    write_varint(buf, dimension_names.len().try_into().unwrap());
-                    for d in dimension_names.iter() {
-                        write_string(buf, d);
-                    }
-                    write_varint(buf, max_players);
-                    write_varint(buf, view_distance);
-                    write_varint(buf, simulation_distance);
-                    write_bool(buf, reduced_debug_info);
-                    write_bool(buf, enable_respawn_screen);
-                    write_bool(buf, do_limited_crafting);
-                    write_string(buf, dimension_type);
-                    write_string(buf, dimension_name);
-                    write_long(buf, hashed_seed);
-                    write_game_mode(buf, game_mode);
-                    match prev_game_mode {
-                        None => write_ibyte(buf, -1),
-                        Some(gm) => write_game_mode(buf, gm),
-                    }
-                    write_bool(buf, is_debug);
-                    write_bool(buf, is_superflat);
-                    match death_info {
-                        None => write_bool(buf, false),
-                        Some(i) => {
-                            write_bool(buf, true);
-                            write_string(buf, i.dimension);
-                            write_position(buf, &i.location);
-                        }
-                    }
-                    write_varint(buf, portal_cooldown);
-                }
-                OutPacket::FinishConfig => {
-                    // packet ID:
-                    write_varint(buf, 0x02);
-                }
-                OutPacket::ChunkDataAndUpdateLight {
-                    chunk_x,
-                    chunk_z,
-                    heightmaps,
-                    data,
-                    block_entities,
-                    sky_light_mask,
-                    block_light_mask,
-                    empty_sky_light_mask,
-                    empty_block_light_mask,
-                    sky_light_arrays,
-                    block_light_arrays,
-                } => {
-                    // packet ID:
-                    write_varint(buf, 0x25);
-
-                    write_int(buf, chunk_x);
-                    write_int(buf, chunk_z);
-                    write_compound_nbt(buf, &heightmaps);
-                    write_varint(buf, data.len().try_into().unwrap());
-                    for x in data.iter().copied() {
-                        write_ibyte(buf, x);
-                    }
-                    write_varint(buf, block_entities.len().try_into().unwrap());
-                    for bent in block_entities.iter() {
-                        write_block_entity(buf, bent);
-                    }
-                    write_bitset(buf, &sky_light_mask);
-                    write_bitset(buf, &block_light_mask);
-                    write_bitset(buf, &empty_sky_light_mask);
-                    write_bitset(buf, &empty_block_light_mask);
-                    write_varint(buf, sky_light_arrays.len().try_into().unwrap());
-                    for arr in sky_light_arrays.iter() {
-                        write_varint(buf, 2048);
-                        for b in arr.iter().copied() {
-                            write_ibyte(buf, b);
-                        }
-                    }
-                    write_varint(buf, block_light_arrays.len().try_into().unwrap());
-                    for arr in block_light_arrays.iter() {
-                        write_varint(buf, 2048);
-                        for b in arr.iter().copied() {
-                            write_ibyte(buf, b);
-                        }
+            write_varint(buf, entity_ids.len().try_into().unwrap());
+            for id in entity_ids.iter().copied() {
+                write_varint(buf, id);
+            }
+        }
+        OutPacket::SetContainerSlot {
+            window_id,
+            state_id,
+            slot,
+            item,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x15);
+
+            write_ibyte(buf, window_id);
+            write_varint(buf, state_id);
+            write_short(buf, slot);
+            write_slot(buf, item.as_ref());
+        }
+        OutPacket::UpdateTags { registries } => {
+            // packet ID:
+            write_varint(buf, 0x70);
+
+            write_tags(buf, registries);
+        }
+        OutPacket::UpdateRecipes {
+            recipes,
+            items,
+            tags,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x6F);
+
+            write_recipes(buf, recipes, items, tags);
+        }
+        OutPacket::MerchantOffers {
+            window_id,
+            offers,
+            villager_level,
+            experience,
+            is_regular_villager,
+            can_restock,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x2B);
+
+            write_varint(buf, window_id);
+            write_varint(buf, offers.len().try_into().unwrap());
+            for offer in offers {
+                write_merchant_offer(buf, offer);
+            }
+            write_varint(buf, villager_level);
+            write_varint(buf, experience);
+            write_bool(buf, is_regular_villager);
+            write_bool(buf, can_restock);
+        }
+        OutPacket::MapData {
+            map_id,
+            scale,
+            locked,
+            icons,
+            patch,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x2A);
+
+            write_varint(buf, map_id);
+            write_ibyte(buf, scale);
+            write_bool(buf, locked);
+            write_bool(buf, icons.is_some());
+            if let Some(icons) = icons {
+                write_varint(buf, icons.len().try_into().unwrap());
+                for icon in icons {
+                    write_varint(buf, icon.kind);
+                    write_ibyte(buf, icon.x);
+                    write_ibyte(buf, icon.z);
+                    write_ubyte(buf, icon.direction);
+                    write_bool(buf, icon.name.is_some());
+                    if let Some(name) = &icon.name {
+                        write_string(buf, &name.to_json());
                     }
                 }
-                OutPacket::SyncPlayerPos {
-                    x,
-                    y,
-                    z,
-                    yaw,
-                    pitch,
-                    flags,
-                    teleport_id,
-                } => {
-                    // packet ID:
-                    write_varint(buf, 0x3E);
+            }
+            match patch {
+                // no columns means no patch
+                None => write_ubyte(buf, 0),
+                Some(patch) => {
+                    write_ubyte(buf, patch.columns);
+                    write_ubyte(buf, patch.rows);
+                    write_ubyte(buf, patch.x);
+                    write_ubyte(buf, patch.z);
+                    write_varint(buf, patch.colors.len().try_into().unwrap());
+                    buf.extend_from_slice(&patch.colors);
+                }
+            }
+        }
+        OutPacket::EntityEffect { entity_id, effect } => {
+            // packet ID:
+            write_varint(buf, 0x6E);
+
+            write_varint(buf, entity_id);
+            write_varint(buf, effect.effect.id());
+            write_ibyte(buf, effect.amplifier);
+            // -1 for infinite
+            write_varint(
+                buf,
+                effect
+                    .duration
+                    .map_or(-1, |d| d.try_into().unwrap_or(i32::MAX)),
+            );
+            let flags = u8::from(effect.ambient)
+                | u8::from(effect.show_particles) << 1
+                | u8::from(effect.show_icon) << 2;
+            write_ubyte(buf, flags);
+            // no factor data, which only darkness uses
+            write_bool(buf, false);
+        }
+        OutPacket::RemoveEntityEffect { entity_id, effect } => {
+            // packet ID:
+            write_varint(buf, 0x41);
+
+            write_varint(buf, entity_id);
+            write_varint(buf, effect.id());
+        }
+        OutPacket::SetExperience { bar, level, total } => {
+            // packet ID:
+            write_varint(buf, 0x58);
+
+            write_float(buf, bar);
+            write_varint(buf, level);
+            write_varint(buf, total);
+        }
+        OutPacket::ChangeDifficulty { difficulty, locked } => {
+            // packet ID:
+            write_varint(buf, 0x0B);
+
+            write_ubyte(buf, difficulty as u8);
+            write_bool(buf, locked);
+        }
+        OutPacket::SetHealth {
+            health,
+            food,
+            saturation,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x59);
+
+            write_float(buf, health);
+            write_varint(buf, food);
+            write_float(buf, saturation);
+        }
+        OutPacket::CombatDeath { player_id, message } => {
+            // packet ID:
+            write_varint(buf, 0x3A);
 
-                    write_double(buf, x);
-                    write_double(buf, y);
-                    write_double(buf, z);
-                    write_float(buf, yaw);
-                    write_ibyte(buf, flags);
-                    write_varint(buf, teleport_id);
+            write_varint(buf, player_id);
+            write_string(buf, &message.to_json());
+        }
+        OutPacket::EnterCombat => {
+            // packet ID:
+            write_varint(buf, 0x39);
+        }
+        OutPacket::EndCombat { duration } => {
+            // packet ID:
+            write_varint(buf, 0x38);
+
+            write_varint(buf, duration);
+        }
+        OutPacket::Respawn {
+            dimension_type,
+            dimension_name,
+            hashed_seed,
+            game_mode,
+            prev_game_mode,
+            is_debug,
+            is_superflat,
+            death_info,
+            portal_cooldown,
+            data_kept,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x43);
+
+            write_string(buf, dimension_type);
+            write_string(buf, dimension_name);
+            write_long(buf, hashed_seed);
+            write_game_mode(buf, game_mode);
+            match prev_game_mode {
+                None => write_ibyte(buf, -1),
+                Some(gm) => write_game_mode(buf, gm),
+            }
+            write_bool(buf, is_debug);
+            write_bool(buf, is_superflat);
+            match death_info {
+                None => write_bool(buf, false),
+                Some(i) => {
+                    write_bool(buf, true);
+                    write_string(buf, i.dimension);
+                    write_position(buf, &i.location);
+                }
+            }
+            write_varint(buf, portal_cooldown);
+            write_ibyte(buf, data_kept);
+        }
+        OutPacket::UpdateAdvancements {
+            reset,
+            added,
+            removed,
+            progress,
+        } => {
+            // packet ID:
+            write_varint(buf, 0x6C);
+
+            write_bool(buf, reset);
+            write_varint(buf, added.len().try_into().unwrap());
+            for (id, advancement) in added {
+                write_string(buf, id);
+                write_advancement(buf, advancement);
+            }
+            write_varint(buf, removed.len().try_into().unwrap());
+            for id in removed {
+                write_string(buf, id);
+            }
+            write_varint(buf, progress.len().try_into().unwrap());
+            for (id, p) in progress {
+                write_string(buf, id);
+                write_advancement_progress(buf, p);
+            }
+        }
+        OutPacket::UpdateRecipeBook { action, settings } => {
+            // packet ID:
+            write_varint(buf, 0x3F);
+
+            let (action_id, ids, seen) = match action {
+                RecipeBookAction::Init { known, seen } => (0, known, Some(seen)),
+                RecipeBookAction::Add(ids) => (1, ids, None),
+                RecipeBookAction::Remove(ids) => (2, ids, None),
+            };
+            write_varint(buf, action_id);
+            for book in [
+                settings.crafting,
+                settings.furnace,
+                settings.blast_furnace,
+                settings.smoker,
+            ] {
+                write_bool(buf, book.open);
+                write_bool(buf, book.filtering);
+            }
+            for list in std::iter::once(ids).chain(seen) {
+                write_varint(buf, list.len().try_into().unwrap());
+                for id in list.iter() {
+                    write_string(buf, id);
                 }
             }
+        }
+        OutPacket::Raw { id, payload } => {
+            write_varint(buf, id);
+            buf.extend_from_slice(payload);
+        }
+    }
+}
+
+pub(crate) fn write_slot(buf: &mut Vec<u8>, slot: Option<&Slot>) {
+    let Some(slot) = slot else {
+        write_bool(buf, false);
+        return;
+    };
+    write_bool(buf, true);
+    write_varint(buf, slot.item_id);
+    write_ibyte(buf, slot.count);
+    match slot.nbt {
+        Some(nbt) => buf.extend_from_slice(nbt),
+        // TAG_End
+        None => write_ubyte(buf, 0x00),
+    }
+}
 
-            let _ = prevent_oopsie_doopsie;
+fn write_tags(buf: &mut Vec<u8>, registries: &[RegistryTags]) {
+    write_varint(buf, registries.len().try_into().unwrap());
+    for r in registries {
+        write_string(buf, &r.registry);
+        write_varint(buf, r.tags.len().try_into().unwrap());
+        for (name, ids) in &r.tags {
+            write_string(buf, name);
+            write_varint(buf, ids.len().try_into().unwrap());
+            for &id in ids {
+                write_varint(buf, id);
+            }
         }
-        write_varint(&mut self.w, buf.len().try_into().unwrap());
-        self.w.write(&buf).unwrap();
     }
 }
 
-pub(crate) fn read_varint<R: Read>(r: &mut R) -> i64 {
-    read_varint_with_nread(r).0
+/// `Write::write_all_vectored()` is unstable
+fn write_all_vectored<W: Write>(w: &mut W, mut bufs: &mut [IoSlice<'_>]) -> std::io::Result<()> {
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match w.write_vectored(bufs) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
 }
 
-// returns the varint and how many bytes were read for it.
-// returns (varint, nread).
-pub(crate) fn read_varint_with_nread<R: Read>(r: &mut R) -> (i64, i64) {
-    let mut ret = 0;
-    let mut shift = 0;
-    let mut nread = 0;
+/// Prefixes a packet (ID and body) with its length
+pub(crate) fn frame_packet(packet: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(packet.len() + 3);
+    frame_packet_into(packet, &mut frame);
+    frame
+}
 
-    let mut b = [0];
-    loop {
-        r.read_exact(&mut b).unwrap();
-        nread += 1;
-        let cur = b[0];
-        ret |= ((cur & 0b01111111) as i64) << shift;
-        shift += 7;
-        if cur & (1 << 7) == 0 {
+/// Like `frame_packet()`, appending to `frame`
+pub(crate) fn frame_packet_into(packet: &[u8], frame: &mut Vec<u8>) {
+    write_varint(frame, packet.len().try_into().unwrap());
+    frame.extend_from_slice(packet);
+}
+
+/// Longest a compressed packet can say it is once decompressed, like vanilla
+const MAX_DECOMPRESSED_LEN: usize = 1 << 23;
+
+/// Like `frame_packet_into()`, in the compressed format if `compression` has a threshold: the
+/// packet's uncompressed length (0 if it's under the threshold and left uncompressed) followed
+/// by the packet, zlib-compressed
+fn frame_compressed_into(packet: &[u8], compression: Option<usize>, frame: &mut Vec<u8>) {
+    let Some(threshold) = compression else {
+        frame_packet_into(packet, frame);
+        return;
+    };
+    let mut data = Vec::with_capacity(packet.len() + 16);
+    if packet.len() < threshold {
+        write_varint(&mut data, 0);
+        data.extend_from_slice(packet);
+    } else {
+        write_varint(&mut data, packet.len().try_into().unwrap());
+        data.extend(zlib_compress(packet));
+    }
+    frame_packet_into(&data, frame);
+}
+
+/// The packet in the body of a compressed frame (everything after its length prefix)
+fn decompress_packet(body: &[u8], threshold: usize) -> Result<Vec<u8>, DecodeError> {
+    let mut r = PacketReader::new(body);
+    let data_len = r.varint()?;
+    if data_len == 0 {
+        return Ok(r.rest().to_vec());
+    }
+    let data_len = usize::try_from(data_len).map_err(|_| DecodeError::BadCompression)?;
+    // vanilla clients only compress packets at least as long as the threshold
+    if data_len < threshold || data_len > MAX_DECOMPRESSED_LEN {
+        return Err(DecodeError::BadCompression);
+    }
+    match zlib_decompress_max(r.rest(), data_len) {
+        Ok(packet) if packet.len() == data_len => Ok(packet),
+        _ => Err(DecodeError::BadCompression),
+    }
+}
+
+/// Max length of a packet frame: the length prefix is at most a 3-byte varint
+pub(crate) const MAX_FRAME_LEN: usize = (1 << 21) - 1;
+
+/// Reads one length-prefixed packet frame, returning it including its length prefix.
+/// Unlike the `read_*` functions, this reports errors (i.e. disconnects) instead of panicking.
+pub(crate) fn read_frame<R: Read>(r: &mut R) -> Result<Vec<u8>, DisconnectCause> {
+    let truncated = |e: std::io::Error| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => DisconnectCause::Truncated,
+        kind => DisconnectCause::Io(kind),
+    };
+
+    let mut frame = Vec::new();
+    let mut len = 0;
+    for i in 0.. {
+        let mut b = [0];
+        if i == 0 {
+            // EOF before a frame starts is a clean close
+            loop {
+                match r.read(&mut b) {
+                    Ok(0) => return Err(DisconnectCause::Closed),
+                    Ok(_) => break,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(DisconnectCause::Io(e.kind())),
+                }
+            }
+        } else {
+            r.read_exact(&mut b).map_err(truncated)?;
+        }
+        frame.push(b[0]);
+        len |= ((b[0] & 0b01111111) as usize) << (7 * i);
+        if b[0] & (1 << 7) == 0 {
             break;
         }
+        if i == 2 {
+            return Err(DisconnectCause::BadFrame("packet length prefix too long"));
+        }
+    }
+    if len > MAX_FRAME_LEN {
+        return Err(DisconnectCause::BadFrame("packet too long"));
     }
 
-    (ret, nread)
+    let prefix_len = frame.len();
+    frame.resize(prefix_len + len, 0);
+    r.read_exact(&mut frame[prefix_len..]).map_err(truncated)?;
+    Ok(frame)
+}
+
+pub(crate) fn read_varint<R: Read>(r: &mut R) -> i32 {
+    VarInt::read(r).0 .0
+}
+
+// returns the varint and how many bytes were read for it.
+// returns (varint, nread).
+pub(crate) fn read_varint_with_nread<R: Read>(r: &mut R) -> (i32, i32) {
+    let (int, nread) = VarInt::read(r);
+    (int.0, nread.try_into().unwrap())
 }
 
 /// Reads a string prefixed by its length as a varint.
 /// Returns the read string and how many bytes were read to deserialize the string.
 /// (because of Java's stupid "Modified UTF-8" the # of bytes read might differ from string.len().
-pub(crate) fn read_varint_string_with_nread<R: Read>(r: &mut R) -> (String, i64) {
+pub(crate) fn read_varint_string_with_nread<R: Read>(r: &mut R) -> (String, i32) {
     let (len, lennread) = read_varint_with_nread(r);
     let mut vs = vec![0; len.try_into().unwrap()];
     r.read_exact(&mut vs).unwrap();
@@ -523,36 +3171,16 @@ pub(crate) fn read_varint_string_with_nread<R: Read>(r: &mut R) -> (String, i64)
     (String::from_utf8(vs).unwrap(), len + lennread)
 }
 
-pub(crate) fn read_ushort_string<R: Read>(r: &mut R) -> String {
-    let len = read_ushort(r);
-    let mut vs = vec![0; len.try_into().unwrap()];
-    r.read_exact(&mut vs).unwrap();
-    // TODO: convert from Java's "Modified UTF-8" :(
-    String::from_utf8(vs).unwrap()
-}
-
 pub(crate) fn write_ushort_string<W: Write>(w: &mut W, s: &str) {
     write_ushort(w, s.len().try_into().unwrap());
     // TODO: convert to java "Modified UTF-8"
-    w.write(s.as_bytes()).unwrap();
+    w.write_all(s.as_bytes()).unwrap();
 }
 
 pub(crate) fn read_varint_string<R: Read>(r: &mut R) -> String {
     read_varint_string_with_nread(r).0
 }
 
-pub(crate) fn read_short<R: Read>(r: &mut R) -> i16 {
-    let mut b = [0, 0];
-    r.read_exact(&mut b).unwrap();
-    i16::from_be_bytes(b)
-}
-
-pub(crate) fn read_ushort<R: Read>(r: &mut R) -> u16 {
-    let mut b = [0, 0];
-    r.read_exact(&mut b).unwrap();
-    u16::from_be_bytes(b)
-}
-
 pub(crate) fn read_int<R: Read>(r: &mut R) -> i32 {
     let mut b = [0; 4];
     r.read_exact(&mut b).unwrap();
@@ -577,18 +3205,6 @@ pub(crate) fn read_ubyte<R: Read>(r: &mut R) -> u8 {
     b[0]
 }
 
-pub(crate) fn read_float<R: Read>(r: &mut R) -> f32 {
-    let mut b = [0; 4];
-    r.read_exact(&mut b).unwrap();
-    f32::from_be_bytes(b)
-}
-
-pub(crate) fn read_double<R: Read>(r: &mut R) -> f64 {
-    let mut b = [0; 8];
-    r.read_exact(&mut b).unwrap();
-    f64::from_be_bytes(b)
-}
-
 pub(crate) fn read_bool<R: Read>(r: &mut R) -> bool {
     match read_ubyte(r) {
         0 => false,
@@ -597,52 +3213,51 @@ pub(crate) fn read_bool<R: Read>(r: &mut R) -> bool {
     }
 }
 
-pub(crate) fn read_uuid<R: Read>(r: &mut R) -> u128 {
-    let mut b = [0; 16];
-    r.read_exact(&mut b).unwrap();
-    u128::from_be_bytes(b)
+pub(crate) fn read_position<R: Read>(r: &mut R) -> Position {
+    let packed = read_long(r);
+    // arithmetic shifts sign-extend each field
+    Position {
+        x: (packed >> 38) as i32,
+        z: (packed << 26 >> 38) as i32,
+        y: (packed << 52 >> 52) as i16,
+    }
 }
 
-// TODO: is this really correct? negative numbers always send 64 bits?
-pub(crate) fn write_varint<W: Write>(w: &mut W, int: i64) {
-    let seg_bits = 0b01111111;
-    let mut int = u64::from_ne_bytes(int.to_ne_bytes());
-
-    loop {
-        if int & !seg_bits == 0 {
-            write_ubyte(w, (int & 0xFF).try_into().unwrap());
-            break;
-        }
-
-        write_ubyte(w, ((int & seg_bits) | (1 << 7)).try_into().unwrap());
-        int >>= 7;
-    }
+pub(crate) fn write_varint<W: Write>(w: &mut W, int: i32) {
+    VarInt(int).write(w);
 }
 
 pub(crate) fn write_ubyte<W: Write>(w: &mut W, byte: u8) {
-    w.write(&[byte]).unwrap();
+    w.write_all(&[byte]).unwrap();
 }
 
 pub(crate) fn write_ibyte<W: Write>(w: &mut W, byte: i8) {
-    w.write(&byte.to_be_bytes()).unwrap();
+    w.write_all(&byte.to_be_bytes()).unwrap();
+}
+
+/// Writes `bytes` with one `write_all()`, rather than a call per byte
+pub(crate) fn write_ibytes<W: Write>(w: &mut W, bytes: &[i8]) {
+    // SAFETY: i8 and u8 have the same size and alignment, and every bit pattern is valid for both
+    let bytes = unsafe { std::slice::from_raw_parts(bytes.as_ptr().cast::<u8>(), bytes.len()) };
+    w.write_all(bytes).unwrap();
 }
 
 pub(crate) fn write_short<W: Write>(w: &mut W, short: i16) {
-    w.write(&short.to_be_bytes()).unwrap();
+    w.write_all(&short.to_be_bytes()).unwrap();
 }
 
 pub(crate) fn write_ushort<W: Write>(w: &mut W, ushort: u16) {
-    w.write(&ushort.to_be_bytes()).unwrap();
+    w.write_all(&ushort.to_be_bytes()).unwrap();
 }
 
-pub(crate) fn write_uuid<W: Write>(w: &mut W, uuid: u128) {
-    w.write(&uuid.to_be_bytes()).unwrap();
+pub(crate) fn write_uuid<W: Write>(w: &mut W, uuid: Uuid) {
+    w.write_all(&uuid.0.to_be_bytes()).unwrap();
 }
 
 pub(crate) fn write_string<W: Write>(w: &mut W, s: &str) {
     write_varint(w, s.len().try_into().unwrap());
     // TODO: java's dumbass "Modified UTF-8" again
-    w.write(s.as_bytes()).unwrap();
+    w.write_all(s.as_bytes()).unwrap();
 }
 
 pub(crate) fn write_bool<W: Write>(w: &mut W, b: bool) {
@@ -650,19 +3265,23 @@ pub(crate) fn write_bool<W: Write>(w: &mut W, b: bool) {
 }
 
 pub(crate) fn write_int<W: Write>(w: &mut W, int: i32) {
-    w.write(&int.to_be_bytes()).unwrap();
+    w.write_all(&int.to_be_bytes()).unwrap();
 }
 
 pub(crate) fn write_long<W: Write>(w: &mut W, long: i64) {
-    w.write(&long.to_be_bytes()).unwrap();
+    w.write_all(&long.to_be_bytes()).unwrap();
 }
 
 pub(crate) fn write_float<W: Write>(w: &mut W, x: f32) {
-    w.write(&x.to_be_bytes()).unwrap();
+    w.write_all(&x.to_be_bytes()).unwrap();
 }
 
 pub(crate) fn write_double<W: Write>(w: &mut W, x: f64) {
-    w.write(&x.to_be_bytes()).unwrap();
+    w.write_all(&x.to_be_bytes()).unwrap();
+}
+
+pub(crate) fn write_angle<W: Write>(w: &mut W, angle: Angle) {
+    write_ubyte(w, angle.0);
 }
 
 pub(crate) fn write_game_mode<W: Write>(w: &mut W, gm: GameMode) {
@@ -670,25 +3289,14 @@ pub(crate) fn write_game_mode<W: Write>(w: &mut W, gm: GameMode) {
 }
 
 pub(crate) fn write_position<W: Write>(w: &mut W, p: &Position) {
-    let mask_26bits: i64 = 0x3FFFFFF;
-    let mask_12bits: i64 = 0xFFF;
-
-    let x = p.x as i64;
-    let z = p.z as i64;
-    let y = p.y as i64;
-
-    let mut packed: i64 = 0;
-
-    assert!(x == x & mask_26bits, "Position.x is bigger than 26 bits");
-    assert!(z == z & mask_26bits, "Position.z is bigger than 26 bits");
-    assert!(y == y & mask_12bits, "Position.y is bigger than 12 bits");
-
-    // TODO: double-check this signed bitbasking - sign bit kept?
-    packed |= (x & mask_26bits) << 38;
-    packed |= (z & mask_26bits) << 12;
-    packed |= y & mask_12bits;
+    if let Err(e) = Position::new(p.x, p.y.into(), p.z) {
+        panic!("{e}");
+    }
 
-    w.write(&packed.to_be_bytes()).unwrap();
+    let x = i64::from(p.x) & 0x3FFFFFF;
+    let z = i64::from(p.z) & 0x3FFFFFF;
+    let y = i64::from(p.y) & 0xFFF;
+    write_long(w, (x << 38) | (z << 12) | y);
 }
 
 pub(crate) fn write_bitset<W: Write>(w: &mut W, bs: &BitSet) {
@@ -698,17 +3306,292 @@ pub(crate) fn write_bitset<W: Write>(w: &mut W, bs: &BitSet) {
     }
 }
 
-pub(crate) fn write_block_entity<W: Write>(w: &mut W, bent: &BlockEntity<'_>) {
+pub(crate) fn write_block_entity<W: Write>(
+    w: &mut W,
+    bent: &BlockEntity<'_>,
+    protocol_version: i32,
+) {
     write_ibyte(w, ((bent.x as i8 & 15) << 4) | (bent.z as i8 & 15));
     write_short(w, bent.y);
     write_varint(w, bent.tipe);
-    write_compound_nbt(w, &bent.data);
+    write_network_nbt(w, &bent.data, protocol_version);
+}
+
+/// The Player Info Update actions bitmask for the fields set in `e`
+fn player_info_actions(e: &PlayerInfoEntry<'_>) -> u8 {
+    let mut actions = 0;
+    if e.add_player.is_some() {
+        actions |= 0x01;
+    }
+    if e.game_mode.is_some() {
+        actions |= 0x04;
+    }
+    if e.listed.is_some() {
+        actions |= 0x08;
+    }
+    if e.latency.is_some() {
+        actions |= 0x10;
+    }
+    if e.display_name.is_some() {
+        actions |= 0x20;
+    }
+    actions
+}
+
+/// The first protocol version (1.20.3, and its snapshots from 23w40a) that sends chat components
+/// as NBT rather than JSON
+const NBT_CHAT_SINCE: i32 = 765;
+
+/// Writes a chat component the way clients speaking `protocol_version` take it
+fn write_chat<W: Write>(w: &mut W, c: &TextComponent, protocol_version: i32) {
+    if protocol_version >= NBT_CHAT_SINCE {
+        write_network_nbt(w, &c.to_nbt(), protocol_version);
+    } else {
+        write_string(w, &c.to_json());
+    }
+}
+
+pub(crate) fn write_metadata_entry<W: Write>(w: &mut W, entry: &MetadataEntry<'_>) {
+    write_ubyte(w, entry.index);
+    write_varint(w, entry.value.type_id());
+    match entry.value {
+        MetadataValue::Byte(x) => write_ibyte(w, x),
+        MetadataValue::VarInt(x) => write_varint(w, x),
+        MetadataValue::Float(x) => write_float(w, x),
+        MetadataValue::String(s) => write_string(w, s),
+        MetadataValue::Chat(c) => write_string(w, &c.to_json()),
+        MetadataValue::OptChat(c) => match c {
+            Some(c) => {
+                write_bool(w, true);
+                write_string(w, &c.to_json());
+            }
+            None => write_bool(w, false),
+        },
+        MetadataValue::Boolean(b) => write_bool(w, b),
+        // encoded as 0 for absent, and x + 1 otherwise
+        MetadataValue::OptVarInt(x) => write_varint(w, x.map_or(0, |x| x + 1)),
+        MetadataValue::Pose(p) => write_varint(w, p as i32),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn position_roundtrip() {
+        for (x, y, z) in [
+            (0, 0, 0),
+            (-1, -1, -1),
+            (18357644, 831, -20882616),
+            (Position::MIN_XZ, Position::MIN_Y, Position::MAX_XZ),
+            (Position::MAX_XZ, Position::MAX_Y, Position::MIN_XZ),
+        ] {
+            let p = Position::new(x, y, z).unwrap();
+            let mut buf = Vec::new();
+            write_position(&mut buf, &p);
+            assert_eq!(read_position(&mut &buf[..]), p);
+        }
+
+        // example from the protocol docs
+        let mut buf = Vec::new();
+        write_position(&mut buf, &Position::new(18357644, 831, -20882616).unwrap());
+        assert_eq!(buf, 0x4607632c15b4833f_i64.to_be_bytes());
+
+        assert!(Position::new(Position::MAX_XZ + 1, 0, 0).is_err());
+        assert!(Position::new(0, Position::MIN_Y - 1, 0).is_err());
+    }
+
+    #[test]
+    fn unknown_packet() {
+        let mut ps = PacketStream::new(Vec::new());
+        let frame = frame_packet(&[0x7F, 1, 2, 3]);
+        assert!(matches!(
+            ps.decode(&frame).unwrap(),
+            InPacket::Unknown {
+                id: 0x7F,
+                state: ProtocolState::Handshaking,
+                data: [1, 2, 3]
+            }
+        ));
+    }
+
+    #[test]
+    fn config_keep_alive_and_pong() {
+        assert!(matches!(
+            decode_packet(ProtocolState::Config, &[0x03, 0, 0, 0, 0, 0, 0, 0, 7]),
+            Ok(InPacket::KeepAliveConfig { id: 7 })
+        ));
+        assert!(matches!(
+            decode_packet(ProtocolState::Config, &[0x04, 0, 0, 1, 0]),
+            Ok(InPacket::PongConfig { id: 256 })
+        ));
+        assert_eq!(
+            encode_packet(OutPacket::PingConfig { id: 256 }, PROTOCOL_VERSION),
+            [0x04, 0, 0, 1, 0]
+        );
+    }
+
+    #[test]
+    fn raw_packet() {
+        let packet = OutPacket::Raw {
+            id: 0x7A,
+            payload: &[0x05, b'h', b'e', b'l', b'l', b'o'],
+        };
+        let encoded = encode_packet(packet, PROTOCOL_VERSION);
+        assert_eq!(encoded[0], 0x7A);
+
+        let mut reader = PacketReader::new(&encoded[1..]);
+        assert_eq!(reader.str().unwrap(), "hello");
+        assert!(reader.is_empty());
+        assert!(reader.varint().is_err());
+    }
+
+    #[test]
+    fn disconnect_chat_format() {
+        let reason = TextComponent::text("bye");
+        let json = encode_packet(OutPacket::Disconnect { reason: &reason }, PROTOCOL_VERSION);
+        assert_eq!(json[..3], [0x1B, 14, b'{']);
+        let nbt = encode_packet(OutPacket::Disconnect { reason: &reason }, NBT_CHAT_SINCE);
+        // a nameless compound, with a "text" string
+        assert_eq!(nbt[..3], [0x1B, 10, 8]);
+        assert!(nbt.ends_with(b"bye\0"));
+    }
+
+    #[test]
+    fn player_info_listed_and_display_name() {
+        let name = TextComponent::text("[Admin] Notch");
+        let entries = [
+            PlayerInfoEntry::new(Uuid(1))
+                .listed(false)
+                .display_name(Some(&name)),
+            PlayerInfoEntry::new(Uuid(2))
+                .listed(true)
+                .display_name(None),
+        ];
+        let packet = encode_packet(
+            OutPacket::PlayerInfoUpdate { entries: &entries },
+            PROTOCOL_VERSION,
+        );
+        assert_eq!(packet[..3], [0x3C, 0x28, 2]);
+        // not listed, has a display name, then its JSON
+        assert_eq!(packet[19..22], [0, 1, 24]);
+        // listed, no display name
+        assert!(packet.ends_with(&[1, 0]));
+    }
+
+    #[test]
+    fn chunk_light_bytes() {
+        let data = [-1, 0, 1, i8::MIN, i8::MAX];
+        let light = [[-128i8; 2048]];
+        let packet = OutPacket::ChunkDataAndUpdateLight {
+            chunk_x: 0,
+            chunk_z: 0,
+            heightmaps: CompoundNbt::new(""),
+            data: &data,
+            block_entities: &[],
+            sky_light_mask: BitSet::with_num_bits(1),
+            block_light_mask: BitSet::with_num_bits(1),
+            empty_sky_light_mask: BitSet::with_num_bits(1),
+            empty_block_light_mask: BitSet::with_num_bits(1),
+            sky_light_arrays: &light,
+            block_light_arrays: &[],
+        };
+        let encoded = encode_packet(packet, PROTOCOL_VERSION);
+        let data_at = encoded
+            .windows(6)
+            .position(|w| w == [5, 0xFF, 0, 1, 0x80, 0x7F])
+            .unwrap();
+        // the sky light array, then no block light arrays
+        assert!(encoded[data_at..].ends_with(&[&[0x80; 2048][..], &[0]].concat()));
+    }
+
+    #[test]
+    fn vehicles() {
+        let mut p = vec![0x1A];
+        for v in [1.5f64, 64.0, -2.0] {
+            p.extend_from_slice(&v.to_be_bytes());
+        }
+        p.extend_from_slice(&90f32.to_be_bytes());
+        p.extend_from_slice(&0f32.to_be_bytes());
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &p).unwrap(),
+            InPacket::MoveVehicle {
+                x: 1.5,
+                y: 64.0,
+                z: -2.0,
+                yaw: 90.0,
+                pitch: 0.0
+            }
+        ));
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &[0x1B, 0, 1]).unwrap(),
+            InPacket::PaddleBoat {
+                left: false,
+                right: true
+            }
+        ));
+        let mut p = vec![0x33];
+        p.extend_from_slice(&7u128.to_be_bytes());
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &p).unwrap(),
+            InPacket::TeleportToEntity { target: Uuid(7) }
+        ));
+        assert_eq!(
+            encode_packet(
+                OutPacket::SetPassengers {
+                    vehicle: 9,
+                    passengers: &[1, 2]
+                },
+                PROTOCOL_VERSION
+            ),
+            [0x5B, 9, 2, 1, 2]
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        // Set Player On Ground
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &[0x19, 0x01]),
+            Ok(InPacket::SetPlayerOnGround { on_ground: true })
+        ));
+        assert_eq!(
+            decode_packet(ProtocolState::Play, &[0x19]).unwrap_err(),
+            DecodeError::UnexpectedEnd
+        );
+        assert_eq!(
+            decode_packet(ProtocolState::Play, &[0x19, 0x02]).unwrap_err(),
+            DecodeError::BadValue {
+                field: "bool",
+                value: 2
+            }
+        );
+        assert_eq!(
+            decode_packet(ProtocolState::Play, &[0xFF; 6]).unwrap_err(),
+            DecodeError::VarIntTooLong
+        );
+        // Change Difficulty
+        assert!(matches!(
+            decode_packet(ProtocolState::Play, &[0x02, 0x03]),
+            Ok(InPacket::ChangeDifficulty {
+                difficulty: Difficulty::Hard
+            })
+        ));
+        assert_eq!(
+            decode_packet(ProtocolState::Play, &[0x02, 0x04]).unwrap_err(),
+            DecodeError::BadValue {
+                field: "difficulty",
+                value: 4
+            }
+        );
+        // Login Start with a name that isn't UTF-8
+        assert_eq!(
+            decode_packet(ProtocolState::Login, &[0x00, 0x01, 0xFF]).unwrap_err(),
+            DecodeError::InvalidString
+        );
+    }
+
     #[test]
     fn test_bitset() {
         let mut bs = BitSet::with_num_bits(1);
@@ -726,5 +3609,136 @@ mod tests {
                 assert!(!bs.get(i), "Unexpected bit {i} = 1");
             }
         }
+
+        let mut bs = BitSet::with_num_bits(130);
+        assert!(bs.is_empty());
+        for i in [129, 3, 64, 70] {
+            bs.set_value(i, true);
+        }
+        bs.clear(70);
+        assert_eq!(bs.count_ones(), 3);
+        assert_eq!(bs.ones().collect::<Vec<_>>(), [3, 64, 129]);
+
+        let mut buf = Vec::new();
+        write_bitset(&mut buf, &bs);
+        assert_eq!(BitSet::read(&mut PacketReader::new(&buf)), Ok(bs));
+
+        // lengths from the wire are checked, not trusted
+        let mut buf = Vec::new();
+        write_varint(&mut buf, -1);
+        assert_eq!(
+            BitSet::read(&mut PacketReader::new(&buf)),
+            Err(DecodeError::BadValue {
+                field: "bitset length",
+                value: -1
+            })
+        );
+        let mut buf = Vec::new();
+        write_varint(&mut buf, i32::MAX);
+        write_long(&mut buf, 1);
+        assert_eq!(
+            BitSet::read(&mut PacketReader::new(&buf)),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn compression() {
+        let mut ps = PacketStream::new(Vec::new());
+        ps.send(OutPacket::SetCompression { threshold: 16 })
+            .unwrap();
+        ps.set_compression(Some(16));
+        let profile = GameProfile::new(Uuid(1), "Steve");
+        ps.send(OutPacket::LoginSuccess { profile: &profile })
+            .unwrap();
+        ps.send(OutPacket::FinishConfig).unwrap();
+
+        let written = std::mem::take(ps.writer_mut());
+        let mut r = &written[..];
+        assert_eq!(read_frame(&mut r).unwrap(), [2, 0x03, 16]);
+        // long enough to be compressed
+        let frame = read_frame(&mut r).unwrap();
+        let login_success = encode_packet(OutPacket::LoginSuccess { profile: &profile }, 764);
+        assert_eq!(
+            decompress_packet(&frame[1..], 16),
+            Ok(login_success.clone())
+        );
+        assert_ne!(frame[1], 0);
+        // too short, so only marked as uncompressed
+        assert_eq!(read_frame(&mut r).unwrap(), [2, 0, 0x02]);
+
+        // incoming frames come out uncompressed
+        let compressed = frame_packet(&[&[0x00, 0x03][..]].concat());
+        assert_eq!(ps.translate_incoming(compressed), Ok(vec![vec![1, 0x03]]));
+        let mut data = Vec::new();
+        write_varint(&mut data, login_success.len() as i32);
+        data.extend(zlib_compress(&login_success));
+        assert_eq!(
+            ps.translate_incoming(frame_packet(&data)),
+            Ok(vec![frame_packet(&login_success)])
+        );
+        // saying it's longer than it is, or compressing something under the threshold
+        data[0] += 1;
+        assert_eq!(
+            ps.translate_incoming(frame_packet(&data)),
+            Err(DecodeError::BadCompression)
+        );
+        let mut data = vec![1];
+        data.extend(zlib_compress(&[0x03]));
+        assert_eq!(
+            ps.translate_incoming(frame_packet(&data)),
+            Err(DecodeError::BadCompression)
+        );
+    }
+
+    #[test]
+    fn frame_truncation() {
+        struct FailingWriter;
+        impl Write for FailingWriter {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::ConnectionReset.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        assert_eq!(read_frame(&mut [2, 0, 0].as_slice()), Ok(vec![2, 0, 0]));
+        assert_eq!(read_frame(&mut [].as_slice()), Err(DisconnectCause::Closed));
+        assert_eq!(
+            read_frame(&mut [5, 0].as_slice()),
+            Err(DisconnectCause::Truncated)
+        );
+        assert_eq!(
+            read_frame(&mut [0x80].as_slice()),
+            Err(DisconnectCause::Truncated)
+        );
+
+        let mut ps = PacketStream::new(Vec::new());
+        let sizes = ps
+            .send_all([
+                OutPacket::FinishConfig,
+                OutPacket::PingResponse { payload: 7 },
+            ])
+            .unwrap();
+        assert_eq!(sizes, [2, 10]);
+        assert_eq!(ps.writer_mut().len(), 12);
+
+        let mut ps = PacketStream::new(FailingWriter);
+        assert!(ps.send(OutPacket::FinishConfig).is_err());
+        assert_eq!(
+            ps.send(OutPacket::FinishConfig).unwrap_err().kind(),
+            std::io::ErrorKind::BrokenPipe
+        );
+
+        // a capture that can't be written stops, and the error is kept for the server
+        let mut ps = PacketStream::new(Vec::new());
+        ps.set_capture(Some(PacketCapture::new(FailingWriter)));
+        assert!(ps.take_capture_error().is_none());
+        ps.send(OutPacket::FinishConfig).unwrap();
+        ps.send(OutPacket::FinishConfig).unwrap();
+        let e = ps.take_capture_error().unwrap();
+        assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset);
+        assert!(ps.take_capture_error().is_none());
     }
 }
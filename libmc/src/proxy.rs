@@ -0,0 +1,300 @@
+//! Proxy mode: libmc sits between a client and a vanilla server, relaying everything unchanged
+//! and logging the packets, for finding out how packets libmc doesn't implement yet are laid out.
+//!
+//! The upstream server has to run with `online-mode=false`, since encrypted connections can't be
+//! read. Compression is fine.
+
+use crate::compress::inflate;
+use crate::*;
+use std::io::{self, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+#[derive(Debug, Clone, Default)]
+pub struct ProxyOptions {
+    /// Where to write a `PacketCapture` of each connection (as `<n>.jsonl`), if anywhere
+    pub capture_dir: Option<PathBuf>,
+    /// Also log clientbound packets and serverbound packets libmc can't decode, by ID and length
+    pub log_undecoded: bool,
+}
+
+/// Accepts clients on `listen` and proxies each of them to `upstream`. Only returns on errors
+/// binding `listen`.
+pub fn run_proxy(
+    listen: impl ToSocketAddrs,
+    upstream: &str,
+    options: ProxyOptions,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    accept_proxied(listener, upstream.to_string(), options);
+    Ok(())
+}
+
+fn accept_proxied(listener: TcpListener, upstream: String, options: ProxyOptions) {
+    for (id, client) in listener.incoming().enumerate() {
+        let Ok(client) = client else {
+            continue;
+        };
+        let upstream = upstream.clone();
+        let options = options.clone();
+        thread::spawn(move || {
+            if let Err(e) = proxy_connection(id, client, &upstream, options) {
+                eprintln!("[{id}] proxying failed: {e}");
+            }
+        });
+    }
+}
+
+fn proxy_connection(
+    id: usize,
+    client: TcpStream,
+    upstream: &str,
+    options: ProxyOptions,
+) -> io::Result<()> {
+    let server = TcpStream::connect(upstream)?;
+    let _ = client.set_nodelay(true);
+    let _ = server.set_nodelay(true);
+    let capture = match &options.capture_dir {
+        Some(dir) => Some(PacketCapture::create(dir.join(format!("{id}.jsonl")))?),
+        None => None,
+    };
+
+    // Frames are queued for logging on this thread right before they're forwarded, so anything
+    // sent in reply to a frame (e.g. compressed packets after Set Compression) is logged after it.
+    let (tx, rx) = mpsc::channel();
+    for (dir, from, to) in [
+        (PacketDirection::Serverbound, &client, &server),
+        (PacketDirection::Clientbound, &server, &client),
+    ] {
+        let (from, to) = (from.try_clone()?, to.try_clone()?);
+        let tx = tx.clone();
+        thread::spawn(move || relay(dir, from, to, tx));
+    }
+    drop(tx);
+
+    let mut session = Session {
+        id,
+        ps: PacketStream::new(io::sink()),
+        compressed: false,
+        capture,
+        log_undecoded: options.log_undecoded,
+    };
+    println!("[{id}] connected");
+    for (dir, frame) in rx {
+        // a packet that can't be unpacked can't be relied on to be forwarded right either
+        if let Err(e) = session.log(dir, &frame) {
+            let _ = client.shutdown(Shutdown::Both);
+            let _ = server.shutdown(Shutdown::Both);
+            return Err(e);
+        }
+    }
+    println!("[{id}] disconnected");
+    Ok(())
+}
+
+/// Bad data from either side, which ends the proxied connection
+fn invalid_data(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Copies frames from one side to the other until either closes
+fn relay(
+    dir: PacketDirection,
+    from: TcpStream,
+    mut to: TcpStream,
+    tx: Sender<(PacketDirection, Vec<u8>)>,
+) {
+    let mut r = BufReader::new(&from);
+    while let Ok(frame) = read_frame(&mut r) {
+        let _ = tx.send((dir, frame.clone()));
+        if to.write_all(&frame).is_err() {
+            break;
+        }
+    }
+    // so the other direction stops too
+    let _ = from.shutdown(Shutdown::Both);
+    let _ = to.shutdown(Shutdown::Both);
+}
+
+fn arrow(dir: PacketDirection) -> &'static str {
+    match dir {
+        PacketDirection::Serverbound => "C->S",
+        PacketDirection::Clientbound => "S->C",
+    }
+}
+
+struct Session {
+    id: usize,
+    /// Tracks the connection's state, by decoding what the client sends
+    ps: PacketStream<io::Sink>,
+    /// Whether the server has enabled compression
+    compressed: bool,
+    capture: Option<PacketCapture>,
+    log_undecoded: bool,
+}
+
+impl Session {
+    fn log(&mut self, dir: PacketDirection, frame: &[u8]) -> io::Result<()> {
+        let packet = self.packet(frame)?;
+        let state = self.ps.state();
+        if let Some(capture) = &mut self.capture {
+            if let Err(e) = capture.record(dir, state, &packet) {
+                eprintln!("[{}] packet capture failed, stopping it: {e}", self.id);
+                self.capture = None;
+            }
+        }
+
+        let mut r = PacketReader::new(&packet);
+        let id = r.varint().map_err(invalid_data)?;
+        match dir {
+            PacketDirection::Serverbound => {
+                let framed = frame_packet(&packet);
+                match self.ps.decode(&framed) {
+                    Ok(InPacket::Unknown { .. }) => self.log_undecoded(dir, state, id, &packet),
+                    Ok(decoded) => println!("[{}] {} {decoded:?}", self.id, arrow(dir)),
+                    Err(e) => println!("[{}] {} malformed packet: {e}", self.id, arrow(dir)),
+                }
+            }
+            PacketDirection::Clientbound => {
+                // Set Compression
+                if state == ProtocolState::Login && id == 0x03 {
+                    let threshold = r.varint().map_err(invalid_data)?;
+                    self.compressed = threshold >= 0;
+                }
+                self.log_undecoded(dir, state, id, &packet);
+            }
+        }
+        Ok(())
+    }
+
+    fn log_undecoded(&self, dir: PacketDirection, state: ProtocolState, id: i32, packet: &[u8]) {
+        if self.log_undecoded {
+            println!(
+                "[{}] {} {state:?} 0x{id:02X} ({} bytes)",
+                self.id,
+                arrow(dir),
+                packet.len()
+            );
+        }
+    }
+
+    /// The packet (ID and body) in a frame, decompressing it if needed
+    fn packet(&self, frame: &[u8]) -> io::Result<Vec<u8>> {
+        let mut r = PacketReader::new(frame);
+        r.varint().map_err(invalid_data)?;
+        if !self.compressed {
+            return Ok(r.rest().to_vec());
+        }
+        if r.varint().map_err(invalid_data)? == 0 {
+            return Ok(r.rest().to_vec());
+        }
+        // zlib: a 2 byte header, DEFLATE data, and a checksum
+        r.slice(2).map_err(invalid_data)?;
+        let (data, _) = inflate(r.rest()).map_err(invalid_data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn relay_and_capture() {
+        let dir = std::env::temp_dir().join(format!("libmc-proxy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_addr = proxy.local_addr().unwrap();
+        let options = ProxyOptions {
+            capture_dir: Some(dir.clone()),
+            log_undecoded: false,
+        };
+        let upstream_addr = upstream.local_addr().unwrap().to_string();
+        thread::spawn(move || accept_proxied(proxy, upstream_addr, options));
+
+        let mut client = TcpStream::connect(proxy_addr).unwrap();
+        let (mut server, _) = upstream.accept().unwrap();
+
+        let mut handshake = vec![0x00];
+        write_varint(&mut handshake, PROTOCOL_VERSION);
+        write_string(&mut handshake, "localhost");
+        handshake.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut handshake, 2);
+        let sent = frame_packet(&handshake);
+        client.write_all(&sent).unwrap();
+        let mut received = vec![0; sent.len()];
+        server.read_exact(&mut received).unwrap();
+        assert_eq!(received, sent, "relayed unchanged");
+
+        // Set Compression, then a Login Success too short to be compressed
+        let sent = [frame_packet(&[0x03, 0x80, 0x02]), vec![3, 0x00, 0x02, 0xAA]].concat();
+        server.write_all(&sent).unwrap();
+        let mut received = vec![0; sent.len()];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(received, sent);
+        drop((client, server));
+
+        let path = dir.join("0.jsonl");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let packets = loop {
+            // the capture is complete once the connection's thread has dropped it
+            let packets = read_capture_file(&path).unwrap_or_default();
+            if packets.len() == 3 || Instant::now() > deadline {
+                break packets;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        let data: Vec<_> = packets
+            .iter()
+            .map(|p| (p.dir, p.state, &p.data[..]))
+            .collect();
+        assert_eq!(
+            data,
+            [
+                (
+                    PacketDirection::Serverbound,
+                    ProtocolState::Handshaking,
+                    &handshake[..]
+                ),
+                (
+                    PacketDirection::Clientbound,
+                    ProtocolState::Login,
+                    &[0x03, 0x80, 0x02][..]
+                ),
+                (
+                    PacketDirection::Clientbound,
+                    ProtocolState::Login,
+                    &[0x02, 0xAA]
+                ),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_frames_are_errors() {
+        let mut session = Session {
+            id: 0,
+            ps: PacketStream::new(io::sink()),
+            compressed: true,
+            capture: None,
+            log_undecoded: false,
+        };
+        // a data length, but no zlib data
+        let frame = frame_packet(&[0x05]);
+        assert_eq!(
+            session.packet(&frame).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        // a zlib header, then garbage
+        let frame = frame_packet(&[0x05, 0x78, 0x9C, 0xFF, 0xFF]);
+        assert!(session.log(PacketDirection::Clientbound, &frame).is_err());
+    }
+}
@@ -0,0 +1,801 @@
+//! RakNet, the UDP transport Bedrock edition runs on: the offline handshake, reliable and ordered
+//! delivery with ACKs and resends, and splitting packets that don't fit in one datagram.
+//!
+//! Only what Bedrock clients use is implemented: one ordering channel, no security, and
+//! `ReliableOrdered` for everything sent.
+
+use crate::util::random_u64;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// The RakNet protocol version Bedrock speaks
+pub const RAKNET_PROTOCOL: u8 = 11;
+
+/// Marks offline (unconnected) messages
+const MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+
+const CONNECTED_PING: u8 = 0x00;
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PING_OPEN: u8 = 0x02;
+const CONNECTED_PONG: u8 = 0x03;
+const OPEN_CONNECTION_REQUEST_1: u8 = 0x05;
+const OPEN_CONNECTION_REPLY_1: u8 = 0x06;
+const OPEN_CONNECTION_REQUEST_2: u8 = 0x07;
+const OPEN_CONNECTION_REPLY_2: u8 = 0x08;
+const CONNECTION_REQUEST: u8 = 0x09;
+const CONNECTION_REQUEST_ACCEPTED: u8 = 0x10;
+const NEW_INCOMING_CONNECTION: u8 = 0x13;
+const DISCONNECTION_NOTIFICATION: u8 = 0x15;
+const INCOMPATIBLE_PROTOCOL_VERSION: u8 = 0x19;
+const UNCONNECTED_PONG: u8 = 0x1C;
+/// Packets from here on are the application's (Bedrock's game packets are 0xFE)
+const USER_PACKET: u8 = 0x80;
+
+const FLAG_VALID: u8 = 0x80;
+const FLAG_ACK: u8 = 0x40;
+const FLAG_NACK: u8 = 0x20;
+const FLAG_SPLIT: u8 = 0x10;
+
+/// UDP and IPv4 headers, which RakNet's MTU includes
+const UDP_OVERHEAD: usize = 28;
+/// Datagram header, and the largest frame header (reliable, ordered and split)
+const FRAME_OVERHEAD: usize = 4 + 1 + 2 + 3 + 3 + 1 + 10;
+const MIN_MTU: usize = 576;
+const MAX_MTU: usize = 1492;
+/// Resent if not ACKed by then
+const RESEND_AFTER: Duration = Duration::from_millis(500);
+/// Split packets with more parts are dropped
+const MAX_SPLIT_PARTS: u32 = 8192;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Reliability {
+    Unreliable,
+    UnreliableSequenced,
+    Reliable,
+    ReliableOrdered,
+    ReliableSequenced,
+}
+
+impl Reliability {
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Unreliable),
+            1 => Some(Self::UnreliableSequenced),
+            2 | 5 => Some(Self::Reliable),
+            3 | 6 => Some(Self::ReliableOrdered),
+            4 | 7 => Some(Self::ReliableSequenced),
+            _ => None,
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Self::Unreliable => 0,
+            Self::UnreliableSequenced => 1,
+            Self::Reliable => 2,
+            Self::ReliableOrdered => 3,
+            Self::ReliableSequenced => 4,
+        }
+    }
+
+    fn is_reliable(self) -> bool {
+        matches!(
+            self,
+            Self::Reliable | Self::ReliableOrdered | Self::ReliableSequenced
+        )
+    }
+
+    fn is_sequenced(self) -> bool {
+        matches!(self, Self::UnreliableSequenced | Self::ReliableSequenced)
+    }
+
+    /// Sequenced packets are ordered too: they carry an order index
+    fn is_ordered(self) -> bool {
+        matches!(self, Self::ReliableOrdered) || self.is_sequenced()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Split {
+    count: u32,
+    id: u16,
+    index: u32,
+}
+
+/// One message, or part of one, in a datagram
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    reliability: Reliability,
+    reliable_index: u32,
+    sequenced_index: u32,
+    order_index: u32,
+    order_channel: u8,
+    split: Option<Split>,
+    body: Vec<u8>,
+}
+
+impl Frame {
+    fn encoded_len(&self) -> usize {
+        let mut len = 3 + self.body.len();
+        if self.reliability.is_reliable() {
+            len += 3;
+        }
+        if self.reliability.is_sequenced() {
+            len += 3;
+        }
+        if self.reliability.is_ordered() {
+            len += 4;
+        }
+        if self.split.is_some() {
+            len += 10;
+        }
+        len
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        let split_flag = if self.split.is_some() { FLAG_SPLIT } else { 0 };
+        buf.push(self.reliability.id() << 5 | split_flag);
+        let bits = u16::try_from(self.body.len() * 8).unwrap();
+        buf.extend_from_slice(&bits.to_be_bytes());
+        if self.reliability.is_reliable() {
+            write_u24(buf, self.reliable_index);
+        }
+        if self.reliability.is_sequenced() {
+            write_u24(buf, self.sequenced_index);
+        }
+        if self.reliability.is_ordered() {
+            write_u24(buf, self.order_index);
+            buf.push(self.order_channel);
+        }
+        if let Some(split) = self.split {
+            buf.extend_from_slice(&split.count.to_be_bytes());
+            buf.extend_from_slice(&split.id.to_be_bytes());
+            buf.extend_from_slice(&split.index.to_be_bytes());
+        }
+        buf.extend_from_slice(&self.body);
+    }
+
+    fn read(r: &mut Reader) -> Option<Self> {
+        let flags = r.u8()?;
+        let reliability = Reliability::from_id(flags >> 5)?;
+        let len = usize::from(r.u16()?).div_ceil(8);
+        let mut frame = Frame {
+            reliability,
+            reliable_index: 0,
+            sequenced_index: 0,
+            order_index: 0,
+            order_channel: 0,
+            split: None,
+            body: Vec::new(),
+        };
+        if reliability.is_reliable() {
+            frame.reliable_index = r.u24()?;
+        }
+        if reliability.is_sequenced() {
+            frame.sequenced_index = r.u24()?;
+        }
+        if reliability.is_ordered() {
+            frame.order_index = r.u24()?;
+            frame.order_channel = r.u8()?;
+        }
+        if flags & FLAG_SPLIT != 0 {
+            frame.split = Some(Split {
+                count: u32::from_be_bytes(r.array()?),
+                id: u16::from_be_bytes(r.array()?),
+                index: u32::from_be_bytes(r.array()?),
+            });
+        }
+        frame.body = r.take(len)?.to_vec();
+        Some(frame)
+    }
+}
+
+/// Reads big-endian fields, and RakNet's little-endian 24-bit ones
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Some(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N).map(|b| b.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.array::<1>().map(|[b]| b)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    fn u24(&mut self) -> Option<u32> {
+        self.array::<3>()
+            .map(|[a, b, c]| u32::from_le_bytes([a, b, c, 0]))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        self.array().map(i64::from_be_bytes)
+    }
+
+    fn magic(&mut self) -> Option<()> {
+        (self.array::<16>()? == MAGIC).then_some(())
+    }
+}
+
+fn write_u24(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&n.to_le_bytes()[..3]);
+}
+
+fn write_addr(buf: &mut Vec<u8>, addr: SocketAddr) {
+    match addr {
+        SocketAddr::V4(a) => {
+            buf.push(4);
+            buf.extend(a.ip().octets().iter().map(|b| !b));
+            buf.extend_from_slice(&a.port().to_be_bytes());
+        }
+        SocketAddr::V6(a) => {
+            buf.push(6);
+            // AF_INET6, as Windows numbers it
+            buf.extend_from_slice(&23u16.to_le_bytes());
+            buf.extend_from_slice(&a.port().to_be_bytes());
+            buf.extend_from_slice(&a.flowinfo().to_be_bytes());
+            buf.extend_from_slice(&a.ip().octets());
+            buf.extend_from_slice(&a.scope_id().to_be_bytes());
+        }
+    }
+}
+
+/// ACK or NACK of datagram sequence numbers, as ranges
+fn write_ack(flag: u8, seqs: &mut Vec<u32>) -> Vec<u8> {
+    seqs.sort_unstable();
+    seqs.dedup();
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &seq in seqs.iter() {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == seq => *end = seq,
+            _ => ranges.push((seq, seq)),
+        }
+    }
+    let mut buf = vec![FLAG_VALID | flag];
+    buf.extend_from_slice(&u16::try_from(ranges.len()).unwrap().to_be_bytes());
+    for (start, end) in ranges {
+        if start == end {
+            buf.push(1);
+            write_u24(&mut buf, start);
+        } else {
+            buf.push(0);
+            write_u24(&mut buf, start);
+            write_u24(&mut buf, end);
+        }
+    }
+    buf
+}
+
+fn read_ack(r: &mut Reader) -> Option<Vec<u32>> {
+    let mut seqs = Vec::new();
+    for _ in 0..r.u16()? {
+        if r.u8()? != 0 {
+            seqs.push(r.u24()?);
+        } else {
+            let (start, end) = (r.u24()?, r.u24()?);
+            // a bogus range shouldn't make us allocate gigabytes
+            seqs.extend((start..=end).take(4096));
+        }
+    }
+    Some(seqs)
+}
+
+/// The reliability layer of one connection: turns messages into datagrams and back
+#[derive(Debug)]
+struct Session {
+    mtu: usize,
+    next_seq: u32,
+    next_reliable_index: u32,
+    next_order_index: u32,
+    next_split_id: u16,
+    /// Frames waiting for the next flush
+    outgoing: Vec<Frame>,
+    /// Sent datagrams that haven't been ACKed, by sequence number
+    unacked: HashMap<u32, (Instant, Vec<Frame>)>,
+    /// Received datagrams to ACK on the next flush
+    to_ack: Vec<u32>,
+    received_reliable: HashSet<u32>,
+    /// Reliable indexes below this have all been received
+    reliable_floor: u32,
+    splits: HashMap<u16, Vec<Option<Vec<u8>>>>,
+    next_expected_order: u32,
+    /// Ordered messages that arrived early, by order index
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+    last_received: Instant,
+}
+
+impl Session {
+    fn new(mtu: usize, now: Instant) -> Self {
+        Self {
+            mtu,
+            next_seq: 0,
+            next_reliable_index: 0,
+            next_order_index: 0,
+            next_split_id: 0,
+            outgoing: Vec::new(),
+            unacked: HashMap::new(),
+            to_ack: Vec::new(),
+            received_reliable: HashSet::new(),
+            reliable_floor: 0,
+            splits: HashMap::new(),
+            next_expected_order: 0,
+            out_of_order: BTreeMap::new(),
+            last_received: now,
+        }
+    }
+
+    /// Queues `body` to be sent, reliable and ordered
+    fn send(&mut self, body: &[u8]) {
+        self.queue(body, Reliability::ReliableOrdered);
+    }
+
+    fn queue(&mut self, body: &[u8], reliability: Reliability) {
+        let max_body = self.mtu - UDP_OVERHEAD - FRAME_OVERHEAD;
+        let order_index = self.next_order_index;
+        if reliability.is_ordered() {
+            self.next_order_index += 1;
+        }
+        let parts: Vec<&[u8]> = if body.len() <= max_body {
+            vec![body]
+        } else {
+            body.chunks(max_body).collect()
+        };
+        let split_id = self.next_split_id;
+        if parts.len() > 1 {
+            self.next_split_id = self.next_split_id.wrapping_add(1);
+        }
+        for (i, part) in parts.iter().enumerate() {
+            let reliable_index = self.next_reliable_index;
+            if reliability.is_reliable() {
+                self.next_reliable_index += 1;
+            }
+            self.outgoing.push(Frame {
+                reliability,
+                reliable_index,
+                sequenced_index: 0,
+                order_index,
+                order_channel: 0,
+                split: (parts.len() > 1).then_some(Split {
+                    count: parts.len() as u32,
+                    id: split_id,
+                    index: i as u32,
+                }),
+                body: part.to_vec(),
+            });
+        }
+    }
+
+    /// The datagrams to send now: ACKs, resends, and queued frames
+    fn flush(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut datagrams = Vec::new();
+        if !self.to_ack.is_empty() {
+            datagrams.push(write_ack(FLAG_ACK, &mut self.to_ack));
+            self.to_ack.clear();
+        }
+
+        let stale: Vec<u32> = self
+            .unacked
+            .iter()
+            .filter(|(_, (sent, _))| now.duration_since(*sent) >= RESEND_AFTER)
+            .map(|(&seq, _)| seq)
+            .collect();
+        for seq in stale {
+            self.resend(seq);
+        }
+
+        let limit = self.mtu - UDP_OVERHEAD;
+        let mut frames = Vec::new();
+        let mut len = 4;
+        for frame in std::mem::take(&mut self.outgoing) {
+            if len + frame.encoded_len() > limit && !frames.is_empty() {
+                datagrams.push(self.datagram(std::mem::take(&mut frames), now));
+                len = 4;
+            }
+            len += frame.encoded_len();
+            frames.push(frame);
+        }
+        if !frames.is_empty() {
+            datagrams.push(self.datagram(frames, now));
+        }
+        datagrams
+    }
+
+    fn datagram(&mut self, frames: Vec<Frame>, now: Instant) -> Vec<u8> {
+        let seq = self.next_seq;
+        self.next_seq = (self.next_seq + 1) & 0xFF_FFFF;
+        let mut buf = vec![FLAG_VALID | 0x04];
+        write_u24(&mut buf, seq);
+        for frame in &frames {
+            frame.write(&mut buf);
+        }
+        if frames.iter().any(|f| f.reliability.is_reliable()) {
+            self.unacked.insert(seq, (now, frames));
+        }
+        buf
+    }
+
+    /// Sends a datagram's frames again, in a new datagram
+    fn resend(&mut self, seq: u32) {
+        if let Some((_, frames)) = self.unacked.remove(&seq) {
+            self.outgoing.extend(frames);
+        }
+    }
+
+    /// Handles a datagram, returning the messages it completed, in order
+    fn receive(&mut self, datagram: &[u8], now: Instant) -> Option<Vec<Vec<u8>>> {
+        self.last_received = now;
+        let mut r = Reader { buf: datagram };
+        let flags = r.u8()?;
+        if flags & FLAG_ACK != 0 {
+            for seq in read_ack(&mut r)? {
+                self.unacked.remove(&seq);
+            }
+            return Some(Vec::new());
+        }
+        if flags & FLAG_NACK != 0 {
+            for seq in read_ack(&mut r)? {
+                self.resend(seq);
+            }
+            return Some(Vec::new());
+        }
+
+        self.to_ack.push(r.u24()?);
+        let mut messages = Vec::new();
+        while !r.buf.is_empty() {
+            let frame = Frame::read(&mut r)?;
+            if frame.reliability.is_reliable() && !self.first_receipt(frame.reliable_index) {
+                continue;
+            }
+            let reliability = frame.reliability;
+            let order_index = frame.order_index;
+            let Some(body) = self.reassemble(frame) else {
+                continue;
+            };
+            if reliability == Reliability::ReliableOrdered {
+                self.out_of_order.insert(order_index, body);
+                while let Some(body) = self.out_of_order.remove(&self.next_expected_order) {
+                    messages.push(body);
+                    self.next_expected_order += 1;
+                }
+            } else {
+                messages.push(body);
+            }
+        }
+        Some(messages)
+    }
+
+    /// Whether a reliable frame is new, rather than a resend of one already received
+    fn first_receipt(&mut self, index: u32) -> bool {
+        if index < self.reliable_floor || !self.received_reliable.insert(index) {
+            return false;
+        }
+        while self.received_reliable.remove(&self.reliable_floor) {
+            self.reliable_floor += 1;
+        }
+        true
+    }
+
+    /// The whole message, once every part of a split one has arrived
+    fn reassemble(&mut self, frame: Frame) -> Option<Vec<u8>> {
+        let Some(split) = frame.split else {
+            return Some(frame.body);
+        };
+        if split.count == 0 || split.count > MAX_SPLIT_PARTS || split.index >= split.count {
+            return None;
+        }
+        let parts = self
+            .splits
+            .entry(split.id)
+            .or_insert_with(|| vec![None; split.count as usize]);
+        *parts.get_mut(split.index as usize)? = Some(frame.body);
+        if parts.iter().any(Option::is_none) {
+            return None;
+        }
+        let parts = self.splits.remove(&split.id)?;
+        Some(parts.into_iter().flatten().flatten().collect())
+    }
+}
+
+#[derive(Debug)]
+struct Peer {
+    session: Session,
+    /// Set once the client sent New Incoming Connection
+    connected: bool,
+}
+
+/// What happened on a `RakNetServer`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RakNetEvent {
+    Connected(SocketAddr),
+    /// A message from the client, starting with its ID (e.g. 0xFE for Bedrock game packets)
+    Message(SocketAddr, Vec<u8>),
+    Disconnected(SocketAddr),
+}
+
+/// Accepts RakNet connections on a UDP socket. Call `poll()` in a loop to receive, and to send
+/// ACKs and resends.
+#[derive(Debug)]
+pub struct RakNetServer {
+    socket: UdpSocket,
+    guid: u64,
+    /// Sent in Unconnected Pongs, for the server list
+    advertisement: String,
+    peers: HashMap<SocketAddr, Peer>,
+    /// Peers that send nothing for this long are disconnected
+    pub timeout: Duration,
+}
+
+impl RakNetServer {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            guid: random_u64(),
+            advertisement: String::new(),
+            peers: HashMap::new(),
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sets what's sent in reply to pings (for Bedrock, see `BedrockFrontend::set_motd()`)
+    pub fn set_advertisement(&mut self, advertisement: String) {
+        self.advertisement = advertisement;
+    }
+
+    pub fn guid(&self) -> u64 {
+        self.guid
+    }
+
+    /// Sends `message` to `addr`, reliable and ordered. Returns false if it isn't connected.
+    pub fn send(&mut self, addr: SocketAddr, message: &[u8]) -> bool {
+        let Some(peer) = self.peers.get_mut(&addr) else {
+            return false;
+        };
+        peer.session.send(message);
+        true
+    }
+
+    pub fn disconnect(&mut self, addr: SocketAddr) {
+        if let Some(mut peer) = self.peers.remove(&addr) {
+            peer.session
+                .queue(&[DISCONNECTION_NOTIFICATION], Reliability::Reliable);
+            for datagram in peer.session.flush(Instant::now()) {
+                let _ = self.socket.send_to(&datagram, addr);
+            }
+        }
+    }
+
+    /// Receives for up to `timeout`, then sends what's queued
+    pub fn poll(&mut self, timeout: Duration) -> io::Result<Vec<RakNetEvent>> {
+        let mut events = Vec::new();
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0; MAX_MTU];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) => self.received(&buf[..len], from, &mut events),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    break;
+                }
+                // e.g. ICMP port unreachable for a client that went away, on Windows
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let now = Instant::now();
+        let timed_out: Vec<SocketAddr> = self
+            .peers
+            .iter()
+            .filter(|(_, p)| now.duration_since(p.session.last_received) > self.timeout)
+            .map(|(&addr, _)| addr)
+            .collect();
+        for addr in timed_out {
+            self.peers.remove(&addr);
+            events.push(RakNetEvent::Disconnected(addr));
+        }
+        for (&addr, peer) in &mut self.peers {
+            for datagram in peer.session.flush(now) {
+                self.socket.send_to(&datagram, addr)?;
+            }
+        }
+        Ok(events)
+    }
+
+    fn received(&mut self, datagram: &[u8], from: SocketAddr, events: &mut Vec<RakNetEvent>) {
+        if datagram.first().is_some_and(|&b| b & FLAG_VALID != 0) {
+            if let Some(peer) = self.peers.get_mut(&from) {
+                let messages = peer.session.receive(datagram, Instant::now());
+                for message in messages.unwrap_or_default() {
+                    self.handle_message(from, message, events);
+                }
+            }
+            return;
+        }
+        if let Some(reply) = self.offline_reply(datagram, from) {
+            let _ = self.socket.send_to(&reply, from);
+        }
+    }
+
+    /// The reply to an unconnected message, opening a connection if it's the last step
+    fn offline_reply(&mut self, packet: &[u8], from: SocketAddr) -> Option<Vec<u8>> {
+        let mut r = Reader { buf: packet };
+        let mut reply = Vec::new();
+        match r.u8()? {
+            UNCONNECTED_PING | UNCONNECTED_PING_OPEN => {
+                let time = r.i64()?;
+                r.magic()?;
+                reply.push(UNCONNECTED_PONG);
+                reply.extend_from_slice(&time.to_be_bytes());
+                reply.extend_from_slice(&self.guid.to_be_bytes());
+                reply.extend_from_slice(&MAGIC);
+                let advertisement = self.advertisement.as_bytes();
+                reply.extend_from_slice(&u16::try_from(advertisement.len()).ok()?.to_be_bytes());
+                reply.extend_from_slice(advertisement);
+            }
+            OPEN_CONNECTION_REQUEST_1 => {
+                r.magic()?;
+                if r.u8()? != RAKNET_PROTOCOL {
+                    reply.push(INCOMPATIBLE_PROTOCOL_VERSION);
+                    reply.push(RAKNET_PROTOCOL);
+                    reply.extend_from_slice(&MAGIC);
+                    reply.extend_from_slice(&self.guid.to_be_bytes());
+                    return Some(reply);
+                }
+                // the request is padded to the MTU the client wants
+                let mtu = (packet.len() + UDP_OVERHEAD).clamp(MIN_MTU, MAX_MTU);
+                reply.push(OPEN_CONNECTION_REPLY_1);
+                reply.extend_from_slice(&MAGIC);
+                reply.extend_from_slice(&self.guid.to_be_bytes());
+                // no security
+                reply.push(0);
+                reply.extend_from_slice(&(mtu as u16).to_be_bytes());
+            }
+            OPEN_CONNECTION_REQUEST_2 => {
+                r.magic()?;
+                // the server's address, which we know
+                match r.u8()? {
+                    4 => r.take(6)?,
+                    6 => r.take(28)?,
+                    _ => return None,
+                };
+                let mtu = usize::from(r.u16()?).clamp(MIN_MTU, MAX_MTU);
+                reply.push(OPEN_CONNECTION_REPLY_2);
+                reply.extend_from_slice(&MAGIC);
+                reply.extend_from_slice(&self.guid.to_be_bytes());
+                write_addr(&mut reply, from);
+                reply.extend_from_slice(&(mtu as u16).to_be_bytes());
+                // no encryption
+                reply.push(0);
+                self.peers.insert(
+                    from,
+                    Peer {
+                        session: Session::new(mtu, Instant::now()),
+                        connected: false,
+                    },
+                );
+            }
+            _ => return None,
+        }
+        Some(reply)
+    }
+
+    fn handle_message(
+        &mut self,
+        from: SocketAddr,
+        message: Vec<u8>,
+        events: &mut Vec<RakNetEvent>,
+    ) {
+        let Some(peer) = self.peers.get_mut(&from) else {
+            return;
+        };
+        let mut r = Reader { buf: &message };
+        let Some(id) = r.u8() else {
+            return;
+        };
+        match id {
+            CONNECTED_PING => {
+                let Some(time) = r.i64() else {
+                    return;
+                };
+                let mut pong = vec![CONNECTED_PONG];
+                pong.extend_from_slice(&time.to_be_bytes());
+                pong.extend_from_slice(&time.to_be_bytes());
+                peer.session.queue(&pong, Reliability::Unreliable);
+            }
+            CONNECTION_REQUEST => {
+                // client GUID, then the time
+                let Some(time) = r.take(8).and_then(|_| r.i64()) else {
+                    return;
+                };
+                let mut accepted = vec![CONNECTION_REQUEST_ACCEPTED];
+                write_addr(&mut accepted, from);
+                // system index, and the server's internal addresses
+                accepted.extend_from_slice(&0u16.to_be_bytes());
+                let unspecified = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+                for _ in 0..10 {
+                    write_addr(&mut accepted, unspecified);
+                }
+                accepted.extend_from_slice(&time.to_be_bytes());
+                accepted.extend_from_slice(&time.to_be_bytes());
+                peer.session.queue(&accepted, Reliability::Reliable);
+            }
+            NEW_INCOMING_CONNECTION if !peer.connected => {
+                peer.connected = true;
+                events.push(RakNetEvent::Connected(from));
+            }
+            DISCONNECTION_NOTIFICATION => {
+                self.peers.remove(&from);
+                events.push(RakNetEvent::Disconnected(from));
+            }
+            id if id >= USER_PACKET && peer.connected => {
+                events.push(RakNetEvent::Message(from, message));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_and_reorder() {
+        let now = Instant::now();
+        let mut a = Session::new(MIN_MTU, now);
+        let mut b = Session::new(MIN_MTU, now);
+        let big: Vec<u8> = (0..2000u32).map(|i| i as u8).collect();
+        a.send(&[0xFE, 1]);
+        a.send(&big);
+        a.send(&[0xFE, 2]);
+
+        let mut datagrams = a.flush(now);
+        assert!(datagrams.len() > 3, "the big message is split");
+        // delivered in order even though they arrive backwards, and only once
+        datagrams.reverse();
+        datagrams.push(datagrams[0].clone());
+        let received: Vec<Vec<u8>> = datagrams
+            .iter()
+            .flat_map(|d| b.receive(d, now).unwrap())
+            .collect();
+        assert_eq!(received, [vec![0xFE, 1], big, vec![0xFE, 2]]);
+
+        // the ACK clears everything a is waiting on
+        let acks = b.flush(now);
+        assert_eq!(acks.len(), 1);
+        a.receive(&acks[0], now).unwrap();
+        assert!(a.unacked.is_empty());
+        assert!(a.flush(now + RESEND_AFTER).is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::util::random_u64;
+use crate::*;
+
+/// Vanilla's default `randomTickSpeed` gamerule
+pub const DEFAULT_RANDOM_TICK_SPEED: u32 = 3;
+
+/// Picks blocks to random tick, like vanilla does for crop growth, fire spread, leaf decay, ice
+/// melting and so on: every tick, `speed` random blocks in each section of each ticking chunk.
+/// Sections that are all air are skipped.
+///
+/// Call `tick()` from `Server::tick()`, with the chunks in simulation distance
+/// (`ChunkTickets::chunks_at(ChunkLevel::BlockTicking)`), or every loaded chunk.
+#[derive(Debug, Clone)]
+pub struct RandomTicker {
+    speed: u32,
+    /// xorshift64* state, never 0
+    rng: u64,
+}
+
+impl Default for RandomTicker {
+    fn default() -> Self {
+        Self::new(DEFAULT_RANDOM_TICK_SPEED)
+    }
+}
+
+impl RandomTicker {
+    pub fn new(speed: u32) -> Self {
+        Self {
+            speed,
+            rng: random_u64() | 1,
+        }
+    }
+
+    /// Blocks ticked per section per tick. 0 turns random ticks off.
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed = speed;
+    }
+
+    /// Follows the `randomTickSpeed` game rule. Call it again when the rules change.
+    pub fn apply_game_rules(&mut self, rules: &GameRules) {
+        self.speed = rules.random_tick_speed();
+    }
+
+    fn next(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Picks this tick's blocks in `chunks` (the ones loaded in `store`), then calls `f` with the
+    /// store, each block's position and its state. `f` can change whatever it likes, e.g. spread
+    /// fire into the next chunk over. Returns how many blocks were ticked.
+    pub fn tick(
+        &mut self,
+        store: &mut ChunkStore,
+        chunks: impl IntoIterator<Item = ChunkPos>,
+        mut f: impl FnMut(&mut ChunkStore, Position, u32),
+    ) -> usize {
+        let picked = self.pick(store, chunks);
+        for &pos in &picked {
+            // an earlier tick may have changed it
+            if let Some(state) = store.block(&pos) {
+                f(store, pos, state);
+            }
+        }
+        picked.len()
+    }
+
+    /// This tick's blocks in `chunks`, without ticking them
+    pub fn pick(
+        &mut self,
+        store: &ChunkStore,
+        chunks: impl IntoIterator<Item = ChunkPos>,
+    ) -> Vec<Position> {
+        let mut picked = Vec::new();
+        for pos in chunks {
+            let Some(chunk) = store.get(pos) else {
+                continue;
+            };
+            for (i, section) in chunk.sections().iter().enumerate() {
+                if section.block_count() == 0 {
+                    continue;
+                }
+                let bottom = chunk.min_y() + 16 * i as i32;
+                for _ in 0..self.speed {
+                    let r = self.next();
+                    picked.push(Position {
+                        x: pos.x * 16 + (r & 15) as i32,
+                        y: (bottom + (r >> 8 & 15) as i32) as i16,
+                        z: pos.z * 16 + (r >> 4 & 15) as i32,
+                    });
+                }
+            }
+        }
+        picked
+    }
+
+    /// Like `tick()`, posting a `RandomTickEvent` to the plugins for each block and setting
+    /// the blocks that they change
+    pub fn tick_plugins(
+        &mut self,
+        store: &mut ChunkStore,
+        chunks: impl IntoIterator<Item = ChunkPos>,
+        plugins: &mut PluginManager,
+    ) -> usize {
+        self.tick(store, chunks, |store, pos, state| {
+            let mut event = RandomTickEvent::new(pos, state);
+            if plugins.post(&mut event) {
+                if let Some(new_state) = event.new_state {
+                    store.set_block(&pos, new_state);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_non_empty_sections() {
+        const WHEAT: u32 = 4278;
+        let mut store = ChunkStore::new(-64, 384, 0, 64);
+        for x in 0..2 {
+            store.load(ChunkPos::new(x, 0));
+        }
+        // fill the section at y 0 to 15 of one chunk with wheat
+        let chunk = store.get_mut(ChunkPos::new(1, 0)).unwrap();
+        for i in 0..4096 {
+            chunk.set_block(i % 16, (i / 256) as i32, i / 16 % 16, WHEAT);
+        }
+
+        let mut ticker = RandomTicker::default();
+        let mut ticked = Vec::new();
+        let chunks: Vec<_> = store.positions().collect();
+        let n = ticker.tick(&mut store, chunks, |_, pos, state| {
+            ticked.push((pos, state))
+        });
+        assert_eq!(n, 3);
+        for (pos, state) in ticked {
+            assert_eq!(state, WHEAT);
+            assert!((16..32).contains(&pos.x) && (0..16).contains(&pos.y));
+        }
+
+        struct Grow;
+        impl Plugin for Grow {
+            fn name(&self) -> &str {
+                "grow"
+            }
+
+            fn on_enable(&mut self, ctx: &mut PluginContext<'_>) {
+                ctx.subscribe(Priority::Normal, |e: &mut RandomTickEvent| {
+                    e.new_state = Some(e.state + 1);
+                });
+            }
+        }
+        let mut plugins = PluginManager::new();
+        plugins.register(Box::new(Grow)).unwrap();
+        ticker.set_speed(4096);
+        ticker.tick_plugins(&mut store, [ChunkPos::new(1, 0)], &mut plugins);
+        let grown = (0..4096)
+            .filter(|i| {
+                store.block(&Position {
+                    x: 16 + i % 16,
+                    y: (i / 256) as i16,
+                    z: i / 16 % 16,
+                }) != Some(WHEAT)
+            })
+            .count();
+        assert!(grown > 1000, "{grown} grew");
+    }
+}
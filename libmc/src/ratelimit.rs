@@ -0,0 +1,115 @@
+use crate::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// How much a client may send before it's kicked, over any one second
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PacketRateLimits {
+    pub max_packets_per_second: Option<usize>,
+    pub max_bytes_per_second: Option<usize>,
+}
+
+impl Default for PacketRateLimits {
+    /// Generous enough for any legitimate client
+    fn default() -> Self {
+        Self {
+            max_packets_per_second: Some(500),
+            max_bytes_per_second: Some(1024 * 1024),
+        }
+    }
+}
+
+impl PacketRateLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_packets_per_second: None,
+            max_bytes_per_second: None,
+        }
+    }
+}
+
+/// Tracks what one client sends, against `PacketRateLimits`
+#[derive(Debug)]
+pub struct PacketRateTracker<C: Clock = SystemClock> {
+    limits: PacketRateLimits,
+    /// (when, bytes) of the packets received during the last `WINDOW`
+    recent: VecDeque<(Instant, usize)>,
+    window_bytes: usize,
+    clock: C,
+}
+
+impl PacketRateTracker {
+    pub fn new(limits: PacketRateLimits) -> Self {
+        Self::with_clock(limits, SystemClock)
+    }
+}
+
+impl<C: Clock> PacketRateTracker<C> {
+    pub fn with_clock(limits: PacketRateLimits, clock: C) -> Self {
+        Self {
+            limits,
+            recent: VecDeque::new(),
+            window_bytes: 0,
+            clock,
+        }
+    }
+
+    /// Records a received packet of `bytes` bytes. Returns false if the client is now over a limit.
+    pub fn record(&mut self, bytes: usize) -> bool {
+        let now = self.clock.now();
+        while let Some(&(t, n)) = self.recent.front() {
+            if now - t < WINDOW {
+                break;
+            }
+            self.window_bytes -= n;
+            self.recent.pop_front();
+        }
+        self.recent.push_back((now, bytes));
+        self.window_bytes += bytes;
+
+        let PacketRateLimits {
+            max_packets_per_second,
+            max_bytes_per_second,
+        } = self.limits;
+        max_packets_per_second.is_none_or(|max| self.recent.len() <= max)
+            && max_bytes_per_second.is_none_or(|max| self.window_bytes <= max)
+    }
+
+    /// Packets received during the last second
+    pub fn packets_per_second(&self) -> usize {
+        self.recent.len()
+    }
+
+    /// Bytes received during the last second
+    pub fn bytes_per_second(&self) -> usize {
+        self.window_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spam() {
+        let clock = ManualClock::new();
+        let mut t = PacketRateTracker::with_clock(
+            PacketRateLimits {
+                max_packets_per_second: Some(3),
+                max_bytes_per_second: Some(100),
+            },
+            clock.clone(),
+        );
+        assert!(t.record(10));
+        assert!(t.record(10));
+        assert!(t.record(10));
+        assert!(!t.record(10), "4th packet within a second");
+
+        clock.advance(WINDOW);
+        assert!(t.record(100));
+        assert_eq!(t.bytes_per_second(), 100);
+        assert!(!t.record(1), "101 bytes within a second");
+    }
+}
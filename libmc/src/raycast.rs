@@ -0,0 +1,169 @@
+use crate::*;
+
+/// How far survival players can reach blocks (creative players reach 5.0)
+pub const SURVIVAL_BLOCK_REACH: f64 = 4.5;
+
+/// Where a ray hit a block
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RaycastHit {
+    pub block: Position,
+    /// The face that was hit, as in `InPacket::PlayerAction`
+    pub face: i8,
+    /// The exact point that was hit
+    pub point: [f64; 3],
+    /// From the ray's origin to `point`
+    pub distance: f64,
+}
+
+impl RaycastHit {
+    /// Where in the block the ray hit, as in `InPacket::UseItemOn::cursor`
+    pub fn cursor(&self) -> [f32; 3] {
+        let corner = [self.block.x, i32::from(self.block.y), self.block.z];
+        std::array::from_fn(|i| (self.point[i] - f64::from(corner[i])) as f32)
+    }
+}
+
+/// Follows a ray from `origin` (e.g. a player's eyes) along `direction` (e.g. `look_vector()`)
+/// through the block grid, for up to `reach` blocks, and returns the first solid block it hits.
+/// `is_solid` is asked about each block the ray passes through, nearest first.
+pub fn raycast(
+    origin: [f64; 3],
+    direction: [f64; 3],
+    reach: f64,
+    mut is_solid: impl FnMut(Position) -> bool,
+) -> Option<RaycastHit> {
+    raycast_boxes(origin, direction, reach, |pos| {
+        if is_solid(pos) {
+            std::slice::from_ref(&Aabb::FULL_BLOCK)
+        } else {
+            &[]
+        }
+    })
+}
+
+impl BlockShapes {
+    /// Like `raycast()`, but hits blocks' actual shapes, so e.g. a ray can pass over a slab.
+    /// `block_at` gives the state at a block position.
+    pub fn raycast(
+        &self,
+        origin: [f64; 3],
+        direction: [f64; 3],
+        reach: f64,
+        mut block_at: impl FnMut(Position) -> i32,
+    ) -> Option<RaycastHit> {
+        raycast_boxes(origin, direction, reach, |pos| self.shape(block_at(pos)))
+    }
+}
+
+/// Walks the blocks along the ray in order (Amanatides & Woo's algorithm),
+/// testing the ray against the boxes `shape_at` gives for each of them
+fn raycast_boxes<'s>(
+    origin: [f64; 3],
+    direction: [f64; 3],
+    reach: f64,
+    mut shape_at: impl FnMut(Position) -> &'s [Aabb],
+) -> Option<RaycastHit> {
+    let len = direction.iter().map(|d| d * d).sum::<f64>().sqrt();
+    if len == 0.0 {
+        return None;
+    }
+    let dir = direction.map(|d| d / len);
+
+    let mut cell = origin.map(|o| o.floor() as i32);
+    let step = dir.map(|d| if d > 0.0 { 1 } else { -1 });
+    // how far along the ray the next block boundary on each axis is
+    let mut t_max: [f64; 3] = std::array::from_fn(|i| {
+        let boundary = f64::from(cell[i]) + if dir[i] > 0.0 { 1.0 } else { 0.0 };
+        if dir[i] == 0.0 {
+            f64::INFINITY
+        } else {
+            (boundary - origin[i]) / dir[i]
+        }
+    });
+    let t_delta = dir.map(|d| (1.0 / d).abs());
+
+    let mut t = 0.0;
+    while t <= reach {
+        let block = Position::new(cell[0], cell[1], cell[2]).ok()?;
+        let offset = cell.map(f64::from);
+        let hit = shape_at(block)
+            .iter()
+            .filter_map(|b| b.offset(offset).ray_entry(origin, dir))
+            .filter(|&(t, _)| t <= reach)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((distance, axis)) = hit {
+            // from inside a box, say it hit the side facing back along the ray
+            let axis = axis.unwrap_or_else(|| {
+                (0..3)
+                    .max_by(|&a, &b| dir[a].abs().total_cmp(&dir[b].abs()))
+                    .unwrap()
+            });
+            return Some(RaycastHit {
+                block,
+                face: face(axis, dir[axis]),
+                point: std::array::from_fn(|i| origin[i] + dir[i] * distance),
+                distance,
+            });
+        }
+
+        let axis = (0..3)
+            .min_by(|&a, &b| t_max[a].total_cmp(&t_max[b]))
+            .unwrap();
+        t = t_max[axis];
+        cell[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+    }
+    None
+}
+
+/// The face a ray going along `axis` in direction `d` enters a box through
+fn face(axis: usize, d: f64) -> i8 {
+    match (axis, d > 0.0) {
+        (0, true) => 4,
+        (0, false) => 5,
+        (1, true) => 0,
+        (1, false) => 1,
+        (2, true) => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looking_at_blocks() {
+        // ground at y = 63, and a bottom slab at (2, 64, 0)
+        let solid = |p: Position| p.y == 63;
+        let eyes = [0.5, 65.62, 0.5];
+
+        let down = raycast(eyes, look_vector(0.0, 90.0), SURVIVAL_BLOCK_REACH, solid).unwrap();
+        assert_eq!(down.block, Position { x: 0, y: 63, z: 0 });
+        assert_eq!(down.face, 1);
+        assert!((down.distance - 1.62).abs() < 1e-9);
+        assert!((down.cursor()[1] - 1.0).abs() < 1e-6);
+
+        // straight ahead (+z) there's nothing within reach
+        assert_eq!(raycast(eyes, [0.0, 0.0, 1.0], 4.5, solid), None);
+
+        // looking at the slab from the west, slightly down
+        let mut shapes = BlockShapes::new();
+        shapes.set(0, Vec::new());
+        shapes.set(2, vec![Aabb::new([0.0; 3], [1.0, 0.5, 1.0])]);
+        let block_at = |p: Position| match (p.x, p.y, p.z) {
+            (_, 63, _) => 1,
+            (2, 64, 0) => 2,
+            _ => 0,
+        };
+        let dir = [1.0, -0.5, 0.0];
+        let hit = shapes
+            .raycast([0.5, 65.5, 0.5], dir, 5.0, block_at)
+            .unwrap();
+        assert_eq!(hit.block, Position { x: 2, y: 64, z: 0 });
+        assert_eq!(hit.face, 1);
+        // a full block there would be hit on its west side
+        let hit = raycast([0.5, 65.5, 0.5], dir, 5.0, |p| block_at(p) != 0).unwrap();
+        assert_eq!(hit.face, 4);
+    }
+}
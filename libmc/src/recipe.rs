@@ -0,0 +1,718 @@
+use crate::*;
+use std::fs;
+use std::path::Path;
+
+/// Items that can go in one slot of a recipe: item identifiers, and tags written `#minecraft:planks`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Ingredient(pub Vec<String>);
+
+impl Ingredient {
+    fn from_json(json: &Json) -> Result<Self, RegistryError> {
+        let choice = |j: &Json| {
+            if let Some(item) = j.get("item").and_then(Json::as_str) {
+                Ok(item.to_owned())
+            } else if let Some(tag) = j.get("tag").and_then(Json::as_str) {
+                Ok(format!("#{tag}"))
+            } else {
+                Err(RegistryError::BadReport("bad ingredient"))
+            }
+        };
+        match json.as_array() {
+            Some(choices) => choices
+                .iter()
+                .map(choice)
+                .collect::<Result<_, _>>()
+                .map(Self),
+            None => Ok(Self(vec![choice(json)?])),
+        }
+    }
+
+    /// Whether `item` (an identifier) can be used for this ingredient
+    pub fn matches(&self, item: &str, tags: &TagRegistry) -> bool {
+        self.0.iter().any(|choice| match choice.strip_prefix('#') {
+            Some(tag) => tags.contains("minecraft:item", tag, item),
+            None => choice == item,
+        })
+    }
+
+    /// The protocol IDs of every item that can be used, with tags expanded
+    fn item_ids(&self, items: &ItemRegistry, tags: &TagRegistry) -> Vec<i32> {
+        let mut ids = Vec::new();
+        for choice in &self.0 {
+            let names = match choice.strip_prefix('#') {
+                Some(tag) => tags.entries("minecraft:item", tag),
+                None => vec![choice.as_str()],
+            };
+            ids.extend(names.into_iter().filter_map(|name| items.id(name)));
+        }
+        ids
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecipeKind {
+    /// A crafting recipe with its ingredients in a pattern, row by row (None for empty slots)
+    Shaped {
+        width: usize,
+        height: usize,
+        pattern: Vec<Option<Ingredient>>,
+        show_notification: bool,
+    },
+    /// A crafting recipe whose ingredients can be anywhere in the grid
+    Shapeless {
+        ingredients: Vec<Ingredient>,
+    },
+    /// Smelting, blasting, smoking and campfire cooking (see `Recipe::recipe_type`)
+    Cooking {
+        ingredient: Ingredient,
+        experience: f32,
+        /// In ticks
+        cooking_time: i32,
+    },
+    Stonecutting {
+        ingredient: Ingredient,
+    },
+}
+
+/// A recipe as data packs define it. Recipes of other kinds (smithing, and vanilla's special
+/// crafting recipes like map cloning) aren't supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recipe {
+    /// e.g. `minecraft:ladder`
+    pub id: String,
+    /// e.g. `minecraft:crafting_shaped`
+    pub recipe_type: String,
+    /// Recipes with the same group are shown together in the recipe book
+    pub group: String,
+    /// The recipe book tab: `building`, `redstone`, `equipment` or `misc` for crafting, and `food`,
+    /// `blocks` or `misc` for cooking
+    pub category: String,
+    pub kind: RecipeKind,
+    /// The item made, and how many
+    pub result: (String, i8),
+}
+
+impl Recipe {
+    /// Reads a recipe from its data pack JSON. Returns None for recipe types that aren't supported.
+    pub fn from_json(id: &str, json: &str) -> Result<Option<Self>, RegistryError> {
+        let json = Json::parse(json).map_err(RegistryError::Json)?;
+        let bad = RegistryError::BadReport;
+        let recipe_type = json
+            .get("type")
+            .and_then(Json::as_str)
+            .ok_or(bad("recipe without a type"))?;
+        let str_field = |key| json.get(key).and_then(Json::as_str);
+        let ingredient =
+            || Ingredient::from_json(json.get("ingredient").ok_or(bad("no ingredient"))?);
+        let count = |j: &Json| j.get("count").and_then(Json::as_f64).map_or(1, |c| c as i8);
+
+        let kind = match recipe_type {
+            "minecraft:crafting_shaped" => {
+                let rows: Vec<&str> = json
+                    .get("pattern")
+                    .and_then(Json::as_array)
+                    .ok_or(bad("no pattern"))?
+                    .iter()
+                    .map(|row| row.as_str().ok_or(bad("bad pattern")))
+                    .collect::<Result<_, _>>()?;
+                let key = json
+                    .get("key")
+                    .and_then(Json::as_object)
+                    .ok_or(bad("no key"))?;
+                let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+                let mut pattern = Vec::new();
+                for row in &rows {
+                    let mut cells: Vec<Option<Ingredient>> = row
+                        .chars()
+                        .map(|c| match c {
+                            ' ' => Ok(None),
+                            c => {
+                                let (_, ingredient) = key
+                                    .iter()
+                                    .find(|(k, _)| k.chars().eq([c]))
+                                    .ok_or(bad("pattern symbol not in key"))?;
+                                Ingredient::from_json(ingredient).map(Some)
+                            }
+                        })
+                        .collect::<Result<_, _>>()?;
+                    cells.resize(width, None);
+                    pattern.extend(cells);
+                }
+                RecipeKind::Shaped {
+                    width,
+                    height: rows.len(),
+                    pattern,
+                    show_notification: json.get("show_notification").and_then(Json::as_bool)
+                        != Some(false),
+                }
+            }
+            "minecraft:crafting_shapeless" => RecipeKind::Shapeless {
+                ingredients: json
+                    .get("ingredients")
+                    .and_then(Json::as_array)
+                    .ok_or(bad("no ingredients"))?
+                    .iter()
+                    .map(Ingredient::from_json)
+                    .collect::<Result<_, _>>()?,
+            },
+            "minecraft:smelting"
+            | "minecraft:blasting"
+            | "minecraft:smoking"
+            | "minecraft:campfire_cooking" => RecipeKind::Cooking {
+                ingredient: ingredient()?,
+                experience: json.get("experience").and_then(Json::as_f64).unwrap_or(0.0) as f32,
+                cooking_time: json
+                    .get("cookingtime")
+                    .and_then(Json::as_f64)
+                    .map_or(200, |t| t as i32),
+            },
+            "minecraft:stonecutting" => RecipeKind::Stonecutting {
+                ingredient: ingredient()?,
+            },
+            _ => return Ok(None),
+        };
+
+        let result = json.get("result").ok_or(bad("no result"))?;
+        let result = match result.as_str() {
+            // cooking and stonecutting results are just the item, with the count beside it
+            Some(item) => (item.to_owned(), count(&json)),
+            None => (
+                result
+                    .get("item")
+                    .and_then(Json::as_str)
+                    .ok_or(bad("bad result"))?
+                    .to_owned(),
+                count(result),
+            ),
+        };
+        let default_category = match kind {
+            RecipeKind::Stonecutting { .. } => "",
+            _ => "misc",
+        };
+        Ok(Some(Self {
+            id: id.to_owned(),
+            recipe_type: recipe_type.to_owned(),
+            group: str_field("group").unwrap_or("").to_owned(),
+            category: str_field("category").unwrap_or(default_category).to_owned(),
+            kind,
+            result,
+        }))
+    }
+
+    /// Whether a crafting grid `grid_width` slots wide (holding item identifiers) makes this
+    /// recipe. Shaped recipes can be anywhere in the grid, and mirrored.
+    pub fn matches_grid(
+        &self,
+        grid: &[Option<&str>],
+        grid_width: usize,
+        tags: &TagRegistry,
+    ) -> bool {
+        match &self.kind {
+            RecipeKind::Shaped {
+                width,
+                height,
+                pattern,
+                ..
+            } => {
+                let Some((min_x, min_y, w, h)) = occupied_bounds(grid, grid_width) else {
+                    return false;
+                };
+                if (w, h) != (*width, *height) {
+                    return false;
+                }
+                let matches = |mirrored: bool| {
+                    (0..h).all(|y| {
+                        (0..w).all(|x| {
+                            let px = if mirrored { w - 1 - x } else { x };
+                            let item = grid[(min_y + y) * grid_width + min_x + x];
+                            match (&pattern[y * w + px], item) {
+                                (None, None) => true,
+                                (Some(ingredient), Some(item)) => ingredient.matches(item, tags),
+                                _ => false,
+                            }
+                        })
+                    })
+                };
+                matches(false) || matches(true)
+            }
+            RecipeKind::Shapeless { ingredients } => {
+                let mut items: Vec<&str> = grid.iter().flatten().copied().collect();
+                if items.len() != ingredients.len() {
+                    return false;
+                }
+                // greedy, which is good enough unless ingredients overlap in awkward ways
+                ingredients.iter().all(|ingredient| {
+                    match items.iter().position(|item| ingredient.matches(item, tags)) {
+                        Some(i) => {
+                            items.swap_remove(i);
+                            true
+                        }
+                        None => false,
+                    }
+                })
+            }
+            RecipeKind::Cooking { .. } | RecipeKind::Stonecutting { .. } => false,
+        }
+    }
+}
+
+/// (x, y, width, height) of the non-empty part of a crafting grid
+fn occupied_bounds(
+    grid: &[Option<&str>],
+    grid_width: usize,
+) -> Option<(usize, usize, usize, usize)> {
+    let filled: Vec<(usize, usize)> = grid
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.is_some())
+        .map(|(i, _)| (i % grid_width, i / grid_width))
+        .collect();
+    let min_x = filled.iter().map(|p| p.0).min()?;
+    let max_x = filled.iter().map(|p| p.0).max()?;
+    let min_y = filled.iter().map(|p| p.1).min()?;
+    let max_y = filled.iter().map(|p| p.1).max()?;
+    Some((min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+/// What crafting once with a grid does, from `Recipes::craft()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crafted<'r, 'a> {
+    pub recipe: &'r Recipe,
+    /// The stack that goes in the result slot
+    pub result: Slot<'static>,
+    /// What's left in each slot of the grid: one of each item used up, or replaced by its
+    /// container (an empty bucket for a milk bucket, and so on)
+    pub remaining: Vec<Option<Slot<'a>>>,
+    /// Containers that didn't fit back in their slot because it wasn't used up. Vanilla gives
+    /// these to the player, or drops them if their inventory is full.
+    pub leftovers: Vec<Slot<'static>>,
+}
+
+/// The item left behind in the grid when `item` is used in a crafting recipe
+fn crafting_remainder(item: &str) -> Option<&'static str> {
+    match item {
+        "minecraft:milk_bucket"
+        | "minecraft:water_bucket"
+        | "minecraft:lava_bucket"
+        | "minecraft:powder_snow_bucket" => Some("minecraft:bucket"),
+        "minecraft:honey_bottle" | "minecraft:dragon_breath" => Some("minecraft:glass_bottle"),
+        _ => None,
+    }
+}
+
+/// All of a server's recipes
+#[derive(Debug, Clone, Default)]
+pub struct Recipes {
+    recipes: Vec<Recipe>,
+}
+
+impl Recipes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a recipe, replacing the one with the same ID
+    pub fn add(&mut self, recipe: Recipe) {
+        self.recipes.retain(|r| r.id != recipe.id);
+        self.recipes.push(recipe);
+    }
+
+    /// Loads the recipes of a data pack's `data` folder, such as the one the vanilla server jar
+    /// extracts. Unsupported recipe types are skipped.
+    pub fn load_datapack(&mut self, data_dir: impl AsRef<Path>) -> Result<(), RegistryError> {
+        for namespace in fs::read_dir(data_dir)? {
+            let namespace = namespace?;
+            let ns = namespace.file_name().to_string_lossy().into_owned();
+            let dir = namespace.path().join("recipes");
+            if dir.is_dir() {
+                self.load_dir(&ns, &dir, "")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_dir(&mut self, namespace: &str, dir: &Path, prefix: &str) -> Result<(), RegistryError> {
+        let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            if path.is_dir() {
+                self.load_dir(namespace, &path, &format!("{prefix}{name}/"))?;
+            } else if let Some(name) = name.strip_suffix(".json") {
+                let id = format!("{namespace}:{prefix}{name}");
+                if let Some(recipe) = Recipe::from_json(&id, &fs::read_to_string(&path)?)? {
+                    self.add(recipe);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Recipe> {
+        self.recipes.iter().find(|r| r.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Recipe> {
+        self.recipes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.recipes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recipes.is_empty()
+    }
+
+    /// The crafting recipe a crafting grid makes, if any (see `Recipe::matches_grid()`)
+    pub fn find_crafting(
+        &self,
+        grid: &[Option<&str>],
+        grid_width: usize,
+        tags: &TagRegistry,
+    ) -> Option<&Recipe> {
+        self.recipes
+            .iter()
+            .find(|r| r.matches_grid(grid, grid_width, tags))
+    }
+
+    /// Crafts once with a grid `grid_width` slots wide: the recipe it makes, its result, and what's
+    /// left in the grid afterwards. None if it doesn't make anything (or makes an item that isn't
+    /// in `items`).
+    ///
+    /// Compare the result with what the client put in the result slot to check a crafting click.
+    pub fn craft<'a>(
+        &self,
+        grid: &[Option<Slot<'a>>],
+        grid_width: usize,
+        items: &ItemRegistry,
+        tags: &TagRegistry,
+    ) -> Option<Crafted<'_, 'a>> {
+        // unknown items still take up their slot, but match nothing
+        let names: Vec<Option<&str>> = grid
+            .iter()
+            .map(|slot| {
+                slot.as_ref()
+                    .map(|slot| slot.item_name(items).unwrap_or(""))
+            })
+            .collect();
+        let recipe = self.find_crafting(&names, grid_width, tags)?;
+        let result = items.stack(&recipe.result.0, recipe.result.1)?;
+
+        let mut leftovers = Vec::new();
+        let remaining = grid
+            .iter()
+            .zip(&names)
+            .map(|(slot, name)| {
+                let slot = slot.as_ref()?;
+                let remainder = name
+                    .and_then(crafting_remainder)
+                    .and_then(|remainder| items.stack(remainder, 1));
+                if slot.count > 1 {
+                    leftovers.extend(remainder);
+                    Some(Slot {
+                        count: slot.count - 1,
+                        ..slot.clone()
+                    })
+                } else {
+                    remainder
+                }
+            })
+            .collect();
+        Some(Crafted {
+            recipe,
+            result,
+            remaining,
+            leftovers,
+        })
+    }
+}
+
+fn crafting_category(category: &str) -> i32 {
+    match category {
+        "building" => 0,
+        "redstone" => 1,
+        "equipment" => 2,
+        _ => 3,
+    }
+}
+
+fn cooking_category(category: &str) -> i32 {
+    match category {
+        "food" => 0,
+        "blocks" => 1,
+        _ => 2,
+    }
+}
+
+/// The body of Update Recipes. Items not in `items` are left out of ingredients.
+pub(crate) fn write_recipes(
+    buf: &mut Vec<u8>,
+    recipes: &[Recipe],
+    items: &ItemRegistry,
+    tags: &TagRegistry,
+) {
+    let write_ingredient = |buf: &mut Vec<u8>, ingredient: Option<&Ingredient>| {
+        let ids = ingredient.map_or_else(Vec::new, |i| i.item_ids(items, tags));
+        write_varint(buf, ids.len().try_into().unwrap());
+        for id in ids {
+            write_bool(buf, true);
+            write_varint(buf, id);
+            write_ibyte(buf, 1);
+            // no NBT
+            write_ubyte(buf, 0x00);
+        }
+    };
+    let write_result = |buf: &mut Vec<u8>, recipe: &Recipe| {
+        let (item, count) = &recipe.result;
+        match items.id(item) {
+            Some(id) => {
+                write_bool(buf, true);
+                write_varint(buf, id);
+                write_ibyte(buf, *count);
+                write_ubyte(buf, 0x00);
+            }
+            None => write_bool(buf, false),
+        }
+    };
+
+    write_varint(buf, recipes.len().try_into().unwrap());
+    for recipe in recipes {
+        write_string(buf, &recipe.recipe_type);
+        write_string(buf, &recipe.id);
+        match &recipe.kind {
+            RecipeKind::Shaped {
+                width,
+                height,
+                pattern,
+                show_notification,
+            } => {
+                write_varint(buf, (*width).try_into().unwrap());
+                write_varint(buf, (*height).try_into().unwrap());
+                write_string(buf, &recipe.group);
+                write_varint(buf, crafting_category(&recipe.category));
+                for ingredient in pattern {
+                    write_ingredient(buf, ingredient.as_ref());
+                }
+                write_result(buf, recipe);
+                write_bool(buf, *show_notification);
+            }
+            RecipeKind::Shapeless { ingredients } => {
+                write_string(buf, &recipe.group);
+                write_varint(buf, crafting_category(&recipe.category));
+                write_varint(buf, ingredients.len().try_into().unwrap());
+                for ingredient in ingredients {
+                    write_ingredient(buf, Some(ingredient));
+                }
+                write_result(buf, recipe);
+            }
+            RecipeKind::Cooking {
+                ingredient,
+                experience,
+                cooking_time,
+            } => {
+                write_string(buf, &recipe.group);
+                write_varint(buf, cooking_category(&recipe.category));
+                write_ingredient(buf, Some(ingredient));
+                write_result(buf, recipe);
+                write_float(buf, *experience);
+                write_varint(buf, *cooking_time);
+            }
+            RecipeKind::Stonecutting { ingredient } => {
+                write_string(buf, &recipe.group);
+                write_ingredient(buf, Some(ingredient));
+                write_result(buf, recipe);
+            }
+        }
+    }
+}
+
+/// The recipe books of the crafting table and furnaces
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecipeBookType {
+    Crafting,
+    Furnace,
+    BlastFurnace,
+    Smoker,
+}
+
+/// Whether a recipe book is open, and whether it only shows craftable recipes
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RecipeBookState {
+    pub open: bool,
+    pub filtering: bool,
+}
+
+/// A player's recipe book settings, which the client sends as they change and expects back when
+/// it joins
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct RecipeBookSettings {
+    pub crafting: RecipeBookState,
+    pub furnace: RecipeBookState,
+    pub blast_furnace: RecipeBookState,
+    pub smoker: RecipeBookState,
+}
+
+impl RecipeBookSettings {
+    pub fn get_mut(&mut self, book: RecipeBookType) -> &mut RecipeBookState {
+        match book {
+            RecipeBookType::Crafting => &mut self.crafting,
+            RecipeBookType::Furnace => &mut self.furnace,
+            RecipeBookType::BlastFurnace => &mut self.blast_furnace,
+            RecipeBookType::Smoker => &mut self.smoker,
+        }
+    }
+}
+
+/// What an Update Recipe Book does, with recipe IDs
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RecipeBookAction<'a> {
+    /// Sent on join: the recipes the player knows, and which of them it's seen already (the rest
+    /// are highlighted as new)
+    Init {
+        known: &'a [&'a str],
+        seen: &'a [&'a str],
+    },
+    /// Unlocks recipes, with a toast
+    Add(&'a [&'a str]),
+    Remove(&'a [&'a str]),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crafting() {
+        let ladder = Recipe::from_json(
+            "minecraft:ladder",
+            r####"{
+                "type": "minecraft:crafting_shaped",
+                "category": "misc",
+                "key": {"#": {"item": "minecraft:stick"}},
+                "pattern": ["# #", "###", "# #"],
+                "result": {"count": 3, "item": "minecraft:ladder"},
+                "show_notification": true
+            }"####,
+        )
+        .unwrap()
+        .unwrap();
+        let torch = Recipe::from_json(
+            "minecraft:torch",
+            r##"{
+                "type": "minecraft:crafting_shaped",
+                "category": "misc",
+                "key": {"#": {"item": "minecraft:stick"}, "X": [{"item": "minecraft:coal"}, {"item": "minecraft:charcoal"}]},
+                "pattern": ["X", "#"],
+                "result": {"count": 4, "item": "minecraft:torch"}
+            }"##,
+        )
+        .unwrap()
+        .unwrap();
+        let iron = Recipe::from_json(
+            "minecraft:iron_ingot_from_smelting_raw_iron",
+            r#"{
+                "type": "minecraft:smelting",
+                "category": "misc",
+                "cookingtime": 200,
+                "experience": 0.7,
+                "group": "iron_ingot",
+                "ingredient": {"item": "minecraft:raw_iron"},
+                "result": "minecraft:iron_ingot"
+            }"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(iron.result, ("minecraft:iron_ingot".to_owned(), 1));
+        assert_eq!(
+            Recipe::from_json(
+                "minecraft:map_cloning",
+                r#"{"type": "minecraft:crafting_special_mapcloning"}"#
+            )
+            .unwrap(),
+            None
+        );
+
+        let mut recipes = Recipes::new();
+        recipes.add(ladder);
+        recipes.add(torch);
+        recipes.add(iron);
+        let tags = TagRegistry::new();
+        let (s, c) = (Some("minecraft:stick"), Some("minecraft:charcoal"));
+        // a torch in the bottom right corner of a crafting table
+        let grid = [None, None, None, None, None, c, None, None, s];
+        let made = recipes.find_crafting(&grid, 3, &tags).unwrap();
+        assert_eq!(made.id, "minecraft:torch");
+        let grid = [s, None, s, s, s, s, s, None, None];
+        assert!(recipes.find_crafting(&grid, 3, &tags).is_none());
+
+        let mut items = ItemRegistry::new();
+        items.insert("minecraft:stick", 1);
+        items.insert("minecraft:coal", 2);
+        items.insert("minecraft:torch", 3);
+        let torch = [recipes.get("minecraft:torch").unwrap().clone()];
+        let packet = encode_packet(
+            OutPacket::UpdateRecipes {
+                recipes: &torch,
+                items: &items,
+                tags: &tags,
+            },
+            PROTOCOL_VERSION,
+        );
+        let mut expected = vec![0x6F, 1];
+        write_string(&mut expected, "minecraft:crafting_shaped");
+        write_string(&mut expected, "minecraft:torch");
+        // 1x2, no group, misc
+        expected.extend_from_slice(&[1, 2, 0, 3]);
+        // coal (charcoal isn't known), stick, 4 torches, show notification
+        expected.extend_from_slice(&[1, 1, 2, 1, 0, 1, 1, 1, 1, 0, 1, 3, 4, 0, 1]);
+        assert_eq!(packet, expected);
+    }
+
+    #[test]
+    fn craft_from_slots() {
+        let cake = Recipe::from_json(
+            "minecraft:cake",
+            r#"{
+                "type": "minecraft:crafting_shapeless",
+                "ingredients": [{"item": "minecraft:milk_bucket"}, {"item": "minecraft:sugar"}],
+                "result": {"item": "minecraft:cake"}
+            }"#,
+        )
+        .unwrap()
+        .unwrap();
+        let mut recipes = Recipes::new();
+        recipes.add(cake);
+        let mut items = ItemRegistry::new();
+        for (i, name) in ["milk_bucket", "sugar", "cake", "bucket", "stone"]
+            .into_iter()
+            .enumerate()
+        {
+            items.insert(&format!("minecraft:{name}"), i as i32);
+        }
+        let tags = TagRegistry::new();
+
+        let milk = |count| Some(Slot::new(0, count));
+        let grid = [milk(1), None, None, Some(Slot::new(1, 5))];
+        let crafted = recipes.craft(&grid, 2, &items, &tags).unwrap();
+        assert_eq!(crafted.recipe.id, "minecraft:cake");
+        assert_eq!(crafted.result, Slot::new(2, 1));
+        assert_eq!(
+            crafted.remaining,
+            [Some(Slot::new(3, 1)), None, None, Some(Slot::new(1, 4))]
+        );
+        assert!(crafted.leftovers.is_empty());
+
+        // the bucket has nowhere to go but the player's inventory
+        let grid = [milk(2), None, None, Some(Slot::new(1, 1))];
+        let crafted = recipes.craft(&grid, 2, &items, &tags).unwrap();
+        assert_eq!(crafted.remaining, [milk(1), None, None, None]);
+        assert_eq!(crafted.leftovers, [Slot::new(3, 1)]);
+
+        let grid = [milk(1), Some(Slot::new(4, 1)), None, Some(Slot::new(1, 1))];
+        assert!(recipes.craft(&grid, 2, &items, &tags).is_none());
+        let grid = [milk(1), Some(Slot::new(99, 1)), None, None];
+        assert!(recipes.craft(&grid, 2, &items, &tags).is_none());
+    }
+}
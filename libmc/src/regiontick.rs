@@ -0,0 +1,284 @@
+use crate::*;
+use std::collections::{BTreeMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Chunks along each side of a region, by default (128 blocks)
+pub const DEFAULT_REGION_SIZE: u32 = 8;
+
+/// A change outside the region that asked for it, made once every region has ticked
+type Deferred = Box<dyn FnOnce(&mut ChunkStore) + Send>;
+
+/// One region's chunks, while it ticks on its own thread.
+///
+/// Only the region's own chunks can be changed directly. Changes anywhere else go through
+/// `defer()` (or `set_block()`), and are made once every region has ticked. The chunks around
+/// the region can be read as they were before the tick.
+pub struct TickRegion {
+    chunks: ChunkStore,
+    /// Copies of the loaded chunks next to the region, from before the tick
+    border: ChunkStore,
+    deferred: Vec<Deferred>,
+}
+
+impl TickRegion {
+    /// The region's own chunks
+    pub fn chunks(&self) -> &ChunkStore {
+        &self.chunks
+    }
+
+    pub fn chunks_mut(&mut self) -> &mut ChunkStore {
+        &mut self.chunks
+    }
+
+    pub fn owns(&self, chunk: ChunkPos) -> bool {
+        self.chunks.is_loaded(chunk)
+    }
+
+    /// The block state at `pos`: as it is now in the region's own chunks, or as it was before
+    /// the tick in the chunks next to them. None anywhere else.
+    pub fn block(&self, pos: &Position) -> Option<u32> {
+        self.chunks.block(pos).or_else(|| self.border.block(pos))
+    }
+
+    /// Sets the block at `pos` now if it's in the region, or after the tick if it isn't. Returns
+    /// whether it was set now.
+    pub fn set_block(&mut self, pos: Position, state: u32) -> bool {
+        if self.owns(ChunkPos::new(pos.x >> 4, pos.z >> 4)) {
+            self.chunks.set_block(&pos, state);
+            true
+        } else {
+            self.defer(move |store| {
+                store.set_block(&pos, state);
+            });
+            false
+        }
+    }
+
+    /// Calls `f` with the whole world once every region has ticked. Deferred changes are made in
+    /// the order the regions are in, and then the order they were deferred in.
+    pub fn defer(&mut self, f: impl FnOnce(&mut ChunkStore) + Send + 'static) {
+        self.deferred.push(Box::new(f));
+    }
+}
+
+/// Ticks the loaded chunks of a world on several threads, so block ticks, random ticks and
+/// entity updates scale with cores on large worlds.
+///
+/// The chunks are split into square regions of `size` chunks a side, each ticked on its own
+/// thread with only its own chunks (see `TickRegion`), so regions can't get in each other's
+/// way. Anything one does to another is deferred to the end of the tick.
+///
+/// Call `tick()` from `Server::tick()` with the chunks in simulation distance
+/// (`ChunkTickets::chunks_at(ChunkLevel::BlockTicking)`), or every loaded chunk.
+#[derive(Debug, Clone)]
+pub struct RegionTicker {
+    size: u32,
+    threads: usize,
+}
+
+impl Default for RegionTicker {
+    fn default() -> Self {
+        Self::new(DEFAULT_REGION_SIZE, ChunkEncodePool::default_threads())
+    }
+}
+
+impl RegionTicker {
+    pub fn new(size: u32, threads: usize) -> Self {
+        assert!(size > 0, "regions must have chunks in them");
+        assert!(threads > 0, "RegionTicker needs at least one thread");
+        Self { size, threads }
+    }
+
+    /// The region `chunk` is in
+    pub fn region_of(&self, chunk: ChunkPos) -> (i32, i32) {
+        let size = self.size as i32;
+        (chunk.x.div_euclid(size), chunk.z.div_euclid(size))
+    }
+
+    /// `chunks` grouped by region, in the order regions are ticked and merged
+    pub fn partition(&self, chunks: impl IntoIterator<Item = ChunkPos>) -> Vec<Vec<ChunkPos>> {
+        let mut regions = BTreeMap::<_, Vec<_>>::new();
+        for chunk in chunks {
+            regions
+                .entry(self.region_of(chunk))
+                .or_default()
+                .push(chunk);
+        }
+        regions.into_values().collect()
+    }
+
+    /// Calls `f` with the regions of `chunks` (the ones loaded in `store`) on the thread pool,
+    /// then puts their chunks back and makes the changes they deferred. If `f` panics, every
+    /// chunk is still put back (that region's as it left them, without its deferred changes)
+    /// before the panic carries on in the caller.
+    ///
+    /// Returns how many regions were ticked.
+    pub fn tick(
+        &self,
+        store: &mut ChunkStore,
+        chunks: impl IntoIterator<Item = ChunkPos>,
+        f: impl Fn(&mut TickRegion) + Sync,
+    ) -> usize {
+        let groups = self.partition(chunks.into_iter().filter(|&c| store.is_loaded(c)));
+        // the borders are copied before any region's chunks are moved out
+        let borders: Vec<_> = groups
+            .iter()
+            .map(|group| {
+                let own: HashSet<_> = group.iter().copied().collect();
+                let around: HashSet<_> = group
+                    .iter()
+                    .flat_map(|&c| {
+                        (-1..=1).flat_map(move |dx| (-1..=1).map(move |dz| c.offset(dx, dz)))
+                    })
+                    .filter(|c| !own.contains(c))
+                    .collect();
+                store.copy_of(around)
+            })
+            .collect();
+        let mut regions: Vec<_> = groups
+            .into_iter()
+            .zip(borders)
+            .map(|(group, border)| TickRegion {
+                chunks: store.split_off(group),
+                border,
+                deferred: Vec::new(),
+            })
+            .collect();
+
+        let queue = Mutex::new(regions.iter_mut());
+        let panicked = Mutex::new(None);
+        thread::scope(|s| {
+            for _ in 0..self.threads.min(queue.lock().unwrap().len()) {
+                s.spawn(|| loop {
+                    // the lock is only held while taking a region, not while ticking it
+                    let Some(region) = queue.lock().unwrap().next() else {
+                        return;
+                    };
+                    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(region))) {
+                        region.deferred.clear();
+                        panicked.lock().unwrap().get_or_insert(payload);
+                    }
+                });
+            }
+        });
+
+        let ticked = regions.len();
+        let mut deferred = Vec::new();
+        for region in regions {
+            store.append(region.chunks);
+            deferred.extend(region.deferred);
+        }
+        for f in deferred {
+            f(store);
+        }
+        if let Some(payload) = panicked.into_inner().unwrap() {
+            panic::resume_unwind(payload);
+        }
+        ticked
+    }
+
+    /// Random ticks `chunks` region by region, `speed` blocks per section like
+    /// `RandomTicker::tick()`. `f` is called with the block's region, position and state.
+    /// Returns how many blocks were ticked.
+    pub fn random_tick(
+        &self,
+        store: &mut ChunkStore,
+        chunks: impl IntoIterator<Item = ChunkPos>,
+        speed: u32,
+        f: impl Fn(&mut TickRegion, Position, u32) + Sync,
+    ) -> usize {
+        let ticked = AtomicUsize::new(0);
+        self.tick(store, chunks, |region| {
+            let own: Vec<_> = region.chunks.positions().collect();
+            let picked = RandomTicker::new(speed).pick(&region.chunks, own);
+            ticked.fetch_add(picked.len(), Ordering::Relaxed);
+            for pos in picked {
+                // an earlier tick may have changed it
+                if let Some(state) = region.chunks.block(&pos) {
+                    f(region, pos, state);
+                }
+            }
+        });
+        ticked.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_regions_and_defers_across_them() {
+        const STONE: u32 = 1;
+        const DIRT: u32 = 10;
+        let mut store = ChunkStore::new(-64, 384, 0, 64);
+        for x in -2..2 {
+            for z in -2..2 {
+                store.load(ChunkPos::new(x, z));
+            }
+        }
+        let ticker = RegionTicker::new(2, 3);
+        assert_eq!(ticker.region_of(ChunkPos::new(-1, 1)), (-1, 0));
+        assert_eq!(ticker.partition(store.positions()).len(), 4);
+
+        // every region puts stone at its chunks' corners, and dirt just west of each
+        let chunks: Vec<_> = store.positions().collect();
+        let n = ticker.tick(&mut store, chunks.clone(), |region| {
+            let own: Vec<_> = region.chunks().positions().collect();
+            for chunk in own {
+                let corner = Position {
+                    x: chunk.x * 16,
+                    y: 0,
+                    z: chunk.z * 16,
+                };
+                let west = Position {
+                    x: corner.x - 1,
+                    ..corner
+                };
+                // the region to the west is read from before the tick, without its dirt
+                if !region.owns(ChunkPos::new(chunk.x - 1, chunk.z)) && chunk.x > -2 {
+                    assert_eq!(region.block(&west), Some(0));
+                }
+                assert!(region.set_block(corner, STONE));
+                region.set_block(west, DIRT);
+            }
+        });
+        assert_eq!(n, 4);
+        assert_eq!(store.len(), 16);
+        for &chunk in &chunks {
+            let corner = Position {
+                x: chunk.x * 16,
+                y: 0,
+                z: chunk.z * 16,
+            };
+            assert_eq!(store.block(&corner), Some(STONE));
+            if chunk.x > -2 {
+                let west = Position {
+                    x: corner.x - 1,
+                    ..corner
+                };
+                assert_eq!(store.block(&west), Some(DIRT));
+            }
+        }
+
+        // 16 chunks with one non-empty section each
+        let ticked = ticker.random_tick(&mut store, chunks.clone(), 3, |_, _, _| {});
+        assert_eq!(ticked, 48);
+
+        // the panic reaches the caller, with every chunk back in the store
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            ticker.tick(&mut store, chunks, |region| {
+                region.set_block(Position { x: 0, y: 1, z: 0 }, DIRT);
+                if region.owns(ChunkPos::new(0, 0)) {
+                    panic!("tick failed");
+                }
+            })
+        }));
+        assert!(res.is_err());
+        assert_eq!(store.len(), 16);
+        assert_eq!(store.block(&Position { x: 0, y: 1, z: 0 }), Some(DIRT));
+    }
+}
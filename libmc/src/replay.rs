@@ -0,0 +1,200 @@
+//! Replays captured connections through libmc's packet handling, so protocol regressions can be
+//! caught by tests without a live client.
+
+use crate::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::Path;
+
+/// A `Replay::check_clientbound()` packet that doesn't match the capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayMismatch {
+    /// The frame(s) the capture has next, or None if it has no more clientbound packets there
+    pub expected: Option<Vec<u8>>,
+    /// What libmc encoded
+    pub actual: Vec<u8>,
+}
+
+impl fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.expected {
+            Some(expected) => write!(f, "expected {expected:02x?}, encoded {:02x?}", self.actual),
+            None => write!(f, "unexpected packet {:02x?}", self.actual),
+        }
+    }
+}
+
+impl std::error::Error for ReplayMismatch {}
+
+/// Plays the client's side of a capture into a packet stream like `run_server()`'s.
+///
+/// Serverbound packets are translated and decoded as they would be for a live client (a client on
+/// another version gets `builtin_translator()`), and what the server is expected to send back can
+/// be checked against the clientbound packets in the capture. Both are taken in capture order.
+#[derive(Debug)]
+pub struct Replay {
+    ps: PacketStream<Vec<u8>>,
+    packets: VecDeque<CapturedPacket>,
+    /// Translated frames not decoded yet
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl Replay {
+    pub fn new(packets: Vec<CapturedPacket>) -> Self {
+        Self {
+            ps: PacketStream::new(Vec::new()),
+            packets: packets.into(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        Ok(Self::new(read_capture_file(path)?))
+    }
+
+    /// Decodes the next serverbound packet and passes it to `f`. Clientbound packets that come
+    /// before it in the capture are skipped. Returns None at the end of the capture, and panics if
+    /// the packet can't be decoded.
+    pub fn next_serverbound<T>(&mut self, f: impl FnOnce(InPacket) -> T) -> Option<T> {
+        // replies a translator made up go first, as they do in `run_server()`
+        self.pending.extend(self.ps.take_injected());
+        while self.pending.is_empty() {
+            let packet = self.packets.pop_front()?;
+            if packet.dir == PacketDirection::Serverbound {
+                let frames = self
+                    .ps
+                    .translate_incoming(frame_packet(&packet.data))
+                    .unwrap_or_else(|e| panic!("bad packet in capture: {e}"));
+                self.pending.extend(frames);
+            }
+        }
+        let frame = self.pending.pop_front().unwrap();
+        let packet = self
+            .ps
+            .decode(&frame)
+            .unwrap_or_else(|e| panic!("bad packet in capture: {e}"));
+        if let InPacket::Handshake {
+            protocol_version, ..
+        } = packet
+        {
+            if protocol_version != PROTOCOL_VERSION {
+                if let Some(translator) = builtin_translator(protocol_version) {
+                    self.ps.set_translator(translator);
+                }
+            }
+        }
+        Some(f(packet))
+    }
+
+    /// Decodes all remaining serverbound packets, in their `Debug` format
+    pub fn serverbound_debug(&mut self) -> Vec<String> {
+        std::iter::from_fn(|| self.next_serverbound(|p| format!("{p:?}"))).collect()
+    }
+
+    /// Encodes `packet` for the client, and checks it against the next packet in the capture.
+    /// Packets the translator drops match without using up a captured packet.
+    pub fn check_clientbound(&mut self, packet: OutPacket) -> Result<(), ReplayMismatch> {
+        let written = self.ps.writer_mut().len();
+        // writing to a Vec can't fail
+        self.ps.send(packet).unwrap();
+        let actual = self.ps.writer_mut().split_off(written);
+        if actual.is_empty() {
+            return Ok(());
+        }
+
+        let expected = match self.packets.front() {
+            Some(p) if p.dir == PacketDirection::Clientbound => {
+                self.packets.pop_front().map(|p| p.data)
+            }
+            _ => None,
+        };
+        match expected {
+            Some(expected) if frame_packet(&expected) == actual => Ok(()),
+            expected => Err(ReplayMismatch {
+                expected: expected.map(|p| frame_packet(&p)),
+                actual,
+            }),
+        }
+    }
+
+    /// Whether every packet in the capture has been replayed
+    pub fn is_done(&self) -> bool {
+        self.packets.is_empty() && self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captured(dir: PacketDirection, state: ProtocolState, data: Vec<u8>) -> CapturedPacket {
+        CapturedPacket {
+            time_ms: 0,
+            dir,
+            state,
+            data,
+        }
+    }
+
+    #[test]
+    fn login() {
+        let mut handshake = vec![0x00];
+        write_varint(&mut handshake, PROTOCOL_VERSION);
+        write_string(&mut handshake, "localhost");
+        handshake.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut handshake, 2);
+        let mut login_start = vec![0x00];
+        write_string(&mut login_start, "Steve");
+        write_uuid(&mut login_start, Uuid(1));
+        let profile = GameProfile::new(Uuid(1), "Steve");
+        let login_success = encode_packet(OutPacket::LoginSuccess { profile: &profile }, 764);
+
+        let mut replay = Replay::new(vec![
+            captured(
+                PacketDirection::Serverbound,
+                ProtocolState::Handshaking,
+                handshake,
+            ),
+            captured(
+                PacketDirection::Serverbound,
+                ProtocolState::Login,
+                login_start,
+            ),
+            captured(
+                PacketDirection::Clientbound,
+                ProtocolState::Login,
+                login_success,
+            ),
+            captured(
+                PacketDirection::Serverbound,
+                ProtocolState::Login,
+                vec![0x03],
+            ),
+        ]);
+
+        let handshake = replay.next_serverbound(|p| {
+            matches!(
+                p,
+                InPacket::Handshake {
+                    next_state: HandshakeNextState::Login,
+                    ..
+                }
+            )
+        });
+        assert_eq!(handshake, Some(true));
+        let login_start =
+            replay.next_serverbound(|p| matches!(p, InPacket::LoginStart { name: "Steve", .. }));
+        assert_eq!(login_start, Some(true));
+
+        assert_eq!(
+            replay.check_clientbound(OutPacket::LoginSuccess { profile: &profile }),
+            Ok(())
+        );
+        let mismatch = replay
+            .check_clientbound(OutPacket::LoginSuccess { profile: &profile })
+            .unwrap_err();
+        assert_eq!(mismatch.expected, None, "the capture has LoginAck next");
+        assert_eq!(replay.serverbound_debug(), ["LoginAck"]);
+        assert!(replay.is_done());
+    }
+}
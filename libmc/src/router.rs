@@ -0,0 +1,439 @@
+use crate::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Identifies a server added to a `ServerRouter`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ServerId(usize);
+
+/// Lets servers in a `ServerRouter` hand clients to each other
+#[derive(Debug, Clone, Default)]
+pub struct TransferHandle {
+    pending: Rc<RefCell<Vec<(ClientID, ServerId)>>>,
+}
+
+impl TransferHandle {
+    /// Moves `cid` to the server `to` once the current callback returns
+    pub fn transfer(&self, cid: ClientID, to: ServerId) {
+        self.pending.borrow_mut().push((cid, to));
+    }
+}
+
+/// Runs several `Server`s (e.g. a lobby and game worlds) in one process, and moves logged-in
+/// clients between them without a reconnect. The connection itself (and its protocol state) stays
+/// with `run_server()`; only which server gets the client's packets changes.
+///
+/// New clients go to the first server added. A transferred client is `on_detach()`ed from its old
+/// server and `on_attach()`ed to the new one. Every server is ticked.
+///
+/// Servers can also be picked by the address clients connect to, for virtual hosts like
+/// `lobby.example.com` and `pvp.example.com` on one port (see `add_host()`). Clients are handed
+/// over as soon as their handshake arrives, so the server for their host is the one that filters
+/// it, answers their pings and logs them in.
+pub struct ServerRouter {
+    servers: Vec<Box<dyn Server>>,
+    routes: HashMap<ClientID, ServerId>,
+    transfers: TransferHandle,
+    /// (lowercase host or `*.` pattern, server), in the order they were added
+    hosts: Vec<(String, ServerId)>,
+}
+
+impl Default for ServerRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerRouter {
+    pub fn new() -> Self {
+        Self {
+            servers: Vec::new(),
+            routes: HashMap::new(),
+            transfers: TransferHandle::default(),
+            hosts: Vec::new(),
+        }
+    }
+
+    /// For the servers to request transfers with
+    pub fn transfer_handle(&self) -> TransferHandle {
+        self.transfers.clone()
+    }
+
+    /// The id the next server added will get, for servers that need to know their own id
+    pub fn next_id(&self) -> ServerId {
+        ServerId(self.servers.len())
+    }
+
+    pub fn add(&mut self, server: Box<dyn Server>) -> ServerId {
+        self.servers.push(server);
+        ServerId(self.servers.len() - 1)
+    }
+
+    /// Sends clients that connect to `host` (as typed in by the player, in any case) to the server
+    /// `id`. `*.example.com` matches every subdomain of `example.com`. Exact hosts win over
+    /// patterns, and earlier patterns over later ones.
+    pub fn add_host(&mut self, host: &str, id: ServerId) {
+        assert!(id.0 < self.servers.len(), "host for unknown {id:?}");
+        self.hosts.push((host.to_ascii_lowercase(), id));
+    }
+
+    /// The server for clients that connect to `host`: the first server, unless `add_host()` says
+    /// otherwise
+    pub fn server_for_host(&self, host: &str) -> ServerId {
+        // a trailing dot is the same host, fully qualified
+        let host = host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase();
+        let exact = self.hosts.iter().find(|(h, _)| *h == host);
+        let pattern = || {
+            self.hosts.iter().find(|(h, _)| {
+                h.strip_prefix("*.").is_some_and(|domain| {
+                    host.strip_suffix(domain)
+                        .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.'))
+                })
+            })
+        };
+        exact.or_else(pattern).map_or(ServerId(0), |&(_, id)| id)
+    }
+
+    /// Which server `cid` is currently on
+    pub fn server_of(&self, cid: ClientID) -> Option<ServerId> {
+        self.routes.get(&cid).copied()
+    }
+
+    fn route(&mut self, cid: ClientID) -> &mut dyn Server {
+        let id = self.routes[&cid];
+        self.servers[id.0].as_mut()
+    }
+
+    fn apply_transfers(&mut self) {
+        // servers can request more transfers while being attached
+        loop {
+            let pending = std::mem::take(&mut *self.transfers.pending.borrow_mut());
+            if pending.is_empty() {
+                return;
+            }
+            for (cid, to) in pending {
+                let Some(&from) = self.routes.get(&cid) else {
+                    // already disconnected
+                    continue;
+                };
+                assert!(to.0 < self.servers.len(), "transfer to unknown {to:?}");
+                if from == to {
+                    continue;
+                }
+                self.servers[from.0].on_detach(cid);
+                self.routes.insert(cid, to);
+                self.servers[to.0].on_attach(cid);
+            }
+        }
+    }
+}
+
+impl Server for ServerRouter {
+    fn on_connect(&mut self, cid: ClientID) {
+        assert!(!self.servers.is_empty(), "ServerRouter has no servers");
+        self.routes.insert(cid, ServerId(0));
+        self.servers[0].on_connect(cid);
+        self.apply_transfers();
+    }
+
+    fn on_disconnect(&mut self, cid: ClientID, cause: DisconnectCause) {
+        self.route(cid).on_disconnect(cid, cause);
+        self.routes.remove(&cid);
+        self.apply_transfers();
+    }
+
+    fn handle_packet(&mut self, cid: ClientID, packet: InPacket) {
+        self.route(cid).handle_packet(cid, packet);
+        self.apply_transfers();
+    }
+
+    fn tick(&mut self, tick: u64) {
+        for s in &mut self.servers {
+            s.tick(tick);
+        }
+        self.apply_transfers();
+    }
+
+    fn on_ticks_skipped(&mut self, behind: Duration) {
+        for s in &mut self.servers {
+            s.on_ticks_skipped(behind);
+        }
+    }
+
+    /// Every server gets a copy of the handle
+    fn on_start(&mut self, handle: ServerHandle) {
+        for s in &mut self.servers {
+            s.on_start(handle.clone());
+        }
+    }
+
+    /// Every server gets to reload its own settings; the first config returned is switched to
+    fn reload_config(&mut self) -> Option<ServerConfig> {
+        let mut config = None;
+        for s in &mut self.servers {
+            let reloaded = s.reload_config();
+            config = config.or(reloaded);
+        }
+        config
+    }
+
+    fn on_config_reloaded(&mut self, needs_restart: &[&'static str]) {
+        for s in &mut self.servers {
+            s.on_config_reloaded(needs_restart);
+        }
+    }
+
+    fn on_shutdown(&mut self) {
+        for s in &mut self.servers {
+            s.on_shutdown();
+        }
+    }
+
+    fn flush_policy(&mut self, cid: ClientID) -> FlushPolicy {
+        self.servers[0].flush_policy(cid)
+    }
+
+    fn packet_rate_limits(&mut self, cid: ClientID) -> PacketRateLimits {
+        self.servers[0].packet_rate_limits(cid)
+    }
+
+    fn filter_connection(&mut self, addr: std::net::SocketAddr) -> bool {
+        self.servers[0].filter_connection(addr)
+    }
+
+    /// Hands the client to the server for its host first, which then gets to filter it
+    fn filter_handshake(
+        &mut self,
+        cid: ClientID,
+        handshake: &HandshakeInfo,
+    ) -> Result<(), TextComponent> {
+        let to = self.server_for_host(handshake.server_addr);
+        self.transfers.transfer(cid, to);
+        self.apply_transfers();
+        self.route(cid).filter_handshake(cid, handshake)
+    }
+
+    fn filter_login(&mut self, cid: ClientID, name: &str, uuid: Uuid) -> Result<(), TextComponent> {
+        self.route(cid).filter_login(cid, name, uuid)
+    }
+
+    fn on_handshake(&mut self, cid: ClientID, handshake: &HandshakeInfo) {
+        self.route(cid).on_handshake(cid, handshake);
+        self.apply_transfers();
+    }
+
+    fn on_capture_failed(&mut self, cid: ClientID, error: std::io::Error) {
+        self.route(cid).on_capture_failed(cid, error);
+    }
+
+    fn on_view_distance(&mut self, cid: ClientID, view_distance: i32) {
+        self.route(cid).on_view_distance(cid, view_distance);
+        self.apply_transfers();
+    }
+
+    fn connection_limits(&mut self) -> ConnectionLimits {
+        self.servers[0].connection_limits()
+    }
+
+    /// Pings are answered by the first server, which clients connect to
+    fn status_cache(&mut self) -> Option<StatusCache> {
+        self.servers.first_mut()?.status_cache()
+    }
+
+    /// Pings are answered by the server for the host they were sent to
+    fn status_cache_for(&mut self, cid: ClientID) -> Option<StatusCache> {
+        match self.routes.get(&cid) {
+            Some(_) => self.route(cid).status_cache_for(cid),
+            None => self.status_cache(),
+        }
+    }
+
+    fn login_dimensions(&mut self, cid: ClientID) -> Option<LoginDimensions> {
+        self.route(cid).login_dimensions(cid)
+    }
+
+    fn translator(&mut self, protocol_version: i32) -> Option<Box<dyn ProtocolTranslator>> {
+        self.servers.first_mut()?.translator(protocol_version)
+    }
+
+    fn bedrock_blocks(&mut self) -> Arc<BedrockBlocks> {
+        match self.servers.first_mut() {
+            Some(s) => s.bedrock_blocks(),
+            None => Arc::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    struct Logger {
+        name: &'static str,
+        log: Log,
+        transfers: TransferHandle,
+        /// where clients that send a packet get sent
+        send_to: Option<ServerId>,
+    }
+
+    impl Server for Logger {
+        fn on_connect(&mut self, cid: ClientID) {
+            self.log
+                .borrow_mut()
+                .push(format!("{} connect {}", self.name, cid.index()));
+        }
+
+        fn on_disconnect(&mut self, cid: ClientID, _cause: DisconnectCause) {
+            self.log
+                .borrow_mut()
+                .push(format!("{} disconnect {}", self.name, cid.index()));
+        }
+
+        fn handle_packet(&mut self, cid: ClientID, _packet: InPacket) {
+            self.log
+                .borrow_mut()
+                .push(format!("{} packet {}", self.name, cid.index()));
+            if let Some(to) = self.send_to {
+                self.transfers.transfer(cid, to);
+            }
+        }
+
+        fn tick(&mut self, _tick: u64) {}
+
+        fn on_attach(&mut self, cid: ClientID) {
+            self.log
+                .borrow_mut()
+                .push(format!("{} attach {}", self.name, cid.index()));
+        }
+
+        fn on_detach(&mut self, cid: ClientID) {
+            self.log
+                .borrow_mut()
+                .push(format!("{} detach {}", self.name, cid.index()));
+        }
+
+        fn on_view_distance(&mut self, cid: ClientID, view_distance: i32) {
+            self.log.borrow_mut().push(format!(
+                "{} view distance {} {view_distance}",
+                self.name,
+                cid.index()
+            ));
+        }
+
+        fn reload_config(&mut self) -> Option<ServerConfig> {
+            self.log.borrow_mut().push(format!("{} reload", self.name));
+            let config = ServerConfig {
+                brand: self.name.to_owned(),
+                ..ServerConfig::default()
+            };
+            (self.name != "lobby").then_some(config)
+        }
+    }
+
+    #[test]
+    fn lobby_to_game() {
+        let log = Log::default();
+        let mut router = ServerRouter::new();
+        let game_id = ServerId(1);
+        router.add(Box::new(Logger {
+            name: "lobby",
+            log: log.clone(),
+            transfers: router.transfer_handle(),
+            send_to: Some(game_id),
+        }));
+        assert_eq!(router.next_id(), game_id);
+        router.add(Box::new(Logger {
+            name: "game",
+            log: log.clone(),
+            transfers: router.transfer_handle(),
+            send_to: None,
+        }));
+
+        let cid = ClientID::new(7, 0);
+        router.on_connect(cid);
+        router.handle_packet(cid, InPacket::LoginAck);
+        assert_eq!(router.server_of(cid), Some(game_id));
+        router.handle_packet(cid, InPacket::LoginAck);
+        router.on_view_distance(cid, 6);
+        router.on_disconnect(cid, DisconnectCause::Closed);
+        assert_eq!(
+            *log.borrow(),
+            [
+                "lobby connect 7",
+                "lobby packet 7",
+                "lobby detach 7",
+                "game attach 7",
+                "game packet 7",
+                "game view distance 7 6",
+                "game disconnect 7"
+            ]
+        );
+    }
+
+    #[test]
+    fn reloads_every_server() {
+        let log = Log::default();
+        let mut router = ServerRouter::new();
+        for name in ["lobby", "pvp", "game"] {
+            router.add(Box::new(Logger {
+                name,
+                log: log.clone(),
+                transfers: router.transfer_handle(),
+                send_to: None,
+            }));
+        }
+        let config = router.reload_config().unwrap();
+        assert_eq!(config.brand, "pvp");
+        assert_eq!(*log.borrow(), ["lobby reload", "pvp reload", "game reload"]);
+    }
+
+    #[test]
+    fn routes_by_host() {
+        let log = Log::default();
+        let mut router = ServerRouter::new();
+        for name in ["lobby", "pvp"] {
+            router.add(Box::new(Logger {
+                name,
+                log: log.clone(),
+                transfers: router.transfer_handle(),
+                send_to: None,
+            }));
+        }
+        let pvp = ServerId(1);
+        router.add_host("*.pvp.example.com", pvp);
+        router.add_host("PvP.example.com", pvp);
+        assert_eq!(router.server_for_host("pvp.example.com."), pvp);
+        assert_eq!(router.server_for_host("eu.pvp.example.com"), pvp);
+        assert_eq!(router.server_for_host("xpvp.example.com"), ServerId(0));
+        assert_eq!(router.server_for_host("lobby.example.com"), ServerId(0));
+
+        let cid = ClientID::new(3, 0);
+        router.on_connect(cid);
+        let handshake = HandshakeInfo {
+            addr: None,
+            listener: DEFAULT_LISTENER,
+            protocol_version: PROTOCOL_VERSION,
+            server_addr: "pvp.example.com",
+            server_port: 25565,
+            forge: None,
+            next_state: HandshakeNextState::Login,
+        };
+        assert!(router.filter_handshake(cid, &handshake).is_ok());
+        assert_eq!(router.server_of(cid), Some(pvp));
+        router.handle_packet(cid, InPacket::LoginAck);
+        assert_eq!(
+            *log.borrow(),
+            [
+                "lobby connect 3",
+                "lobby detach 3",
+                "pvp attach 3",
+                "pvp packet 3"
+            ]
+        );
+    }
+}
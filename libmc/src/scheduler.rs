@@ -0,0 +1,173 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+type Task<C> = Box<dyn FnMut(&mut C, &mut Scheduler<C>)>;
+
+/// Identifies a scheduled task, for cancelling it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TaskHandle(u64);
+
+struct Entry<C> {
+    f: Task<C>,
+    due: u64,
+    /// `Some` for repeating tasks
+    period: Option<u64>,
+}
+
+/// Runs delayed and repeating tasks, with delays measured in ticks.
+///
+/// Keep one in your `Server` and drive it from `Server::tick()` by calling `run()`.
+/// Tasks are called with the `C` passed to `run()` (e.g. the server's world state),
+/// and the scheduler itself, so they can schedule or cancel other tasks.
+pub struct Scheduler<C> {
+    tasks: HashMap<u64, Entry<C>>,
+    /// (due tick, task id). Can contain stale entries for cancelled tasks.
+    queue: BinaryHeap<Reverse<(u64, u64)>>,
+    next_id: u64,
+    /// The tick that was last run
+    now: u64,
+    /// The task that's currently running, if it's a repeating one
+    running: Option<u64>,
+    running_cancelled: bool,
+}
+
+impl<C> Default for Scheduler<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Scheduler<C> {
+    pub fn new() -> Self {
+        Self {
+            tasks: HashMap::new(),
+            queue: BinaryHeap::new(),
+            next_id: 0,
+            now: 0,
+            running: None,
+            running_cancelled: false,
+        }
+    }
+
+    /// Runs `f` once, `delay` ticks from now (at least 1)
+    pub fn run_later(
+        &mut self,
+        delay: u64,
+        f: impl FnMut(&mut C, &mut Scheduler<C>) + 'static,
+    ) -> TaskHandle {
+        self.insert(delay, None, Box::new(f))
+    }
+
+    /// Runs `f` every `period` ticks (at least 1), starting `delay` ticks from now (at least 1)
+    pub fn run_repeating(
+        &mut self,
+        delay: u64,
+        period: u64,
+        f: impl FnMut(&mut C, &mut Scheduler<C>) + 'static,
+    ) -> TaskHandle {
+        self.insert(delay, Some(period.max(1)), Box::new(f))
+    }
+
+    fn insert(&mut self, delay: u64, period: Option<u64>, f: Task<C>) -> TaskHandle {
+        let id = self.next_id;
+        self.next_id += 1;
+        let due = self.now + delay.max(1);
+        self.tasks.insert(id, Entry { f, due, period });
+        self.queue.push(Reverse((due, id)));
+        TaskHandle(id)
+    }
+
+    /// Stops a task from running (again). Returns false if it already finished or was cancelled.
+    /// A repeating task can cancel itself.
+    pub fn cancel(&mut self, handle: TaskHandle) -> bool {
+        if self.running == Some(handle.0) && !self.running_cancelled {
+            self.running_cancelled = true;
+            return true;
+        }
+        self.tasks.remove(&handle.0).is_some()
+    }
+
+    pub fn is_scheduled(&self, handle: TaskHandle) -> bool {
+        self.tasks.contains_key(&handle.0)
+            || (self.running == Some(handle.0) && !self.running_cancelled)
+    }
+
+    /// Number of pending tasks
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Runs the tasks that are due by `tick`. Call this once per tick with the tick number
+    /// passed to `Server::tick()`.
+    pub fn run(&mut self, tick: u64, ctx: &mut C) {
+        self.now = tick;
+        while let Some(&Reverse((due, id))) = self.queue.peek() {
+            if due > tick {
+                break;
+            }
+            self.queue.pop();
+            // stale queue entry for a cancelled task
+            let Some(mut entry) = self.tasks.remove(&id) else {
+                continue;
+            };
+            if entry.due != due {
+                continue;
+            }
+
+            self.running = entry.period.map(|_| id);
+            self.running_cancelled = false;
+            (entry.f)(ctx, self);
+            self.running = None;
+
+            if let Some(period) = entry.period {
+                if !self.running_cancelled {
+                    entry.due = tick + period;
+                    self.queue.push(Reverse((entry.due, id)));
+                    self.tasks.insert(id, entry);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn delayed_and_repeating() {
+        let mut sched = Scheduler::<Vec<&str>>::new();
+        let mut log = Vec::new();
+
+        sched.run_later(3, |log, _| log.push("later"));
+        let cancelled = sched.run_later(2, |log, _| log.push("cancelled"));
+        let mut runs = 0;
+        // the repeating task cancels itself, so it needs its own handle
+        let me = Rc::new(Cell::new(None));
+        let handle = Rc::clone(&me);
+        let repeating = sched.run_repeating(1, 2, move |log, sched| {
+            log.push("repeat");
+            runs += 1;
+            if runs == 2 {
+                assert!(sched.cancel(handle.get().unwrap()));
+                sched.run_later(0, |log, _| log.push("spawned"));
+            }
+        });
+        me.set(Some(repeating));
+        assert!(sched.cancel(cancelled));
+        assert!(!sched.cancel(cancelled));
+
+        for tick in 0..10 {
+            sched.run(tick, &mut log);
+        }
+        assert_eq!(log, ["repeat", "later", "repeat", "spawned"]);
+        assert!(!sched.is_scheduled(repeating));
+        assert!(sched.is_empty());
+    }
+}
@@ -0,0 +1,432 @@
+use crate::nbt::{compound_list, invalid, read_nbt_file, write_nbt_file};
+use crate::*;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// Versions of the Sponge schematic format
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SchematicVersion {
+    /// What WorldEdit wrote before 1.20.5
+    V2,
+    #[default]
+    V3,
+}
+
+/// A Sponge schematic (`.schem`), what WorldEdit saves clipboards as.
+///
+/// Blocks are kept the way the format has them: a palette of states, and an index into it for
+/// every block in the box, x first, then z, then y. Block entity and entity data include their `id`,
+/// like in structure files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schematic {
+    /// The version written by `to_nbt()`
+    pub version: SchematicVersion,
+    pub data_version: i32,
+    /// Width (x), height (y) and length (z)
+    pub size: [u16; 3],
+    /// Where the schematic was copied from, relative to the copier (not used by libmc)
+    pub offset: [i32; 3],
+    pub palette: Vec<BlockState>,
+    pub blocks: Vec<usize>,
+    /// Positions are relative to the schematic's corner
+    pub block_entities: Vec<([i32; 3], CompoundNbt<'static>)>,
+    /// Positions are relative to the schematic's corner
+    pub entities: Vec<([f64; 3], CompoundNbt<'static>)>,
+}
+
+impl Schematic {
+    /// An empty schematic full of air
+    pub fn new(size: [u16; 3], data_version: i32) -> Self {
+        let volume = size.iter().map(|&s| usize::from(s)).product();
+        Self {
+            version: SchematicVersion::default(),
+            data_version,
+            size,
+            offset: [0; 3],
+            palette: vec![BlockState::new("minecraft:air")],
+            blocks: vec![0; volume],
+            block_entities: Vec::new(),
+            entities: Vec::new(),
+        }
+    }
+
+    fn index(&self, [x, y, z]: [i32; 3]) -> Option<usize> {
+        let [w, h, l] = self.size.map(i32::from);
+        if !(0..w).contains(&x) || !(0..h).contains(&y) || !(0..l).contains(&z) {
+            return None;
+        }
+        usize::try_from(x + z * w + y * w * l).ok()
+    }
+
+    pub fn block_at(&self, pos: [i32; 3]) -> Option<&BlockState> {
+        self.index(pos).map(|i| &self.palette[self.blocks[i]])
+    }
+
+    /// Sets the block at `pos` (relative to the corner), adding `state` to the palette if it's new.
+    /// Positions outside the schematic are ignored.
+    pub fn set_block(&mut self, pos: [i32; 3], state: BlockState) {
+        let Some(i) = self.index(pos) else {
+            return;
+        };
+        self.blocks[i] = match self.palette.iter().position(|s| *s == state) {
+            Some(p) => p,
+            None => {
+                self.palette.push(state);
+                self.palette.len() - 1
+            }
+        };
+    }
+
+    /// Reads a version 2 or 3 schematic
+    pub fn from_nbt(root: &CompoundNbt<'static>) -> io::Result<Self> {
+        // version 3 wraps everything in a "Schematic" compound
+        let (version, schem, blocks) = match root.get("Schematic") {
+            Some(Nbt::Compound(schem)) => match schem.get("Blocks") {
+                Some(Nbt::Compound(blocks)) => (SchematicVersion::V3, schem, Some(blocks)),
+                _ => (SchematicVersion::V3, schem, None),
+            },
+            _ => (SchematicVersion::V2, root, Some(root)),
+        };
+        match (version, schem.get("Version")) {
+            (SchematicVersion::V2, Some(Nbt::Int(2)))
+            | (SchematicVersion::V3, Some(Nbt::Int(3))) => {}
+            _ => return Err(invalid("unsupported schematic version")),
+        }
+
+        let short = |key| match schem.get(key) {
+            Some(&Nbt::Short(v)) => Ok(v as u16),
+            _ => Err(invalid("missing schematic size")),
+        };
+        let mut schematic = Self::new(
+            [short("Width")?, short("Height")?, short("Length")?],
+            match schem.get("DataVersion") {
+                Some(&Nbt::Int(v)) => v,
+                _ => return Err(invalid("missing DataVersion")),
+            },
+        );
+        schematic.version = version;
+        if let Some(Nbt::IntArray(offset)) = schem.get("Offset") {
+            schematic.offset = offset[..]
+                .try_into()
+                .map_err(|_| invalid("Offset isn't 3 ints"))?;
+        }
+
+        let Some(blocks) = blocks else {
+            return Ok(schematic);
+        };
+        if let Some(Nbt::Compound(palette)) = blocks.get("Palette") {
+            let mut states = vec![None; palette.props().count()];
+            for (state, index) in palette.props() {
+                let &Nbt::Int(index) = index else {
+                    return Err(invalid("palette index isn't an int"));
+                };
+                let slot = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| states.get_mut(i))
+                    .ok_or_else(|| invalid("palette index out of range"))?;
+                *slot = Some(BlockState::parse(state).ok_or_else(|| invalid("bad block state"))?);
+            }
+            schematic.palette = states
+                .into_iter()
+                .collect::<Option<_>>()
+                .ok_or_else(|| invalid("palette has gaps"))?;
+        }
+        let data_key = match version {
+            SchematicVersion::V2 => "BlockData",
+            SchematicVersion::V3 => "Data",
+        };
+        if let Some(Nbt::ByteArray(data)) = blocks.get(data_key) {
+            let indices = read_varints(data)?;
+            if indices.len() != schematic.blocks.len() {
+                return Err(invalid("block data doesn't fill the schematic"));
+            }
+            if indices.iter().any(|&i| i >= schematic.palette.len()) {
+                return Err(invalid("block isn't in the palette"));
+            }
+            schematic.blocks = indices;
+        }
+
+        for entity in compound_list(blocks, "BlockEntities")? {
+            let Some(Nbt::IntArray(pos)) = entity.get("Pos") else {
+                return Err(invalid("block entity without a Pos"));
+            };
+            let pos = pos[..]
+                .try_into()
+                .map_err(|_| invalid("Pos isn't 3 ints"))?;
+            schematic
+                .block_entities
+                .push((pos, entity_data(entity, version)?));
+        }
+        for entity in compound_list(schem, "Entities")? {
+            let Some(Nbt::List(NbtList::Double(pos))) = entity.get("Pos") else {
+                return Err(invalid("entity without a Pos"));
+            };
+            let pos = pos[..]
+                .try_into()
+                .map_err(|_| invalid("Pos isn't 3 doubles"))?;
+            schematic
+                .entities
+                .push((pos, entity_data(entity, version)?));
+        }
+        Ok(schematic)
+    }
+
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let mut palette = CompoundNbt::new("");
+        for (i, state) in self.palette.iter().enumerate() {
+            palette.set(state.to_string(), Nbt::Int(i as i32));
+        }
+        let mut data = Vec::new();
+        for &block in &self.blocks {
+            write_varint(&mut data, block as i32);
+        }
+        let data = Nbt::ByteArray(data.into_iter().map(|b| b as i8).collect());
+        let block_entities: Vec<_> = self
+            .block_entities
+            .iter()
+            .map(|(pos, nbt)| {
+                let mut c = entity_compound(nbt, self.version);
+                c.set("Pos", Nbt::IntArray(pos.to_vec().into()));
+                c
+            })
+            .collect();
+        let entities: Vec<_> = self
+            .entities
+            .iter()
+            .map(|(pos, nbt)| {
+                let mut c = entity_compound(nbt, self.version);
+                c.set("Pos", Nbt::List(NbtList::Double(pos.to_vec().into())));
+                c
+            })
+            .collect();
+
+        let version = match self.version {
+            SchematicVersion::V2 => 2,
+            SchematicVersion::V3 => 3,
+        };
+        let mut schem = CompoundNbt::new("Schematic");
+        schem.set("Version", Nbt::Int(version));
+        schem.set("DataVersion", Nbt::Int(self.data_version));
+        schem.set("Width", Nbt::Short(self.size[0] as i16));
+        schem.set("Height", Nbt::Short(self.size[1] as i16));
+        schem.set("Length", Nbt::Short(self.size[2] as i16));
+        schem.set("Offset", Nbt::IntArray(self.offset.to_vec().into()));
+        schem.set("Entities", Nbt::List(NbtList::Compound(entities.into())));
+        let block_entities = Nbt::List(NbtList::Compound(block_entities.into()));
+        match self.version {
+            SchematicVersion::V2 => {
+                schem.set("PaletteMax", Nbt::Int(self.palette.len() as i32));
+                schem.set("Palette", Nbt::Compound(palette));
+                schem.set("BlockData", data);
+                schem.set("BlockEntities", block_entities);
+                schem
+            }
+            SchematicVersion::V3 => {
+                let mut blocks = CompoundNbt::new("");
+                blocks.set("Palette", Nbt::Compound(palette));
+                blocks.set("Data", data);
+                blocks.set("BlockEntities", block_entities);
+                schem.set("Blocks", Nbt::Compound(blocks));
+                let mut root = CompoundNbt::new("");
+                root.set("Schematic", Nbt::Compound(schem));
+                root
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match read_nbt_file(path)? {
+            Some(root) => Self::from_nbt(&root),
+            None => Err(io::Error::new(ErrorKind::NotFound, "no such schematic")),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_nbt_file(path, &self.to_nbt())
+    }
+
+    /// The schematic as a structure, e.g. to paste it with `place_structure()`.
+    /// Air is kept, so pasting clears what was there.
+    pub fn to_structure(&self) -> Structure {
+        let mut blocks = Vec::with_capacity(self.blocks.len());
+        let [w, h, l] = self.size.map(i32::from);
+        for y in 0..h {
+            for z in 0..l {
+                for x in 0..w {
+                    let pos = [x, y, z];
+                    let nbt = self
+                        .block_entities
+                        .iter()
+                        .find(|(p, _)| *p == pos)
+                        .map(|(_, nbt)| nbt.clone());
+                    blocks.push(StructureBlock {
+                        pos,
+                        state: self.blocks[blocks.len()],
+                        nbt,
+                    });
+                }
+            }
+        }
+        let entities = self
+            .entities
+            .iter()
+            .map(|(pos, nbt)| StructureEntity {
+                pos: *pos,
+                block_pos: pos.map(|p| p.floor() as i32),
+                nbt: nbt.clone(),
+            })
+            .collect();
+        Structure {
+            data_version: self.data_version,
+            size: [w, h, l],
+            palette: self.palette.clone(),
+            blocks,
+            entities,
+        }
+    }
+
+    /// A structure as a schematic. Parts of the structure without blocks (structure voids) become air.
+    pub fn from_structure(structure: &Structure) -> Self {
+        let size = structure
+            .size
+            .map(|s| s.clamp(0, i32::from(u16::MAX)) as u16);
+        let mut schematic = Self::new(size, structure.data_version);
+        schematic.palette = structure.palette.clone();
+        let air = BlockState::new("minecraft:air");
+        let air = match schematic.palette.iter().position(|s| *s == air) {
+            Some(i) => i,
+            None => {
+                schematic.palette.push(air);
+                schematic.palette.len() - 1
+            }
+        };
+        schematic.blocks.fill(air);
+        for block in &structure.blocks {
+            if let Some(i) = schematic.index(block.pos) {
+                schematic.blocks[i] = block.state;
+                if let Some(nbt) = &block.nbt {
+                    schematic.block_entities.push((block.pos, nbt.clone()));
+                }
+            }
+        }
+        schematic.entities = structure
+            .entities
+            .iter()
+            .map(|e| (e.pos, e.nbt.clone()))
+            .collect();
+        schematic
+    }
+}
+
+/// Block entity or entity data with its `id`, from an entry of `BlockEntities` or `Entities`
+fn entity_data(
+    entry: &CompoundNbt<'static>,
+    version: SchematicVersion,
+) -> io::Result<CompoundNbt<'static>> {
+    let Some(Nbt::String(id)) = entry.get("Id") else {
+        return Err(invalid("entity without an Id"));
+    };
+    let mut nbt = CompoundNbt::new("");
+    nbt.set("id", Nbt::String(id.clone()));
+    // version 2 keeps the data next to Pos and Id, version 3 in Data
+    let data = match (version, entry.get("Data")) {
+        (SchematicVersion::V2, _) => Some(entry),
+        (SchematicVersion::V3, Some(Nbt::Compound(data))) => Some(data),
+        (SchematicVersion::V3, _) => None,
+    };
+    for (key, value) in data.into_iter().flat_map(CompoundNbt::props) {
+        if !matches!(key, "Pos" | "Id" | "id") {
+            nbt.set(key.to_owned(), value.clone());
+        }
+    }
+    Ok(nbt)
+}
+
+/// The other way around from `entity_data()`, without the `Pos`
+fn entity_compound(nbt: &CompoundNbt<'static>, version: SchematicVersion) -> CompoundNbt<'static> {
+    let mut data = CompoundNbt::new("");
+    for (key, value) in nbt.props() {
+        if key != "id" {
+            data.set(key.to_owned(), value.clone());
+        }
+    }
+    let mut c = match version {
+        SchematicVersion::V2 => data,
+        SchematicVersion::V3 => {
+            let mut c = CompoundNbt::new("");
+            c.set("Data", Nbt::Compound(data));
+            c
+        }
+    };
+    if let Some(Nbt::String(id)) = nbt.get("id") {
+        c.set("Id", Nbt::String(id.clone()));
+    }
+    c
+}
+
+fn read_varints(data: &[i8]) -> io::Result<Vec<usize>> {
+    let mut values = Vec::new();
+    let (mut value, mut shift) = (0usize, 0);
+    for &byte in data {
+        let byte = byte as u8;
+        value |= usize::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            values.push(value);
+            (value, shift) = (0, 0);
+        } else {
+            shift += 7;
+            if shift > 28 {
+                return Err(invalid("varint too long"));
+            }
+        }
+    }
+    if shift != 0 {
+        return Err(invalid("block data ends in the middle of a varint"));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schematic_roundtrip() {
+        let mut schem = Schematic::new([3, 2, 2], 3578);
+        schem.set_block([0, 0, 0], BlockState::new("minecraft:stone"));
+        let stairs = BlockState::parse("minecraft:oak_stairs[facing=east,half=bottom]").unwrap();
+        assert_eq!(
+            stairs.to_string(),
+            "minecraft:oak_stairs[facing=east,half=bottom]"
+        );
+        schem.set_block([2, 1, 1], stairs.clone());
+        // enough states that indices take two bytes
+        for i in 0..200 {
+            schem.set_block([1, 0, 1], BlockState::new(format!("test:block_{i}")));
+        }
+        let mut sign = CompoundNbt::new("");
+        sign.set("id", Nbt::String("minecraft:sign".into()));
+        sign.set("is_waxed", Nbt::Byte(1));
+        schem.block_entities.push(([0, 0, 0], sign));
+        let mut pig = CompoundNbt::new("");
+        pig.set("id", Nbt::String("minecraft:pig".into()));
+        schem.entities.push(([1.5, 1.0, 0.5], pig));
+        assert_eq!(schem.block_at([2, 1, 1]), Some(&stairs));
+        assert_eq!(schem.block_at([3, 0, 0]), None);
+
+        let path =
+            std::env::temp_dir().join(format!("libmc-schematic-{}.schem", std::process::id()));
+        for version in [SchematicVersion::V2, SchematicVersion::V3] {
+            schem.version = version;
+            schem.save(&path).unwrap();
+            assert_eq!(Schematic::load(&path).unwrap(), schem);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let structure = schem.to_structure();
+        assert_eq!(structure.blocks.len(), 12);
+        assert_eq!(structure.palette[structure.blocks[11].state], stairs);
+        assert!(structure.blocks[0].nbt.is_some());
+        assert_eq!(Schematic::from_structure(&structure), schem);
+    }
+}
@@ -0,0 +1,519 @@
+//! Scoreboard objectives and scores, and per-player statistics, along with
+//! their vanilla on-disk formats (`data/scoreboard.dat` and `stats/<uuid>.json`)
+//! so they survive restarts.
+
+use crate::nbt::{compound_list, invalid, read_nbt_file, write_nbt_file};
+use crate::util::write_atomically;
+use crate::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// The data version of 1.20.2 world files
+pub const DATA_VERSION: i32 = 3578;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum ObjectiveRenderType {
+    #[default]
+    Integer = 0,
+    Hearts = 1,
+}
+
+#[derive(Debug, Clone)]
+pub enum ObjectiveAction<'a> {
+    Create {
+        display_name: &'a TextComponent,
+        render_type: ObjectiveRenderType,
+    },
+    Remove,
+    Update {
+        display_name: &'a TextComponent,
+        render_type: ObjectiveRenderType,
+    },
+}
+
+impl ObjectiveRenderType {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Integer => "integer",
+            Self::Hearts => "hearts",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "integer" => Some(Self::Integer),
+            "hearts" => Some(Self::Hearts),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Objective {
+    pub name: String,
+    /// e.g. `dummy` or `minecraft.custom:minecraft.jump`
+    pub criteria: String,
+    /// JSON text component
+    pub display_name: String,
+    pub render_type: ObjectiveRenderType,
+}
+
+impl Objective {
+    /// A `dummy` objective, whose scores are only changed by the server
+    pub fn dummy(name: impl Into<String>, display_name: &TextComponent) -> Self {
+        Self {
+            name: name.into(),
+            criteria: "dummy".to_owned(),
+            display_name: display_name.to_json(),
+            render_type: ObjectiveRenderType::Integer,
+        }
+    }
+}
+
+/// Objectives, the scores each score holder (usually a player name) has in them,
+/// and which objectives are shown in which display slots
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scoreboard {
+    objectives: BTreeMap<String, Objective>,
+    /// objective -> score holder -> score
+    scores: BTreeMap<String, BTreeMap<String, i32>>,
+    /// display slot (0 = list, 1 = sidebar, 2 = below name, 3.. = team sidebars) -> objective
+    display_slots: BTreeMap<u8, String>,
+}
+
+impl Scoreboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false (and does nothing) if there's already an objective with that name
+    pub fn add_objective(&mut self, objective: Objective) -> bool {
+        if self.objectives.contains_key(&objective.name) {
+            return false;
+        }
+        self.scores.insert(objective.name.clone(), BTreeMap::new());
+        self.objectives.insert(objective.name.clone(), objective);
+        true
+    }
+
+    /// Removes an objective, along with its scores and display slots
+    pub fn remove_objective(&mut self, name: &str) -> Option<Objective> {
+        self.scores.remove(name);
+        self.display_slots.retain(|_, obj| obj != name);
+        self.objectives.remove(name)
+    }
+
+    pub fn objective(&self, name: &str) -> Option<&Objective> {
+        self.objectives.get(name)
+    }
+
+    pub fn objectives(&self) -> impl Iterator<Item = &Objective> {
+        self.objectives.values()
+    }
+
+    pub fn score(&self, objective: &str, holder: &str) -> Option<i32> {
+        self.scores.get(objective)?.get(holder).copied()
+    }
+
+    /// Returns false if there's no such objective
+    pub fn set_score(&mut self, objective: &str, holder: &str, score: i32) -> bool {
+        let Some(scores) = self.scores.get_mut(objective) else {
+            return false;
+        };
+        scores.insert(holder.to_owned(), score);
+        true
+    }
+
+    /// Adds `delta` to a score (which starts at 0), returning the new score, or None if there's no such objective
+    pub fn add_score(&mut self, objective: &str, holder: &str, delta: i32) -> Option<i32> {
+        let score = self
+            .scores
+            .get_mut(objective)?
+            .entry(holder.to_owned())
+            .or_insert(0);
+        *score = score.wrapping_add(delta);
+        Some(*score)
+    }
+
+    /// All the scores in an objective, by score holder
+    pub fn scores(&self, objective: &str) -> impl Iterator<Item = (&str, i32)> {
+        self.scores
+            .get(objective)
+            .into_iter()
+            .flatten()
+            .map(|(holder, score)| (holder.as_str(), *score))
+    }
+
+    /// Removes all of a score holder's scores
+    pub fn reset_scores(&mut self, holder: &str) {
+        for scores in self.scores.values_mut() {
+            scores.remove(holder);
+        }
+    }
+
+    /// Shows `objective` in a display slot, or clears the slot. Returns false if there's no such objective.
+    pub fn set_display_slot(&mut self, slot: u8, objective: Option<&str>) -> bool {
+        match objective {
+            Some(name) if !self.objectives.contains_key(name) => false,
+            Some(name) => {
+                self.display_slots.insert(slot, name.to_owned());
+                true
+            }
+            None => {
+                self.display_slots.remove(&slot);
+                true
+            }
+        }
+    }
+
+    pub fn display_slot(&self, slot: u8) -> Option<&Objective> {
+        self.objectives.get(self.display_slots.get(&slot)?)
+    }
+
+    /// The scoreboard in the format of vanilla's `scoreboard.dat`
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let objectives: Vec<_> = self
+            .objectives
+            .values()
+            .map(|obj| {
+                let mut c = CompoundNbt::new("");
+                c.set("Name", Nbt::String(obj.name.clone().into()));
+                c.set("CriteriaName", Nbt::String(obj.criteria.clone().into()));
+                c.set("DisplayName", Nbt::String(obj.display_name.clone().into()));
+                c.set("RenderType", Nbt::String(obj.render_type.name().into()));
+                c
+            })
+            .collect();
+        let player_scores: Vec<_> = self
+            .scores
+            .iter()
+            .flat_map(|(obj, scores)| {
+                scores
+                    .iter()
+                    .map(move |(holder, score)| (obj, holder, score))
+            })
+            .map(|(obj, holder, score)| {
+                let mut c = CompoundNbt::new("");
+                c.set("Name", Nbt::String(holder.clone().into()));
+                c.set("Objective", Nbt::String(obj.clone().into()));
+                c.set("Score", Nbt::Int(*score));
+                c.set("Locked", Nbt::Byte(0));
+                c
+            })
+            .collect();
+        let mut display_slots = CompoundNbt::new("DisplaySlots");
+        for (slot, obj) in &self.display_slots {
+            display_slots.set(format!("slot_{slot}"), Nbt::String(obj.clone().into()));
+        }
+
+        let mut data = CompoundNbt::new("data");
+        data.set(
+            "Objectives",
+            Nbt::List(NbtList::Compound(Cow::Owned(objectives))),
+        );
+        data.set(
+            "PlayerScores",
+            Nbt::List(NbtList::Compound(Cow::Owned(player_scores))),
+        );
+        data.set("DisplaySlots", Nbt::Compound(display_slots));
+        data.set(
+            "Teams",
+            Nbt::List(NbtList::Compound(Cow::Owned(Vec::new()))),
+        );
+
+        let mut root = CompoundNbt::new("");
+        root.set("data", Nbt::Compound(data));
+        root.set("DataVersion", Nbt::Int(DATA_VERSION));
+        root
+    }
+
+    /// Reads a scoreboard in the format of vanilla's `scoreboard.dat`.
+    /// Scores in objectives that don't exist are dropped.
+    pub fn from_nbt(root: &CompoundNbt<'_>) -> io::Result<Self> {
+        let Some(Nbt::Compound(data)) = root.get("data") else {
+            return Err(invalid("missing data"));
+        };
+        let mut board = Self::new();
+
+        for obj in compound_list(data, "Objectives")? {
+            let render_type = match obj.get("RenderType") {
+                Some(Nbt::String(s)) => ObjectiveRenderType::from_name(s)
+                    .ok_or_else(|| invalid("unknown objective render type"))?,
+                _ => ObjectiveRenderType::Integer,
+            };
+            board.add_objective(Objective {
+                name: string(obj, "Name")?,
+                criteria: string(obj, "CriteriaName")?,
+                display_name: string(obj, "DisplayName")?,
+                render_type,
+            });
+        }
+        for score in compound_list(data, "PlayerScores")? {
+            let Some(Nbt::Int(value)) = score.get("Score") else {
+                return Err(invalid("missing Score"));
+            };
+            board.set_score(
+                &string(score, "Objective")?,
+                &string(score, "Name")?,
+                *value,
+            );
+        }
+        if let Some(Nbt::Compound(slots)) = data.get("DisplaySlots") {
+            for (key, value) in slots.props() {
+                let slot = key.strip_prefix("slot_").and_then(|s| s.parse().ok());
+                if let (Some(slot), Nbt::String(obj)) = (slot, value) {
+                    board.set_display_slot(slot, Some(obj));
+                }
+            }
+        }
+        Ok(board)
+    }
+
+    /// Loads a gzipped `scoreboard.dat`. A missing file is an empty scoreboard.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match read_nbt_file(path)? {
+            Some(root) => Self::from_nbt(&root),
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// Saves to a gzipped `scoreboard.dat`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_nbt_file(path, &self.to_nbt())
+    }
+}
+
+/// One player's statistics, by stat type (`minecraft:custom`, `minecraft:mined`, ...)
+/// and then stat (`minecraft:jump`, `minecraft:stone`, ...)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    stats: BTreeMap<String, BTreeMap<String, i32>>,
+}
+
+impl PlayerStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unset stats are 0
+    pub fn get(&self, stat_type: &str, stat: &str) -> i32 {
+        self.stats
+            .get(stat_type)
+            .and_then(|s| s.get(stat))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn set(&mut self, stat_type: &str, stat: &str, value: i32) {
+        self.stats
+            .entry(stat_type.to_owned())
+            .or_default()
+            .insert(stat.to_owned(), value);
+    }
+
+    /// Returns the new value. Like vanilla, stats saturate instead of overflowing.
+    pub fn increment(&mut self, stat_type: &str, stat: &str, by: i32) -> i32 {
+        let value = self.get(stat_type, stat).saturating_add(by);
+        self.set(stat_type, stat, value);
+        value
+    }
+
+    /// Whether the stat has been set at all
+    pub fn has(&self, stat_type: &str, stat: &str) -> bool {
+        self.stats
+            .get(stat_type)
+            .is_some_and(|s| s.contains_key(stat))
+    }
+
+    /// All the stats of one type
+    pub fn of_type(&self, stat_type: &str) -> impl Iterator<Item = (&str, i32)> {
+        self.stats
+            .get(stat_type)
+            .into_iter()
+            .flatten()
+            .map(|(stat, value)| (stat.as_str(), *value))
+    }
+
+    /// The stats in the format of vanilla's `stats/<uuid>.json`
+    pub fn to_json(&self) -> String {
+        let mut out = String::from(r#"{"stats":{"#);
+        for (i, (stat_type, stats)) in self.stats.iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            write_json_string(&mut out, stat_type);
+            out.push_str(":{");
+            for (j, (stat, value)) in stats.iter().enumerate() {
+                if j != 0 {
+                    out.push(',');
+                }
+                write_json_string(&mut out, stat);
+                out.push(':');
+                out.push_str(&value.to_string());
+            }
+            out.push('}');
+        }
+        out.push_str(&format!(r#"}},"DataVersion":{DATA_VERSION}}}"#));
+        out
+    }
+
+    pub fn from_json(s: &str) -> io::Result<Self> {
+        let json = Json::parse(s).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let mut stats = Self::new();
+        let types = json
+            .get("stats")
+            .and_then(Json::as_object)
+            .ok_or_else(|| invalid("missing stats"))?;
+        for (stat_type, values) in types {
+            let values = values
+                .as_object()
+                .ok_or_else(|| invalid("stat type isn't an object"))?;
+            for (stat, value) in values {
+                let value = value
+                    .as_f64()
+                    .ok_or_else(|| invalid("stat isn't a number"))?;
+                stats.set(stat_type, stat, value as i32);
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// Every player's statistics, stored in a `stats` directory the way vanilla does
+#[derive(Debug)]
+pub struct StatsStore {
+    dir: PathBuf,
+    players: HashMap<Uuid, PlayerStats>,
+    /// players whose stats changed since the last save
+    dirty: HashSet<Uuid>,
+}
+
+impl StatsStore {
+    /// Loads the stats of every player in `dir`, which is created if it doesn't exist
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut players = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(uuid) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(Uuid::parse)
+            else {
+                continue;
+            };
+            players.insert(uuid, PlayerStats::from_json(&fs::read_to_string(&path)?)?);
+        }
+        Ok(Self {
+            dir,
+            players,
+            dirty: HashSet::new(),
+        })
+    }
+
+    pub fn get(&self, uuid: Uuid) -> Option<&PlayerStats> {
+        self.players.get(&uuid)
+    }
+
+    /// A player's stats, to be modified. They're written out on the next `save()`.
+    pub fn get_mut(&mut self, uuid: Uuid) -> &mut PlayerStats {
+        self.dirty.insert(uuid);
+        self.players.entry(uuid).or_default()
+    }
+
+    /// Every player's stats, including those of players that are offline
+    pub fn players(&self) -> impl Iterator<Item = (Uuid, &PlayerStats)> {
+        self.players.iter().map(|(uuid, stats)| (*uuid, stats))
+    }
+
+    /// The `n` players with the highest value of a stat, highest first. Players who don't have the stat are left out.
+    pub fn top(&self, stat_type: &str, stat: &str, n: usize) -> Vec<(Uuid, i32)> {
+        let mut all: Vec<_> = self
+            .players
+            .iter()
+            .filter(|(_, stats)| stats.has(stat_type, stat))
+            .map(|(uuid, stats)| (*uuid, stats.get(stat_type, stat)))
+            .collect();
+        // ties are broken by UUID so the order is stable
+        all.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        all.truncate(n);
+        all
+    }
+
+    /// Writes out the stats that changed since the last save
+    pub fn save(&mut self) -> io::Result<()> {
+        for uuid in self.dirty.drain() {
+            let path = self.dir.join(format!("{uuid}.json"));
+            write_atomically(&path, self.players[&uuid].to_json().as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn string(c: &CompoundNbt<'_>, key: &str) -> io::Result<String> {
+    match c.get(key) {
+        Some(Nbt::String(s)) => Ok(s.to_string()),
+        _ => Err(invalid("missing string")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persist_and_reload() {
+        let dir = std::env::temp_dir().join(format!("libmc-scoreboard-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut board = Scoreboard::new();
+        assert!(board.add_objective(Objective::dummy("kills", &"Kills".into())));
+        assert!(!board.add_objective(Objective::dummy("kills", &"Again".into())));
+        board.set_score("kills", "Notch", 3);
+        assert_eq!(board.add_score("kills", "Notch", 2), Some(5));
+        assert!(!board.set_score("deaths", "Notch", 1));
+        board.set_display_slot(1, Some("kills"));
+        board.save(&dir.join("scoreboard.dat")).unwrap();
+
+        let loaded = Scoreboard::load(&dir.join("scoreboard.dat")).unwrap();
+        assert_eq!(loaded, board);
+        assert_eq!(loaded.display_slot(1).unwrap().name, "kills");
+        assert_eq!(
+            Scoreboard::load(&dir.join("missing.dat")).unwrap(),
+            Scoreboard::new()
+        );
+
+        let uuid = 0x069a79f444e94726a5befca90e38aaf5;
+        let mut store = StatsStore::open(dir.join("stats")).unwrap();
+        store
+            .get_mut(Uuid(uuid))
+            .increment("minecraft:custom", "minecraft:jump", 7);
+        store
+            .get_mut(Uuid(uuid))
+            .set("minecraft:mined", "minecraft:stone", i32::MAX);
+        store
+            .get_mut(Uuid(uuid))
+            .increment("minecraft:mined", "minecraft:stone", 1);
+        store.save().unwrap();
+        assert!(dir
+            .join("stats/069a79f4-44e9-4726-a5be-fca90e38aaf5.json")
+            .exists());
+
+        let store = StatsStore::open(dir.join("stats")).unwrap();
+        let stats = store.get(Uuid(uuid)).unwrap();
+        assert_eq!(stats.get("minecraft:custom", "minecraft:jump"), 7);
+        assert_eq!(stats.get("minecraft:mined", "minecraft:stone"), i32::MAX);
+        assert_eq!(stats.get("minecraft:mined", "minecraft:dirt"), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
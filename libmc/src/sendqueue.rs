@@ -0,0 +1,157 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// A client this far behind on receiving gets disconnected, instead of queueing without bound
+pub(crate) const MAX_QUEUED_BYTES: usize = 8 * 1024 * 1024;
+
+/// Writes to a socket from a dedicated thread, so game logic can send packets freely and a slow
+/// client never blocks the tick loop. Writes only queue the data; the thread writes it in order.
+/// With `NetBackend::EventLoop`, the event loop does the writing instead of a thread.
+///
+/// Once a write on the thread fails, the thread stops and every later write here fails.
+#[derive(Debug)]
+pub(crate) struct SendQueue {
+    inner: Inner,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Thread {
+        tx: Sender<Vec<u8>>,
+        /// Bytes handed to the thread but not yet written out
+        queued: Arc<AtomicUsize>,
+        thread: JoinHandle<()>,
+    },
+    #[cfg(feature = "mio")]
+    EventLoop(crate::eventloop::EventLoopWriter),
+}
+
+impl SendQueue {
+    pub fn new<W: Write + Send + 'static>(mut inner: W) -> Self {
+        let (tx, rx) = mpsc::channel::<Vec<u8>>();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let thread_queued = queued.clone();
+        let thread = thread::spawn(move || {
+            for data in rx {
+                let res = inner.write_all(&data).and_then(|()| inner.flush());
+                thread_queued.fetch_sub(data.len(), Ordering::Relaxed);
+                if res.is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            inner: Inner::Thread { tx, queued, thread },
+        }
+    }
+
+    #[cfg(feature = "mio")]
+    pub fn event_loop(writer: crate::eventloop::EventLoopWriter) -> Self {
+        Self {
+            inner: Inner::EventLoop(writer),
+        }
+    }
+
+    /// Waits for everything queued to be written out (or for the writing to fail)
+    pub fn finish(self) {
+        match self.inner {
+            Inner::Thread { tx, thread, .. } => {
+                drop(tx);
+                let _ = thread.join();
+            }
+            #[cfg(feature = "mio")]
+            Inner::EventLoop(writer) => writer.finish(),
+        }
+    }
+
+    pub fn queued_bytes(&self) -> usize {
+        match &self.inner {
+            Inner::Thread { queued, .. } => queued.load(Ordering::Relaxed),
+            #[cfg(feature = "mio")]
+            Inner::EventLoop(writer) => writer.queued_bytes(),
+        }
+    }
+}
+
+impl Write for SendQueue {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let queued_bytes = self.queued_bytes();
+        let (tx, queued) = match &mut self.inner {
+            Inner::Thread { tx, queued, .. } => (tx, queued),
+            #[cfg(feature = "mio")]
+            Inner::EventLoop(writer) => return writer.write(data),
+        };
+        if queued_bytes + data.len() > MAX_QUEUED_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "client isn't keeping up with what's sent to it",
+            ));
+        }
+        queued.fetch_add(data.len(), Ordering::Relaxed);
+        tx.send(data.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(data.len())
+    }
+
+    /// Doesn't wait for the data to actually be written
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+    use std::time::{Duration, Instant};
+
+    /// Forwards writes to the test, and fails once it's done
+    struct Forward(Sender<Vec<u8>>);
+
+    impl Write for Forward {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0
+                .send(data.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn recv(rx: &Receiver<Vec<u8>>) -> Vec<u8> {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn in_order_until_failure() {
+        let (tx, rx) = mpsc::channel();
+        let mut q = SendQueue::new(Forward(tx));
+        q.write_all(&[1, 2]).unwrap();
+        q.write_all(&[3]).unwrap();
+        assert_eq!(recv(&rx), [1, 2]);
+        assert_eq!(recv(&rx), [3]);
+
+        // finish() waits for the thread to write everything
+        let (tx2, rx2) = mpsc::channel();
+        let mut q2 = SendQueue::new(Forward(tx2));
+        q2.write_all(&[9]).unwrap();
+        q2.finish();
+        assert_eq!(rx2.try_recv().unwrap(), [9]);
+
+        assert!(q.write_all(&vec![0; MAX_QUEUED_BYTES + 1]).is_err());
+
+        // once the thread's write fails, so do ours
+        drop(rx);
+        let start = Instant::now();
+        while q.write_all(&[4]).is_ok() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::yield_now();
+        }
+    }
+}
@@ -1,65 +1,2044 @@
+use crate::ownedpacket::QueuedPacket;
+use crate::sendqueue::SendQueue;
+use crate::websocket::{websocket_handshake, WsReader, WsWriter};
 use crate::*;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Copy, Clone)]
-pub struct ClientID(u32);
+/// How often players are sent a Keep Alive, in ticks (as often as vanilla)
+const KEEP_ALIVE_INTERVAL: u64 = 15 * TICKS_PER_SECOND as u64;
+
+/// How often the tab list's ping bars are updated, in ticks (as often as vanilla)
+const LATENCY_UPDATE_INTERVAL: u64 = 30 * TICKS_PER_SECOND as u64;
+
+/// A connected client.
+///
+/// IDs are generational: once a client disconnects, its index goes to a later client, but with
+/// the next generation. So an ID kept after its client left (e.g. by a scheduled task) never
+/// refers to anyone else, and sending to it just fails.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ClientID {
+    index: u32,
+    generation: u32,
+}
+
+impl ClientID {
+    pub(crate) const fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+
+    /// Small and reused, e.g. to keep per-client data in a `Vec`. Check the generation too.
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+
+    /// The index and generation packed together, e.g. for a key that fits in a `u64`
+    pub fn to_bits(self) -> u64 {
+        u64::from(self.generation) << 32 | u64::from(self.index)
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self::new(bits as u32, (bits >> 32) as u32)
+    }
+}
+
+/// Hands out `ClientID`s to the threads that accept clients. An index is only reused once the
+/// main thread releases its client.
+#[derive(Debug, Default)]
+pub(crate) struct ClientIDAllocator(Mutex<ClientSlots>);
+
+#[derive(Debug, Default)]
+struct ClientSlots {
+    /// The current generation of each index
+    generations: Vec<u32>,
+    free: Vec<u32>,
+}
+
+impl ClientIDAllocator {
+    pub(crate) fn allocate(&self) -> ClientID {
+        let mut slots = self.0.lock().unwrap();
+        let index = match slots.free.pop() {
+            Some(index) => index,
+            None => {
+                slots.generations.push(0);
+                (slots.generations.len() - 1) as u32
+            }
+        };
+        ClientID::new(index, slots.generations[index as usize])
+    }
+
+    /// Lets `cid`'s index be reused, by a new generation. Releasing it again does nothing.
+    pub(crate) fn release(&self, cid: ClientID) {
+        let mut slots = self.0.lock().unwrap();
+        let Some(generation) = slots.generations.get_mut(cid.index as usize) else {
+            return;
+        };
+        if *generation == cid.generation {
+            *generation = generation.wrapping_add(1);
+            slots.free.push(cid.index);
+        }
+    }
+}
+
+/// Why a client disconnected
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DisconnectCause {
+    /// The client closed the connection in between packets
+    Closed,
+    /// The connection ended in the middle of a packet
+    Truncated,
+    /// The client sent a malformed packet frame
+    BadFrame(&'static str),
+    /// The client sent a packet that couldn't be decoded, with why if it's known
+    BadPacket(Option<DecodeError>),
+    /// Sending a packet to the client failed
+    WriteFailed(std::io::ErrorKind),
+    /// The server kicked the client, with `ServerHandle::kick()`
+    Kicked,
+    /// The client sent more than its `PacketRateLimits` allow
+    PacketSpam,
+    /// The client sent nothing for `ServerConfig::read_timeout`
+    TimedOut,
+    /// The player did nothing for `ServerConfig::player_idle_timeout`
+    Idle,
+    /// The client tried to log in with a protocol version that isn't supported, and was told so
+    UnsupportedVersion(i32),
+    Io(std::io::ErrorKind),
+}
 
 pub trait Server {
+    /// Called when a client connects. Its address is in `ServerHandle::session()`; where it
+    /// connected to and its protocol version come with its handshake, in `on_handshake()`.
     fn on_connect(&mut self, cid: ClientID);
-    fn on_disconnect(&mut self, cid: ClientID);
+    fn on_disconnect(&mut self, cid: ClientID, cause: DisconnectCause);
     fn handle_packet(&mut self, cid: ClientID, packet: InPacket);
+    /// Called 20 times per second, in between handling packets. `tick` counts up from 0.
+    fn tick(&mut self, tick: u64);
+
+    /// Called when the server fell so far behind that it skipped ticks instead of catching up on
+    /// them, with how far behind it was (vanilla logs "Can't keep up!")
+    fn on_ticks_skipped(&mut self, _behind: Duration) {}
+
+    /// Called instead of `on_connect()` when a client is handed over from another server (see `ServerRouter`)
+    fn on_attach(&mut self, _cid: ClientID) {}
+
+    /// Called instead of `on_disconnect()` when a client is handed over to another server
+    fn on_detach(&mut self, _cid: ClientID) {}
+
+    /// Called when a client connects, to choose when packets sent to it are written out
+    fn flush_policy(&mut self, _cid: ClientID) -> FlushPolicy {
+        FlushPolicy::Adaptive
+    }
+
+    /// Called right after a client connects, before `on_connect()`, e.g. for IP bans.
+    /// Returning false closes the connection without anything else being called for it.
+    fn filter_connection(&mut self, _addr: SocketAddr) -> bool {
+        true
+    }
+
+    /// Called with a client's handshake, before anything else is done with it. Returning an error
+    /// disconnects the client, showing it the error if it was logging in.
+    fn filter_handshake(
+        &mut self,
+        _cid: ClientID,
+        _handshake: &HandshakeInfo,
+    ) -> Result<(), TextComponent> {
+        Ok(())
+    }
+
+    /// Called with a player's Login Start, before they're let in. Returning an error disconnects
+    /// them with it, e.g. when a `PlayerJoinEvent` was cancelled.
+    fn filter_login(
+        &mut self,
+        _cid: ClientID,
+        _name: &str,
+        _uuid: Uuid,
+    ) -> Result<(), TextComponent> {
+        Ok(())
+    }
+
+    /// Called with a client's handshake once it has been accepted: where the client is connecting
+    /// from, the address and port it connected to (e.g. for virtual hosts), and its protocol version
+    fn on_handshake(&mut self, _cid: ClientID, _handshake: &HandshakeInfo) {}
+
+    /// Called when the view distance `cid` is sent chunks for changes: when it asks for a
+    /// different one (see `ClientSession::effective_view_distance()`), or the server's changes on
+    /// reload. The client has already been told; pass it on to its `ChunkSendQueue`. Before the
+    /// first change it's `ServerHandle::view_distance()`.
+    fn on_view_distance(&mut self, _cid: ClientID, _view_distance: i32) {}
+
+    /// Called when writing `cid`'s packet capture (see `ServerHandle::set_capture()`) failed, e.g.
+    /// because the disk is full. The capture has been stopped.
+    fn on_capture_failed(&mut self, _cid: ClientID, _error: std::io::Error) {}
+
+    /// Called once when `run_server()` starts, to limit how many clients can connect
+    fn connection_limits(&mut self) -> ConnectionLimits {
+        ConnectionLimits::default()
+    }
+
+    /// Called when a client connects, to choose how many packets it may send before it's kicked
+    fn packet_rate_limits(&mut self, _cid: ClientID) -> PacketRateLimits {
+        PacketRateLimits::default()
+    }
+
+    /// Where server list pings get their status from. Without one, a default status is sent.
+    fn status_cache(&mut self) -> Option<StatusCache> {
+        None
+    }
+
+    /// Where `cid`'s server list ping gets its status from, e.g. to show another MOTD depending
+    /// on the address it pinged (see `ServerRouter::add_host()`). `status_cache()` by default.
+    fn status_cache_for(&mut self, _cid: ClientID) -> Option<StatusCache> {
+        self.status_cache()
+    }
+
+    /// The dimensions `cid` is told about when it joins, and the one it spawns in (see
+    /// `Dimensions::login_dimensions()`). Without them, it's told about a placeholder dimension.
+    fn login_dimensions(&mut self, _cid: ClientID) -> Option<LoginDimensions> {
+        None
+    }
+
+    /// Called once when `run_server()` starts, with the handle to send packets through
+    fn on_start(&mut self, _handle: ServerHandle) {}
+
+    /// Called when a reload is asked for (with `ServerHandle::request_reload()`, or SIGHUP if
+    /// `ServerConfig::reload_on_sighup` is set), for the config to switch to, e.g. read from
+    /// `server.properties` again. None keeps the current one. See `ServerHandle::reload_config()`.
+    fn reload_config(&mut self) -> Option<ServerConfig> {
+        None
+    }
+
+    /// Called after the config from `reload_config()` was switched to, with the settings that kept
+    /// their old values because they can't change without a restart, e.g. to log them
+    fn on_config_reloaded(&mut self, _needs_restart: &[&'static str]) {}
+
+    /// Called on `ServerHandle::shutdown()`, after every client got `on_disconnect()`ed, to save
+    /// worlds and the like. `run_server()` returns afterwards.
+    fn on_shutdown(&mut self) {}
+
+    /// Called for clients whose protocol version isn't `PROTOCOL_VERSION`, to get a translator
+    /// for them. Clients without one can still ping the server, but are told that they're outdated
+    /// (or that the server is) when they try to log in.
+    fn translator(&mut self, protocol_version: i32) -> Option<Box<dyn ProtocolTranslator>> {
+        builtin_translator(protocol_version)
+    }
+
+    /// Called when a Bedrock client connects (see `ServerBuilder::bedrock()`), for the Bedrock
+    /// blocks it's shown. By default every block but air is stone.
+    fn bedrock_blocks(&mut self) -> Arc<BedrockBlocks> {
+        Arc::default()
+    }
 }
 
-pub fn run_server<S: Server>(mut s: S) {
-    let todo_cid = ClientID(0);
+/// What a client said in its handshake, for `Server::filter_handshake()`
+#[derive(Debug, Copy, Clone)]
+pub struct HandshakeInfo<'a> {
+    /// Where the client is connecting from
+    pub addr: Option<SocketAddr>,
+    /// Which of the server's addresses it connected to (see `ClientSession::listener`), e.g. to
+    /// only let clients through a proxy's port
+    pub listener: &'a str,
+    pub protocol_version: i32,
+    /// The address the client connected to, as typed in by the player, without any Forge marker
+    pub server_addr: &'a str,
+    pub server_port: u16,
+    /// Set for Forge clients, e.g. to reject modded clients
+    pub forge: Option<ForgeMarker>,
+    pub next_state: HandshakeNextState,
+}
+
+/// How a client's packet frames are carried
+pub(crate) enum Transport {
+    Tcp,
+    /// In binary messages, over a stream that has done the WebSocket handshake
+    WebSocket,
+    /// Over TCP, read and written by the event loop, which gave us this to write with
+    #[cfg(feature = "mio")]
+    EventLoop(crate::eventloop::EventLoopWriter),
+    /// As Bedrock game packets, by the Bedrock front-end's thread
+    Bedrock(BedrockLink),
+}
+
+/// A client's connection, for closing it and setting it up
+pub(crate) enum Socket {
+    Tcp(TcpStream),
+    /// Bedrock clients share the front-end's UDP socket, and RakNet has its own timeouts
+    Bedrock(BedrockLink),
+}
 
-    let (stream, _) = std::net::TcpListener::bind("127.0.0.1:25565")
-        .unwrap()
-        .accept()
-        .unwrap();
-    let mut ps = PacketStream::new(std::io::BufReader::new(&stream), &stream);
+impl Socket {
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        match self {
+            Socket::Tcp(stream) => stream.peer_addr(),
+            Socket::Bedrock(link) => Ok(link.peer_addr()),
+        }
+    }
+
+    fn try_clone(&self) -> std::io::Result<TcpStream> {
+        match self {
+            Socket::Tcp(stream) => stream.try_clone(),
+            Socket::Bedrock(_) => Err(std::io::ErrorKind::Unsupported.into()),
+        }
+    }
 
-    // TODO: multiple clients (increment cid)
-    s.on_connect(todo_cid);
+    fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.shutdown(how),
+            Socket::Bedrock(link) => {
+                link.shutdown(how);
+                Ok(())
+            }
+        }
+    }
+
+    fn set_nodelay(&self, nodelay: bool) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_nodelay(nodelay),
+            Socket::Bedrock(_) => Ok(()),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_read_timeout(timeout),
+            Socket::Bedrock(_) => Ok(()),
+        }
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_write_timeout(timeout),
+            Socket::Bedrock(_) => Ok(()),
+        }
+    }
+}
+
+impl From<TcpStream> for Socket {
+    fn from(stream: TcpStream) -> Self {
+        Socket::Tcp(stream)
+    }
+}
+
+/// Sent from the network threads to the main (tick) thread
+pub(crate) enum NetEvent {
+    /// With the tag of the listener it came from
+    Connected(ClientID, Socket, Transport, Arc<str>),
+    /// A whole packet frame, including its length prefix
+    Frame(ClientID, Vec<u8>),
+    Closed(ClientID, DisconnectCause),
+    /// A `StatusCache` has the reply to a client's status request
+    Status(ClientID, Arc<str>),
+}
+
+struct Connection {
+    /// Incoming frames are decoded on the main thread, with `ps.decode()`
+    ps: PacketStream<CoalescingWriter<SendQueue>>,
+    stream: Socket,
+    session: ClientSession,
+    bandwidth: BandwidthTracker,
+    /// Encoded packets held back by the bandwidth cap, oldest first
+    deferred: VecDeque<(PacketCategory, Vec<u8>)>,
+    /// What the client sends us
+    incoming: PacketRateTracker,
+    /// Times the Keep Alives we send
+    ping: PingTracker,
+    idle: IdleTracker<Rc<dyn Clock>>,
+    /// Why we closed the connection, if we did
+    kicked: Option<DisconnectCause>,
+    backpressure: BackpressurePolicy,
+    /// What the client was told its view distance is, or will be told when it joins
+    view_distance: i32,
+}
+
+impl Connection {
+    /// Closes the socket. The client's reader thread then notices and reports the disconnect.
+    fn kick(&mut self, cause: DisconnectCause) {
+        self.kicked.get_or_insert(cause);
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Tells the client why it's being disconnected, then disconnects it. Only the reading half is
+    /// closed right away, so that the send thread still gets to write out the disconnect packet.
+    fn disconnect(&mut self, reason: &TextComponent, cause: DisconnectCause) {
+        if self.kicked.is_some() {
+            return;
+        }
+        let packet = match self.ps.state() {
+            ProtocolState::Login => Some(OutPacket::DisconnectLogin { reason }),
+            ProtocolState::Config => Some(OutPacket::DisconnectConfig { reason }),
+            ProtocolState::Play => Some(OutPacket::Disconnect { reason }),
+            ProtocolState::Handshaking | ProtocolState::Status => None,
+        };
+        let sent = match packet {
+            Some(packet) => self
+                .send(packet)
+                .and_then(|()| self.ps.writer_mut().flush()),
+            None => Ok(()),
+        };
+        if sent.is_err() {
+            self.kick(cause);
+            return;
+        }
+        self.kicked = Some(cause);
+        let _ = self.stream.shutdown(Shutdown::Read);
+    }
+
+    /// Works out the client's view distance again, with the server's being `max`, and tells it if
+    /// it's in game. Returns the new view distance if it changed.
+    fn update_view_distance(&mut self, max: i32) -> std::io::Result<Option<i32>> {
+        let view_distance = self.session.effective_view_distance(max);
+        if view_distance == self.view_distance {
+            return Ok(None);
+        }
+        self.view_distance = view_distance;
+        if self.ps.state() == ProtocolState::Play {
+            self.send(OutPacket::SetRenderDistance { view_distance })?;
+        }
+        Ok(Some(view_distance))
+    }
+
+    /// Bytes waiting to be written to the client
+    fn queued_bytes(&mut self) -> usize {
+        let writer = self.ps.writer_mut();
+        writer.buffered() + writer.get_ref().queued_bytes()
+    }
+
+    /// Whether a packet should be sent, per the `BackpressurePolicy`. Fails if the client should
+    /// be kicked instead.
+    fn should_send(&mut self, category: PacketCategory, droppable: bool) -> std::io::Result<bool> {
+        let queued = self.queued_bytes();
+        match self.backpressure.action_for(queued, category, droppable) {
+            BackpressureAction::Send => Ok(true),
+            BackpressureAction::Drop => Ok(false),
+            BackpressureAction::Kick => Err(falling_behind()),
+        }
+    }
+
+    /// Whether a packet of `category` has to wait for the bandwidth cap. Once one does, later ones
+    /// of deferrable categories wait behind it, so they stay in order.
+    fn must_defer(&mut self, category: PacketCategory) -> bool {
+        (category.is_deferrable() && !self.deferred.is_empty()) || !self.bandwidth.allows(category)
+    }
+
+    /// Holds `packet` back until `send_deferred()`, or drops it if a later packet makes up for it
+    fn defer(&mut self, category: PacketCategory, packet: OutPacket) {
+        if !packet.is_droppable() {
+            let encoded = encode_packet(packet, self.ps.protocol_version());
+            self.deferred.push_back((category, encoded));
+        }
+    }
+
+    /// Sends `packet`, unless it's dropped because the client is behind, or held back by the
+    /// bandwidth cap until `send_deferred()`
+    fn send(&mut self, packet: OutPacket) -> std::io::Result<()> {
+        let category = packet.category();
+        if self.must_defer(category) {
+            self.defer(category, packet);
+            return Ok(());
+        }
+        if !self.should_send(category, packet.is_droppable())? {
+            return Ok(());
+        }
+        let nbytes = self.ps.send(packet)?;
+        self.bandwidth.record(category, nbytes);
+        Ok(())
+    }
+
+    /// Sends a packet encoded with `encode_packet()`, kicking the client if that fails. Returns
+    /// false if it wasn't sent (packets held back by the bandwidth cap count as sent).
+    fn send_encoded(&mut self, category: PacketCategory, packet: &[u8]) -> bool {
+        if self.must_defer(category) {
+            self.deferred.push_back((category, packet.to_vec()));
+            return true;
+        }
+        let res = match self.should_send(category, false) {
+            Ok(true) => self.ps.send_encoded(packet),
+            Ok(false) => return false,
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(nbytes) => {
+                self.bandwidth.record(category, nbytes);
+                true
+            }
+            Err(e) => {
+                self.kick(DisconnectCause::WriteFailed(e.kind()));
+                false
+            }
+        }
+    }
+
+    /// Sends `packets` in one write, leaving out the ones dropped because the client is behind
+    fn send_all(&mut self, mut packets: Vec<OutPacket>) -> std::io::Result<()> {
+        let queued = self.queued_bytes();
+        let mut kick = false;
+        packets.retain(|packet| match self.backpressure.action(queued, packet) {
+            BackpressureAction::Send => true,
+            BackpressureAction::Drop => false,
+            BackpressureAction::Kick => {
+                kick = true;
+                false
+            }
+        });
+        if kick {
+            return Err(falling_behind());
+        }
+        packets.retain(|packet| {
+            let category = packet.category();
+            if !self.must_defer(category) {
+                return true;
+            }
+            self.defer(category, packet.clone());
+            false
+        });
+        let categories: Vec<_> = packets.iter().map(OutPacket::category).collect();
+        let sizes = self.ps.send_all(packets)?;
+        for (category, nbytes) in categories.into_iter().zip(sizes) {
+            self.bandwidth.record(category, nbytes);
+        }
+        Ok(())
+    }
+
+    /// Sends the packets held back by the bandwidth cap, for as long as it allows
+    fn send_deferred(&mut self) -> std::io::Result<()> {
+        while let Some(&(category, _)) = self.deferred.front() {
+            if !self.bandwidth.allows(category) {
+                break;
+            }
+            let (category, packet) = self.deferred.pop_front().unwrap();
+            if self.should_send(category, false)? {
+                let nbytes = self.ps.send_encoded(&packet)?;
+                self.bandwidth.record(category, nbytes);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a client that's too far behind is kicked
+fn falling_behind() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        "client isn't keeping up with what's sent to it",
+    )
+}
+
+/// What `ServerHandle`s share with `run_server()`
+struct Runtime {
+    conns: RefCell<HashMap<ClientID, Connection>>,
+    sampler: RefCell<TickSampler>,
+    tps: Cell<f64>,
+    /// Set by `ServerHandle::shutdown()`, with the reason clients are shown
+    shutdown: RefCell<Option<TextComponent>>,
+    throttle: RefCell<LoginThrottle>,
+    /// The `Server`'s own limits, before `max_players` is applied to them
+    limits: ConnectionLimits,
+    /// Replaced on reload, so the old one can still be used while it happens
+    config: RefCell<Rc<ServerConfig>>,
+    /// Set by `ServerHandle::request_reload()`
+    reload_requested: Cell<bool>,
+    /// Clients whose view distance changed, for `Server::on_view_distance()`
+    view_distance_changes: RefCell<Vec<(ClientID, i32)>>,
+    gauges: RefCell<Vec<(&'static str, f64)>>,
+    /// What the metrics HTTP listener serves, if there is one
+    metrics_export: RefCell<Option<Arc<Mutex<String>>>>,
+    /// What the diagnostics listener serves, if there is one
+    diagnostics_export: RefCell<Option<Arc<Mutex<String>>>>,
+    chunks: RefCell<ChunkCache>,
+    encoder: ChunkEncodePool,
+    /// Chunks being encoded by `encoder`
+    encoding: RefCell<Vec<PendingChunk>>,
+    difficulty: Cell<Difficulty>,
+    difficulty_locked: Cell<bool>,
+    entity_ids: RefCell<EntityIdAllocator>,
+    /// Shared with the threads that accept clients
+    client_ids: Arc<ClientIDAllocator>,
+    /// Packets from `PacketSender`s, sent by `deliver_queued_packets()`
+    queued: Receiver<QueuedPacket>,
+    queue_tx: Sender<QueuedPacket>,
+    /// What players' idle times are measured with
+    clock: Rc<dyn Clock>,
+}
+
+/// A chunk waiting on the `ChunkEncodePool`
+#[derive(Debug)]
+struct PendingChunk {
+    chunk: ChunkPos,
+    protocol_version: i32,
+    /// Who to send it to once it's encoded
+    recipients: Vec<ClientID>,
+    /// Set if it changed since being submitted, so the packet mustn't be cached
+    invalidated: bool,
+}
+
+/// Lets a `Server` send packets to and manage its clients, at any time (not just in response to
+/// a packet). Servers get one in `Server::on_start()`.
+#[derive(Clone)]
+pub struct ServerHandle {
+    rt: Rc<Runtime>,
+}
+
+impl ServerHandle {
+    fn new(
+        config: ServerConfig,
+        limits: ConnectionLimits,
+        client_ids: Arc<ClientIDAllocator>,
+    ) -> Self {
+        Self::with_clock(config, limits, client_ids, Rc::new(SystemClock))
+    }
+
+    fn with_clock(
+        config: ServerConfig,
+        limits: ConnectionLimits,
+        client_ids: Arc<ClientIDAllocator>,
+        clock: Rc<dyn Clock>,
+    ) -> Self {
+        let encoder = ChunkEncodePool::new(config.chunk_encode_threads);
+        let difficulty = config.difficulty;
+        let (queue_tx, queued) = mpsc::channel();
+        Self {
+            rt: Rc::new(Runtime {
+                conns: RefCell::default(),
+                sampler: RefCell::default(),
+                tps: Cell::new(TICKS_PER_SECOND.into()),
+                shutdown: RefCell::default(),
+                throttle: RefCell::new(LoginThrottle::new(capped_limits(&limits, &config))),
+                limits,
+                config: RefCell::new(Rc::new(config)),
+                reload_requested: Cell::new(false),
+                view_distance_changes: RefCell::default(),
+                gauges: RefCell::default(),
+                metrics_export: RefCell::default(),
+                diagnostics_export: RefCell::default(),
+                chunks: RefCell::default(),
+                encoder,
+                encoding: RefCell::default(),
+                difficulty: Cell::new(difficulty),
+                difficulty_locked: Cell::new(false),
+                entity_ids: RefCell::default(),
+                client_ids,
+                queued,
+                queue_tx,
+                clock,
+            }),
+        }
+    }
+
+    /// Sends `packet` to `cid`. Returns false if it isn't connected, or the write failed (in which
+    /// case the client is disconnected).
+    pub fn send(&self, cid: ClientID, packet: OutPacket) -> bool {
+        self.with_conn(cid, |conn| {
+            if conn.kicked.is_some() {
+                return false;
+            }
+            let res = conn.send(packet);
+            if let Err(e) = &res {
+                conn.kick(DisconnectCause::WriteFailed(e.kind()));
+            }
+            res.is_ok()
+        }) == Some(true)
+    }
+
+    /// Sends several packets to `cid` in one write, like `send()`
+    pub fn send_all(&self, cid: ClientID, packets: Vec<OutPacket>) -> bool {
+        self.with_conn(cid, |conn| {
+            if conn.kicked.is_some() {
+                return false;
+            }
+            let res = conn.send_all(packets);
+            if let Err(e) = &res {
+                conn.kick(DisconnectCause::WriteFailed(e.kind()));
+            }
+            res.is_ok()
+        }) == Some(true)
+    }
+
+    /// The clients that are connected (and haven't been kicked), with who they are and where
+    /// they're from, by `ClientID::index()`. Only the ones that are `is_playing()` are in game.
+    pub fn clients(&self) -> Vec<ConnectedClient> {
+        let mut clients: Vec<_> = self
+            .rt
+            .conns
+            .borrow()
+            .iter()
+            .filter(|(_, conn)| conn.kicked.is_none())
+            .map(|(&cid, conn)| ConnectedClient::new(cid, &conn.session, conn.ps.state()))
+            .collect();
+        clients.sort_by_key(|c| c.cid.index());
+        clients
+    }
+
+    pub fn is_connected(&self, cid: ClientID) -> bool {
+        self.with_conn(cid, |conn| conn.kicked.is_none()) == Some(true)
+    }
+
+    /// Disconnects `cid`, showing it `reason`. `Server::on_disconnect()` is called shortly after,
+    /// with `DisconnectCause::Kicked`.
+    pub fn kick(&self, cid: ClientID, reason: &TextComponent) {
+        self.with_conn(cid, |conn| conn.disconnect(reason, DisconnectCause::Kicked));
+    }
+
+    /// The client's protocol state, e.g. to tell whether it's in game yet
+    pub fn state(&self, cid: ClientID) -> Option<ProtocolState> {
+        self.with_conn(cid, |conn| conn.ps.state())
+    }
+
+    /// What `cid` has told us about itself so far: its handshake, login and settings. This is a
+    /// copy; use `with_session()` to change it.
+    pub fn session(&self, cid: ClientID) -> Option<ClientSession> {
+        self.with_conn(cid, |conn| conn.session.clone())
+    }
+
+    /// Calls `f` with `cid`'s session, e.g. to attach something to it (see
+    /// `ClientSession::insert()`)
+    pub fn with_session<R>(
+        &self,
+        cid: ClientID,
+        f: impl FnOnce(&mut ClientSession) -> R,
+    ) -> Option<R> {
+        self.with_conn(cid, |conn| f(&mut conn.session))
+    }
+
+    /// The view distance `cid` is sent chunks for: its own, capped to the server's
+    pub fn view_distance(&self, cid: ClientID) -> Option<i32> {
+        self.with_conn(cid, |conn| conn.view_distance)
+    }
+
+    /// How long since `cid`'s player last did something (see `IdleTracker`)
+    pub fn idle_time(&self, cid: ClientID) -> Option<Duration> {
+        self.with_conn(cid, |conn| conn.idle.idle_for())
+    }
+
+    /// Kicks the players that have been idle for longer than `ServerConfig::player_idle_timeout`
+    fn kick_idle_players(&self) {
+        let Some(timeout) = self.config().player_idle_timeout else {
+            return;
+        };
+        let reason = TextComponent::translate("multiplayer.disconnect.idling", []);
+        for conn in self.rt.conns.borrow_mut().values_mut() {
+            if conn.ps.state() == ProtocolState::Play && conn.idle.idle_for() > timeout {
+                conn.disconnect(&reason, DisconnectCause::Idle);
+            }
+        }
+    }
+
+    /// How much has been sent to `cid` recently
+    pub fn bandwidth(&self, cid: ClientID) -> Option<BandwidthStats> {
+        self.with_conn(cid, |conn| conn.bandwidth.stats())
+    }
+
+    /// Caps what's sent to `cid`, in bytes per second (see `BandwidthTracker`), instead of
+    /// `ServerConfig::bandwidth_cap`. Packets over it are held back and sent on later ticks.
+    pub fn set_bandwidth_cap(&self, cid: ClientID, cap: Option<u64>) {
+        self.with_conn(cid, |conn| conn.bandwidth.set_cap(cap));
+    }
+
+    /// Records every packet sent to and received from `cid` from now on (see `PacketCapture`),
+    /// or stops recording with `None`. Call it from `Server::on_connect()` to include the handshake.
+    pub fn set_capture(&self, cid: ClientID, capture: Option<PacketCapture>) {
+        self.with_conn(cid, |conn| conn.ps.set_capture(capture));
+    }
+
+    /// Runs every packet sent to and received from `cid` from now on through `interceptor` (see
+    /// `PacketInterceptor`). Call it from `Server::on_connect()` to include the handshake.
+    pub fn add_interceptor(&self, cid: ClientID, interceptor: Box<dyn PacketInterceptor>) {
+        self.with_conn(cid, |conn| conn.ps.add_interceptor(interceptor));
+    }
+
+    /// Whether `cid` is behind on what's sent to it, per its `BackpressurePolicy`. Hold off on
+    /// sending it more chunks if so.
+    pub fn is_backed_up(&self, cid: ClientID) -> bool {
+        self.with_conn(cid, |conn| {
+            let queued = conn.queued_bytes();
+            conn.backpressure.is_backed_up(queued)
+        }) == Some(true)
+    }
+
+    /// Changes what's done about `cid` falling behind, from `ServerConfig::backpressure`
+    pub fn set_backpressure(&self, cid: ClientID, policy: BackpressurePolicy) {
+        self.with_conn(cid, |conn| conn.backpressure = policy);
+    }
+
+    /// Whether a packet of `category` can be sent to `cid` without going over its bandwidth cap
+    pub fn bandwidth_allows(&self, cid: ClientID, category: PacketCategory) -> bool {
+        self.with_conn(cid, |conn| conn.bandwidth.allows(category)) == Some(true)
+    }
+
+    /// Stops the server once the current callback returns: every client is disconnected with
+    /// `reason`, `Server::on_shutdown()` is called, and `run_server()` returns.
+    pub fn shutdown(&self, reason: TextComponent) {
+        self.rt.shutdown.borrow_mut().get_or_insert(reason);
+    }
+
+    /// Average ticks per second over the last few seconds
+    pub fn tps(&self) -> f64 {
+        self.rt.tps.get()
+    }
+
+    /// Average milliseconds per tick spent not idling (see `TickSampler`)
+    pub fn mspt(&self) -> f64 {
+        self.rt.sampler.borrow().mspt()
+    }
+
+    /// Timings of the last tick
+    pub fn latest_tick(&self) -> Option<TickSample> {
+        self.rt.sampler.borrow().latest()
+    }
+
+    /// What the server was started with
+    /// The current config. It's replaced when reloaded, so don't keep it around.
+    pub fn config(&self) -> Rc<ServerConfig> {
+        Rc::clone(&self.rt.config.borrow())
+    }
+
+    /// Reloads the config with `Server::reload_config()`, at the end of this loop through the
+    /// server's events
+    pub fn request_reload(&self) {
+        self.rt.reload_requested.set(true);
+    }
 
+    /// Switches to `config` without restarting. Returns the settings that can't change without a
+    /// restart, which keep their old values: the addresses, `backend`, `chunk_encode_threads`
+    /// and `reload_on_sighup`.
+    ///
+    /// The MOTD, `max_players`, brand and `difficulty` apply to the next ping or join. Players
+    /// are sent the new view and simulation distances, and kicked if they're no longer on the
+    /// whitelist. The new timeouts apply to every connection, but only to new ones with
+    /// `NetBackend::EventLoop`, and a new `backpressure` policy replaces every client's.
+    pub fn reload_config(&self, config: ServerConfig) -> Vec<&'static str> {
+        let old = self.config();
+        let (config, kept) = old.reloaded(config);
+        self.rt
+            .throttle
+            .borrow_mut()
+            .set_limits(capped_limits(&self.rt.limits, &config));
+        *self.rt.config.borrow_mut() = Rc::new(config);
+        let config = self.config();
+
+        let not_whitelisted = not_whitelisted_message();
+        let mut changes = self.rt.view_distance_changes.borrow_mut();
+        for (&cid, conn) in self.rt.conns.borrow_mut().iter_mut() {
+            if conn.kicked.is_some() {
+                continue;
+            }
+            if conn
+                .session
+                .username
+                .as_deref()
+                .is_some_and(|name| !config.is_whitelisted(name))
+            {
+                conn.disconnect(&not_whitelisted, DisconnectCause::Kicked);
+                continue;
+            }
+            if config.backpressure != old.backpressure {
+                conn.backpressure = config.backpressure.clone();
+            }
+            if config.bandwidth_cap != old.bandwidth_cap {
+                conn.bandwidth.set_cap(config.bandwidth_cap);
+            }
+            let _ = conn.stream.set_read_timeout(Some(config.read_timeout));
+            let _ = conn.stream.set_write_timeout(Some(config.write_timeout));
+            match conn.update_view_distance(config.view_distance) {
+                Ok(Some(view_distance)) => changes.push((cid, view_distance)),
+                Ok(None) => {}
+                Err(e) => {
+                    conn.kick(DisconnectCause::WriteFailed(e.kind()));
+                    continue;
+                }
+            }
+            if conn.ps.state() != ProtocolState::Play {
+                continue;
+            }
+            let mut packets = Vec::new();
+            if config.simulation_distance != old.simulation_distance {
+                packets.push(OutPacket::SetSimulationDistance {
+                    simulation_distance: config.simulation_distance,
+                });
+            }
+            if let Err(e) = conn.send_all(packets) {
+                conn.kick(DisconnectCause::WriteFailed(e.kind()));
+            }
+        }
+        kept
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.rt.difficulty.get()
+    }
+
+    pub fn is_difficulty_locked(&self) -> bool {
+        self.rt.difficulty_locked.get()
+    }
+
+    /// Changes the difficulty and tells every player. This works even if the difficulty is
+    /// locked, like the `/difficulty` command does.
+    pub fn set_difficulty(&self, difficulty: Difficulty) {
+        self.rt.difficulty.set(difficulty);
+        self.broadcast(self.difficulty_packet());
+    }
+
+    /// Greys out the difficulty button in players' options menus
+    pub fn lock_difficulty(&self, locked: bool) {
+        self.rt.difficulty_locked.set(locked);
+        self.broadcast(self.difficulty_packet());
+    }
+
+    /// An entity ID no other entity (including players) has, to be freed with `free_entity_id()`
+    /// once the entity is removed from clients
+    pub fn allocate_entity_id(&self) -> i32 {
+        self.rt.entity_ids.borrow_mut().allocate_id()
+    }
+
+    /// Reserves `count` consecutive entity IDs, e.g. for an `EntityStore`, and returns the first
+    pub fn reserve_entity_ids(&self, count: u32) -> i32 {
+        self.rt.entity_ids.borrow_mut().reserve_range(count)
+    }
+
+    /// Returns false if `id` wasn't allocated, or was already freed
+    pub fn free_entity_id(&self, id: i32) -> bool {
+        self.rt.entity_ids.borrow_mut().free_id(id)
+    }
+
+    /// A random UUID no other entity has, to be freed with `free_entity_uuid()`
+    pub fn allocate_entity_uuid(&self) -> Uuid {
+        self.rt.entity_ids.borrow_mut().allocate_uuid()
+    }
+
+    pub fn free_entity_uuid(&self, uuid: Uuid) -> bool {
+        self.rt.entity_ids.borrow_mut().free_uuid(uuid)
+    }
+
+    /// Sends every player a Keep Alive, which also measures their latency
+    fn send_keep_alives(&self) {
+        for conn in self.rt.conns.borrow_mut().values_mut() {
+            if conn.kicked.is_some() || conn.ps.state() != ProtocolState::Play {
+                continue;
+            }
+            let packet = conn.ping.keep_alive();
+            if let Err(e) = conn.send(packet) {
+                conn.kick(DisconnectCause::WriteFailed(e.kind()));
+            }
+        }
+    }
+
+    /// Updates the ping bars in everyone's tab list
+    fn broadcast_latencies(&self) {
+        let entries: Vec<_> = self
+            .rt
+            .conns
+            .borrow()
+            .values()
+            .filter(|c| c.kicked.is_none() && c.ps.state() == ProtocolState::Play)
+            .filter_map(|c| {
+                Some(PlayerInfoEntry::new(c.session.uuid?).latency(c.ping.latency_ms()))
+            })
+            .collect();
+        if !entries.is_empty() {
+            self.broadcast(OutPacket::PlayerInfoUpdate { entries: &entries });
+        }
+    }
+
+    fn difficulty_packet(&self) -> OutPacket<'static> {
+        OutPacket::ChangeDifficulty {
+            difficulty: self.difficulty(),
+            locked: self.is_difficulty_locked(),
+        }
+    }
+
+    /// Sent in reply to status requests when the server doesn't provide a `StatusCache`
+    fn default_status(&self) -> String {
+        let players = self
+            .rt
+            .conns
+            .borrow()
+            .values()
+            .filter(|c| c.kicked.is_none() && c.ps.state() == ProtocolState::Play)
+            .count();
+        let config = self.config();
+        StatusResponse::new()
+            .players(
+                players.try_into().unwrap_or(i32::MAX),
+                config.max_players.try_into().unwrap_or(i32::MAX),
+            )
+            .motd(config.motd.clone())
+            .to_json()
+    }
+
+    /// A snapshot of the server's metrics
+    pub fn metrics(&self) -> ServerMetrics {
+        let mut metrics = ServerMetrics {
+            tps: self.tps(),
+            mspt: self.mspt(),
+            last_tick: self.latest_tick().map_or(Duration::ZERO, |t| t.tick),
+            gauges: self.rt.gauges.borrow().clone(),
+            ..Default::default()
+        };
+        for conn in self.rt.conns.borrow_mut().values_mut() {
+            if conn.kicked.is_some() {
+                continue;
+            }
+            metrics.connections += 1;
+            if conn.ps.state() == ProtocolState::Play {
+                metrics.players += 1;
+            }
+            metrics.packets_in_per_second += conn.incoming.packets_per_second() as u64;
+            metrics.bytes_in_per_second += conn.incoming.bytes_per_second() as u64;
+            let out = conn.bandwidth.stats();
+            metrics.packets_out_per_second += out.packets;
+            metrics.bytes_out_per_second += out.bytes_per_second();
+            metrics.out_by_category.add(&out);
+        }
+        metrics
+    }
+
+    /// Sets a gauge of the server's own (e.g. `chunks_loaded`) to report in `metrics()`.
+    /// `name` should be a valid Prometheus metric name.
+    pub fn set_gauge(&self, name: &'static str, value: f64) {
+        let mut gauges = self.rt.gauges.borrow_mut();
+        match gauges.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => gauges.push((name, value)),
+        }
+    }
+
+    /// Serves the metrics over HTTP on `addr`, in the Prometheus text format, updated every second
+    pub fn serve_metrics(&self, addr: impl ToSocketAddrs) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let text = Arc::new(Mutex::new(self.metrics().to_prometheus()));
+        *self.rt.metrics_export.borrow_mut() = Some(Arc::clone(&text));
+        thread::spawn(move || serve_metrics(listener, text));
+        Ok(())
+    }
+
+    fn update_metrics_export(&self) {
+        if let Some(text) = &*self.rt.metrics_export.borrow() {
+            *text.lock().unwrap() = self.metrics().to_prometheus();
+        }
+        if let Some(text) = &*self.rt.diagnostics_export.borrow() {
+            *text.lock().unwrap() = self.diagnostics().to_text();
+        }
+    }
+
+    /// A dump of the server's live state: tick times, chunks, and each client's queue and
+    /// packet counts
+    pub fn diagnostics(&self) -> ServerDiagnostics {
+        let mut clients: Vec<_> = self
+            .rt
+            .conns
+            .borrow_mut()
+            .iter_mut()
+            .filter(|(_, conn)| conn.kicked.is_none())
+            .map(|(&cid, conn)| ClientDiagnostics {
+                cid,
+                username: conn.session.username.clone(),
+                state: conn.ps.state(),
+                queued_bytes: conn.queued_bytes(),
+                view_distance: conn.view_distance,
+                latency: conn.session.latency,
+                packets: conn.ps.counters().clone(),
+            })
+            .collect();
+        clients.sort_by_key(|c| std::cmp::Reverse(c.queued_bytes));
+        ServerDiagnostics {
+            tps: self.tps(),
+            mspt: self.mspt(),
+            ticks: TickHistogram::from_samples(self.rt.sampler.borrow().samples()),
+            cached_chunks: self.rt.chunks.borrow().len(),
+            encoding_chunks: self.rt.encoding.borrow().len(),
+            gauges: self.rt.gauges.borrow().clone(),
+            clients,
+        }
+    }
+
+    /// Serves `diagnostics()` as text on `addr`, updated every second, to whatever connects (e.g.
+    /// `nc`). It has player names and addresses, so keep it to localhost or an admin network.
+    pub fn serve_diagnostics(&self, addr: impl ToSocketAddrs) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let text = Arc::new(Mutex::new(self.diagnostics().to_text()));
+        *self.rt.diagnostics_export.borrow_mut() = Some(Arc::clone(&text));
+        thread::spawn(move || serve_diagnostics(listener, text));
+        Ok(())
+    }
+
+    /// For sending packets from other threads, e.g. ones doing I/O for the server
+    pub fn packet_sender(&self) -> PacketSender {
+        PacketSender {
+            tx: self.rt.queue_tx.clone(),
+        }
+    }
+
+    /// Sends the packets queued by `PacketSender`s
+    fn deliver_queued_packets(&self) {
+        while let Ok(queued) = self.rt.queued.try_recv() {
+            match queued {
+                QueuedPacket::To(cid, packet) => {
+                    packet.with_packet(|p| self.send(cid, p));
+                }
+                QueuedPacket::Broadcast(packet) => packet.with_packet(|p| self.broadcast(p)),
+            }
+        }
+    }
+
+    /// Sends `packet` to every client. It's only encoded once (per protocol version).
+    pub fn broadcast(&self, packet: OutPacket) {
+        self.broadcast_filter(packet, |_| true);
+    }
+
+    /// Sends `packet` to every client that `filter` returns true for. `filter` can't use the handle.
+    pub fn broadcast_filter(&self, packet: OutPacket, mut filter: impl FnMut(ClientID) -> bool) {
+        let category = packet.category();
+        // nearly always just one
+        let mut encoded: HashMap<i32, Vec<u8>> = HashMap::new();
+        for (&cid, conn) in self.rt.conns.borrow_mut().iter_mut() {
+            if conn.kicked.is_some() || conn.ps.state() != ProtocolState::Play || !filter(cid) {
+                continue;
+            }
+            let buf = encoded
+                .entry(conn.ps.protocol_version())
+                .or_insert_with_key(|&version| encode_packet(packet.clone(), version));
+            conn.send_encoded(category, buf);
+        }
+    }
+
+    /// Sends `chunk` to `cid`, from the chunk cache if it's there. Otherwise `packet` is called
+    /// to make its `ChunkDataAndUpdateLight`, and the encoded packet is cached for other clients.
+    pub fn send_chunk<'a>(
+        &self,
+        cid: ClientID,
+        chunk: ChunkPos,
+        packet: impl FnOnce() -> OutPacket<'a>,
+    ) -> bool {
+        self.broadcast_chunk_filter(chunk, packet, |c| c == cid) == 1
+    }
+
+    /// Sends `chunk` (like `send_chunk()`) to every client that `filter` returns true for.
+    /// Returns how many it was sent to.
+    pub fn broadcast_chunk_filter<'a>(
+        &self,
+        chunk: ChunkPos,
+        packet: impl FnOnce() -> OutPacket<'a>,
+        mut filter: impl FnMut(ClientID) -> bool,
+    ) -> usize {
+        let mut cache = self.rt.chunks.borrow_mut();
+        let mut packet = Some(packet);
+        let mut made: Option<OutPacket> = None;
+        let mut sent = 0;
+        for (&cid, conn) in self.rt.conns.borrow_mut().iter_mut() {
+            if conn.kicked.is_some() || conn.ps.state() != ProtocolState::Play || !filter(cid) {
+                continue;
+            }
+            let buf = cache.get_or_encode(chunk, conn.ps.protocol_version(), || {
+                made.get_or_insert_with(|| packet.take().unwrap()()).clone()
+            });
+            if conn.send_encoded(PacketCategory::Chunks, &buf) {
+                sent += 1;
+            }
+        }
+        sent
+    }
+
+    /// Sends `chunk` to each of `to` (that's playing), like `send_chunk()` but encoding the packet
+    /// on the chunk encoding threads instead of right away. It goes out by the next tick once
+    /// encoded. Cached packets are still sent right away. If making the packet panics, the panic
+    /// carries on in `run_server()` once it's noticed.
+    pub fn send_chunk_async(
+        &self,
+        chunk: ChunkPos,
+        source: Arc<dyn ChunkPacketSource>,
+        to: &[ClientID],
+    ) {
+        let cache = self.rt.chunks.borrow();
+        let mut encoding = self.rt.encoding.borrow_mut();
+        for &cid in to {
+            self.with_conn(cid, |conn| {
+                if conn.kicked.is_some() || conn.ps.state() != ProtocolState::Play {
+                    return;
+                }
+                let version = conn.ps.protocol_version();
+                if let Some(buf) = cache.get(chunk, version) {
+                    conn.send_encoded(PacketCategory::Chunks, &buf);
+                    return;
+                }
+                let existing = encoding
+                    .iter_mut()
+                    .find(|p| p.chunk == chunk && p.protocol_version == version);
+                match existing {
+                    Some(pending) => pending.recipients.push(cid),
+                    None => {
+                        self.rt.encoder.submit(chunk, version, Arc::clone(&source));
+                        encoding.push(PendingChunk {
+                            chunk,
+                            protocol_version: version,
+                            recipients: vec![cid],
+                            invalidated: false,
+                        });
+                    }
+                }
+            });
+        }
+    }
+
+    /// Caches and sends the chunks the encoding threads have finished
+    fn deliver_encoded_chunks(&self) {
+        for encoded in self.rt.encoder.completed() {
+            let mut encoding = self.rt.encoding.borrow_mut();
+            let Some(i) = encoding.iter().position(|p| {
+                p.chunk == encoded.chunk && p.protocol_version == encoded.protocol_version
+            }) else {
+                continue;
+            };
+            let pending = encoding.swap_remove(i);
+            drop(encoding);
+            // as if it had panicked here, like `send_chunk()` would have
+            let packet = encoded.packet.unwrap_or_else(|e| panic::resume_unwind(e));
+            let packet: Rc<[u8]> = packet.into();
+            if !pending.invalidated {
+                self.rt.chunks.borrow_mut().insert(
+                    encoded.chunk,
+                    encoded.protocol_version,
+                    packet.clone(),
+                );
+            }
+            for cid in pending.recipients {
+                self.with_conn(cid, |conn| {
+                    if conn.kicked.is_none() {
+                        conn.send_encoded(PacketCategory::Chunks, &packet);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Drops `chunk` from the chunk cache. Call this whenever a block in it changes.
+    pub fn invalidate_chunk(&self, chunk: ChunkPos) {
+        self.rt.chunks.borrow_mut().invalidate(chunk);
+        for pending in self.rt.encoding.borrow_mut().iter_mut() {
+            if pending.chunk == chunk {
+                pending.invalidated = true;
+            }
+        }
+    }
+
+    /// Applies the `ConnectionLimits` to `cid` starting to log in
+    fn check_login(&self, cid: ClientID) -> Result<(), LoginRejection> {
+        let conns = self.rt.conns.borrow();
+        let Some(ip) = conns.get(&cid).and_then(|c| c.session.addr).map(|a| a.ip()) else {
+            return Ok(());
+        };
+        let others: Vec<&Connection> = conns
+            .iter()
+            .filter(|&(&other, c)| {
+                other != cid
+                    && c.kicked.is_none()
+                    && !matches!(
+                        c.ps.state(),
+                        ProtocolState::Handshaking | ProtocolState::Status
+                    )
+            })
+            .map(|(_, c)| c)
+            .collect();
+        let from_ip = others
+            .iter()
+            .filter(|c| c.session.addr.is_some_and(|a| a.ip() == ip))
+            .count();
+        self.rt
+            .throttle
+            .borrow_mut()
+            .check(ip, others.len(), from_ip)
+    }
+
+    /// Runs `f` on `cid`'s connection, if it's still connected
+    fn with_conn<T>(&self, cid: ClientID, f: impl FnOnce(&mut Connection) -> T) -> Option<T> {
+        self.rt.conns.borrow_mut().get_mut(&cid).map(f)
+    }
+}
+
+/// `limits`, with no more connections than `config.max_players`
+fn capped_limits(limits: &ConnectionLimits, config: &ServerConfig) -> ConnectionLimits {
+    let mut limits = limits.clone();
+    limits.max_connections = Some(
+        limits
+            .max_connections
+            .map_or(config.max_players, |max| max.min(config.max_players)),
+    );
+    limits
+}
+
+/// Runs `s` on 127.0.0.1:25565 with the default `ServerConfig`. See `ServerBuilder` for more.
+pub fn run_server<S: Server>(s: S) {
+    run_server_with(ServerConfig::default(), s).unwrap();
+}
+
+pub(crate) fn run_server_with<S: Server>(config: ServerConfig, mut s: S) -> std::io::Result<()> {
+    if config.online_mode {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "online mode isn't supported, since libmc has no encryption",
+        ));
+    }
+    // bind them all before accepting anything, so a bad address fails before clients get in
+    let mut listeners = vec![(TcpListener::bind(config.addr)?, DEFAULT_LISTENER.into())];
+    for (addr, tag) in &config.extra_listeners {
+        listeners.push((TcpListener::bind(addr)?, Arc::from(tag.as_str())));
+    }
+    let ws_listener = config.websocket_addr.map(TcpListener::bind).transpose()?;
+    let bedrock = config.bedrock_addr.map(BedrockFrontend::bind).transpose()?;
+    let (tx, rx) = mpsc::channel();
+    // shared by the listeners, so client IDs are unique
+    let client_ids = Arc::new(ClientIDAllocator::default());
+    for (listener, tag) in listeners {
+        let accept_tx = tx.clone();
+        let accept_cid = Arc::clone(&client_ids);
+        match config.backend {
+            NetBackend::Threads => {
+                thread::spawn(move || accept_connections(listener, tag, accept_tx, accept_cid));
+            }
+            #[cfg(feature = "mio")]
+            NetBackend::EventLoop => {
+                crate::eventloop::spawn_event_loop(listener, tag, accept_tx, accept_cid, &config)?;
+            }
+        }
+    }
+    if let Some(ws_listener) = ws_listener {
+        let accept_tx = tx.clone();
+        let accept_cid = Arc::clone(&client_ids);
+        thread::spawn(move || accept_websockets(ws_listener, accept_tx, accept_cid));
+    }
+    if let Some(mut frontend) = bedrock {
+        frontend.set_motd(&plain_text(&config.motd.to_json()), 0, config.max_players)?;
+        let accept_tx = tx.clone();
+        let accept_cid = Arc::clone(&client_ids);
+        thread::spawn(move || serve_bedrock(frontend, accept_tx, accept_cid));
+    }
+
+    if config.reload_on_sighup {
+        crate::sighup::install();
+    }
+    let handle = ServerHandle::new(config, s.connection_limits(), client_ids);
+    s.on_start(handle.clone());
+    let mut ticker = TickLoop::new();
+    let mut last_tick_end = Instant::now();
+    let mut idle = Duration::ZERO;
     loop {
-        let packet = ps.next_packet();
-        if let &InPacket::LoginStart { .. } = &packet {
-            ps.send(OutPacket::LoginSuccess {
-                uuid: 123,
-                username: "foobar",
-                props: &[],
+        let wait_start = Instant::now();
+        let ev = rx.recv_timeout(ticker.time_until_next_tick());
+        idle += wait_start.elapsed();
+        match ev {
+            Ok(ev) => handle_event(&mut s, &handle, &tx, ev),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => panic!("accept thread died"),
+        }
+        handle.deliver_encoded_chunks();
+        handle.deliver_queued_packets();
+        if handle.rt.reload_requested.take() | crate::sighup::take() {
+            if let Some(config) = s.reload_config() {
+                let needs_restart = handle.reload_config(config);
+                s.on_config_reloaded(&needs_restart);
+            }
+        }
+        let view_distance_changes = handle.rt.view_distance_changes.take();
+        for (cid, view_distance) in view_distance_changes {
+            s.on_view_distance(cid, view_distance);
+        }
+
+        if let Some(tick) = ticker.poll_tick() {
+            if let Some(behind) = ticker.take_skipped() {
+                s.on_ticks_skipped(behind);
+            }
+            let tick_start = Instant::now();
+            s.tick(tick);
+            if tick % u64::from(TICKS_PER_SECOND) == 0 {
+                handle.update_metrics_export();
+                handle.kick_idle_players();
+            }
+            if tick % KEEP_ALIVE_INTERVAL == 0 {
+                handle.send_keep_alives();
+            }
+            if tick % LATENCY_UPDATE_INTERVAL == 0 && handle.config().tab_list_latency {
+                handle.broadcast_latencies();
+            }
+            let mut failed_captures = Vec::new();
+            for (&cid, conn) in handle.rt.conns.borrow_mut().iter_mut() {
+                let res = match conn.kicked {
+                    None => conn.send_deferred(),
+                    Some(_) => Ok(()),
+                };
+                if let Err(e) = res.and_then(|()| conn.ps.writer_mut().end_tick()) {
+                    conn.kick(DisconnectCause::WriteFailed(e.kind()));
+                }
+                if let Some(e) = conn.ps.take_capture_error() {
+                    failed_captures.push((cid, e));
+                }
+            }
+            for (cid, e) in failed_captures {
+                s.on_capture_failed(cid, e);
+            }
+            let tick_end = Instant::now();
+
+            let full = tick_end - last_tick_end;
+            let tick = tick_end - tick_start;
+            handle.rt.tps.set(ticker.tps());
+            let sample = TickSample {
+                full,
+                tick,
+                tasks: full.saturating_sub(tick + idle),
+                idle,
+            };
+            handle.rt.sampler.borrow_mut().record(sample);
+            last_tick_end = tick_end;
+            idle = Duration::ZERO;
+        }
+
+        let shutdown = handle.rt.shutdown.borrow_mut().take();
+        if let Some(reason) = shutdown {
+            shut_down(&mut s, &handle, &reason);
+            return Ok(());
+        }
+    }
+}
+
+fn shut_down<S: Server>(s: &mut S, handle: &ServerHandle, reason: &TextComponent) {
+    let conns: Vec<_> = handle.rt.conns.borrow_mut().drain().collect();
+    let mut queues = Vec::new();
+    for (cid, mut conn) in conns {
+        conn.disconnect(reason, DisconnectCause::Kicked);
+        s.on_disconnect(cid, conn.kicked.unwrap_or(DisconnectCause::Kicked));
+        queues.push(conn.ps.into_writer().into_inner());
+    }
+    // make sure the disconnect packets actually get sent
+    for queue in queues {
+        queue.finish();
+    }
+    s.on_shutdown();
+}
+
+fn accept_connections(
+    listener: TcpListener,
+    tag: Arc<str>,
+    tx: Sender<NetEvent>,
+    client_ids: Arc<ClientIDAllocator>,
+) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let Ok(read_half) = stream.try_clone() else {
+            continue;
+        };
+        let cid = client_ids.allocate();
+
+        if tx
+            .send(NetEvent::Connected(
+                cid,
+                stream.into(),
+                Transport::Tcp,
+                Arc::clone(&tag),
+            ))
+            .is_err()
+        {
+            // the server shut down
+            return;
+        }
+        let tx = tx.clone();
+        thread::spawn(move || read_frames(cid, BufReader::new(read_half), tx));
+    }
+}
+
+/// Like `accept_connections()`, but each client's thread does the WebSocket handshake first
+fn accept_websockets(
+    listener: TcpListener,
+    tx: Sender<NetEvent>,
+    client_ids: Arc<ClientIDAllocator>,
+) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let Ok(read_half) = stream.try_clone() else {
+            continue;
+        };
+        let tx = tx.clone();
+        let client_ids = Arc::clone(&client_ids);
+        thread::spawn(move || {
+            // until the handshake is done; then it's set like for any connection
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+            let mut r = BufReader::new(read_half);
+            if websocket_handshake(&mut r, &mut stream).is_err() {
+                return;
+            }
+            let cid = client_ids.allocate();
+            let tag = WEBSOCKET_LISTENER.into();
+            if tx
+                .send(NetEvent::Connected(
+                    cid,
+                    stream.into(),
+                    Transport::WebSocket,
+                    tag,
+                ))
+                .is_ok()
+            {
+                read_frames(cid, WsReader::new(r), tx);
+            }
+        });
+    }
+}
+
+/// Reads packet frames from a client until it disconnects
+fn read_frames(cid: ClientID, mut r: impl Read, tx: Sender<NetEvent>) {
+    let cause = loop {
+        match read_frame(&mut r) {
+            Ok(frame) => {
+                if tx.send(NetEvent::Frame(cid, frame)).is_err() {
+                    return;
+                }
+            }
+            Err(DisconnectCause::Io(
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut,
+            )) => break DisconnectCause::TimedOut,
+            Err(cause) => break cause,
+        }
+    };
+    let _ = tx.send(NetEvent::Closed(cid, cause));
+}
+
+fn handle_event<S: Server>(s: &mut S, handle: &ServerHandle, tx: &Sender<NetEvent>, ev: NetEvent) {
+    match ev {
+        NetEvent::Connected(cid, stream, transport, listener) => {
+            let addr = stream.peer_addr().ok();
+            if addr.is_some_and(|addr| !s.filter_connection(addr)) {
+                // the reader thread's `Closed` is then ignored, as there's no connection
+                let _ = stream.shutdown(Shutdown::Both);
+                handle.rt.client_ids.release(cid);
+                return;
+            }
+            // batching is up to the CoalescingWriter
+            let _ = stream.set_nodelay(true);
+            // so the send thread doesn't hang forever on a client that stopped reading
+            let config = handle.config();
+            let _ = stream.set_write_timeout(Some(config.write_timeout));
+            // applies to the reader thread's clone of the stream too
+            let _ = stream.set_read_timeout(Some(config.read_timeout));
+            let queue = match transport {
+                Transport::Tcp => stream.try_clone().map(SendQueue::new),
+                Transport::WebSocket => stream
+                    .try_clone()
+                    .map(|stream| SendQueue::new(WsWriter::new(stream))),
+                #[cfg(feature = "mio")]
+                Transport::EventLoop(writer) => Ok(SendQueue::event_loop(writer)),
+                Transport::Bedrock(link) => Ok(SendQueue::new(link)),
+            };
+            let Ok(queue) = queue else {
+                // like a filtered connection: the server never hears of it
+                let _ = stream.shutdown(Shutdown::Both);
+                handle.rt.client_ids.release(cid);
+                return;
+            };
+            let writer = CoalescingWriter::new(queue, s.flush_policy(cid));
+            let mut ps = PacketStream::new(writer);
+            if let Socket::Bedrock(_) = stream {
+                ps.set_translator(Box::new(BedrockTranslator::new(s.bedrock_blocks())));
+            }
+            let mut session = ClientSession::new(addr);
+            session.listener = listener;
+            handle.rt.conns.borrow_mut().insert(
+                cid,
+                Connection {
+                    ps,
+                    stream,
+                    session,
+                    bandwidth: BandwidthTracker::new(config.bandwidth_cap),
+                    deferred: VecDeque::new(),
+                    incoming: PacketRateTracker::new(s.packet_rate_limits(cid)),
+                    ping: PingTracker::new(),
+                    idle: IdleTracker::with_clock(Rc::clone(&handle.rt.clock)),
+                    kicked: None,
+                    backpressure: config.backpressure.clone(),
+                    view_distance: config.view_distance,
+                },
+            );
+            s.on_connect(cid);
+        }
+        NetEvent::Frame(cid, frame) => {
+            // frames can still arrive after we've kicked the client
+            let translated = handle.with_conn(cid, |conn| {
+                if !conn.incoming.record(frame.len()) {
+                    let reason = TextComponent::text("Kicked for exceeding packet rate limit");
+                    conn.disconnect(&reason, DisconnectCause::PacketSpam);
+                    return None;
+                }
+                match conn.ps.translate_incoming(frame) {
+                    Ok(frames) => Some(frames),
+                    Err(e) => {
+                        conn.kick(DisconnectCause::BadPacket(Some(e)));
+                        None
+                    }
+                }
+            });
+            let Some(Some(frames)) = translated else {
+                return;
+            };
+            let mut frames = VecDeque::from(frames);
+            while let Some(frame) = frames.pop_front() {
+                if !handle_frame(s, handle, tx, cid, frame) {
+                    return;
+                }
+                // replies a translator made up on the client's behalf
+                match handle.with_conn(cid, |conn| conn.ps.take_injected()) {
+                    Some(injected) => frames.extend(injected),
+                    None => return,
+                }
+            }
+        }
+        NetEvent::Status(cid, json) => {
+            handle.with_conn(cid, |conn| {
+                if let Err(e) = conn.send(OutPacket::StatusResponse { json: &json }) {
+                    conn.kick(DisconnectCause::WriteFailed(e.kind()));
+                }
             });
         }
+        NetEvent::Closed(cid, cause) => {
+            let removed = handle.rt.conns.borrow_mut().remove(&cid);
+            if let Some(conn) = removed {
+                handle.rt.client_ids.release(cid);
+                if let Some(id) = conn.session.entity_id {
+                    handle.free_entity_id(id);
+                }
+                // our own reason for closing it trumps the EOF the reader thread then saw
+                s.on_disconnect(cid, conn.kicked.unwrap_or(cause));
+            }
+        }
+    }
+}
 
-        if let &InPacket::LoginAck = &packet {
-            ps.send(OutPacket::FinishConfig);
+/// Handles one frame in our protocol version. Returns false if the client got kicked.
+///
+/// The connection isn't borrowed while the `Server` is called, since it may use its `ServerHandle`.
+fn handle_frame<S: Server>(
+    s: &mut S,
+    handle: &ServerHandle,
+    tx: &Sender<NetEvent>,
+    cid: ClientID,
+    frame: Vec<u8>,
+) -> bool {
+    // a malformed packet only takes down its own connection
+    let decoded = handle.with_conn(cid, |conn| match conn.ps.decode(&frame) {
+        Ok(packet) => {
+            conn.session.record(&packet);
+            conn.idle.handle(&packet);
+            if conn.ping.handle(&packet).is_some() {
+                conn.session.latency = conn.ping.average();
+            }
+            Some(packet)
         }
+        Err(e) => {
+            conn.kick(DisconnectCause::BadPacket(Some(e)));
+            None
+        }
+    });
+    let Some(Some(packet)) = decoded else {
+        return false;
+    };
+    let (addr, listener) = handle
+        .with_conn(cid, |conn| {
+            (conn.session.addr, Arc::clone(&conn.session.listener))
+        })
+        .unwrap_or((None, DEFAULT_LISTENER.into()));
+    let handshake = match packet {
+        InPacket::Handshake {
+            protocol_version,
+            server_addr,
+            server_port,
+            next_state,
+        } => {
+            let (server_addr, forge) = ForgeMarker::split_server_addr(server_addr);
+            Some(HandshakeInfo {
+                addr,
+                listener: &listener,
+                protocol_version,
+                server_addr,
+                server_port,
+                forge,
+                next_state,
+            })
+        }
+        _ => None,
+    };
+    if let Some(handshake) = &handshake {
+        if let Err(reason) = s.filter_handshake(cid, handshake) {
+            handle.with_conn(cid, |conn| {
+                conn.disconnect(&reason, DisconnectCause::Kicked)
+            });
+            return false;
+        }
+    }
+    if let InPacket::Handshake {
+        next_state: HandshakeNextState::Login,
+        ..
+    } = packet
+    {
+        if let Err(rejection) = handle.check_login(cid) {
+            handle.with_conn(cid, |conn| {
+                conn.disconnect(&rejection.message(), DisconnectCause::Kicked)
+            });
+            return false;
+        }
+    }
+    if let InPacket::Handshake {
+        protocol_version, ..
+    } = packet
+    {
+        if protocol_version != PROTOCOL_VERSION {
+            match s.translator(protocol_version) {
+                Some(translator) => {
+                    handle.with_conn(cid, |conn| conn.ps.set_translator(translator));
+                }
+                None if matches!(
+                    packet,
+                    InPacket::Handshake {
+                        next_state: HandshakeNextState::Login,
+                        ..
+                    }
+                ) =>
+                {
+                    // the Login Disconnect packet is the same in every version
+                    let reason = outdated_message(protocol_version);
+                    let cause = DisconnectCause::UnsupportedVersion(protocol_version);
+                    handle.with_conn(cid, |conn| conn.disconnect(&reason, cause));
+                    return false;
+                }
+                None => {}
+            }
+        }
+    }
+    if let InPacket::LoginStart { name, player_uuid } = packet {
+        let allowed = match handle.config().is_whitelisted(name) {
+            true => s.filter_login(cid, name, player_uuid),
+            false => Err(not_whitelisted_message()),
+        };
+        if let Err(reason) = allowed {
+            handle.with_conn(cid, |conn| {
+                conn.disconnect(&reason, DisconnectCause::Kicked)
+            });
+            return false;
+        }
+    }
+    if let Some(handshake) = &handshake {
+        s.on_handshake(cid, handshake);
+    }
+    let status_cache = match packet {
+        InPacket::StatusRequest => s.status_cache_for(cid),
+        _ => None,
+    };
+    let default_status = match (&packet, &status_cache) {
+        (InPacket::StatusRequest, None) => Some(handle.default_status()),
+        _ => None,
+    };
+    let dimensions = match packet {
+        InPacket::FinishConfig => Some(s.login_dimensions(cid).unwrap_or_default()),
+        _ => None,
+    };
+    if let InPacket::FinishConfig = packet {
+        let id = handle.allocate_entity_id();
+        handle.with_conn(cid, |conn| {
+            conn.session.entity_id = Some(id);
+            conn.idle.reset();
+        });
+    }
+    let replied = handle.with_conn(cid, |conn| {
+        let mut res = handle_login_flow(
+            conn,
+            &handle.config(),
+            (handle.difficulty(), handle.is_difficulty_locked()),
+            &packet,
+            dimensions.as_ref(),
+        );
+        if let Some(json) = &default_status {
+            res = res.and_then(|()| conn.send(OutPacket::StatusResponse { json }));
+        }
+        if let Err(e) = &res {
+            conn.kick(DisconnectCause::WriteFailed(e.kind()));
+        }
+        res.is_ok()
+    });
+    if replied != Some(true) {
+        return false;
+    }
+    if let Some(cache) = status_cache {
+        let tx = tx.clone();
+        cache.request(move |json| {
+            let _ = tx.send(NetEvent::Status(cid, json));
+        });
+    }
+    if let InPacket::ClientInfoConfig { .. } | InPacket::ClientInfoPlay { .. } = packet {
+        let max = handle.config().view_distance;
+        let changed = handle.with_conn(cid, |conn| {
+            let res = conn.update_view_distance(max);
+            if let Err(e) = &res {
+                conn.kick(DisconnectCause::WriteFailed(e.kind()));
+            }
+            res
+        });
+        match changed {
+            Some(Ok(Some(view_distance))) => handle
+                .rt
+                .view_distance_changes
+                .borrow_mut()
+                .push((cid, view_distance)),
+            Some(Ok(None)) => {}
+            _ => return false,
+        }
+    }
+    if handle.config().client_difficulty {
+        match packet {
+            InPacket::ChangeDifficulty { difficulty } if !handle.is_difficulty_locked() => {
+                handle.set_difficulty(difficulty);
+            }
+            InPacket::LockDifficulty { locked: true } => handle.lock_difficulty(true),
+            _ => {}
+        }
+    }
+    s.handle_packet(cid, packet);
+    true
+}
+
+/// What vanilla servers tell players who aren't on the whitelist
+fn not_whitelisted_message() -> TextComponent {
+    TextComponent::text("You are not white-listed on this server!")
+}
 
-        if let &InPacket::FinishConfig = &packet {
-            ps.send(OutPacket::LoginPlay {
-                entity_id: 1,
+/// What vanilla servers tell clients on other versions
+fn outdated_message(protocol_version: i32) -> TextComponent {
+    if protocol_version < PROTOCOL_VERSION {
+        TextComponent::text(format!("Outdated client! Please use {MINECRAFT_VERSION}"))
+    } else {
+        TextComponent::text(format!("Outdated server! I'm still on {MINECRAFT_VERSION}"))
+    }
+}
+
+fn handle_login_flow(
+    conn: &mut Connection,
+    config: &ServerConfig,
+    (difficulty, locked): (Difficulty, bool),
+    packet: &InPacket,
+    dimensions: Option<&LoginDimensions>,
+) -> std::io::Result<()> {
+    if let &InPacket::PingRequest { payload } = packet {
+        conn.send(OutPacket::PingResponse { payload })?;
+    }
+
+    if let InPacket::LoginStart { name, player_uuid } = packet {
+        // Bedrock batches are compressed by the front-end
+        let bedrock = matches!(conn.stream, Socket::Bedrock(_));
+        if let (Some(threshold), false) = (config.compression_threshold, bedrock) {
+            let threshold = threshold.try_into().unwrap_or(i32::MAX);
+            conn.send(OutPacket::SetCompression { threshold })?;
+            conn.ps.set_compression(Some(threshold as usize));
+        }
+        let profile = GameProfile::new(*player_uuid, *name);
+        conn.send(OutPacket::LoginSuccess { profile: &profile })?;
+    }
+
+    if let &InPacket::LoginAck = packet {
+        conn.send(OutPacket::FinishConfig)?;
+    }
+
+    if let (&InPacket::FinishConfig, Some(dimensions)) = (packet, dimensions) {
+        let names: Vec<&str> = dimensions.names.iter().map(|n| &n[..]).collect();
+        // the start of the join burst, so it goes out in one write
+        conn.send_all(vec![
+            OutPacket::LoginPlay {
+                // set just before this is called
+                entity_id: conn.session.entity_id.unwrap_or_default(),
                 is_hardcore: false,
-                dimension_names: &["foo:bar"],
-                max_players: 456,
-                view_distance: 111,
-                simulation_distance: 222,
+                dimension_names: &names,
+                max_players: config.max_players.try_into().unwrap_or(i32::MAX),
+                view_distance: conn.view_distance,
+                simulation_distance: config.simulation_distance,
                 reduced_debug_info: false,
                 enable_respawn_screen: true,
                 do_limited_crafting: false,
-                dimension_type: "foo:baz",
-                dimension_name: "foo:bar",
-                hashed_seed: 999,
+                dimension_type: &dimensions.dimension_type,
+                dimension_name: &dimensions.dimension_name,
+                hashed_seed: dimensions.hashed_seed,
                 game_mode: GameMode::Spectator,
                 prev_game_mode: None,
-                is_debug: false,
-                is_superflat: false,
+                is_debug: dimensions.is_debug,
+                is_superflat: dimensions.is_superflat,
                 death_info: None,
                 portal_cooldown: 5,
-            });
+            },
+            // the client stays on the loading screen until it knows where it is
+            OutPacket::SyncPlayerPos {
+                x: 0.0,
+                y: 64.0,
+                z: 0.0,
+                yaw: 0.0,
+                pitch: 0.0,
+                flags: 0,
+                teleport_id: 0,
+            },
+            OutPacket::ChangeDifficulty { difficulty, locked },
+        ])?;
+        conn.send(OutPacket::PluginMessagePlay {
+            channel: BRAND_CHANNEL,
+            data: &encode_brand(&config.brand),
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_ids_are_generational() {
+        let ids = ClientIDAllocator::default();
+        let a = ids.allocate();
+        let b = ids.allocate();
+        assert_ne!(a, b);
+        ids.release(a);
+        ids.release(a);
+        let c = ids.allocate();
+        assert_eq!(c.index(), a.index());
+        assert_eq!(c.generation(), a.generation() + 1);
+        assert_ne!(c, a);
+        assert_eq!(ids.allocate().index(), 2);
+        assert_eq!(ClientID::from_bits(c.to_bits()), c);
+    }
+
+    /// Records why clients disconnected
+    #[derive(Default)]
+    struct Disconnects(Rc<RefCell<Vec<DisconnectCause>>>);
+
+    impl Server for Disconnects {
+        fn on_connect(&mut self, _cid: ClientID) {}
+
+        fn on_disconnect(&mut self, _cid: ClientID, cause: DisconnectCause) {
+            self.0.borrow_mut().push(cause);
         }
-        s.handle_packet(todo_cid, packet);
+
+        fn handle_packet(&mut self, _cid: ClientID, _packet: InPacket) {}
+
+        fn tick(&mut self, _tick: u64) {}
     }
 
-    // TODO: multiple clients (increment cid)
-    s.on_disconnect(todo_cid);
+    #[test]
+    fn kicks_idle_players() {
+        let clock = ManualClock::new();
+        let config = ServerConfig {
+            player_idle_timeout: Some(Duration::from_secs(60)),
+            ..ServerConfig::default()
+        };
+        let limits = ConnectionLimits::default();
+        let handle =
+            ServerHandle::with_clock(config, limits, Arc::default(), Rc::new(clock.clone()));
+        let mut s = Disconnects::default();
+        let causes = Rc::clone(&s.0);
+        let (tx, _rx) = mpsc::channel();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let cid = handle.rt.client_ids.allocate();
+        let tag = DEFAULT_LISTENER.into();
+        let connected = NetEvent::Connected(cid, stream.into(), Transport::Tcp, tag);
+        handle_event(&mut s, &handle, &tx, connected);
+
+        // log in, and acknowledge the login and the end of configuration
+        let mut handshake = vec![0x00];
+        write_varint(&mut handshake, PROTOCOL_VERSION);
+        write_string(&mut handshake, "localhost");
+        handshake.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut handshake, 2);
+        let mut login = vec![0x00];
+        write_string(&mut login, "Steve");
+        write_uuid(&mut login, Uuid(1));
+        for packet in [handshake, login, vec![0x03], vec![0x02]] {
+            let frame = NetEvent::Frame(cid, frame_packet(&packet));
+            handle_event(&mut s, &handle, &tx, frame);
+        }
+        let state = handle.with_conn(cid, |conn| conn.ps.state());
+        assert_eq!(state, Some(ProtocolState::Play));
+
+        clock.advance(Duration::from_secs(60));
+        handle.kick_idle_players();
+        assert_eq!(handle.with_conn(cid, |conn| conn.kicked), Some(None));
+        clock.advance(Duration::from_secs(1));
+        handle.kick_idle_players();
+        let kicked = handle.with_conn(cid, |conn| conn.kicked);
+        assert_eq!(kicked, Some(Some(DisconnectCause::Idle)));
+
+        // the reader thread then sees the connection close
+        handle_event(
+            &mut s,
+            &handle,
+            &tx,
+            NetEvent::Closed(cid, DisconnectCause::Closed),
+        );
+        assert_eq!(*causes.borrow(), [DisconnectCause::Idle]);
+    }
+
+    #[test]
+    fn bedrock_clients_join() {
+        // Bedrock batches are compressed their own way
+        let config = ServerConfig {
+            compression_threshold: Some(0),
+            ..ServerConfig::default()
+        };
+        let limits = ConnectionLimits::default();
+        let handle =
+            ServerHandle::with_clock(config, limits, Arc::default(), Rc::new(ManualClock::new()));
+        let mut s = Disconnects::default();
+        let (tx, _rx) = mpsc::channel();
+
+        let (link_tx, link_rx) = mpsc::channel();
+        let cid = handle.rt.client_ids.allocate();
+        let link = BedrockLink::new(cid, "127.0.0.1:19132".parse().unwrap(), link_tx);
+        let socket = Socket::Bedrock(link.clone());
+        let tag = BEDROCK_LISTENER.into();
+        let connected = NetEvent::Connected(cid, socket, Transport::Bedrock(link), tag);
+        handle_event(&mut s, &handle, &tx, connected);
+        for packet in login_packets(None, "Steve", Uuid(1)) {
+            let frame = NetEvent::Frame(cid, frame_packet(&packet));
+            handle_event(&mut s, &handle, &tx, frame);
+        }
+        let state = handle.with_conn(cid, |conn| conn.ps.state());
+        assert_eq!(state, Some(ProtocolState::Play));
+
+        // what the client got: Bedrock packets, starting with Start Game (0x0B)
+        handle.with_conn(cid, |conn| conn.ps.writer_mut().flush().unwrap());
+        let mut sent = Vec::new();
+        while let Ok(BedrockCommand::Send(_, data)) = link_rx.recv_timeout(Duration::from_secs(5)) {
+            sent.extend_from_slice(&data);
+            if let Ok(frame) = read_frame(&mut &sent[..]) {
+                let mut r = PacketReader::new(&frame);
+                r.varint().unwrap();
+                assert_eq!(r.varint(), Ok(0x0B));
+                break;
+            }
+        }
+        assert!(read_frame(&mut &sent[..]).is_ok(), "nothing was sent");
+
+        handle.kick(cid, &TextComponent::text("bye"));
+        let closed = link_rx
+            .iter()
+            .find(|c| matches!(c, BedrockCommand::Close(..)));
+        assert!(matches!(
+            closed,
+            Some(BedrockCommand::Close(_, Shutdown::Read))
+        ));
+    }
 }
@@ -0,0 +1,431 @@
+use crate::*;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The plugin channel that clients and servers name their software on, e.g. `vanilla`
+pub const BRAND_CHANNEL: &str = "minecraft:brand";
+
+/// The least view distance clients are sent chunks for, whatever they ask for (as in vanilla)
+pub const MIN_VIEW_DISTANCE: i32 = 2;
+
+/// The data of a `minecraft:brand` plugin message
+pub fn encode_brand(brand: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_string(&mut data, brand);
+    data
+}
+
+/// The marker Forge clients add to the server address in their handshake, which says which
+/// version of Forge's handshake they speak
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ForgeMarker {
+    /// `\0FML\0`, before 1.13
+    Fml,
+    /// `\0FML2\0`, 1.13 to 1.17
+    Fml2,
+    /// `\0FML3\0`, since 1.18
+    Fml3,
+}
+
+impl ForgeMarker {
+    /// Splits the server address from a handshake into the address the player typed in, and the
+    /// Forge marker if there is one. Anything else after a NUL is dropped too.
+    pub fn split_server_addr(raw: &str) -> (&str, Option<Self>) {
+        let mut parts = raw.split('\0');
+        let addr = parts.next().unwrap_or_default();
+        let marker = parts.find_map(|part| match part {
+            "FML" => Some(Self::Fml),
+            "FML2" => Some(Self::Fml2),
+            "FML3" => Some(Self::Fml3),
+            _ => None,
+        });
+        (addr, marker)
+    }
+}
+
+/// A connected client, as listed by `ServerHandle::clients()`, e.g. for `/list`
+#[derive(Debug, Clone)]
+pub struct ConnectedClient {
+    pub cid: ClientID,
+    /// From Login Start
+    pub username: Option<String>,
+    /// From Login Start
+    pub uuid: Option<Uuid>,
+    pub state: ProtocolState,
+    /// Where the client is connecting from
+    pub addr: Option<SocketAddr>,
+    /// The tag of the address it connected to (see `ClientSession::listener`)
+    pub listener: Arc<str>,
+    pub latency: Option<Duration>,
+}
+
+impl ConnectedClient {
+    pub(crate) fn new(cid: ClientID, session: &ClientSession, state: ProtocolState) -> Self {
+        Self {
+            cid,
+            username: session.username.clone(),
+            uuid: session.uuid,
+            state,
+            addr: session.addr,
+            listener: Arc::clone(&session.listener),
+            latency: session.latency,
+        }
+    }
+
+    /// Whether it's in game, rather than pinging, logging in or being configured
+    pub fn is_playing(&self) -> bool {
+        self.state == ProtocolState::Play
+    }
+}
+
+/// The settings a client sends in Client Information
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    /// e.g. `en_us`
+    pub locale: String,
+    pub view_distance: i8,
+    pub chat_mode: ChatMode,
+    pub chat_colors: bool,
+    pub displayed_skin_parts: SkinParts,
+    pub main_hand: MainHand,
+    pub enable_text_filtering: bool,
+    pub allow_server_listings: bool,
+}
+
+/// A value attached to a session. Clone so sessions can be too.
+trait Attachment: Any + Send {
+    fn clone_box(&self) -> Box<dyn Attachment>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+    fn type_name(&self) -> &'static str;
+}
+
+impl<T: Any + Clone + Send> Attachment for T {
+    fn clone_box(&self) -> Box<dyn Attachment> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+}
+
+/// At most one value of each type
+#[derive(Default)]
+struct Attachments(HashMap<TypeId, Box<dyn Attachment>>);
+
+impl Clone for Attachments {
+    fn clone(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|(&k, v)| (k, (**v).clone_box()))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Debug for Attachments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(self.0.values().map(|v| v.type_name()))
+            .finish()
+    }
+}
+
+/// What libmc knows about a connected client from its handshake, login and settings, so servers
+/// don't have to track it from packets themselves. Get it with `ServerHandle::session()`.
+///
+/// Servers and plugins can attach their own per-client data to it too, one value per type (see
+/// `insert()`), rather than keeping maps by `ClientID` next to it. It's dropped when the client
+/// disconnects. Change it with `ServerHandle::with_session()`.
+#[derive(Debug, Clone)]
+pub struct ClientSession {
+    /// Where the client is connecting from
+    pub addr: Option<SocketAddr>,
+    /// The tag of the address it connected to: `DEFAULT_LISTENER`, `WEBSOCKET_LISTENER`, or one
+    /// from `ServerConfig::extra_listeners`
+    pub listener: Arc<str>,
+    /// `PROTOCOL_VERSION` until the handshake says otherwise
+    pub protocol_version: i32,
+    /// The address the client connected to, as typed in by the player (empty before the handshake)
+    pub server_addr: String,
+    pub server_port: u16,
+    /// Set for Forge clients
+    pub forge: Option<ForgeMarker>,
+    /// From Login Start
+    pub username: Option<String>,
+    /// From Login Start
+    pub uuid: Option<Uuid>,
+    /// The latest settings the client sent
+    pub info: Option<ClientInfo>,
+    /// The client's software, e.g. `vanilla` or `fabric`, from its `minecraft:brand` message
+    pub brand: Option<String>,
+    /// The player's entity ID, given when it finishes configuration
+    pub entity_id: Option<i32>,
+    /// The smoothed round trip time of the server's Keep Alives, once one was answered
+    pub latency: Option<Duration>,
+    attachments: Attachments,
+}
+
+impl ClientSession {
+    pub fn new(addr: Option<SocketAddr>) -> Self {
+        Self {
+            addr,
+            listener: DEFAULT_LISTENER.into(),
+            protocol_version: PROTOCOL_VERSION,
+            server_addr: String::new(),
+            server_port: 0,
+            forge: None,
+            username: None,
+            uuid: None,
+            info: None,
+            brand: None,
+            entity_id: None,
+            latency: None,
+            attachments: Attachments::default(),
+        }
+    }
+
+    /// Attaches `value`, returning the `T` that was attached before
+    pub fn insert<T: Any + Clone + Send>(&mut self, value: T) -> Option<T> {
+        let old = self
+            .attachments
+            .0
+            .insert(TypeId::of::<T>(), Box::new(value))?;
+        old.into_any().downcast().ok().map(|b| *b)
+    }
+
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.attachments
+            .0
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref()
+    }
+
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.attachments
+            .0
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut()
+    }
+
+    /// The attached `T`, attaching `T::default()` first if there isn't one
+    pub fn get_or_default<T: Any + Clone + Send + Default>(&mut self) -> &mut T {
+        self.attachments
+            .0
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(T::default()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("attachment of the wrong type")
+    }
+
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        let old = self.attachments.0.remove(&TypeId::of::<T>())?;
+        old.into_any().downcast().ok().map(|b| *b)
+    }
+
+    /// Updates the session from a packet the client sent
+    pub fn record(&mut self, packet: &InPacket) {
+        match *packet {
+            InPacket::Handshake {
+                protocol_version,
+                server_addr,
+                server_port,
+                ..
+            } => {
+                let (addr, forge) = ForgeMarker::split_server_addr(server_addr);
+                self.protocol_version = protocol_version;
+                self.server_addr = addr.to_owned();
+                self.server_port = server_port;
+                self.forge = forge;
+            }
+            InPacket::LoginStart { name, player_uuid } => {
+                self.username = Some(name.to_owned());
+                self.uuid = Some(player_uuid);
+            }
+            InPacket::ClientInfoConfig {
+                locale,
+                view_distance,
+                chat_mode,
+                chat_colors,
+                displayed_skin_parts,
+                main_hand,
+                enable_text_filtering,
+                allow_server_listings,
+            }
+            | InPacket::ClientInfoPlay {
+                locale,
+                view_distance,
+                chat_mode,
+                chat_colors,
+                displayed_skin_parts,
+                main_hand,
+                enable_text_filtering,
+                allow_server_listings,
+            } => {
+                self.info = Some(ClientInfo {
+                    locale: locale.to_owned(),
+                    view_distance,
+                    chat_mode,
+                    chat_colors,
+                    displayed_skin_parts,
+                    main_hand,
+                    enable_text_filtering,
+                    allow_server_listings,
+                });
+            }
+            InPacket::PluginMessageConfig { channel, data }
+            | InPacket::PluginMessagePlay { channel, data }
+                if channel == BRAND_CHANNEL =>
+            {
+                if let Ok(brand) = PacketReader::new(data).str() {
+                    self.brand = Some(brand.to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the client said it's modded, with a Forge marker or a brand other than `vanilla`
+    pub fn is_modded(&self) -> bool {
+        self.forge.is_some() || self.brand.as_deref().is_some_and(|b| b != "vanilla")
+    }
+
+    /// The client's view distance, if it has sent its settings
+    pub fn view_distance(&self) -> Option<i8> {
+        self.info.as_ref().map(|i| i.view_distance)
+    }
+
+    /// The view distance to send the client chunks for: the one it asked for, but at least
+    /// `MIN_VIEW_DISTANCE` and at most the server's `max`. `max` until it has sent its settings.
+    pub fn effective_view_distance(&self, max: i32) -> i32 {
+        match self.view_distance() {
+            Some(requested) => {
+                i32::from(requested).clamp(MIN_VIEW_DISTANCE, max.max(MIN_VIEW_DISTANCE))
+            }
+            None => max,
+        }
+    }
+
+    pub fn locale(&self) -> Option<&str> {
+        self.info.as_ref().map(|i| i.locale.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login() {
+        let mut session = ClientSession::new(None);
+        session.record(&InPacket::Handshake {
+            protocol_version: 763,
+            server_addr: "mc.example.com",
+            server_port: 25565,
+            next_state: HandshakeNextState::Login,
+        });
+        session.record(&InPacket::LoginStart {
+            name: "Notch",
+            player_uuid: Uuid(7),
+        });
+        assert_eq!(session.view_distance(), None);
+        session.record(&InPacket::PluginMessageConfig {
+            channel: BRAND_CHANNEL,
+            data: &encode_brand("vanilla"),
+        });
+        session.record(&InPacket::ClientInfoConfig {
+            locale: "en_us",
+            view_distance: 12,
+            chat_mode: ChatMode::Enabled,
+            chat_colors: true,
+            displayed_skin_parts: SkinParts::ALL.with(SkinParts::CAPE, false),
+            main_hand: MainHand::Right,
+            enable_text_filtering: false,
+            allow_server_listings: true,
+        });
+
+        assert_eq!(session.protocol_version, 763);
+        assert_eq!(session.server_addr, "mc.example.com");
+        assert_eq!(session.username.as_deref(), Some("Notch"));
+        assert_eq!(session.uuid, Some(Uuid(7)));
+        let client = ConnectedClient::new(ClientID::new(1, 0), &session, ProtocolState::Config);
+        assert_eq!(client.username.as_deref(), Some("Notch"));
+        assert!(!client.is_playing());
+        assert_eq!(session.view_distance(), Some(12));
+        assert_eq!(session.effective_view_distance(10), 10);
+        assert_eq!(session.effective_view_distance(16), 12);
+        // changed in game
+        session.record(&InPacket::ClientInfoPlay {
+            locale: "en_us",
+            view_distance: 0,
+            chat_mode: ChatMode::Enabled,
+            chat_colors: true,
+            displayed_skin_parts: SkinParts::ALL.with(SkinParts::CAPE, false),
+            main_hand: MainHand::Right,
+            enable_text_filtering: false,
+            allow_server_listings: true,
+        });
+        assert_eq!(session.effective_view_distance(10), MIN_VIEW_DISTANCE);
+        assert_eq!(session.locale(), Some("en_us"));
+        assert_eq!(session.brand.as_deref(), Some("vanilla"));
+        assert!(!session.is_modded());
+        let parts = session.info.as_ref().unwrap().displayed_skin_parts;
+        assert!(parts.hat() && parts.left_pants_leg() && !parts.cape());
+        assert_eq!(parts.metadata().value, MetadataValue::Byte(0x7E));
+
+        session.record(&InPacket::Handshake {
+            protocol_version: 764,
+            server_addr: "mc.example.com\0FML3\0",
+            server_port: 25565,
+            next_state: HandshakeNextState::Login,
+        });
+        assert_eq!(session.server_addr, "mc.example.com");
+        assert_eq!(session.forge, Some(ForgeMarker::Fml3));
+        assert!(session.is_modded());
+        assert_eq!(
+            ForgeMarker::split_server_addr("localhost\0FML\0"),
+            ("localhost", Some(ForgeMarker::Fml))
+        );
+    }
+
+    #[test]
+    fn attachments() {
+        #[derive(Debug, Clone, Default, PartialEq)]
+        struct Kills(u32);
+
+        let mut session = ClientSession::new(None);
+        assert_eq!(session.get::<Kills>(), None);
+        session.get_or_default::<Kills>().0 += 1;
+        assert_eq!(session.insert(Kills(5)), Some(Kills(1)));
+        session.insert("team red");
+        let copy = session.clone();
+        session.get_mut::<Kills>().unwrap().0 += 1;
+        assert_eq!(session.get::<Kills>(), Some(&Kills(6)));
+        assert_eq!(copy.get::<Kills>(), Some(&Kills(5)));
+        assert_eq!(copy.get::<&str>(), Some(&"team red"));
+        assert_eq!(session.remove::<Kills>(), Some(Kills(6)));
+        assert_eq!(session.get::<Kills>(), None);
+    }
+}
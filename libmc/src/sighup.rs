@@ -0,0 +1,31 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by the signal handler, taken by the tick loop
+static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Makes SIGHUP set a flag for `take()` instead of ending the process
+#[cfg(unix)]
+pub(crate) fn install() {
+    use std::ffi::c_int;
+
+    const SIGHUP: c_int = 1;
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+    extern "C" fn on_sighup(_: c_int) {
+        RECEIVED.store(true, Ordering::Relaxed);
+    }
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        signal(SIGHUP, on_sighup);
+    }
+}
+
+/// There's no SIGHUP
+#[cfg(not(unix))]
+pub(crate) fn install() {}
+
+/// Whether SIGHUP was received since the last call
+pub(crate) fn take() -> bool {
+    RECEIVED.swap(false, Ordering::Relaxed)
+}
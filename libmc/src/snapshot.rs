@@ -0,0 +1,113 @@
+//! Snapshot protocol support (the `snapshot` feature): lets clients on the current snapshot join
+//! before libmc moves to the release it leads up to, by translating their packets like
+//! `Translator763` does for older clients. libmc itself keeps speaking `PROTOCOL_VERSION`.
+//!
+//! Snapshots change from week to week, so this only covers one at a time. To move to a newer
+//! snapshot, update the version constants and the tables of packets it renumbered.
+
+use crate::*;
+
+/// The protocol version of the snapshot that `SnapshotTranslator` translates for. Snapshot
+/// protocol versions have bit 30 set.
+pub const SNAPSHOT_PROTOCOL_VERSION: i32 = 0x4000_0092;
+
+/// The snapshot that `SNAPSHOT_PROTOCOL_VERSION` belongs to
+pub const SNAPSHOT_VERSION: &str = "23w40a";
+
+/// (ID in libmc's protocol, ID in the snapshot) of clientbound play packets the snapshot
+/// renumbered. Packets that aren't listed have the same ID in both.
+const CLIENTBOUND_PLAY: &[(i32, i32)] = &[];
+
+/// Like `CLIENTBOUND_PLAY`, for serverbound play packets
+const SERVERBOUND_PLAY: &[(i32, i32)] = &[];
+
+/// Translates for clients on `SNAPSHOT_VERSION`. Play packets are renumbered by the tables above;
+/// their bodies, and packets in other states, are passed through unchanged.
+#[derive(Debug, Default)]
+pub struct SnapshotTranslator;
+
+impl SnapshotTranslator {
+    fn clientbound_play_id(id: i32) -> i32 {
+        CLIENTBOUND_PLAY
+            .iter()
+            .find(|&&(ours, _)| ours == id)
+            .map_or(id, |&(_, theirs)| theirs)
+    }
+
+    fn serverbound_play_id(id: i32) -> i32 {
+        SERVERBOUND_PLAY
+            .iter()
+            .find(|&&(_, theirs)| theirs == id)
+            .map_or(id, |&(ours, _)| ours)
+    }
+
+    fn renumber(packet: &[u8], map: fn(i32) -> i32) -> Result<Vec<u8>, DecodeError> {
+        let mut r = PacketReader::new(packet);
+        let id = r.varint()?;
+        let mut out = Vec::with_capacity(packet.len() + 1);
+        write_varint(&mut out, map(id));
+        out.extend_from_slice(r.rest());
+        Ok(out)
+    }
+}
+
+impl ProtocolTranslator for SnapshotTranslator {
+    fn client_version(&self) -> i32 {
+        SNAPSHOT_PROTOCOL_VERSION
+    }
+
+    fn serverbound(
+        &mut self,
+        state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<(), DecodeError> {
+        match state {
+            ProtocolState::Play => out.push(Self::renumber(packet, Self::serverbound_play_id)?),
+            _ => out.push(packet.to_vec()),
+        }
+        Ok(())
+    }
+
+    fn clientbound(
+        &mut self,
+        state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+        _reply: &mut Vec<Vec<u8>>,
+    ) {
+        match state {
+            // libmc encoded it, so it has a valid ID
+            ProtocolState::Play => {
+                out.push(Self::renumber(packet, Self::clientbound_play_id).unwrap())
+            }
+            _ => out.push(packet.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_bijective() {
+        for table in [CLIENTBOUND_PLAY, SERVERBOUND_PLAY] {
+            for (i, a) in table.iter().enumerate() {
+                for b in &table[i + 1..] {
+                    assert!(a.0 != b.0 && a.1 != b.1, "{a:?} and {b:?} clash");
+                }
+            }
+        }
+        for &(ours, theirs) in CLIENTBOUND_PLAY {
+            assert_eq!(SnapshotTranslator::clientbound_play_id(ours), theirs);
+        }
+
+        let mut t = builtin_translator(SNAPSHOT_PROTOCOL_VERSION).unwrap();
+        assert_eq!(t.client_version(), SNAPSHOT_PROTOCOL_VERSION);
+        let mut out = Vec::new();
+        t.serverbound(ProtocolState::Login, &[0x03], &mut out)
+            .unwrap();
+        assert_eq!(out, [vec![0x03]]);
+    }
+}
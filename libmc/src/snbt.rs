@@ -0,0 +1,441 @@
+//! Stringified NBT, as used in commands and data packs: `{Count: 1b, id: "minecraft:stone"}`.
+//!
+//! `Nbt`, `CompoundNbt` and `NbtList` display as SNBT. Compound keys are sorted, so the output is stable.
+
+use crate::*;
+use std::borrow::Cow;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnbtError {
+    /// byte offset into the input where parsing failed
+    pub offset: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for SnbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bad SNBT at byte {}: {}", self.offset, self.msg)
+    }
+}
+
+impl std::error::Error for SnbtError {}
+
+impl Nbt<'static> {
+    pub fn parse_snbt(s: &str) -> Result<Nbt<'static>, SnbtError> {
+        let mut p = Parser { s, pos: 0 };
+        let v = p.value()?;
+        p.skip_ws();
+        if p.pos != s.len() {
+            return Err(p.err("trailing characters"));
+        }
+        Ok(v)
+    }
+}
+
+impl CompoundNbt<'static> {
+    /// Parses SNBT that has to be a compound, giving it the name `name`
+    pub fn parse_snbt(s: &str, name: &str) -> Result<CompoundNbt<'static>, SnbtError> {
+        match Nbt::parse_snbt(s)? {
+            Nbt::Compound(c) => {
+                let mut named = CompoundNbt::new(name.to_string());
+                for (k, v) in c.props() {
+                    named.set(k.to_string(), v.clone());
+                }
+                Ok(named)
+            }
+            _ => Err(SnbtError {
+                offset: 0,
+                msg: "expected a compound",
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Nbt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Nbt::Compound(c) => c.fmt(f),
+            Nbt::Byte(x) => write!(f, "{x}b"),
+            Nbt::Short(x) => write!(f, "{x}s"),
+            Nbt::Int(x) => write!(f, "{x}"),
+            Nbt::Long(x) => write!(f, "{x}L"),
+            // Debug always includes a '.' or exponent
+            Nbt::Float(x) => write!(f, "{x:?}f"),
+            Nbt::Double(x) => write!(f, "{x:?}d"),
+            Nbt::ByteArray(a) => write_seq(f, "B;", a.iter().map(|x| Nbt::Byte(*x))),
+            Nbt::String(s) => write_quoted(f, s),
+            Nbt::List(l) => l.fmt(f),
+            Nbt::IntArray(a) => write_seq(f, "I;", a.iter().map(|x| Nbt::Int(*x))),
+            Nbt::LongArray(a) => write_seq(f, "L;", a.iter().map(|x| Nbt::Long(*x))),
+        }
+    }
+}
+
+impl fmt::Display for CompoundNbt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut props: Vec<_> = self.props().collect();
+        props.sort_unstable_by_key(|(k, _)| *k);
+        f.write_str("{")?;
+        for (i, (k, v)) in props.into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            if !k.is_empty() && k.chars().all(is_unquoted_char) {
+                f.write_str(k)?;
+            } else {
+                write_quoted(f, k)?;
+            }
+            write!(f, ": {v}")?;
+        }
+        f.write_str("}")
+    }
+}
+
+impl fmt::Display for NbtList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NbtList::Compound(l) => write_seq(f, "", l.iter().map(|x| Nbt::Compound(x.clone()))),
+            NbtList::Byte(l) => write_seq(f, "", l.iter().map(|x| Nbt::Byte(*x))),
+            NbtList::Short(l) => write_seq(f, "", l.iter().map(|x| Nbt::Short(*x))),
+            NbtList::Int(l) => write_seq(f, "", l.iter().map(|x| Nbt::Int(*x))),
+            NbtList::Long(l) => write_seq(f, "", l.iter().map(|x| Nbt::Long(*x))),
+            NbtList::Float(l) => write_seq(f, "", l.iter().map(|x| Nbt::Float(*x))),
+            NbtList::Double(l) => write_seq(f, "", l.iter().map(|x| Nbt::Double(*x))),
+            NbtList::String(l) => write_seq(f, "", l.iter().map(|x| Nbt::String(x.clone()))),
+            NbtList::List(l) => write_seq(f, "", l.iter().map(|x| Nbt::List(x.clone()))),
+            NbtList::ByteArray(l) => write_seq(f, "", l.iter().map(|x| Nbt::ByteArray(x.clone()))),
+            NbtList::IntArray(l) => write_seq(f, "", l.iter().map(|x| Nbt::IntArray(x.clone()))),
+            NbtList::LongArray(l) => write_seq(f, "", l.iter().map(|x| Nbt::LongArray(x.clone()))),
+        }
+    }
+}
+
+/// `[prefix a, b, c]`
+fn write_seq<'a>(
+    f: &mut fmt::Formatter<'_>,
+    prefix: &str,
+    items: impl Iterator<Item = Nbt<'a>>,
+) -> fmt::Result {
+    f.write_str("[")?;
+    f.write_str(prefix)?;
+    for (i, x) in items.enumerate() {
+        if i > 0 || !prefix.is_empty() {
+            f.write_str(if i > 0 { ", " } else { " " })?;
+        }
+        write!(f, "{x}")?;
+    }
+    f.write_str("]")
+}
+
+/// Double quotes, unless the string contains some and no single quotes
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    let quote = if s.contains('"') && !s.contains('\'') {
+        '\''
+    } else {
+        '"'
+    };
+    write!(f, "{quote}")?;
+    for c in s.chars() {
+        if c == quote || c == '\\' {
+            write!(f, "\\")?;
+        }
+        write!(f, "{c}")?;
+    }
+    write!(f, "{quote}")
+}
+
+/// Characters allowed in unquoted strings and keys
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+struct Parser<'s> {
+    s: &'s str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn err(&self, msg: &'static str) -> SnbtError {
+        SnbtError {
+            offset: self.pos,
+            msg,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), SnbtError> {
+        self.skip_ws();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.err(match c {
+                ':' => "expected ':'",
+                ';' => "expected ';'",
+                _ => "unexpected character",
+            }))
+        }
+    }
+
+    fn value(&mut self) -> Result<Nbt<'static>, SnbtError> {
+        self.skip_ws();
+        match self.peek() {
+            Some('{') => self.compound().map(Nbt::Compound),
+            Some('[') => self.list(),
+            Some('"' | '\'') => Ok(Nbt::String(self.quoted()?.into())),
+            Some(c) if is_unquoted_char(c) => {
+                let start = self.pos;
+                let token = self.unquoted();
+                Ok(parse_scalar(token)
+                    .unwrap_or_else(|| Nbt::String(self.s[start..self.pos].to_string().into())))
+            }
+            Some(_) => Err(self.err("unexpected character")),
+            None => Err(self.err("unexpected end of input")),
+        }
+    }
+
+    fn unquoted(&mut self) -> &str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !is_unquoted_char(c) {
+                break;
+            }
+            self.pos += 1;
+        }
+        &self.s[start..self.pos]
+    }
+
+    fn quoted(&mut self) -> Result<String, SnbtError> {
+        let quote = self.peek().unwrap();
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.err("unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '\\' => match self.peek() {
+                    Some(e @ ('\\' | '"' | '\'')) => {
+                        out.push(e);
+                        self.pos += 1;
+                    }
+                    _ => return Err(self.err("bad escape")),
+                },
+                c if c == quote => return Ok(out),
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, SnbtError> {
+        self.skip_ws();
+        match self.peek() {
+            Some('"' | '\'') => self.quoted(),
+            Some(c) if is_unquoted_char(c) => Ok(self.unquoted().to_string()),
+            _ => Err(self.err("expected a key")),
+        }
+    }
+
+    fn compound(&mut self) -> Result<CompoundNbt<'static>, SnbtError> {
+        self.pos += 1; // '{'
+        let mut compound = CompoundNbt::new("");
+        self.skip_ws();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(compound);
+        }
+        loop {
+            let key = self.key()?;
+            self.expect(':')?;
+            let value = self.value()?;
+            compound.set(key, value);
+            self.skip_ws();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(compound);
+                }
+                _ => return Err(self.err("expected ',' or '}'")),
+            }
+        }
+    }
+
+    /// Lists and arrays
+    fn list(&mut self) -> Result<Nbt<'static>, SnbtError> {
+        self.pos += 1; // '['
+        self.skip_ws();
+        let array_type = match &self.s.as_bytes()[self.pos..] {
+            [t @ (b'B' | b'I' | b'L'), b';', ..] => Some(*t),
+            _ => None,
+        };
+        if array_type.is_some() {
+            self.pos += 2;
+        }
+
+        let mut items = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+        } else {
+            loop {
+                self.skip_ws();
+                let start = self.pos;
+                items.push((start, self.value()?));
+                self.skip_ws();
+                match self.peek() {
+                    Some(',') => self.pos += 1,
+                    Some(']') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return Err(self.err("expected ',' or ']'")),
+                }
+            }
+        }
+
+        macro_rules! collect {
+            ($variant:ident, $msg:expr) => {
+                items
+                    .into_iter()
+                    .map(|(offset, v)| match v {
+                        Nbt::$variant(x) => Ok(x),
+                        _ => Err(SnbtError { offset, msg: $msg }),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+        }
+
+        const MIXED: &str = "all elements of a list must have the same type";
+        Ok(match array_type {
+            Some(b'B') => Nbt::ByteArray(Cow::Owned(collect!(Byte, "expected a byte")?)),
+            Some(b'I') => Nbt::IntArray(Cow::Owned(collect!(Int, "expected an int")?)),
+            Some(b'L') => Nbt::LongArray(Cow::Owned(collect!(Long, "expected a long")?)),
+            _ => Nbt::List(match items.first() {
+                // an empty list has no element type to go by
+                None | Some((_, Nbt::Compound(_))) => {
+                    NbtList::Compound(collect!(Compound, MIXED)?.into())
+                }
+                Some((_, Nbt::Byte(_))) => NbtList::Byte(collect!(Byte, MIXED)?.into()),
+                Some((_, Nbt::Short(_))) => NbtList::Short(collect!(Short, MIXED)?.into()),
+                Some((_, Nbt::Int(_))) => NbtList::Int(collect!(Int, MIXED)?.into()),
+                Some((_, Nbt::Long(_))) => NbtList::Long(collect!(Long, MIXED)?.into()),
+                Some((_, Nbt::Float(_))) => NbtList::Float(collect!(Float, MIXED)?.into()),
+                Some((_, Nbt::Double(_))) => NbtList::Double(collect!(Double, MIXED)?.into()),
+                Some((_, Nbt::String(_))) => NbtList::String(collect!(String, MIXED)?.into()),
+                Some((_, Nbt::List(_))) => NbtList::List(collect!(List, MIXED)?.into()),
+                Some((_, Nbt::ByteArray(_))) => {
+                    NbtList::ByteArray(collect!(ByteArray, MIXED)?.into())
+                }
+                Some((_, Nbt::IntArray(_))) => NbtList::IntArray(collect!(IntArray, MIXED)?.into()),
+                Some((_, Nbt::LongArray(_))) => {
+                    NbtList::LongArray(collect!(LongArray, MIXED)?.into())
+                }
+            }),
+        })
+    }
+}
+
+/// Parses an unquoted token as a number or boolean. Anything else is an unquoted string.
+fn parse_scalar(token: &str) -> Option<Nbt<'static>> {
+    match token {
+        "true" => return Some(Nbt::Byte(1)),
+        "false" => return Some(Nbt::Byte(0)),
+        _ => {}
+    }
+
+    let (body, suffix) = match token.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&token[..i], Some(c.to_ascii_lowercase())),
+        _ => (token, None),
+    };
+    // "1e5" would otherwise be a float with an 'e' suffix... which vanilla treats as a string too
+    if body.is_empty() || !body.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c)) {
+        return None;
+    }
+    let is_integer = !body.contains(['.', 'e', 'E']);
+    match suffix {
+        Some('b') if is_integer => body.parse().ok().map(Nbt::Byte),
+        Some('s') if is_integer => body.parse().ok().map(Nbt::Short),
+        Some('l') if is_integer => body.parse().ok().map(Nbt::Long),
+        Some('f') => body.parse().ok().map(Nbt::Float),
+        Some('d') => body.parse().ok().map(Nbt::Double),
+        None if is_integer => body.parse().ok().map(Nbt::Int),
+        None => body.parse().ok().map(Nbt::Double),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_item() {
+        let c = CompoundNbt::parse_snbt(
+            r#"{Count: 1b, id: "minecraft:stone", tag: {Damage: 3s, Name: 'it\'s', Lore: [a, b]},
+                Pos: [1.5d, 2.0, -3d], Colors: [I; 1, -2], Hidden: true, Level: 2147483648}"#,
+            "item",
+        )
+        .unwrap();
+        assert_eq!(c.name(), "item");
+        assert!(matches!(c.get("Count"), Some(Nbt::Byte(1))));
+        assert!(matches!(c.get("id"), Some(Nbt::String(s)) if s == "minecraft:stone"));
+        let Some(Nbt::Compound(tag)) = c.get("tag") else {
+            panic!("expected compound");
+        };
+        assert!(matches!(tag.get("Damage"), Some(Nbt::Short(3))));
+        assert!(matches!(tag.get("Name"), Some(Nbt::String(s)) if s == "it's"));
+        assert!(matches!(tag.get("Lore"), Some(Nbt::List(NbtList::String(l))) if l.len() == 2));
+        assert!(matches!(c.get("Pos"), Some(Nbt::List(NbtList::Double(l))) if l[2] == -3.0));
+        assert!(matches!(c.get("Colors"), Some(Nbt::IntArray(a)) if a[..] == [1, -2]));
+        assert!(matches!(c.get("Hidden"), Some(Nbt::Byte(1))));
+        // too big for an int
+        assert!(matches!(c.get("Level"), Some(Nbt::String(_))));
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        let snbt = r#"{"": [I; 1, -2], Count: 1b, Empty: [], Lore: ["it's \\ \""], Pos: [1.5d, 2.0d], Speed: 0.1f, Time: 5L, "a b": 'say "hi"', tag: {Damage: 3s}}"#;
+        let nbt = Nbt::parse_snbt(snbt).unwrap();
+        assert_eq!(nbt.to_string(), snbt);
+        assert_eq!(Nbt::parse_snbt(&nbt.to_string()).unwrap().to_string(), snbt);
+        assert_eq!(Nbt::Double(1e300).to_string(), "1e300d");
+        for nested in [
+            "[[1s, 2s], [], [3s]]",
+            "[[I; 1, 2], [I;]]",
+            r#"[[["a"]], [[]]]"#,
+        ] {
+            assert_eq!(Nbt::parse_snbt(nested).unwrap().to_string(), nested);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Nbt::parse_snbt("[1, 2b]").unwrap_err(),
+            SnbtError {
+                offset: 4,
+                msg: "all elements of a list must have the same type"
+            }
+        );
+        assert!(Nbt::parse_snbt("{a: 1").is_err());
+        assert!(Nbt::parse_snbt("{a 1}").is_err());
+        assert!(Nbt::parse_snbt("[B; 1, 2]").is_err());
+        assert!(Nbt::parse_snbt("\"abc").is_err());
+        assert!(matches!(Nbt::parse_snbt("1.5f"), Ok(Nbt::Float(x)) if x == 1.5));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::*;
+use std::collections::HashSet;
+
+/// Vanilla's default `spawn-protection` radius
+pub const DEFAULT_SPAWN_PROTECTION: u32 = 16;
+
+/// Radius (in chunks) of the ticket vanilla keeps around the spawn chunk
+pub const SPAWN_CHUNK_TICKET_RADIUS: i32 = 11;
+
+/// Stops non-ops from editing blocks near the world spawn, like vanilla's `spawn-protection`.
+///
+/// As in vanilla, there is no protection while nobody is op, and a radius of 0 disables it.
+#[derive(Debug, Clone)]
+pub struct SpawnProtection {
+    spawn: Position,
+    radius: u32,
+    ops: HashSet<Uuid>,
+}
+
+impl SpawnProtection {
+    pub fn new(spawn: Position, radius: u32) -> Self {
+        Self {
+            spawn,
+            radius,
+            ops: HashSet::new(),
+        }
+    }
+
+    /// Uses the `spawn-protection` setting
+    pub fn from_properties(props: &ServerProperties, spawn: Position) -> Self {
+        Self::new(
+            spawn,
+            props.get_or("spawn-protection", DEFAULT_SPAWN_PROTECTION),
+        )
+    }
+
+    pub fn spawn(&self) -> Position {
+        self.spawn
+    }
+
+    pub fn set_spawn(&mut self, spawn: Position) {
+        self.spawn = spawn;
+    }
+
+    pub fn radius(&self) -> u32 {
+        self.radius
+    }
+
+    pub fn set_op(&mut self, uuid: Uuid, op: bool) {
+        if op {
+            self.ops.insert(uuid);
+        } else {
+            self.ops.remove(&uuid);
+        }
+    }
+
+    pub fn is_op(&self, uuid: Uuid) -> bool {
+        self.ops.contains(&uuid)
+    }
+
+    /// Whether `pos` is protected from non-ops
+    pub fn is_protected(&self, pos: Position) -> bool {
+        if self.radius == 0 || self.ops.is_empty() {
+            return false;
+        }
+        let dx = pos.x.abs_diff(self.spawn.x);
+        let dz = pos.z.abs_diff(self.spawn.z);
+        dx.max(dz) <= self.radius
+    }
+
+    pub fn can_edit(&self, player: Uuid, pos: Position) -> bool {
+        self.is_op(player) || !self.is_protected(pos)
+    }
+
+    /// Registers handlers cancelling block breaks and placements that aren't allowed.
+    /// `lookup` finds the `SpawnProtection` in the bus context and the UUID of a client.
+    pub fn register<C: 'static>(
+        bus: &mut EventBus<C>,
+        lookup: impl Fn(&C, ClientID) -> Option<(&SpawnProtection, Uuid)> + Clone + 'static,
+    ) -> [HandlerId; 2] {
+        let place_lookup = lookup.clone();
+        [
+            bus.register(Priority::High, move |ctx, e: &mut BlockBreakEvent| {
+                if let Some((prot, uuid)) = lookup(ctx, e.cid) {
+                    e.cancelled |= !prot.can_edit(uuid, e.location);
+                }
+            }),
+            bus.register(Priority::High, move |ctx, e: &mut BlockPlaceEvent| {
+                if let Some((prot, uuid)) = place_lookup(ctx, e.cid) {
+                    e.cancelled |= !prot.can_edit(uuid, e.location);
+                }
+            }),
+        ]
+    }
+}
+
+/// The chunks vanilla keeps loaded around the world spawn, regardless of where players are
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpawnChunks {
+    center: ChunkPos,
+}
+
+impl SpawnChunks {
+    pub fn new(spawn: Position) -> Self {
+        Self {
+            center: BlockPos::from(spawn).chunk(),
+        }
+    }
+
+    /// The chunk the spawn is in
+    pub fn center(&self) -> ChunkPos {
+        self.center
+    }
+
+    /// The 23x23 chunks that are always loaded
+    pub fn is_loaded(&self, chunk: ChunkPos) -> bool {
+        chunk.chebyshev_distance(self.center) <= SPAWN_CHUNK_TICKET_RADIUS as u32
+    }
+
+    /// The 21x21 chunks where blocks (redstone, crops, ...) keep ticking
+    pub fn is_block_ticking(&self, chunk: ChunkPos) -> bool {
+        chunk.chebyshev_distance(self.center) < SPAWN_CHUNK_TICKET_RADIUS as u32
+    }
+
+    /// The 19x19 chunks where entities keep ticking
+    pub fn is_entity_ticking(&self, chunk: ChunkPos) -> bool {
+        chunk.chebyshev_distance(self.center) < SPAWN_CHUNK_TICKET_RADIUS as u32 - 1
+    }
+
+    /// Every always-loaded chunk, for loading them at startup
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkPos> {
+        self.center.square(SPAWN_CHUNK_TICKET_RADIUS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protection() {
+        let spawn = Position {
+            x: 100,
+            y: 64,
+            z: -20,
+        };
+        let props = ServerProperties::parse("spawn-protection=4");
+        let mut prot = SpawnProtection::from_properties(&props, spawn);
+        let near = Position {
+            x: 104,
+            y: 0,
+            z: -24,
+        };
+        let far = Position {
+            x: 105,
+            y: 0,
+            z: -20,
+        };
+        assert!(!prot.is_protected(near), "no ops, no protection");
+
+        prot.set_op(Uuid(1), true);
+        assert!(prot.is_protected(near));
+        assert!(!prot.is_protected(far));
+
+        let mut bus = EventBus::<SpawnProtection>::new();
+        SpawnProtection::register(&mut bus, |prot, cid| {
+            Some((prot, Uuid(u128::from(cid.index()))))
+        });
+        let mut place = BlockPlaceEvent {
+            cid: ClientID::new(2, 0),
+            location: near,
+            cancelled: false,
+        };
+        assert!(!bus.post(&mut prot, &mut place));
+        let mut place = BlockPlaceEvent {
+            cid: ClientID::new(1, 0),
+            location: near,
+            cancelled: false,
+        };
+        assert!(bus.post(&mut prot, &mut place), "ops can edit");
+
+        let chunks = SpawnChunks::new(spawn);
+        assert_eq!(chunks.center(), ChunkPos::new(6, -2));
+        assert_eq!(chunks.chunks().count(), 23 * 23);
+        assert!(
+            chunks.is_entity_ticking(ChunkPos::new(15, -2))
+                && !chunks.is_entity_ticking(ChunkPos::new(16, -2))
+        );
+        assert!(chunks.is_loaded(ChunkPos::new(17, 9)) && !chunks.is_loaded(ChunkPos::new(18, 9)));
+    }
+}
@@ -0,0 +1,309 @@
+use crate::chat::write_json_string;
+use crate::util::base64_encode;
+use crate::*;
+use std::fmt::Write as _;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The `version` object of a status response. Clients compare it with their own version to show
+/// whether they can join, so `StatusCache` providers that don't use `StatusResponse` should
+/// include it as is.
+pub fn status_version_json() -> String {
+    format!(r#"{{"name":"{MINECRAFT_VERSION}","protocol":{PROTOCOL_VERSION}}}"#)
+}
+
+/// Builds the JSON of a status response, which is what the server list shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusResponse {
+    pub version_name: String,
+    pub protocol: i32,
+    pub max_players: i32,
+    pub online_players: i32,
+    /// Names and UUIDs of the players shown when hovering over the player count
+    pub sample: Vec<(String, Uuid)>,
+    pub motd: TextComponent,
+    /// A `data:image/png;base64,...` URL of a 64x64 PNG
+    pub favicon: Option<String>,
+}
+
+impl Default for StatusResponse {
+    fn default() -> Self {
+        Self {
+            version_name: MINECRAFT_VERSION.to_string(),
+            protocol: PROTOCOL_VERSION,
+            max_players: 20,
+            online_players: 0,
+            sample: Vec::new(),
+            motd: TextComponent::text("A Minecraft Server"),
+            favicon: None,
+        }
+    }
+}
+
+impl StatusResponse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports another version than libmc's, e.g. when clients are let in through a translator.
+    /// Clients show the server as incompatible if `protocol` isn't theirs.
+    pub fn version(mut self, name: impl Into<String>, protocol: i32) -> Self {
+        self.version_name = name.into();
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn players(mut self, online: i32, max: i32) -> Self {
+        self.online_players = online;
+        self.max_players = max;
+        self
+    }
+
+    /// Adds a player to the sample shown when hovering over the player count
+    pub fn sample_player(mut self, name: impl Into<String>, uuid: Uuid) -> Self {
+        self.sample.push((name.into(), uuid));
+        self
+    }
+
+    pub fn motd(mut self, motd: TextComponent) -> Self {
+        self.motd = motd;
+        self
+    }
+
+    /// Sets the server icon. Fails if `png` isn't a 64x64 PNG, which is all clients accept.
+    pub fn favicon_png(mut self, png: &[u8]) -> io::Result<Self> {
+        const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+        // the IHDR chunk comes first, and starts with the width and height
+        let size = png.get(16..24);
+        if !png.starts_with(SIGNATURE) || size != Some(&[0, 0, 0, 64, 0, 0, 0, 64]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "server icons have to be 64x64 PNGs",
+            ));
+        }
+        self.favicon = Some(format!("data:image/png;base64,{}", base64_encode(png)));
+        Ok(self)
+    }
+
+    /// Sets the server icon from a 64x64 PNG file, like vanilla's `server-icon.png`
+    pub fn favicon_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.favicon_png(&std::fs::read(path)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write!(out, r#"{{"version":{{"name":"#).unwrap();
+        write_json_string(&mut out, &self.version_name);
+        write!(
+            out,
+            r#","protocol":{}}},"players":{{"max":{},"online":{}"#,
+            self.protocol, self.max_players, self.online_players
+        )
+        .unwrap();
+        if !self.sample.is_empty() {
+            out.push_str(r#","sample":["#);
+            for (i, (name, uuid)) in self.sample.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(r#"{"name":"#);
+                write_json_string(&mut out, name);
+                write!(out, r#","id":"{uuid}"}}"#).unwrap();
+            }
+            out.push(']');
+        }
+        write!(out, r#"}},"description":{}"#, self.motd.to_json()).unwrap();
+        if let Some(favicon) = &self.favicon {
+            out.push_str(r#","favicon":"#);
+            write_json_string(&mut out, favicon);
+        }
+        out.push('}');
+        out
+    }
+}
+
+type Provider = dyn Fn() -> String + Send + Sync;
+type Waiter = Box<dyn FnOnce(Arc<str>) + Send>;
+
+/// Produces server list status JSON with a (potentially slow) provider, run on a background
+/// thread, and caches it for a short time so ping floods don't call the provider every time.
+///
+/// Requests within `ttl` of the last refresh get the cached JSON right away. Once it's stale,
+/// requests still get the stale JSON right away while one refresh runs in the background;
+/// only requests before the first refresh has finished have to wait for it. Clones share the cache.
+#[derive(Clone)]
+pub struct StatusCache<C: Clock = SystemClock> {
+    inner: Arc<Inner<C>>,
+}
+
+struct Inner<C> {
+    provider: Box<Provider>,
+    ttl: Duration,
+    clock: C,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    cached: Option<(Instant, Arc<str>)>,
+    refreshing: bool,
+    /// Requests waiting for the first refresh
+    waiters: Vec<Waiter>,
+}
+
+impl StatusCache {
+    pub fn new(ttl: Duration, provider: impl Fn() -> String + Send + Sync + 'static) -> Self {
+        Self::with_clock(ttl, provider, SystemClock)
+    }
+}
+
+impl<C: Clock + Send + Sync + 'static> StatusCache<C> {
+    pub fn with_clock(
+        ttl: Duration,
+        provider: impl Fn() -> String + Send + Sync + 'static,
+        clock: C,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                provider: Box::new(provider),
+                ttl,
+                clock,
+                state: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Calls `reply` with the status JSON, either right away or from the refresh thread
+    pub fn request(&self, reply: impl FnOnce(Arc<str>) + Send + 'static) {
+        let mut state = self.inner.state.lock().unwrap();
+        let now = self.inner.clock.now();
+        let fresh = state
+            .cached
+            .as_ref()
+            .is_some_and(|(at, _)| now.duration_since(*at) < self.inner.ttl);
+        if !fresh && !state.refreshing {
+            state.refreshing = true;
+            let inner = Arc::clone(&self.inner);
+            thread::spawn(move || Self::refresh(&inner));
+        }
+
+        match &state.cached {
+            Some((_, json)) => {
+                let json = Arc::clone(json);
+                drop(state);
+                reply(json);
+            }
+            None => state.waiters.push(Box::new(reply)),
+        }
+    }
+
+    /// Makes the next request refresh the cache
+    pub fn invalidate(&self) {
+        if let Some((at, _)) = &mut self.inner.state.lock().unwrap().cached {
+            *at -= self.inner.ttl;
+        }
+    }
+
+    fn refresh(inner: &Inner<C>) {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| (inner.provider)()));
+        let mut state = inner.state.lock().unwrap();
+        state.refreshing = false;
+        let Ok(json) = result else {
+            // the next request tries again; until then waiters keep waiting
+            return;
+        };
+        let json: Arc<str> = json.into();
+        state.cached = Some((inner.clock.now(), Arc::clone(&json)));
+        let waiters = std::mem::take(&mut state.waiters);
+        drop(state);
+        for reply in waiters {
+            reply(Arc::clone(&json));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+
+    #[test]
+    fn default_status() {
+        let json = Json::parse(&StatusResponse::new().to_json()).unwrap();
+        let version = json.get("version").unwrap();
+        assert_eq!(version.get("name").unwrap().as_str(), Some("1.20.2"));
+        assert_eq!(version.get("protocol").unwrap().as_f64(), Some(764.0));
+    }
+
+    #[test]
+    fn builder() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 64, 0, 0, 0, 64]);
+        let status = StatusResponse::new()
+            .players(1, 100)
+            .sample_player("Steve", Uuid(1))
+            .motd(TextComponent::text("Hello \"world\""))
+            .favicon_png(&png)
+            .unwrap();
+        let json = Json::parse(&status.to_json()).unwrap();
+        let players = json.get("players").unwrap();
+        assert_eq!(players.get("max").unwrap().as_f64(), Some(100.0));
+        let sample = &players.get("sample").unwrap().as_array().unwrap()[0];
+        assert_eq!(
+            sample.get("id").unwrap().as_str(),
+            Some("00000000-0000-0000-0000-000000000001")
+        );
+        let motd = json.get("description").unwrap().get("text").unwrap();
+        assert_eq!(motd.as_str(), Some("Hello \"world\""));
+        let favicon = json.get("favicon").unwrap().as_str().unwrap();
+        assert!(favicon.starts_with("data:image/png;base64,iVBORw0KGgo"));
+
+        assert!(StatusResponse::new().favicon_png(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn cached_and_coalesced() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let clock = ManualClock::new();
+        let provider_calls = Arc::clone(&calls);
+        let cache = StatusCache::with_clock(
+            Duration::from_secs(5),
+            move || {
+                let n = provider_calls.fetch_add(1, Ordering::SeqCst) + 1;
+                thread::sleep(Duration::from_millis(20));
+                format!("status {n}")
+            },
+            clock.clone(),
+        );
+
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..10 {
+            let tx = tx.clone();
+            cache.request(move |json| tx.send(json).unwrap());
+        }
+        for _ in 0..10 {
+            assert_eq!(&*rx.recv().unwrap(), "status 1");
+        }
+        assert_eq!(
+            calls.load(Ordering::SeqCst),
+            1,
+            "one provider call for the flood"
+        );
+
+        // stale: answered with the old status while refreshing
+        clock.advance(Duration::from_secs(6));
+        let tx2 = tx.clone();
+        cache.request(move |json| tx2.send(json).unwrap());
+        assert_eq!(&*rx.recv().unwrap(), "status 1");
+        while calls.load(Ordering::SeqCst) < 2 || cache.inner.state.lock().unwrap().refreshing {
+            thread::sleep(Duration::from_millis(5));
+        }
+        cache.request(move |json| tx.send(json).unwrap());
+        assert_eq!(&*rx.recv().unwrap(), "status 2");
+    }
+}
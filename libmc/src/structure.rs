@@ -0,0 +1,520 @@
+use crate::nbt::{compound_list, invalid, read_nbt_file, write_nbt_file};
+use crate::*;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// A block state by name, as structure files (and commands) have them,
+/// e.g. `minecraft:oak_stairs[facing=east,half=bottom]`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockState {
+    pub name: String,
+    pub properties: Vec<(String, String)>,
+}
+
+impl BlockState {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Parses a state written like `minecraft:oak_stairs[facing=east,half=bottom]`
+    pub fn parse(s: &str) -> Option<Self> {
+        let Some((name, props)) = s.split_once('[') else {
+            return (!s.is_empty()).then(|| Self::new(s));
+        };
+        let mut state = Self::new(name);
+        let props = props.strip_suffix(']')?;
+        if !props.is_empty() {
+            for prop in props.split(',') {
+                let (k, v) = prop.split_once('=')?;
+                state = state.with(k.trim(), v.trim());
+            }
+        }
+        Some(state)
+    }
+
+    /// The state turned by `rotation`: facings, axes, sign rotations and side connections turn with it
+    pub fn rotated(&self, rotation: Rotation) -> Self {
+        let properties = self.properties.iter().map(|(k, v)| {
+            let turn = |side: &str| rotation.turn_side(side).unwrap_or(side).to_owned();
+            match k.as_str() {
+                "facing" => (k.clone(), turn(v)),
+                "north" | "east" | "south" | "west" => (turn(k), v.clone()),
+                "axis" if rotation.swaps_axes() => (
+                    k.clone(),
+                    match v.as_str() {
+                        "x" => "z".into(),
+                        "z" => "x".into(),
+                        _ => v.clone(),
+                    },
+                ),
+                "rotation" => match v.parse::<u8>() {
+                    Ok(r) => (
+                        k.clone(),
+                        ((r + rotation.quarter_turns() * 4) % 16).to_string(),
+                    ),
+                    Err(_) => (k.clone(), v.clone()),
+                },
+                _ => (k.clone(), v.clone()),
+            }
+        });
+        Self {
+            name: self.name.clone(),
+            properties: properties.collect(),
+        }
+    }
+
+    /// The state flipped by `mirror`
+    pub fn mirrored(&self, mirror: Mirror) -> Self {
+        if mirror == Mirror::None {
+            return self.clone();
+        }
+        let properties = self.properties.iter().map(|(k, v)| {
+            let flip = |side: &str| mirror.flip_side(side).unwrap_or(side).to_owned();
+            match (k.as_str(), v.as_str()) {
+                ("facing", _) => (k.clone(), flip(v)),
+                ("north" | "east" | "south" | "west", _) => (flip(k), v.clone()),
+                ("rotation", _) => match v.parse::<u8>() {
+                    Ok(r) => (k.clone(), mirror.flip_rotation(r).to_string()),
+                    Err(_) => (k.clone(), v.clone()),
+                },
+                // stair corners and door hinges are handed
+                ("shape" | "hinge", _) => (
+                    k.clone(),
+                    if let Some(s) = v.strip_suffix("left") {
+                        format!("{s}right")
+                    } else if let Some(s) = v.strip_suffix("right") {
+                        format!("{s}left")
+                    } else {
+                        v.clone()
+                    },
+                ),
+                _ => (k.clone(), v.clone()),
+            }
+        });
+        Self {
+            name: self.name.clone(),
+            properties: properties.collect(),
+        }
+    }
+}
+
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if !self.properties.is_empty() {
+            let props: Vec<_> = self
+                .properties
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect();
+            write!(f, "[{}]", props.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Clockwise, seen from above
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Counterclockwise90,
+}
+
+impl Rotation {
+    fn quarter_turns(self) -> u8 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::Counterclockwise90 => 3,
+        }
+    }
+
+    fn swaps_axes(self) -> bool {
+        self.quarter_turns() % 2 == 1
+    }
+
+    fn turn_side(self, side: &str) -> Option<&'static str> {
+        const SIDES: [&str; 4] = ["north", "east", "south", "west"];
+        let i = SIDES.iter().position(|s| *s == side)?;
+        Some(SIDES[(i + usize::from(self.quarter_turns())) % 4])
+    }
+
+    /// Turns a block position around the origin
+    pub fn apply(self, [x, y, z]: [i32; 3]) -> [i32; 3] {
+        match self {
+            Rotation::None => [x, y, z],
+            Rotation::Clockwise90 => [-z, y, x],
+            Rotation::Clockwise180 => [-x, y, -z],
+            Rotation::Counterclockwise90 => [z, y, -x],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Mirror {
+    #[default]
+    None,
+    /// Flips along the z axis (north and south swap)
+    LeftRight,
+    /// Flips along the x axis (east and west swap)
+    FrontBack,
+}
+
+impl Mirror {
+    fn flip_side(self, side: &str) -> Option<&'static str> {
+        match (self, side) {
+            (Mirror::LeftRight, "north") => Some("south"),
+            (Mirror::LeftRight, "south") => Some("north"),
+            (Mirror::FrontBack, "east") => Some("west"),
+            (Mirror::FrontBack, "west") => Some("east"),
+            _ => None,
+        }
+    }
+
+    /// Flips a sign's or banner's `rotation` (0 to 15, 0 facing south)
+    fn flip_rotation(self, rotation: u8) -> u8 {
+        match self {
+            Mirror::None => rotation,
+            Mirror::LeftRight => (24 - rotation) % 16,
+            Mirror::FrontBack => (16 - rotation) % 16,
+        }
+    }
+
+    /// Flips a block position through the origin
+    pub fn apply(self, [x, y, z]: [i32; 3]) -> [i32; 3] {
+        match self {
+            Mirror::None => [x, y, z],
+            Mirror::LeftRight => [x, y, -z],
+            Mirror::FrontBack => [-x, y, z],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureBlock {
+    /// Relative to the structure's corner
+    pub pos: [i32; 3],
+    /// Index into `Structure::palette`
+    pub state: usize,
+    /// The block entity's data, if it has one
+    pub nbt: Option<CompoundNbt<'static>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureEntity {
+    /// Relative to the structure's corner
+    pub pos: [f64; 3],
+    pub block_pos: [i32; 3],
+    /// With the entity's `id`
+    pub nbt: CompoundNbt<'static>,
+}
+
+/// A vanilla structure file (what structure blocks save and `/place template` places),
+/// gzipped NBT in a world's `generated/<namespace>/structures/`.
+///
+/// Structures with several palettes (`palettes`, only shipwrecks use them) aren't supported.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Structure {
+    pub data_version: i32,
+    pub size: [i32; 3],
+    pub palette: Vec<BlockState>,
+    pub blocks: Vec<StructureBlock>,
+    pub entities: Vec<StructureEntity>,
+}
+
+impl Structure {
+    pub fn from_nbt(root: &CompoundNbt<'static>) -> io::Result<Self> {
+        let data_version = match root.get("DataVersion") {
+            Some(Nbt::Int(v)) => *v,
+            _ => 0,
+        };
+
+        let mut palette = Vec::new();
+        for state in compound_list(root, "palette")? {
+            let Some(Nbt::String(name)) = state.get("Name") else {
+                return Err(invalid("palette entry without a Name"));
+            };
+            let mut block = BlockState::new(name.to_string());
+            if let Some(Nbt::Compound(props)) = state.get("Properties") {
+                for (key, value) in props.props() {
+                    if let Nbt::String(value) = value {
+                        block = block.with(key, value.to_string());
+                    }
+                }
+                // compounds don't keep their order
+                block.properties.sort();
+            }
+            palette.push(block);
+        }
+
+        let mut blocks = Vec::new();
+        for block in compound_list(root, "blocks")? {
+            let Some(&Nbt::Int(state)) = block.get("state") else {
+                return Err(invalid("block without a state"));
+            };
+            let state = usize::try_from(state)
+                .ok()
+                .filter(|&s| s < palette.len())
+                .ok_or_else(|| invalid("block state isn't in the palette"))?;
+            let nbt = match block.get("nbt") {
+                Some(Nbt::Compound(nbt)) => Some(nbt.clone()),
+                _ => None,
+            };
+            blocks.push(StructureBlock {
+                pos: int3(block, "pos")?,
+                state,
+                nbt,
+            });
+        }
+
+        let mut entities = Vec::new();
+        for entity in compound_list(root, "entities")? {
+            let Some(Nbt::List(NbtList::Double(pos))) = entity.get("pos") else {
+                return Err(invalid("entity without a pos"));
+            };
+            let Some(Nbt::Compound(nbt)) = entity.get("nbt") else {
+                return Err(invalid("entity without nbt"));
+            };
+            entities.push(StructureEntity {
+                pos: pos[..]
+                    .try_into()
+                    .map_err(|_| invalid("pos isn't 3 doubles"))?,
+                block_pos: int3(entity, "blockPos")?,
+                nbt: nbt.clone(),
+            });
+        }
+
+        Ok(Self {
+            data_version,
+            size: int3(root, "size")?,
+            palette,
+            blocks,
+            entities,
+        })
+    }
+
+    pub fn to_nbt(&self) -> CompoundNbt<'static> {
+        let palette: Vec<_> = self
+            .palette
+            .iter()
+            .map(|state| {
+                let mut c = CompoundNbt::new("");
+                c.set("Name", Nbt::String(state.name.clone().into()));
+                if !state.properties.is_empty() {
+                    let mut props = CompoundNbt::new("");
+                    for (k, v) in &state.properties {
+                        props.set(k.clone(), Nbt::String(v.clone().into()));
+                    }
+                    c.set("Properties", Nbt::Compound(props));
+                }
+                c
+            })
+            .collect();
+        let blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let mut c = CompoundNbt::new("");
+                c.set("pos", Nbt::List(NbtList::Int(block.pos.to_vec().into())));
+                c.set("state", Nbt::Int(block.state as i32));
+                if let Some(nbt) = &block.nbt {
+                    c.set("nbt", Nbt::Compound(nbt.clone()));
+                }
+                c
+            })
+            .collect();
+        let entities: Vec<_> = self
+            .entities
+            .iter()
+            .map(|entity| {
+                let mut c = CompoundNbt::new("");
+                c.set(
+                    "pos",
+                    Nbt::List(NbtList::Double(entity.pos.to_vec().into())),
+                );
+                c.set(
+                    "blockPos",
+                    Nbt::List(NbtList::Int(entity.block_pos.to_vec().into())),
+                );
+                c.set("nbt", Nbt::Compound(entity.nbt.clone()));
+                c
+            })
+            .collect();
+
+        let mut root = CompoundNbt::new("");
+        root.set("DataVersion", Nbt::Int(self.data_version));
+        root.set("size", Nbt::List(NbtList::Int(self.size.to_vec().into())));
+        root.set("palette", Nbt::List(NbtList::Compound(palette.into())));
+        root.set("blocks", Nbt::List(NbtList::Compound(blocks.into())));
+        root.set("entities", Nbt::List(NbtList::Compound(entities.into())));
+        root
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match read_nbt_file(path)? {
+            Some(root) => Self::from_nbt(&root),
+            None => Err(io::Error::new(ErrorKind::NotFound, "no such structure")),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_nbt_file(path, &self.to_nbt())
+    }
+
+    /// Where the structure's entities go when it's placed like `place_structure()` does,
+    /// in world coordinates
+    pub fn placed_entities(
+        &self,
+        origin: [i32; 3],
+        rotation: Rotation,
+        mirror: Mirror,
+    ) -> Vec<([f64; 3], &CompoundNbt<'static>)> {
+        self.entities
+            .iter()
+            .map(|entity| {
+                // blocks span a whole unit, so points flip and turn around the block's far edge
+                let [mut x, y, mut z] = entity.pos;
+                match mirror {
+                    Mirror::None => {}
+                    Mirror::LeftRight => z = 1.0 - z,
+                    Mirror::FrontBack => x = 1.0 - x,
+                }
+                let [x, z] = match rotation {
+                    Rotation::None => [x, z],
+                    Rotation::Clockwise90 => [1.0 - z, x],
+                    Rotation::Clockwise180 => [1.0 - x, 1.0 - z],
+                    Rotation::Counterclockwise90 => [z, 1.0 - x],
+                };
+                let pos = [x, y, z];
+                (
+                    std::array::from_fn(|i| pos[i] + f64::from(origin[i])),
+                    &entity.nbt,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Places `structure`'s blocks with its corner at `origin`, mirrored and then turned around that
+/// corner (like `/place template`). `set_block` is called with each block's world position, state,
+/// and block entity data.
+pub fn place_structure(
+    structure: &Structure,
+    origin: [i32; 3],
+    rotation: Rotation,
+    mirror: Mirror,
+    mut set_block: impl FnMut([i32; 3], &BlockState, Option<&CompoundNbt<'static>>),
+) {
+    let palette: Vec<_> = structure
+        .palette
+        .iter()
+        .map(|state| state.mirrored(mirror).rotated(rotation))
+        .collect();
+    for block in &structure.blocks {
+        let pos = rotation.apply(mirror.apply(block.pos));
+        set_block(
+            std::array::from_fn(|i| pos[i] + origin[i]),
+            &palette[block.state],
+            block.nbt.as_ref(),
+        );
+    }
+}
+
+fn int3(c: &CompoundNbt<'_>, key: &str) -> io::Result<[i32; 3]> {
+    match c.get(key) {
+        Some(Nbt::List(NbtList::Int(list))) => {
+            list[..].try_into().map_err(|_| invalid("expected 3 ints"))
+        }
+        _ => Err(invalid("missing block position")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn place_rotated() {
+        let mut chest = CompoundNbt::new("");
+        chest.set("id", Nbt::String("minecraft:chest".into()));
+        let mut sheep = CompoundNbt::new("");
+        sheep.set("id", Nbt::String("minecraft:sheep".into()));
+        let structure = Structure {
+            data_version: 3578,
+            size: [2, 1, 1],
+            palette: vec![
+                BlockState::new("minecraft:stone"),
+                BlockState::new("minecraft:chest")
+                    .with("facing", "north")
+                    .with("type", "single"),
+            ],
+            blocks: vec![
+                StructureBlock {
+                    pos: [0, 0, 0],
+                    state: 0,
+                    nbt: None,
+                },
+                StructureBlock {
+                    pos: [1, 0, 0],
+                    state: 1,
+                    nbt: Some(chest),
+                },
+            ],
+            entities: vec![StructureEntity {
+                pos: [1.5, 0.0, 0.5],
+                block_pos: [1, 0, 0],
+                nbt: sheep,
+            }],
+        };
+
+        let path = std::env::temp_dir().join(format!("libmc-structure-{}.nbt", std::process::id()));
+        structure.save(&path).unwrap();
+        assert_eq!(Structure::load(&path).unwrap(), structure);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut placed = Vec::new();
+        place_structure(
+            &structure,
+            [10, 64, 10],
+            Rotation::Clockwise90,
+            Mirror::None,
+            |pos, state, nbt| placed.push((pos, state.clone(), nbt.is_some())),
+        );
+        assert_eq!(placed[0].0, [10, 64, 10]);
+        // east of the corner turns into south of it
+        assert_eq!(placed[1].0, [10, 64, 11]);
+        assert_eq!(placed[1].1.get("facing"), Some("east"));
+        assert!(placed[1].2);
+        // the sheep stays in the middle of the chest's block
+        let entities = structure.placed_entities([10, 64, 10], Rotation::Clockwise90, Mirror::None);
+        assert_eq!(entities[0].0, [10.5, 64.0, 11.5]);
+
+        let sign = BlockState::new("minecraft:oak_sign").with("rotation", "4");
+        assert_eq!(
+            sign.rotated(Rotation::Clockwise180).get("rotation"),
+            Some("12")
+        );
+        assert_eq!(sign.mirrored(Mirror::LeftRight).get("rotation"), Some("4"));
+        assert_eq!(sign.mirrored(Mirror::FrontBack).get("rotation"), Some("12"));
+    }
+}
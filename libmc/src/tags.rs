@@ -0,0 +1,253 @@
+use crate::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Vanilla's tag folders (under `data/<namespace>/tags/`), and the registries their tags are for
+const TAG_FOLDERS: [(&str, &str); 5] = [
+    ("blocks", "minecraft:block"),
+    ("items", "minecraft:item"),
+    ("fluids", "minecraft:fluid"),
+    ("entity_types", "minecraft:entity_type"),
+    ("game_events", "minecraft:game_event"),
+];
+
+/// The tags of one registry as sent in Update Tags: each tag's name and the IDs of its entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryTags {
+    /// e.g. `minecraft:block`
+    pub registry: String,
+    pub tags: Vec<(String, Vec<i32>)>,
+}
+
+/// Named groups of blocks, items, etc. (e.g. `minecraft:climbable`), which clients need for
+/// movement prediction and which a lot of game logic checks against.
+///
+/// Tags are kept by identifier, and can include other tags (written `#minecraft:logs`) like in
+/// data packs. They're only turned into IDs for sending, with `resolve()`.
+#[derive(Debug, Clone, Default)]
+pub struct TagRegistry {
+    /// registry -> tag -> values
+    tags: HashMap<String, HashMap<String, Vec<String>>>,
+}
+
+impl TagRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `values` to a tag (creating it if needed), or replaces its values if `replace`
+    pub fn add(&mut self, registry: &str, tag: &str, values: Vec<String>, replace: bool) {
+        let existing = self
+            .tags
+            .entry(registry.to_owned())
+            .or_default()
+            .entry(tag.to_owned())
+            .or_default();
+        if replace {
+            existing.clear();
+        }
+        for v in values {
+            if !existing.contains(&v) {
+                existing.push(v);
+            }
+        }
+    }
+
+    /// Adds a tag from its JSON file in a data pack, e.g. `data/minecraft/tags/blocks/climbable.json`
+    pub fn add_json(&mut self, registry: &str, tag: &str, json: &str) -> Result<(), RegistryError> {
+        let json = Json::parse(json).map_err(RegistryError::Json)?;
+        let values = json
+            .get("values")
+            .and_then(Json::as_array)
+            .ok_or(RegistryError::BadReport("tag without values"))?
+            .iter()
+            .map(|v| {
+                // optional entries are objects
+                v.as_str()
+                    .or_else(|| v.get("id").and_then(Json::as_str))
+                    .map(str::to_owned)
+                    .ok_or(RegistryError::BadReport("bad tag value"))
+            })
+            .collect::<Result<_, _>>()?;
+        let replace = json.get("replace").and_then(Json::as_bool) == Some(true);
+        self.add(registry, tag, values, replace);
+        Ok(())
+    }
+
+    /// Loads the block, item, fluid, entity type and game event tags of a data pack's `data`
+    /// folder, such as the one the vanilla server jar extracts
+    pub fn load_datapack(&mut self, data_dir: impl AsRef<Path>) -> Result<(), RegistryError> {
+        for namespace in fs::read_dir(data_dir)? {
+            let namespace = namespace?;
+            let ns = namespace.file_name().to_string_lossy().into_owned();
+            for (folder, registry) in TAG_FOLDERS {
+                let dir = namespace.path().join("tags").join(folder);
+                if dir.is_dir() {
+                    self.load_tag_dir(registry, &ns, &dir, "")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Tags can be in subfolders, which become part of their name
+    fn load_tag_dir(
+        &mut self,
+        registry: &str,
+        namespace: &str,
+        dir: &Path,
+        prefix: &str,
+    ) -> Result<(), RegistryError> {
+        let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+        // so that loading is deterministic
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            if path.is_dir() {
+                self.load_tag_dir(registry, namespace, &path, &format!("{prefix}{name}/"))?;
+            } else if let Some(name) = name.strip_suffix(".json") {
+                let tag = format!("{namespace}:{prefix}{name}");
+                self.add_json(registry, &tag, &fs::read_to_string(&path)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The tags of `registry`, in no particular order
+    pub fn tags(&self, registry: &str) -> impl Iterator<Item = &str> {
+        self.tags
+            .get(registry)
+            .into_iter()
+            .flat_map(|tags| tags.keys().map(String::as_str))
+    }
+
+    /// Everything in a tag, with the tags it includes expanded. Empty if there's no such tag.
+    pub fn entries(&self, registry: &str, tag: &str) -> Vec<&str> {
+        let mut out = Vec::new();
+        let mut seen = HashSet::new();
+        self.expand(registry, tag, &mut out, &mut seen);
+        out
+    }
+
+    fn expand<'s: 't, 't>(
+        &'s self,
+        registry: &str,
+        tag: &'t str,
+        out: &mut Vec<&'s str>,
+        seen: &mut HashSet<&'t str>,
+    ) {
+        // tags that include each other are only expanded once
+        if !seen.insert(tag) {
+            return;
+        }
+        let Some(values) = self.tags.get(registry).and_then(|tags| tags.get(tag)) else {
+            return;
+        };
+        for v in values {
+            match v.strip_prefix('#') {
+                Some(inner) => self.expand(registry, inner, out, seen),
+                None if !out.contains(&v.as_str()) => out.push(v),
+                None => {}
+            }
+        }
+    }
+
+    /// Whether `entry` (e.g. `minecraft:ladder`) is in a tag (e.g. `minecraft:climbable`)
+    pub fn contains(&self, registry: &str, tag: &str, entry: &str) -> bool {
+        self.entries(registry, tag).contains(&entry)
+    }
+
+    /// `registry`'s tags with their entries turned into IDs by `id`, for Update Tags.
+    /// Entries `id` doesn't know are left out.
+    pub fn resolve(&self, registry: &str, id: impl Fn(&str) -> Option<i32>) -> RegistryTags {
+        let mut names: Vec<&str> = self.tags(registry).collect();
+        names.sort_unstable();
+        let tags = names
+            .into_iter()
+            .map(|tag| {
+                let ids = self
+                    .entries(registry, tag)
+                    .into_iter()
+                    .filter_map(&id)
+                    .collect();
+                (tag.to_owned(), ids)
+            })
+            .collect();
+        RegistryTags {
+            registry: registry.to_owned(),
+            tags,
+        }
+    }
+
+    /// The item tags, with IDs from `items`
+    pub fn item_tags(&self, items: &ItemRegistry) -> RegistryTags {
+        self.resolve("minecraft:item", |name| items.id(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datapack() {
+        let data = std::env::temp_dir().join(format!("libmc-tags-{}", std::process::id()));
+        let blocks = data.join("minecraft/tags/blocks");
+        fs::create_dir_all(blocks.join("mineable")).unwrap();
+        fs::write(
+            blocks.join("climbable.json"),
+            r##"{"values": ["minecraft:ladder", "#minecraft:vines", {"id": "test:rope", "required": false}]}"##,
+        )
+        .unwrap();
+        fs::write(
+            blocks.join("vines.json"),
+            r#"{"values": ["minecraft:vine", "minecraft:twisting_vines"]}"#,
+        )
+        .unwrap();
+        fs::write(
+            blocks.join("mineable/axe.json"),
+            r#"{"values": ["minecraft:oak_log"]}"#,
+        )
+        .unwrap();
+
+        let mut tags = TagRegistry::new();
+        let res = tags.load_datapack(&data);
+        fs::remove_dir_all(&data).unwrap();
+        res.unwrap();
+
+        assert!(tags.contains("minecraft:block", "minecraft:climbable", "minecraft:vine"));
+        assert!(!tags.contains("minecraft:block", "minecraft:vines", "minecraft:ladder"));
+        assert_eq!(
+            tags.entries("minecraft:block", "minecraft:mineable/axe"),
+            ["minecraft:oak_log"]
+        );
+
+        let ids = ["minecraft:ladder", "minecraft:vine", "minecraft:oak_log"];
+        let resolved = tags.resolve("minecraft:block", |name| {
+            ids.iter().position(|&n| n == name).map(|i| i as i32)
+        });
+        assert_eq!(
+            resolved.tags,
+            [
+                ("minecraft:climbable".to_owned(), vec![0, 1]),
+                ("minecraft:mineable/axe".to_owned(), vec![2]),
+                ("minecraft:vines".to_owned(), vec![1]),
+            ]
+        );
+
+        let mut packet = encode_packet(
+            OutPacket::UpdateTags {
+                registries: &[resolved],
+            },
+            PROTOCOL_VERSION,
+        );
+        assert_eq!(packet[..2], [0x70, 1]);
+        packet = encode_packet(
+            OutPacket::UpdateTagsConfig { registries: &[] },
+            PROTOCOL_VERSION,
+        );
+        assert_eq!(packet, [0x08, 0]);
+    }
+}
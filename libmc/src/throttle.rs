@@ -0,0 +1,145 @@
+use crate::*;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Caps on connections, enforced when a client starts logging in (status pings aren't limited)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// Most clients logging in or logged in at once
+    pub max_connections: Option<usize>,
+    /// Most clients logging in or logged in at once from one IP
+    pub max_per_ip: Option<usize>,
+    /// Most login attempts from one IP within `login_window`
+    pub max_logins_per_ip: Option<usize>,
+    pub login_window: Duration,
+}
+
+impl Default for ConnectionLimits {
+    /// No limits
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            max_per_ip: None,
+            max_logins_per_ip: None,
+            login_window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Why a login was refused
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoginRejection {
+    ServerFull,
+    TooManyFromIp,
+    TooManyAttempts,
+}
+
+impl LoginRejection {
+    /// What the client is told
+    pub fn message(self) -> TextComponent {
+        TextComponent::text(match self {
+            LoginRejection::ServerFull => "The server is full!",
+            LoginRejection::TooManyFromIp => "Too many connections from your IP address",
+            LoginRejection::TooManyAttempts => {
+                "Connection throttled! Please wait before reconnecting."
+            }
+        })
+    }
+}
+
+/// Applies `ConnectionLimits` to login attempts
+#[derive(Debug)]
+pub struct LoginThrottle<C: Clock = SystemClock> {
+    limits: ConnectionLimits,
+    /// Recent login attempts per IP, oldest first
+    attempts: HashMap<IpAddr, VecDeque<Instant>>,
+    clock: C,
+}
+
+impl LoginThrottle {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self::with_clock(limits, SystemClock)
+    }
+}
+
+impl<C: Clock> LoginThrottle<C> {
+    pub fn with_clock(limits: ConnectionLimits, clock: C) -> Self {
+        Self {
+            limits,
+            attempts: HashMap::new(),
+            clock,
+        }
+    }
+
+    pub fn limits(&self) -> &ConnectionLimits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: ConnectionLimits) {
+        self.limits = limits;
+    }
+
+    /// Records a login attempt from `ip`. `total` and `from_ip` count the clients that are already
+    /// logging in or logged in (not including this one). Rejected attempts count too.
+    pub fn check(
+        &mut self,
+        ip: IpAddr,
+        total: usize,
+        from_ip: usize,
+    ) -> Result<(), LoginRejection> {
+        let now = self.clock.now();
+        let window = self.limits.login_window;
+        self.attempts
+            .retain(|_, times| times.back().is_some_and(|&t| now - t < window));
+        let times = self.attempts.entry(ip).or_default();
+        while times.front().is_some_and(|&t| now - t >= window) {
+            times.pop_front();
+        }
+        times.push_back(now);
+
+        if self
+            .limits
+            .max_logins_per_ip
+            .is_some_and(|max| times.len() > max)
+        {
+            Err(LoginRejection::TooManyAttempts)
+        } else if self.limits.max_connections.is_some_and(|max| total >= max) {
+            Err(LoginRejection::ServerFull)
+        } else if self.limits.max_per_ip.is_some_and(|max| from_ip >= max) {
+            Err(LoginRejection::TooManyFromIp)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let clock = ManualClock::new();
+        let mut t = LoginThrottle::with_clock(
+            ConnectionLimits {
+                max_connections: Some(10),
+                max_per_ip: Some(2),
+                max_logins_per_ip: Some(3),
+                login_window: Duration::from_secs(10),
+            },
+            clock.clone(),
+        );
+        let a: IpAddr = [10, 0, 0, 1].into();
+        let b: IpAddr = [10, 0, 0, 2].into();
+
+        assert_eq!(t.check(a, 0, 0), Ok(()));
+        assert_eq!(t.check(a, 1, 1), Ok(()));
+        assert_eq!(t.check(a, 2, 2), Err(LoginRejection::TooManyFromIp));
+        assert_eq!(t.check(a, 2, 1), Err(LoginRejection::TooManyAttempts));
+        assert_eq!(t.check(b, 10, 0), Err(LoginRejection::ServerFull));
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(t.check(a, 2, 1), Ok(()));
+    }
+}
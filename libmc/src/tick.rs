@@ -0,0 +1,142 @@
+use crate::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const TICKS_PER_SECOND: u32 = 20;
+pub const TICK_DURATION: Duration = Duration::from_millis(1000 / TICKS_PER_SECOND as u64);
+
+/// When the server falls further behind than this, the missed ticks are skipped instead of caught up on
+const MAX_CATCH_UP: Duration = Duration::from_secs(2);
+
+/// How many recent ticks the TPS is averaged over
+const TPS_WINDOW: usize = 5 * TICKS_PER_SECOND as usize;
+
+/// Keeps time for a fixed-rate (20 TPS) game loop.
+///
+/// Ticks that are late run back-to-back until the loop has caught up, unless it is
+/// so far behind that catching up would take too long, in which case those ticks are skipped.
+#[derive(Debug)]
+pub struct TickLoop<C: Clock = SystemClock> {
+    clock: C,
+    next_tick: Instant,
+    tick: u64,
+    /// start times of the most recent ticks
+    recent: VecDeque<Instant>,
+    /// How far behind the loop was when it last skipped ticks, until `take_skipped()`
+    skipped: Option<Duration>,
+}
+
+impl Default for TickLoop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TickLoop {
+    /// The first tick is due immediately
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<C: Clock> TickLoop<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            next_tick: clock.now(),
+            clock,
+            tick: 0,
+            recent: VecDeque::with_capacity(TPS_WINDOW),
+            skipped: None,
+        }
+    }
+
+    /// How many ticks have been run
+    pub fn ticks_run(&self) -> u64 {
+        self.tick
+    }
+
+    /// How long until the next tick is due (zero if it's overdue)
+    pub fn time_until_next_tick(&self) -> Duration {
+        self.next_tick.saturating_duration_since(self.clock.now())
+    }
+
+    /// If a tick is due, returns its number and schedules the next one
+    pub fn poll_tick(&mut self) -> Option<u64> {
+        let now = self.clock.now();
+        if now < self.next_tick {
+            return None;
+        }
+
+        let behind = now - self.next_tick;
+        if behind > MAX_CATCH_UP {
+            self.skipped = Some(behind);
+            self.next_tick = now;
+        }
+        self.next_tick += TICK_DURATION;
+
+        if self.recent.len() == TPS_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+
+        let tick = self.tick;
+        self.tick += 1;
+        Some(tick)
+    }
+
+    /// How far behind the loop was if it skipped ticks since the last call, like when vanilla
+    /// logs "Can't keep up!"
+    pub fn take_skipped(&mut self) -> Option<Duration> {
+        self.skipped.take()
+    }
+
+    /// Average ticks per second over the last few seconds
+    pub fn tps(&self) -> f64 {
+        let (Some(first), Some(last)) = (self.recent.front(), self.recent.back()) else {
+            return TICKS_PER_SECOND.into();
+        };
+        let elapsed = last.duration_since(*first).as_secs_f64();
+        if elapsed == 0.0 {
+            return TICKS_PER_SECOND.into();
+        }
+        // ticks run back-to-back while catching up can push this over 20
+        ((self.recent.len() - 1) as f64 / elapsed).min(TICKS_PER_SECOND.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_tick_immediate() {
+        let mut t = TickLoop::new();
+        assert_eq!(t.poll_tick(), Some(0));
+        assert_eq!(t.poll_tick(), None, "next tick isn't due yet");
+        assert!(t.time_until_next_tick() <= TICK_DURATION);
+        assert_eq!(t.ticks_run(), 1);
+        assert_eq!(t.tps(), 20.0);
+    }
+
+    #[test]
+    fn catch_up_and_skip() {
+        let clock = ManualClock::new();
+        let mut t = TickLoop::with_clock(clock.clone());
+        assert_eq!(t.poll_tick(), Some(0));
+
+        clock.advance(TICK_DURATION * 3);
+        assert_eq!(t.poll_tick(), Some(1));
+        assert_eq!(t.poll_tick(), Some(2));
+        assert_eq!(t.poll_tick(), Some(3));
+        assert_eq!(t.poll_tick(), None);
+        assert_eq!(t.time_until_next_tick(), TICK_DURATION);
+
+        // too far behind to catch up
+        assert_eq!(t.take_skipped(), None);
+        clock.advance(MAX_CATCH_UP * 2);
+        assert_eq!(t.poll_tick(), Some(4));
+        assert_eq!(t.poll_tick(), None);
+        assert_eq!(t.take_skipped(), Some(MAX_CATCH_UP * 2 - TICK_DURATION));
+        assert_eq!(t.take_skipped(), None);
+    }
+}
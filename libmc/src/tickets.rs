@@ -0,0 +1,214 @@
+use crate::*;
+use std::collections::HashMap;
+
+/// Levels at or below this are entity ticking, as in vanilla
+const ENTITY_TICKING_LEVEL: u32 = 31;
+/// The highest level a chunk is loaded at
+const BORDER_LEVEL: u32 = 33;
+
+/// How much of the game runs in a loaded chunk
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChunkLevel {
+    /// Loaded, so it can be read and written, but nothing in it runs
+    Border,
+    /// Blocks tick (random ticks, scheduled ticks, redstone), entities don't
+    BlockTicking,
+    /// Everything runs
+    EntityTicking,
+}
+
+impl ChunkLevel {
+    fn from_level(level: u32) -> Option<Self> {
+        match level {
+            0..=ENTITY_TICKING_LEVEL => Some(ChunkLevel::EntityTicking),
+            32 => Some(ChunkLevel::BlockTicking),
+            BORDER_LEVEL => Some(ChunkLevel::Border),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies a ticket added with `ChunkTickets::add_ticket()`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TicketId(u64);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum TicketOwner {
+    Player(ClientID),
+    Other(TicketId),
+}
+
+/// A chunk whose level changed in a `ChunkTickets::update()`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChunkLevelChange {
+    pub chunk: ChunkPos,
+    /// None if it wasn't loaded
+    pub from: Option<ChunkLevel>,
+    /// None if it should be unloaded
+    pub to: Option<ChunkLevel>,
+}
+
+/// Decides which chunks are simulated, separately from which are sent to clients (see
+/// `ChunkSendQueue`), with tickets like vanilla's.
+///
+/// A ticket at a chunk gives it a level, and each chunk further away (in either axis) a level one
+/// higher. A chunk's level is the lowest any ticket gives it, and decides its `ChunkLevel`.
+/// Players get a ticket that makes chunks within their simulation distance entity ticking, with
+/// a ring of block ticking and a ring of border chunks around them.
+#[derive(Debug, Default)]
+pub struct ChunkTickets {
+    /// (center, level)
+    tickets: HashMap<TicketOwner, (ChunkPos, u32)>,
+    next_id: u64,
+    levels: HashMap<ChunkPos, u32>,
+    dirty: bool,
+}
+
+impl ChunkTickets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets (or moves) a player's ticket
+    pub fn set_player(&mut self, cid: ClientID, chunk: ChunkPos, simulation_distance: u32) {
+        let level = ENTITY_TICKING_LEVEL.saturating_sub(simulation_distance);
+        self.set(TicketOwner::Player(cid), chunk, level);
+    }
+
+    pub fn remove_player(&mut self, cid: ClientID) {
+        self.dirty |= self.tickets.remove(&TicketOwner::Player(cid)).is_some();
+    }
+
+    /// Adds a ticket of some other kind, e.g. to keep the spawn chunks loaded.
+    /// `chunk` gets `level` (lower is more), like vanilla's ticket levels.
+    pub fn add_ticket(&mut self, chunk: ChunkPos, level: u32) -> TicketId {
+        let id = TicketId(self.next_id);
+        self.next_id += 1;
+        self.set(TicketOwner::Other(id), chunk, level);
+        id
+    }
+
+    pub fn remove_ticket(&mut self, id: TicketId) {
+        self.dirty |= self.tickets.remove(&TicketOwner::Other(id)).is_some();
+    }
+
+    fn set(&mut self, owner: TicketOwner, chunk: ChunkPos, level: u32) {
+        if self.tickets.insert(owner, (chunk, level)) != Some((chunk, level)) {
+            self.dirty = true;
+        }
+    }
+
+    /// Applies the ticket changes since the last update, and returns the chunks that changed level
+    /// (to load newly loaded chunks and unload the ones that are no longer needed)
+    pub fn update(&mut self) -> Vec<ChunkLevelChange> {
+        if !std::mem::take(&mut self.dirty) {
+            return Vec::new();
+        }
+        let mut levels: HashMap<ChunkPos, u32> = HashMap::new();
+        for &(center, level) in self.tickets.values() {
+            let Some(radius) = BORDER_LEVEL.checked_sub(level) else {
+                continue;
+            };
+            for chunk in center.square(radius as i32) {
+                let l = levels.entry(chunk).or_insert(u32::MAX);
+                *l = (*l).min(level + chunk.chebyshev_distance(center));
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (&chunk, &old) in &self.levels {
+            let from = ChunkLevel::from_level(old);
+            let to = levels.get(&chunk).and_then(|&l| ChunkLevel::from_level(l));
+            if from != to {
+                changes.push(ChunkLevelChange { chunk, from, to });
+            }
+        }
+        for (&chunk, &new) in &levels {
+            if !self.levels.contains_key(&chunk) {
+                let to = ChunkLevel::from_level(new);
+                changes.push(ChunkLevelChange {
+                    chunk,
+                    from: None,
+                    to,
+                });
+            }
+        }
+        self.levels = levels;
+        changes
+    }
+
+    /// As of the last `update()`. None if the chunk doesn't need to be loaded.
+    pub fn level(&self, chunk: ChunkPos) -> Option<ChunkLevel> {
+        self.levels
+            .get(&chunk)
+            .and_then(|&l| ChunkLevel::from_level(l))
+    }
+
+    /// Whether blocks in `chunk` should tick
+    pub fn is_ticking(&self, chunk: ChunkPos) -> bool {
+        self.level(chunk) >= Some(ChunkLevel::BlockTicking)
+    }
+
+    /// Whether entities in `chunk` should tick
+    pub fn is_entity_ticking(&self, chunk: ChunkPos) -> bool {
+        self.level(chunk) == Some(ChunkLevel::EntityTicking)
+    }
+
+    /// The chunks at `level` or above, in no particular order
+    pub fn chunks_at(&self, level: ChunkLevel) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.levels
+            .iter()
+            .filter(move |&(_, &l)| ChunkLevel::from_level(l) >= Some(level))
+            .map(|(&chunk, _)| chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn player_tickets() {
+        let mut tickets = ChunkTickets::new();
+        let cid = ClientID::new(0, 0);
+        tickets.set_player(cid, ChunkPos::new(0, 0), 2);
+        let changes = tickets.update();
+        // entity ticking within 2, then a ring each of block ticking and border
+        assert_eq!(changes.len(), 9 * 9);
+        assert!(tickets.is_entity_ticking(ChunkPos::new(2, -2)));
+        assert_eq!(
+            tickets.level(ChunkPos::new(3, 0)),
+            Some(ChunkLevel::BlockTicking)
+        );
+        assert_eq!(
+            tickets.level(ChunkPos::new(0, -4)),
+            Some(ChunkLevel::Border)
+        );
+        assert_eq!(tickets.level(ChunkPos::new(5, 0)), None);
+        assert_eq!(tickets.chunks_at(ChunkLevel::BlockTicking).count(), 7 * 7);
+        assert!(tickets.update().is_empty(), "nothing changed");
+
+        tickets.set_player(cid, ChunkPos::new(1, 0), 2);
+        let mut changes = tickets.update();
+        changes.sort_by_key(|c| c.chunk);
+        assert!(changes
+            .iter()
+            .any(|c| c.chunk == ChunkPos::new(5, 0) && c.from.is_none()));
+        assert!(
+            changes.iter().all(|c| c.chunk.x != 0),
+            "same distance as before"
+        );
+        assert_eq!(
+            changes[0],
+            ChunkLevelChange {
+                chunk: ChunkPos::new(-4, -4),
+                from: Some(ChunkLevel::Border),
+                to: None,
+            }
+        );
+
+        tickets.remove_player(cid);
+        assert!(tickets.update().iter().all(|c| c.to.is_none()));
+        assert_eq!(tickets.chunks_at(ChunkLevel::Border).count(), 0);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::*;
+use std::collections::{HashMap, HashSet};
+
+/// A client that entities are tracked for
+#[derive(Debug)]
+struct Viewer {
+    /// The viewer's own entity, which it is never sent, and which it follows around
+    own: Option<EntityHandle>,
+    pos: Vec3,
+    /// In chunks
+    view_distance: u32,
+    visible: HashSet<i32>,
+    /// Entities that left its sight this update, to remove from the client
+    removed: Vec<i32>,
+    /// Entities that came into sight this update, to spawn on the client
+    entered: Vec<EntityHandle>,
+}
+
+/// Decides which entities each client can see, and sends them only what they need: entities are
+/// spawned on a client when they come within range of it and removed when they leave it, and
+/// their movement and other changes only go to the clients that can see them. Game code just
+/// changes the entities in an `EntityStore`.
+///
+/// An entity is in range of a client when it's within the entity type's tracking range (see
+/// `EntityType::tracking_range()`) and the client's view distance, horizontally.
+///
+/// Call `tick()` once per tick from `Server::tick()`, instead of `EntityStore::tick()`.
+#[derive(Debug, Default)]
+pub struct EntityTracker {
+    viewers: HashMap<ClientID, Viewer>,
+    /// Tracking ranges in blocks that replace the defaults
+    ranges: HashMap<EntityType, f64>,
+}
+
+impl EntityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracks entities of type `kind` `blocks` away, instead of its default tracking range
+    pub fn set_range(&mut self, kind: EntityType, blocks: f64) {
+        self.ranges.insert(kind, blocks);
+    }
+
+    /// How far away entities of type `kind` are tracked, in blocks
+    pub fn range(&self, kind: EntityType) -> f64 {
+        range_in(&self.ranges, kind)
+    }
+
+    /// Starts tracking entities for `cid`, which sees them from `pos`. If it has an entity of its
+    /// own (`own`, e.g. its player), it isn't sent that, and sees from wherever that is instead.
+    pub fn add_viewer(
+        &mut self,
+        cid: ClientID,
+        own: Option<EntityHandle>,
+        pos: Vec3,
+        view_distance: u32,
+    ) {
+        self.viewers.insert(
+            cid,
+            Viewer {
+                own,
+                pos,
+                view_distance,
+                visible: HashSet::new(),
+                removed: Vec::new(),
+                entered: Vec::new(),
+            },
+        );
+    }
+
+    /// Moves a viewer that has no entity of its own
+    pub fn move_viewer(&mut self, cid: ClientID, pos: Vec3) {
+        if let Some(viewer) = self.viewers.get_mut(&cid) {
+            viewer.pos = pos;
+        }
+    }
+
+    pub fn set_view_distance(&mut self, cid: ClientID, view_distance: u32) {
+        if let Some(viewer) = self.viewers.get_mut(&cid) {
+            viewer.view_distance = view_distance;
+        }
+    }
+
+    /// Stops tracking entities for `cid`, e.g. when it disconnects. Nothing is sent to it.
+    pub fn remove_viewer(&mut self, cid: ClientID) -> bool {
+        self.viewers.remove(&cid).is_some()
+    }
+
+    /// Whether `cid` has been sent the entity `entity_id`
+    pub fn can_see(&self, cid: ClientID, entity_id: i32) -> bool {
+        self.viewers
+            .get(&cid)
+            .is_some_and(|viewer| viewer.visible.contains(&entity_id))
+    }
+
+    /// The clients that have been sent the entity `entity_id`, e.g. to send them its animations
+    pub fn viewers_of(&self, entity_id: i32) -> impl Iterator<Item = ClientID> + '_ {
+        self.viewers
+            .iter()
+            .filter(move |(_, viewer)| viewer.visible.contains(&entity_id))
+            .map(|(&cid, _)| cid)
+    }
+
+    /// Ticks `store` (see `EntityStore::tick()`), and gives the packets for each viewer whose
+    /// entities changed: removals first, then changes to the entities it could already see, then
+    /// the entities that came into sight.
+    pub fn update<'a>(
+        &'a mut self,
+        store: &'a mut EntityStore,
+    ) -> Vec<(ClientID, Vec<OutPacket<'a>>)> {
+        let changes = store.advance();
+        let store = &*store;
+
+        let ranges = &self.ranges;
+        for viewer in self.viewers.values_mut() {
+            viewer.removed.clear();
+            viewer.entered.clear();
+            for &id in store.removed_ids() {
+                if viewer.visible.remove(&id) {
+                    viewer.removed.push(id);
+                }
+            }
+            if let Some(pos) = viewer.own.and_then(|h| store.position(h)) {
+                viewer.pos = pos;
+            }
+            let view_distance = viewer.view_distance as f64 * 16.0;
+            for h in store.iter() {
+                let (Some(id), Some(kind), Some(pos)) =
+                    (store.entity_id(h), store.kind(h), store.position(h))
+                else {
+                    continue;
+                };
+                let range = range_in(ranges, kind).min(view_distance);
+                let (dx, dz) = (pos.x - viewer.pos.x, pos.z - viewer.pos.z);
+                let in_range = viewer.own != Some(h) && dx * dx + dz * dz <= range * range;
+                if in_range && !viewer.visible.contains(&id) {
+                    viewer.entered.push(h);
+                } else if !in_range && viewer.visible.remove(&id) {
+                    viewer.removed.push(id);
+                }
+            }
+        }
+
+        let changed = store.packets(&changes);
+        let mut out = Vec::new();
+        for (&cid, viewer) in &mut self.viewers {
+            let mut packets = Vec::new();
+            if !viewer.removed.is_empty() {
+                packets.push(OutPacket::RemoveEntities {
+                    entity_ids: &viewer.removed,
+                });
+            }
+            // entities that just spawned, or were just removed, aren't in the visible set yet
+            packets.extend(
+                changed
+                    .iter()
+                    .filter(|p| entity_of(p).is_some_and(|id| viewer.visible.contains(&id)))
+                    .cloned(),
+            );
+            for &h in &viewer.entered {
+                viewer.visible.extend(store.entity_id(h));
+                packets.extend(store.spawn_packets(h));
+            }
+            if !packets.is_empty() {
+                out.push((cid, packets));
+            }
+        }
+        out
+    }
+
+    /// Like `update()`, sending each viewer its packets. Viewers that have disconnected stop
+    /// being tracked.
+    pub fn tick(&mut self, store: &mut EntityStore, handle: &ServerHandle) {
+        for (cid, packets) in self.update(store) {
+            handle.send_all(cid, packets);
+        }
+        self.viewers.retain(|&cid, _| handle.is_connected(cid));
+    }
+}
+
+fn range_in(ranges: &HashMap<EntityType, f64>, kind: EntityType) -> f64 {
+    ranges
+        .get(&kind)
+        .copied()
+        .unwrap_or(kind.tracking_range() as f64 * 16.0)
+}
+
+/// The entity a packet from `EntityStore::packets()` is about, if it's about only one
+fn entity_of(packet: &OutPacket) -> Option<i32> {
+    match *packet {
+        OutPacket::SpawnEntity { entity_id, .. }
+        | OutPacket::TeleportEntity { entity_id, .. }
+        | OutPacket::UpdateEntityRotation { entity_id, .. }
+        | OutPacket::UpdateEntityPosition { entity_id, .. }
+        | OutPacket::UpdateEntityPositionAndRotation { entity_id, .. }
+        | OutPacket::SetHeadRotation { entity_id, .. }
+        | OutPacket::SetEntityVelocity { entity_id, .. }
+        | OutPacket::SetEntityMetadata { entity_id, .. }
+        | OutPacket::SetEquipment { entity_id, .. } => Some(entity_id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawns_and_removes_by_range() {
+        let near = ClientID::new(0, 0);
+        let far = ClientID::new(1, 0);
+        let mut store = EntityStore::new(1);
+        let player = store.spawn(EntityType::Player, Uuid(1), Vec3::from([0.0, 64.0, 0.0]));
+        let zombie = store.spawn(EntityType::Zombie, Uuid(2), Vec3::from([100.0, 64.0, 0.0]));
+        let mut tracker = EntityTracker::new();
+        assert_eq!(tracker.range(EntityType::Zombie), 128.0);
+        tracker.add_viewer(near, Some(player), Vec3::from([0.0, 64.0, 0.0]), 10);
+        tracker.add_viewer(far, None, Vec3::from([1000.0, 64.0, 0.0]), 10);
+
+        // the zombie is in range of the player, who isn't sent itself
+        let mut out = tracker.update(&mut store);
+        assert_eq!(out.len(), 1);
+        let (cid, packets) = out.pop().unwrap();
+        assert_eq!(cid, near);
+        assert!(matches!(
+            packets[..],
+            [OutPacket::SpawnEntity {
+                entity_id: 2,
+                entity_type: EntityType::Zombie,
+                ..
+            }]
+        ));
+        assert!(tracker.can_see(near, 2) && !tracker.can_see(near, 1));
+        assert_eq!(tracker.viewers_of(2).collect::<Vec<_>>(), [near]);
+
+        // moves only go to the clients that can see the entity
+        store.set_position(zombie, Vec3::from([101.0, 64.0, 0.0]));
+        let out = tracker.update(&mut store);
+        assert!(matches!(
+            out[..],
+            [(
+                cid,
+                ref packets,
+            )] if cid == near && matches!(packets[..], [OutPacket::UpdateEntityPosition { entity_id: 2, .. }])
+        ));
+
+        // the zombie walks from one client to the other
+        store.set_position(zombie, Vec3::from([950.0, 64.0, 0.0]));
+        let mut out = tracker.update(&mut store);
+        out.sort_by_key(|(cid, _)| cid.index());
+        assert!(matches!(
+            out[0].1[..],
+            [OutPacket::RemoveEntities { entity_ids: [2] }]
+        ));
+        assert!(matches!(
+            out[1].1[..],
+            [OutPacket::SpawnEntity { entity_id: 2, .. }]
+        ));
+        assert!(tracker.can_see(far, 2) && !tracker.can_see(near, 2));
+
+        // players are seen from further away, but not past the view distance
+        store.set_position(player, Vec3::from([600.0, 64.0, 0.0]));
+        tracker.update(&mut store);
+        assert!(!tracker.can_see(far, 1));
+        tracker.set_view_distance(far, 32);
+        assert_eq!(tracker.update(&mut store).len(), 1);
+        assert!(tracker.can_see(far, 1));
+
+        store.despawn(zombie);
+        let out = tracker.update(&mut store);
+        assert!(matches!(
+            out[..],
+            [(_, ref packets)] if matches!(packets[..], [OutPacket::RemoveEntities { entity_ids: [2] }])
+        ));
+        assert_eq!(tracker.viewers_of(2).count(), 0);
+        assert!(tracker.remove_viewer(far));
+    }
+}
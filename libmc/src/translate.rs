@@ -0,0 +1,321 @@
+//! Protocol translation: lets clients on other protocol versions join by rewriting packets
+//! between their version and the one libmc speaks (`PROTOCOL_VERSION`), in the spirit of ViaVersion.
+
+use crate::*;
+use std::fmt::Debug;
+
+/// Rewrites packets between one client protocol version and `PROTOCOL_VERSION`.
+///
+/// Packets are passed as their ID followed by their body, without the length prefix.
+/// Each method pushes zero or more packets to `out`, so packets can be dropped, split or made up.
+/// `state` is the connection's state in libmc's protocol.
+pub trait ProtocolTranslator: Debug {
+    /// The protocol version of the clients this translates for
+    fn client_version(&self) -> i32;
+
+    /// Rewrites a packet from the client into libmc's version. Clients can send anything, so this
+    /// returns an error instead of panicking on packets it can't parse.
+    fn serverbound(
+        &mut self,
+        state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<(), DecodeError>;
+
+    /// Rewrites a packet from libmc into the client's version. Packets pushed to `reply` are
+    /// handled as if the client had sent them, for exchanges the client's version doesn't have.
+    fn clientbound(
+        &mut self,
+        state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+        reply: &mut Vec<Vec<u8>>,
+    );
+}
+
+/// The translator libmc ships for `protocol_version`, if any
+pub fn builtin_translator(protocol_version: i32) -> Option<Box<dyn ProtocolTranslator>> {
+    match protocol_version {
+        763 => Some(Box::new(Translator763::default())),
+        #[cfg(feature = "snapshot")]
+        SNAPSHOT_PROTOCOL_VERSION => Some(Box::new(SnapshotTranslator)),
+        _ => None,
+    }
+}
+
+fn split_id(packet: &[u8]) -> Result<(i32, &[u8]), DecodeError> {
+    let mut r = PacketReader::new(packet);
+    let id = r.varint()?;
+    Ok((id, r.rest()))
+}
+
+fn with_id(id: i32, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 2);
+    write_varint(&mut packet, id);
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Translates for 1.20 and 1.20.1 clients (protocol 763), which predate the configuration state.
+///
+/// The login is completed on the client's behalf, and registry data is moved into Login (play)
+/// where 1.20.1 expects it. Other play packets are renumbered; their bodies are passed through
+/// unchanged, which is right for most but not all of them (e.g. players are spawned with their
+/// own packet in 1.20.1).
+#[derive(Debug, Default)]
+pub struct Translator763 {
+    /// Registry Data sent during configuration, for Login (play)
+    registry_codec: Option<Vec<u8>>,
+}
+
+impl Translator763 {
+    /// 1.20.1 IDs of serverbound play packets, to 1.20.2 IDs
+    fn serverbound_play_id(id: i32) -> i32 {
+        match id {
+            // 1.20.2 added Chunk Batch Received (0x07), Acknowledge Configuration (0x0B)
+            // and Ping Request (0x1D)
+            0x00..=0x06 => id,
+            0x07..=0x09 => id + 1,
+            0x0A..=0x1A => id + 2,
+            _ => id + 3,
+        }
+    }
+
+    /// 1.20.2 IDs of clientbound play packets, to 1.20.1 IDs. None if 1.20.1 doesn't have it.
+    fn clientbound_play_id(id: i32) -> Option<i32> {
+        // 1.20.2 removed Spawn Player (0x03) and Feature Flags (1.20.1's 0x6B), and added
+        // Chunk Batch Finished/Start (0x0C, 0x0D), Pong Response (0x34) and Start Configuration (0x65)
+        match id {
+            0x00..=0x02 => Some(id),
+            0x03..=0x0B => Some(id + 1),
+            0x0C | 0x0D | 0x34 | 0x65 => None,
+            0x0E..=0x33 => Some(id - 1),
+            0x35..=0x64 => Some(id - 2),
+            0x66..=0x6D => Some(id - 3),
+            _ => Some(id - 2),
+        }
+    }
+
+    /// Effect packets: 1.20.1's effect IDs start at 1, 1.20.2's at 0
+    fn effect_id(body: &[u8]) -> Vec<u8> {
+        let mut r = body;
+        let entity_id = read_varint(&mut r);
+        let effect = read_varint(&mut r);
+
+        let mut out = Vec::with_capacity(body.len() + 1);
+        write_varint(&mut out, entity_id);
+        write_varint(&mut out, effect + 1);
+        out.extend_from_slice(r);
+        out
+    }
+
+    /// Login Start: 1.20.1 has an optional UUID, 1.20.2 always has one
+    fn login_start(body: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut r = PacketReader::new(body);
+        let name = r.str()?;
+        let uuid = if r.bool()? { r.uuid()? } else { Uuid(0) };
+
+        let mut packet = with_id(0x00, &[]);
+        write_string(&mut packet, name);
+        write_uuid(&mut packet, uuid);
+        Ok(packet)
+    }
+
+    /// Login (play): reordered, and with the registries that 1.20.2 sends during configuration
+    fn login_play(&self, body: &[u8]) -> Vec<u8> {
+        let mut r = body;
+        let entity_id = read_int(&mut r);
+        let is_hardcore = read_bool(&mut r);
+        let dimension_names: Vec<String> = (0..read_varint(&mut r))
+            .map(|_| read_varint_string(&mut r))
+            .collect();
+        let max_players = read_varint(&mut r);
+        let view_distance = read_varint(&mut r);
+        let simulation_distance = read_varint(&mut r);
+        let reduced_debug_info = read_bool(&mut r);
+        let enable_respawn_screen = read_bool(&mut r);
+        // do_limited_crafting doesn't exist in 1.20.1
+        read_bool(&mut r);
+        let dimension_type = read_varint_string(&mut r);
+        let dimension_name = read_varint_string(&mut r);
+        let hashed_seed = read_long(&mut r);
+        let game_mode = read_ubyte(&mut r);
+        let prev_game_mode = read_byte(&mut r);
+        // is_debug, is_superflat, death location and portal cooldown are unchanged
+        let tail = r;
+
+        let mut p = with_id(0x28, &[]);
+        write_int(&mut p, entity_id);
+        write_bool(&mut p, is_hardcore);
+        write_ubyte(&mut p, game_mode);
+        write_ibyte(&mut p, prev_game_mode);
+        write_varint(&mut p, dimension_names.len().try_into().unwrap());
+        for name in &dimension_names {
+            write_string(&mut p, name);
+        }
+        match &self.registry_codec {
+            Some(codec) => p.extend_from_slice(codec),
+            // an empty compound with an empty name
+            None => p.extend_from_slice(&[0x0A, 0x00, 0x00, 0x00]),
+        }
+        write_string(&mut p, &dimension_type);
+        write_string(&mut p, &dimension_name);
+        write_long(&mut p, hashed_seed);
+        write_varint(&mut p, max_players);
+        write_varint(&mut p, view_distance);
+        write_varint(&mut p, simulation_distance);
+        write_bool(&mut p, reduced_debug_info);
+        write_bool(&mut p, enable_respawn_screen);
+        p.extend_from_slice(tail);
+        p
+    }
+}
+
+impl ProtocolTranslator for Translator763 {
+    fn client_version(&self) -> i32 {
+        763
+    }
+
+    fn serverbound(
+        &mut self,
+        state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<(), DecodeError> {
+        let (id, body) = split_id(packet)?;
+        match (state, id) {
+            (ProtocolState::Login, 0x00) => out.push(Self::login_start(body)?),
+            (ProtocolState::Play, _) => out.push(with_id(Self::serverbound_play_id(id), body)),
+            _ => out.push(packet.to_vec()),
+        }
+        Ok(())
+    }
+
+    fn clientbound(
+        &mut self,
+        state: ProtocolState,
+        packet: &[u8],
+        out: &mut Vec<Vec<u8>>,
+        reply: &mut Vec<Vec<u8>>,
+    ) {
+        // libmc encoded it, so it has a valid ID
+        let (id, body) = split_id(packet).unwrap();
+        match (state, id) {
+            // Login Success: 1.20.1 clients go straight to play, so acknowledge it for them
+            (ProtocolState::Login, 0x02) => {
+                out.push(packet.to_vec());
+                reply.push(with_id(0x03, &[]));
+            }
+            // Registry Data
+            (ProtocolState::Config, 0x05) => self.registry_codec = Some(body.to_vec()),
+            // Finish Configuration
+            (ProtocolState::Config, 0x02) => reply.push(with_id(0x02, &[])),
+            // nothing else from the configuration state exists in 1.20.1
+            (ProtocolState::Config, _) => {}
+            // Login (play)
+            (ProtocolState::Play, 0x29) => out.push(self.login_play(body)),
+            // Chunk Batch Finished: 1.20.1 clients don't batch, so answer for them at the
+            // rate a 1.20.2 client starts with
+            (ProtocolState::Play, 0x0C) => {
+                let mut received = with_id(0x07, &[]);
+                write_float(&mut received, 9.0);
+                reply.push(received);
+            }
+            // Entity Effect, Remove Entity Effect
+            (ProtocolState::Play, 0x6E | 0x41) => out.push(with_id(
+                Self::clientbound_play_id(id).unwrap(),
+                &Self::effect_id(body),
+            )),
+            (ProtocolState::Play, _) => {
+                if let Some(id) = Self::clientbound_play_id(id) {
+                    out.push(with_id(id, body));
+                }
+            }
+            _ => out.push(packet.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_as_1_20_1() {
+        let mut ps = PacketStream::new(Vec::new());
+        // what a packet from the client turns into
+        let recv = |ps: &mut PacketStream<Vec<u8>>, packet: &[u8]| {
+            let mut frames = ps.translate_incoming(frame_packet(packet)).unwrap();
+            assert_eq!(frames.len(), 1);
+            frames.pop().unwrap()
+        };
+
+        // handshake
+        let mut p = vec![0x00];
+        write_varint(&mut p, 763);
+        write_string(&mut p, "localhost");
+        p.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut p, 2);
+        let frame = recv(&mut ps, &p);
+        ps.decode(&frame).unwrap();
+        ps.set_translator(builtin_translator(ps.protocol_version()).unwrap());
+
+        // a truncated Login Start is an error, not a panic
+        let mut p = vec![0x00];
+        write_string(&mut p, "Steve");
+        assert_eq!(
+            ps.translate_incoming(frame_packet(&p)),
+            Err(DecodeError::UnexpectedEnd)
+        );
+
+        // 1.20.1 Login Start without a UUID
+        let mut p = vec![0x00];
+        write_string(&mut p, "Steve");
+        write_bool(&mut p, false);
+        let frame = recv(&mut ps, &p);
+        assert!(matches!(
+            ps.decode(&frame).unwrap(),
+            InPacket::LoginStart {
+                name: "Steve",
+                player_uuid: Uuid(0)
+            }
+        ));
+
+        let profile = GameProfile::new(Uuid(1), "Steve");
+        ps.send(OutPacket::LoginSuccess { profile: &profile })
+            .unwrap();
+        let injected = ps.take_injected();
+        assert_eq!(injected, [vec![1, 0x03]], "Login Acknowledged");
+        assert!(matches!(
+            ps.decode(&injected[0]).unwrap(),
+            InPacket::LoginAck
+        ));
+
+        // Finish Configuration isn't sent, but acknowledged
+        let written = ps.writer_mut().len();
+        assert_eq!(ps.send(OutPacket::FinishConfig).unwrap(), 0);
+        assert_eq!(ps.writer_mut().len(), written);
+        let injected = ps.take_injected();
+        assert!(matches!(
+            ps.decode(&injected[0]).unwrap(),
+            InPacket::FinishConfig
+        ));
+
+        // 1.20.1's Set Player On Ground is 0x17
+        let frame = recv(&mut ps, &[0x17, 0x01]);
+        assert!(matches!(
+            ps.decode(&frame).unwrap(),
+            InPacket::SetPlayerOnGround { on_ground: true }
+        ));
+
+        // 1.20.2's System Chat is 0x67, 1.20.1's is 0x64
+        let written = ps.writer_mut().len();
+        let text = TextComponent::text("hi");
+        ps.send(OutPacket::SystemChat {
+            content: &text,
+            overlay: false,
+        })
+        .unwrap();
+        assert_eq!(ps.writer_mut()[written + 1], 0x64);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::util::{md5, random_u128};
+use crate::*;
+use std::fmt;
+
+/// A player's or entity's UUID.
+///
+/// Displays hyphenated (`069a79f4-44e9-4726-a5be-fca90e38aaf5`), the way vanilla names player
+/// files and JSON has them; `simple()` gives it without hyphens, as Mojang's API has them.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Uuid(pub u128);
+
+impl Uuid {
+    /// A random (version 4) UUID, e.g. for a new entity
+    pub fn random() -> Self {
+        Self(random_u128() & !(0xF000 << 64) & !(0xC << 60) | 0x4000 << 64 | 0x8 << 60)
+    }
+
+    /// The UUID vanilla gives `username` in offline mode (a version 3 UUID of
+    /// `OfflinePlayer:<username>`)
+    pub fn offline(username: &str) -> Self {
+        let mut hash = md5(format!("OfflinePlayer:{username}").as_bytes());
+        hash[6] = hash[6] & 0x0F | 0x30;
+        hash[8] = hash[8] & 0x3F | 0x80;
+        Self(u128::from_be_bytes(hash))
+    }
+
+    /// Parses a UUID with or without hyphens
+    pub fn parse(s: &str) -> Option<Self> {
+        let hex = if s.len() == 36 {
+            let parts: Vec<&str> = s.split('-').collect();
+            if !parts.iter().map(|p| p.len()).eq([8, 4, 4, 4, 12]) {
+                return None;
+            }
+            parts.concat()
+        } else if s.len() == 32 {
+            s.to_owned()
+        } else {
+            return None;
+        };
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        u128::from_str_radix(&hex, 16).ok().map(Self)
+    }
+
+    /// Without hyphens (`069a79f444e94726a5befca90e38aaf5`)
+    pub fn simple(self) -> String {
+        format!("{:032x}", self.0)
+    }
+
+    pub fn from_longs(most: i64, least: i64) -> Self {
+        Self((most as u64 as u128) << 64 | least as u64 as u128)
+    }
+
+    /// The high and low halves, as in NBT's older `UUIDMost`/`UUIDLeast` pair of longs
+    pub fn to_longs(self) -> (i64, i64) {
+        ((self.0 >> 64) as i64, self.0 as i64)
+    }
+
+    pub fn from_int_array(ints: [i32; 4]) -> Self {
+        Self(
+            ints.iter()
+                .fold(0, |acc, &i| acc << 32 | u128::from(i as u32)),
+        )
+    }
+
+    /// As four ints, most significant first, which is how NBT stores UUIDs since 1.16
+    pub fn to_int_array(self) -> [i32; 4] {
+        std::array::from_fn(|i| (self.0 >> (96 - 32 * i)) as i32)
+    }
+
+    /// As an `IntArray` tag, e.g. an entity's `UUID`
+    pub fn to_nbt(self) -> Nbt<'static> {
+        Nbt::IntArray(self.to_int_array().to_vec().into())
+    }
+
+    /// Reads an `IntArray` tag, or a hyphenated string as some older data has
+    pub fn from_nbt(nbt: &Nbt) -> Option<Self> {
+        match nbt {
+            Nbt::IntArray(ints) => ints[..].try_into().ok().map(Self::from_int_array),
+            Nbt::String(s) => Self::parse(s),
+            _ => None,
+        }
+    }
+
+    /// Reads a UUID from `c` under `key`, or from the pair of longs `<key>Most` and `<key>Least`
+    pub fn from_compound(c: &CompoundNbt, key: &str) -> Option<Self> {
+        if let Some(uuid) = c.get(key).and_then(Self::from_nbt) {
+            return Some(uuid);
+        }
+        match (c.get(&format!("{key}Most")), c.get(&format!("{key}Least"))) {
+            (Some(&Nbt::Long(most)), Some(&Nbt::Long(least))) => {
+                Some(Self::from_longs(most, least))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = self.simple();
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &s[..8],
+            &s[8..12],
+            &s[12..16],
+            &s[16..20],
+            &s[20..]
+        )
+    }
+}
+
+impl fmt::Debug for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Uuid({self})")
+    }
+}
+
+impl From<u128> for Uuid {
+    fn from(uuid: u128) -> Self {
+        Self(uuid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        let uuid = Uuid::parse("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        assert_eq!(uuid.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!(Uuid::parse(&uuid.simple()), Some(uuid));
+        assert_eq!(Uuid::parse("069a79f4-44e9-4726-a5be-fca90e38aaf"), None);
+        assert_eq!(Uuid::parse("069a79f4-44e9-4726-a5be-fca90e38aaf+"), None);
+
+        assert_eq!(
+            uuid.to_int_array(),
+            [0x069a79f4, 0x44e94726, 0xa5befca9_u32 as i32, 0x0e38aaf5]
+        );
+        assert_eq!(Uuid::from_int_array(uuid.to_int_array()), uuid);
+        let (most, least) = uuid.to_longs();
+        assert_eq!(Uuid::from_longs(most, least), uuid);
+        assert_eq!(Uuid::from_nbt(&uuid.to_nbt()), Some(uuid));
+
+        let mut c = CompoundNbt::new("");
+        c.set("OwnerMost", Nbt::Long(most));
+        c.set("OwnerLeast", Nbt::Long(least));
+        assert_eq!(Uuid::from_compound(&c, "Owner"), Some(uuid));
+        assert_eq!(Uuid::from_compound(&c, "UUID"), None);
+
+        // what vanilla gives "Notch" in offline mode
+        assert_eq!(
+            Uuid::offline("Notch").to_string(),
+            "b50ad385-829d-3141-a216-7e7d7539ba7f"
+        );
+        let random = Uuid::random();
+        assert_eq!(random.to_string().as_bytes()[14], b'4');
+    }
+}
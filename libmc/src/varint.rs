@@ -0,0 +1,199 @@
+//! The protocol's variable-length integers. Both are little-endian groups of 7 bits, with the
+//! top bit of each byte set if another byte follows. Negative numbers are encoded as their
+//! two's complement bits, so they always take the maximum length.
+
+use crate::*;
+use std::io::{Read, Write};
+
+/// A 32-bit integer taking 1 to 5 bytes
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct VarInt(pub i32);
+
+/// A 64-bit integer taking 1 to 10 bytes
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct VarLong(pub i64);
+
+impl VarInt {
+    pub const MAX_LEN: usize = 5;
+
+    /// How many bytes `self` is encoded as
+    pub fn encoded_len(self) -> usize {
+        group_count(u64::from(self.0 as u32))
+    }
+
+    pub fn write<W: Write>(self, w: &mut W) {
+        let (buf, len) = self.encode();
+        w.write_all(&buf[..len]).unwrap();
+    }
+
+    /// The encoded bytes, in the first `len` bytes of the array: (array, len)
+    pub fn encode(self) -> ([u8; Self::MAX_LEN], usize) {
+        encode_groups(u64::from(self.0 as u32))
+    }
+
+    /// Reads a VarInt, returning it and how many bytes it took up.
+    /// Panics on I/O errors and on VarInts longer than 5 bytes, like the other `read_*` functions.
+    pub fn read<R: Read>(r: &mut R) -> (Self, usize) {
+        let (bits, nread) = read_groups(r, Self::MAX_LEN);
+        (Self(bits as u32 as i32), nread)
+    }
+
+    /// Decodes the VarInt at the start of `buf`, returning it and how many bytes it took up.
+    /// Faster than `read()` when the bytes are already in memory.
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+        // most VarInts (IDs, lengths, small counts) are one byte
+        if let Some(&b) = buf.first() {
+            if b & 0x80 == 0 {
+                return Ok((Self(b.into()), 1));
+            }
+        }
+        let (bits, len) = decode_groups(buf, Self::MAX_LEN)?;
+        Ok((Self(bits as u32 as i32), len))
+    }
+}
+
+impl VarLong {
+    pub const MAX_LEN: usize = 10;
+
+    /// How many bytes `self` is encoded as
+    pub fn encoded_len(self) -> usize {
+        group_count(self.0 as u64)
+    }
+
+    pub fn write<W: Write>(self, w: &mut W) {
+        let (buf, len) = self.encode();
+        w.write_all(&buf[..len]).unwrap();
+    }
+
+    /// The encoded bytes, in the first `len` bytes of the array: (array, len)
+    pub fn encode(self) -> ([u8; Self::MAX_LEN], usize) {
+        encode_groups(self.0 as u64)
+    }
+
+    /// Reads a VarLong, returning it and how many bytes it took up.
+    /// Panics on I/O errors and on VarLongs longer than 10 bytes.
+    pub fn read<R: Read>(r: &mut R) -> (Self, usize) {
+        let (bits, nread) = read_groups(r, Self::MAX_LEN);
+        (Self(bits as i64), nread)
+    }
+
+    /// Decodes the VarLong at the start of `buf`, returning it and how many bytes it took up
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let (bits, len) = decode_groups(buf, Self::MAX_LEN)?;
+        Ok((Self(bits as i64), len))
+    }
+}
+
+impl From<i32> for VarInt {
+    fn from(x: i32) -> Self {
+        Self(x)
+    }
+}
+
+impl From<VarInt> for i32 {
+    fn from(x: VarInt) -> Self {
+        x.0
+    }
+}
+
+impl From<i64> for VarLong {
+    fn from(x: i64) -> Self {
+        Self(x)
+    }
+}
+
+impl From<VarLong> for i64 {
+    fn from(x: VarLong) -> Self {
+        x.0
+    }
+}
+
+fn group_count(bits: u64) -> usize {
+    let significant = 64 - bits.leading_zeros() as usize;
+    significant.div_ceil(7).max(1)
+}
+
+/// Sets the continuation bit on every byte, then clears it on the last one, instead of deciding
+/// byte by byte whether another follows
+fn encode_groups<const N: usize>(bits: u64) -> ([u8; N], usize) {
+    let len = group_count(bits);
+    let mut buf = [0; N];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (bits >> (7 * i)) as u8 | 0x80;
+    }
+    buf[len - 1] &= 0x7F;
+    (buf, len)
+}
+
+fn decode_groups(buf: &[u8], max_len: usize) -> Result<(u64, usize), DecodeError> {
+    let mut bits = 0;
+    for (i, &b) in buf.iter().take(max_len).enumerate() {
+        bits |= u64::from(b & 0x7F) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok((bits, i + 1));
+        }
+    }
+    if buf.len() < max_len {
+        Err(DecodeError::UnexpectedEnd)
+    } else {
+        Err(DecodeError::VarIntTooLong)
+    }
+}
+
+fn read_groups<R: Read>(r: &mut R, max_len: usize) -> (u64, usize) {
+    let mut bits = 0;
+    for i in 0..max_len {
+        let b = read_ubyte(r);
+        bits |= u64::from(b & 0x7F) << (7 * i);
+        if b & 0x80 == 0 {
+            return (bits, i + 1);
+        }
+    }
+    panic!("varint longer than {max_len} bytes");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_examples() {
+        let cases: &[(i32, &[u8])] = &[
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (255, &[0xff, 0x01]),
+            (25565, &[0xdd, 0xc7, 0x01]),
+            (2097151, &[0xff, 0xff, 0x7f]),
+            (i32::MAX, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+            (-1, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+            (i32::MIN, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+        ];
+        for &(x, bytes) in cases {
+            let mut buf = Vec::new();
+            VarInt(x).write(&mut buf);
+            assert_eq!(buf, bytes, "{x}");
+            assert_eq!(VarInt(x).encoded_len(), bytes.len());
+            assert_eq!(VarInt::read(&mut &buf[..]), (VarInt(x), bytes.len()));
+            assert_eq!(VarInt::decode(&buf), Ok((VarInt(x), bytes.len())));
+            assert_eq!(
+                VarInt::decode(&buf[..bytes.len() - 1]),
+                Err(DecodeError::UnexpectedEnd)
+            );
+        }
+
+        let mut buf = Vec::new();
+        VarLong(-1).write(&mut buf);
+        assert_eq!(
+            buf,
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+        assert_eq!(VarLong::read(&mut &buf[..]), (VarLong(-1), 10));
+        assert_eq!(VarLong::decode(&buf), Ok((VarLong(-1), 10)));
+
+        let too_long = [0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert!(std::panic::catch_unwind(|| VarInt::read(&mut &too_long[..])).is_err());
+        assert_eq!(VarInt::decode(&too_long), Err(DecodeError::VarIntTooLong));
+    }
+}
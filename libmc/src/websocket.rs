@@ -0,0 +1,207 @@
+//! WebSocket transport, for browser-based or tunneled clients. Each binary message carries part of
+//! the usual stream of packet frames, so the rest of libmc doesn't know the difference.
+
+use crate::util::{base64_encode, sha1};
+use std::io::{self, BufRead, Read, Write};
+
+/// Appended to the client's key to prove we speak WebSocket (RFC 6455)
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Longest HTTP upgrade request accepted
+const MAX_REQUEST_LEN: usize = 8192;
+
+fn bad(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads the client's HTTP upgrade request from `r` and accepts it on `w`
+pub(crate) fn websocket_handshake(r: &mut impl BufRead, w: &mut impl Write) -> io::Result<()> {
+    let mut key = None;
+    let mut protocol = None;
+    let mut request_len = 0;
+    loop {
+        let mut line = String::new();
+        request_len += r.read_line(&mut line)?;
+        if request_len > MAX_REQUEST_LEN {
+            return Err(bad("WebSocket upgrade request too long"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim().to_string();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "sec-websocket-key" => key = Some(value),
+                // clients that ask for a subprotocol require one to be picked
+                "sec-websocket-protocol" => {
+                    protocol = value.split(',').next().map(|p| p.trim().to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+    let key = key.ok_or(bad("not a WebSocket upgrade request"))?;
+
+    let accept = base64_encode(&sha1(format!("{key}{ACCEPT_GUID}").as_bytes()));
+    let mut response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n"
+    );
+    if let Some(protocol) = protocol {
+        response += &format!("Sec-WebSocket-Protocol: {protocol}\r\n");
+    }
+    response += "\r\n";
+    w.write_all(response.as_bytes())?;
+    w.flush()
+}
+
+/// Reads the payloads of the binary messages a client sends, as one stream.
+/// A close message is the end of the stream. Pings are ignored, since clients don't send them.
+#[derive(Debug)]
+pub(crate) struct WsReader<R: Read> {
+    inner: R,
+    /// Payload bytes left in the current frame
+    remaining: u64,
+    mask: [u8; 4],
+    /// Where in the payload we are, for unmasking
+    pos: usize,
+}
+
+impl<R: Read> WsReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            remaining: 0,
+            mask: [0; 4],
+            pos: 0,
+        }
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut b = [0; N];
+        self.inner.read_exact(&mut b)?;
+        Ok(b)
+    }
+
+    /// Reads frame headers until a data frame with payload left. Returns false on a close frame.
+    fn next_frame(&mut self) -> io::Result<bool> {
+        while self.remaining == 0 {
+            let [b0, b1] = self.read_array()?;
+            let opcode = b0 & 0x0F;
+            let masked = b1 & 0x80 != 0;
+            let len = match b1 & 0x7F {
+                126 => u16::from_be_bytes(self.read_array()?).into(),
+                127 => u64::from_be_bytes(self.read_array()?),
+                len => len.into(),
+            };
+            self.mask = if masked { self.read_array()? } else { [0; 4] };
+            self.pos = 0;
+            self.remaining = len;
+            match opcode {
+                // continuation, binary
+                0x0 | 0x2 => {}
+                0x1 => return Err(bad("text WebSocket message")),
+                0x8 => return Ok(false),
+                // ping, pong: skip the payload
+                _ => {
+                    io::copy(&mut (&mut self.inner).take(len), &mut io::sink())?;
+                    self.remaining = 0;
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for WsReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || !self.next_frame()? {
+            return Ok(0);
+        }
+        let n = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..n])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        for b in &mut buf[..n] {
+            *b ^= self.mask[self.pos % 4];
+            self.pos += 1;
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Sends every write as one binary message
+#[derive(Debug)]
+pub(crate) struct WsWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> WsWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+}
+
+impl<W: Write> Write for WsWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        // FIN, binary; servers don't mask
+        let mut frame = vec![0x82];
+        match data.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len @ 126..=0xFFFF => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(data);
+        self.inner.write_all(&frame)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_and_frames() {
+        // example from RFC 6455
+        let request = "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        let mut response = Vec::new();
+        websocket_handshake(&mut request.as_bytes(), &mut response).unwrap();
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 "));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        // a masked binary message split over two frames, then a ping, then a close
+        let mask = [1, 2, 3, 4];
+        let mut wire = vec![0x02, 0x82];
+        wire.extend_from_slice(&mask);
+        wire.extend([0x10 ^ 1, 0x20 ^ 2]);
+        wire.extend([0x80, 0x81]);
+        wire.extend_from_slice(&mask);
+        wire.push(0x30 ^ 1);
+        wire.extend([0x89, 0x00]);
+        wire.extend([0x88, 0x00]);
+        let mut payload = Vec::new();
+        WsReader::new(&wire[..]).read_to_end(&mut payload).unwrap();
+        assert_eq!(payload, [0x10, 0x20, 0x30]);
+
+        let mut w = WsWriter::new(Vec::new());
+        w.write_all(&[7; 200]).unwrap();
+        assert_eq!(w.inner[..4], [0x82, 126, 0, 200]);
+        assert_eq!(w.inner.len(), 4 + 200);
+    }
+}
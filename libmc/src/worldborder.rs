@@ -0,0 +1,208 @@
+use crate::*;
+use std::time::Duration;
+
+/// The diameter of the border in a new vanilla world
+pub const DEFAULT_BORDER_DIAMETER: f64 = 59999968.0;
+
+/// How far from the center nether portals may lead (vanilla's maximum)
+const PORTAL_TELEPORT_BOUNDARY: i32 = 29999984;
+
+/// Milliseconds per tick
+const TICK_MS: i64 = 50;
+
+/// A world border that can grow or shrink over time, like the `/worldborder` command's.
+///
+/// Changes return the packet that tells clients about them; players that join later get
+/// `initialize_packet()`. Call `tick()` every tick so resizing progresses along with the clients.
+#[derive(Debug, Clone)]
+pub struct WorldBorder {
+    center: (f64, f64),
+    /// The diameter when the current resize started
+    from: f64,
+    to: f64,
+    /// How long the current resize takes, and how far into it we are, in milliseconds
+    duration: i64,
+    elapsed: i64,
+    warning_blocks: i32,
+    warning_time: i32,
+}
+
+impl Default for WorldBorder {
+    fn default() -> Self {
+        Self::new(0.0, 0.0, DEFAULT_BORDER_DIAMETER)
+    }
+}
+
+impl WorldBorder {
+    pub fn new(center_x: f64, center_z: f64, diameter: f64) -> Self {
+        Self {
+            center: (center_x, center_z),
+            from: diameter,
+            to: diameter,
+            duration: 0,
+            elapsed: 0,
+            warning_blocks: 5,
+            warning_time: 15,
+        }
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        self.center
+    }
+
+    /// The current diameter, partway through a resize if one is going on
+    pub fn diameter(&self) -> f64 {
+        if self.elapsed >= self.duration {
+            return self.to;
+        }
+        let progress = self.elapsed as f64 / self.duration as f64;
+        self.from + (self.to - self.from) * progress
+    }
+
+    /// The diameter the border is resizing to, or the current one if it isn't resizing
+    pub fn target_diameter(&self) -> f64 {
+        self.to
+    }
+
+    pub fn is_resizing(&self) -> bool {
+        self.elapsed < self.duration
+    }
+
+    /// How long the current resize has left
+    pub fn remaining(&self) -> Duration {
+        Duration::from_millis((self.duration - self.elapsed).max(0) as u64)
+    }
+
+    pub fn warning_blocks(&self) -> i32 {
+        self.warning_blocks
+    }
+
+    pub fn warning_time(&self) -> i32 {
+        self.warning_time
+    }
+
+    pub fn tick(&mut self) {
+        if self.is_resizing() {
+            self.elapsed = (self.elapsed + TICK_MS).min(self.duration);
+        }
+    }
+
+    pub fn set_center(&mut self, x: f64, z: f64) -> OutPacket<'static> {
+        self.center = (x, z);
+        OutPacket::SetBorderCenter { x, z }
+    }
+
+    /// Resizes the border right away, stopping any resize that's going on
+    pub fn set_diameter(&mut self, diameter: f64) -> OutPacket<'static> {
+        self.from = diameter;
+        self.to = diameter;
+        self.duration = 0;
+        self.elapsed = 0;
+        OutPacket::SetBorderSize { diameter }
+    }
+
+    /// Resizes the border from its current diameter to `diameter` over `time`
+    pub fn lerp_diameter(&mut self, diameter: f64, time: Duration) -> OutPacket<'static> {
+        self.from = self.diameter();
+        self.to = diameter;
+        self.duration = time.as_millis().try_into().unwrap_or(i64::MAX);
+        self.elapsed = 0;
+        OutPacket::SetBorderLerpSize {
+            old_diameter: self.from,
+            new_diameter: self.to,
+            speed: self.duration,
+        }
+    }
+
+    pub fn set_warning_blocks(&mut self, warning_blocks: i32) -> OutPacket<'static> {
+        self.warning_blocks = warning_blocks;
+        OutPacket::SetBorderWarningDistance { warning_blocks }
+    }
+
+    /// In seconds
+    pub fn set_warning_time(&mut self, warning_time: i32) -> OutPacket<'static> {
+        self.warning_time = warning_time;
+        OutPacket::SetBorderWarningDelay { warning_time }
+    }
+
+    /// Tells a client about the whole border, including how far along a resize is
+    pub fn initialize_packet(&self) -> OutPacket<'static> {
+        OutPacket::InitializeWorldBorder {
+            x: self.center.0,
+            z: self.center.1,
+            old_diameter: self.diameter(),
+            new_diameter: self.to,
+            speed: (self.duration - self.elapsed).max(0),
+            portal_teleport_boundary: PORTAL_TELEPORT_BOUNDARY,
+            warning_blocks: self.warning_blocks,
+            warning_time: self.warning_time,
+        }
+    }
+
+    /// How far inside the border a point is; negative if it's outside
+    pub fn distance_inside(&self, x: f64, z: f64) -> f64 {
+        let radius = self.diameter() / 2.0;
+        let dx = radius - (x - self.center.0).abs();
+        let dz = radius - (z - self.center.1).abs();
+        dx.min(dz)
+    }
+
+    pub fn contains(&self, x: f64, z: f64) -> bool {
+        self.distance_inside(x, z) >= 0.0
+    }
+
+    /// The closest point inside the border, for keeping players in
+    pub fn clamp(&self, x: f64, z: f64) -> (f64, f64) {
+        let radius = self.diameter() / 2.0;
+        (
+            x.clamp(self.center.0 - radius, self.center.0 + radius),
+            z.clamp(self.center.1 - radius, self.center.1 + radius),
+        )
+    }
+
+    /// Whether a block is fully inside the border, e.g. for refusing to let players break or place it
+    pub fn contains_block(&self, pos: &Position) -> bool {
+        let (x, z) = (f64::from(pos.x), f64::from(pos.z));
+        self.contains(x, z) && self.contains(x + 1.0, z + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrinking() {
+        let mut border = WorldBorder::new(10.0, 0.0, 100.0);
+        assert!(border.contains(59.0, -49.0));
+        assert!(!border.contains(61.0, 0.0));
+        assert_eq!(border.clamp(100.0, -3.0), (60.0, -3.0));
+
+        assert!(matches!(
+            border.lerp_diameter(50.0, Duration::from_secs(1)),
+            OutPacket::SetBorderLerpSize { speed: 1000, .. }
+        ));
+        for _ in 0..10 {
+            border.tick();
+        }
+        assert_eq!(border.diameter(), 75.0);
+        assert!(matches!(
+            border.initialize_packet(),
+            OutPacket::InitializeWorldBorder {
+                old_diameter: 75.0,
+                new_diameter: 50.0,
+                speed: 500,
+                ..
+            }
+        ));
+        assert_eq!(border.distance_inside(10.0, 30.0), 7.5);
+
+        for _ in 0..20 {
+            border.tick();
+        }
+        assert!(!border.is_resizing());
+        assert_eq!(border.diameter(), 50.0);
+        assert!(border.contains_block(&Position { x: 34, y: 0, z: 0 }));
+        assert!(!border.contains_block(&Position { x: 35, y: 0, z: 0 }));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::gamerules::level_data;
+use crate::*;
+
+/// How long a Minecraft day is, in ticks
+pub const DAY_TICKS: i64 = 24000;
+
+/// How often vanilla sends everyone the time, in ticks
+pub const TIME_UPDATE_INTERVAL: u64 = 20;
+
+/// Drives a world's clock: its age goes up every tick, and its time of day too while the
+/// `doDaylightCycle` game rule is on.
+///
+/// Call `tick()` from `Server::tick()`, and broadcast `packet()` every `TIME_UPDATE_INTERVAL`
+/// ticks (and to players as they join), so clients keep their sun in step.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct WorldTime {
+    /// Ticks since the world was created
+    pub world_age: i64,
+    /// Ticks since the first sunrise: 0 is sunrise, 6000 noon, 18000 midnight. Not wrapped
+    /// around, so it also counts days.
+    pub time_of_day: i64,
+}
+
+impl WorldTime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tick(&mut self, rules: &GameRules) {
+        self.world_age += 1;
+        if rules.do_daylight_cycle() {
+            self.time_of_day += 1;
+        }
+    }
+
+    /// How far into the current day it is, from 0 up to `DAY_TICKS`
+    pub fn day_time(&self) -> i64 {
+        self.time_of_day.rem_euclid(DAY_TICKS)
+    }
+
+    pub fn day(&self) -> i64 {
+        self.time_of_day.div_euclid(DAY_TICKS)
+    }
+
+    /// Update Time. Clients move the sun on their own between updates, unless it's stopped.
+    pub fn packet(&self, rules: &GameRules) -> OutPacket<'static> {
+        // a negative time of day stops the client's clock; it's never negative for 0
+        let time_of_day = match rules.do_daylight_cycle() {
+            true => self.time_of_day,
+            false => -self.time_of_day.max(1),
+        };
+        OutPacket::UpdateTime {
+            world_age: self.world_age,
+            time_of_day,
+        }
+    }
+
+    /// Reads `Time` and `DayTime` from `level.dat`'s root compound
+    pub fn from_level_dat(root: &CompoundNbt<'_>) -> Self {
+        let long = |key| match level_data(root).and_then(|data| data.get(key)) {
+            Some(&Nbt::Long(v)) => v,
+            _ => 0,
+        };
+        Self {
+            world_age: long("Time"),
+            time_of_day: long("DayTime"),
+        }
+    }
+
+    /// Puts the time in `level.dat`'s root compound
+    pub fn write_level_dat(&self, root: &mut CompoundNbt<'static>) {
+        let mut data = match level_data(root) {
+            Some(data) => data.clone(),
+            None => CompoundNbt::new("Data"),
+        };
+        data.set("Time", Nbt::Long(self.world_age));
+        data.set("DayTime", Nbt::Long(self.time_of_day));
+        root.set("Data", Nbt::Compound(data));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daylight_cycle_follows_rule() {
+        let mut rules = GameRules::new();
+        let mut time = WorldTime::new();
+        time.tick(&rules);
+        rules
+            .set("doDaylightCycle", GameRuleValue::Bool(false))
+            .unwrap();
+        time.tick(&rules);
+        assert_eq!((time.world_age, time.time_of_day), (2, 1));
+        assert!(matches!(
+            time.packet(&rules),
+            OutPacket::UpdateTime {
+                world_age: 2,
+                time_of_day: -1
+            }
+        ));
+
+        time.time_of_day = DAY_TICKS * 3 + 6000;
+        assert_eq!((time.day(), time.day_time()), (3, 6000));
+        let mut root = CompoundNbt::new("");
+        rules.write_level_dat(&mut root);
+        time.write_level_dat(&mut root);
+        assert_eq!(WorldTime::from_level_dat(&root), time);
+        assert!(!GameRules::from_level_dat(&root).do_daylight_cycle());
+    }
+}
@@ -3,18 +3,31 @@ use libmc::*;
 struct BasicServer {}
 
 impl Server for BasicServer {
-    fn on_connect(&mut self, cid: ClientID) {}
+    fn on_connect(&mut self, _cid: ClientID) {}
 
-    fn on_disconnect(&mut self, cid: ClientID) {}
+    fn on_disconnect(&mut self, _cid: ClientID, _cause: DisconnectCause) {}
 
-    fn handle_packet(&mut self, cid: ClientID, packet: InPacket) {
+    fn handle_packet(&mut self, _cid: ClientID, packet: InPacket) {
         dbg!(packet);
     }
+
+    fn tick(&mut self, _tick: u64) {}
 }
 
 fn main() {
-    // TODO: this loop temporary until libmc handles multiple clients with async
-    loop {
-        run_server(BasicServer {})
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        // e.g. `mcserver proxy localhost:25566 captures`, for a vanilla server on port 25566
+        [mode, upstream, rest @ ..] if mode == "proxy" => {
+            let options = ProxyOptions {
+                capture_dir: rest.first().map(Into::into),
+                log_undecoded: true,
+            };
+            if let Some(dir) = &options.capture_dir {
+                std::fs::create_dir_all(dir).unwrap();
+            }
+            run_proxy("127.0.0.1:25565", upstream, options).unwrap();
+        }
+        _ => run_server(BasicServer {}),
     }
 }